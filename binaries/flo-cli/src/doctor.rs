@@ -0,0 +1,249 @@
+use crate::grpc::WithSecret;
+use crate::Result;
+use flo_grpc::controller::flo_controller_client::FloControllerClient;
+use flo_grpc::Channel;
+use flo_w3gs::net::W3GSListener;
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+use std::time::{Duration, Instant};
+use structopt::StructOpt;
+use tokio::net::{lookup_host, TcpListener, TcpStream, UdpSocket};
+use tokio::time::timeout;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const PING_COUNT: usize = 5;
+const PING_TIMEOUT: Duration = Duration::from_secs(1);
+/// Default Warcraft III game port
+const WAR3_LAN_PORT: u16 = 6112;
+
+#[derive(Debug, StructOpt)]
+pub struct Command {
+  /// Node addresses to ping in addition to the nodes listed by the controller
+  #[structopt(long = "node")]
+  nodes: Vec<String>,
+}
+
+impl Command {
+  pub async fn run(&self) -> Result<()> {
+    let mut report = Report::default();
+
+    check_controller(&mut report).await;
+
+    let mut nodes = list_nodes(&mut report).await;
+    nodes.extend(self.nodes.iter().map(|addr| (addr.clone(), addr.clone())));
+    for (name, ip_addr) in nodes {
+      check_node(&mut report, &name, &ip_addr).await;
+    }
+
+    check_lan_listener(&mut report).await;
+    check_port(
+      &mut report,
+      "warcraft lan port",
+      SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, WAR3_LAN_PORT),
+      "another program is hosting a LAN game, close it or other LAN hosting tools",
+    )
+    .await;
+    check_port(
+      &mut report,
+      "flo client port",
+      SocketAddrV4::new(Ipv4Addr::LOCALHOST, flo_constants::CLIENT_WS_PORT),
+      "another flo client is running, close it before starting a new one",
+    )
+    .await;
+
+    if report.failed > 0 {
+      anyhow::bail!("{} check(s) failed", report.failed)
+    }
+    println!("all checks passed");
+    Ok(())
+  }
+}
+
+#[derive(Default)]
+struct Report {
+  failed: usize,
+}
+
+impl Report {
+  fn ok(&self, name: &str, detail: impl std::fmt::Display) {
+    println!("[ ok ] {}: {}", name, detail);
+  }
+
+  fn warn(&self, name: &str, detail: impl std::fmt::Display) {
+    println!("[warn] {}: {}", name, detail);
+  }
+
+  fn fail(&mut self, name: &str, detail: impl std::fmt::Display, hint: &str) {
+    self.failed += 1;
+    println!("[fail] {}: {}", name, detail);
+    println!("       {}", hint);
+  }
+}
+
+/// Unlike the other commands, defaults to the production controller
+fn controller_host() -> String {
+  std::env::var("FLO_CONTROLLER_HOST")
+    .unwrap_or_else(|_| flo_constants::CONTROLLER_HOST.to_string())
+}
+
+async fn check_controller(report: &mut Report) {
+  const NAME: &str = "controller";
+
+  let host = format!(
+    "{}:{}",
+    controller_host(),
+    flo_constants::CONTROLLER_SOCKET_PORT
+  );
+  let addr = match lookup_host(&host).await.map(|mut addrs| addrs.next()) {
+    Ok(Some(addr)) => addr,
+    Ok(None) | Err(_) => {
+      return report.fail(
+        NAME,
+        format!("could not resolve `{}`", host),
+        "check your internet connection and DNS settings",
+      )
+    }
+  };
+
+  let t = Instant::now();
+  match timeout(CONNECT_TIMEOUT, TcpStream::connect(addr)).await {
+    Ok(Ok(_)) => report.ok(
+      NAME,
+      format!("connected to {} in {}ms", addr, t.elapsed().as_millis()),
+    ),
+    Ok(Err(err)) => report.fail(
+      NAME,
+      format!("connect to {}: {}", addr, err),
+      "a firewall or antivirus may be blocking flo",
+    ),
+    Err(_) => report.fail(
+      NAME,
+      format!("connect to {}: timed out", addr),
+      "a firewall or antivirus may be blocking flo",
+    ),
+  }
+}
+
+/// Returns (name, ip address) of the nodes known by the controller
+async fn list_nodes(report: &mut Report) -> Vec<(String, String)> {
+  const NAME: &str = "node list";
+
+  let res = async {
+    let endpoint = Channel::from_shared(format!(
+      "tcp://{}:{}",
+      controller_host(),
+      flo_constants::CONTROLLER_GRPC_PORT
+    ))?;
+    let channel = timeout(CONNECT_TIMEOUT, endpoint.connect()).await??;
+    let mut client = FloControllerClient::with_interceptor(channel, WithSecret);
+    Ok::<_, anyhow::Error>(client.list_nodes(()).await?.into_inner().nodes)
+  }
+  .await;
+
+  match res {
+    Ok(nodes) => {
+      report.ok(NAME, format!("{} node(s)", nodes.len()));
+      nodes
+        .into_iter()
+        .map(|node| (node.name, node.ip_addr))
+        .collect()
+    }
+    Err(err) => {
+      report.warn(
+        NAME,
+        format!("{}, pass node addresses with `--node` to ping them", err),
+      );
+      vec![]
+    }
+  }
+}
+
+async fn check_node(report: &mut Report, name: &str, ip_addr: &str) {
+  let name = format!("node {}", name);
+
+  let addr: SocketAddr = match lookup_host((ip_addr, flo_constants::NODE_ECHO_PORT))
+    .await
+    .map(|mut addrs| addrs.next())
+  {
+    Ok(Some(addr)) => addr,
+    Ok(None) | Err(_) => {
+      return report.fail(
+        &name,
+        format!("invalid address `{}`", ip_addr),
+        "check the node address",
+      )
+    }
+  };
+
+  match ping(addr).await {
+    Ok(rtts) if rtts.is_empty() => report.fail(
+      &name,
+      format!("no reply from {}", addr),
+      "outgoing UDP traffic may be blocked by a firewall or the router",
+    ),
+    Ok(rtts) => {
+      let avg = rtts.iter().sum::<u128>() / rtts.len() as u128;
+      let loss = (PING_COUNT - rtts.len()) * 100 / PING_COUNT;
+      let detail = format!("{}ms avg, {}% loss", avg, loss);
+      if loss > 0 {
+        report.warn(&name, detail)
+      } else {
+        report.ok(&name, detail)
+      }
+    }
+    Err(err) => report.fail(
+      &name,
+      format!("ping {}: {}", addr, err),
+      "outgoing UDP traffic may be blocked by a firewall",
+    ),
+  }
+}
+
+/// Sends echo datagrams to the node, returns the rtt of every reply in milliseconds
+async fn ping(addr: SocketAddr) -> Result<Vec<u128>> {
+  let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await?;
+  socket.connect(addr).await?;
+
+  let mut rtts = vec![];
+  let mut buf = [0_u8; 4];
+  for seq in 0..PING_COUNT as u32 {
+    let t = Instant::now();
+    socket.send(&seq.to_be_bytes()).await?;
+    let deadline = t + PING_TIMEOUT;
+    loop {
+      let now = Instant::now();
+      if now >= deadline {
+        break;
+      }
+      match timeout(deadline - now, socket.recv(&mut buf)).await {
+        Ok(Ok(4)) if buf == seq.to_be_bytes() => {
+          rtts.push(t.elapsed().as_millis());
+          break;
+        }
+        Ok(Ok(_)) => continue,
+        Ok(Err(err)) => return Err(err.into()),
+        Err(_) => break,
+      }
+    }
+  }
+  Ok(rtts)
+}
+
+async fn check_lan_listener(report: &mut Report) {
+  const NAME: &str = "lan listener";
+
+  match W3GSListener::bind().await {
+    Ok(listener) => report.ok(NAME, format!("bound to port {}", listener.port())),
+    Err(err) => report.fail(
+      NAME,
+      err,
+      "flo can't host LAN games, allow flo in your firewall or antivirus",
+    ),
+  }
+}
+
+async fn check_port(report: &mut Report, name: &str, addr: SocketAddrV4, hint: &str) {
+  match TcpListener::bind(addr).await {
+    Ok(_) => report.ok(name, format!("{} is free", addr)),
+    Err(err) => report.fail(name, format!("{}: {}", addr, err), hint),
+  }
+}
@@ -1,11 +1,14 @@
 use structopt::StructOpt;
 
 mod client;
+mod doctor;
 mod env;
 mod game;
 mod grpc;
 mod lan;
+mod map;
 mod server;
+mod token;
 mod observer;
 mod kinesis;
 
@@ -30,15 +33,25 @@ enum Opt {
     #[structopt(subcommand)]
     cmd: observer::Command,
   },
+  Map {
+    #[structopt(subcommand)]
+    cmd: map::Command,
+  },
+  Token {
+    #[structopt(subcommand)]
+    cmd: token::Command,
+  },
   Kinesis {
     #[structopt(subcommand)]
     cmd: kinesis::Command,
-  }
+  },
+  /// Check connectivity to flo services and local network setup
+  Doctor(doctor::Command),
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
-  dotenv::dotenv()?;
+  dotenv::dotenv().ok();
   // flo_log_subscriber::init_env_override("debug,h2=error,async_dnssd=error");
   flo_log_subscriber::init();
 
@@ -57,9 +70,18 @@ async fn main() -> Result<()> {
     Opt::Observer { cmd } => {
       cmd.run().await?;
     }
+    Opt::Map { cmd } => {
+      cmd.run().await?;
+    }
+    Opt::Token { cmd } => {
+      cmd.run().await?;
+    }
     Opt::Kinesis { cmd } => {
       cmd.run().await?;
     }
+    Opt::Doctor(cmd) => {
+      cmd.run().await?;
+    }
   }
 
   Ok(())
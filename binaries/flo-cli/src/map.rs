@@ -0,0 +1,107 @@
+use crate::Result;
+use flo_controller::game::MapSlot;
+use flo_controller::map::{Map, MapForce, MapPlayer, MapSha1};
+use flo_w3map::W3Map;
+use std::path::PathBuf;
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+pub enum Command {
+  /// Print the map data the controller receives and the slot layout it derives from it
+  Inspect { path: PathBuf },
+}
+
+impl Command {
+  pub async fn run(&self) -> Result<()> {
+    match *self {
+      Command::Inspect { ref path } => {
+        let (w3map, checksum) = W3Map::open_with_checksum(path)?;
+        let (width, height) = w3map.dimension();
+
+        // same fields as the map detail the client hands to the controller
+        let map = Map {
+          sha1: MapSha1(checksum.sha1),
+          checksum: checksum.crc32,
+          name: w3map.name().to_string(),
+          description: w3map.description().to_string(),
+          author: w3map.author().to_string(),
+          path: path.to_string_lossy().to_string(),
+          width,
+          height,
+          players: w3map
+            .get_players()
+            .into_iter()
+            .map(|p| MapPlayer {
+              name: p.name.to_string(),
+              r#type: p.r#type,
+              race: p.race,
+              flags: p.flags,
+            })
+            .collect(),
+          forces: w3map
+            .get_forces()
+            .into_iter()
+            .map(|f| MapForce {
+              name: f.name.to_string(),
+              flags: f.flags,
+              player_set: f.player_set,
+            })
+            .collect(),
+          flags: w3map.flags().bits(),
+        };
+
+        println!("name: {}", map.name);
+        println!("author: {}", map.author);
+        println!("suggested players: {}", w3map.suggested_players());
+        println!("size: {}x{}", map.width, map.height);
+        println!("file size: {}", checksum.file_size);
+        println!("sha1: {}", checksum.get_sha1_hex_string());
+        println!("crc32: {}", checksum.crc32);
+        println!("xoro: {}", checksum.xoro);
+        println!("flags: 0x{:x}", map.flags);
+        println!("fixed player settings: {}", map.fixed_player_settings());
+        println!("custom forces: {}", map.custom_forces());
+
+        println!("players: {}", map.players.len());
+        for (idx, player) in map.players.iter().enumerate() {
+          println!(
+            "  [{}] {}: type = {}, race = {}, flags = 0x{:x}",
+            idx, player.name, player.r#type, player.race, player.flags
+          );
+        }
+
+        println!("forces: {}", map.forces.len());
+        for (idx, force) in map.forces.iter().enumerate() {
+          println!(
+            "  [{}] {}: players = {:?}, flags = 0x{:x}",
+            idx,
+            force.name,
+            (0..map.players.len())
+              .filter(|i| force.player_set & (1 << i) != 0)
+              .collect::<Vec<_>>(),
+            force.flags
+          );
+        }
+
+        println!("slots:");
+        for (idx, slot) in MapSlot::from_map(&map).iter().enumerate() {
+          println!(
+            "  [{}] team = {}, race = {}, computer = {}, fixed = {}",
+            idx,
+            slot
+              .team
+              .map(|team| team.to_string())
+              .unwrap_or_else(|| "-".to_string()),
+            slot
+              .race
+              .map(|race| format!("{:?}", race))
+              .unwrap_or_else(|| "selectable".to_string()),
+            slot.computer,
+            slot.fixed
+          );
+        }
+      }
+    }
+    Ok(())
+  }
+}
@@ -0,0 +1,112 @@
+use crate::Result;
+use serde_json::Value;
+use std::time::{SystemTime, UNIX_EPOCH};
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+pub enum Command {
+  /// Decode a player, join or node token and verify it against `JWT_SECRET_BASE64`
+  Inspect { token: String },
+}
+
+impl Command {
+  pub async fn run(&self) -> Result<()> {
+    match *self {
+      Command::Inspect { ref token } => {
+        let token = token.trim();
+        if let Some(bytes) = decode_node_token(token) {
+          inspect_node_token(&bytes);
+        } else {
+          inspect_jwt(token)?;
+        }
+      }
+    }
+    Ok(())
+  }
+}
+
+/// Node tokens are random 16 byte ids, sent as hex or uuid strings
+fn decode_node_token(token: &str) -> Option<Vec<u8>> {
+  let bytes = hex::decode(token.replace('-', "")).ok()?;
+  if bytes.len() == 16 {
+    Some(bytes)
+  } else {
+    None
+  }
+}
+
+fn inspect_node_token(bytes: &[u8]) {
+  println!("type: node player token");
+  println!("hex: {}", hex::encode(bytes));
+  // random ids without claims or signature
+  println!("signature: none, only valid on the node the controller registered it on");
+}
+
+fn inspect_jwt(token: &str) -> Result<()> {
+  let parts: Vec<&str> = token.split('.').collect();
+  if parts.len() != 3 {
+    anyhow::bail!("not a node token or a JWT");
+  }
+
+  let header: Value =
+    serde_json::from_slice(&base64::decode_config(parts[0], base64::URL_SAFE_NO_PAD)?)?;
+  let claims: Value =
+    serde_json::from_slice(&base64::decode_config(parts[1], base64::URL_SAFE_NO_PAD)?)?;
+
+  let kind = if claims.get("player_id").is_some() {
+    TokenKind::Player
+  } else if claims.get("game_id").is_some() {
+    TokenKind::Join
+  } else {
+    TokenKind::Unknown
+  };
+
+  println!("type: {}", kind.name());
+  println!("header: {}", header);
+  println!("claims: {}", claims);
+
+  if let Some(exp) = claims.get("exp").and_then(Value::as_i64) {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+    if exp > now {
+      println!("expires: {} (in {}s)", exp, exp - now);
+    } else {
+      println!("expires: {} (expired {}s ago)", exp, now - exp);
+    }
+  }
+
+  if std::env::var_os("JWT_SECRET_BASE64").is_none() {
+    println!("signature: not verified, `JWT_SECRET_BASE64` is not set");
+    return Ok(());
+  }
+
+  let verified = match kind {
+    TokenKind::Player => flo_controller::player::token::validate_player_token(token).map(|_| ()),
+    TokenKind::Join => flo_controller::game::token::validate_join_token(token).map(|_| ()),
+    TokenKind::Unknown => {
+      println!("signature: not verified, unknown token type");
+      return Ok(());
+    }
+  };
+  match verified {
+    Ok(()) => println!("signature: valid"),
+    Err(err) => println!("signature: invalid: {}", err),
+  }
+
+  Ok(())
+}
+
+enum TokenKind {
+  Player,
+  Join,
+  Unknown,
+}
+
+impl TokenKind {
+  fn name(&self) -> &'static str {
+    match *self {
+      TokenKind::Player => "player token",
+      TokenKind::Join => "game join token",
+      TokenKind::Unknown => "unknown JWT",
+    }
+  }
+}
@@ -32,7 +32,19 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     });
   }
 
-  tokio::try_join!(serve_grpc(state.clone()), serve_socket(state.clone()))?;
+  #[cfg(not(feature = "graphql"))]
+  let res = tokio::try_join!(serve_grpc(state.clone()), serve_socket(state.clone())).map(|_| ());
+  #[cfg(feature = "graphql")]
+  let res = tokio::try_join!(
+    serve_grpc(state.clone()),
+    serve_socket(state.clone()),
+    flo_controller::serve_graphql(state.clone())
+  )
+  .map(|_| ());
+
+  flo_log_subscriber::shutdown();
+
+  res?;
 
   Ok(())
 }
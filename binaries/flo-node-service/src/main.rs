@@ -18,9 +18,15 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     flo_log_subscriber::init();
   }
 
+  flo_log_subscriber::report::init(concat!("flo-node@", env!("CARGO_PKG_VERSION")));
+
   tracing::info!("starting.");
 
-  serve().await?;
+  let res = serve().await;
+
+  flo_log_subscriber::shutdown();
+
+  res?;
 
   Ok(())
 }
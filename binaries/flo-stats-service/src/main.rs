@@ -5,11 +5,12 @@ use async_graphql::http::{playground_source, GraphQLPlaygroundConfig};
 use async_graphql::Schema;
 use async_graphql_axum::{GraphQLRequest, GraphQLResponse, GraphQLSubscription};
 use axum::headers::HeaderValue;
-use axum::http::Method;
-use axum::response::{self, IntoResponse};
+use axum::http::{header, Method, StatusCode};
+use axum::response::{self, Headers, IntoResponse};
 use axum::routing::get;
 use axum::{extract, AddExtensionLayer, Router, Server};
-use flo_observer_edge::FloObserverEdge;
+use flo_observer_edge::{FloObserverEdge, FloObserverEdgeHandle};
+use serde::Deserialize;
 use tower_http::cors::{CorsLayer, Origin};
 
 async fn graphql_handler(
@@ -25,6 +26,40 @@ async fn graphql_playground() -> impl IntoResponse {
   ))
 }
 
+#[derive(Deserialize)]
+struct ReplayQuery {
+  token: String,
+}
+
+async fn download_replay(
+  edge: extract::Extension<FloObserverEdgeHandle>,
+  extract::Path(game_id): extract::Path<i32>,
+  extract::Query(query): extract::Query<ReplayQuery>,
+) -> Result<impl IntoResponse, StatusCode> {
+  match flo_observer::token::validate_replay_token(&query.token) {
+    Ok(token) if token.game_id == game_id => {}
+    _ => return Err(StatusCode::FORBIDDEN),
+  }
+  let data = match edge.fetch_archive(game_id).await {
+    Ok(Some(data)) => data,
+    Ok(None) => return Err(StatusCode::NOT_FOUND),
+    Err(err) => {
+      tracing::error!(game_id, "fetch archive: {}", err);
+      return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    }
+  };
+  Ok((
+    Headers(vec![
+      (header::CONTENT_TYPE, "application/gzip".to_string()),
+      (
+        header::CONTENT_DISPOSITION,
+        format!("attachment; filename=\"flo-{}.gz\"", game_id),
+      ),
+    ]),
+    data,
+  ))
+}
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
   #[cfg(debug_assertions)]
@@ -40,8 +75,9 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
   let edge = FloObserverEdge::from_env().await?;
 
+  let handle = edge.handle();
   let schema = Schema::build(QueryRoot, MutationRoot, SubscriptionRoot)
-    .data(edge.handle())
+    .data(handle.clone())
     .finish();
 
   tokio::spawn(async move {
@@ -53,7 +89,9 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
   let app = Router::new()
     .route("/", get(graphql_playground).post(graphql_handler))
     .route("/ws", GraphQLSubscription::new(schema.clone()))
+    .route("/replays/:game_id", get(download_replay))
     .layer(AddExtensionLayer::new(schema))
+    .layer(AddExtensionLayer::new(handle))
     .layer({
       let allowed_list: [HeaderValue; 4] = [
         "http://localhost:3000".parse().unwrap(),
@@ -1,4 +1,6 @@
 use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
 use tracing_subscriber::EnvFilter;
 
 #[cfg(debug_assertions)]
@@ -11,7 +13,11 @@ pub fn init(debug: bool) {
       LevelFilter::INFO.into()
     });
 
-  tracing_subscriber::fmt().with_env_filter(filter).init();
+  tracing_subscriber::fmt()
+    .with_env_filter(filter)
+    .finish()
+    .with(flo_log_subscriber::report::layer())
+    .init();
 }
 
 #[cfg(not(debug_assertions))]
@@ -37,5 +43,7 @@ pub fn init(debug: bool) {
     .with_env_filter(filter)
     .with_writer(non_blocking)
     .with_ansi(false)
+    .finish()
+    .with(flo_log_subscriber::report::layer())
     .init();
 }
@@ -38,6 +38,7 @@ fn main() {
   let opt = Opt::from_args();
 
   log::init(opt.debug);
+  flo_log_subscriber::report::init(&format!("flo@{}", flo_client::FLO_VERSION));
 
   let res = std::panic::catch_unwind(|| -> Result<_> {
     let rt = Runtime::new()?;
@@ -66,6 +67,7 @@ fn main() {
       stdout.write(msg.as_bytes()).unwrap();
       stdout.flush().unwrap();
       rt.block_on(tokio::signal::ctrl_c()).unwrap();
+      flo_log_subscriber::shutdown();
     }
     Err(err) => {
       let msg = serde_json::to_string(&serde_json::json!({
@@ -1,8 +1,28 @@
+#[cfg(feature = "standalone")]
+mod standalone;
+
 #[tokio::main]
 async fn main() {
   flo_log_subscriber::init_env_override("debug");
 
-  let task = flo_client::start(Default::default()).await.unwrap();
+  #[cfg(feature = "standalone")]
+  {
+    let mut args = std::env::args().skip(1);
+    if args.next().as_deref() == Some("standalone") {
+      if let Err(err) = standalone::run(args.collect()).await {
+        tracing::error!("standalone: {}", err);
+      }
+      flo_log_subscriber::shutdown();
+      return;
+    }
+  }
+
+  let task = flo_client::start(flo_client::StartConfig {
+    controller_host: std::env::var("FLO_CONTROLLER_HOST").ok(),
+    ..Default::default()
+  })
+  .await
+  .unwrap();
   let join = tokio::spawn(task.serve());
   let ctrl_c = tokio::signal::ctrl_c();
 
@@ -10,4 +30,6 @@ async fn main() {
     res = join => res.unwrap(),
     _ = ctrl_c => {},
   }
+
+  flo_log_subscriber::shutdown();
 }
@@ -0,0 +1,95 @@
+//! Runs a controller, a node and the client in one process.
+//!
+//! The controller uses the PostgreSQL database at `DATABASE_URL`, or an embedded server
+//! storing its data in `FLO_STANDALONE_DATA_DIR` if it is not set.
+//! Every name passed on the command line gets a player and a token,
+//! the first player is signed in on the local client.
+//! Other players connect with their tokens and `FLO_CONTROLLER_HOST`
+//! set to this machine's address.
+
+use flo_controller::standalone::{StandaloneConfig, StandalonePlayer};
+use flo_controller::{serve_grpc, serve_socket, ControllerState};
+use pg_embed::pg_enums::PgAuthMethod;
+use pg_embed::pg_fetch::{PgFetchSettings, PG_V13};
+use pg_embed::postgres::{PgEmbed, PgSettings};
+use std::path::PathBuf;
+use std::time::Duration;
+
+type Result<T, E = Box<dyn std::error::Error>> = std::result::Result<T, E>;
+
+pub async fn run(player_names: Vec<String>) -> Result<()> {
+  if player_names.is_empty() {
+    return Err("usage: flo standalone <player name>...".into());
+  }
+
+  dotenv::dotenv().ok();
+  let _db = start_embedded_db().await?;
+  flo_controller::standalone::init_secrets();
+
+  let players = flo_controller::standalone::init(StandaloneConfig {
+    node_secret: std::env::var("FLO_NODE_SECRET")?,
+    node_ip_addr: std::env::var("FLO_STANDALONE_IP_ADDR")
+      .unwrap_or_else(|_| "127.0.0.1".to_string()),
+    player_names,
+  })
+  .await?;
+
+  for StandalonePlayer { id, name, token } in &players {
+    println!("player #{} {}: {}", id, name, token);
+  }
+
+  let state = ControllerState::init().await?.into_ref();
+  let client = flo_client::start(flo_client::StartConfig {
+    token: Some(players[0].token.clone()),
+    controller_host: Some("127.0.0.1".to_string()),
+    ..Default::default()
+  })
+  .await?;
+
+  let controller = async { tokio::try_join!(serve_grpc(state.clone()), serve_socket(state)) };
+
+  tokio::select! {
+    res = controller => res.map(|_| ())?,
+    res = flo_node::serve() => res?,
+    _ = client.serve() => {},
+    _ = tokio::signal::ctrl_c() => {},
+  }
+
+  Ok(())
+}
+
+/// Starts an embedded PostgreSQL server and points `DATABASE_URL` to it if it is not set.
+/// The server is stopped when the returned value is dropped
+async fn start_embedded_db() -> Result<Option<PgEmbed>> {
+  if std::env::var_os("DATABASE_URL").is_some() {
+    return Ok(None);
+  }
+
+  let data_dir = std::env::var_os("FLO_STANDALONE_DATA_DIR")
+    .map(PathBuf::from)
+    .unwrap_or_else(|| PathBuf::from("flo-standalone"));
+  tracing::info!("starting embedded database in {}", data_dir.display());
+
+  let mut db = PgEmbed::new(
+    PgSettings {
+      database_dir: data_dir.join("db"),
+      port: 15432,
+      user: "postgres".to_string(),
+      password: "postgres".to_string(),
+      auth_method: PgAuthMethod::Plain,
+      // players and games are kept between runs
+      persistent: true,
+      timeout: Some(Duration::from_secs(30)),
+      migration_dir: None,
+    },
+    PgFetchSettings {
+      version: PG_V13,
+      ..Default::default()
+    },
+  )
+  .await?;
+  db.setup().await?;
+  db.start_db().await?;
+  std::env::set_var("DATABASE_URL", db.full_db_uri("postgres"));
+  Ok(Some(db))
+}
@@ -18,6 +18,7 @@ use crate::node::{
   self, GetNode, NodeRegistry, SetActiveNode, UpdateAddressesAndGetNodePingMap, UpdateNodes,
 };
 use crate::platform::{GetClientConfig, Platform};
+use crate::updater::{CheckRelease, Updater};
 use crate::StartConfig;
 use flo_config::ClientConfig;
 use flo_net::packet::FloPacket;
@@ -32,12 +33,14 @@ pub struct ControllerClient {
   platform: Addr<Platform>,
   nodes: Addr<NodeRegistry>,
   lan: Addr<Lan>,
+  updater: Addr<Updater>,
   conn: Option<Owner<ControllerStream>>,
   conn_id: u64,
   ws_conn: Option<Session>,
   current_session: Option<PlayerSession>,
   initial_token: Option<String>,
   mute_list: Vec<i32>,
+  game_mute_list: Vec<i32>,
 }
 
 impl ControllerClient {
@@ -49,6 +52,7 @@ impl ControllerClient {
       self.nodes.clone(),
       self.conn_id,
       &self.config.controller_host,
+      self.config.proxy.clone(),
       token,
     );
     self.conn.replace(stream.start());
@@ -70,17 +74,16 @@ impl ControllerClient {
       return;
     };
 
-    let node_info = if let Ok(node_info) = self
-      .nodes
-      .send(GetNode {
-        node_id: event.node_id,
-      })
-      .await
-    {
+    // relayed players connect to the relay node with the same token
+    let node_id = event.relay_node_id.unwrap_or(event.node_id);
+    if node_id != event.node_id {
+      tracing::info!(game_id, node_id, "relayed to node #{}", event.node_id);
+    }
+    let node_info = if let Ok(node_info) = self.nodes.send(GetNode { node_id }).await {
       if let Some(v) = node_info {
         v
       } else {
-        tracing::error!("unknown node id: {}", event.node_id);
+        tracing::error!("unknown node id: {}", node_id);
         return;
       }
     } else {
@@ -94,26 +97,29 @@ impl ControllerClient {
       game: event.game_info,
     };
 
-    if let Err(err) = self
+    match self
       .lan
       .send(msg)
       .await
       .map_err(Error::from)
       .and_then(std::convert::identity)
     {
-      tracing::error!("update lan game: {}", err);
-      self
-        .ws_send(OutgoingMessage::GameStartError(message::ErrorMessage::new(
-          err,
-        )))
-        .await;
-    } else {
-      self
-        .ws_send(OutgoingMessage::GameStarted(message::GameStarted {
-          game_id,
-          lan_game_name: { crate::lan::get_lan_game_name(game_id, player_session.player.id) },
-        }))
-        .await;
+      Ok(lan_game_name) => {
+        self
+          .ws_send(OutgoingMessage::GameStarted(message::GameStarted {
+            game_id,
+            lan_game_name,
+          }))
+          .await;
+      }
+      Err(err) => {
+        tracing::error!("update lan game: {}", err);
+        self
+          .ws_send(OutgoingMessage::GameStartError(message::ErrorMessage::new(
+            err,
+          )))
+          .await;
+      }
     }
   }
 
@@ -148,12 +154,14 @@ impl Service<StartConfig> for ControllerClient {
       platform,
       nodes: registry.resolve().await?,
       lan: registry.resolve().await?,
+      updater: registry.resolve().await?,
       conn: None,
       conn_id: 0,
       ws_conn: None,
       current_session: None,
       initial_token: registry.data().token.clone(),
       mute_list: vec![],
+      game_mute_list: vec![],
     })
   }
 }
@@ -226,6 +234,9 @@ impl Handler<ControllerEvent> for ControllerClient {
               if let Some(current) = self.current_session.as_mut() {
                 current.game_id = update.game_id;
                 current.status = update.status;
+                if let Some(read_only) = update.read_only {
+                  current.read_only = read_only;
+                }
                 tracing::info!(
                   player_id = current.player.id,
                   "player session updated: game_id = {:?}",
@@ -238,6 +249,13 @@ impl Handler<ControllerEvent> for ControllerClient {
               }
             }
           },
+          ControllerEventData::PlayerInfoUpdate(player) => {
+            if let Some(current) = self.current_session.as_mut() {
+              if current.player.id == player.id {
+                current.player = player;
+              }
+            }
+          }
           ControllerEventData::GameInfoUpdate(event) => match event.game_info {
             Some(game_info) => {
               tracing::debug!(game_id = game_info.game_id, "game info update");
@@ -260,6 +278,9 @@ impl Handler<ControllerEvent> for ControllerClient {
               tracing::error!("select active node: {}", err);
             }
           }
+          ControllerEventData::ReleaseAvailable(release) => {
+            self.updater.notify(CheckRelease(release)).await.ok();
+          }
           ControllerEventData::Disconnected => {
             if let Some(stream) = self.conn.take() {
               ctx.spawn(async move {
@@ -458,6 +479,7 @@ impl Handler<LanEvent> for ControllerClient {
 
 pub struct UpdateMuteList {
   pub mute_list: Vec<i32>,
+  pub game_mute_list: Vec<i32>,
 }
 
 impl Message for UpdateMuteList {
@@ -469,22 +491,64 @@ impl Handler<UpdateMuteList> for ControllerClient {
   async fn handle(
     &mut self,
     _: &mut Context<Self>,
-    UpdateMuteList { mute_list }: UpdateMuteList,
+    UpdateMuteList {
+      mute_list,
+      game_mute_list,
+    }: UpdateMuteList,
   ) -> <UpdateMuteList as Message>::Result {
     self.mute_list = mute_list;
+    self.game_mute_list = game_mute_list;
   }
 }
 
 pub struct GetMuteList;
 
+#[derive(Debug, Default)]
+pub struct MuteLists {
+  pub mute_list: Vec<i32>,
+  /// Players muted for the current game only
+  pub game_mute_list: Vec<i32>,
+}
+
 impl Message for GetMuteList {
-  type Result = Vec<i32>;
+  type Result = MuteLists;
 }
 
 #[async_trait]
 impl Handler<GetMuteList> for ControllerClient {
-  async fn handle(&mut self, _: &mut Context<Self>, _: GetMuteList) -> Vec<i32> {
-    self.mute_list.clone()
+  async fn handle(&mut self, _: &mut Context<Self>, _: GetMuteList) -> MuteLists {
+    MuteLists {
+      mute_list: self.mute_list.clone(),
+      game_mute_list: self.game_mute_list.clone(),
+    }
+  }
+}
+
+/// Saves the players muted for the current game, restored if the client reconnects
+pub struct UpdateGameMuteList {
+  pub game_id: i32,
+  pub mute_list: Vec<i32>,
+}
+
+impl Message for UpdateGameMuteList {
+  type Result = Result<()>;
+}
+
+#[async_trait]
+impl Handler<UpdateGameMuteList> for ControllerClient {
+  async fn handle(
+    &mut self,
+    _: &mut Context<Self>,
+    UpdateGameMuteList { game_id, mute_list }: UpdateGameMuteList,
+  ) -> Result<()> {
+    self.game_mute_list = mute_list.clone();
+    self
+      .send_frame(
+        flo_net::proto::flo_connect::PacketGameMuteListUpdateRequest { game_id, mute_list }
+          .encode_as_frame()?,
+      )
+      .await?;
+    Ok(())
   }
 }
 
@@ -505,7 +569,35 @@ impl Handler<MutePlayer> for ControllerClient {
   ) -> Result<()> {
     self
       .send_frame(
-        flo_net::proto::flo_connect::PacketPlayerMuteAddRequest { player_id }.encode_as_frame()?,
+        flo_net::proto::flo_connect::PacketPlayerMuteAddRequest {
+          player_id,
+          ..Default::default()
+        }
+        .encode_as_frame()?,
+      )
+      .await?;
+    Ok(())
+  }
+}
+
+pub struct RehostGame {
+  pub game_id: i32,
+}
+
+impl Message for RehostGame {
+  type Result = Result<()>;
+}
+
+#[async_trait]
+impl Handler<RehostGame> for ControllerClient {
+  async fn handle(
+    &mut self,
+    _: &mut Context<Self>,
+    RehostGame { game_id }: RehostGame,
+  ) -> Result<()> {
+    self
+      .send_frame(
+        flo_net::proto::flo_connect::PacketGameRehostRequest { game_id }.encode_as_frame()?,
       )
       .await?;
     Ok(())
@@ -529,8 +621,68 @@ impl Handler<UnmutePlayer> for ControllerClient {
   ) -> Result<()> {
     self
       .send_frame(
-        flo_net::proto::flo_connect::PacketPlayerMuteRemoveRequest { player_id }
-          .encode_as_frame()?,
+        flo_net::proto::flo_connect::PacketPlayerMuteRemoveRequest {
+          player_id,
+          ..Default::default()
+        }
+        .encode_as_frame()?,
+      )
+      .await?;
+    Ok(())
+  }
+}
+
+/// Mutes the players with a name matching `pattern` in all future games
+pub struct MuteNamePattern {
+  pub pattern: String,
+}
+
+impl Message for MuteNamePattern {
+  type Result = Result<()>;
+}
+
+#[async_trait]
+impl Handler<MuteNamePattern> for ControllerClient {
+  async fn handle(
+    &mut self,
+    _: &mut Context<Self>,
+    MuteNamePattern { pattern }: MuteNamePattern,
+  ) -> Result<()> {
+    self
+      .send_frame(
+        flo_net::proto::flo_connect::PacketPlayerMuteAddRequest {
+          name_pattern: pattern,
+          ..Default::default()
+        }
+        .encode_as_frame()?,
+      )
+      .await?;
+    Ok(())
+  }
+}
+
+pub struct UnmuteNamePattern {
+  pub pattern: String,
+}
+
+impl Message for UnmuteNamePattern {
+  type Result = Result<()>;
+}
+
+#[async_trait]
+impl Handler<UnmuteNamePattern> for ControllerClient {
+  async fn handle(
+    &mut self,
+    _: &mut Context<Self>,
+    UnmuteNamePattern { pattern }: UnmuteNamePattern,
+  ) -> Result<()> {
+    self
+      .send_frame(
+        flo_net::proto::flo_connect::PacketPlayerMuteRemoveRequest {
+          name_pattern: pattern,
+          ..Default::default()
+        }
+        .encode_as_frame()?,
       )
       .await?;
     Ok(())
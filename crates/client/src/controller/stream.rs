@@ -6,8 +6,10 @@ use crate::message::message::OutgoingMessage;
 use crate::node::{AddNode, GetNodePingMap, NodeRegistry, RemoveNode, UpdateNodes};
 use crate::ping::PingUpdate;
 use crate::platform::{CalcMapChecksum, GetClientPlatformInfo, Platform};
+use flo_net::capability::Capabilities;
 use flo_net::packet::*;
 use flo_net::proto::flo_connect as proto;
+use flo_net::proxy::ProxyConfig;
 use flo_net::stream::FloStream;
 use flo_state::{async_trait, Actor, Addr, Context, Handler, Message};
 use flo_types::game::*;
@@ -22,6 +24,7 @@ use tracing_futures::Instrument;
 pub struct ControllerStream {
   id: u64,
   domain: String,
+  proxy: Option<String>,
   token: String,
   parent: Addr<ControllerClient>,
   frame_tx: Sender<Frame>,
@@ -38,12 +41,14 @@ impl ControllerStream {
     nodes: Addr<NodeRegistry>,
     id: u64,
     domain: &str,
+    proxy: Option<String>,
     token: String,
   ) -> Self {
     let (frame_tx, frame_rx) = channel(5);
     Self {
       id,
       domain: domain.to_string(),
+      proxy,
       token: token.to_string(),
       parent,
       frame_tx,
@@ -89,6 +94,7 @@ impl ControllerStream {
   async fn connect_and_serve(
     id: u64,
     domain: &str,
+    proxy: Option<&str>,
     token: String,
     mut frame_receiver: Receiver<Frame>,
     owner: Addr<Self>,
@@ -98,7 +104,13 @@ impl ControllerStream {
     let addr = format!("{}:{}", domain, flo_constants::CONTROLLER_SOCKET_PORT);
     tracing::debug!("connect addr: {}", addr);
 
-    let mut stream = FloStream::connect_no_delay(addr).await?;
+    let mut stream = match proxy.map(str::parse::<ProxyConfig>).transpose()? {
+      Some(proxy) => {
+        tracing::debug!("connect via proxy: {}:{}", proxy.host, proxy.port);
+        FloStream::connect_proxy(&proxy, (domain, flo_constants::CONTROLLER_SOCKET_PORT)).await?
+      }
+      None => FloStream::connect_no_delay(addr).await?,
+    };
 
     tracing::debug!("connected");
 
@@ -106,20 +118,29 @@ impl ControllerStream {
       .send(proto::PacketClientConnect {
         connect_version: Some(crate::version::FLO_VERSION.into()),
         token,
+        capabilities: Some(Capabilities::local().into()),
       })
       .await?;
 
     let reply = stream.recv_frame().await?;
 
-    let (session, nodes): (PlayerSession, _) = flo_net::try_flo_packet! {
+    let (session, nodes, latest_release, capabilities) = flo_net::try_flo_packet! {
       reply => {
         p: proto::PacketClientConnectAccept => {
           (
             PlayerSession::unpack(p.session)?,
-            p.nodes
+            p.nodes,
+            p.latest_release,
+            p.capabilities
           )
         }
         p: proto::PacketClientConnectReject => {
+          if let Some(upgrade) = p.upgrade_required {
+            return Err(Error::UpgradeRequired(message::UpgradeRequired {
+              min_version: upgrade.min_version.map(|v| v.to_string()).unwrap_or_default(),
+              download_url: upgrade.download_url,
+            }))
+          }
           return Err(Error::ConnectionRequestRejected(S2ProtoEnum::unpack_enum(p.reason())))
         }
       }
@@ -127,6 +148,9 @@ impl ControllerStream {
 
     let player_id = session.player.id;
 
+    stream.set_capabilities(Capabilities::local().negotiate(capabilities.as_ref()));
+    tracing::debug!(player_id, "capabilities: {:?}", stream.capabilities());
+
     tracing::debug!(
       player_id,
       "player = {}, status = {:?}",
@@ -144,6 +168,11 @@ impl ControllerStream {
       )
       .await?;
     parent.send(UpdateNodes { nodes }).await??;
+    if let Some(release) = latest_release {
+      parent
+        .notify(ControllerEventData::ReleaseAvailable(release).wrap(id))
+        .await?;
+    }
     parent
       .notify(SendWs::new(
         id,
@@ -151,6 +180,8 @@ impl ControllerStream {
       ))
       .await?;
 
+    let mut last_reliable_seq = 0;
+
     loop {
       tokio::select! {
         next_send = frame_receiver.recv() => {
@@ -183,6 +214,26 @@ impl ControllerStream {
                 }
               }
 
+              // acknowledge then unwrap, duplicate messages are only acknowledged again
+              if frame.type_id == PacketTypeId::ReliableMessage {
+                let msg: proto::PacketReliableMessage = match frame.decode() {
+                  Ok(msg) => msg,
+                  Err(e) => {
+                    tracing::error!("decode reliable message: {}", e);
+                    continue;
+                  }
+                };
+                if let Err(e) = stream.send(proto::PacketReliableMessageAck { seq: msg.seq }).await {
+                  tracing::debug!("exiting: send error: {}", e);
+                  break;
+                }
+                if msg.seq <= last_reliable_seq {
+                  continue;
+                }
+                last_reliable_seq = msg.seq;
+                frame = Frame::new(PacketTypeId::from(msg.type_id as u8), msg.payload);
+              }
+
               match Self::handle_frame(id, player_id, frame, &mut stream, &owner, &parent, &nodes_reg).await {
                 Ok(_) => {},
                 Err(e) => {
@@ -231,9 +282,13 @@ impl ControllerStream {
     flo_net::try_flo_packet! {
       frame => {
         p: proto::PacketClientDisconnect => {
+          let message = match p.taken_over_by_ip {
+            Some(ip) => format!("Session taken over by a connection from {}", ip),
+            None => format!("Server closed the connection: {:?}", p.reason),
+          };
           SendWs::new(id, OutgoingMessage::Disconnect(message::Disconnect {
               reason: S2ProtoEnum::unpack_i32(p.reason)?,
-              message: format!("Server closed the connection: {:?}", p.reason)
+              message,
             })).notify(parent).await?;
         }
         p: proto::PacketGameInfo => {
@@ -246,7 +301,15 @@ impl ControllerStream {
           let local_game_info = Arc::new(LocalGameInfo::from_game_info(player_id, &game)?);
           owner.send(SetLocalGameInfo(local_game_info.clone().into())).await??;
 
+          #[cfg(feature = "blacklist")]
+          let blacklist_warning = get_blacklist_warning(player_id, game.id, &game.slots);
+
           SendWs::new(id, OutgoingMessage::CurrentGameInfo(game)).notify(parent).await?;
+
+          #[cfg(feature = "blacklist")]
+          if let Some(warning) = blacklist_warning {
+            SendWs::new(id, OutgoingMessage::BlacklistWarning(warning)).notify(parent).await?;
+          }
         }
         p: proto::PacketGamePlayerEnter => {
           let slot_index = p.slot_index;
@@ -262,10 +325,20 @@ impl ControllerStream {
               }
             }
           )).await??;
+          let enter: message::GamePlayerEnter = S2ProtoUnpack::unpack(p)?;
+
+          #[cfg(feature = "blacklist")]
+          let blacklist_warning = get_blacklist_warning(player_id, enter.game_id, std::slice::from_ref(&enter.slot));
+
           SendWs::new(
             id,
-            OutgoingMessage::GamePlayerEnter(S2ProtoUnpack::unpack(p)?)
+            OutgoingMessage::GamePlayerEnter(enter)
           ).notify(parent).await?;
+
+          #[cfg(feature = "blacklist")]
+          if let Some(warning) = blacklist_warning {
+            SendWs::new(id, OutgoingMessage::BlacklistWarning(warning)).notify(parent).await?;
+          }
         }
         p: proto::PacketGamePlayerLeave => {
           owner.send(UpdateLocalGameInfo::new({
@@ -320,6 +393,23 @@ impl ControllerStream {
             .send(UpdateNodes{ nodes: p.nodes.clone() })
             .await??;
         }
+        p: proto::PacketPlayerInfoUpdate => {
+          if let Some(player) = p.player.map(PlayerInfo::unpack).transpose()? {
+            parent.notify(ControllerEventData::PlayerInfoUpdate(player.clone()).wrap(id)).await?;
+            // fails with `LocalGameInfoNotFound` if not in a game
+            owner.send(UpdateLocalGameInfo::new({
+              let player = player.clone();
+              move |info| -> Result<_> {
+                info.update_player(&player);
+                Ok(())
+              }
+            })).await?.ok();
+            SendWs::new(
+              id,
+              OutgoingMessage::PlayerInfoUpdate(player)
+            ).notify(parent).await?;
+          }
+        }
         p: proto::PacketGameSelectNode => {
           parent.notify(ControllerEventData::SelectNode(p.node_id).wrap(id)).await?;
           owner.send(UpdateLocalGameInfo::new({
@@ -346,6 +436,50 @@ impl ControllerStream {
             OutgoingMessage::GamePlayerPingMapSnapshot(p)
           ).notify(parent).await?;
         }
+        p: proto::PacketGameMapVote => {
+          SendWs::new(
+            id,
+            OutgoingMessage::GameMapVote(p)
+          ).notify(parent).await?;
+        }
+        p: proto::PacketGameSummary => {
+          tracing::debug!(game_id = p.game_id, "game summary: {:?}", p);
+          SendWs::new(
+            id,
+            OutgoingMessage::GameSummary(p)
+          ).notify(parent).await?;
+        }
+        p: proto::PacketGameSlotPingUpdate => {
+          SendWs::new(
+            id,
+            OutgoingMessage::GameSlotPingUpdate(p)
+          ).notify(parent).await?;
+        }
+        p: proto::PacketGameChat => {
+          SendWs::new(
+            id,
+            OutgoingMessage::GameChat(p)
+          ).notify(parent).await?;
+        }
+        p: proto::PacketGameChatClear => {
+          SendWs::new(
+            id,
+            OutgoingMessage::GameChatClear(p)
+          ).notify(parent).await?;
+        }
+        p: proto::PacketGameRefereeUpdate => {
+          SendWs::new(
+            id,
+            OutgoingMessage::GameRefereeUpdate(p)
+          ).notify(parent).await?;
+        }
+        p: proto::PacketGameInvite => {
+          tracing::info!(game_id = p.game_id, "invited to rehosted game");
+          SendWs::new(
+            id,
+            OutgoingMessage::GameInvite(p)
+          ).notify(parent).await?;
+        }
         p: proto::PacketGameStartReject => {
           SendWs::new(
             id,
@@ -374,6 +508,7 @@ impl ControllerStream {
             if info.game_id == p.game_id {
               parent.notify(ControllerEventData::GameReceived(GameReceivedEvent {
                 node_id: p.node_id,
+                relay_node_id: p.relay_node_id,
                 game_info: info,
                 player_token: p.player_token,
               }).wrap(id)).await?;
@@ -385,9 +520,10 @@ impl ControllerStream {
           }
         }
         p: proto::PacketPlayerMuteListUpdate => {
-          tracing::debug!("mute list update: {:?}", p.mute_list);
+          tracing::debug!("mute list update: {:?}, game: {:?}", p.mute_list, p.game_mute_list);
           parent.notify(UpdateMuteList {
-            mute_list: p.mute_list
+            mute_list: p.mute_list,
+            game_mute_list: p.game_mute_list,
           }).await?;
         }
         // client status update from node
@@ -450,17 +586,33 @@ impl Actor for ControllerStream {
       {
         let id = self.id;
         let domain = self.domain.clone();
+        let proxy = self.proxy.clone();
         let token = self.token.clone();
         let owner = ctx.addr();
         let parent = self.parent.clone();
         let nodes = self.nodes.clone();
         async move {
-          if let Err(err) =
-            Self::connect_and_serve(id, &domain, token, frame_rx, owner, parent.clone(), nodes)
-              .await
+          if let Err(err) = Self::connect_and_serve(
+            id,
+            &domain,
+            proxy.as_deref(),
+            token,
+            frame_rx,
+            owner,
+            parent.clone(),
+            nodes,
+          )
+          .await
           {
             tracing::error!("controller stream error: {}", err);
 
+            if let Error::UpgradeRequired(upgrade) = &err {
+              SendWs::new(id, OutgoingMessage::UpgradeRequired(upgrade.clone()))
+                .notify(&parent)
+                .await
+                .ok();
+            }
+
             SendWs::new(
               id,
               OutgoingMessage::ConnectRejected(message::ErrorMessage::new(match &err {
@@ -695,6 +847,8 @@ pub enum ControllerEventData {
   GameInfoUpdate(GameInfoUpdateEvent),
   GameReceived(GameReceivedEvent),
   SelectNode(Option<i32>),
+  PlayerInfoUpdate(PlayerInfo),
+  ReleaseAvailable(proto::ClientRelease),
   Disconnected,
 }
 
@@ -704,6 +858,39 @@ impl ControllerEventData {
   }
 }
 
+/// Checks the players of `slots` against the local blacklist,
+/// so that the player is warned while still in the lobby
+#[cfg(feature = "blacklist")]
+fn get_blacklist_warning(
+  player_id: i32,
+  game_id: i32,
+  slots: &[Slot],
+) -> Option<message::BlacklistWarning> {
+  use flo_w3c::blacklist;
+
+  let players: Vec<_> = slots
+    .iter()
+    .filter_map(|slot| slot.player.as_ref())
+    .filter(|player| player.id != player_id)
+    .filter_map(|player| match blacklist::read(&player.name) {
+      Ok(entry) => entry.map(|entry| message::BlacklistedPlayer {
+        player_id: player.id,
+        name: player.name.clone(),
+        reason: entry.to_string(),
+      }),
+      Err(err) => {
+        tracing::warn!("read blacklist: {}", err);
+        None
+      }
+    })
+    .collect();
+  if players.is_empty() {
+    None
+  } else {
+    Some(message::BlacklistWarning { game_id, players })
+  }
+}
+
 #[derive(Debug)]
 pub enum PlayerSessionUpdateEvent {
   Full(PlayerSession),
@@ -718,6 +905,8 @@ pub struct GameInfoUpdateEvent {
 #[derive(Debug)]
 pub struct GameReceivedEvent {
   pub node_id: i32,
+  /// Node forwarding the connection to `node_id`
+  pub relay_node_id: Option<i32>,
   pub game_info: Arc<LocalGameInfo>,
   pub player_token: Vec<u8>,
 }
@@ -24,6 +24,8 @@ pub enum Error {
   UnexpectedW3GSPacket(flo_w3gs::packet::Packet),
   #[error("Slot not resolved")]
   SlotNotResolved,
+  #[error("Invalid handicap: {0}")]
+  InvalidHandicap(i32),
   #[error("Stream closed unexpectedly")]
   StreamClosed,
   #[error("Disconnected from Flo controller")]
@@ -38,6 +40,8 @@ pub enum Error {
   War3NotLocated,
   #[error("Connection request rejected by server: {0:?}")]
   ConnectionRequestRejected(flo_types::game::RejectReason),
+  #[error("Client upgrade required: {}, download: {}", .0.min_version, .0.download_url)]
+  UpgradeRequired(crate::message::message::UpgradeRequired),
   #[error("Connection request rejected by server: {0:?}")]
   ObserverConnectionRequestRejected(flo_net::observer::ObserverConnectRejectReason),
   #[error("Local game info not yet received")]
@@ -75,6 +79,10 @@ pub enum Error {
   Json(#[from] serde_json::Error),
   #[error("Io: {0}")]
   Io(#[from] std::io::Error),
+  #[error("Http: {0}")]
+  Http(#[from] hyper::Error),
+  #[error("Update: {0}")]
+  Update(String),
 }
 
 pub type Result<T, E = Error> = std::result::Result<T, E>;
@@ -1,5 +1,5 @@
 use crate::error::{Error, Result};
-use flo_types::game::{GameInfo, PlayerInfo, Slot};
+use flo_types::game::{GameInfo, GameMode, PlayerInfo, Slot};
 use std::collections::HashMap;
 
 #[derive(Debug, Clone)]
@@ -15,6 +15,7 @@ pub struct LocalGameInfo {
   pub players: HashMap<i32, PlayerInfo>,
   pub slots: Vec<Slot>,
   pub host_player: Option<PlayerInfo>,
+  pub game_mode: GameMode,
 }
 
 impl LocalGameInfo {
@@ -42,6 +43,22 @@ impl LocalGameInfo {
         .collect(),
       slots: game.slots.clone(),
       host_player: game.created_by.clone(),
+      game_mode: game.game_mode,
     })
   }
+
+  /// Replaces the info of a renamed player
+  pub fn update_player(&mut self, player: &PlayerInfo) {
+    if let Some(v) = self.players.get_mut(&player.id) {
+      *v = player.clone();
+    }
+    for slot in &mut self.slots {
+      if slot.player.as_ref().map(|p| p.id) == Some(player.id) {
+        slot.player = Some(player.clone());
+      }
+    }
+    if self.host_player.as_ref().map(|p| p.id) == Some(player.id) {
+      self.host_player = Some(player.clone());
+    }
+  }
 }
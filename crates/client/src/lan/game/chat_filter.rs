@@ -0,0 +1,75 @@
+use std::collections::BTreeSet;
+use std::sync::Arc;
+
+/// Masks words of the `chat_filter_words` list in chat messages received during the game
+#[derive(Debug, Clone)]
+pub struct ChatFilter {
+  words: Arc<BTreeSet<String>>,
+}
+
+impl ChatFilter {
+  /// Returns `None` if the list is empty
+  pub fn new<I, S>(words: I) -> Option<Self>
+  where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+  {
+    let words: BTreeSet<String> = words
+      .into_iter()
+      .map(|w| w.as_ref().trim().to_lowercase())
+      .filter(|w| !w.is_empty())
+      .collect();
+    if words.is_empty() {
+      None
+    } else {
+      Some(ChatFilter {
+        words: Arc::new(words),
+      })
+    }
+  }
+
+  /// Replaces each character of listed words with `*`, matching is case insensitive.
+  /// Returns `None` if nothing was masked
+  pub fn mask(&self, message: &str) -> Option<String> {
+    let mut output = String::with_capacity(message.len());
+    let mut masked = false;
+    let mut word_start = None;
+    for (i, c) in message.char_indices().chain(Some((message.len(), ' '))) {
+      if c.is_alphanumeric() {
+        word_start.get_or_insert(i);
+        continue;
+      }
+      if let Some(start) = word_start.take() {
+        let word = &message[start..i];
+        if self.words.contains(&word.to_lowercase()) {
+          masked = true;
+          output.extend(word.chars().map(|_| '*'));
+        } else {
+          output.push_str(word);
+        }
+      }
+      if i < message.len() {
+        output.push(c);
+      }
+    }
+    if masked {
+      Some(output)
+    } else {
+      None
+    }
+  }
+}
+
+#[test]
+fn test_chat_filter() {
+  assert!(ChatFilter::new(vec!["", " "]).is_none());
+
+  let filter = ChatFilter::new(vec!["Noob", "gg"]).unwrap();
+  assert_eq!(
+    filter.mask("you NOOB, gg!"),
+    Some("you ****, **!".to_string())
+  );
+  assert_eq!(filter.mask("noobs ggg"), None);
+  assert_eq!(filter.mask("gg"), Some("**".to_string()));
+  assert_eq!(filter.mask(""), None);
+}
@@ -0,0 +1,65 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// `-stats` sends a request to the stats API for each target player
+const STATS_COOLDOWN: Duration = Duration::from_secs(10);
+const REHOST_COOLDOWN: Duration = Duration::from_secs(10);
+const BLACKLIST_COOLDOWN: Duration = Duration::from_secs(2);
+
+/// Limits how often the local player can run chat commands that do more than printing local state
+#[derive(Debug, Default)]
+pub struct CommandCooldowns {
+  last_used: HashMap<&'static str, Instant>,
+}
+
+impl CommandCooldowns {
+  /// Records a use of `command`.
+  /// Returns the remaining time if the command is still cooling down, commands without cooldown are always allowed
+  pub fn try_use(&mut self, command: &str, now: Instant) -> Result<(), Duration> {
+    let (command, cooldown) = match get_cooldown(command) {
+      Some(v) => v,
+      None => return Ok(()),
+    };
+    if let Some(last_used) = self.last_used.get(command) {
+      let elapsed = now.saturating_duration_since(*last_used);
+      if elapsed < cooldown {
+        return Err(cooldown - elapsed);
+      }
+    }
+    self.last_used.insert(command, now);
+    Ok(())
+  }
+}
+
+fn get_cooldown(command: &str) -> Option<(&'static str, Duration)> {
+  match command {
+    "stats" => Some(("stats", STATS_COOLDOWN)),
+    "rehost" => Some(("rehost", REHOST_COOLDOWN)),
+    "blacklist" | "unblacklist" | "blacklisted" => Some(("blacklist", BLACKLIST_COOLDOWN)),
+    _ => None,
+  }
+}
+
+#[test]
+fn test_command_cooldowns() {
+  let mut cooldowns = CommandCooldowns::default();
+  let now = Instant::now();
+
+  assert_eq!(cooldowns.try_use("stats", now), Ok(()));
+  assert_eq!(
+    cooldowns.try_use("stats", now + Duration::from_secs(4)),
+    Err(Duration::from_secs(6))
+  );
+  // other commands have their own cooldown
+  assert_eq!(cooldowns.try_use("rehost", now), Ok(()));
+  assert_eq!(cooldowns.try_use("game", now), Ok(()));
+  assert_eq!(cooldowns.try_use("game", now), Ok(()));
+  // rejected uses don't extend the cooldown
+  assert_eq!(cooldowns.try_use("stats", now + STATS_COOLDOWN), Ok(()));
+
+  assert_eq!(cooldowns.try_use("blacklist", now), Ok(()));
+  assert_eq!(
+    cooldowns.try_use("unblacklist", now + Duration::from_secs(1)),
+    Err(Duration::from_secs(1))
+  );
+}
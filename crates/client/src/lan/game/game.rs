@@ -1,12 +1,20 @@
-use crate::controller::{ControllerClient, GetMuteList, MutePlayer, UnmutePlayer};
+use crate::controller::{
+  ControllerClient, GetMuteList, MuteNamePattern, MutePlayer, RehostGame, UnmuteNamePattern,
+  UnmutePlayer, UpdateGameMuteList,
+};
 use crate::error::*;
+use crate::lan::game::chat_filter::ChatFilter;
+use crate::lan::game::command_cooldown::CommandCooldowns;
 use crate::lan::game::{GameEndReason, LanGameInfo};
 use crate::node::stream::NodeStreamSender;
 use crate::node::NodeInfo;
+use crate::overlay::GameOverlay;
 use flo_net::w3gs::W3GSPacket;
 use flo_state::Addr;
 use flo_types::node::NodeGameStatus;
+use flo_util::binary::IntoCStringLossy;
 use flo_util::chat::{parse_chat_command, ChatCommand};
+use flo_util::name_pattern;
 #[cfg(feature = "blacklist")]
 use flo_w3c::blacklist;
 use flo_w3c::stats::get_stats;
@@ -21,11 +29,13 @@ use flo_w3gs::protocol::leave::LeaveAck;
 use flo_w3gs::protocol::ping::PingFromHost;
 use parking_lot::Mutex;
 use std::collections::BTreeSet;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use tokio::sync::mpsc::{Receiver, Sender};
 use tokio::sync::watch::Receiver as WatchReceiver;
 use tokio::time::interval;
 
+const W3GS_SEND_BATCH_SIZE: usize = 32;
+
 #[derive(Debug)]
 pub enum GameResult {
   Disconnected,
@@ -43,6 +53,10 @@ pub struct GameHandler<'a> {
   client: &'a mut Addr<ControllerClient>,
   muted_players: BTreeSet<u8>,
   end_reason: &'a Mutex<Option<GameEndReason>>,
+  overlay: Option<GameOverlay>,
+  chat_filter: Option<ChatFilter>,
+  chat_filter_enabled: bool,
+  command_cooldowns: CommandCooldowns,
 }
 
 impl<'a> GameHandler<'a> {
@@ -56,6 +70,8 @@ impl<'a> GameHandler<'a> {
     w3gs_rx: &'a mut Receiver<Packet>,
     client: &'a mut Addr<ControllerClient>,
     end_reason: &'a Mutex<Option<GameEndReason>>,
+    overlay: Option<GameOverlay>,
+    chat_filter: Option<ChatFilter>,
   ) -> Self {
     GameHandler {
       info,
@@ -68,6 +84,10 @@ impl<'a> GameHandler<'a> {
       client,
       muted_players: BTreeSet::new(),
       end_reason,
+      overlay,
+      chat_filter,
+      chat_filter_enabled: true,
+      command_cooldowns: CommandCooldowns::default(),
     }
   }
 
@@ -76,16 +96,18 @@ impl<'a> GameHandler<'a> {
     deferred_in_packets: Vec<Packet>,
     deferred_out_packets: Vec<Packet>,
   ) -> Result<GameResult> {
-    let mute_list = if let Ok(v) = self.client.send(GetMuteList).await {
-      v
-    } else {
-      vec![]
-    };
+    let mute_lists = self.client.send(GetMuteList).await.unwrap_or_default();
     let mut muted_names = vec![];
     #[cfg(feature = "blacklist")]
+    if let Err(err) = blacklist::prune_expired() {
+      tracing::warn!("prune expired blacklist entries: {}", err);
+    }
+    #[cfg(feature = "blacklist")]
     let mut blacklisted = vec![];
     for p in &self.info.slot_info.player_infos {
-      if mute_list.contains(&p.player_id) {
+      if mute_lists.mute_list.contains(&p.player_id)
+        || mute_lists.game_mute_list.contains(&p.player_id)
+      {
         muted_names.push(p.name.clone());
         self.muted_players.insert(p.slot_player_id);
       }
@@ -164,7 +186,7 @@ impl<'a> GameHandler<'a> {
         }
         next = self.w3gs_rx.recv() => {
           if let Some(pkt) = next {
-            self.handle_incoming_w3gs(pkt).await?;
+            self.handle_incoming_w3gs_batch(pkt).await?;
           } else {
             return Err(Error::TaskCancelled(anyhow::format_err!("W3GS tx dropped")))
           }
@@ -175,19 +197,61 @@ impl<'a> GameHandler<'a> {
 
   #[inline]
   async fn handle_incoming_w3gs(&mut self, pkt: Packet) -> Result<()> {
+    if let Some(pkt) = self.filter_incoming_w3gs(pkt)? {
+      self.w3gs_stream.send(pkt).await?;
+    }
+    Ok(())
+  }
+
+  /// Writes the packets already received from the node at once
+  async fn handle_incoming_w3gs_batch(&mut self, pkt: Packet) -> Result<()> {
+    let mut batch = Vec::with_capacity(W3GS_SEND_BATCH_SIZE);
+    let mut next = Some(pkt);
+    while let Some(pkt) = next.take() {
+      if let Some(pkt) = self.filter_incoming_w3gs(pkt)? {
+        batch.push(pkt);
+      }
+      if batch.len() < W3GS_SEND_BATCH_SIZE {
+        next = self.w3gs_rx.try_recv().ok();
+      }
+    }
+    match batch.len() {
+      0 => {}
+      1 => self.w3gs_stream.send(batch.remove(0)).await?,
+      _ => self.w3gs_stream.send_all(batch).await?,
+    }
+    Ok(())
+  }
+
+  fn filter_incoming_w3gs(&mut self, pkt: Packet) -> Result<Option<Packet>> {
+    if let Some(overlay) = self.overlay.as_mut() {
+      overlay.handle_packet(&pkt);
+    }
+
     match pkt.type_id() {
       OutgoingKeepAlive::PACKET_TYPE_ID => {}
       OutgoingAction::PACKET_TYPE_ID => {}
       ChatFromHost::PACKET_TYPE_ID => {
-        if !self.muted_players.is_empty() {
-          let pkt: ChatFromHost = pkt.decode_simple()?;
+        let filter = self
+          .chat_filter
+          .clone()
+          .filter(|_| self.chat_filter_enabled);
+        if !self.muted_players.is_empty() || filter.is_some() {
+          let mut chat: ChatFromHost = pkt.decode_simple()?;
+          let from_player = chat.from_player();
           if let ChatToHost {
-            message: ChatMessage::Scoped { .. },
+            message: ChatMessage::Scoped {
+              ref mut message, ..
+            },
             ..
-          } = pkt.0
+          } = chat.0
           {
-            if self.muted_players.contains(&pkt.from_player()) {
-              return Ok(());
+            if self.muted_players.contains(&from_player) {
+              return Ok(None);
+            }
+            if let Some(masked) = filter.and_then(|f| f.mask(&message.to_string_lossy())) {
+              *message = masked.as_str().into_c_string_lossy();
+              return Ok(Some(Packet::simple(chat)?));
             }
           }
         }
@@ -197,8 +261,7 @@ impl<'a> GameHandler<'a> {
 
     // tracing::debug!("send: {:?}", pkt.type_id());
 
-    self.w3gs_stream.send(pkt).await?;
-    Ok(())
+    Ok(Some(pkt))
   }
 
   async fn handle_game_status_change(&mut self, status: NodeGameStatus) -> Result<()> {
@@ -214,7 +277,12 @@ impl<'a> GameHandler<'a> {
         match pkt.message {
           ChatMessage::Scoped { message, .. } => {
             if let Some(cmd) = parse_chat_command(message.as_bytes()) {
-              if self.handle_chat_command(cmd) {
+              let muted_players = self.muted_players.clone();
+              let handled = self.handle_chat_command(cmd);
+              if self.muted_players != muted_players {
+                self.save_game_mutes();
+              }
+              if handled {
                 return Ok(());
               }
             }
@@ -251,6 +319,19 @@ impl<'a> GameHandler<'a> {
   }
 
   fn handle_chat_command(&mut self, cmd: ChatCommand) -> bool {
+    let name = cmd.raw().split_whitespace().next().unwrap_or_default();
+    if let Err(wait) = self.command_cooldowns.try_use(name, Instant::now()) {
+      self.send_chats_to_self(
+        self.info.slot_info.my_slot_player_id,
+        vec![format!(
+          "Please wait {} seconds before using -{} again.",
+          (wait.as_millis() + 999) / 1000,
+          name
+        )],
+      );
+      return true;
+    }
+
     match cmd.raw() {
       "flo" => {
         let messages = vec![
@@ -260,9 +341,14 @@ impl<'a> GameHandler<'a> {
           "-unmuteall: Unmute all players.".to_string(),
           "-mute/mutef: Mute your opponent (1v1), or display a player list.".to_string(),
           "-mute/mutef <ID>: Mute a player.".to_string(),
+          "-mutef <pattern>: Mute players matching a name pattern, e.g. -mutef smurf*".to_string(),
           "-unmute/unmutef: Unmute your opponent (1v1), or display a player list.".to_string(),
           "-unmute/unmutef <ID>: Unmute a player.".to_string(),
           "-rtt: Print round-trip time information.".to_string(),
+          "-delay: Print the game step and the delay of each player.".to_string(),
+          "-time: Print elapsed game time and start time.".to_string(),
+          "-filter: Toggle the chat filter.".to_string(),
+          "-rehost: Create a new lobby with the same map and players (host only).".to_string(),
           "-stats: Print opponent/opponents statistics.".to_string(),
           "-stats <ID>: Print player statistics, or display a player list.".to_string(),
         ];
@@ -271,8 +357,8 @@ impl<'a> GameHandler<'a> {
       "game" => {
         let mut messages = vec![
           format!(
-            "Game: {} (#{})",
-            self.info.game.name, self.info.game.game_id
+            "Game: {} (#{}), {:?}",
+            self.info.game.name, self.info.game.game_id, self.info.game.game_mode
           ),
           format!(
             "Server: {}, {}, {} (#{})",
@@ -322,7 +408,9 @@ impl<'a> GameHandler<'a> {
             if slot.slot_player_id == self.info.slot_info.my_slot_player_id {
               return None;
             }
-            if self.info.game.slots[slot.slot_index].settings.team == my_team as i32 {
+            if self.info.game.game_mode.is_team_based()
+              && self.info.game.slots[slot.slot_index].settings.team == my_team as i32
+            {
               return None;
             }
             Some(slot.slot_player_id)
@@ -334,6 +422,39 @@ impl<'a> GameHandler<'a> {
           vec![format!("All opponents muted.")],
         );
       }
+      "filter" => {
+        let message = if self.chat_filter.is_none() {
+          "Chat filter is not configured, add words to `chat_filter_words` in flo.toml."
+        } else {
+          self.chat_filter_enabled = !self.chat_filter_enabled;
+          if self.chat_filter_enabled {
+            "Chat filter enabled."
+          } else {
+            "Chat filter disabled."
+          }
+        };
+        self.send_chats_to_self(
+          self.info.slot_info.my_slot_player_id,
+          vec![message.to_string()],
+        );
+      }
+      "rehost" => {
+        let is_host = self
+          .info
+          .game
+          .host_player
+          .as_ref()
+          .map(|p| p.id == self.info.game.player_id)
+          .unwrap_or_default();
+        if !is_host {
+          self.send_chats_to_self(
+            self.info.slot_info.my_slot_player_id,
+            vec!["Only the host can rehost the game.".to_string()],
+          );
+        } else {
+          self.rehost();
+        }
+      }
       "unmuteall" => {
         self.muted_players.clear();
         self.send_chats_to_self(
@@ -359,7 +480,9 @@ impl<'a> GameHandler<'a> {
               if slot.slot_player_id == self.info.slot_info.my_slot_player_id {
                 return None;
               }
-              if self.info.game.slots[slot.slot_index].settings.team == my_team as i32 {
+              if self.info.game.game_mode.is_team_based()
+                && self.info.game.slots[slot.slot_index].settings.team == my_team as i32
+              {
                 return None;
               }
               Some((
@@ -445,7 +568,9 @@ impl<'a> GameHandler<'a> {
           &cmd["blacklist ".len()..]
         };
         if args.is_empty() {
-          let mut msgs = vec![format!("Type `-blacklist <ID>` to blacklist:")];
+          let mut msgs = vec![format!(
+            "Type `-blacklist <ID> [30d] <reason>` to blacklist:"
+          )];
           for slot in &self.info.slot_info.player_infos {
             msgs.push(format!(
               " ID={} {}",
@@ -457,12 +582,13 @@ impl<'a> GameHandler<'a> {
         } else {
           let args_split: Vec<&str> = args.split_whitespace().collect();
           let id_or_name = args_split[0];
-          let reason = if args_split.len() > 1 {
-            args_split
-              .into_iter()
-              .skip(1)
-              .collect::<Vec<&str>>()
-              .join(" ")
+          let duration = args_split.get(1).and_then(|v| blacklist::parse_duration(v));
+          let duration_desc = duration
+            .map(|_| format!(" for {}", args_split[1]))
+            .unwrap_or_default();
+          let skip = if duration.is_some() { 2 } else { 1 };
+          let reason = if args_split.len() > skip {
+            args_split[skip..].join(" ")
           } else {
             "no reason".to_string()
           };
@@ -486,10 +612,10 @@ impl<'a> GameHandler<'a> {
                   );
                 }
               } else {
-                if blacklist::blacklist(targets[0].as_str(), &reason).is_ok() {
+                if blacklist::blacklist(targets[0].as_str(), &reason, duration).is_ok() {
                   self.send_chats_to_self(
                     self.info.slot_info.my_slot_player_id,
-                    vec![format!("{} blacklisted", &targets[0])],
+                    vec![format!("{} blacklisted{}", &targets[0], duration_desc)],
                   );
                 }
               }
@@ -518,10 +644,10 @@ impl<'a> GameHandler<'a> {
                   );
                 }
               } else {
-                if blacklist::blacklist(targets[0].as_str(), &reason).is_ok() {
+                if blacklist::blacklist(targets[0].as_str(), &reason, duration).is_ok() {
                   self.send_chats_to_self(
                     self.info.slot_info.my_slot_player_id,
-                    vec![format!("{} blacklisted", &targets[0])],
+                    vec![format!("{} blacklisted{}", &targets[0], duration_desc)],
                   );
                 }
               }
@@ -620,6 +746,8 @@ impl<'a> GameHandler<'a> {
                 msgs
               });
             }
+          } else if forever && name_pattern::is_pattern(id) {
+            self.save_mute_pattern(id, true);
           } else {
             self.send_chats_to_self(
               self.info.slot_info.my_slot_player_id,
@@ -710,6 +838,8 @@ impl<'a> GameHandler<'a> {
                 msgs
               });
             }
+          } else if forever && name_pattern::is_pattern(id) {
+            self.save_mute_pattern(id, false);
           } else {
             self.send_chats_to_self(
               self.info.slot_info.my_slot_player_id,
@@ -744,6 +874,112 @@ impl<'a> GameHandler<'a> {
     tokio::spawn(async move { send_chats_to_self(&mut tx, player_id, messages).await });
   }
 
+  fn rehost(&self) {
+    let mut tx = self.w3gs_tx.clone();
+    let client = self.client.clone();
+    let game_id = self.info.game.game_id;
+    let my_slot_player_id = self.info.slot_info.my_slot_player_id;
+    tokio::spawn(async move {
+      let message = match client
+        .send(RehostGame { game_id })
+        .await
+        .map_err(Error::from)
+        .and_then(std::convert::identity)
+      {
+        Ok(_) => {
+          "Rehost requested, the other players will be invited to the new lobby.".to_string()
+        }
+        Err(err) => {
+          tracing::error!("rehost: {}", err);
+          format!("Rehost failed: {}", err)
+        }
+      };
+      send_chats_to_self(&mut tx, my_slot_player_id, vec![message]).await;
+    });
+  }
+
+  fn save_mute_pattern(&mut self, pattern: &str, muted: bool) {
+    let my_slot_player_id = self.info.slot_info.my_slot_player_id;
+    let pattern = match name_pattern::normalize(pattern) {
+      Some(pattern) => pattern,
+      None => {
+        self.send_chats_to_self(
+          my_slot_player_id,
+          vec![format!("Invalid name pattern. Example: -mutef smurf*")],
+        );
+        return;
+      }
+    };
+
+    let mut names = vec![];
+    for info in &self.info.slot_info.player_infos {
+      if info.slot_player_id == my_slot_player_id || !name_pattern::matches(&pattern, &info.name) {
+        continue;
+      }
+      if muted {
+        self.muted_players.insert(info.slot_player_id);
+      } else {
+        self.muted_players.remove(&info.slot_player_id);
+      }
+      names.push(info.name.clone());
+    }
+    let desc = if names.is_empty() {
+      pattern.clone()
+    } else {
+      format!("{} ({})", pattern, names.join(", "))
+    };
+
+    let mut tx = self.w3gs_tx.clone();
+    let client = self.client.clone();
+    tokio::spawn(async move {
+      let action = if muted { "Muted" } else { "Un-muted" };
+      let send = if muted {
+        client.send(MuteNamePattern { pattern }).await
+      } else {
+        client.send(UnmuteNamePattern { pattern }).await
+      }
+      .map_err(Error::from);
+      if let Err(err) = send.and_then(std::convert::identity) {
+        tracing::error!("save mute pattern failed: {}", err);
+        send_chats_to_self(
+          &mut tx,
+          my_slot_player_id,
+          vec![format!("{} temporary: {}", action, desc)],
+        )
+        .await;
+      } else {
+        send_chats_to_self(
+          &mut tx,
+          my_slot_player_id,
+          vec![format!("{} forever: {}", action, desc)],
+        )
+        .await;
+      }
+    });
+  }
+
+  fn save_game_mutes(&self) {
+    let client = self.client.clone();
+    let game_id = self.info.game.game_id;
+    let mute_list: Vec<i32> = self
+      .info
+      .slot_info
+      .player_infos
+      .iter()
+      .filter(|info| self.muted_players.contains(&info.slot_player_id))
+      .map(|info| info.player_id)
+      .collect();
+    tokio::spawn(async move {
+      let send = client
+        .send(UpdateGameMuteList { game_id, mute_list })
+        .await
+        .map_err(Error::from);
+      if let Err(err) = send.and_then(std::convert::identity) {
+        tracing::error!("save game mutes failed: {}", err);
+      }
+    });
+  }
+
   fn save_mute(&self, player_id: i32, name: String, muted: bool) {
     let mut tx = self.w3gs_tx.clone();
     let client = self.client.clone();
@@ -1,8 +1,11 @@
+mod chat_filter;
+mod command_cooldown;
 mod game;
 mod lobby;
 mod proxy;
 pub mod slot;
 
+pub use self::chat_filter::ChatFilter;
 pub use self::lobby::{LobbyAction, LobbyHandler};
 pub use self::proxy::GameEndReason;
 use crate::controller::ControllerClient;
@@ -11,12 +14,13 @@ use crate::game::LocalGameInfo;
 use crate::lan::game::proxy::PlayerEvent;
 use crate::lan::game::slot::LanSlotInfo;
 #[cfg(not(feature = "worker"))]
-use crate::lan::get_lan_game_name;
+use crate::lan::{get_lan_game_name, LanGameOptions};
 use crate::node::stream::NodeConnectToken;
 use crate::node::NodeInfo;
 use flo_lan::{GameInfo, MdnsPublisher};
 use flo_state::Addr;
 use flo_task::SpawnScope;
+use flo_types::game::SlotStatus;
 use flo_types::node::{NodeGameStatus, SlotClientStatus};
 use flo_w3gs::protocol::game::GameSettings;
 use flo_w3map::MapChecksum;
@@ -49,15 +53,20 @@ impl LanGame {
     player_token: Vec<u8>,
     game: Arc<LocalGameInfo>,
     map_checksum: MapChecksum,
+    options: LanGameOptions,
     client: Addr<ControllerClient>,
   ) -> Result<Self> {
     let mdns_shutdown_notify = Arc::new(Notify::new());
 
     let game_id = game.game_id;
     #[cfg(not(feature = "worker"))]
-    let game_name = get_lan_game_name(game.game_id, my_player_id);
+    let game_name = options
+      .game_name
+      .unwrap_or_else(|| get_lan_game_name(game.game_id, my_player_id));
     #[cfg(feature = "worker")]
-    let game_name = format!("{}-{}", game.name, my_player_id);
+    let game_name = options
+      .game_name
+      .unwrap_or_else(|| format!("{}-{}", game.name, my_player_id));
     let mut game_info = GameInfo::new(
       game.game_id,
       &game_name,
@@ -65,6 +74,12 @@ impl LanGame {
       game.map_sha1,
       game.map_checksum,
     )?;
+    game_info.game_id = options.host_counter.to_string();
+    game_info.players_num = game
+      .slots
+      .iter()
+      .filter(|slot| slot.settings.status == SlotStatus::Occupied && slot.settings.team != 24)
+      .count() as u8;
     let token = NodeConnectToken::from_vec(player_token).ok_or_else(|| Error::InvalidNodeToken)?;
 
     let proxy = LanProxy::start(
@@ -80,6 +95,10 @@ impl LanGame {
       },
       node,
       token,
+      options.port_range,
+      options.proxy,
+      options.overlay,
+      options.chat_filter,
       client.clone(),
     )
     .await?;
@@ -88,12 +107,13 @@ impl LanGame {
     let state = Arc::new(State {
       game_id,
       my_player_id,
+      game_name,
     });
     tokio::spawn(
       {
         let mut scope = scope.handle();
         let mdns_shutdown_notify = mdns_shutdown_notify.clone();
-        let publisher = MdnsPublisher::start(game_info).await?;
+        let publisher = MdnsPublisher::start_with_options(game_info, options.publisher).await?;
         async move {
           let _publisher = publisher;
           tokio::select! {
@@ -121,6 +141,10 @@ impl LanGame {
     self.state.game_id
   }
 
+  pub fn game_name(&self) -> &str {
+    &self.state.game_name
+  }
+
   pub async fn update_game_status(&self, status: NodeGameStatus) {
     if ![
       NodeGameStatus::Created,
@@ -159,4 +183,5 @@ impl LanGame {
 struct State {
   game_id: i32,
   my_player_id: i32,
+  game_name: String,
 }
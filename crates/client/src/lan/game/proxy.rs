@@ -1,5 +1,6 @@
 use crate::controller::ControllerClient;
 use crate::error::*;
+use crate::lan::game::chat_filter::ChatFilter;
 use crate::lan::game::game::GameHandler;
 use crate::lan::game::lobby::{LobbyAction, LobbyHandler};
 use crate::lan::game::slot::index_to_player_id;
@@ -7,6 +8,8 @@ use crate::lan::game::LanGameInfo;
 use crate::lan::LanEvent;
 use crate::node::stream::{NodeConnectToken, NodeStream, NodeStreamSender};
 use crate::node::NodeInfo;
+use crate::overlay::{GameOverlay, OverlaySender};
+use flo_net::proxy::ProxyConfig;
 use flo_state::Addr;
 use flo_task::{SpawnScope, SpawnScopeHandle};
 use flo_types::node::{NodeGameStatus, SlotClientStatus};
@@ -20,6 +23,7 @@ use flo_w3gs::protocol::packet::*;
 use flo_w3gs::protocol::ping::{PingFromHost, PongToHost};
 use parking_lot::Mutex;
 use std::collections::HashMap;
+use std::ops::RangeInclusive;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 use tokio::sync::mpsc::{channel, Receiver, Sender};
@@ -49,10 +53,17 @@ impl LanProxy {
     info: LanGameInfo,
     node: Arc<NodeInfo>,
     token: NodeConnectToken,
+    port_range: Option<RangeInclusive<u16>>,
+    proxy: Option<ProxyConfig>,
+    overlay: Option<OverlaySender>,
+    chat_filter: Option<ChatFilter>,
     client: Addr<ControllerClient>,
   ) -> Result<Self> {
     let scope = SpawnScope::new();
-    let listener = W3GSListener::bind().await?;
+    let listener = match port_range {
+      Some(range) => W3GSListener::bind_port_range(range).await?,
+      None => W3GSListener::bind().await?,
+    };
     let port = listener.port();
     let (status_tx, status_rx) = watch::channel(None);
     let (event_tx, event_rx) = channel(10);
@@ -66,6 +77,7 @@ impl LanProxy {
     let node_stream = NodeStream::connect(
       &info,
       node.client_socket_addr(),
+      proxy,
       token,
       client.clone(),
       w3gs_tx.clone(),
@@ -79,6 +91,8 @@ impl LanProxy {
       info,
       stream: node_stream.sender(),
       game_status_rx: status_rx,
+      overlay,
+      chat_filter,
     });
 
     tokio::spawn({
@@ -144,6 +158,8 @@ struct State {
   info: LanGameInfo,
   stream: NodeStreamSender,
   game_status_rx: watch::Receiver<Option<NodeGameStatus>>,
+  overlay: Option<OverlaySender>,
+  chat_filter: Option<ChatFilter>,
 }
 
 impl State {
@@ -265,6 +281,11 @@ impl State {
       &mut w3gs_rx,
       &mut client,
       &end_reason,
+      self
+        .overlay
+        .clone()
+        .map(|sender| GameOverlay::start(sender, &self.info)),
+      self.chat_filter.clone(),
     );
     tokio::select! {
       _ = &mut dropped => {}
@@ -97,7 +97,10 @@ where
       slot.race = player_slot.settings.race.into();
       slot.color = player_slot.settings.color as u8;
       slot.team = player_slot.settings.team as u8;
-      slot.handicap = player_slot.settings.handicap as u8;
+      slot.handicap = player_slot
+        .settings
+        .lan_handicap()
+        .ok_or_else(|| Error::InvalidHandicap(player_slot.settings.handicap))?;
       slot.download_status = 100;
     } else {
       slot.computer = true;
@@ -106,7 +109,10 @@ where
       slot.race = player_slot.settings.race.into();
       slot.color = player_slot.settings.color as u8;
       slot.team = player_slot.settings.team as u8;
-      slot.handicap = player_slot.settings.handicap as u8;
+      slot.handicap = player_slot
+        .settings
+        .lan_handicap()
+        .ok_or_else(|| Error::InvalidHandicap(player_slot.settings.handicap))?;
       slot.download_status = 100;
     }
   }
@@ -5,24 +5,32 @@ use lazy_static::lazy_static;
 use std::collections::HashMap;
 use std::sync::Arc;
 
-use game::LanGame;
+use game::{ChatFilter, LanGame};
 
 use crate::controller::ControllerClient;
 use crate::error::*;
 use crate::game::LocalGameInfo;
 use crate::node::stream::NodeStreamEvent;
 use crate::node::NodeInfo;
-use crate::platform::{CalcMapChecksum, Platform};
+use crate::overlay::{GetOverlaySender, Overlay, OverlaySender};
+use crate::platform::{CalcMapChecksum, GetClientConfig, Platform};
 use crate::StartConfig;
 use flo_state::{
   async_trait, Actor, Addr, Context, Deferred, Handler, Message, RegistryRef, Service,
 };
+use flo_config::LanHostCounter;
+use flo_lan::PublisherOptions;
+use flo_net::proxy::ProxyConfig;
 use flo_types::node::{NodeGameStatus, SlotClientStatus};
+use std::ops::RangeInclusive;
+use std::time::Duration;
 
 pub struct Lan {
   platform: Addr<Platform>,
   client: Deferred<ControllerClient, StartConfig>,
+  overlay: Addr<Overlay>,
   active_game: Option<LanGame>,
+  host_counter: u32,
 }
 
 impl Actor for Lan {}
@@ -33,10 +41,13 @@ impl Service<StartConfig> for Lan {
 
   async fn create(registry: &mut RegistryRef<StartConfig>) -> Result<Self, Self::Error> {
     let platform = registry.resolve().await?;
+    let overlay = registry.resolve().await?;
     Ok(Lan {
       platform,
       client: registry.deferred(),
+      overlay,
       active_game: None,
+      host_counter: 0,
     })
   }
 }
@@ -49,7 +60,8 @@ pub struct ReplaceLanGame {
 }
 
 impl Message for ReplaceLanGame {
-  type Result = Result<()>;
+  /// The LAN game name
+  type Result = Result<String>;
 }
 
 #[async_trait]
@@ -65,14 +77,13 @@ impl Handler<ReplaceLanGame> for Lan {
     }: ReplaceLanGame,
   ) -> <ReplaceLanGame as Message>::Result {
     let game_id = game.game_id;
-    if self
+    if let Some(active_game) = self
       .active_game
       .as_ref()
-      .map(|g| g.is_same_game(game_id, my_player_id))
-      == Some(true)
+      .filter(|g| g.is_same_game(game_id, my_player_id))
     {
       tracing::debug!("skip create: same game");
-      return Ok(());
+      return Ok(active_game.game_name().to_string());
     }
 
     let checksum = self
@@ -87,22 +98,49 @@ impl Handler<ReplaceLanGame> for Lan {
         last_game.shutdown();
       }
 
+      let config = self.platform.send(GetClientConfig).await?;
+      self.host_counter = self.host_counter.wrapping_add(1);
+      let options = LanGameOptions {
+        game_name: config
+          .lan_game_name_template
+          .as_ref()
+          .map(|template| format_lan_game_name(template, &game, my_player_id)),
+        host_counter: match config.lan_host_counter {
+          LanHostCounter::GameId => game_id as u32,
+          LanHostCounter::Incremental => self.host_counter,
+        },
+        publisher: PublisherOptions {
+          refresh_interval: config
+            .lan_refresh_interval_secs
+            .filter(|v| *v > 0)
+            .map(Duration::from_secs),
+          interfaces: config.lan_interfaces.clone(),
+        },
+        port_range: config.lan_port_range.map(|v| v.range()),
+        proxy: config.proxy.as_deref().map(str::parse).transpose()?,
+        overlay: self.overlay.send(GetOverlaySender).await?,
+        chat_filter: ChatFilter::new(&config.chat_filter_words),
+      };
+
       let lan_game = LanGame::create(
         my_player_id,
         node,
         player_token,
         game,
         checksum,
+        options,
         self.client.resolve().await?,
       )
       .await?;
       tracing::info!(player_id = my_player_id, game_id, "lan game created.");
+      set_report_game(Some((game_id, my_player_id)));
+      let game_name = lan_game.game_name().to_string();
       self.active_game = Some(lan_game);
+      Ok(game_name)
     } else {
       self.active_game.take();
-      return Err(Error::MapChecksumMismatch);
+      Err(Error::MapChecksumMismatch)
     }
-    Ok(())
   }
 }
 
@@ -204,6 +242,7 @@ impl Handler<StopLanGame> for Lan {
       if let Some(game) = self.active_game.take() {
         game.shutdown();
       }
+      set_report_game(None);
     }
   }
 }
@@ -222,9 +261,50 @@ impl Handler<KillLanGame> for Lan {
     _: KillLanGame,
   ) -> <KillLanGame as Message>::Result {
     self.active_game.take();
+    set_report_game(None);
   }
 }
 
+/// Tags error reports with the active game
+fn set_report_game(game: Option<(i32, i32)>) {
+  flo_log::report::set_tag("game_id", game.map(|(game_id, _)| game_id.to_string()));
+  flo_log::report::set_tag("player_id", game.map(|(_, player_id)| player_id.to_string()));
+}
+
+/// Options of the LAN game advertisement
+#[derive(Debug)]
+pub struct LanGameOptions {
+  /// Overrides the default game name
+  pub game_name: Option<String>,
+  pub host_counter: u32,
+  pub publisher: PublisherOptions,
+  pub port_range: Option<RangeInclusive<u16>>,
+  /// Proxy used to connect to the node
+  pub proxy: Option<ProxyConfig>,
+  pub overlay: Option<OverlaySender>,
+  pub chat_filter: Option<ChatFilter>,
+}
+
+fn format_lan_game_name(template: &str, game: &LocalGameInfo, player_id: i32) -> String {
+  let my_team = game
+    .slots
+    .iter()
+    .find(|slot| slot.player.as_ref().map(|p| p.id) == Some(player_id))
+    .map(|slot| slot.settings.team);
+  let opponents = game
+    .slots
+    .iter()
+    .filter(|slot| slot.settings.team != 24 && Some(slot.settings.team) != my_team)
+    .filter_map(|slot| slot.player.as_ref().map(|p| p.name.as_str()))
+    .collect::<Vec<_>>()
+    .join(", ");
+  template
+    .replace("{game_id}", &game.game_id.to_string())
+    .replace("{game_name}", &game.name)
+    .replace("{player_id}", &player_id.to_string())
+    .replace("{opponents}", &opponents)
+}
+
 pub fn get_lan_game_name(game_id: i32, player_id: i32) -> String {
   use hash_ids::HashIds;
   lazy_static! {
@@ -5,8 +5,10 @@ mod lan;
 mod message;
 mod node;
 pub mod observer;
+mod overlay;
 mod ping;
 pub mod platform;
+mod updater;
 mod version;
 
 use crate::message::{GetPort, Listener};
@@ -5,9 +5,12 @@ use std::borrow::Cow;
 use std::str::FromStr;
 
 use flo_net::proto::flo_connect::{
-  PacketGamePlayerLeave, PacketGamePlayerPingMapSnapshot, PacketGamePlayerPingMapSnapshotRequest,
-  PacketGameSelectNode, PacketGameSelectNodeRequest, PacketGameStartReject, PacketGameStartRequest,
-  PacketGameStarting, PacketPlayerPingMapUpdate,
+  PacketGameChat, PacketGameChatClear, PacketGameChatRequest, PacketGameInvite, PacketGameMapVote,
+  PacketGameMapVoteRequest, PacketGamePlayerLeave, PacketGamePlayerPingMapSnapshot,
+  PacketGamePlayerPingMapSnapshotRequest, PacketGameRefereeUpdate, PacketGameSelectNode,
+  PacketGameSelectNodeRequest, PacketGameSlotCloseRequest, PacketGameSlotPingUpdate,
+  PacketGameSlotReserveRequest, PacketGameSlotShuffleRequest, PacketGameStartReject,
+  PacketGameStartRequest, PacketGameStarting, PacketGameSummary, PacketPlayerPingMapUpdate,
 };
 
 use crate::error::{Error, Result};
@@ -32,6 +35,11 @@ pub enum IncomingMessage {
   GamePlayerPingMapSnapshotRequest(PacketGamePlayerPingMapSnapshotRequest),
   ListNodesRequest,
   GameStartRequest(PacketGameStartRequest),
+  GameMapVoteRequest(PacketGameMapVoteRequest),
+  GameSlotCloseRequest(PacketGameSlotCloseRequest),
+  GameSlotReserveRequest(PacketGameSlotReserveRequest),
+  GameSlotShuffleRequest(PacketGameSlotShuffleRequest),
+  GameChatRequest(PacketGameChatRequest),
   StartTestGame(StartTestGame),
   KillTestGame,
   SetNodeAddrOverrides(SetNodeAddrOverrides),
@@ -46,6 +54,7 @@ pub enum OutgoingMessage {
   ReloadClientInfoError(ErrorMessage),
   PlayerSession(PlayerSession),
   ConnectRejected(ErrorMessage),
+  UpgradeRequired(UpgradeRequired),
   Disconnect(Disconnect),
   ListMaps(MapList),
   ListMapsError(ErrorMessage),
@@ -56,6 +65,7 @@ pub enum OutgoingMessage {
   GamePlayerLeave(PacketGamePlayerLeave),
   GameSlotUpdate(GameSlotUpdate),
   PlayerSessionUpdate(PlayerSessionUpdate),
+  PlayerInfoUpdate(PlayerInfo),
   ListNodes(NodeList),
   PingUpdate(PingUpdate),
   GameSelectNode(PacketGameSelectNode),
@@ -67,6 +77,14 @@ pub enum OutgoingMessage {
   GameStartError(ErrorMessage),
   GameSlotClientStatusUpdate(ClientUpdateSlotClientStatus),
   GameStatusUpdate(GameStatusUpdate),
+  GameMapVote(PacketGameMapVote),
+  GameSummary(PacketGameSummary),
+  GameInvite(PacketGameInvite),
+  GameSlotPingUpdate(PacketGameSlotPingUpdate),
+  GameChat(PacketGameChat),
+  GameChatClear(PacketGameChatClear),
+  GameRefereeUpdate(PacketGameRefereeUpdate),
+  BlacklistWarning(BlacklistWarning),
   GameDisconnect,
   SetNodeAddrOverridesError(ErrorMessage),
 }
@@ -111,6 +129,12 @@ impl ErrorMessage {
   }
 }
 
+#[derive(Debug, Clone, Serialize)]
+pub struct UpgradeRequired {
+  pub min_version: String,
+  pub download_url: String,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct Connect {
   pub token: String,
@@ -152,12 +176,29 @@ pub struct GameStarted {
   pub lan_game_name: String,
 }
 
+/// Players of the current lobby found in the local blacklist
+#[derive(Debug, Serialize)]
+pub struct BlacklistWarning {
+  pub game_id: i32,
+  pub players: Vec<BlacklistedPlayer>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BlacklistedPlayer {
+  pub player_id: i32,
+  pub name: String,
+  pub reason: String,
+}
+
 #[derive(Debug, Serialize, Deserialize, S2ProtoPack)]
 #[s2_grpc(message_type(flo_net::proto::flo_connect::PacketGameSlotUpdateRequest))]
 pub struct GameSlotUpdateRequest {
   pub game_id: i32,
   pub slot_index: i32,
   pub slot_settings: SlotSettings,
+  /// Set by the UI to retry the update safely
+  #[serde(default)]
+  pub request_id: String,
 }
 
 #[derive(Debug, Serialize, Deserialize, S2ProtoUnpack)]
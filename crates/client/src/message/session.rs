@@ -14,8 +14,9 @@ use crate::platform::{
 };
 use flo_net::packet::FloPacket;
 use flo_net::proto::flo_connect::{
-  PacketGamePlayerPingMapSnapshotRequest, PacketGameSlotUpdateRequest, PacketGameStartRequest,
-  PacketListNodesRequest,
+  PacketGameChatRequest, PacketGameMapVoteRequest, PacketGamePlayerPingMapSnapshotRequest,
+  PacketGameSlotCloseRequest, PacketGameSlotReserveRequest, PacketGameSlotShuffleRequest,
+  PacketGameSlotUpdateRequest, PacketGameStartRequest, PacketListNodesRequest,
 };
 use flo_platform::ClientPlatformInfo;
 use flo_state::Addr;
@@ -164,9 +165,25 @@ impl Worker {
           .send_frame::<PacketGamePlayerPingMapSnapshotRequest>(req)
           .await?;
       }
-      IncomingMessage::GameStartRequest(req) => {
+      IncomingMessage::GameStartRequest(mut req) => {
+        req.trace_context = flo_log::trace_context::current().unwrap_or_default();
         self.send_frame::<PacketGameStartRequest>(req).await?;
       }
+      IncomingMessage::GameMapVoteRequest(req) => {
+        self.send_frame::<PacketGameMapVoteRequest>(req).await?;
+      }
+      IncomingMessage::GameSlotCloseRequest(req) => {
+        self.send_frame::<PacketGameSlotCloseRequest>(req).await?;
+      }
+      IncomingMessage::GameSlotReserveRequest(req) => {
+        self.send_frame::<PacketGameSlotReserveRequest>(req).await?;
+      }
+      IncomingMessage::GameSlotShuffleRequest(req) => {
+        self.send_frame::<PacketGameSlotShuffleRequest>(req).await?;
+      }
+      IncomingMessage::GameChatRequest(req) => {
+        self.send_frame::<PacketGameChatRequest>(req).await?;
+      }
       IncomingMessage::StartTestGame(msg) => {
         self.platform.send(msg).await??;
       }
@@ -8,7 +8,7 @@ use flo_state::{async_trait, Actor, Context, Handler, Message, Owner, RegistryRe
 use flo_types::ping::PingStats;
 use serde::Serialize;
 use std::collections::BTreeMap;
-use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+use std::net::SocketAddr;
 
 pub struct NodeRegistry {
   map: BTreeMap<i32, NodeInfo>,
@@ -24,6 +24,35 @@ impl NodeRegistry {
       ping: PingActor::new().start(),
     }
   }
+
+  fn addresses(&self) -> Vec<SocketAddr> {
+    let mut addresses = vec![];
+    for node in self.map.values() {
+      if let Some(addr) = self.addr_overrides.get(&node.id) {
+        addresses.push(*addr);
+      } else {
+        addresses.push(node.socket_addr);
+        addresses.extend(node.socket_addr_v6);
+      }
+    }
+    addresses
+  }
+
+  /// Dual-stack nodes are connected over IPv6 if the IPv6 address is reachable.
+  fn select_addr(&self, node: &NodeInfo, ping_map: &BTreeMap<SocketAddr, PingStats>) -> SocketAddr {
+    if let Some(addr) = self.addr_overrides.get(&node.id) {
+      return *addr;
+    }
+    node
+      .socket_addr_v6
+      .filter(|addr| {
+        ping_map
+          .get(addr)
+          .map(|stats| stats.current.is_some())
+          .unwrap_or_default()
+      })
+      .unwrap_or(node.socket_addr)
+  }
 }
 
 impl Actor for NodeRegistry {}
@@ -52,13 +81,14 @@ impl Handler<GetNode> for NodeRegistry {
     _: &mut Context<Self>,
     GetNode { node_id }: GetNode,
   ) -> <GetNode as Message>::Result {
-    self.map.get(&node_id).cloned().map(|mut info| {
-      if let Some(addr) = self.addr_overrides.get(&info.id) {
-        info.socket_addr = *addr;
-        tracing::debug!(node_id, "using override address: {:?}", addr);
-      }
-      info
-    })
+    let mut info = self.map.get(&node_id).cloned()?;
+    let ping_map = self.ping.send(GetPingMap).await.unwrap_or_default();
+    let addr = self.select_addr(&info, &ping_map);
+    if addr != info.socket_addr {
+      tracing::debug!(node_id, "using address: {:?}", addr);
+      info.socket_addr = addr;
+    }
+    Some(info)
   }
 }
 
@@ -94,67 +124,30 @@ impl Handler<UpdateNodes> for NodeRegistry {
     }
 
     for node in nodes {
-      let socket_addr = match parse_node_addr(&node) {
+      let info = match NodeInfo::from_node(node) {
         Ok(v) => v,
-        Err(err) => {
-          tracing::error!(node_id = node.id, "skip node: {}", err);
+        Err((node_id, err)) => {
+          tracing::error!(node_id, "skip node: {}", err);
           continue;
         }
       };
-      let name = node.name;
-
-      self.map.insert(
-        node.id,
-        NodeInfo {
-          id: node.id,
-          name: name.to_string(),
-          location: node.location.to_string(),
-          country_id: node.country_id.to_string(),
-          socket_addr,
-        },
-      );
+      self.map.insert(info.id, info);
     }
 
-    let addresses: Vec<_> = self
-      .map
-      .values()
-      .map(|v| {
-        self
-          .addr_overrides
-          .get(&v.id)
-          .cloned()
-          .unwrap_or_else(|| v.socket_addr)
-      })
-      .collect();
+    let addresses = self.addresses();
     self.ping.send(UpdateAddresses { addresses }).await?;
 
     Ok(())
   }
 }
 
-fn parse_node_addr(node: &Node) -> Result<SocketAddr> {
-  let ip_str: &str = &node.ip_addr;
-  let (ip, port) = if ip_str.contains(":") {
-    let addr = if let Some(addr) = ip_str.parse::<SocketAddrV4>().ok() {
-      addr
-    } else {
-      return Err(Error::InvalidNodeConfig);
-    };
-
-    (
-      addr.ip().clone(),
-      addr.port() + flo_constants::NODE_ECHO_PORT_OFFSET,
-    )
-  } else {
-    let addr: Ipv4Addr = if let Some(addr) = ip_str.parse::<Ipv4Addr>().ok() {
-      addr
-    } else {
-      return Err(Error::InvalidNodeConfig);
-    };
-    let port = flo_constants::NODE_ECHO_PORT;
-    (addr, port)
-  };
-  Ok(SocketAddr::from((ip, port)))
+fn parse_node_addr(addr: &str) -> Result<SocketAddr> {
+  flo_net::addr::parse_node_addr(
+    addr,
+    flo_constants::NODE_ECHO_PORT,
+    flo_constants::NODE_ECHO_PORT_OFFSET,
+  )
+  .ok_or_else(|| Error::InvalidNodeConfig)
 }
 
 pub struct SetActiveNode {
@@ -174,10 +167,12 @@ impl Handler<SetActiveNode> for NodeRegistry {
   ) -> <SetActiveNode as Message>::Result {
     if let Some(node_id) = node_id {
       if let Some(node) = self.map.get(&node_id) {
+        let ping_map = self.ping.send(GetPingMap).await?;
+        let address = self.select_addr(node, &ping_map);
         self
           .ping
           .send(SetActiveAddress {
-            address: Some(node.socket_addr),
+            address: Some(address),
           })
           .await??;
       } else {
@@ -208,12 +203,7 @@ impl Handler<GetNodePingMap> for NodeRegistry {
         .map
         .iter()
         .filter_map(|(id, node)| {
-          let addr = self
-            .addr_overrides
-            .get(id)
-            .cloned()
-            .unwrap_or_else(|| node.socket_addr.clone());
-
+          let addr = self.select_addr(node, &ping_map);
           ping_map.get(&addr).cloned().map(|stats| (*id, stats))
         })
         .collect(),
@@ -241,11 +231,7 @@ impl Handler<UpdateAddressesAndGetNodePingMap> for NodeRegistry {
         .map
         .iter()
         .filter_map(|(id, node)| {
-          let addr = self
-            .addr_overrides
-            .get(id)
-            .cloned()
-            .unwrap_or_else(|| node.socket_addr.clone());
+          let addr = self.select_addr(node, &ping_map);
           ping_map.get(&addr).cloned().map(|stats| (*id, stats))
         })
         .collect(),
@@ -268,34 +254,22 @@ impl Handler<AddNode> for NodeRegistry {
     _: &mut Context<Self>,
     AddNode { node }: AddNode,
   ) -> <AddNode as Message>::Result {
-    let socket_addr = match parse_node_addr(&node) {
+    let info = match NodeInfo::from_node(node) {
       Ok(v) => v,
-      Err(err) => {
-        tracing::error!(node_id = node.id, "skip node: {}", err);
+      Err((node_id, err)) => {
+        tracing::error!(node_id, "skip node: {}", err);
         return;
       }
     };
-    let name = node.name;
-
-    self.map.insert(
-      node.id,
-      NodeInfo {
-        id: node.id,
-        name: name.to_string(),
-        location: node.location.to_string(),
-        country_id: node.country_id.to_string(),
-        socket_addr,
-      },
-    );
-
-    self
-      .ping
-      .notify(AddAddress {
-        address: socket_addr,
-      })
-      .await
-      .ok();
-    tracing::debug!(node_id = node.id, "add node: {}", socket_addr);
+    let node_id = info.id;
+    let socket_addr = info.socket_addr;
+    let socket_addr_v6 = info.socket_addr_v6;
+    self.map.insert(node_id, info);
+
+    for address in std::iter::once(socket_addr).chain(socket_addr_v6) {
+      self.ping.notify(AddAddress { address }).await.ok();
+    }
+    tracing::debug!(node_id, "add node: {}", socket_addr);
   }
 }
 
@@ -315,13 +289,9 @@ impl Handler<RemoveNode> for NodeRegistry {
     RemoveNode { node_id }: RemoveNode,
   ) -> <RemoveNode as Message>::Result {
     if let Some(node) = self.map.remove(&node_id) {
-      self
-        .ping
-        .notify(RemoveAddress {
-          address: node.socket_addr,
-        })
-        .await
-        .ok();
+      for address in std::iter::once(node.socket_addr).chain(node.socket_addr_v6) {
+        self.ping.notify(RemoveAddress { address }).await.ok();
+      }
       tracing::debug!(node_id, "remove node: {}", node.socket_addr);
     } else {
       tracing::warn!(node_id, "removed node was not found");
@@ -344,7 +314,11 @@ impl Handler<SetNodeAddrOverrides> for NodeRegistry {
     _: &mut Context<Self>,
     SetNodeAddrOverrides { overrides }: SetNodeAddrOverrides,
   ) -> <SetNodeAddrOverrides as Message>::Result {
-    let mut addresses: Vec<_> = self.map.values().map(|v| v.socket_addr).collect();
+    let mut addresses: Vec<_> = self
+      .map
+      .values()
+      .flat_map(|v| std::iter::once(v.socket_addr).chain(v.socket_addr_v6))
+      .collect();
     for (id, addr) in overrides.iter() {
       if !addresses.contains(addr) {
         tracing::debug!(node_id = *id, "addr override: {}", addr);
@@ -372,7 +346,7 @@ impl Handler<ClearNodeAddrOverrides> for NodeRegistry {
   ) -> <SetNodeAddrOverrides as Message>::Result {
     self.addr_overrides.clear();
 
-    let addresses: Vec<_> = self.map.values().map(|v| v.socket_addr).collect();
+    let addresses = self.addresses();
     self.ping.notify(UpdateAddresses { addresses }).await?;
 
     Ok(())
@@ -392,9 +366,26 @@ pub struct NodeInfo {
   pub location: String,
   pub country_id: String,
   socket_addr: SocketAddr,
+  socket_addr_v6: Option<SocketAddr>,
 }
 
 impl NodeInfo {
+  fn from_node(node: Node) -> Result<Self, (i32, Error)> {
+    let socket_addr = parse_node_addr(&node.ip_addr).map_err(|err| (node.id, err))?;
+    let socket_addr_v6 = match node.ip_addr_v6.as_deref().filter(|v| !v.is_empty()) {
+      Some(addr) => Some(parse_node_addr(addr).map_err(|err| (node.id, err))?),
+      None => None,
+    };
+    Ok(NodeInfo {
+      id: node.id,
+      name: node.name,
+      location: node.location,
+      country_id: node.country_id,
+      socket_addr,
+      socket_addr_v6,
+    })
+  }
+
   pub fn client_socket_addr(&self) -> SocketAddr {
     self.socket_addr_offset(flo_constants::NODE_CLIENT_PORT_OFFSET)
   }
@@ -5,8 +5,10 @@ use crate::lan::game::LanGameInfo;
 use crate::lan::LanEvent;
 use backoff::backoff::Backoff;
 use backoff::{self, ExponentialBackoff};
+use flo_net::capability::Capabilities;
 use flo_net::packet::*;
 use flo_net::proto::flo_node as proto;
+use flo_net::proxy::ProxyConfig;
 use flo_net::stream::FloStream;
 use flo_net::w3gs::{W3GSAckQueue, W3GSFrameExt, W3GSMetadata, W3GSPacket, W3GSPacketTypeId};
 use flo_state::Addr;
@@ -51,6 +53,7 @@ impl NodeStream {
   pub async fn connect(
     game: &LanGameInfo,
     addr: SocketAddr,
+    proxy: Option<ProxyConfig>,
     token: NodeConnectToken,
     client: Addr<ControllerClient>,
     game_tx: Sender<W3GSPacket>,
@@ -65,6 +68,7 @@ impl NodeStream {
       player_id: game.game.player_id,
       slot_player_id: game.slot_info.my_slot_player_id,
       addr,
+      proxy,
       token,
       client,
       game_tx,
@@ -108,6 +112,7 @@ struct Session {
   player_id: i32,
   slot_player_id: u8,
   addr: SocketAddr,
+  proxy: Option<ProxyConfig>,
   token: NodeConnectToken,
   client: Addr<ControllerClient>,
   game_tx: Sender<W3GSPacket>,
@@ -295,13 +300,22 @@ impl Session {
     self.notify_disconnected().await;
   }
 
+  async fn connect_stream(&self) -> Result<FloStream> {
+    let stream = match self.proxy.as_ref() {
+      Some(proxy) => FloStream::connect_proxy(proxy, self.addr).await?,
+      None => FloStream::connect_no_delay(self.addr).await?,
+    };
+    Ok(stream)
+  }
+
   async fn connect(&self) -> Result<(FloStream, Connection)> {
-    let mut stream = FloStream::connect_no_delay(self.addr).await?;
+    let mut stream = self.connect_stream().await?;
 
     stream
       .send(proto::PacketClientConnect {
         version: Some(crate::version::FLO_VERSION.into()),
         token: self.token.to_vec(),
+        capabilities: Some(Capabilities::local().into()),
         ..Default::default()
       })
       .await?;
@@ -320,6 +334,7 @@ impl Session {
             p.version,
             p.game_status,
           );
+          stream.set_capabilities(Capabilities::local().negotiate(p.capabilities.as_ref()));
           let status = NodeGameStatusSnapshot::unpack(p)?;
           (player_id, status)
         }
@@ -372,7 +387,7 @@ impl Session {
         _ => None,
       }
     };
-    let mut stream = FloStream::connect_no_delay(self.addr).await?;
+    let mut stream = self.connect_stream().await?;
 
     stream
       .send(proto::PacketClientConnect {
@@ -380,6 +395,7 @@ impl Session {
         token: self.token.to_vec(),
         retry_shutdown: true,
         leave_reason,
+        capabilities: Some(Capabilities::local().into()),
       })
       .await?;
 
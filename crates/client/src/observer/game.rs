@@ -1,7 +1,7 @@
 use super::send_queue::SendQueue;
 use crate::error::{Error, Result};
 use crate::lan::game::slot::{LanSlotInfo, SelfPlayer};
-use crate::platform::{GetClientPlatformInfo, OpenMap, Platform};
+use crate::platform::{GetClientConfig, GetClientPlatformInfo, OpenMap, Platform};
 use flo_lan::MdnsPublisher;
 use flo_observer::record::GameRecordData;
 use flo_state::Addr;
@@ -74,7 +74,10 @@ where
       return Err(Error::MapChecksumMismatch);
     }
 
-    let listener = W3GSListener::bind().await?;
+    let listener = match platform.send(GetClientConfig).await?.lan_port_range {
+      Some(range) => W3GSListener::bind_port_range(range.range()).await?,
+      None => W3GSListener::bind().await?,
+    };
 
     let (map_width, map_height) = map.map.dimension();
     let game_settings = GameSettings::new(
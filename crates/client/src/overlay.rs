@@ -0,0 +1,375 @@
+//! Live game feed for stream overlays.
+//!
+//! If `overlay_port` is set, the client accepts WebSocket connections on localhost
+//! and publishes facts of the running game as JSON events: players, elapsed game time,
+//! APM estimates, leaves and chat. The feed is read-only, new connections receive
+//! the `game_started` event of the running game first.
+//!
+//! Browser pages can only connect from the flo web origins or `overlay_origins`.
+
+use crate::error::*;
+use crate::lan::game::LanGameInfo;
+use crate::platform::{GetClientConfig, Platform};
+use crate::StartConfig;
+use async_tungstenite::tungstenite::Message as WsMessage;
+use flo_state::{async_trait, Actor, Context, Handler, Message, RegistryRef, Service};
+use flo_types::game::Race;
+use flo_w3gs::action::{IncomingAction, IncomingAction2, TimeSlot};
+use flo_w3gs::chat::{ChatFromHost, ChatMessage};
+use flo_w3gs::leave::PlayerLeft;
+use flo_w3gs::packet::Packet;
+use flo_w3gs::protocol::constants::PacketTypeId;
+use futures::SinkExt;
+use http::{Request, Response, StatusCode};
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::net::{Ipv4Addr, SocketAddrV4};
+use std::sync::Arc;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast;
+use tracing_futures::Instrument;
+
+const CHANNEL_SIZE: usize = 64;
+/// Game time between two `tick` events
+const TICK_INTERVAL_MS: u32 = 1000;
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum OverlayEvent {
+  GameStarted {
+    game_id: i32,
+    game_name: String,
+    map_path: String,
+    players: Vec<OverlayPlayer>,
+  },
+  Tick {
+    elapsed_ms: u32,
+    apm: Vec<OverlayApm>,
+  },
+  PlayerLeft {
+    player_id: i32,
+    reason: String,
+  },
+  Chat {
+    player_id: i32,
+    message: String,
+  },
+  GameEnded {
+    game_id: i32,
+    elapsed_ms: u32,
+  },
+}
+
+#[derive(Debug, Serialize)]
+pub struct OverlayPlayer {
+  pub player_id: i32,
+  pub name: String,
+  pub team: i32,
+  pub race: Race,
+}
+
+#[derive(Debug, Serialize)]
+pub struct OverlayApm {
+  pub player_id: i32,
+  pub apm: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct OverlaySender {
+  tx: broadcast::Sender<Arc<str>>,
+  current_game: Arc<Mutex<Option<Arc<str>>>>,
+}
+
+impl OverlaySender {
+  fn send(&self, event: &OverlayEvent) {
+    let json: Arc<str> = match serde_json::to_string(event) {
+      Ok(json) => json.into(),
+      Err(err) => {
+        tracing::error!("serialize overlay event: {}", err);
+        return;
+      }
+    };
+    match event {
+      OverlayEvent::GameStarted { .. } => {
+        self.current_game.lock().replace(json.clone());
+      }
+      OverlayEvent::GameEnded { .. } => {
+        self.current_game.lock().take();
+      }
+      _ => {}
+    }
+    self.tx.send(json).ok();
+  }
+}
+
+pub struct Overlay {
+  sender: Option<OverlaySender>,
+  listener: Option<TcpListener>,
+  origins: Arc<Vec<String>>,
+}
+
+#[async_trait]
+impl Actor for Overlay {
+  async fn started(&mut self, ctx: &mut Context<Self>) {
+    let (listener, sender) = match (self.listener.take(), self.sender.clone()) {
+      (Some(listener), Some(sender)) => (listener, sender),
+      _ => return,
+    };
+    let origins = self.origins.clone();
+    ctx.spawn(serve(listener, sender, origins).instrument(tracing::debug_span!("overlay")));
+  }
+}
+
+#[async_trait]
+impl Service<StartConfig> for Overlay {
+  type Error = Error;
+
+  async fn create(registry: &mut RegistryRef<StartConfig>) -> Result<Self, Self::Error> {
+    let platform = registry.resolve::<Platform>().await?;
+    let config = platform.send(GetClientConfig).await?;
+
+    let port = if let Some(port) = config.overlay_port {
+      port
+    } else {
+      return Ok(Overlay {
+        sender: None,
+        listener: None,
+        origins: Arc::new(vec![]),
+      });
+    };
+
+    let listener = TcpListener::bind(SocketAddrV4::new(Ipv4Addr::LOCALHOST, port)).await?;
+    tracing::info!("overlay feed listening on {}", port);
+
+    let (tx, _) = broadcast::channel(CHANNEL_SIZE);
+    Ok(Overlay {
+      sender: Some(OverlaySender {
+        tx,
+        current_game: Arc::new(Mutex::new(None)),
+      }),
+      listener: Some(listener),
+      origins: Arc::new(config.overlay_origins),
+    })
+  }
+}
+
+pub struct GetOverlaySender;
+
+impl Message for GetOverlaySender {
+  type Result = Option<OverlaySender>;
+}
+
+#[async_trait]
+impl Handler<GetOverlaySender> for Overlay {
+  async fn handle(
+    &mut self,
+    _: &mut Context<Self>,
+    _: GetOverlaySender,
+  ) -> <GetOverlaySender as Message>::Result {
+    self.sender.clone()
+  }
+}
+
+async fn serve(listener: TcpListener, sender: OverlaySender, origins: Arc<Vec<String>>) {
+  loop {
+    let stream = match listener.accept().await {
+      Ok((stream, _)) => stream,
+      Err(err) => {
+        tracing::error!("overlay accept: {}", err);
+        return;
+      }
+    };
+    tokio::spawn(publish(stream, sender.clone(), origins.clone()));
+  }
+}
+
+async fn publish(stream: TcpStream, sender: OverlaySender, origins: Arc<Vec<String>>) {
+  let callback = |req: &Request<()>, res: Response<()>| check_origin(&origins, req, res);
+  let mut stream = match async_tungstenite::tokio::accept_hdr_async(stream, callback).await {
+    Ok(stream) => stream,
+    Err(err) => {
+      tracing::debug!("overlay handshake: {}", err);
+      return;
+    }
+  };
+
+  let mut rx = sender.tx.subscribe();
+  let current_game = sender.current_game.lock().clone();
+  if let Some(json) = current_game {
+    if stream
+      .send(WsMessage::Text(json.to_string()))
+      .await
+      .is_err()
+    {
+      return;
+    }
+  }
+
+  loop {
+    let json = match rx.recv().await {
+      Ok(json) => json,
+      Err(broadcast::error::RecvError::Lagged(n)) => {
+        tracing::debug!("overlay client lagged: {} events skipped", n);
+        continue;
+      }
+      Err(broadcast::error::RecvError::Closed) => return,
+    };
+    if stream
+      .send(WsMessage::Text(json.to_string()))
+      .await
+      .is_err()
+    {
+      return;
+    }
+  }
+}
+
+/// Rejects browser pages of other origins,
+/// clients that don't send an `Origin` header are not browsers and are accepted
+fn check_origin(
+  origins: &[String],
+  req: &Request<()>,
+  res: Response<()>,
+) -> Result<Response<()>, Response<Option<String>>> {
+  let origin = match req.headers().get(http::header::ORIGIN) {
+    Some(origin) => origin.to_str().unwrap_or_default(),
+    None => return Ok(res),
+  };
+  if flo_constants::CLIENT_ORIGINS.contains(&origin) || origins.iter().any(|v| v == origin) {
+    return Ok(res);
+  }
+  tracing::warn!("overlay connection rejected, origin: {}", origin);
+  Err(
+    Response::builder()
+      .status(StatusCode::FORBIDDEN)
+      .body(None)
+      .unwrap(),
+  )
+}
+
+/// Tracks the running game and publishes its events
+pub struct GameOverlay {
+  sender: OverlaySender,
+  game_id: i32,
+  player_id_lookup: BTreeMap<u8, i32>,
+  elapsed_ms: u32,
+  last_tick_ms: u32,
+  action_counts: BTreeMap<u8, u32>,
+}
+
+impl GameOverlay {
+  pub fn start(sender: OverlaySender, info: &LanGameInfo) -> Self {
+    let players = info
+      .slot_info
+      .player_infos
+      .iter()
+      .map(|p| {
+        let settings = &info.game.slots[p.slot_index].settings;
+        OverlayPlayer {
+          player_id: p.player_id,
+          name: p.name.clone(),
+          team: settings.team,
+          race: settings.race,
+        }
+      })
+      .collect();
+    sender.send(&OverlayEvent::GameStarted {
+      game_id: info.game.game_id,
+      game_name: info.game.name.clone(),
+      map_path: info.game.map_path.clone(),
+      players,
+    });
+    GameOverlay {
+      sender,
+      game_id: info.game.game_id,
+      player_id_lookup: info
+        .slot_info
+        .player_infos
+        .iter()
+        .map(|p| (p.slot_player_id, p.player_id))
+        .collect(),
+      elapsed_ms: 0,
+      last_tick_ms: 0,
+      action_counts: BTreeMap::new(),
+    }
+  }
+
+  /// Inspects a packet sent to the game, errors are ignored
+  pub fn handle_packet(&mut self, pkt: &Packet) {
+    match pkt.type_id() {
+      PacketTypeId::IncomingAction => {
+        if let Ok(IncomingAction(slot)) = pkt.decode_payload() {
+          self.handle_time_slot(slot);
+        }
+      }
+      PacketTypeId::IncomingAction2 => {
+        if let Ok(IncomingAction2(slot)) = pkt.decode_payload() {
+          self.handle_time_slot(slot);
+        }
+      }
+      PacketTypeId::ChatFromHost => {
+        if let Ok(chat) = pkt.decode_simple::<ChatFromHost>() {
+          let player_id = self.player_id_lookup.get(&chat.from_player()).cloned();
+          if let (Some(player_id), ChatMessage::Scoped { message, .. }) =
+            (player_id, chat.0.message)
+          {
+            self.sender.send(&OverlayEvent::Chat {
+              player_id,
+              message: message.to_string_lossy().to_string(),
+            });
+          }
+        }
+      }
+      PacketTypeId::PlayerLeft => {
+        if let Ok(left) = pkt.decode_simple::<PlayerLeft>() {
+          if let Some(player_id) = self.player_id_lookup.get(&left.player_id).cloned() {
+            self.sender.send(&OverlayEvent::PlayerLeft {
+              player_id,
+              reason: format!("{:?}", left.reason),
+            });
+          }
+        }
+      }
+      _ => {}
+    }
+  }
+
+  fn handle_time_slot(&mut self, slot: TimeSlot) {
+    self.elapsed_ms = self
+      .elapsed_ms
+      .saturating_add(slot.time_increment_ms as u32);
+    for action in &slot.actions {
+      let count = action.actions().take_while(|v| v.is_ok()).count() as u32;
+      *self.action_counts.entry(action.player_id).or_default() += count;
+    }
+
+    if self.elapsed_ms - self.last_tick_ms >= TICK_INTERVAL_MS {
+      self.last_tick_ms = self.elapsed_ms;
+      let minutes = self.elapsed_ms as f64 / 60_000.0;
+      let apm = self
+        .action_counts
+        .iter()
+        .filter_map(|(id, count)| {
+          Some(OverlayApm {
+            player_id: self.player_id_lookup.get(id).cloned()?,
+            apm: (*count as f64 / minutes).round() as u32,
+          })
+        })
+        .collect();
+      self.sender.send(&OverlayEvent::Tick {
+        elapsed_ms: self.elapsed_ms,
+        apm,
+      });
+    }
+  }
+}
+
+impl Drop for GameOverlay {
+  fn drop(&mut self) {
+    self.sender.send(&OverlayEvent::GameEnded {
+      game_id: self.game_id,
+      elapsed_ms: self.elapsed_ms,
+    });
+  }
+}
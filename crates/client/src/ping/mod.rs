@@ -2,14 +2,13 @@ use crate::error::Result;
 use crate::ping::collect::{GetPingStats, PingCollectActor, PingReply, SetActive};
 use flo_state::{async_trait, Actor, Addr, Context, Handler, Message, Owner};
 use flo_types::ping::PingStats;
-use flo_util::binary::Ipv4Addr;
+use flo_net::addr::{from_dual_stack, to_dual_stack};
 use futures::StreamExt;
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
 use std::net::SocketAddr;
 use std::time::Duration;
 use thiserror::Error;
-use tokio::net::UdpSocket;
 use tokio::sync::mpsc;
 use tokio::time::sleep;
 
@@ -32,17 +31,22 @@ impl PingActor {
   }
 
   async fn worker(addr: Addr<Self>, rx: &mut mpsc::Receiver<SendPing>) -> Result<(), PingError> {
-    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await?;
+    let socket = flo_net::listener::bind_udp(0).await?;
+    let dual_stack = socket.local_addr()?.is_ipv6();
     let mut buf = [0_u8; 4];
     loop {
       tokio::select! {
         Some(SendPing { to, data }) = rx.recv() => {
-          socket.send_to(&data, to).await?;
+          let to = if dual_stack { to_dual_stack(to) } else { to };
+          // an unreachable address family should not stop pinging other nodes
+          if let Err(err) = socket.send_to(&data, to).await {
+            tracing::debug!("send ping to {}: {}", to, err);
+          }
         }
         Ok((size, from)) = socket.recv_from(&mut buf) => {
           if size == 4 {
             addr.send(RecvPong {
-              from,
+              from: from_dual_stack(from),
               data: buf
             }).await.map_err(|_| PingError::SenderGone)?;
           }
@@ -272,6 +276,8 @@ pub enum PingError {
   SenderGone,
   #[error("io: {0}")]
   Io(#[from] std::io::Error),
+  #[error("net: {0}")]
+  Net(#[from] flo_net::error::Error),
   #[error("time overflow")]
   TimeOverflow,
 }
@@ -249,6 +249,7 @@ impl Handler<GetMapDetail> for Platform {
               player_set: f.player_set,
             })
             .collect(),
+          flags: map.flags().bits(),
         })
       })
       .await
@@ -378,7 +379,13 @@ async fn load(
     #[cfg(not(feature = "worker"))]
     let config = {
       let _ = start_config;
-      ClientConfig::load().unwrap_or_default()
+      ClientConfig::load().unwrap_or_else(|err| {
+        match err {
+          flo_config::error::Error::Io(ref err) if err.kind() == std::io::ErrorKind::NotFound => {}
+          err => tracing::warn!("load config: {}, using defaults", err),
+        }
+        Default::default()
+      })
     };
     let info = ClientPlatformInfo::with_config(&config).map_err(|e| match e {
       PlatformError::NoInstallationFolder => PlatformStateError::InstallationPath,
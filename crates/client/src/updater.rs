@@ -0,0 +1,380 @@
+//! Self-update of the client binary.
+//!
+//! The controller advertises its latest release on connect. If it is newer than
+//! the running client, the artifact of the current target is downloaded, its ed25519
+//! signature is verified against the key set in `FLO_UPDATE_PUBLIC_KEY` at build time,
+//! and the binary is swapped. The new version runs on next start.
+//!
+//! The signature covers `<version>\n<target>\n` followed by the SHA-256 digest of the
+//! artifact, so a signed artifact can't be served as another version or for another target.
+
+use crate::error::*;
+use crate::platform::{GetClientConfig, Platform};
+use crate::version::FLO_VERSION;
+use crate::StartConfig;
+use flo_constants::version::Version;
+use flo_net::proto::flo_connect::{ClientRelease, ClientReleaseArtifact};
+use flo_net::proxy::ProxyConfig;
+use flo_state::{async_trait, Actor, Context, Handler, Message, RegistryRef, Service};
+use hyper::client::connect::Connect;
+use std::fs;
+use std::future::Future;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::task::Poll;
+use tokio::net::TcpStream;
+
+const PUBLIC_KEY_BASE64: Option<&str> = option_env!("FLO_UPDATE_PUBLIC_KEY");
+const MAX_ARTIFACT_SIZE: u64 = 100 * 1024 * 1024;
+const MAX_REDIRECTS: usize = 5;
+
+pub struct Updater {
+  public_key: Option<Vec<u8>>,
+  proxy: Option<String>,
+  in_progress: Option<Version>,
+  installed: Option<Version>,
+}
+
+impl Actor for Updater {}
+
+#[async_trait]
+impl Service<StartConfig> for Updater {
+  type Error = Error;
+
+  async fn create(registry: &mut RegistryRef<StartConfig>) -> Result<Self, Self::Error> {
+    let platform = registry.resolve::<Platform>().await?;
+    let config = platform.send(GetClientConfig).await?;
+
+    if let Ok(exe) = std::env::current_exe() {
+      remove_previous(&exe);
+    }
+
+    let public_key = PUBLIC_KEY_BASE64
+      .filter(|_| config.auto_update)
+      .and_then(|key| match base64::decode(key) {
+        Ok(key) => Some(key),
+        Err(err) => {
+          tracing::error!("invalid update public key: {}", err);
+          None
+        }
+      });
+
+    Ok(Updater {
+      public_key,
+      proxy: config.proxy,
+      in_progress: None,
+      installed: None,
+    })
+  }
+}
+
+pub struct CheckRelease(pub ClientRelease);
+
+impl Message for CheckRelease {
+  type Result = ();
+}
+
+#[async_trait]
+impl Handler<CheckRelease> for Updater {
+  async fn handle(
+    &mut self,
+    ctx: &mut Context<Self>,
+    CheckRelease(release): CheckRelease,
+  ) -> <CheckRelease as Message>::Result {
+    let version: Version = match release.version {
+      Some(version) => version.into(),
+      None => return,
+    };
+
+    if version <= FLO_VERSION
+      || self.in_progress.is_some()
+      || self.installed.map(|v| v >= version) == Some(true)
+    {
+      return;
+    }
+
+    let public_key = if let Some(key) = self.public_key.clone() {
+      key
+    } else {
+      tracing::info!("new version available: {}", version);
+      return;
+    };
+
+    let target = current_target();
+    let artifact =
+      if let Some(artifact) = release.artifacts.into_iter().find(|a| a.target == target) {
+        artifact
+      } else {
+        tracing::warn!(
+          "new version available: {}, no artifact for {}",
+          version,
+          target
+        );
+        return;
+      };
+
+    self.in_progress = Some(version);
+    let proxy = self.proxy.clone();
+    let addr = ctx.addr();
+    ctx.spawn(async move {
+      tracing::info!("downloading update: {}", version);
+      let installed = match install(version, &artifact, &public_key, proxy.as_deref()).await {
+        Ok(_) => {
+          tracing::info!("update installed, restart to apply: {}", version);
+          true
+        }
+        Err(err) => {
+          tracing::error!("install update {}: {}", version, err);
+          false
+        }
+      };
+      addr.notify(UpdateResult { version, installed }).await.ok();
+    });
+  }
+}
+
+struct UpdateResult {
+  version: Version,
+  installed: bool,
+}
+
+impl Message for UpdateResult {
+  type Result = ();
+}
+
+#[async_trait]
+impl Handler<UpdateResult> for Updater {
+  async fn handle(
+    &mut self,
+    _: &mut Context<Self>,
+    UpdateResult { version, installed }: UpdateResult,
+  ) -> <UpdateResult as Message>::Result {
+    self.in_progress.take();
+    if installed {
+      self.installed = Some(version);
+    }
+  }
+}
+
+fn current_target() -> String {
+  format!("{}-{}", std::env::consts::OS, std::env::consts::ARCH)
+}
+
+async fn install(
+  version: Version,
+  artifact: &ClientReleaseArtifact,
+  public_key: &[u8],
+  proxy: Option<&str>,
+) -> Result<()> {
+  let target = current_target();
+  if artifact.target != target {
+    return Err(Error::Update(format!(
+      "artifact target `{}` doesn't match `{}`",
+      artifact.target, target
+    )));
+  }
+
+  let signature = base64::decode(&artifact.signature)
+    .map_err(|err| Error::Update(format!("invalid signature: {}", err)))?;
+  let data = match proxy.map(str::parse::<ProxyConfig>).transpose()? {
+    Some(proxy) => {
+      let connector = hyper_tls::HttpsConnector::new_with_connector(ProxyConnector(proxy));
+      download(hyper::Client::builder().build(connector), &artifact.url).await?
+    }
+    None => {
+      let connector = hyper_tls::HttpsConnector::new();
+      download(hyper::Client::builder().build(connector), &artifact.url).await?
+    }
+  };
+
+  verify(public_key, version, &target, &data, &signature)?;
+
+  let exe = std::env::current_exe()?;
+  tokio::task::spawn_blocking(move || swap(&exe, &data)).await?
+}
+
+/// The message signed for the artifact of `version` built for `target`
+fn signed_message(version: Version, target: &str, data: &[u8]) -> Vec<u8> {
+  let mut message = format!("{}\n{}\n", version, target).into_bytes();
+  message.extend_from_slice(ring::digest::digest(&ring::digest::SHA256, data).as_ref());
+  message
+}
+
+fn verify(
+  public_key: &[u8],
+  version: Version,
+  target: &str,
+  data: &[u8],
+  signature: &[u8],
+) -> Result<()> {
+  use ring::signature::{UnparsedPublicKey, ED25519};
+  UnparsedPublicKey::new(&ED25519, public_key)
+    .verify(&signed_message(version, target, data), signature)
+    .map_err(|_| Error::Update("signature verification failed".to_string()))
+}
+
+async fn download<C>(client: hyper::Client<C, hyper::Body>, url: &str) -> Result<Vec<u8>>
+where
+  C: Connect + Clone + Send + Sync + 'static,
+{
+  use hyper::body::HttpBody;
+  use hyper::header::{CONTENT_LENGTH, LOCATION};
+
+  let mut uri: hyper::Uri = url
+    .parse()
+    .map_err(|err| Error::Update(format!("invalid url `{}`: {}", url, err)))?;
+
+  for _ in 0..MAX_REDIRECTS {
+    let res = client.get(uri.clone()).await?;
+
+    if res.status().is_redirection() {
+      uri = res
+        .headers()
+        .get(LOCATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse().ok())
+        .ok_or_else(|| Error::Update("invalid redirect".to_string()))?;
+      continue;
+    }
+
+    if !res.status().is_success() {
+      return Err(Error::Update(format!("download: {}", res.status())));
+    }
+
+    let len = res
+      .headers()
+      .get(CONTENT_LENGTH)
+      .and_then(|v| v.to_str().ok())
+      .and_then(|v| v.parse::<u64>().ok());
+    if len.map(|len| len > MAX_ARTIFACT_SIZE) == Some(true) {
+      return Err(Error::Update("artifact too large".to_string()));
+    }
+
+    // Content-Length can be missing or wrong, the cap is enforced while reading
+    let mut body = res.into_body();
+    let mut data = Vec::with_capacity(len.unwrap_or_default() as usize);
+    while let Some(chunk) = body.data().await {
+      let chunk = chunk?;
+      if (data.len() + chunk.len()) as u64 > MAX_ARTIFACT_SIZE {
+        return Err(Error::Update("artifact too large".to_string()));
+      }
+      data.extend_from_slice(&chunk);
+    }
+    return Ok(data);
+  }
+
+  Err(Error::Update("too many redirects".to_string()))
+}
+
+/// Opens the connections of the update download through the configured proxy
+#[derive(Clone)]
+struct ProxyConnector(ProxyConfig);
+
+impl hyper::service::Service<hyper::Uri> for ProxyConnector {
+  type Response = TcpStream;
+  type Error = flo_net::error::Error;
+  type Future = Pin<Box<dyn Future<Output = Result<TcpStream, Self::Error>> + Send>>;
+
+  fn poll_ready(&mut self, _: &mut std::task::Context<'_>) -> Poll<Result<(), Self::Error>> {
+    Poll::Ready(Ok(()))
+  }
+
+  fn call(&mut self, uri: hyper::Uri) -> Self::Future {
+    let proxy = self.0.clone();
+    Box::pin(async move {
+      let host = uri
+        .host()
+        .ok_or_else(|| flo_net::error::Error::ProxyConnect(format!("no host in `{}`", uri)))?;
+      let port = uri.port_u16().unwrap_or_else(|| {
+        if uri.scheme_str() == Some("http") {
+          80
+        } else {
+          443
+        }
+      });
+      proxy.connect((host, port).into()).await
+    })
+  }
+}
+
+/// Replaces `exe` with `data`, keeping the running binary as `<exe>.old`
+fn swap(exe: &Path, data: &[u8]) -> Result<()> {
+  let staged = with_suffix(exe, "new");
+  let previous = with_suffix(exe, "old");
+
+  fs::write(&staged, data)?;
+
+  #[cfg(unix)]
+  fs::set_permissions(&staged, fs::metadata(exe)?.permissions())?;
+
+  if let Err(err) = fs::remove_file(&previous) {
+    if err.kind() != ErrorKind::NotFound {
+      return Err(err.into());
+    }
+  }
+
+  // a running binary can be renamed but not overwritten on Windows
+  fs::rename(exe, &previous)?;
+  if let Err(err) = fs::rename(&staged, exe) {
+    fs::rename(&previous, exe).ok();
+    return Err(err.into());
+  }
+
+  Ok(())
+}
+
+/// Removes the binary replaced by the last update
+fn remove_previous(exe: &Path) {
+  let previous = with_suffix(exe, "old");
+  match fs::remove_file(&previous) {
+    Ok(_) => tracing::debug!("removed previous binary: {}", previous.display()),
+    Err(err) if err.kind() != ErrorKind::NotFound => {
+      tracing::warn!("remove previous binary: {}", err);
+    }
+    Err(_) => {}
+  }
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+  let mut name = path.as_os_str().to_owned();
+  name.push(".");
+  name.push(suffix);
+  PathBuf::from(name)
+}
+
+#[test]
+fn test_verify_signature() {
+  use ring::signature::{Ed25519KeyPair, KeyPair};
+
+  let key_pair = Ed25519KeyPair::from_seed_unchecked(&[7; 32]).unwrap();
+  let public_key = key_pair.public_key().as_ref();
+  let version = Version::parse("1.2.3");
+  let data = b"flo";
+  let signature = key_pair.sign(&signed_message(version, "windows-x86_64", data));
+  let signature = signature.as_ref();
+
+  assert!(verify(public_key, version, "windows-x86_64", data, signature).is_ok());
+  // replayed as another version or for another target
+  assert!(verify(
+    public_key,
+    Version::parse("1.2.4"),
+    "windows-x86_64",
+    data,
+    signature
+  )
+  .is_err());
+  assert!(verify(public_key, version, "linux-x86_64", data, signature).is_err());
+  // tampered artifact
+  assert!(verify(public_key, version, "windows-x86_64", b"fl0", signature).is_err());
+  // signed by another key
+  let other = Ed25519KeyPair::from_seed_unchecked(&[8; 32]).unwrap();
+  assert!(verify(
+    other.public_key().as_ref(),
+    version,
+    "windows-x86_64",
+    data,
+    signature
+  )
+  .is_err());
+}
@@ -234,6 +234,10 @@ impl DecodeInputReceiver {
         const MIN_SIZE: usize = <#repr_ty as #mod_path::BinDecode>::MIN_SIZE;
         const FIXED_SIZE: bool = <#repr_ty as #mod_path::BinDecode>::FIXED_SIZE;
         fn decode<T: #mod_path::Buf>(buf: &mut T) -> std::result::Result<Self, #mod_path::BinDecodeError> {
+          if buf.remaining() < Self::MIN_SIZE {
+            return Err(#mod_path::BinDecodeError::incomplete());
+          }
+
           Ok(Self(<#repr_ty as #mod_path::BinDecode>::decode(buf)?))
         }
       }
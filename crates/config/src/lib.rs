@@ -1,5 +1,6 @@
 use serde::{Deserialize, Serialize};
 use std::fs;
+use std::ops::RangeInclusive;
 use std::path::PathBuf;
 
 pub mod error;
@@ -13,6 +14,96 @@ pub struct ClientConfig {
   pub installation_path: Option<PathBuf>,
   pub controller_host: String,
   pub stats_host: String,
+  /// LAN game name, supports `{game_id}`, `{game_name}`, `{player_id}` and `{opponents}`
+  pub lan_game_name_template: Option<String>,
+  pub lan_host_counter: LanHostCounter,
+  /// Re-publish the LAN game every N seconds
+  pub lan_refresh_interval_secs: Option<u64>,
+  /// Network interface indexes to announce the LAN game on, all interfaces if empty
+  #[serde(default)]
+  pub lan_interfaces: Vec<u32>,
+  /// TCP ports used by the local W3GS listener, OS-assigned if not set
+  pub lan_port_range: Option<PortRange>,
+  /// Proxy for connections to the controller and nodes,
+  /// `socks5://[user:password@]host[:port]` or `http://[user:password@]host[:port]`
+  pub proxy: Option<String>,
+  /// Download and install new releases advertised by the controller
+  #[serde(default = "default_auto_update")]
+  pub auto_update: bool,
+  /// Local port of the live game feed for stream overlays, disabled if not set
+  pub overlay_port: Option<u16>,
+  /// Browser origins allowed to read the overlay feed, in addition to the flo web origins
+  #[serde(default)]
+  pub overlay_origins: Vec<String>,
+  /// Words masked in chat messages received during games, toggled with `-filter`
+  #[serde(default)]
+  pub chat_filter_words: Vec<String>,
+}
+
+fn default_auto_update() -> bool {
+  true
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct PortRange {
+  pub start: u16,
+  pub end: u16,
+}
+
+impl PortRange {
+  pub fn range(&self) -> RangeInclusive<u16> {
+    self.start..=self.end
+  }
+
+  /// Returns an error if the range is inverted
+  pub fn validate(&self) -> Result<()> {
+    if self.start > self.end {
+      return Err(Error::InvalidPortRange(format!(
+        "{}-{}",
+        self.start, self.end
+      )));
+    }
+    Ok(())
+  }
+}
+
+impl std::str::FromStr for PortRange {
+  type Err = Error;
+
+  /// Parses `16000` or `16000-16010`
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let invalid = || Error::InvalidPortRange(s.to_string());
+    let mut parts = s.splitn(2, '-');
+    let start = parts
+      .next()
+      .unwrap_or_default()
+      .trim()
+      .parse()
+      .map_err(|_| invalid())?;
+    let end = match parts.next() {
+      Some(end) => end.trim().parse().map_err(|_| invalid())?,
+      None => start,
+    };
+    let range = PortRange { start, end };
+    range.validate().map_err(|_| invalid())?;
+    Ok(range)
+  }
+}
+
+/// Value of the host counter in the LAN game advertisement
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LanHostCounter {
+  /// Use the flo game id
+  GameId,
+  /// Increase by 1 for each hosted LAN game
+  Incremental,
+}
+
+impl Default for LanHostCounter {
+  fn default() -> Self {
+    LanHostCounter::GameId
+  }
 }
 
 impl Default for ClientConfig {
@@ -23,6 +114,16 @@ impl Default for ClientConfig {
       installation_path: None,
       controller_host: flo_constants::CONTROLLER_HOST.to_string(),
       stats_host: flo_constants::STATS_HOST.to_string(),
+      lan_game_name_template: None,
+      lan_host_counter: LanHostCounter::default(),
+      lan_refresh_interval_secs: None,
+      lan_interfaces: vec![],
+      lan_port_range: None,
+      proxy: None,
+      auto_update: true,
+      overlay_port: None,
+      overlay_origins: vec![],
+      chat_filter_words: vec![],
     }
   }
 }
@@ -31,7 +132,7 @@ impl ClientConfig {
   pub fn from_env() -> Result<Self> {
     let mut config = ClientConfig::default();
 
-    config.apply_env();
+    config.apply_env()?;
 
     Ok(config)
   }
@@ -44,6 +145,16 @@ impl ClientConfig {
       pub installation_path: Option<PathBuf>,
       pub controller_host: Option<String>,
       pub stats_host: Option<String>,
+      pub lan_game_name_template: Option<String>,
+      pub lan_host_counter: Option<LanHostCounter>,
+      pub lan_refresh_interval_secs: Option<u64>,
+      pub lan_interfaces: Option<Vec<u32>>,
+      pub lan_port_range: Option<PortRange>,
+      pub proxy: Option<String>,
+      pub auto_update: Option<bool>,
+      pub overlay_port: Option<u16>,
+      pub overlay_origins: Option<Vec<String>>,
+      pub chat_filter_words: Option<Vec<String>>,
     }
 
     let config: TomlConfig = toml::from_str(&fs::read_to_string("flo.toml")?)?;
@@ -57,9 +168,23 @@ impl ClientConfig {
       stats_host: config
         .stats_host
         .unwrap_or_else(|| flo_constants::STATS_HOST.to_string()),
+      lan_game_name_template: config.lan_game_name_template,
+      lan_host_counter: config.lan_host_counter.unwrap_or_default(),
+      lan_refresh_interval_secs: config.lan_refresh_interval_secs,
+      lan_interfaces: config.lan_interfaces.unwrap_or_default(),
+      lan_port_range: config.lan_port_range,
+      proxy: config.proxy,
+      auto_update: config.auto_update.unwrap_or(true),
+      overlay_port: config.overlay_port,
+      overlay_origins: config.overlay_origins.unwrap_or_default(),
+      chat_filter_words: config.chat_filter_words.unwrap_or_default(),
     };
 
-    config.apply_env();
+    if let Some(range) = config.lan_port_range.as_ref() {
+      range.validate()?;
+    }
+
+    config.apply_env()?;
 
     Ok(config)
   }
@@ -68,7 +193,7 @@ impl ClientConfig {
     fs::write("flo.toml", toml::to_string_pretty(self)?).map_err(Into::into)
   }
 
-  fn apply_env(&mut self) {
+  fn apply_env(&mut self) -> Result<()> {
     use std::env;
 
     if let Ok(Some(port)) = env::var("FLO_LOCAL_PORT")
@@ -94,5 +219,73 @@ impl ClientConfig {
     if let Ok(domain) = env::var("FLO_STATS_HOST") {
       self.stats_host = domain;
     }
+
+    if let Ok(value) = env::var("FLO_LAN_INTERFACES") {
+      self.lan_interfaces = value
+        .split(',')
+        .filter_map(|v| v.trim().parse().ok())
+        .collect();
+    }
+
+    if let Ok(value) = env::var("FLO_LAN_PORT_RANGE") {
+      self.lan_port_range = Some(value.parse()?);
+    }
+
+    if let Ok(proxy) = env::var("FLO_PROXY") {
+      self.proxy = Some(proxy).filter(|v| !v.is_empty());
+    }
+
+    if let Ok(value) = env::var("FLO_AUTO_UPDATE") {
+      self.auto_update = !matches!(value.trim(), "0" | "false");
+    }
+
+    if let Ok(Some(port)) = env::var("FLO_OVERLAY_PORT")
+      .ok()
+      .map(|v| v.parse())
+      .transpose()
+    {
+      self.overlay_port = Some(port);
+    }
+
+    if let Ok(value) = env::var("FLO_OVERLAY_ORIGINS") {
+      self.overlay_origins = value
+        .split(',')
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+        .collect();
+    }
+
+    if let Ok(value) = env::var("FLO_CHAT_FILTER_WORDS") {
+      self.chat_filter_words = value
+        .split(',')
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+        .collect();
+    }
+
+    Ok(())
   }
 }
+
+#[test]
+fn test_port_range() {
+  assert_eq!(
+    "16000".parse::<PortRange>().unwrap(),
+    PortRange {
+      start: 16000,
+      end: 16000
+    }
+  );
+  assert_eq!(
+    " 16000 - 16010 ".parse::<PortRange>().unwrap().range(),
+    16000..=16010
+  );
+  assert!(matches!(
+    "16010-16000".parse::<PortRange>(),
+    Err(Error::InvalidPortRange(_))
+  ));
+  assert!("".parse::<PortRange>().is_err());
+  assert!("16000-".parse::<PortRange>().is_err());
+  assert!("16000-70000".parse::<PortRange>().is_err());
+  assert!(PortRange { start: 2, end: 1 }.validate().is_err());
+}
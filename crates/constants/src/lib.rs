@@ -5,6 +5,7 @@ pub const CONTROLLER_HOST: &str = "service.w3flo.com";
 pub const STATS_HOST: &str = "stats.w3flo.com";
 pub const CONTROLLER_GRPC_PORT: u16 = 3549;
 pub const CONTROLLER_SOCKET_PORT: u16 = 3550;
+pub const CONTROLLER_GRAPHQL_PORT: u16 = 3560;
 pub const CLIENT_WS_PORT: u16 = 3551;
 pub const CLIENT_ORIGINS: &[&str] = &[
   "http://localhost:3000",
@@ -21,6 +22,8 @@ pub const NODE_CLIENT_PORT: u16 = 3554;
 pub const NODE_CLIENT_PORT_OFFSET: u16 = NODE_CLIENT_PORT - NODE_ECHO_PORT;
 pub const NODE_HTTP_PORT: u16 = 3555;
 pub const NODE_HTTP_PORT_OFFSET: u16 = NODE_HTTP_PORT - NODE_ECHO_PORT;
+pub const GAME_STEP_MIN_MS: u16 = 15;
+pub const GAME_STEP_MAX_MS: u16 = 250;
 pub const MIN_FLO_VERSION: version::Version = Version {
   major: 0,
   minor: 9,
@@ -29,4 +32,5 @@ pub const MIN_FLO_VERSION: version::Version = Version {
 pub const OBSERVER_GRPC_PORT: u16 = 3556;
 pub const OBSERVER_SOCKET_PORT: u16 = 3557;
 pub const OBSERVER_GRAPHQL_PORT: u16 = 3558;
+pub const OBSERVER_WEBSOCKET_PORT: u16 = 3559;
 pub const OBSERVER_FAST_FORWARDING_SPEED: f64 = 3.;
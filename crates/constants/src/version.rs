@@ -19,6 +19,20 @@ impl Version {
   }
 }
 
+impl std::str::FromStr for Version {
+  type Err = std::num::ParseIntError;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let mut parts = s.trim().trim_start_matches('v').splitn(3, '.');
+    let mut next = || parts.next().unwrap_or_default().parse::<i32>();
+    Ok(Version {
+      major: next()?,
+      minor: next()?,
+      patch: next()?,
+    })
+  }
+}
+
 impl fmt::Display for Version {
   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
     write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
@@ -0,0 +1,74 @@
+use diesel::prelude::*;
+
+use crate::audit::{AuditAction, AuditActor, AuditLog};
+use crate::db::DbConn;
+use crate::error::*;
+use crate::schema::audit_log;
+
+pub fn append(
+  conn: &DbConn,
+  actor: &AuditActor,
+  action: AuditAction,
+  target_id: Option<i32>,
+) -> Result<()> {
+  use audit_log::dsl;
+  diesel::insert_into(audit_log::table)
+    .values((
+      dsl::api_client_id.eq(actor.api_client_id),
+      dsl::actor.eq(actor.actor.as_deref()),
+      dsl::action.eq(action),
+      dsl::target_id.eq(target_id),
+      dsl::reason.eq(actor.reason.as_deref()),
+    ))
+    .execute(conn)?;
+  Ok(())
+}
+
+#[derive(Debug)]
+pub struct ListAuditLog {
+  pub audit_logs: Vec<AuditLog>,
+  pub next_id: Option<i32>,
+}
+
+/// Lists the audit log of an API client, newest first
+pub fn list(
+  conn: &DbConn,
+  api_client_id: i32,
+  action: Option<AuditAction>,
+  target_id: Option<i32>,
+  next_id: Option<i32>,
+) -> Result<ListAuditLog> {
+  const PAGE_SIZE: i64 = 100;
+  let mut q = audit_log::table
+    .select(AuditLog::COLUMNS)
+    .filter(audit_log::api_client_id.eq(api_client_id))
+    .order(audit_log::id.desc())
+    .limit(PAGE_SIZE + 1)
+    .into_boxed();
+
+  if let Some(action) = action {
+    q = q.filter(audit_log::action.eq(action));
+  }
+
+  if let Some(target_id) = target_id {
+    q = q.filter(audit_log::target_id.eq(target_id));
+  }
+
+  if let Some(id) = next_id {
+    q = q.filter(audit_log::id.le(id));
+  }
+
+  let mut rows = q.load::<AuditLog>(conn)?;
+  let next_id = if rows.len() > PAGE_SIZE as usize {
+    let id = rows.last().map(|row| row.id);
+    rows.truncate(PAGE_SIZE as usize);
+    id
+  } else {
+    None
+  };
+
+  Ok(ListAuditLog {
+    audit_logs: rows,
+    next_id,
+  })
+}
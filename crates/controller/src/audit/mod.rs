@@ -0,0 +1,75 @@
+pub mod db;
+
+use bs_diesel_utils::BSDieselEnum;
+use chrono::{DateTime, Utc};
+use s2_grpc_utils::{S2ProtoEnum, S2ProtoPack};
+use serde::{Deserialize, Serialize};
+
+use crate::schema::audit_log;
+
+/// Admin API calls recorded in the audit log
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, PartialEq, BSDieselEnum, S2ProtoEnum)]
+#[repr(i32)]
+#[s2_grpc(proto_enum_type(flo_grpc::controller::AuditAction))]
+pub enum AuditAction {
+  /// target: game id
+  CancelGame = 0,
+  /// target: player id
+  CreatePlayerBan = 1,
+  /// target: player id
+  RemovePlayerBan = 2,
+  Reload = 3,
+  /// target: player id
+  DeletePlayer = 4,
+  /// target: game id
+  GetGameChatLog = 5,
+  /// target: player report id
+  ResolvePlayerReport = 6,
+  /// target: node id
+  RegisterNode = 7,
+  /// target: node id
+  DeregisterNode = 8,
+  /// target: game id
+  GetGameHistory = 9,
+}
+
+/// Who made an admin API call and why
+#[derive(Debug, Clone)]
+pub struct AuditActor {
+  pub api_client_id: i32,
+  /// Operator name forwarded by the API client
+  pub actor: Option<String>,
+  pub reason: Option<String>,
+}
+
+#[derive(Debug, Queryable, Serialize, Deserialize, S2ProtoPack)]
+#[s2_grpc(message_type = "flo_grpc::controller::AuditLog")]
+pub struct AuditLog {
+  pub id: i32,
+  pub actor: Option<String>,
+  #[s2_grpc(proto_enum)]
+  pub action: AuditAction,
+  pub target_id: Option<i32>,
+  pub reason: Option<String>,
+  pub created_at: DateTime<Utc>,
+}
+
+pub(crate) type AuditLogColumns = (
+  audit_log::id,
+  audit_log::actor,
+  audit_log::action,
+  audit_log::target_id,
+  audit_log::reason,
+  audit_log::created_at,
+);
+
+impl AuditLog {
+  pub(crate) const COLUMNS: AuditLogColumns = (
+    audit_log::id,
+    audit_log::actor,
+    audit_log::action,
+    audit_log::target_id,
+    audit_log::reason,
+    audit_log::created_at,
+  );
+}
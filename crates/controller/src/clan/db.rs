@@ -0,0 +1,185 @@
+use diesel::prelude::*;
+
+use crate::clan::{Clan, ClanWithMembers};
+use crate::db::DbConn;
+use crate::error::*;
+use crate::player::PlayerRef;
+use crate::schema::{clan, clan_member, player};
+
+pub fn get(conn: &DbConn, api_client_id: i32, id: i32) -> Result<ClanWithMembers> {
+  let clan = clan::table
+    .find(id)
+    .filter(clan::api_client_id.eq(api_client_id))
+    .select(Clan::COLUMNS)
+    .first::<Clan>(conn)
+    .optional()?
+    .ok_or_else(|| Error::ClanNotFound)?;
+  let members = clan_member::table
+    .inner_join(player::table)
+    .select(PlayerRef::COLUMNS)
+    .filter(clan_member::clan_id.eq(id))
+    .order(clan_member::id)
+    .load(conn)?;
+  Ok(ClanWithMembers { clan, members })
+}
+
+pub fn create(conn: &DbConn, api_client_id: i32, name: &str, tag: &str) -> Result<Clan> {
+  if !Clan::is_valid_tag(tag) {
+    return Err(Error::ClanTagInvalid);
+  }
+
+  conn.transaction(|| {
+    check_tag_available(conn, api_client_id, tag, None)?;
+    diesel::insert_into(clan::table)
+      .values((
+        clan::api_client_id.eq(api_client_id),
+        clan::name.eq(name),
+        clan::tag.eq(tag),
+      ))
+      .returning(Clan::COLUMNS)
+      .get_result(conn)
+      .map_err(Into::into)
+  })
+}
+
+/// Updates name and tag, the tag of members is updated as well
+pub fn update(conn: &DbConn, api_client_id: i32, id: i32, name: &str, tag: &str) -> Result<Clan> {
+  if !Clan::is_valid_tag(tag) {
+    return Err(Error::ClanTagInvalid);
+  }
+
+  conn.transaction(|| {
+    check_tag_available(conn, api_client_id, tag, Some(id))?;
+    let clan = diesel::update(
+      clan::table
+        .find(id)
+        .filter(clan::api_client_id.eq(api_client_id)),
+    )
+    .set((clan::name.eq(name), clan::tag.eq(tag)))
+    .returning(Clan::COLUMNS)
+    .get_result::<Clan>(conn)
+    .optional()?
+    .ok_or_else(|| Error::ClanNotFound)?;
+
+    let member_ids = clan_member::table
+      .select(clan_member::player_id)
+      .filter(clan_member::clan_id.eq(id));
+    diesel::update(player::table.filter(player::id.eq_any(member_ids)))
+      .set(player::clan_tag.eq(tag))
+      .execute(conn)?;
+
+    Ok(clan)
+  })
+}
+
+pub fn delete(conn: &DbConn, api_client_id: i32, id: i32) -> Result<()> {
+  conn.transaction(|| {
+    let member_ids = clan_member::table
+      .select(clan_member::player_id)
+      .filter(clan_member::clan_id.eq(id));
+    diesel::update(player::table.filter(player::id.eq_any(member_ids)))
+      .set(player::clan_tag.eq(Option::<String>::None))
+      .execute(conn)?;
+
+    let n = diesel::delete(
+      clan::table
+        .find(id)
+        .filter(clan::api_client_id.eq(api_client_id)),
+    )
+    .execute(conn)?;
+    if n == 0 {
+      return Err(Error::ClanNotFound);
+    }
+    Ok(())
+  })
+}
+
+/// Adds a player to a clan, a player can only be a member of one clan
+pub fn add_member(conn: &DbConn, api_client_id: i32, id: i32, player_id: i32) -> Result<()> {
+  conn.transaction(|| {
+    let tag: String = clan::table
+      .find(id)
+      .filter(clan::api_client_id.eq(api_client_id))
+      .select(clan::tag)
+      .first(conn)
+      .optional()?
+      .ok_or_else(|| Error::ClanNotFound)?;
+
+    let n = diesel::insert_into(clan_member::table)
+      .values((
+        clan_member::clan_id.eq(id),
+        clan_member::player_id.eq(player_id),
+      ))
+      .on_conflict(clan_member::player_id)
+      .do_nothing()
+      .execute(conn)?;
+    if n == 0 {
+      return Err(Error::PlayerAlreadyInClan);
+    }
+
+    diesel::update(player::table.find(player_id))
+      .set(player::clan_tag.eq(tag))
+      .execute(conn)?;
+    Ok(())
+  })
+}
+
+pub fn remove_member(conn: &DbConn, api_client_id: i32, id: i32, player_id: i32) -> Result<()> {
+  conn.transaction(|| {
+    let clan_ids = clan::table
+      .select(clan::id)
+      .filter(clan::api_client_id.eq(api_client_id));
+    let n = diesel::delete(
+      clan_member::table.filter(
+        clan_member::clan_id
+          .eq(id)
+          .and(clan_member::clan_id.eq_any(clan_ids))
+          .and(clan_member::player_id.eq(player_id)),
+      ),
+    )
+    .execute(conn)?;
+    if n == 0 {
+      return Err(Error::PlayerNotInClan);
+    }
+
+    diesel::update(player::table.find(player_id))
+      .set(player::clan_tag.eq(Option::<String>::None))
+      .execute(conn)?;
+    Ok(())
+  })
+}
+
+pub fn is_member(conn: &DbConn, id: i32, player_id: i32) -> Result<bool> {
+  let n: i64 = clan_member::table
+    .filter(
+      clan_member::clan_id
+        .eq(id)
+        .and(clan_member::player_id.eq(player_id)),
+    )
+    .count()
+    .get_result(conn)?;
+  Ok(n > 0)
+}
+
+fn check_tag_available(
+  conn: &DbConn,
+  api_client_id: i32,
+  tag: &str,
+  exclude_id: Option<i32>,
+) -> Result<()> {
+  let mut q = clan::table
+    .filter(
+      clan::api_client_id
+        .eq(api_client_id)
+        .and(clan::tag.ilike(tag)),
+    )
+    .into_boxed();
+  if let Some(id) = exclude_id {
+    q = q.filter(clan::id.ne(id));
+  }
+  let n: i64 = q.count().get_result(conn)?;
+  if n > 0 {
+    return Err(Error::ClanTagTaken);
+  }
+  Ok(())
+}
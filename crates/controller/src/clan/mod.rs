@@ -0,0 +1,44 @@
+pub mod db;
+
+use chrono::{DateTime, Utc};
+use s2_grpc_utils::S2ProtoPack;
+use serde::{Deserialize, Serialize};
+
+use crate::player::PlayerRef;
+use crate::schema::clan;
+
+#[derive(Debug, Queryable, Serialize, Deserialize, S2ProtoPack)]
+#[s2_grpc(message_type = "flo_grpc::clan::Clan")]
+pub struct Clan {
+  pub id: i32,
+  pub name: String,
+  pub tag: String,
+  pub created_at: DateTime<Utc>,
+}
+
+pub(crate) type ClanColumns = (clan::id, clan::name, clan::tag, clan::created_at);
+
+impl Clan {
+  pub(crate) const COLUMNS: ClanColumns = (clan::id, clan::name, clan::tag, clan::created_at);
+
+  /// Tags are 2 to 4 ASCII letters or digits, like Battle.net clan tags
+  pub fn is_valid_tag(tag: &str) -> bool {
+    (2..=4).contains(&tag.len()) && tag.bytes().all(|b| b.is_ascii_alphanumeric())
+  }
+}
+
+#[derive(Debug, S2ProtoPack)]
+#[s2_grpc(message_type = "flo_grpc::clan::ClanWithMembers")]
+pub struct ClanWithMembers {
+  pub clan: Clan,
+  pub members: Vec<PlayerRef>,
+}
+
+#[test]
+fn test_is_valid_tag() {
+  assert!(Clan::is_valid_tag("FLO"));
+  assert!(Clan::is_valid_tag("w3"));
+  assert!(!Clan::is_valid_tag("F"));
+  assert!(!Clan::is_valid_tag("FLOWC"));
+  assert!(!Clan::is_valid_tag("F O"));
+}
@@ -1,3 +1,4 @@
+use flo_net::capability::Capabilities;
 use flo_net::connect::*;
 use flo_net::packet::*;
 use flo_net::stream::FloStream;
@@ -13,6 +14,8 @@ pub async fn handle_handshake(stream: &mut FloStream) -> Result<ConnectState> {
 
   tracing::debug!("client version = {}", client_version);
 
+  stream.set_capabilities(Capabilities::local().negotiate(req.capabilities.as_ref()));
+
   let token = validate_player_token(&req.token)?;
 
   tracing::debug!(token.player_id);
@@ -1,42 +1,50 @@
+use flo_net::capability::{Capabilities, Feature};
 use flo_net::connect;
 use flo_net::listener::FloListener;
 use flo_net::packet::FloPacket;
 use flo_net::packet::OptionalFieldExt;
+use flo_net::packet::PacketTypeId;
 use flo_net::proto;
 use flo_net::stream::FloStream;
 use s2_grpc_utils::{S2ProtoPack, S2ProtoUnpack};
 use std::collections::HashMap;
-use std::time::Duration;
 
+use crate::db::ExecutorExt;
 use crate::error::*;
 use crate::state::{ActorMapExt, ControllerStateRef};
 
 mod handshake;
+mod reliable;
 mod sender;
-use crate::game::messages::{ResolveGamePlayerPingBroadcastTargets, UpdateSlot};
+use crate::game::messages::{
+  AddGamePlayer, CastMapVote, GetGameMuteList, NotifyGamePlayerPingUpdate, PlayerRejoin,
+  RehostGame, ReserveSlot, ResolveGamePlayerPingBroadcastTargets, SendChat, SetSlotClosed,
+  ShuffleSlots, UpdateGameMuteList, UpdateSlot,
+};
+use crate::game::state::idle::RemoveDeadPlayer;
 use crate::game::state::node::SelectNode;
 use crate::game::state::player::GetGamePlayers;
-use crate::game::state::registry::UpdateGameNodeCache;
+use crate::game::state::registry::{Remove, RemoveGamePlayer, UpdateGameNodeCache};
 use crate::game::state::start::{StartGameCheck, StartGamePlayerAck};
 use crate::game::SlotSettings;
 use crate::node::messages::ListNode;
-use crate::player::state::conn::{Connect, Disconnect};
-use crate::player::state::ping::{GetPlayersPingSnapshot, UpdatePing};
+use crate::player::state::conn::{Connect, ConnectResult, Disconnect};
+use crate::player::state::pending::PENDING_FRAMES_TTL;
+use crate::player::state::ping::UpdatePing;
 use flo_net::ping::{PingMsg, PingStream};
 use flo_types::ping::PingStats;
 use futures::{StreamExt, TryStreamExt};
-pub use sender::{PlayerReceiver, PlayerSender, PlayerSenderMessage};
-
-const PING_INTERVAL: Duration = Duration::from_secs(30);
-const PING_TIMEOUT: Duration = Duration::from_secs(5);
+use reliable::ReliableSender;
+use tracing_futures::Instrument;
+pub use sender::{PlayerReceiver, PlayerSender, PlayerSenderMessage, SendDropPolicy};
 
 pub async fn serve(state: ControllerStateRef) -> Result<()> {
   state
     .db
-    .exec(|conn| crate::game::db::reset_instance_state(conn))
+    .exec_traced(|conn| crate::game::db::reset_instance_state(conn))
     .await?;
 
-  let mut listener = FloListener::bind_v4(flo_constants::CONTROLLER_SOCKET_PORT).await?;
+  let mut listener = FloListener::bind(flo_constants::CONTROLLER_SOCKET_PORT).await?;
   tracing::info!("listening on port {}", listener.port());
 
   while let Some(mut stream) = listener.incoming().try_next().await? {
@@ -55,22 +63,53 @@ pub async fn serve(state: ControllerStateRef) -> Result<()> {
       let player_id = accepted.player_id;
       tracing::debug!("accepted: player_id = {}", player_id);
 
-      if accepted.client_version < flo_constants::MIN_FLO_VERSION {
+      let min_version = *crate::config::MIN_CLIENT_VERSION;
+      if accepted.client_version < min_version {
+        tracing::debug!(
+          player_id,
+          "rejected: client version too old: {}",
+          accepted.client_version
+        );
         stream
           .send(proto::flo_connect::PacketClientConnectReject {
             lobby_version: Some(From::from(crate::version::FLO_LOBBY_VERSION)),
             reason: proto::flo_connect::ClientConnectRejectReason::ClientVersionTooOld.into(),
+            upgrade_required: Some(proto::flo_connect::ClientUpgradeRequired {
+              min_version: Some(min_version.into()),
+              download_url: crate::config::CLIENT_DOWNLOAD_URL.clone(),
+            }),
           })
           .await?;
         stream.shutdown().await?;
         return Ok(());
       }
 
-      if let Err(err) = handle_stream(state.clone(), player_id, stream).await {
-        tracing::debug!("stream error: {}", err);
-      }
+      let (sender, receiver) = PlayerSender::new(player_id);
+      let session_id = sender.session_id();
+      let mut reliable =
+        ReliableSender::new(stream.capabilities().supports(Feature::ReliableDelivery));
+      let dead = match handle_stream(state.clone(), sender, receiver, stream, &mut reliable).await {
+        Ok(end) => end == StreamEnd::Dead,
+        Err(err) => {
+          tracing::debug!("stream error: {}", err);
+          false
+        }
+      };
 
-      state.players.send(Disconnect { player_id }).await?;
+      let disconnected = state
+        .player_packet_sender
+        .disconnect(Disconnect {
+          player_id,
+          session_id,
+          unacked: reliable.into_unacked(),
+        })
+        .await?;
+      if let Some(game_id) = disconnected.and_then(|res| res.game_id) {
+        if dead {
+          tokio::time::sleep(PENDING_FRAMES_TTL).await;
+          remove_dead_player(&state, game_id, player_id).await;
+        }
+      }
       tracing::debug!("exiting: player_id = {}", player_id);
       Ok::<_, crate::error::Error>(())
     });
@@ -81,17 +120,36 @@ pub async fn serve(state: ControllerStateRef) -> Result<()> {
   Ok(())
 }
 
-#[tracing::instrument(target = "player_stream", skip(state, stream))]
+#[derive(Debug, PartialEq)]
+enum StreamEnd {
+  Closed,
+  /// Heartbeat timeout, send timeout or ack timeout
+  Dead,
+}
+
+#[tracing::instrument(
+  target = "player_stream",
+  skip(state, sender, receiver, stream, reliable),
+  fields(player_id = sender.player_id())
+)]
 async fn handle_stream(
   state: ControllerStateRef,
-  player_id: i32,
+  sender: PlayerSender,
+  mut receiver: PlayerReceiver,
   mut stream: FloStream,
-) -> Result<()> {
-  let (sender, mut receiver) = PlayerSender::new(player_id);
+  reliable: &mut ReliableSender,
+) -> Result<StreamEnd> {
+  let player_id = sender.player_id();
 
-  send_initial_state(state.clone(), &mut stream, sender).await?;
+  if send_initial_state(state.clone(), &mut stream, sender).await? == ConnectResult::Rejected {
+    tracing::debug!("rejected: session exists");
+    return Ok(StreamEnd::Closed);
+  }
 
-  let mut ping = PingStream::interval(PING_INTERVAL, PING_TIMEOUT);
+  let mut ping = PingStream::interval(
+    *crate::config::PLAYER_PING_INTERVAL,
+    *crate::config::PLAYER_PING_TIMEOUT,
+  );
   ping.start();
 
   loop {
@@ -103,7 +161,7 @@ async fn handle_stream(
           },
           PingMsg::Timeout => {
             tracing::debug!("heartbeat timeout");
-            break;
+            return Ok(StreamEnd::Dead);
           },
         }
       }
@@ -111,15 +169,24 @@ async fn handle_stream(
         if let Some(msg) = next {
           match msg {
             PlayerSenderMessage::Frame(frame) => {
+              let frame = match reliable.wrap(frame) {
+                Ok(frame) => frame,
+                Err(Error::PlayerAckTimeout) => {
+                  tracing::debug!("ack timeout");
+                  return Ok(StreamEnd::Dead);
+                }
+                Err(err) => return Err(err),
+              };
               if let Err(e) = stream.send_frame_timeout(frame).await {
                 tracing::debug!("send error: {}", e);
-                break;
+                return Ok(StreamEnd::Dead);
               }
             }
-            PlayerSenderMessage::Disconnect(reason) => {
+            PlayerSenderMessage::Disconnect { reason, taken_over_by_ip } => {
               use flo_net::proto::flo_connect::PacketClientDisconnect;
               if let Err(e) = stream.send(PacketClientDisconnect {
-                reason: reason.into()
+                reason: reason.into(),
+                taken_over_by_ip,
               }).await {
                 tracing::debug!("send error: {}", e);
               }
@@ -138,10 +205,22 @@ async fn handle_stream(
           continue;
         }
 
+        if frame.type_id == PacketTypeId::ReliableMessageAck {
+          let ack: proto::flo_connect::PacketReliableMessageAck = frame.decode()?;
+          reliable.ack(ack.seq);
+          continue;
+        }
+
+        // a read-only session is promoted when the primary one disconnects
+        if receiver.is_read_only() {
+          tracing::debug!("read-only session, ignoring packet: {:?}", frame.type_id);
+          continue;
+        }
+
         flo_net::try_flo_packet! {
           frame => {
             packet: proto::flo_connect::PacketGameSlotUpdateRequest => {
-              handle_game_slot_update_request(state.clone(), player_id, packet).await?;
+              handle_game_slot_update_request(state.clone(), player_id, packet).await;
             }
             _packet: proto::flo_connect::PacketListNodesRequest => {
               handle_list_nodes_request(state.clone(), player_id).await?;
@@ -167,25 +246,47 @@ async fn handle_stream(
             packet: proto::flo_connect::PacketPlayerMuteRemoveRequest => {
               handle_player_mute_list_update_request(state.clone(), player_id, packet.into()).await?;
             }
+            packet: proto::flo_connect::PacketGameMapVoteRequest => {
+              handle_game_map_vote_request(state.clone(), player_id, packet).await?;
+            }
+            packet: proto::flo_connect::PacketGameSlotShuffleRequest => {
+              handle_game_slot_shuffle_request(state.clone(), player_id, packet).await?;
+            }
+            packet: proto::flo_connect::PacketGameSlotCloseRequest => {
+              handle_game_slot_close_request(state.clone(), player_id, packet).await?;
+            }
+            packet: proto::flo_connect::PacketGameSlotReserveRequest => {
+              handle_game_slot_reserve_request(state.clone(), player_id, packet).await?;
+            }
+            packet: proto::flo_connect::PacketGameRehostRequest => {
+              handle_game_rehost_request(state.clone(), player_id, packet).await;
+            }
+            packet: proto::flo_connect::PacketGameChatRequest => {
+              handle_game_chat_request(state.clone(), player_id, packet).await;
+            }
+            packet: proto::flo_connect::PacketGameMuteListUpdateRequest => {
+              handle_game_mute_list_update_request(state.clone(), player_id, packet).await;
+            }
           }
         }
       }
     }
   }
 
-  Ok(())
+  Ok(StreamEnd::Closed)
 }
 
 async fn send_initial_state(
   state: ControllerStateRef,
   stream: &mut FloStream,
   sender: PlayerSender,
-) -> Result<()> {
+) -> Result<ConnectResult> {
   let player_id = sender.player_id();
+  let ip = stream.peer_addr()?.ip();
 
   let (player, active_slots) = state
     .db
-    .exec(move |conn| -> Result<_> {
+    .exec_traced(move |conn| -> Result<_> {
       Ok((
         crate::player::db::get_ref(conn, player_id)?,
         crate::game::db::get_player_active_slots(conn, player_id)?,
@@ -193,16 +294,32 @@ async fn send_initial_state(
     })
     .await?;
 
-  let game_id = active_slots.last().map(|s| s.game_id);
+  let game_id = match active_slots.last() {
+    Some(slot) => rejoin_game(&state, player_id, slot.game_id).await?,
+    None => None,
+  };
 
-  state
-    .players
-    .notify(Connect {
+  let result = state
+    .player_packet_sender
+    .connect(Connect {
       game_id: game_id.clone(),
       sender,
+      ip,
     })
     .await?;
 
+  if result == ConnectResult::Rejected {
+    stream
+      .send(proto::flo_connect::PacketClientConnectReject {
+        lobby_version: Some(From::from(crate::version::FLO_LOBBY_VERSION)),
+        reason: proto::flo_connect::ClientConnectRejectReason::SessionExists.into(),
+        upgrade_required: None,
+      })
+      .await?;
+    stream.shutdown().await?;
+    return Ok(result);
+  }
+
   let frame_accept = connect::PacketClientConnectAccept {
     lobby_version: Some(From::from(crate::version::FLO_LOBBY_VERSION)),
     session: Some({
@@ -215,20 +332,41 @@ async fn send_initial_state(
           PlayerStatus::Idle.into()
         },
         game_id: game_id.clone(),
+        read_only: result == ConnectResult::ReadOnly,
       }
     }),
     nodes: state.nodes.send(ListNode).await?.pack()?,
+    latest_release: crate::config::CLIENT_RELEASE
+      .as_ref()
+      .and_then(|release| release.pack()),
+    capabilities: Some(Capabilities::local().into()),
   }
   .encode_as_frame()?;
 
   let mut frames = vec![frame_accept];
 
   if let Some(game_id) = game_id {
-    let (mut game, node_player_token) = state
+    let (mut game, node_player_token, mute_list) = state
       .db
-      .exec(move |conn| crate::game::db::get_full_and_node_token(conn, game_id, player_id))
+      .exec_traced(move |conn| -> Result<_> {
+        let (game, node_player_token) =
+          crate::game::db::get_full_and_node_token(conn, game_id, player_id)?;
+        let mute_list = crate::player::db::get_mute_list_map(conn, &game.get_player_ids())?
+          .remove(&player_id)
+          .unwrap_or_default();
+        Ok((game, node_player_token, mute_list))
+      })
       .await?;
 
+    let game_mute_list = state
+      .games
+      .send_to(game_id, GetGameMuteList { player_id })
+      .await
+      .unwrap_or_else(|err| {
+        tracing::warn!(game_id, player_id, "get game mute list: {}", err);
+        vec![]
+      });
+
     let node_id = game.node.as_ref().map(|node| node.id);
 
     if game.mask_player_names {
@@ -244,12 +382,21 @@ async fn send_initial_state(
     let frame = connect::PacketGameInfo { game: Some(game) }.encode_as_frame()?;
     frames.push(frame);
 
+    let frame = proto::flo_connect::PacketPlayerMuteListUpdate {
+      mute_list,
+      game_mute_list,
+    }
+    .encode_as_frame()?;
+    frames.push(frame);
+
     if let Some(player_token) = node_player_token {
       let frame = connect::PacketGamePlayerToken {
         node_id: node_id.ok_or_else(|| Error::GameNodeNotSelected)?,
         game_id,
         player_id,
         player_token: player_token.to_vec(),
+        // relays are not persisted, reconnecting players connect directly
+        relay_node_id: None,
       }
       .encode_as_frame()?;
       frames.push(frame);
@@ -257,26 +404,114 @@ async fn send_initial_state(
   }
 
   stream.send_frames(frames).await?;
-  Ok(())
+  Ok(result)
 }
 
-async fn handle_game_slot_update_request(
-  state: ControllerStateRef,
+/// Reattaches a reconnecting player to the game the database says they joined,
+/// returns `None` if the game is not loaded
+async fn rejoin_game(
+  state: &ControllerStateRef,
   player_id: i32,
-  packet: proto::flo_connect::PacketGameSlotUpdateRequest,
-) -> Result<()> {
+  game_id: i32,
+) -> Result<Option<i32>> {
+  match state
+    .games
+    .send_to(game_id, PlayerRejoin { player_id })
+    .await
+  {
+    Ok(()) => {}
+    Err(Error::ActorNotFound) => {
+      tracing::warn!(player_id, game_id, "rejoin: game not loaded");
+      return Ok(None);
+    }
+    Err(err) => return Err(err),
+  }
   state
     .games
-    .send_to(
-      packet.game_id,
-      UpdateSlot {
-        player_id,
-        slot_index: packet.slot_index,
-        settings: SlotSettings::unpack(packet.slot_settings.extract()?)?,
-      },
-    )
+    .send(AddGamePlayer { game_id, player_id })
     .await?;
-  Ok(())
+  Ok(Some(game_id))
+}
+
+/// Releases the lobby slot of a player whose connection died, other players are notified.
+/// Players who reconnected since keep their slot
+async fn remove_dead_player(state: &ControllerStateRef, game_id: i32, player_id: i32) {
+  match state
+    .player_packet_sender
+    .get_online_players(vec![player_id])
+    .await
+  {
+    Ok(online) if !online.is_empty() => {
+      tracing::debug!(game_id, player_id, "dead player reconnected");
+      return;
+    }
+    Ok(_) => {}
+    Err(err) => {
+      tracing::error!(game_id, player_id, "remove dead player: {}", err);
+      return;
+    }
+  }
+
+  let res = state
+    .games
+    .send_to(game_id, RemoveDeadPlayer { player_id })
+    .await;
+  let res = match res {
+    Ok(Some(res)) => res,
+    Ok(None) | Err(Error::ActorNotFound) => return,
+    Err(err) => {
+      tracing::error!(game_id, player_id, "remove dead player: {}", err);
+      return;
+    }
+  };
+  let res = if res.game_ended {
+    state.games.send(Remove { game_id }).await
+  } else {
+    state
+      .games
+      .send(RemoveGamePlayer { game_id, player_id })
+      .await
+  };
+  if let Err(err) = res {
+    tracing::error!(game_id, player_id, "remove dead player: {}", err);
+  }
+}
+
+async fn handle_game_slot_update_request(
+  state: ControllerStateRef,
+  player_id: i32,
+  packet: proto::flo_connect::PacketGameSlotUpdateRequest,
+) {
+  let game_id = packet.game_id;
+  let slot_index = packet.slot_index;
+  let res = match packet
+    .slot_settings
+    .extract()
+    .map_err(Error::from)
+    .and_then(|settings| SlotSettings::unpack(settings).map_err(Error::from))
+  {
+    Ok(settings) => {
+      state
+        .requests
+        .update_slot
+        .run(player_id, game_id, &packet.request_id, || {
+          state.games.send_to(
+            game_id,
+            UpdateSlot {
+              player_id,
+              slot_index,
+              settings,
+            },
+          )
+        })
+        .await
+    }
+    Err(err) => Err(err),
+  };
+  // rejected updates leave the lobby unchanged, the connection is kept
+  if let Err(err) = res {
+    tracing::warn!(game_id, player_id, slot_index, "update slot: {}", err);
+  }
 }
 
 async fn handle_list_nodes_request(state: ControllerStateRef, player_id: i32) -> Result<()> {
@@ -308,8 +543,8 @@ async fn handle_player_ping_map_update_request(
   let mut node_ids: Vec<_> = ping_map.keys().cloned().collect();
 
   state
-    .players
-    .send(UpdatePing {
+    .player_packet_sender
+    .update_ping(UpdatePing {
       player_id,
       ping_map,
     })
@@ -322,7 +557,7 @@ async fn handle_player_ping_map_update_request(
     .games
     .send(ResolveGamePlayerPingBroadcastTargets {
       player_id,
-      node_ids,
+      node_ids: node_ids.clone(),
     })
     .await??;
 
@@ -338,6 +573,14 @@ async fn handle_player_ping_map_update_request(
     )
     .await?;
 
+  state
+    .games
+    .notify(NotifyGamePlayerPingUpdate {
+      player_id,
+      node_ids,
+    })
+    .await?;
+
   Ok(())
 }
 
@@ -350,8 +593,8 @@ async fn handle_game_player_ping_map_snapshot_request(
 
   let players = state.games.send_to(game_id, GetGamePlayers).await?;
   let snapshot = state
-    .players
-    .send(GetPlayersPingSnapshot { players })
+    .player_packet_sender
+    .get_ping_snapshot(players)
     .await?;
 
   let mut node_ping_map = HashMap::<i32, NodePingMap>::new();
@@ -409,9 +652,18 @@ async fn handle_game_start_request(
   player_id: i32,
   packet: proto::flo_connect::PacketGameStartRequest,
 ) -> Result<()> {
+  let span = tracing::info_span!("game_start", game_id = packet.game_id, player_id);
+  flo_log::trace_context::set_parent(&span, &packet.trace_context);
   state
     .games
-    .send_to(packet.game_id, StartGameCheck { player_id })
+    .send_to(
+      packet.game_id,
+      StartGameCheck {
+        player_id,
+        span: span.clone(),
+      },
+    )
+    .instrument(span)
     .await?;
   Ok(())
 }
@@ -428,6 +680,143 @@ async fn handle_game_start_player_client_info_request(
   Ok(())
 }
 
+async fn handle_game_map_vote_request(
+  state: ControllerStateRef,
+  player_id: i32,
+  packet: proto::flo_connect::PacketGameMapVoteRequest,
+) -> Result<()> {
+  state
+    .games
+    .send_to(
+      packet.game_id,
+      CastMapVote {
+        player_id,
+        option_index: packet.option_index,
+      },
+    )
+    .await?;
+  Ok(())
+}
+
+async fn handle_game_slot_shuffle_request(
+  state: ControllerStateRef,
+  player_id: i32,
+  packet: proto::flo_connect::PacketGameSlotShuffleRequest,
+) -> Result<()> {
+  state
+    .games
+    .send_to(
+      packet.game_id,
+      ShuffleSlots {
+        player_id,
+        races: packet.races,
+        teams: packet.teams,
+      },
+    )
+    .await?;
+  Ok(())
+}
+
+async fn handle_game_slot_close_request(
+  state: ControllerStateRef,
+  player_id: i32,
+  packet: proto::flo_connect::PacketGameSlotCloseRequest,
+) -> Result<()> {
+  state
+    .games
+    .send_to(
+      packet.game_id,
+      SetSlotClosed {
+        player_id,
+        slot_index: packet.slot_index,
+        closed: packet.closed,
+      },
+    )
+    .await?;
+  Ok(())
+}
+
+async fn handle_game_slot_reserve_request(
+  state: ControllerStateRef,
+  player_id: i32,
+  packet: proto::flo_connect::PacketGameSlotReserveRequest,
+) -> Result<()> {
+  state
+    .games
+    .send_to(
+      packet.game_id,
+      ReserveSlot {
+        player_id,
+        slot_index: packet.slot_index,
+        reserved_player_id: packet.player_id,
+      },
+    )
+    .await?;
+  Ok(())
+}
+
+async fn handle_game_rehost_request(
+  state: ControllerStateRef,
+  player_id: i32,
+  packet: proto::flo_connect::PacketGameRehostRequest,
+) {
+  let game_id = packet.game_id;
+  if let Err(err) = state
+    .games
+    .send(RehostGame {
+      game_id,
+      player_id,
+    })
+    .await
+    .map_err(Error::from)
+    .and_then(|res| res)
+  {
+    tracing::warn!(game_id, player_id, "rehost game: {}", err);
+  }
+}
+
+async fn handle_game_chat_request(
+  state: ControllerStateRef,
+  player_id: i32,
+  packet: proto::flo_connect::PacketGameChatRequest,
+) {
+  let game_id = packet.game_id;
+  if let Err(err) = state
+    .games
+    .send_to(
+      game_id,
+      SendChat {
+        player_id,
+        message: packet.message,
+      },
+    )
+    .await
+  {
+    tracing::debug!(game_id, player_id, "game chat: {}", err);
+  }
+}
+
+async fn handle_game_mute_list_update_request(
+  state: ControllerStateRef,
+  player_id: i32,
+  packet: proto::flo_connect::PacketGameMuteListUpdateRequest,
+) {
+  let game_id = packet.game_id;
+  if let Err(err) = state
+    .games
+    .send_to(
+      game_id,
+      UpdateGameMuteList {
+        player_id,
+        mute_list: packet.mute_list,
+      },
+    )
+    .await
+  {
+    tracing::debug!(game_id, player_id, "game mute list update: {}", err);
+  }
+}
+
 enum PlayerMuteListUpdate {
   Add(proto::flo_connect::PacketPlayerMuteAddRequest),
   Remove(proto::flo_connect::PacketPlayerMuteRemoveRequest),
@@ -450,14 +839,37 @@ async fn handle_player_mute_list_update_request(
   player_id: i32,
   update: PlayerMuteListUpdate,
 ) -> Result<()> {
-  state
+  let res = state
     .db
-    .exec(move |conn| match update {
+    .exec_traced(move |conn| match update {
+      PlayerMuteListUpdate::Add(req) if !req.name_pattern.is_empty() => {
+        match flo_util::name_pattern::normalize(&req.name_pattern) {
+          Some(pattern) => crate::player::db::add_mute_pattern(conn, player_id, &pattern),
+          None => {
+            tracing::warn!(player_id, "invalid mute pattern: {}", req.name_pattern);
+            Ok(())
+          }
+        }
+      }
       PlayerMuteListUpdate::Add(req) => crate::player::db::add_mute(conn, player_id, req.player_id),
+      PlayerMuteListUpdate::Remove(req) if !req.name_pattern.is_empty() => {
+        crate::player::db::remove_mute_pattern(
+          conn,
+          player_id,
+          &req.name_pattern.trim().to_lowercase(),
+        )
+      }
       PlayerMuteListUpdate::Remove(req) => {
         crate::player::db::remove_mute(conn, player_id, req.player_id)
       }
     })
-    .await?;
-  Ok(())
+    .await
+    .map_err(Error::from);
+  match res {
+    Err(Error::PlayerMutePatternLimit) => {
+      tracing::warn!(player_id, "mute pattern limit reached");
+      Ok(())
+    }
+    res => res,
+  }
 }
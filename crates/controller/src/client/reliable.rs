@@ -0,0 +1,135 @@
+//! Acknowledged delivery of the lobby packets a client can't recover from missing.
+//!
+//! Critical frames are wrapped in `PacketReliableMessage` with an increasing sequence
+//! number and kept until the client acknowledges them. The stream delivers them in order
+//! while it is up, frames still unacknowledged when it goes down are queued for the next
+//! connection of the player, see `player::state::pending`.
+//! The stream is dropped if the client stops acknowledging.
+//! Clients without [`Feature::ReliableDelivery`] receive the frames unwrapped.
+//!
+//! [`Feature::ReliableDelivery`]: flo_net::capability::Feature::ReliableDelivery
+
+use flo_net::packet::{FloPacket, Frame, FramePayload, PacketTypeId};
+use flo_net::proto::flo_connect::PacketReliableMessage;
+use std::collections::VecDeque;
+
+use crate::error::*;
+
+const MAX_UNACKED: usize = 64;
+
+/// Game start, node assignment and kicks
+fn is_critical(type_id: PacketTypeId) -> bool {
+  matches!(
+    type_id,
+    PacketTypeId::GameStarting
+      | PacketTypeId::GameSelectNode
+      | PacketTypeId::GamePlayerToken
+      | PacketTypeId::GamePlayerLeave
+  )
+}
+
+#[derive(Debug)]
+struct Unacked {
+  seq: u64,
+  /// The frame before wrapping, the next connection numbers it again
+  frame: Frame,
+}
+
+#[derive(Debug)]
+pub struct ReliableSender {
+  enabled: bool,
+  next_seq: u64,
+  unacked: VecDeque<Unacked>,
+}
+
+impl ReliableSender {
+  pub fn new(enabled: bool) -> Self {
+    Self {
+      enabled,
+      next_seq: 1,
+      unacked: VecDeque::new(),
+    }
+  }
+
+  /// Wraps critical frames, other frames are returned unchanged
+  pub fn wrap(&mut self, frame: Frame) -> Result<Frame> {
+    if !self.enabled || !is_critical(frame.type_id) {
+      return Ok(frame);
+    }
+
+    let payload = match frame.payload {
+      FramePayload::Bytes(ref bytes) => bytes.to_vec(),
+      FramePayload::W3GS { .. } => return Ok(frame),
+    };
+
+    if self.unacked.len() == MAX_UNACKED {
+      return Err(Error::PlayerAckTimeout);
+    }
+
+    let seq = self.next_seq;
+    self.next_seq += 1;
+    let wrapped = PacketReliableMessage {
+      seq,
+      type_id: u8::from(frame.type_id) as u32,
+      payload,
+    }
+    .encode_as_frame()?;
+    self.unacked.push_back(Unacked { seq, frame });
+    Ok(wrapped)
+  }
+
+  /// Acks are cumulative
+  pub fn ack(&mut self, seq: u64) {
+    while self.unacked.front().map(|m| m.seq <= seq).unwrap_or(false) {
+      self.unacked.pop_front();
+    }
+  }
+
+  /// Unwrapped frames the client didn't acknowledge, in the order they were sent
+  pub fn into_unacked(self) -> Vec<Frame> {
+    self.unacked.into_iter().map(|m| m.frame).collect()
+  }
+}
+
+#[test]
+fn test_reliable_sender() {
+  use flo_net::proto::flo_connect::{PacketGameSelectNode, PacketGameStarting};
+
+  let mut sender = ReliableSender::new(true);
+  let frame = sender
+    .wrap(Frame::new_empty(PacketTypeId::PlayerSessionUpdate))
+    .unwrap();
+  assert_eq!(frame.type_id, PacketTypeId::PlayerSessionUpdate);
+
+  let frame = sender
+    .wrap(PacketGameStarting { game_id: 1 }.encode_as_frame().unwrap())
+    .unwrap();
+  let msg: PacketReliableMessage = frame.decode().unwrap();
+  assert_eq!(msg.seq, 1);
+  assert_eq!(
+    PacketTypeId::from(msg.type_id as u8),
+    PacketTypeId::GameStarting
+  );
+  sender
+    .wrap(
+      PacketGameSelectNode {
+        game_id: 1,
+        node_id: Some(1),
+      }
+      .encode_as_frame()
+      .unwrap(),
+    )
+    .unwrap();
+
+  sender.ack(1);
+  assert_eq!(sender.unacked.len(), 1);
+  let unacked = sender.into_unacked();
+  assert_eq!(unacked.len(), 1);
+  assert_eq!(unacked[0].type_id, PacketTypeId::GameSelectNode);
+
+  let mut sender = ReliableSender::new(false);
+  let frame = sender
+    .wrap(PacketGameStarting { game_id: 1 }.encode_as_frame().unwrap())
+    .unwrap();
+  assert_eq!(frame.type_id, PacketTypeId::GameStarting);
+}
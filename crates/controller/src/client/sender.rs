@@ -1,62 +1,298 @@
 use flo_net::packet::*;
 use flo_net::proto::flo_connect::*;
-use std::time::Duration;
-use tokio::sync::mpsc::{channel, Receiver, Sender};
+use parking_lot::Mutex;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use tokio::sync::Notify;
 
 use crate::error::*;
 
-pub type PlayerReceiver = Receiver<PlayerSenderMessage>;
+static NEXT_SESSION_ID: AtomicU64 = AtomicU64::new(1);
+
+#[derive(Debug)]
 pub enum PlayerSenderMessage {
   Frame(Frame),
-  Disconnect(ClientDisconnectReason),
+  Disconnect {
+    reason: ClientDisconnectReason,
+    taken_over_by_ip: Option<String>,
+  },
+}
+
+/// What happens when the send buffer of a slow connection is full
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SendDropPolicy {
+  /// Queued state updates are replaced by newer ones, then low priority frames are dropped
+  Coalesce,
+  /// Ping updates are dropped
+  DropLowPriority,
+  /// The connection is dropped, the player gets the full state when they reconnect
+  Disconnect,
+}
+
+impl std::str::FromStr for SendDropPolicy {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "coalesce" => Ok(SendDropPolicy::Coalesce),
+      "drop_low_priority" => Ok(SendDropPolicy::DropLowPriority),
+      "disconnect" => Ok(SendDropPolicy::Disconnect),
+      other => Err(format!("unknown send drop policy: {}", other)),
+    }
+  }
+}
+
+#[derive(Debug, PartialEq)]
+enum PushResult {
+  Queued,
+  Coalesced,
+  Dropped,
+  Overflow,
+}
+
+/// Frames carrying the latest state of something, only the last one matters
+fn coalesce_key(frame: &Frame) -> Option<(PacketTypeId, i32, i32)> {
+  let type_id = frame.type_id;
+  let key = match type_id {
+    PacketTypeId::GameSlotUpdate => {
+      let p: PacketGameSlotUpdate = frame.clone().decode().ok()?;
+      (p.game_id, p.slot_index)
+    }
+    PacketTypeId::PlayerPingMapUpdate => {
+      let p: PacketPlayerPingMapUpdate = frame.clone().decode().ok()?;
+      (p.player_id, 0)
+    }
+    PacketTypeId::GameSlotPingUpdate => {
+      let p: PacketGameSlotPingUpdate = frame.clone().decode().ok()?;
+      (p.game_id, 0)
+    }
+    PacketTypeId::GamePlayerPingMapSnapshot => {
+      let p: PacketGamePlayerPingMapSnapshot = frame.clone().decode().ok()?;
+      (p.game_id, 0)
+    }
+    _ => return None,
+  };
+  Some((type_id, key.0, key.1))
+}
+
+fn is_low_priority(type_id: PacketTypeId) -> bool {
+  matches!(
+    type_id,
+    PacketTypeId::PlayerPingMapUpdate
+      | PacketTypeId::GameSlotPingUpdate
+      | PacketTypeId::GamePlayerPingMapSnapshot
+  )
+}
+
+#[derive(Debug)]
+struct Queue {
+  messages: VecDeque<PlayerSenderMessage>,
+  capacity: usize,
+  policy: SendDropPolicy,
+  senders: usize,
+  closed: bool,
 }
 
-#[derive(Debug, Clone)]
+impl Queue {
+  fn push_frame(&mut self, frame: Frame) -> PushResult {
+    if self.messages.len() < self.capacity {
+      self.messages.push_back(PlayerSenderMessage::Frame(frame));
+      return PushResult::Queued;
+    }
+
+    if self.policy == SendDropPolicy::Coalesce {
+      if let Some(key) = coalesce_key(&frame) {
+        let idx = self.position(|queued| coalesce_key(queued) == Some(key));
+        if let Some(idx) = idx {
+          // the older frame is removed so the ordering with other frames is kept
+          self.messages.remove(idx);
+          self.messages.push_back(PlayerSenderMessage::Frame(frame));
+          return PushResult::Coalesced;
+        }
+      }
+    }
+
+    if self.policy == SendDropPolicy::Disconnect {
+      return PushResult::Overflow;
+    }
+
+    if is_low_priority(frame.type_id) {
+      return PushResult::Dropped;
+    }
+    if let Some(idx) = self.position(|queued| is_low_priority(queued.type_id)) {
+      self.messages.remove(idx);
+      self.messages.push_back(PlayerSenderMessage::Frame(frame));
+      return PushResult::Dropped;
+    }
+
+    PushResult::Overflow
+  }
+
+  fn position<F>(&self, f: F) -> Option<usize>
+  where
+    F: Fn(&Frame) -> bool,
+  {
+    self.messages.iter().position(|msg| match *msg {
+      PlayerSenderMessage::Frame(ref frame) => f(frame),
+      PlayerSenderMessage::Disconnect { .. } => false,
+    })
+  }
+}
+
+#[derive(Debug)]
+struct Shared {
+  queue: Mutex<Queue>,
+  notify: Notify,
+  /// Requests of read-only sessions are ignored, see `MultiSessionPolicy::ReadOnly`
+  read_only: AtomicBool,
+}
+
+#[derive(Debug)]
+pub struct PlayerReceiver {
+  shared: Arc<Shared>,
+}
+
+impl PlayerReceiver {
+  pub fn is_read_only(&self) -> bool {
+    self.shared.read_only.load(Ordering::Relaxed)
+  }
+
+  /// Returns `None` once every sender is dropped
+  pub async fn recv(&mut self) -> Option<PlayerSenderMessage> {
+    loop {
+      {
+        let mut queue = self.shared.queue.lock();
+        if let Some(msg) = queue.messages.pop_front() {
+          return Some(msg);
+        }
+        if queue.senders == 0 {
+          return None;
+        }
+      }
+      self.shared.notify.notified().await;
+    }
+  }
+}
+
+impl Drop for PlayerReceiver {
+  fn drop(&mut self) {
+    let mut queue = self.shared.queue.lock();
+    queue.closed = true;
+    queue.messages.clear();
+  }
+}
+
+#[derive(Debug)]
 pub struct PlayerSender {
   player_id: i32,
-  sender: Sender<PlayerSenderMessage>,
+  session_id: u64,
+  shared: Arc<Shared>,
 }
 
 impl PlayerSender {
   pub fn new(player_id: i32) -> (Self, PlayerReceiver) {
-    let (sender, receiver) = channel(8);
-    (PlayerSender { player_id, sender }, receiver)
+    Self::with_buf(
+      player_id,
+      *crate::config::PLAYER_SEND_BUFFER_SIZE,
+      *crate::config::PLAYER_SEND_DROP_POLICY,
+    )
+  }
+
+  fn with_buf(player_id: i32, capacity: usize, policy: SendDropPolicy) -> (Self, PlayerReceiver) {
+    let shared = Arc::new(Shared {
+      queue: Mutex::new(Queue {
+        messages: VecDeque::with_capacity(capacity),
+        capacity,
+        policy,
+        senders: 1,
+        closed: false,
+      }),
+      notify: Notify::new(),
+      read_only: AtomicBool::new(false),
+    });
+    (
+      PlayerSender {
+        player_id,
+        session_id: NEXT_SESSION_ID.fetch_add(1, Ordering::Relaxed),
+        shared: shared.clone(),
+      },
+      PlayerReceiver { shared },
+    )
   }
 
   pub fn player_id(&self) -> i32 {
     self.player_id
   }
 
-  pub async fn disconnect_multi(&mut self) {
-    self.disconnect(ClientDisconnectReason::Multi).await;
+  /// Identifies the connection, a player reconnecting gets a new session id
+  pub fn session_id(&self) -> u64 {
+    self.session_id
   }
 
-  #[tracing::instrument]
-  async fn disconnect(&mut self, reason: ClientDisconnectReason) {
-    self
-      .sender
-      .send_timeout(
-        PlayerSenderMessage::Disconnect(reason),
-        Duration::from_secs(3),
-      )
-      .await
-      .ok();
+  pub fn set_read_only(&self, read_only: bool) {
+    self.shared.read_only.store(read_only, Ordering::Relaxed);
   }
 
-  pub fn try_send(&mut self, frame: Frame) -> bool {
+  pub async fn disconnect_multi(&mut self, taken_over_by_ip: Option<String>) {
     self
-      .sender
-      .try_send(PlayerSenderMessage::Frame(frame))
-      .is_ok()
+      .disconnect(ClientDisconnectReason::Multi, taken_over_by_ip)
+      .await;
+  }
+
+  #[tracing::instrument]
+  async fn disconnect(&mut self, reason: ClientDisconnectReason, taken_over_by_ip: Option<String>) {
+    // bypasses the buffer limit, frames queued before are still sent
+    let mut queue = self.shared.queue.lock();
+    if queue.closed {
+      return;
+    }
+    queue.messages.push_back(PlayerSenderMessage::Disconnect {
+      reason,
+      taken_over_by_ip,
+    });
+    drop(queue);
+    self.shared.notify.notify_one();
+  }
+
+  /// Returns false if the connection is closed or too slow, see [`SendDropPolicy`]
+  pub fn try_send(&self, frame: Frame) -> bool {
+    let mut queue = self.shared.queue.lock();
+    if queue.closed {
+      return false;
+    }
+    let type_id = frame.type_id;
+    let res = queue.push_frame(frame);
+    drop(queue);
+
+    match res {
+      PushResult::Queued => {}
+      PushResult::Coalesced => {
+        crate::metrics::PLAYER_SEND_COALESCED.inc();
+      }
+      PushResult::Dropped => {
+        crate::metrics::PLAYER_SEND_DROPPED.inc();
+      }
+      PushResult::Overflow => {
+        crate::metrics::PLAYER_SEND_OVERFLOWS.inc();
+        tracing::debug!(
+          player_id = self.player_id,
+          "send buffer full: {:?}",
+          type_id
+        );
+        return false;
+      }
+    }
+    self.shared.notify.notify_one();
+    true
   }
 
   pub async fn send_frame(&mut self, frame: Frame) -> Result<()> {
-    self
-      .sender
-      .send(PlayerSenderMessage::Frame(frame))
-      .await
-      .map_err(|_| Error::PlayerStreamClosed)?;
-    Ok(())
+    if self.try_send(frame) {
+      Ok(())
+    } else {
+      Err(Error::PlayerStreamClosed)
+    }
   }
 
   pub async fn send<T: FloPacket>(&mut self, packet: T) -> Result<()> {
@@ -64,3 +300,90 @@ impl PlayerSender {
     Ok(())
   }
 }
+
+impl Clone for PlayerSender {
+  fn clone(&self) -> Self {
+    self.shared.queue.lock().senders += 1;
+    PlayerSender {
+      player_id: self.player_id,
+      session_id: self.session_id,
+      shared: self.shared.clone(),
+    }
+  }
+}
+
+impl Drop for PlayerSender {
+  fn drop(&mut self) {
+    let mut queue = self.shared.queue.lock();
+    queue.senders -= 1;
+    if queue.senders == 0 {
+      drop(queue);
+      self.shared.notify.notify_one();
+    }
+  }
+}
+
+#[test]
+fn test_send_drop_policy() {
+  let slot_update = |slot_index: i32| {
+    PacketGameSlotUpdate {
+      game_id: 1,
+      slot_index,
+      ..Default::default()
+    }
+    .encode_as_frame()
+    .unwrap()
+  };
+  let ping_update = || {
+    PacketGameSlotPingUpdate {
+      game_id: 1,
+      ..Default::default()
+    }
+    .encode_as_frame()
+    .unwrap()
+  };
+  let starting = || PacketGameStarting { game_id: 1 }.encode_as_frame().unwrap();
+  let type_ids = |receiver: &PlayerReceiver| -> Vec<PacketTypeId> {
+    let queue = receiver.shared.queue.lock();
+    queue
+      .messages
+      .iter()
+      .filter_map(|msg| match *msg {
+        PlayerSenderMessage::Frame(ref frame) => Some(frame.type_id),
+        _ => None,
+      })
+      .collect()
+  };
+
+  let (sender, receiver) = PlayerSender::with_buf(1, 3, SendDropPolicy::Coalesce);
+  assert!(sender.try_send(slot_update(0)));
+  assert!(sender.try_send(ping_update()));
+  assert!(sender.try_send(slot_update(1)));
+  // replaces the update of slot 0
+  assert!(sender.try_send(slot_update(0)));
+  assert_eq!(
+    type_ids(&receiver),
+    vec![
+      PacketTypeId::GameSlotPingUpdate,
+      PacketTypeId::GameSlotUpdate,
+      PacketTypeId::GameSlotUpdate
+    ]
+  );
+  // replaces the ping update
+  assert!(sender.try_send(starting()));
+  assert_eq!(type_ids(&receiver)[2], PacketTypeId::GameStarting);
+  assert!(!sender.try_send(starting()));
+
+  let (sender, receiver) = PlayerSender::with_buf(1, 2, SendDropPolicy::DropLowPriority);
+  assert!(sender.try_send(slot_update(0)));
+  assert!(sender.try_send(slot_update(0)));
+  assert!(sender.try_send(ping_update()));
+  assert!(!sender.try_send(slot_update(0)));
+  assert_eq!(type_ids(&receiver).len(), 2);
+
+  let (sender, receiver) = PlayerSender::with_buf(1, 1, SendDropPolicy::Disconnect);
+  assert!(sender.try_send(ping_update()));
+  assert!(!sender.try_send(ping_update()));
+  drop(receiver);
+  assert!(!sender.clone().try_send(ping_update()));
+}
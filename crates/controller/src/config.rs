@@ -2,14 +2,21 @@ use arc_swap::ArcSwap;
 use bs_diesel_utils::{DbConn, ExecutorRef};
 use chrono::{DateTime, Utc};
 use diesel::prelude::*;
+use flo_constants::version::Version;
 use once_cell::sync::Lazy;
+use serde::Deserialize;
 use std::collections::BTreeMap;
 use std::env;
 use std::sync::Arc;
+use std::time::Duration;
 use tonic::{metadata::MetadataValue, service::Interceptor, Request, Status};
 
+use crate::audit::AuditActor;
+use crate::client::SendDropPolicy;
+use crate::db::ExecutorExt;
 use crate::error::*;
 
+use crate::player::session::MultiSessionPolicy;
 use crate::player::PlayerSource;
 use crate::schema::{api_client, player};
 use crate::state::{Data, Reload};
@@ -18,6 +25,312 @@ use flo_state::{async_trait, Actor, Context, Handler, Message, RegistryRef, Serv
 pub static JWT_SECRET_BASE64: Lazy<String> =
   Lazy::new(|| env::var("JWT_SECRET_BASE64").expect("env `JWT_SECRET_BASE64`"));
 
+/// Minimum client version accepted, `FLO_MIN_CLIENT_VERSION` or [`flo_constants::MIN_FLO_VERSION`]
+pub static MIN_CLIENT_VERSION: Lazy<Version> = Lazy::new(|| {
+  let value = env::var("FLO_MIN_CLIENT_VERSION").ok();
+  match value.map(|v| v.parse()) {
+    Some(Ok(version)) => version,
+    Some(Err(err)) => {
+      tracing::error!("invalid `FLO_MIN_CLIENT_VERSION`: {}", err);
+      flo_constants::MIN_FLO_VERSION
+    }
+    None => flo_constants::MIN_FLO_VERSION,
+  }
+});
+
+/// Download page sent to clients older than [`MIN_CLIENT_VERSION`]
+pub static CLIENT_DOWNLOAD_URL: Lazy<String> = Lazy::new(|| {
+  env::var("FLO_CLIENT_DOWNLOAD_URL").unwrap_or_else(|_| "https://w3flo.com".to_string())
+});
+
+/// Latest client release advertised to connecting clients,
+/// loaded from the JSON manifest at `FLO_CLIENT_RELEASE_MANIFEST`
+pub static CLIENT_RELEASE: Lazy<Option<ClientRelease>> = Lazy::new(|| {
+  let path = env::var("FLO_CLIENT_RELEASE_MANIFEST").ok()?;
+  match std::fs::read(&path)
+    .map_err(|err| err.to_string())
+    .and_then(|data| serde_json::from_slice(&data).map_err(|err| err.to_string()))
+  {
+    Ok(release) => Some(release),
+    Err(err) => {
+      tracing::error!("load client release manifest `{}`: {}", path, err);
+      None
+    }
+  }
+});
+
+/// Games a node is assumed to accept when computing the desired node count
+/// if it doesn't report a limit, `FLO_AUTOSCALE_GAMES_PER_NODE` or 100
+pub static AUTOSCALE_GAMES_PER_NODE: Lazy<u32> = Lazy::new(|| {
+  env::var("FLO_AUTOSCALE_GAMES_PER_NODE")
+    .ok()
+    .and_then(|v| v.parse().ok())
+    .filter(|v| *v > 0)
+    .unwrap_or(100)
+});
+
+/// Number of nodes tried before a game start fails, `FLO_GAME_START_MAX_ATTEMPTS` or 3
+pub static GAME_START_MAX_ATTEMPTS: Lazy<usize> = Lazy::new(|| {
+  env::var("FLO_GAME_START_MAX_ATTEMPTS")
+    .ok()
+    .and_then(|v| v.parse().ok())
+    .filter(|v| *v > 0)
+    .unwrap_or(3)
+});
+
+/// Players in a lobby whose client stopped responding are removed after this duration,
+/// `FLO_LOBBY_IDLE_TIMEOUT_SECS`. Idle players are never removed if not set
+pub static LOBBY_IDLE_TIMEOUT: Lazy<Option<Duration>> = Lazy::new(|| {
+  env::var("FLO_LOBBY_IDLE_TIMEOUT_SECS")
+    .ok()
+    .and_then(|v| v.parse().ok())
+    .filter(|v| *v > 0)
+    .map(Duration::from_secs)
+});
+
+/// Handling of a second connection of a connected player, `FLO_MULTI_SESSION_POLICY`:
+/// `evict_old` (default), `reject_new` or `read_only`
+pub static MULTI_SESSION_POLICY: Lazy<MultiSessionPolicy> = Lazy::new(|| {
+  let value = env::var("FLO_MULTI_SESSION_POLICY").ok();
+  match value.map(|v| v.parse()) {
+    Some(Ok(policy)) => policy,
+    Some(Err(err)) => {
+      tracing::error!("invalid `FLO_MULTI_SESSION_POLICY`: {}", err);
+      MultiSessionPolicy::EvictOld
+    }
+    None => MultiSessionPolicy::EvictOld,
+  }
+});
+
+/// Nodes whose average CPU usage reaches this percentage are skipped when picking
+/// a fallback or relay node, `FLO_NODE_MAX_CPU_USAGE`. Disabled if not set
+pub static NODE_MAX_CPU_USAGE: Lazy<Option<f32>> = Lazy::new(|| {
+  env::var("FLO_NODE_MAX_CPU_USAGE")
+    .ok()
+    .and_then(|v| v.parse().ok())
+    .filter(|v| *v > 0.)
+});
+
+/// Interval of the heartbeats sent to player connections, `FLO_PLAYER_PING_INTERVAL_SECS` or 30
+pub static PLAYER_PING_INTERVAL: Lazy<Duration> = Lazy::new(|| {
+  env::var("FLO_PLAYER_PING_INTERVAL_SECS")
+    .ok()
+    .and_then(|v| v.parse().ok())
+    .filter(|v| *v > 0)
+    .map(Duration::from_secs)
+    .unwrap_or(Duration::from_secs(30))
+});
+
+/// Player connections not answering a heartbeat within this duration are considered dead,
+/// their lobby slots are released, `FLO_PLAYER_PING_TIMEOUT_SECS` or 5
+pub static PLAYER_PING_TIMEOUT: Lazy<Duration> = Lazy::new(|| {
+  env::var("FLO_PLAYER_PING_TIMEOUT_SECS")
+    .ok()
+    .and_then(|v| v.parse().ok())
+    .filter(|v| *v > 0)
+    .map(Duration::from_secs)
+    .unwrap_or(Duration::from_secs(5))
+});
+
+/// Frames buffered for each player connection, `FLO_PLAYER_SEND_BUFFER_SIZE` or 32
+pub static PLAYER_SEND_BUFFER_SIZE: Lazy<usize> = Lazy::new(|| {
+  env::var("FLO_PLAYER_SEND_BUFFER_SIZE")
+    .ok()
+    .and_then(|v| v.parse().ok())
+    .filter(|v| *v > 0)
+    .unwrap_or(32)
+});
+
+/// Handling of frames sent to a player connection whose buffer is full,
+/// `FLO_PLAYER_SEND_DROP_POLICY`: `coalesce` (default), `drop_low_priority` or `disconnect`
+pub static PLAYER_SEND_DROP_POLICY: Lazy<SendDropPolicy> = Lazy::new(|| {
+  let value = env::var("FLO_PLAYER_SEND_DROP_POLICY").ok();
+  match value.map(|v| v.parse()) {
+    Some(Ok(policy)) => policy,
+    Some(Err(err)) => {
+      tracing::error!("invalid `FLO_PLAYER_SEND_DROP_POLICY`: {}", err);
+      SendDropPolicy::Coalesce
+    }
+    None => SendDropPolicy::Coalesce,
+  }
+});
+
+/// Players are relayed by another node if their ping to it is lower than their ping
+/// to the game's node by at least this value, `FLO_RELAY_MIN_GAIN_MS`.
+/// Relaying is disabled if not set
+pub static RELAY_MIN_GAIN_MS: Lazy<Option<u32>> = Lazy::new(|| {
+  env::var("FLO_RELAY_MIN_GAIN_MS")
+    .ok()
+    .and_then(|v| v.parse().ok())
+});
+
+/// Base URL of the replay download endpoint, signed links are `<url>/<game_id>?token=<token>`
+pub static REPLAY_DOWNLOAD_URL: Lazy<Option<String>> = Lazy::new(|| {
+  env::var("FLO_REPLAY_DOWNLOAD_URL")
+    .ok()
+    .map(|url| url.trim_end_matches('/').to_string())
+});
+
+/// Players whose behavior score reaches this value start games muted,
+/// `FLO_REPUTATION_AUTO_MUTE_SCORE`. Disabled if not set
+pub static REPUTATION_AUTO_MUTE_SCORE: Lazy<Option<i32>> = Lazy::new(|| {
+  env::var("FLO_REPUTATION_AUTO_MUTE_SCORE")
+    .ok()
+    .and_then(|v| v.parse().ok())
+    .filter(|v| *v > 0)
+});
+
+/// Players whose behavior score reaches this value are flagged as low priority for matchmaking,
+/// `FLO_REPUTATION_LOW_PRIORITY_SCORE`. Disabled if not set
+pub static REPUTATION_LOW_PRIORITY_SCORE: Lazy<Option<i32>> = Lazy::new(|| {
+  env::var("FLO_REPUTATION_LOW_PRIORITY_SCORE")
+    .ok()
+    .and_then(|v| v.parse().ok())
+    .filter(|v| *v > 0)
+});
+
+/// Reports and games older than this number of days don't count in the behavior score,
+/// `FLO_REPUTATION_WINDOW_DAYS` or 30
+pub static REPUTATION_WINDOW_DAYS: Lazy<i64> = Lazy::new(|| {
+  env::var("FLO_REPUTATION_WINDOW_DAYS")
+    .ok()
+    .and_then(|v| v.parse().ok())
+    .filter(|v| *v > 0)
+    .unwrap_or(30)
+});
+
+/// External ladder endpoints receiving finished game results,
+/// loaded from the JSON file at `FLO_RESULT_EXPORTERS`
+pub static RESULT_EXPORTERS: Lazy<Vec<ResultExporterConfig>> = Lazy::new(|| {
+  let path = match env::var("FLO_RESULT_EXPORTERS") {
+    Ok(path) => path,
+    Err(_) => return vec![],
+  };
+  match std::fs::read(&path)
+    .map_err(|err| err.to_string())
+    .and_then(|data| serde_json::from_slice(&data).map_err(|err| err.to_string()))
+  {
+    Ok(exporters) => exporters,
+    Err(err) => {
+      tracing::error!("load result exporters `{}`: {}", path, err);
+      vec![]
+    }
+  }
+});
+
+/// Slot changes of a lobby are applied in memory and written together after this delay,
+/// `FLO_SLOT_WRITE_DELAY_MS` or 250. Set to 0 to write each change immediately
+pub static SLOT_WRITE_DELAY: Lazy<Duration> = Lazy::new(|| {
+  env::var("FLO_SLOT_WRITE_DELAY_MS")
+    .ok()
+    .and_then(|v| v.parse().ok())
+    .map(Duration::from_millis)
+    .unwrap_or(Duration::from_millis(250))
+});
+
+/// OAuth2/OIDC providers players can log in with,
+/// loaded from the JSON file at `FLO_AUTH_PROVIDERS`
+pub static AUTH_PROVIDERS: Lazy<Vec<AuthProviderConfig>> = Lazy::new(|| {
+  let path = match env::var("FLO_AUTH_PROVIDERS") {
+    Ok(path) => path,
+    Err(_) => return vec![],
+  };
+  match std::fs::read(&path)
+    .map_err(|err| err.to_string())
+    .and_then(|data| serde_json::from_slice(&data).map_err(|err| err.to_string()))
+  {
+    Ok(providers) => providers,
+    Err(err) => {
+      tracing::error!("load auth providers `{}`: {}", path, err);
+      vec![]
+    }
+  }
+});
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct AuthProviderConfig {
+  /// e.g. `discord`, prefixes the source id of players created by this provider
+  pub name: String,
+  pub client_id: String,
+  pub client_secret: String,
+  pub token_url: String,
+  pub userinfo_url: String,
+  /// Userinfo field holding the stable user id, e.g. `id` for Discord
+  #[serde(default = "AuthProviderConfig::default_subject_field")]
+  pub subject_field: String,
+  /// Userinfo field holding the display name, e.g. `battletag` for Battle.net
+  #[serde(default = "AuthProviderConfig::default_name_field")]
+  pub name_field: String,
+}
+
+impl AuthProviderConfig {
+  fn default_subject_field() -> String {
+    "sub".to_string()
+  }
+
+  fn default_name_field() -> String {
+    "name".to_string()
+  }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ResultExporterConfig {
+  pub name: String,
+  pub url: String,
+  /// Key used to sign request bodies with HMAC-SHA256, sent in `X-Flo-Signature`
+  pub secret: Option<String>,
+  #[serde(default = "ResultExporterConfig::default_max_retries")]
+  pub max_retries: u32,
+}
+
+impl ResultExporterConfig {
+  fn default_max_retries() -> u32 {
+    5
+  }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ClientRelease {
+  /// e.g. `0.13.0`
+  pub version: String,
+  pub artifacts: Vec<ClientReleaseArtifact>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ClientReleaseArtifact {
+  /// `<os>-<arch>`, e.g. `windows-x86_64`
+  pub target: String,
+  pub url: String,
+  /// Base64 encoded ed25519 signature of `<version>\n<target>\n` followed by
+  /// the SHA-256 digest of the artifact
+  pub signature: String,
+}
+
+impl ClientRelease {
+  pub fn pack(&self) -> Option<flo_net::proto::flo_connect::ClientRelease> {
+    use flo_net::proto::flo_connect;
+    let version: Version = match self.version.parse() {
+      Ok(version) => version,
+      Err(err) => {
+        tracing::error!("invalid client release version `{}`: {}", self.version, err);
+        return None;
+      }
+    };
+    Some(flo_connect::ClientRelease {
+      version: Some(version.into()),
+      artifacts: self
+        .artifacts
+        .iter()
+        .map(|artifact| flo_connect::ClientReleaseArtifact {
+          target: artifact.target.clone(),
+          url: artifact.url.clone(),
+          signature: artifact.signature.clone(),
+        })
+        .collect(),
+    })
+  }
+}
+
 #[derive(Debug, Queryable)]
 pub struct ApiClient {
   id: i32,
@@ -81,6 +394,10 @@ impl Handler<GetInterceptor> for ConfigStorage {
 pub const REQUEST_META_SECRET: &str = "x-flo-secret";
 pub const REQUEST_META_API_CLIENT_ID: &str = "x-flo-api-client-id-bin";
 pub const REQUEST_META_API_PLAYER_ID: &str = "x-flo-api-player-id-bin";
+/// Optional, name of the operator behind an admin API call
+pub const REQUEST_META_AUDIT_ACTOR: &str = "x-flo-audit-actor";
+/// Optional, reason of an admin API call
+pub const REQUEST_META_AUDIT_REASON: &str = "x-flo-audit-reason";
 
 #[derive(Clone)]
 pub struct FloGrpcInterceptor {
@@ -118,7 +435,7 @@ impl ConfigStorage {
     let mut map = BTreeMap::new();
 
     let (api_player_map, items) = db
-      .exec(|conn| -> Result<_> {
+      .exec_traced(|conn| -> Result<_> {
         create_api_players(conn)?;
 
         let api_player_map: BTreeMap<i32, i32> = player::table
@@ -184,6 +501,7 @@ fn create_api_players(conn: &DbConn) -> Result<()> {
 pub trait ApiRequestExt {
   fn get_api_client_id(&self) -> i32;
   fn get_api_player_id(&self) -> i32;
+  fn get_audit_actor(&self) -> AuditActor;
 }
 
 impl<T> ApiRequestExt for Request<T> {
@@ -206,4 +524,20 @@ impl<T> ApiRequestExt for Request<T> {
       .unwrap();
     i32::from_le_bytes([value[0], value[1], value[2], value[3]])
   }
+
+  fn get_audit_actor(&self) -> AuditActor {
+    let get = |key| {
+      self
+        .metadata()
+        .get(key)
+        .and_then(|v| v.to_str().ok())
+        .filter(|v| !v.is_empty())
+        .map(ToString::to_string)
+    };
+    AuditActor {
+      api_client_id: self.get_api_client_id(),
+      actor: get(REQUEST_META_AUDIT_ACTOR),
+      reason: get(REQUEST_META_AUDIT_REASON),
+    }
+  }
 }
@@ -1 +1,28 @@
 pub use bs_diesel_utils::{lock::transaction_with_advisory_lock, DbConn, Executor, ExecutorRef};
+
+use bs_diesel_utils::executor::ExecutorError;
+use flo_state::async_trait;
+use tracing_futures::Instrument;
+
+#[async_trait]
+pub trait ExecutorExt {
+  /// Same as `exec`, wrapped in a `db` span named after the closure
+  async fn exec_traced<F, T, E>(&self, f: F) -> Result<T, ExecutorError<E>>
+  where
+    F: FnOnce(&DbConn) -> Result<T, E> + Send + 'static,
+    T: Send + 'static,
+    E: Send + 'static;
+}
+
+#[async_trait]
+impl ExecutorExt for Executor {
+  async fn exec_traced<F, T, E>(&self, f: F) -> Result<T, ExecutorError<E>>
+  where
+    F: FnOnce(&DbConn) -> Result<T, E> + Send + 'static,
+    T: Send + 'static,
+    E: Send + 'static,
+  {
+    let span = tracing::info_span!("db", query = std::any::type_name::<F>());
+    self.exec(f).instrument(span).await
+  }
+}
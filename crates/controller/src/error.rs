@@ -13,7 +13,7 @@ pub enum Error {
   NodeNotReady,
   #[error("Node rejected connection: {addr:?}: {reason:?}")]
   NodeConnectionRejected {
-    addr: std::net::SocketAddrV4,
+    addr: std::net::SocketAddr,
     reason: flo_net::proto::flo_node::ControllerConnectRejectReason,
   },
   #[error("Unexpected node response")]
@@ -24,16 +24,28 @@ pub enum Error {
   NodeRequestTimeout,
   #[error("Node request cancelled")]
   NodeRequestCancelled,
+  #[error("Node does not support relaying")]
+  NodeRelayUnsupported,
+  #[error("Node does not support ban updates")]
+  NodeBanUpdateUnsupported,
+  #[error("Node rejected relay: {0}")]
+  NodeRelayRejected(String),
   #[error("Invalid node address: {0}")]
   InvalidNodeAddress(String),
+  #[error("Node has active games")]
+  NodeHasActiveGames,
   #[error("Player stream closed")]
   PlayerStreamClosed,
+  #[error("Player stopped acknowledging messages")]
+  PlayerAckTimeout,
   #[error("Player token expired")]
   PlayerTokenExpired,
   #[error("Join link expired")]
   JoinTokenExpired,
   #[error("You are not the host player")]
   PlayerNotHost,
+  #[error("Invalid game step range")]
+  GameStepRangeInvalid,
   #[error("Player not found")]
   PlayerNotFound,
   #[error("Game not found")]
@@ -44,6 +56,12 @@ pub enum Error {
   GameDataInvalid,
   #[error("The game you are trying to join is full")]
   GameFull,
+  #[error("The team is full")]
+  GameTeamFull,
+  #[error("Unknown chat command")]
+  ChatCommandInvalid,
+  #[error("Invalid team layout")]
+  GameTeamLayoutInvalid,
   #[error("Create game request already exists")]
   GameCreating,
   #[error("Create game request rejected: {0:?}")]
@@ -54,16 +72,49 @@ pub enum Error {
   GameNodeNotSelected,
   #[error("Slot update denied")]
   GameSlotUpdateDenied,
+  #[error("Slot settings are fixed by the map")]
+  GameSlotFixedByMap,
+  #[error("Slot team is fixed by the map")]
+  GameSlotTeamFixedByMap,
+  #[error("Slot race is fixed by the map")]
+  GameSlotRaceFixedByMap,
+  #[error("Invalid slot index")]
+  GameSlotIndexInvalid,
+  #[error("Player slots can't be opened or closed")]
+  GameSlotStatusInvalid,
+  #[error("Invalid slots: {0}")]
+  GameSlotsInvalid(String),
+  #[error("The game has been updated by another request, please retry")]
+  GameVersionConflict {
+    version: i32,
+    slots: Vec<crate::game::Slot>,
+  },
   #[error("Game already started")]
   GameStarted,
+  #[error("Game not ended")]
+  GameNotEnded,
+  #[error("Chat log is only available to players of the game")]
+  GameChatLogAccessDenied,
+  #[error("Game has no stored replay")]
+  GameReplayNotFound,
   #[error("Game not in starting state")]
   GameNotStarting,
+  #[error("Game not started")]
+  GameNotStarted,
   #[error("This map has no player slot")]
   MapHasNoPlayer,
+  #[error("No map vote in progress")]
+  MapVoteNotFound,
+  #[error("Invalid map vote option")]
+  MapVoteOptionInvalid,
   #[error("Player not in game")]
   PlayerNotInGame,
+  #[error("Player is not an observer")]
+  PlayerNotObserver,
   #[error("Player already in game")]
   PlayerAlreadyInGame,
+  #[error("Player is in an active game")]
+  PlayerInActiveGame,
   #[error("Player slot not found")]
   PlayerSlotNotFound,
   #[error("Send to player channel timeout")]
@@ -72,6 +123,26 @@ pub enum Error {
   PlayerChannelClosed,
   #[error("Player source id is invalid")]
   PlayerSourceIdInvalid,
+  #[error("Player name is invalid")]
+  PlayerNameInvalid,
+  #[error("Too many mute patterns")]
+  PlayerMutePatternLimit,
+  #[error("Player already in a clan")]
+  PlayerAlreadyInClan,
+  #[error("Player not in clan")]
+  PlayerNotInClan,
+  #[error("Clan not found")]
+  ClanNotFound,
+  #[error("Clan tag must be 2 to 4 letters or digits")]
+  ClanTagInvalid,
+  #[error("Clan tag already taken")]
+  ClanTagTaken,
+  #[error("Game is restricted to clan members")]
+  GameClanRestricted,
+  #[error("Game does not allow guest players")]
+  GameGuestsNotAllowed,
+  #[error("Guest players can not host games")]
+  GameGuestHost,
   #[error("Invalid player source state")]
   InvalidPlayerSourceState,
   #[error("Actor not found")]
@@ -84,6 +155,24 @@ pub enum Error {
   PlayerColorConflict,
   #[error("Invalid player team value")]
   PlayerTeamInvalid,
+  #[error("Invalid player color value, must be 0 to 23")]
+  PlayerColorInvalid,
+  #[error("Invalid player handicap value, must be 50 to 100")]
+  PlayerHandicapInvalid,
+  #[error("Auth provider not found")]
+  AuthProviderNotFound,
+  #[error("Authentication failed: {0}")]
+  AuthFailed(String),
+  #[error("Reported player must be another player of the game")]
+  PlayerReportTargetInvalid,
+  #[error("Report message is too long")]
+  PlayerReportMessageTooLong,
+  #[error("Player report not found")]
+  PlayerReportNotFound,
+  #[error("Player report already resolved")]
+  PlayerReportResolved,
+  #[error("Reports can only be resolved or dismissed")]
+  PlayerReportStatusInvalid,
   #[error("Player not belongs to the current API client")]
   PlayerOwnerCheckFailed,
   #[error("Operation timeout: {0}")]
@@ -102,6 +191,14 @@ pub enum Error {
   Proto(#[from] s2_grpc_utils::result::Error),
   #[error("gRPC transport: {0}")]
   GrpcTransport(#[from] tonic::transport::Error),
+  #[error("gRPC reflection: {0}")]
+  GrpcReflection(#[from] tonic_reflection::server::Error),
+  #[error("http: {0}")]
+  Http(#[from] hyper::Error),
+  #[error("observer: {0}")]
+  Observer(#[from] flo_observer::error::Error),
+  #[error("result export: {0}")]
+  ResultExport(String),
 }
 
 pub type Result<T, E = Error> = std::result::Result<T, E>;
@@ -124,10 +221,53 @@ impl From<Error> for Status {
       e @ Error::GameNotFound
       | e @ Error::PlayerNotFound
       | e @ Error::MapHasNoPlayer
+      | e @ Error::MapVoteOptionInvalid
+      | e @ Error::PlayerHandicapInvalid
+      | e @ Error::PlayerTeamInvalid
+      | e @ Error::PlayerColorInvalid
+      | e @ Error::GameSlotIndexInvalid
+      | e @ Error::GameSlotStatusInvalid
+      | e @ Error::GameSlotFixedByMap
+      | e @ Error::GameSlotTeamFixedByMap
+      | e @ Error::GameSlotRaceFixedByMap
+      | e @ Error::GameStarted
       | e @ Error::GameFull
+      | e @ Error::GameTeamFull
+      | e @ Error::GameTeamLayoutInvalid
+      | e @ Error::GameVersionConflict { .. }
+      | e @ Error::ChatCommandInvalid
       | e @ Error::GameNotCancellable
+      | e @ Error::PlayerInActiveGame
+      | e @ Error::PlayerNameInvalid
+      | e @ Error::PlayerMutePatternLimit
+      | e @ Error::PlayerAlreadyInClan
+      | e @ Error::PlayerNotInClan
+      | e @ Error::ClanNotFound
+      | e @ Error::ClanTagInvalid
+      | e @ Error::ClanTagTaken
+      | e @ Error::GameClanRestricted
+      | e @ Error::GameGuestsNotAllowed
+      | e @ Error::GameGuestHost
+      | e @ Error::PlayerNotObserver
+      | e @ Error::GameNotEnded
+      | e @ Error::GameChatLogAccessDenied
+      | e @ Error::GameReplayNotFound
+      | e @ Error::GameNotStarted
+      | e @ Error::PlayerNotHost
+      | e @ Error::GameStepRangeInvalid
+      | e @ Error::PlayerReportTargetInvalid
+      | e @ Error::PlayerReportMessageTooLong
+      | e @ Error::PlayerReportNotFound
+      | e @ Error::PlayerReportResolved
+      | e @ Error::PlayerReportStatusInvalid
+      | e @ Error::AuthProviderNotFound
+      | e @ Error::NodeNotFound
+      | e @ Error::NodeHasActiveGames
+      | e @ Error::InvalidNodeAddress(_)
       | e @ Error::JoinTokenExpired => Status::invalid_argument(e.to_string()),
-      e @ Error::PlayerTokenExpired => Status::unauthenticated(e.to_string()),
+      e @ Error::PlayerTokenExpired | e @ Error::AuthFailed(_) => {
+        Status::unauthenticated(e.to_string())
+      }
       Error::JsonWebToken(e) => Status::unauthenticated(e.to_string()),
       e => Status::internal(e.to_string()),
     }
@@ -0,0 +1,190 @@
+//! Events published to external services through the `SubscribeEvents` RPC
+
+use chrono::{DateTime, Utc};
+use ring::hmac;
+use s2_grpc_utils::{S2ProtoEnum, S2ProtoPack};
+use tokio::sync::broadcast;
+
+/// Events buffered for each subscriber,
+/// subscribers falling further behind have their stream closed
+const EVENT_CHANNEL_SIZE: usize = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, S2ProtoEnum)]
+#[repr(i32)]
+#[s2_grpc(proto_enum_type(flo_grpc::controller::ControllerEventType))]
+pub enum ControllerEventType {
+  GameCreated = 0,
+  GameStarted = 1,
+  GameEnded = 2,
+  PlayerConnected = 3,
+  PlayerDisconnected = 4,
+  /// A player reached the lobby chat offense threshold
+  PlayerChatEscalated = 5,
+}
+
+#[derive(Debug, Clone)]
+pub struct ControllerEvent {
+  pub r#type: ControllerEventType,
+  pub time: DateTime<Utc>,
+  pub game_id: Option<i32>,
+  pub player_id: Option<i32>,
+}
+
+impl ControllerEvent {
+  pub fn game(r#type: ControllerEventType, game_id: i32) -> Self {
+    Self {
+      r#type,
+      time: Utc::now(),
+      game_id: Some(game_id),
+      player_id: None,
+    }
+  }
+
+  pub fn player(r#type: ControllerEventType, player_id: i32, game_id: Option<i32>) -> Self {
+    Self {
+      r#type,
+      time: Utc::now(),
+      game_id,
+      player_id: Some(player_id),
+    }
+  }
+}
+
+impl S2ProtoPack<flo_grpc::controller::ControllerEvent> for ControllerEvent {
+  fn pack(self) -> Result<flo_grpc::controller::ControllerEvent, s2_grpc_utils::result::Error> {
+    let mut pkt = flo_grpc::controller::ControllerEvent {
+      timestamp: self.time.timestamp_millis(),
+      game_id: self.game_id.unwrap_or_default(),
+      player_id: self.player_id.unwrap_or_default(),
+      ..Default::default()
+    };
+    pkt.set_type(self.r#type.into_proto_enum());
+    Ok(pkt)
+  }
+}
+
+/// Signs outbound events with HMAC-SHA256 so receivers can authenticate them.
+///
+/// The signed message is `<timestamp>.<nonce>.<type>.<game_id>.<player_id>`,
+/// with `type` as its protobuf value and missing ids as `0`.
+/// Receivers should reject stale timestamps and nonces they have already seen.
+pub struct EventSigner {
+  key: hmac::Key,
+}
+
+impl EventSigner {
+  pub fn new(secret: &[u8]) -> Self {
+    Self {
+      key: hmac::Key::new(hmac::HMAC_SHA256, secret),
+    }
+  }
+
+  pub fn sign(
+    &self,
+    event: ControllerEvent,
+  ) -> Result<flo_grpc::controller::ControllerEvent, s2_grpc_utils::result::Error> {
+    let mut pkt = event.pack()?;
+    pkt.nonce = hex::encode(rand::random::<[u8; 16]>());
+    let tag = hmac::sign(&self.key, Self::signed_message(&pkt).as_bytes());
+    pkt.signature = format!("sha256={}", hex::encode(tag.as_ref()));
+    Ok(pkt)
+  }
+
+  fn signed_message(pkt: &flo_grpc::controller::ControllerEvent) -> String {
+    format!(
+      "{}.{}.{}.{}.{}",
+      pkt.timestamp, pkt.nonce, pkt.r#type, pkt.game_id, pkt.player_id
+    )
+  }
+}
+
+/// Subscription filter, empty lists match everything
+#[derive(Debug, Default)]
+pub struct EventFilter {
+  pub types: Vec<ControllerEventType>,
+  pub game_ids: Vec<i32>,
+  pub player_ids: Vec<i32>,
+}
+
+impl EventFilter {
+  pub fn matches(&self, event: &ControllerEvent) -> bool {
+    fn contains(ids: &[i32], id: Option<i32>) -> bool {
+      ids.is_empty() || id.map(|id| ids.contains(&id)).unwrap_or(false)
+    }
+
+    (self.types.is_empty() || self.types.contains(&event.r#type))
+      && contains(&self.game_ids, event.game_id)
+      && contains(&self.player_ids, event.player_id)
+  }
+}
+
+#[derive(Debug, Clone)]
+pub struct EventHub {
+  tx: broadcast::Sender<ControllerEvent>,
+}
+
+impl EventHub {
+  pub fn new() -> Self {
+    let (tx, _) = broadcast::channel(EVENT_CHANNEL_SIZE);
+    Self { tx }
+  }
+
+  pub fn publish(&self, event: ControllerEvent) {
+    // fails if there are no subscribers
+    self.tx.send(event).ok();
+  }
+
+  pub fn subscribe(&self) -> broadcast::Receiver<ControllerEvent> {
+    self.tx.subscribe()
+  }
+}
+
+#[test]
+fn test_event_filter() {
+  let created = ControllerEvent::game(ControllerEventType::GameCreated, 1);
+  let connected = ControllerEvent::player(ControllerEventType::PlayerConnected, 2, Some(1));
+
+  let filter = EventFilter::default();
+  assert!(filter.matches(&created));
+  assert!(filter.matches(&connected));
+
+  let filter = EventFilter {
+    types: vec![ControllerEventType::PlayerConnected],
+    ..Default::default()
+  };
+  assert!(!filter.matches(&created));
+  assert!(filter.matches(&connected));
+
+  let filter = EventFilter {
+    player_ids: vec![2],
+    ..Default::default()
+  };
+  assert!(!filter.matches(&created));
+  assert!(filter.matches(&connected));
+
+  let filter = EventFilter {
+    game_ids: vec![2],
+    ..Default::default()
+  };
+  assert!(!filter.matches(&created));
+  assert!(!filter.matches(&connected));
+}
+
+#[test]
+fn test_event_signer() {
+  let signer = EventSigner::new(b"secret");
+  let event = ControllerEvent::player(ControllerEventType::PlayerConnected, 2, Some(1));
+
+  let a = signer.sign(event.clone()).unwrap();
+  let b = signer.sign(event).unwrap();
+  assert_ne!(a.nonce, b.nonce);
+
+  let key = hmac::Key::new(hmac::HMAC_SHA256, b"secret");
+  let tag = hex::decode(a.signature.trim_start_matches("sha256=")).unwrap();
+  let message = EventSigner::signed_message(&a);
+  assert_eq!(message, format!("{}.{}.3.1.2", a.timestamp, a.nonce),);
+  hmac::verify(&key, message.as_bytes(), &tag).unwrap();
+
+  let other = hmac::Key::new(hmac::HMAC_SHA256, b"other");
+  assert!(hmac::verify(&other, message.as_bytes(), &tag).is_err());
+}
@@ -0,0 +1,179 @@
+//! In-game chat recorded by the node, kept to verify harassment reports.
+
+use bs_diesel_utils::BSDieselEnum;
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use flo_net::proto::flo_node::PacketNodeGameChatLog;
+use s2_grpc_utils::{S2ProtoEnum, S2ProtoPack};
+use serde::{Deserialize, Serialize};
+
+use crate::db::DbConn;
+use crate::error::*;
+use crate::game::GameStatus;
+use crate::schema::{game, game_chat_log, game_used_slot, player};
+
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, PartialEq, BSDieselEnum, S2ProtoEnum)]
+#[repr(i32)]
+#[s2_grpc(proto_enum_type(flo_grpc::game::ChatScope, flo_net::proto::flo_node::ChatScope))]
+pub enum ChatScope {
+  All = 0,
+  Allies = 1,
+  Observers = 2,
+  Private = 3,
+}
+
+#[derive(Debug, Queryable, Serialize, S2ProtoPack)]
+#[s2_grpc(message_type = "flo_grpc::game::ChatLogEntry")]
+pub struct ChatLogEntry {
+  pub id: i64,
+  pub game_id: i32,
+  pub player_id: i32,
+  #[s2_grpc(proto_enum)]
+  pub scope: ChatScope,
+  pub to_player_id: Option<i32>,
+  /// Game time
+  pub time_ms: i32,
+  pub message: String,
+  pub created_at: DateTime<Utc>,
+}
+
+pub(crate) type ChatLogEntryColumns = (
+  game_chat_log::id,
+  game_chat_log::game_id,
+  game_chat_log::player_id,
+  game_chat_log::scope,
+  game_chat_log::to_player_id,
+  game_chat_log::time_ms,
+  game_chat_log::message,
+  game_chat_log::created_at,
+);
+
+impl ChatLogEntry {
+  pub(crate) const COLUMNS: ChatLogEntryColumns = (
+    game_chat_log::id,
+    game_chat_log::game_id,
+    game_chat_log::player_id,
+    game_chat_log::scope,
+    game_chat_log::to_player_id,
+    game_chat_log::time_ms,
+    game_chat_log::message,
+    game_chat_log::created_at,
+  );
+}
+
+/// Stores the chat log reported by `node_id`, replacing any previous log of the game.
+/// The game must be hosted by this node
+pub fn save(conn: &DbConn, node_id: i32, log: &PacketNodeGameChatLog) -> Result<()> {
+  use game_chat_log::dsl;
+
+  let game_id = log.game_id;
+  conn.transaction(|| {
+    let hosted = game::table
+      .filter(game::id.eq(game_id).and(game::node_id.eq(node_id)))
+      .select(game::id)
+      .first::<i32>(conn)
+      .optional()?
+      .is_some();
+    if !hosted {
+      return Err(Error::GameNotFound);
+    }
+
+    let inserts: Vec<_> = log
+      .entries
+      .iter()
+      .map(|entry| {
+        (
+          dsl::game_id.eq(game_id),
+          dsl::player_id.eq(entry.player_id),
+          dsl::scope.eq(ChatScope::unpack_enum(entry.scope())),
+          dsl::to_player_id.eq(entry.to_player_id),
+          dsl::time_ms.eq(entry.time_ms as i32),
+          dsl::message.eq(&entry.message),
+        )
+      })
+      .collect();
+
+    diesel::delete(game_chat_log::table.filter(dsl::game_id.eq(game_id))).execute(conn)?;
+    // stay below the bind parameter limit of postgres
+    for chunk in inserts.chunks(1000) {
+      diesel::insert_into(game_chat_log::table)
+        .values(chunk)
+        .execute(conn)?;
+    }
+    Ok(())
+  })
+}
+
+/// Chat log of a finished game, in order
+pub fn get(conn: &DbConn, game_id: i32) -> Result<Vec<ChatLogEntry>> {
+  let status: GameStatus = game::table
+    .find(game_id)
+    .select(game::status)
+    .first(conn)
+    .optional()?
+    .ok_or_else(|| Error::GameNotFound)?;
+  if status.is_active() {
+    return Err(Error::GameNotEnded);
+  }
+
+  game_chat_log::table
+    .select(ChatLogEntry::COLUMNS)
+    .filter(game_chat_log::game_id.eq(game_id))
+    .order(game_chat_log::id)
+    .load(conn)
+    .map_err(Into::into)
+}
+
+/// Chat log of a finished game as seen by one of its players:
+/// public messages, messages of the player's team and messages sent to or by the player
+pub fn get_for_player(conn: &DbConn, game_id: i32, player_id: i32) -> Result<Vec<ChatLogEntry>> {
+  let teams: Vec<(Option<i32>, i32)> = game_used_slot::table
+    .filter(game_used_slot::game_id.eq(game_id))
+    .select((game_used_slot::player_id, game_used_slot::team))
+    .load(conn)?;
+  let team = teams
+    .iter()
+    .find(|(id, _)| *id == Some(player_id))
+    .map(|(_, team)| *team)
+    .ok_or_else(|| Error::GameChatLogAccessDenied)?;
+  let team_player_ids: Vec<i32> = teams
+    .iter()
+    .filter(|(_, t)| *t == team)
+    .filter_map(|(id, _)| *id)
+    .collect();
+
+  let entries = get(conn, game_id)?;
+  Ok(
+    entries
+      .into_iter()
+      .filter(|entry| {
+        if entry.player_id == player_id || entry.to_player_id == Some(player_id) {
+          return true;
+        }
+        match entry.scope {
+          ChatScope::All => true,
+          ChatScope::Allies => team_player_ids.contains(&entry.player_id),
+          ChatScope::Observers => team == 24,
+          ChatScope::Private => false,
+        }
+      })
+      .collect(),
+  )
+}
+
+/// Checks that the game was created by a player of the API client
+pub fn check_game_api_client_id(conn: &DbConn, api_client_id: i32, game_id: i32) -> Result<()> {
+  let n = game::table
+    .inner_join(player::table)
+    .filter(
+      game::id
+        .eq(game_id)
+        .and(player::api_client_id.eq(api_client_id)),
+    )
+    .count()
+    .get_result::<i64>(conn)?;
+  if n == 0 {
+    return Err(Error::GameChatLogAccessDenied);
+  }
+  Ok(())
+}
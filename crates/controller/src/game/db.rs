@@ -8,16 +8,18 @@ use std::collections::HashMap;
 
 use crate::db::DbConn;
 use crate::error::*;
+use crate::game::history::{self, GameEvent, HistorySlot};
 use crate::game::slots::{UsedSlot, UsedSlotInfo};
 use crate::game::state::GameStatusUpdate;
+use crate::game::stats::PlayerGameStats;
 use crate::game::{
-  Computer, CreateGameSlot, Game, GameEntry, GameStatus, Race, Slot, SlotClientStatus,
-  SlotSettings, SlotStatus, Slots,
+  Computer, CreateGameSlot, Game, GameEntry, GameMode, GameStatus, Race, Slot, SlotClientStatus,
+  SlotSettings, SlotStatus, Slots, TeamLayout,
 };
 use crate::map::Map;
 use crate::node::{NodeRef, NodeRefColumns, PlayerToken};
 use crate::player::{PlayerRef, PlayerRefColumns};
-use crate::schema::{game, game_used_slot, node, player};
+use crate::schema::{game, game_slot_reservation, game_used_slot, node, player};
 use diesel::pg::expression::dsl::{all, any};
 
 pub fn get(conn: &DbConn, id: i32) -> Result<GameRowWithRelated> {
@@ -58,6 +60,8 @@ pub enum GameStatusFilter {
   Open = 1,
   Live = 2,
   Ended = 3,
+  /// Games neither ended nor terminated, the games of `GameListSnapshot`
+  Active = 4,
 }
 
 impl Default for GameStatusFilter {
@@ -81,6 +85,19 @@ pub fn get_entry(conn: &DbConn, id: i32) -> Result<GameEntry> {
   Ok(entry)
 }
 
+pub fn get_entries(conn: &DbConn, ids: &[i32]) -> Result<Vec<GameEntry>> {
+  use game::dsl;
+
+  let entries = game::table
+    .filter(dsl::id.eq(any(ids)))
+    .left_outer_join(node::table)
+    .left_outer_join(player::table)
+    .select(GameEntry::columns())
+    .load(conn)?;
+
+  Ok(entries)
+}
+
 pub fn query(conn: &DbConn, params: &QueryGameParams) -> Result<QueryGame> {
   use game::dsl;
 
@@ -110,6 +127,9 @@ pub fn query(conn: &DbConn, params: &QueryGameParams) -> Result<QueryGame> {
       )
     }
     GameStatusFilter::Ended => q = q.filter(dsl::status.eq(GameStatus::Ended)),
+    GameStatusFilter::Active => {
+      q = q.filter(dsl::status.ne_all(&[GameStatus::Ended, GameStatus::Terminated]))
+    }
   }
 
   if let Some(is_private) = params.is_private.clone() {
@@ -151,10 +171,18 @@ pub fn cancel(conn: &DbConn, game_id: i32, created_by: Option<i32>) -> Result<()
   if status != GameStatus::Preparing && status != GameStatus::Created {
     return Err(Error::GameNotCancellable);
   }
-  diesel::update(game::table.find(game_id))
-    .set(game::status.eq(GameStatus::Ended))
-    .execute(conn)?;
-  Ok(())
+  conn.transaction(|| {
+    diesel::update(game::table.find(game_id))
+      .set(game::status.eq(GameStatus::Ended))
+      .execute(conn)?;
+    history::append(
+      conn,
+      game_id,
+      &GameEvent::Ended {
+        status: GameStatus::Ended,
+      },
+    )
+  })
 }
 
 #[derive(Debug, Deserialize, S2ProtoUnpack)]
@@ -165,6 +193,19 @@ pub struct CreateGameParams {
   pub map: Map,
   pub is_private: bool,
   pub is_live: bool,
+  #[s2_grpc(proto_enum)]
+  pub game_mode: GameMode,
+  pub random_races: bool,
+  pub random_teams: bool,
+  /// Restricts the game to members of the clan
+  pub clan_id: Option<i32>,
+  /// Overrides the game step limits of the node
+  pub step_min: Option<i32>,
+  pub step_max: Option<i32>,
+  /// Allows guest players to join
+  pub allow_guests: bool,
+  /// Limits the number of players per team, e.g. `2v2`
+  pub team_layout: Option<String>,
 }
 
 /// Creates a game, make the creator as the first player
@@ -175,8 +216,26 @@ pub fn create(conn: &DbConn, params: CreateGameParams) -> Result<Game> {
     return Err(Error::MapHasNoPlayer);
   }
 
+  validate_step_range(params.step_min, params.step_max)?;
+
+  if let Some(clan_id) = params.clan_id {
+    if !crate::clan::db::is_member(conn, clan_id, params.player_id)? {
+      return Err(Error::GameClanRestricted);
+    }
+  }
+
   let player = crate::player::db::get_ref(conn, params.player_id)?;
-  let mut slots = Slots::new(max_players);
+  check_guest(&player, true, params.allow_guests)?;
+  let team_layout = params
+    .team_layout
+    .as_deref()
+    .map(str::parse::<TeamLayout>)
+    .transpose()?;
+  if let Some(team_layout) = team_layout.as_ref() {
+    team_layout.validate(max_players)?;
+  }
+  let mut slots = Slots::from_map(&params.map);
+  slots.set_team_layout(team_layout.clone());
   slots.join(&player);
 
   let meta = Meta {
@@ -198,6 +257,15 @@ pub fn create(conn: &DbConn, params: CreateGameParams) -> Result<Game> {
     locked: false,
     node_id: None,
     mask_player_names: false,
+    game_mode: params.game_mode,
+    random_races: params.random_races,
+    random_teams: params.random_teams,
+    clan_id: params.clan_id,
+    equalize_ping: false,
+    step_min: params.step_min,
+    step_max: params.step_max,
+    allow_guests: params.allow_guests,
+    team_layout: team_layout.map(|v| v.to_string()),
   };
 
   let row = conn.transaction(|| -> Result<_> {
@@ -207,11 +275,120 @@ pub fn create(conn: &DbConn, params: CreateGameParams) -> Result<Game> {
       .get_result(conn)?;
     let row = get(conn, id)?;
     upsert_used_slots(conn, row.id, slots.as_used())?;
+    history::append(
+      conn,
+      row.id,
+      &GameEvent::Created {
+        name: row.name.clone(),
+        map_name: row.map_name.clone(),
+        created_by: insert.created_by,
+        node_id: insert.node_id,
+        slots: HistorySlot::from_used(slots.as_used()),
+      },
+    )?;
     Ok(row)
   })?;
   Ok(row.into_game(meta, slots.into_inner())?)
 }
 
+/// Creates a new lobby from a started game of the host, with the same map, settings and slot layout.
+/// The slots of the other players are reserved for them.
+/// Returns the new game and the ids of the players a slot was reserved for.
+pub fn rehost(conn: &DbConn, game_id: i32, player_id: i32) -> Result<(Game, Vec<i32>)> {
+  let prev = get_full(conn, game_id)?;
+  if prev.created_by.id != player_id {
+    return Err(Error::PlayerNotHost);
+  }
+  if prev.status == GameStatus::Preparing || prev.status == GameStatus::Created {
+    return Err(Error::GameNotStarted);
+  }
+  let (clan_id, allow_guests, team_layout): (Option<i32>, bool, Option<String>) = game::table
+    .find(game_id)
+    .select((game::clan_id, game::allow_guests, game::team_layout))
+    .first(conn)?;
+
+  conn.transaction(|| {
+    let game = create(
+      conn,
+      CreateGameParams {
+        player_id,
+        name: prev.name.clone(),
+        map: prev.map.clone(),
+        is_private: prev.is_private,
+        is_live: prev.is_live,
+        game_mode: prev.game_mode,
+        random_races: prev.random_races,
+        random_teams: prev.random_teams,
+        clan_id,
+        step_min: prev.step_min,
+        step_max: prev.step_max,
+        allow_guests,
+        team_layout,
+      },
+    )?;
+
+    let mut used = vec![];
+    let mut reservations = vec![];
+    for (slot_index, slot) in prev.slots.iter().enumerate() {
+      if !slot.is_used() {
+        continue;
+      }
+      match slot.player.as_ref() {
+        Some(player) if player.id != player_id => {
+          reservations.push((slot_index as i32, player.id));
+        }
+        player => used.push(UsedSlot {
+          slot_index: slot_index as i32,
+          settings: slot.settings.clone(),
+          client_status: SlotClientStatus::Pending,
+          player: player.cloned(),
+        }),
+      }
+    }
+
+    if prev.equalize_ping {
+      diesel::update(game::table.find(game.id))
+        .set(game::equalize_ping.eq(true))
+        .execute(conn)?;
+    }
+
+    upsert_used_slots(conn, game.id, used)?;
+    let slots = get_slots(conn, game.id)?.slots;
+    history::append(
+      conn,
+      game.id,
+      &GameEvent::SlotsUpdated {
+        slots: HistorySlot::from_used(slots.as_used()),
+      },
+    )?;
+
+    let mut player_ids = vec![];
+    for (slot_index, reserved_player_id) in reservations {
+      reserve_slot(conn, game.id, slot_index, Some(reserved_player_id))?;
+      player_ids.push(reserved_player_id);
+    }
+
+    Ok((get_full(conn, game.id)?, player_ids))
+  })
+}
+
+/// Step overrides must be within the global limits of the node
+fn validate_step_range(min: Option<i32>, max: Option<i32>) -> Result<()> {
+  let limits =
+    (flo_constants::GAME_STEP_MIN_MS as i32)..=(flo_constants::GAME_STEP_MAX_MS as i32);
+  for value in min.iter().chain(max.iter()) {
+    if !limits.contains(value) {
+      return Err(Error::GameStepRangeInvalid);
+    }
+  }
+  if let (Some(min), Some(max)) = (min, max) {
+    if min > max {
+      return Err(Error::GameStepRangeInvalid);
+    }
+  }
+  Ok(())
+}
+
 #[derive(Debug, Deserialize, S2ProtoUnpack)]
 #[s2_grpc(message_type = "flo_grpc::controller::CreateGameAsBotRequest")]
 pub struct CreateGameAsBotParams {
@@ -222,6 +399,15 @@ pub struct CreateGameAsBotParams {
   pub node_id: i32,
   pub slots: Vec<CreateGameSlot>,
   pub mask_player_names: Option<bool>,
+  #[s2_grpc(proto_enum)]
+  pub game_mode: GameMode,
+  pub random_races: bool,
+  pub random_teams: bool,
+  /// Delays low latency players to match the highest latency of the game
+  pub equalize_ping: Option<bool>,
+  /// Overrides the game step limits of the node, set both to the same value to lock the step
+  pub step_min: Option<i32>,
+  pub step_max: Option<i32>,
 }
 
 /// Creates a full game and lock it
@@ -242,6 +428,8 @@ pub fn create_as_bot(
     return Err(Error::TooManyPlayers);
   }
 
+  validate_step_range(params.step_min, params.step_max)?;
+
   let (player_slots, referee_slots): (Vec<_>, Vec<_>) = params
     .slots
     .iter()
@@ -286,13 +474,19 @@ pub fn create_as_bot(
       return Err(Error::PlayerTeamInvalid);
     }
 
+    let handicap = SlotSettings::normalize_handicap(slot.settings.handicap)
+      .ok_or_else(|| Error::PlayerHandicapInvalid)?;
+
     let player = slot.player_id.clone().and_then(|id| players.remove(&id));
     if slot.player_id.is_some() && player.is_none() {
       return Err(Error::PlayerNotFound);
     }
     slots.push(UsedSlot {
       slot_index: *i as i32,
-      settings: slot.settings.clone(),
+      settings: SlotSettings {
+        handicap,
+        ..slot.settings.clone()
+      },
       client_status: SlotClientStatus::Pending,
       player,
     });
@@ -338,6 +532,15 @@ pub fn create_as_bot(
     locked: true,
     node_id: Some(params.node_id),
     mask_player_names: params.mask_player_names.unwrap_or_default(),
+    game_mode: params.game_mode,
+    random_races: params.random_races,
+    random_teams: params.random_teams,
+    clan_id: None,
+    equalize_ping: params.equalize_ping.unwrap_or_default(),
+    step_min: params.step_min,
+    step_max: params.step_max,
+    allow_guests: false,
+    team_layout: None,
   };
 
   let row = conn.transaction(|| -> Result<_> {
@@ -347,6 +550,17 @@ pub fn create_as_bot(
       .get_result(conn)?;
     let row = get(conn, id)?;
     upsert_used_slots(conn, row.id, slots.as_used())?;
+    history::append(
+      conn,
+      row.id,
+      &GameEvent::Created {
+        name: row.name.clone(),
+        map_name: row.map_name.clone(),
+        created_by: insert.created_by,
+        node_id: insert.node_id,
+        slots: HistorySlot::from_used(slots.as_used()),
+      },
+    )?;
     Ok(row)
   })?;
 
@@ -356,34 +570,59 @@ pub fn create_as_bot(
 /// Adds a player into a game
 pub fn add_player(conn: &DbConn, game_id: i32, player_id: i32) -> Result<Vec<Slot>> {
   let InspectId { status, locked } = inspect_id(conn, game_id)?;
+  check_lobby_status(status, locked)?;
 
-  if locked {
-    return Err(Error::GameSlotUpdateDenied);
-  }
-
-  if status != GameStatus::Preparing {
-    return Err(Error::GameStarted);
-  }
+  let GetSlots {
+    mut slots, version, ..
+  } = get_slots(conn, game_id)?;
 
-  let mut slots = get_slots(conn, game_id)?.slots;
+  slots.check_join(player_id)?;
 
-  if slots.find_player_slot(player_id).is_some() {
-    return Err(Error::PlayerAlreadyInGame);
-  }
-
-  if slots.is_full() {
-    return Err(Error::GameFull);
+  let (clan_id, allow_guests): (Option<i32>, bool) = game::table
+    .find(game_id)
+    .select((game::clan_id, game::allow_guests))
+    .first(conn)?;
+  if let Some(clan_id) = clan_id {
+    if !crate::clan::db::is_member(conn, clan_id, player_id)? {
+      return Err(Error::GameClanRestricted);
+    }
   }
 
   let player = crate::player::db::get_ref(conn, player_id)?;
+  check_guest(&player, false, allow_guests)?;
 
-  slots.join(&player);
+  slots.join(&player).ok_or_else(|| Error::GameFull)?;
 
-  upsert_used_slots(conn, game_id, slots.as_used())?;
+  conn.transaction(|| {
+    swap_version(conn, game_id, version)?;
+    upsert_used_slots(conn, game_id, slots.as_used())?;
+    history::append(
+      conn,
+      game_id,
+      &GameEvent::PlayerJoined {
+        player_id,
+        slots: HistorySlot::from_used(slots.as_used()),
+      },
+    )
+  })?;
 
   Ok(slots.into_inner())
 }
 
+/// Guests can only join games that allow them, they never host
+fn check_guest(player: &PlayerRef, host: bool, allow_guests: bool) -> Result<()> {
+  if !player.source.is_guest() {
+    return Ok(());
+  }
+  if host {
+    return Err(Error::GameGuestHost);
+  }
+  if !allow_guests {
+    return Err(Error::GameGuestsNotAllowed);
+  }
+  Ok(())
+}
+
 #[derive(Debug)]
 pub struct LeaveGame {
   pub game_ended: bool,
@@ -393,47 +632,38 @@ pub struct LeaveGame {
 
 pub fn remove_player(conn: &DbConn, game_id: i32, player_id: i32) -> Result<LeaveGame> {
   let InspectId { status, locked } = inspect_id(conn, game_id)?;
-
-  if locked {
-    return Err(Error::GameSlotUpdateDenied);
-  }
-
-  if status != GameStatus::Preparing {
-    return Err(Error::GameStarted);
-  }
+  check_lobby_status(status, locked)?;
 
   let GetSlots {
     mut slots,
     host_player_id,
+    version,
   } = get_slots(conn, game_id)?;
 
-  // host left, kick all players
-  if player_id == host_player_id {
-    let removed = slots.release_all_player_slots();
-    upsert_used_slots(conn, game_id, slots.as_used())?;
-    end_game(conn, game_id, GameStatus::Ended)?;
-    Ok(LeaveGame {
-      game_ended: true,
-      removed_players: removed,
-      slots: slots.into_inner(),
-    })
-  } else {
-    let mut ended = false;
-    let mut removed_players = Vec::with_capacity(1);
-    if slots.release_player_slot(player_id) {
-      removed_players.push(player_id);
+  let (removed_players, game_ended) = slots.leave(player_id, host_player_id);
+  if !removed_players.is_empty() || game_ended {
+    conn.transaction(|| {
+      swap_version(conn, game_id, version)?;
       upsert_used_slots(conn, game_id, slots.as_used())?;
-      if slots.is_empty() {
-        ended = true;
+      history::append(
+        conn,
+        game_id,
+        &GameEvent::PlayerLeft {
+          player_ids: removed_players.clone(),
+          slots: HistorySlot::from_used(slots.as_used()),
+        },
+      )?;
+      if game_ended {
         end_game(conn, game_id, GameStatus::Ended)?;
       }
-    }
-    Ok(LeaveGame {
-      game_ended: ended,
-      removed_players,
-      slots: slots.into_inner(),
-    })
+      Ok::<_, Error>(())
+    })?;
   }
+  Ok(LeaveGame {
+    game_ended,
+    removed_players,
+    slots: slots.into_inner(),
+  })
 }
 
 #[derive(Queryable)]
@@ -442,6 +672,19 @@ struct InspectId {
   locked: bool,
 }
 
+/// Slots can only be changed in lobbies that are not locked
+pub fn check_lobby_status(status: GameStatus, locked: bool) -> Result<()> {
+  if locked {
+    return Err(Error::GameSlotUpdateDenied);
+  }
+
+  if status != GameStatus::Preparing {
+    return Err(Error::GameStarted);
+  }
+
+  Ok(())
+}
+
 fn inspect_id(conn: &DbConn, game_id: i32) -> Result<InspectId> {
   Ok(
     game::table
@@ -511,6 +754,20 @@ pub fn get_slot_owner_info(conn: &DbConn, game_id: i32, slot_index: i32) -> Resu
   })
 }
 
+/// `(player_id, slot_index)` of the players occupying a slot
+pub fn get_player_slot_indexes(conn: &DbConn, game_id: i32) -> Result<Vec<(i32, i32)>> {
+  let rows: Vec<(Option<i32>, i32)> = game_used_slot::table
+    .filter(game_used_slot::game_id.eq(game_id))
+    .select((game_used_slot::player_id, game_used_slot::slot_index))
+    .load(conn)?;
+  Ok(
+    rows
+      .into_iter()
+      .filter_map(|(player_id, slot_index)| Some((player_id?, slot_index)))
+      .collect(),
+  )
+}
+
 #[derive(Debug)]
 pub struct UpdateSlotSettings {
   pub slots: Vec<Slot>,
@@ -524,29 +781,60 @@ pub fn update_slot_settings(
   settings: SlotSettings,
 ) -> Result<UpdateSlotSettings> {
   let InspectId { status, locked } = inspect_id(conn, game_id)?;
+  check_lobby_status(status, locked)?;
 
-  if locked {
-    return Err(Error::GameSlotUpdateDenied);
-  }
-
-  if status != GameStatus::Preparing {
-    return Err(Error::GameStarted);
-  }
+  let GetSlots {
+    mut slots, version, ..
+  } = get_slots(conn, game_id)?;
 
-  let mut slots = get_slots(conn, game_id)?.slots;
-  let mut updated_indexes = vec![];
-  if let Some(slots) = slots.update_slot_at(slot_index, &settings) {
-    for (index, slot) in slots {
-      sync_slot_at(conn, game_id, index as i32, &slot)?;
-      updated_indexes.push(index);
-    }
-  }
+  let updated_indexes = slots.apply_update(slot_index, &settings)?;
+  let updated: Vec<(i32, Slot)> = updated_indexes
+    .iter()
+    .map(|index| (*index, slots[*index as usize].clone()))
+    .collect();
+  save_slots(conn, game_id, version, &updated, slots.as_used())?;
   Ok(UpdateSlotSettings {
     slots: slots.into_inner(),
     updated_indexes,
   })
 }
 
+/// Slots of a game that accepts slot changes
+pub fn get_lobby_slots(conn: &DbConn, game_id: i32) -> Result<GetSlots> {
+  let InspectId { status, locked } = inspect_id(conn, game_id)?;
+  check_lobby_status(status, locked)?;
+
+  get_slots(conn, game_id)
+}
+
+/// Writes the updated slots if the game is still at `version`, returns the new version.
+/// `used` is recorded in the game history
+pub fn save_slots(
+  conn: &DbConn,
+  game_id: i32,
+  version: i32,
+  updated: &[(i32, Slot)],
+  used: Vec<UsedSlot>,
+) -> Result<i32> {
+  if updated.is_empty() {
+    return Ok(version);
+  }
+  conn.transaction(|| {
+    let version = swap_version(conn, game_id, version)?;
+    for (index, slot) in updated {
+      sync_slot_at(conn, game_id, *index, slot)?;
+    }
+    history::append(
+      conn,
+      game_id,
+      &GameEvent::SlotsUpdated {
+        slots: HistorySlot::from_used(used),
+      },
+    )?;
+    Ok(version)
+  })
+}
+
 fn sync_slot_at(conn: &DbConn, game_id: i32, slot_index: i32, slot: &Slot) -> Result<()> {
   use game_used_slot::dsl;
 
@@ -595,10 +883,25 @@ pub fn update_status(conn: &DbConn, update: &GameStatusUpdate) -> Result<()> {
   let game_id = update.game_id;
   let game_status = GameStatus::from(update.status);
   conn.transaction(|| {
+    let prev_status: GameStatus = game::table
+      .find(game_id)
+      .select(game::dsl::status)
+      .first(conn)?;
+
     diesel::update(game::table.find(update.game_id))
       .set(game::dsl::status.eq(game_status))
       .execute(conn)?;
 
+    if prev_status != game_status {
+      let event = match game_status {
+        GameStatus::Ended | GameStatus::Terminated => GameEvent::Ended {
+          status: game_status,
+        },
+        status => GameEvent::StatusChanged { status },
+      };
+      history::append(conn, game_id, &event)?;
+    }
+
     match game_status {
       GameStatus::Running => {
         diesel::update(
@@ -659,6 +962,7 @@ fn upsert_used_slots(conn: &DbConn, game_id: i32, used_slots: Vec<UsedSlot>) ->
         dsl::color.eq(excluded(dsl::color)),
         dsl::computer.eq(excluded(dsl::computer)),
         dsl::handicap.eq(excluded(dsl::handicap)),
+        dsl::status.eq(excluded(dsl::status)),
         dsl::race.eq(excluded(dsl::race)),
         dsl::client_status.eq(excluded(dsl::client_status)),
       ))
@@ -667,24 +971,296 @@ fn upsert_used_slots(conn: &DbConn, game_id: i32, used_slots: Vec<UsedSlot>) ->
   })
 }
 
+fn get_slot_reservations(
+  conn: &DbConn,
+  game_id: i32,
+) -> Result<std::collections::BTreeMap<usize, i32>> {
+  use game_slot_reservation::dsl;
+  let rows: Vec<(i32, i32)> = game_slot_reservation::table
+    .filter(dsl::game_id.eq(game_id))
+    .select((dsl::slot_index, dsl::player_id))
+    .load(conn)?;
+  Ok(
+    rows
+      .into_iter()
+      .map(|(slot_index, player_id)| (slot_index as usize, player_id))
+      .collect(),
+  )
+}
+
+#[derive(Insertable)]
+#[table_name = "game_slot_reservation"]
+struct SlotReservationInsert {
+  game_id: i32,
+  player_id: i32,
+  slot_index: i32,
+}
+
+/// Reserves a player slot for a player, or removes the reservation if `player_id` is `None`
+pub fn reserve_slot(
+  conn: &DbConn,
+  game_id: i32,
+  slot_index: i32,
+  player_id: Option<i32>,
+) -> Result<()> {
+  use game_slot_reservation::dsl;
+
+  let InspectId { status, locked } = inspect_id(conn, game_id)?;
+  check_lobby_status(status, locked)?;
+
+  let GetSlots { mut slots, .. } = get_slots(conn, game_id)?;
+  slots.reserve(slot_index, player_id)?;
+
+  if let Some(player_id) = player_id {
+    crate::player::db::get_ref(conn, player_id)?;
+  }
+
+  diesel::delete(game_slot_reservation::table.filter(dsl::game_id.eq(game_id))).execute(conn)?;
+  let rows: Vec<_> = slots
+    .reservations()
+    .iter()
+    .map(|(index, player_id)| SlotReservationInsert {
+      game_id,
+      player_id: *player_id,
+      slot_index: *index as i32,
+    })
+    .collect();
+  diesel::insert_into(game_slot_reservation::table)
+    .values(&rows)
+    .execute(conn)?;
+
+  Ok(())
+}
+
+/// Closes or reopens an empty slot
+pub fn set_slot_closed(
+  conn: &DbConn,
+  game_id: i32,
+  slot_index: i32,
+  closed: bool,
+) -> Result<UpdateSlotSettings> {
+  let InspectId { status, locked } = inspect_id(conn, game_id)?;
+  check_lobby_status(status, locked)?;
+
+  let GetSlots {
+    mut slots, version, ..
+  } = get_slots(conn, game_id)?;
+  let slot = slots
+    .set_slot_closed(slot_index, closed)
+    .ok_or_else(|| Error::GameSlotUpdateDenied)?
+    .clone();
+  save_slots(
+    conn,
+    game_id,
+    version,
+    &[(slot_index, slot)],
+    slots.as_used(),
+  )?;
+
+  Ok(UpdateSlotSettings {
+    slots: slots.into_inner(),
+    updated_indexes: vec![slot_index],
+  })
+}
+
+/// Randomizes races and/or teams of the player slots
+pub fn shuffle_slots(
+  conn: &DbConn,
+  game_id: i32,
+  races: bool,
+  teams: bool,
+) -> Result<UpdateSlotSettings> {
+  let InspectId { status, .. } = inspect_id(conn, game_id)?;
+
+  if status != GameStatus::Preparing {
+    return Err(Error::GameStarted);
+  }
+
+  let GetSlots {
+    mut slots, version, ..
+  } = get_slots(conn, game_id)?;
+  let updated_indexes = slots.shuffle(races, teams);
+  let updated: Vec<(i32, Slot)> = updated_indexes
+    .iter()
+    .map(|index| (*index, slots[*index as usize].clone()))
+    .collect();
+  save_slots(conn, game_id, version, &updated, slots.as_used())?;
+  Ok(UpdateSlotSettings {
+    slots: slots.into_inner(),
+    updated_indexes,
+  })
+}
+
+/// Applies the random race / team options of a game,
+/// returns `None` if no option is enabled
+pub fn shuffle_slots_on_start(conn: &DbConn, game_id: i32) -> Result<Option<Game>> {
+  let (races, teams): (bool, bool) = game::table
+    .find(game_id)
+    .select((game::random_races, game::random_teams))
+    .first(conn)
+    .optional()?
+    .ok_or_else(|| Error::GameNotFound)?;
+
+  if !races && !teams {
+    return Ok(None);
+  }
+
+  conn.transaction(|| {
+    shuffle_slots(conn, game_id, races, teams)?;
+    get_full(conn, game_id).map(Some)
+  })
+}
+
+/// Replaces the game map, re-layout slots for the new map
+pub fn update_map(conn: &DbConn, game_id: i32, map: Map) -> Result<Game> {
+  let max_players = map.players.len();
+
+  if max_players == 0 {
+    return Err(Error::MapHasNoPlayer);
+  }
+
+  conn.transaction(|| {
+    let InspectId { status, locked } = inspect_id(conn, game_id)?;
+
+    if locked {
+      return Err(Error::GameSlotUpdateDenied);
+    }
+
+    if status != GameStatus::Preparing {
+      return Err(Error::GameStarted);
+    }
+
+    let GetSlots { slots, version, .. } = get_slots(conn, game_id)?;
+    if let Some(team_layout) = slots.team_layout() {
+      team_layout.validate(max_players)?;
+    }
+    let slots = slots.relayout(&map).ok_or_else(|| Error::TooManyPlayers)?;
+    swap_version(conn, game_id, version)?;
+
+    let row = get(conn, game_id)?;
+    let mut meta: Meta = serde_json::from_value(row.meta)?;
+    meta.map = map;
+
+    diesel::update(game::table.find(game_id))
+      .set((
+        game::map_name.eq(&meta.map.name),
+        game::max_players.eq(max_players as i32),
+        game::meta.eq(serde_json::to_value(&meta)?),
+      ))
+      .execute(conn)?;
+    upsert_used_slots(conn, game_id, slots.as_used())?;
+    history::append(
+      conn,
+      game_id,
+      &GameEvent::MapChanged {
+        map_name: meta.map.name.clone(),
+        slots: HistorySlot::from_used(slots.as_used()),
+      },
+    )?;
+
+    get_full(conn, game_id)
+  })
+}
+
+/// Map vote options must be in the map pool and fit the team layout of the game
+pub fn validate_map_vote_options(conn: &DbConn, game_id: i32, maps: &[Map]) -> Result<()> {
+  let team_layout: Option<String> = game::table
+    .find(game_id)
+    .select(game::team_layout)
+    .first(conn)
+    .optional()?
+    .ok_or_else(|| Error::GameNotFound)?;
+  let team_layout = team_layout
+    .as_deref()
+    .map(str::parse::<TeamLayout>)
+    .transpose()?;
+
+  for map in maps {
+    if !crate::map::db::is_in_pool(conn, map)? {
+      return Err(Error::MapVoteOptionInvalid);
+    }
+    if let Some(team_layout) = team_layout.as_ref() {
+      team_layout.validate(map.players.len())?;
+    }
+  }
+  Ok(())
+}
+
+/// Grants or revokes the referee role of an observer,
+/// returns the referee player ids of the game
+pub fn set_referee(conn: &DbConn, game_id: i32, player_id: i32, referee: bool) -> Result<Vec<i32>> {
+  use game_used_slot::dsl as gus;
+  let InspectId { status, .. } = inspect_id(conn, game_id)?;
+
+  if status != GameStatus::Preparing {
+    return Err(Error::GameStarted);
+  }
+
+  let team: i32 = game_used_slot::table
+    .filter(gus::game_id.eq(game_id).and(gus::player_id.eq(player_id)))
+    .select(gus::team)
+    .first(conn)
+    .optional()?
+    .ok_or_else(|| Error::PlayerNotInGame)?;
+  if referee && team != 24 {
+    return Err(Error::PlayerNotObserver);
+  }
+
+  conn.transaction(|| {
+    let mut ids = get_referee_player_ids(conn, game_id)?;
+    ids.retain(|id| *id != player_id);
+    if referee {
+      ids.push(player_id);
+    }
+    diesel::update(game::table.find(game_id))
+      .set(game::referee_player_ids.eq(&ids))
+      .execute(conn)?;
+    Ok(ids)
+  })
+}
+
+pub fn get_referee_player_ids(conn: &DbConn, game_id: i32) -> Result<Vec<i32>> {
+  game::table
+    .find(game_id)
+    .select(game::referee_player_ids)
+    .first(conn)
+    .optional()?
+    .ok_or_else(|| Error::GameNotFound)
+}
+
 #[derive(Debug)]
-struct GetSlots {
-  host_player_id: i32,
-  slots: Slots,
+pub struct GetSlots {
+  pub host_player_id: i32,
+  /// Compared and incremented by each slot write
+  pub version: i32,
+  pub slots: Slots,
 }
 
 fn get_slots(conn: &DbConn, game_id: i32) -> Result<GetSlots> {
   use game_used_slot::dsl;
 
-  let (host_player_id, max_players): (i32, i32) = {
+  let (host_player_id, max_players, meta, team_layout, version): (
+    i32,
+    i32,
+    Value,
+    Option<String>,
+    i32,
+  ) = {
     use game::dsl;
     game::table
       .find(game_id)
-      .select((dsl::created_by, dsl::max_players))
+      .select((
+        dsl::created_by,
+        dsl::max_players,
+        dsl::meta,
+        dsl::team_layout,
+        dsl::version,
+      ))
       .first(conn)
       .optional()?
       .ok_or_else(|| Error::GameNotFound)?
   };
+  let meta: Meta = serde_json::from_value(meta)?;
 
   let used_slots: Vec<UsedSlot> = game_used_slot::table
     .left_outer_join(player::table)
@@ -692,13 +1268,36 @@ fn get_slots(conn: &DbConn, game_id: i32) -> Result<GetSlots> {
     .filter(dsl::game_id.eq(game_id))
     .load(conn)?;
 
-  let slots = Slots::from_used(max_players as usize, used_slots);
+  let mut slots = Slots::from_used(max_players as usize, used_slots);
+  slots.set_map(&meta.map);
+  slots.set_reservations(get_slot_reservations(conn, game_id)?);
+  slots.set_team_layout(team_layout.as_deref().map(str::parse).transpose()?);
   Ok(GetSlots {
     host_player_id,
+    version,
     slots,
   })
 }
 
+/// Increments the version of the game if it is still `version`,
+/// returns the current slots in a conflict error otherwise.
+/// Called in the transaction writing slots read with [`get_slots`]
+fn swap_version(conn: &DbConn, game_id: i32, version: i32) -> Result<i32> {
+  use game::dsl;
+  let updated =
+    diesel::update(game::table.filter(dsl::id.eq(game_id).and(dsl::version.eq(version))))
+      .set(dsl::version.eq(dsl::version + 1))
+      .execute(conn)?;
+  if updated == 0 {
+    let current = get_slots(conn, game_id)?;
+    return Err(Error::GameVersionConflict {
+      version: current.version,
+      slots: current.slots.into_inner(),
+    });
+  }
+  Ok(version + 1)
+}
+
 fn get_used_slots(conn: &DbConn, game_id: i32) -> Result<Vec<UsedSlot>> {
   use game_used_slot::dsl;
   game_used_slot::table
@@ -732,6 +1331,124 @@ pub fn get_player_active_slots(conn: &DbConn, player_id: i32) -> Result<Vec<Play
   Ok(rows)
 }
 
+/// Ids of the active games the player has a slot in or created
+pub fn get_player_active_game_ids(conn: &DbConn, player_id: i32) -> Result<Vec<i32>> {
+  let mut ids: Vec<i32> = game_used_slot::table
+    .inner_join(game::table)
+    .filter(game::status.eq(any(GameStatus::active_variants())))
+    .filter(game_used_slot::player_id.eq(player_id))
+    .select(game::id)
+    .load(conn)?;
+  ids.extend(
+    game::table
+      .filter(game::status.eq(any(GameStatus::active_variants())))
+      .filter(game::created_by.eq(player_id))
+      .select(game::id)
+      .load::<i32>(conn)?,
+  );
+  ids.sort();
+  ids.dedup();
+  Ok(ids)
+}
+
+/// A game a player took a slot in
+#[derive(Debug, Serialize)]
+pub struct PlayerGameRecord {
+  pub game_id: i32,
+  pub name: String,
+  pub map_name: String,
+  pub status: GameStatus,
+  pub slot_index: i32,
+  pub settings: SlotSettings,
+  pub created_at: DateTime<Utc>,
+  pub started_at: Option<DateTime<Utc>>,
+  pub ended_at: Option<DateTime<Utc>>,
+  pub duration_ms: Option<i32>,
+  /// Reported by the node if the game ran to the end
+  pub stats: Option<PlayerGameStats>,
+  pub replay_url: Option<String>,
+}
+
+#[derive(Debug, Queryable)]
+struct PlayerGameRecordRow {
+  game_id: i32,
+  name: String,
+  map_name: String,
+  status: GameStatus,
+  slot_index: i32,
+  settings: SlotSettings,
+  created_at: DateTime<Utc>,
+  started_at: Option<DateTime<Utc>>,
+  ended_at: Option<DateTime<Utc>>,
+  duration_ms: Option<i32>,
+  archive_url: Option<String>,
+}
+
+pub fn get_player_game_records(conn: &DbConn, player_id: i32) -> Result<Vec<PlayerGameRecord>> {
+  let rows: Vec<PlayerGameRecordRow> = game_used_slot::table
+    .inner_join(game::table)
+    .select((
+      game::id,
+      game::name,
+      game::map_name,
+      game::status,
+      game_used_slot::slot_index,
+      SlotSettings::COLUMNS,
+      game::created_at,
+      game::started_at,
+      game::ended_at,
+      game::duration_ms,
+      game::archive_url,
+    ))
+    .filter(game_used_slot::player_id.eq(player_id))
+    .order(game::id)
+    .load(conn)?;
+  let mut stats: HashMap<i32, PlayerGameStats> =
+    crate::game::stats::get_by_player(conn, player_id)?
+      .into_iter()
+      .map(|s| (s.game_id, s))
+      .collect();
+  rows
+    .into_iter()
+    .map(|row| {
+      let replay_url = match row.archive_url {
+        Some(ref url) => Some(crate::game::replay::sign(row.game_id, url)?.url),
+        None => None,
+      };
+      Ok(PlayerGameRecord {
+        stats: stats.remove(&row.game_id),
+        replay_url,
+        game_id: row.game_id,
+        name: row.name,
+        map_name: row.map_name,
+        status: row.status,
+        slot_index: row.slot_index,
+        settings: row.settings,
+        created_at: row.created_at,
+        started_at: row.started_at,
+        ended_at: row.ended_at,
+        duration_ms: row.duration_ms,
+      })
+    })
+    .collect()
+}
+
+/// Replaces the creator name kept in the metadata of games created by a player
+pub fn rename_game_creator(conn: &DbConn, player_id: i32, name: &str) -> Result<()> {
+  use diesel::sql_types::{Integer, Text};
+  diesel::sql_query(
+    r#"
+    update game
+    set meta = jsonb_set(meta, '{created_by,name}', to_jsonb($1::text))
+    where created_by = $2 and jsonb_typeof(meta->'created_by') = 'object'
+  "#,
+  )
+  .bind::<Text, _>(name)
+  .bind::<Integer, _>(player_id)
+  .execute(conn)?;
+  Ok(())
+}
+
 pub fn get_full(conn: &DbConn, id: i32) -> Result<Game> {
   let row: GameRowWithRelated = game::table
     .find(id)
@@ -855,6 +1572,28 @@ pub fn get_expired_games(conn: &DbConn) -> Result<Vec<i32>> {
     .map_err(Into::into)
 }
 
+/// Moves a game to another node after the selected node failed to create it
+pub fn replace_node(conn: &DbConn, id: i32, node_id: i32) -> Result<()> {
+  conn.transaction(|| {
+    let n: usize = diesel::update(game::table.find(id))
+      .filter(game::status.eq(GameStatus::Preparing))
+      .set(game::node_id.eq(node_id))
+      .execute(conn)?;
+
+    if n != 1 {
+      return Err(Error::GameStarted);
+    }
+
+    history::append(
+      conn,
+      id,
+      &GameEvent::NodeSelected {
+        node_id: Some(node_id),
+      },
+    )
+  })
+}
+
 pub fn select_node(conn: &DbConn, id: i32, player_id: i32, node_id: Option<i32>) -> Result<()> {
   use game::dsl;
 
@@ -868,29 +1607,34 @@ pub fn select_node(conn: &DbConn, id: i32, player_id: i32, node_id: Option<i32>)
     return Err(Error::GameStarted);
   }
 
-  let n: usize = diesel::update(game::table.find(id))
-    .filter(
-      dsl::status
-        .eq(GameStatus::Preparing)
-        .and(game::created_by.eq(player_id)),
-    )
-    .set(dsl::node_id.eq(node_id))
-    .execute(conn)?;
+  conn.transaction(|| {
+    let n: usize = diesel::update(game::table.find(id))
+      .filter(
+        dsl::status
+          .eq(GameStatus::Preparing)
+          .and(game::created_by.eq(player_id)),
+      )
+      .set(dsl::node_id.eq(node_id))
+      .execute(conn)?;
 
-  if n != 1 {
-    return Err(Error::GameSlotUpdateDenied);
-  }
+    if n != 1 {
+      return Err(Error::GameSlotUpdateDenied);
+    }
 
-  Ok(())
+    history::append(conn, id, &GameEvent::NodeSelected { node_id })
+  })
 }
 
 fn end_game(conn: &DbConn, id: i32, status: GameStatus) -> Result<()> {
   use game::dsl;
   conn.transaction(|| -> Result<_> {
-    diesel::update(game::table.find(id))
+    let n: usize = diesel::update(game::table.find(id))
       .filter(dsl::status.ne(status))
       .set((dsl::status.eq(status), dsl::ended_at.eq(sql("now()"))))
       .execute(conn)?;
+    if n > 0 {
+      history::append(conn, id, &GameEvent::Ended { status })?;
+    }
     Ok(())
   })?;
   Ok(())
@@ -909,13 +1653,16 @@ pub fn update_created(
 ) -> Result<()> {
   use game::dsl;
   conn.transaction(|| {
-    diesel::update(game::table.find(id))
+    let n: usize = diesel::update(game::table.find(id))
       .filter(dsl::status.eq(GameStatus::Preparing))
       .set((
         dsl::status.eq(GameStatus::Created),
         dsl::game_version.eq(agreed_version),
       ))
       .execute(conn)?;
+    if n > 0 {
+      history::append(conn, id, &GameEvent::Started)?;
+    }
     for (player_id, token) in player_tokens {
       use game_used_slot::dsl as gus;
       diesel::update(
@@ -928,15 +1675,29 @@ pub fn update_created(
   })
 }
 
+/// Stores the location of the uploaded game record archive
+pub fn update_archive_url(conn: &DbConn, id: i32, url: &str) -> Result<()> {
+  let n = diesel::update(game::table.find(id))
+    .set(game::archive_url.eq(url))
+    .execute(conn)?;
+  if n == 0 {
+    return Err(Error::GameNotFound);
+  }
+  Ok(())
+}
+
 /// Created -> Preparing
 pub fn update_reset_created(conn: &DbConn, id: i32) -> Result<()> {
   use game::dsl;
   use game_used_slot::dsl as gus;
   conn.transaction(|| {
-    diesel::update(game::table.find(id))
+    let n: usize = diesel::update(game::table.find(id))
       .filter(dsl::status.eq(GameStatus::Created))
       .set(dsl::status.eq(GameStatus::Preparing))
       .execute(conn)?;
+    if n > 0 {
+      history::append(conn, id, &GameEvent::StartReset)?;
+    }
     diesel::update(game_used_slot::table.filter(gus::game_id.eq(id)))
       .set(gus::node_token.eq(Option::<Vec<u8>>::None))
       .execute(conn)?;
@@ -1002,6 +1763,12 @@ pub struct GameRowWithRelated {
   pub random_seed: i32,
   pub mask_player_names: bool,
   pub game_version: Option<String>,
+  pub game_mode: GameMode,
+  pub random_races: bool,
+  pub random_teams: bool,
+  pub equalize_ping: bool,
+  pub step_min: Option<i32>,
+  pub step_max: Option<i32>,
 }
 
 pub(crate) type GameRowWithRelatedColumns = (
@@ -1023,6 +1790,12 @@ pub(crate) type GameRowWithRelatedColumns = (
   game::dsl::random_seed,
   game::dsl::mask_player_names,
   game::dsl::game_version,
+  game::dsl::game_mode,
+  game::dsl::random_races,
+  game::dsl::random_teams,
+  game::dsl::equalize_ping,
+  game::dsl::step_min,
+  game::dsl::step_max,
 );
 
 impl GameRowWithRelated {
@@ -1046,6 +1819,12 @@ impl GameRowWithRelated {
       game::dsl::random_seed,
       game::dsl::mask_player_names,
       game::dsl::game_version,
+      game::dsl::game_mode,
+      game::dsl::random_races,
+      game::dsl::random_teams,
+      game::dsl::equalize_ping,
+      game::dsl::step_min,
+      game::dsl::step_max,
     )
   }
 
@@ -1071,6 +1850,12 @@ impl GameRowWithRelated {
       random_seed: self.random_seed,
       mask_player_names: self.mask_player_names,
       game_version: self.game_version,
+      game_mode: self.game_mode,
+      random_races: self.random_races,
+      random_teams: self.random_teams,
+      equalize_ping: self.equalize_ping,
+      step_min: self.step_min,
+      step_max: self.step_max,
     })
   }
 }
@@ -1089,6 +1874,15 @@ pub struct GameInsert<'a> {
   pub locked: bool,
   pub node_id: Option<i32>,
   pub mask_player_names: bool,
+  pub game_mode: GameMode,
+  pub random_races: bool,
+  pub random_teams: bool,
+  pub clan_id: Option<i32>,
+  pub equalize_ping: bool,
+  pub step_min: Option<i32>,
+  pub step_max: Option<i32>,
+  pub allow_guests: bool,
+  pub team_layout: Option<String>,
 }
 
 #[derive(Debug, Insertable)]
@@ -1151,3 +1945,36 @@ impl UsedSlotUpdate {
     }
   }
 }
+
+#[test]
+fn test_validate_step_range() {
+  assert!(validate_step_range(None, None).is_ok());
+  assert!(validate_step_range(Some(30), None).is_ok());
+  assert!(validate_step_range(Some(30), Some(30)).is_ok());
+  assert!(validate_step_range(Some(1), None).is_err());
+  assert!(validate_step_range(None, Some(1000)).is_err());
+  assert!(validate_step_range(Some(60), Some(30)).is_err());
+}
+
+#[test]
+fn test_check_guest() {
+  use crate::game::slots::test_player;
+  use crate::player::PlayerSource;
+
+  let player = test_player(1);
+  let guest = PlayerRef {
+    source: PlayerSource::Guest,
+    ..test_player(2)
+  };
+  assert!(check_guest(&player, true, false).is_ok());
+  assert!(check_guest(&player, false, false).is_ok());
+  assert!(check_guest(&guest, false, true).is_ok());
+  assert!(matches!(
+    check_guest(&guest, false, false),
+    Err(Error::GameGuestsNotAllowed)
+  ));
+  assert!(matches!(
+    check_guest(&guest, true, true),
+    Err(Error::GameGuestHost)
+  ));
+}
@@ -0,0 +1,437 @@
+//! Submits finished game results to external ladder services.
+//!
+//! Each configured endpoint receives a JSON `POST` of [`GameResult`] once the node
+//! reported the game stats. Failed submissions are retried with exponential backoff.
+//! Guest players and custom games are left out so they never affect ladder ratings.
+
+use bs_diesel_utils::ExecutorRef;
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use flo_net::proto::flo_common::PlayerGameResult;
+use flo_net::proto::flo_node::PacketNodeGameStats;
+use flo_state::async_trait;
+use hyper::client::HttpConnector;
+use hyper::header::CONTENT_TYPE;
+use hyper::{Body, Client, Request};
+use hyper_tls::HttpsConnector;
+use ring::hmac;
+use serde::Serialize;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::sleep;
+
+use crate::config::ResultExporterConfig;
+use crate::db::{DbConn, ExecutorExt};
+use crate::error::*;
+use crate::game::{GameMode, Race};
+use crate::player::PlayerSource;
+use crate::schema::{game, game_used_slot, player};
+
+const SIGNATURE_HEADER: &str = "X-Flo-Signature";
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+const RETRY_INITIAL_DELAY: Duration = Duration::from_secs(5);
+const RETRY_MAX_DELAY: Duration = Duration::from_secs(300);
+
+#[derive(Debug, Serialize)]
+pub struct GameResult {
+  pub game_id: i32,
+  pub name: String,
+  pub map_name: String,
+  pub game_mode: GameMode,
+  pub game_version: Option<String>,
+  pub duration_ms: u32,
+  pub started_at: Option<DateTime<Utc>>,
+  pub ended_at: Option<DateTime<Utc>>,
+  pub winner_player_ids: Vec<i32>,
+  pub players: Vec<GameResultPlayer>,
+  pub replay_url: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct GameResultPlayer {
+  pub player_id: i32,
+  pub name: String,
+  pub source: PlayerSource,
+  pub source_id: String,
+  pub realm: Option<String>,
+  pub team: i32,
+  pub race: Race,
+  pub result: PlayerResult,
+  pub apm: u32,
+  pub left_at_ms: Option<u32>,
+}
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum PlayerResult {
+  Unknown,
+  Won,
+  Lost,
+  Draw,
+}
+
+impl From<PlayerGameResult> for PlayerResult {
+  fn from(value: PlayerGameResult) -> Self {
+    match value {
+      PlayerGameResult::Unknown => PlayerResult::Unknown,
+      PlayerGameResult::Won => PlayerResult::Won,
+      PlayerGameResult::Lost => PlayerResult::Lost,
+      PlayerGameResult::Draw => PlayerResult::Draw,
+    }
+  }
+}
+
+/// A destination for finished game results
+#[async_trait]
+pub trait ResultSink: Send + Sync {
+  fn name(&self) -> &str;
+  fn max_retries(&self) -> u32;
+  async fn submit(&self, result: &GameResult) -> Result<()>;
+}
+
+/// Posts results to a HTTP endpoint, optionally signed with HMAC-SHA256
+pub struct HttpResultSink {
+  config: ResultExporterConfig,
+  key: Option<hmac::Key>,
+  client: Client<HttpsConnector<HttpConnector>, Body>,
+}
+
+impl HttpResultSink {
+  pub fn new(config: ResultExporterConfig) -> Self {
+    let key = config
+      .secret
+      .as_ref()
+      .map(|secret| hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes()));
+    HttpResultSink {
+      config,
+      key,
+      client: Client::builder().build(HttpsConnector::new()),
+    }
+  }
+}
+
+#[async_trait]
+impl ResultSink for HttpResultSink {
+  fn name(&self) -> &str {
+    &self.config.name
+  }
+
+  fn max_retries(&self) -> u32 {
+    self.config.max_retries
+  }
+
+  async fn submit(&self, result: &GameResult) -> Result<()> {
+    let body = serde_json::to_vec(result)?;
+    let mut req = Request::post(&self.config.url).header(CONTENT_TYPE, "application/json");
+    if let Some(key) = self.key.as_ref() {
+      let tag = hmac::sign(key, &body);
+      req = req.header(
+        SIGNATURE_HEADER,
+        format!("sha256={}", hex::encode(tag.as_ref())),
+      );
+    }
+    let req = req
+      .body(Body::from(body))
+      .map_err(|err| Error::ResultExport(err.to_string()))?;
+
+    let res = tokio::time::timeout(REQUEST_TIMEOUT, self.client.request(req))
+      .await
+      .map_err(|err| Error::Timeout(err.into()))??;
+    if !res.status().is_success() {
+      return Err(Error::ResultExport(format!(
+        "unexpected status: {}",
+        res.status()
+      )));
+    }
+    Ok(())
+  }
+}
+
+/// Fans finished game results out to all configured sinks
+#[derive(Clone, Default)]
+pub struct ResultExporter {
+  sinks: Arc<Vec<Box<dyn ResultSink>>>,
+}
+
+impl ResultExporter {
+  pub fn new(sinks: Vec<Box<dyn ResultSink>>) -> Self {
+    ResultExporter {
+      sinks: Arc::new(sinks),
+    }
+  }
+
+  /// Creates a [`HttpResultSink`] for each endpoint in [`crate::config::RESULT_EXPORTERS`]
+  pub fn from_config() -> Self {
+    Self::new(
+      crate::config::RESULT_EXPORTERS
+        .iter()
+        .cloned()
+        .map(|config| Box::new(HttpResultSink::new(config)) as Box<dyn ResultSink>)
+        .collect(),
+    )
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.sinks.is_empty()
+  }
+
+  pub async fn export(&self, db: ExecutorRef, stats: PacketNodeGameStats) {
+    if self.is_empty() {
+      return;
+    }
+
+    let game_id = stats.game_id;
+    let result = match db.exec_traced(move |conn| load(conn, &stats)).await {
+      Ok(result) => result,
+      Err(err) => {
+        tracing::error!(game_id, "load game result: {}", err);
+        return;
+      }
+    };
+
+    self.submit(&result).await;
+  }
+
+  /// Sends `result` to all sinks, unrated games are skipped
+  async fn submit(&self, result: &GameResult) {
+    if !result.game_mode.is_rated() {
+      tracing::debug!(game_id = result.game_id, "skip export of unrated game");
+      return;
+    }
+
+    futures::future::join_all(
+      self
+        .sinks
+        .iter()
+        .map(|sink| submit_with_retry(sink.as_ref(), result)),
+    )
+    .await;
+  }
+}
+
+async fn submit_with_retry(sink: &dyn ResultSink, result: &GameResult) {
+  let game_id = result.game_id;
+  let mut delay = RETRY_INITIAL_DELAY;
+  let mut attempt = 0;
+  loop {
+    match sink.submit(result).await {
+      Ok(_) => {
+        tracing::info!(game_id, exporter = sink.name(), "game result exported");
+        return;
+      }
+      Err(err) => {
+        if attempt >= sink.max_retries() {
+          tracing::error!(
+            game_id,
+            exporter = sink.name(),
+            "export game result: {}, giving up after {} attempts",
+            err,
+            attempt + 1
+          );
+          return;
+        }
+        tracing::warn!(
+          game_id,
+          exporter = sink.name(),
+          "export game result: {}, retrying in {:?}",
+          err,
+          delay
+        );
+      }
+    }
+    sleep(delay).await;
+    delay = std::cmp::min(delay * 2, RETRY_MAX_DELAY);
+    attempt += 1;
+  }
+}
+
+fn load(conn: &DbConn, stats: &PacketNodeGameStats) -> Result<GameResult> {
+  let (name, map_name, game_mode, game_version, started_at, ended_at) = game::table
+    .find(stats.game_id)
+    .select((
+      game::name,
+      game::map_name,
+      game::game_mode,
+      game::game_version,
+      game::started_at,
+      game::ended_at,
+    ))
+    .first::<(
+      String,
+      String,
+      GameMode,
+      Option<String>,
+      Option<DateTime<Utc>>,
+      Option<DateTime<Utc>>,
+    )>(conn)
+    .optional()?
+    .ok_or_else(|| Error::GameNotFound)?;
+
+  let rows: Vec<ResultPlayerRow> = game_used_slot::table
+    .inner_join(player::table)
+    .filter(game_used_slot::game_id.eq(stats.game_id))
+    .select((
+      player::id,
+      player::name,
+      player::source,
+      player::source_id,
+      player::realm,
+      game_used_slot::team,
+      game_used_slot::race,
+    ))
+    .load(conn)?;
+
+  let (players, winner_player_ids) = result_players(rows, stats);
+
+  Ok(GameResult {
+    game_id: stats.game_id,
+    name,
+    map_name,
+    game_mode,
+    game_version,
+    duration_ms: stats.duration_ms,
+    started_at,
+    ended_at,
+    winner_player_ids,
+    players,
+    replay_url: None,
+  })
+}
+
+type ResultPlayerRow = (i32, String, PlayerSource, String, Option<String>, i32, Race);
+
+/// Results of the players with stats, guests are left out.
+/// Returns the players and the ids of the winners
+fn result_players(
+  rows: Vec<ResultPlayerRow>,
+  stats: &PacketNodeGameStats,
+) -> (Vec<GameResultPlayer>, Vec<i32>) {
+  let mut winner_player_ids = vec![];
+  let players = rows
+    .into_iter()
+    .filter(|(_, _, source, ..)| !source.is_guest())
+    .filter_map(|(player_id, name, source, source_id, realm, team, race)| {
+      let player_stats = stats.players.iter().find(|p| p.player_id == player_id)?;
+      let result = PlayerResult::from(player_stats.result());
+      if result == PlayerResult::Won {
+        winner_player_ids.push(player_id);
+      }
+      Some(GameResultPlayer {
+        player_id,
+        name,
+        source,
+        source_id,
+        realm,
+        team,
+        race,
+        result,
+        apm: player_stats.apm,
+        left_at_ms: player_stats.left_at_ms,
+      })
+    })
+    .collect();
+  (players, winner_player_ids)
+}
+
+#[cfg(test)]
+#[derive(Clone, Default)]
+struct RecordingSink {
+  payloads: Arc<std::sync::Mutex<Vec<serde_json::Value>>>,
+}
+
+#[cfg(test)]
+#[async_trait]
+impl ResultSink for RecordingSink {
+  fn name(&self) -> &str {
+    "recording"
+  }
+
+  fn max_retries(&self) -> u32 {
+    0
+  }
+
+  async fn submit(&self, result: &GameResult) -> Result<()> {
+    self
+      .payloads
+      .lock()
+      .unwrap()
+      .push(serde_json::to_value(result)?);
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+fn game_result(game_mode: GameMode) -> GameResult {
+  GameResult {
+    game_id: 1,
+    name: "game".to_string(),
+    map_name: "map".to_string(),
+    game_mode,
+    game_version: None,
+    duration_ms: 60_000,
+    started_at: None,
+    ended_at: None,
+    winner_player_ids: vec![],
+    players: vec![],
+    replay_url: None,
+  }
+}
+
+#[test]
+fn test_export_skips_custom_games() {
+  use futures::executor::block_on;
+  let sink = RecordingSink::default();
+  let exporter = ResultExporter::new(vec![Box::new(sink.clone())]);
+  block_on(exporter.submit(&game_result(GameMode::Custom)));
+  assert!(sink.payloads.lock().unwrap().is_empty());
+}
+
+#[test]
+fn test_export_includes_game_mode() {
+  use futures::executor::block_on;
+  let sink = RecordingSink::default();
+  let exporter = ResultExporter::new(vec![Box::new(sink.clone())]);
+  block_on(exporter.submit(&game_result(GameMode::Melee)));
+  block_on(exporter.submit(&game_result(GameMode::FFA)));
+  let payloads = sink.payloads.lock().unwrap();
+  assert_eq!(payloads.len(), 2);
+  assert_eq!(payloads[0]["game_mode"], "Melee");
+  assert_eq!(payloads[1]["game_mode"], "FFA");
+}
+
+#[test]
+fn test_export_skips_guests() {
+  use flo_net::proto::flo_node::PlayerGameStats;
+
+  let stats = PacketNodeGameStats {
+    game_id: 1,
+    duration_ms: 60_000,
+    players: (1..=2)
+      .map(|player_id| PlayerGameStats {
+        player_id,
+        result: PlayerGameResult::Won.into(),
+        ..Default::default()
+      })
+      .collect(),
+  };
+  let row = |player_id: i32, source: PlayerSource| {
+    (
+      player_id,
+      format!("player{}", player_id),
+      source,
+      player_id.to_string(),
+      None,
+      0,
+      Race::Human,
+    )
+  };
+  let (players, winner_player_ids) = result_players(
+    vec![row(1, PlayerSource::Api), row(2, PlayerSource::Guest)],
+    &stats,
+  );
+  assert_eq!(
+    players.iter().map(|p| p.player_id).collect::<Vec<_>>(),
+    vec![1]
+  );
+  assert_eq!(winner_player_ids, vec![1]);
+}
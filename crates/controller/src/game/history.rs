@@ -0,0 +1,269 @@
+//! Append-only log of lobby mutations.
+//!
+//! Every change made through `game::db` records a `GameEvent` in the same transaction,
+//! `replay` folds the events of a game back into its state.
+//! Both are exposed to API clients by the `GetGameHistory` RPC.
+
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use s2_grpc_utils::S2ProtoPack;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::db::DbConn;
+use crate::error::*;
+use crate::game::slots::UsedSlot;
+use crate::game::{GameStatus, SlotSettings};
+use crate::schema::{game, game_event, player};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum GameEvent {
+  Created {
+    name: String,
+    map_name: String,
+    created_by: Option<i32>,
+    node_id: Option<i32>,
+    slots: Vec<HistorySlot>,
+  },
+  PlayerJoined {
+    player_id: i32,
+    slots: Vec<HistorySlot>,
+  },
+  PlayerLeft {
+    player_ids: Vec<i32>,
+    slots: Vec<HistorySlot>,
+  },
+  SlotsUpdated {
+    slots: Vec<HistorySlot>,
+  },
+  MapChanged {
+    map_name: String,
+    slots: Vec<HistorySlot>,
+  },
+  NodeSelected {
+    node_id: Option<i32>,
+  },
+  /// Preparing -> Created
+  Started,
+  /// Created -> Preparing
+  StartReset,
+  StatusChanged {
+    status: GameStatus,
+  },
+  Ended {
+    status: GameStatus,
+  },
+}
+
+/// A used slot, events carry all used slots after the mutation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistorySlot {
+  pub slot_index: i32,
+  pub player_id: Option<i32>,
+  pub settings: SlotSettings,
+}
+
+impl From<UsedSlot> for HistorySlot {
+  fn from(slot: UsedSlot) -> Self {
+    HistorySlot {
+      slot_index: slot.slot_index,
+      player_id: slot.player.map(|p| p.id),
+      settings: slot.settings,
+    }
+  }
+}
+
+impl HistorySlot {
+  pub fn from_used(slots: Vec<UsedSlot>) -> Vec<Self> {
+    slots.into_iter().map(Into::into).collect()
+  }
+}
+
+#[derive(Debug)]
+pub struct GameEventRecord {
+  pub id: i64,
+  pub event: GameEvent,
+  pub created_at: DateTime<Utc>,
+}
+
+impl GameEventRecord {
+  pub fn to_entry(&self) -> Result<GameEventEntry> {
+    Ok(GameEventEntry {
+      id: self.id,
+      data: serde_json::to_string(&self.event)?,
+      created_at: self.created_at,
+    })
+  }
+}
+
+/// A recorded event with its JSON payload, as returned by the API
+#[derive(Debug, S2ProtoPack)]
+#[s2_grpc(message_type = "flo_grpc::game::GameEventEntry")]
+pub struct GameEventEntry {
+  pub id: i64,
+  pub data: String,
+  pub created_at: DateTime<Utc>,
+}
+
+pub fn append(conn: &DbConn, game_id: i32, event: &GameEvent) -> Result<()> {
+  use game_event::dsl;
+  diesel::insert_into(game_event::table)
+    .values((
+      dsl::game_id.eq(game_id),
+      dsl::data.eq(serde_json::to_value(event)?),
+    ))
+    .execute(conn)?;
+  Ok(())
+}
+
+pub fn list(conn: &DbConn, game_id: i32) -> Result<Vec<GameEventRecord>> {
+  use game_event::dsl;
+  let rows: Vec<(i64, Value, DateTime<Utc>)> = game_event::table
+    .filter(dsl::game_id.eq(game_id))
+    .order(dsl::id)
+    .select((dsl::id, dsl::data, dsl::created_at))
+    .load(conn)?;
+  rows
+    .into_iter()
+    .map(|(id, data, created_at)| {
+      Ok(GameEventRecord {
+        id,
+        event: serde_json::from_value(data)?,
+        created_at,
+      })
+    })
+    .collect()
+}
+
+/// Checks that the game was created by a player of the API client
+pub fn check_api_client_id(conn: &DbConn, api_client_id: i32, game_id: i32) -> Result<()> {
+  let n = game::table
+    .inner_join(player::table)
+    .filter(
+      game::id
+        .eq(game_id)
+        .and(player::api_client_id.eq(api_client_id)),
+    )
+    .count()
+    .get_result::<i64>(conn)?;
+  if n == 0 {
+    return Err(Error::PlayerOwnerCheckFailed);
+  }
+  Ok(())
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GameHistoryState {
+  pub name: String,
+  pub map_name: String,
+  pub created_by: Option<i32>,
+  pub node_id: Option<i32>,
+  pub status: GameStatus,
+  pub slots: Vec<HistorySlot>,
+}
+
+/// Reconstructs the game state from its events,
+/// returns `None` if the log doesn't start with `Created`
+pub fn replay<'a, I>(events: I) -> Option<GameHistoryState>
+where
+  I: IntoIterator<Item = &'a GameEvent>,
+{
+  let mut iter = events.into_iter();
+  let mut state = match iter.next()? {
+    GameEvent::Created {
+      name,
+      map_name,
+      created_by,
+      node_id,
+      slots,
+    } => GameHistoryState {
+      name: name.clone(),
+      map_name: map_name.clone(),
+      created_by: *created_by,
+      node_id: *node_id,
+      status: GameStatus::Preparing,
+      slots: slots.clone(),
+    },
+    _ => return None,
+  };
+
+  for event in iter {
+    match event {
+      GameEvent::Created { .. } => return None,
+      GameEvent::PlayerJoined { slots, .. }
+      | GameEvent::PlayerLeft { slots, .. }
+      | GameEvent::SlotsUpdated { slots } => {
+        state.slots = slots.clone();
+      }
+      GameEvent::MapChanged { map_name, slots } => {
+        state.map_name = map_name.clone();
+        state.slots = slots.clone();
+      }
+      GameEvent::NodeSelected { node_id } => {
+        state.node_id = *node_id;
+      }
+      GameEvent::Started => {
+        state.status = GameStatus::Created;
+      }
+      GameEvent::StartReset => {
+        state.status = GameStatus::Preparing;
+      }
+      GameEvent::StatusChanged { status } | GameEvent::Ended { status } => {
+        state.status = *status;
+      }
+    }
+  }
+
+  Some(state)
+}
+
+#[test]
+fn test_replay() {
+  use crate::game::SlotStatus;
+
+  let slot = |slot_index, player_id| HistorySlot {
+    slot_index,
+    player_id: Some(player_id),
+    settings: SlotSettings {
+      status: SlotStatus::Occupied,
+      ..Default::default()
+    },
+  };
+
+  let events = vec![
+    GameEvent::Created {
+      name: "game".to_string(),
+      map_name: "map".to_string(),
+      created_by: Some(1),
+      node_id: None,
+      slots: vec![slot(0, 1)],
+    },
+    GameEvent::PlayerJoined {
+      player_id: 2,
+      slots: vec![slot(0, 1), slot(1, 2)],
+    },
+    GameEvent::NodeSelected { node_id: Some(3) },
+    GameEvent::PlayerLeft {
+      player_ids: vec![2],
+      slots: vec![slot(0, 1)],
+    },
+    GameEvent::Started,
+    GameEvent::StatusChanged {
+      status: GameStatus::Running,
+    },
+  ];
+
+  let events: Vec<GameEvent> = events
+    .iter()
+    .map(|e| serde_json::from_value(serde_json::to_value(e).unwrap()).unwrap())
+    .collect();
+
+  let state = replay(&events).unwrap();
+  assert_eq!(state.node_id, Some(3));
+  assert_eq!(state.status, GameStatus::Running);
+  assert_eq!(state.slots.len(), 1);
+  assert_eq!(state.slots[0].player_id, Some(1));
+
+  assert!(replay(&events[1..]).is_none());
+}
@@ -0,0 +1,134 @@
+//! Action streams flagged by the node analyzer, stored for human review.
+
+use bs_diesel_utils::BSDieselEnum;
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use flo_net::proto::flo_node::PacketNodeActionIncident;
+use s2_grpc_utils::{S2ProtoEnum, S2ProtoPack};
+use serde::{Deserialize, Serialize};
+
+use crate::db::DbConn;
+use crate::error::*;
+use crate::schema::{action_incident, game};
+
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, PartialEq, BSDieselEnum, S2ProtoEnum)]
+#[repr(i32)]
+#[s2_grpc(proto_enum_type(
+  flo_grpc::game::ActionIncidentKind,
+  flo_net::proto::flo_node::ActionIncidentKind
+))]
+pub enum ActionIncidentKind {
+  HighActionRate = 0,
+  MalformedActions = 1,
+}
+
+#[derive(Debug, Queryable, Serialize, S2ProtoPack)]
+#[s2_grpc(message_type = "flo_grpc::game::ActionIncident")]
+pub struct ActionIncident {
+  pub id: i32,
+  pub game_id: i32,
+  pub player_id: i32,
+  pub node_id: i32,
+  #[s2_grpc(proto_enum)]
+  pub kind: ActionIncidentKind,
+  /// Game time
+  pub time_ms: i32,
+  pub detail: String,
+  pub created_at: DateTime<Utc>,
+}
+
+pub(crate) type ActionIncidentColumns = (
+  action_incident::id,
+  action_incident::game_id,
+  action_incident::player_id,
+  action_incident::node_id,
+  action_incident::kind,
+  action_incident::time_ms,
+  action_incident::detail,
+  action_incident::created_at,
+);
+
+impl ActionIncident {
+  pub(crate) const COLUMNS: ActionIncidentColumns = (
+    action_incident::id,
+    action_incident::game_id,
+    action_incident::player_id,
+    action_incident::node_id,
+    action_incident::kind,
+    action_incident::time_ms,
+    action_incident::detail,
+    action_incident::created_at,
+  );
+}
+
+/// Stores an incident reported by `node_id`,
+/// the game must be hosted by this node
+pub fn create(conn: &DbConn, node_id: i32, incident: &PacketNodeActionIncident) -> Result<()> {
+  use action_incident::dsl;
+
+  let hosted = game::table
+    .filter(game::id.eq(incident.game_id).and(game::node_id.eq(node_id)))
+    .select(game::id)
+    .first::<i32>(conn)
+    .optional()?
+    .is_some();
+  if !hosted {
+    return Err(Error::GameNotFound);
+  }
+
+  diesel::insert_into(action_incident::table)
+    .values((
+      dsl::game_id.eq(incident.game_id),
+      dsl::player_id.eq(incident.player_id),
+      dsl::node_id.eq(node_id),
+      dsl::kind.eq(ActionIncidentKind::unpack_enum(incident.kind())),
+      dsl::time_ms.eq(incident.time_ms as i32),
+      dsl::detail.eq(&incident.detail),
+    ))
+    .execute(conn)?;
+  Ok(())
+}
+
+#[derive(Debug)]
+pub struct ListActionIncident {
+  pub incidents: Vec<ActionIncident>,
+  pub next_id: Option<i32>,
+}
+
+/// Lists the incidents of a player, newest first
+pub fn list(
+  conn: &DbConn,
+  player_id: i32,
+  game_id: Option<i32>,
+  next_id: Option<i32>,
+) -> Result<ListActionIncident> {
+  const PAGE_SIZE: i64 = 100;
+  let mut q = action_incident::table
+    .select(ActionIncident::COLUMNS)
+    .filter(action_incident::player_id.eq(player_id))
+    .order(action_incident::id.desc())
+    .limit(PAGE_SIZE + 1)
+    .into_boxed();
+
+  if let Some(game_id) = game_id {
+    q = q.filter(action_incident::game_id.eq(game_id));
+  }
+
+  if let Some(id) = next_id {
+    q = q.filter(action_incident::id.le(id));
+  }
+
+  let mut rows = q.load::<ActionIncident>(conn)?;
+  let next_id = if rows.len() > PAGE_SIZE as usize {
+    let id = rows.last().map(|row| row.id);
+    rows.truncate(PAGE_SIZE as usize);
+    id
+  } else {
+    None
+  };
+
+  Ok(ListActionIncident {
+    incidents: rows,
+    next_id,
+  })
+}
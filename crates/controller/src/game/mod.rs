@@ -1,22 +1,36 @@
+pub mod chat_log;
 pub mod db;
+pub mod export;
+pub mod history;
+pub mod incident;
+pub mod replay;
 mod slots;
+pub mod snapshot;
 pub(crate) mod state;
+pub mod stats;
+pub mod store;
 pub mod token;
 mod types;
 
 pub mod messages {
   pub use super::state::cancel::CancelGame;
-  pub use super::state::create::CreateGame;
-  pub use super::state::join::PlayerJoin;
+  pub use super::state::chat::SendChat;
+  pub use super::state::create::{CreateGame, RehostGame};
+  pub use super::state::join::{PlayerJoin, PlayerRejoin};
   pub use super::state::leave::PlayerLeave;
+  pub use super::state::map_vote::{CastMapVote, StartMapVote};
+  pub use super::state::mute::{GetGameMuteList, UpdateGameMuteList};
+  pub use super::state::slot::{ReserveSlot, SetSlotClosed, ShuffleSlots};
   pub use super::state::node::SelectNode;
   pub use super::state::player::GetGamePlayers;
+  pub use super::state::referee::SetReferee;
   pub use super::state::registry::{
-    AddGamePlayer, Register, Remove, RemoveGamePlayer, ResolveGamePlayerPingBroadcastTargets,
+    AddGamePlayer, NotifyGamePlayerPingUpdate, Register, Remove, RemoveGamePlayer,
+    ResolveGamePlayerPingBroadcastTargets, SaveActionIncident, SaveGameChatLog, SaveGameStats,
   };
   pub use super::state::slot::UpdateSlot;
   pub use super::state::start::{StartGameCheck, StartGamePlayerAck};
 }
 
-pub use slots::Slots;
+pub use slots::{MapSlot, Slots, TeamLayout};
 pub use types::*;
@@ -0,0 +1,46 @@
+//! Download links to the record archives of finished games.
+
+use chrono::{DateTime, TimeZone, Utc};
+use diesel::prelude::*;
+use serde::Serialize;
+
+use crate::db::DbConn;
+use crate::error::*;
+use crate::schema::game;
+
+#[derive(Debug, Serialize)]
+pub struct ReplayUrl {
+  pub url: String,
+  /// `None` if the link does not expire
+  pub expires_at: Option<DateTime<Utc>>,
+}
+
+/// Creates a time-limited link served by `FLO_REPLAY_DOWNLOAD_URL`,
+/// or returns the archive URL as is if the download endpoint is not configured
+pub fn sign(game_id: i32, archive_url: &str) -> Result<ReplayUrl> {
+  let base = match crate::config::REPLAY_DOWNLOAD_URL.as_ref() {
+    Some(base) => base,
+    None => {
+      return Ok(ReplayUrl {
+        url: archive_url.to_string(),
+        expires_at: None,
+      })
+    }
+  };
+  let (token, exp) = flo_observer::token::create_replay_token(game_id)?;
+  Ok(ReplayUrl {
+    url: format!("{}/{}?token={}", base, game_id, token),
+    expires_at: Some(Utc.timestamp(exp, 0)),
+  })
+}
+
+pub fn get(conn: &DbConn, game_id: i32) -> Result<ReplayUrl> {
+  let archive_url: Option<String> = game::table
+    .find(game_id)
+    .select(game::archive_url)
+    .first(conn)
+    .optional()?
+    .ok_or_else(|| Error::GameNotFound)?;
+  let archive_url = archive_url.ok_or_else(|| Error::GameReplayNotFound)?;
+  sign(game_id, &archive_url)
+}
@@ -1,17 +1,131 @@
 use diesel::helper_types::Nullable;
 use diesel::prelude::*;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 
+use crate::error::{Error, Result};
+use crate::game::db::SlotOwnerInfo;
 use crate::game::{
-  Computer, Slot, SlotClientStatus, SlotSettings, SlotSettingsColumns, SlotStatus,
+  Computer, Race, Slot, SlotClientStatus, SlotSettings, SlotSettingsColumns, SlotStatus,
 };
+use crate::map::Map;
 use crate::player::{PlayerRef, PlayerRefColumns};
 use crate::schema::game_used_slot;
 
+/// Player slot settings defined by the map file
+#[derive(Debug, Clone)]
+pub struct MapSlot {
+  /// Team from the map's custom forces
+  pub team: Option<i32>,
+  /// Race fixed by the map, `None` if selectable
+  pub race: Option<Race>,
+  /// Computer player fixed by the map
+  pub computer: bool,
+  /// Settings can't be changed in the lobby
+  pub fixed: bool,
+}
+
+impl MapSlot {
+  pub fn from_map(map: &Map) -> Vec<MapSlot> {
+    let fixed = map.fixed_player_settings();
+    let custom_forces = map.custom_forces();
+    map
+      .players
+      .iter()
+      .enumerate()
+      .map(|(idx, player)| MapSlot {
+        team: if custom_forces {
+          map
+            .forces
+            .iter()
+            .position(|force| force.player_set & (1 << idx) != 0)
+            .map(|team| team as i32)
+        } else {
+          None
+        },
+        race: if fixed {
+          match player.race {
+            1 => Some(Race::Human),
+            2 => Some(Race::Orc),
+            3 => Some(Race::Undead),
+            4 => Some(Race::NightElf),
+            _ => None,
+          }
+        } else {
+          None
+        },
+        computer: fixed && player.r#type == 2,
+        fixed,
+      })
+      .collect()
+  }
+}
+
+/// Maximum number of players per team, parsed from strings like `2v2` or `1v1v1v1`
+#[derive(Debug, Clone, PartialEq)]
+pub struct TeamLayout {
+  team_sizes: Vec<usize>,
+}
+
+impl TeamLayout {
+  pub fn teams(&self) -> usize {
+    self.team_sizes.len()
+  }
+
+  /// Returns `None` if the team is not part of the layout
+  pub fn team_size(&self, team: i32) -> Option<usize> {
+    if team < 0 {
+      return None;
+    }
+    self.team_sizes.get(team as usize).cloned()
+  }
+
+  /// Returns an error if the map doesn't have enough player slots for the layout
+  pub fn validate(&self, map_players: usize) -> Result<()> {
+    if self.teams() > map_players || self.team_sizes.iter().sum::<usize>() > map_players {
+      return Err(Error::GameTeamLayoutInvalid);
+    }
+    Ok(())
+  }
+}
+
+impl std::str::FromStr for TeamLayout {
+  type Err = Error;
+
+  fn from_str(s: &str) -> Result<Self> {
+    let team_sizes = s
+      .split('v')
+      .map(|v| match v.trim().parse::<usize>() {
+        Ok(size) if size > 0 && size <= 24 => Ok(size),
+        _ => Err(Error::GameTeamLayoutInvalid),
+      })
+      .collect::<Result<Vec<_>>>()?;
+    if team_sizes.len() < 2 {
+      return Err(Error::GameTeamLayoutInvalid);
+    }
+    Ok(TeamLayout { team_sizes })
+  }
+}
+
+impl std::fmt::Display for TeamLayout {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    for (i, size) in self.team_sizes.iter().enumerate() {
+      if i > 0 {
+        write!(f, "v")?;
+      }
+      write!(f, "{}", size)?;
+    }
+    Ok(())
+  }
+}
+
 #[derive(Debug)]
 pub struct Slots {
   inner: Vec<Slot>,
   map_players: usize,
+  // slot index -> player id
+  reservations: BTreeMap<usize, i32>,
+  map_slots: Vec<MapSlot>,
+  team_layout: Option<TeamLayout>,
 }
 
 impl Slots {
@@ -22,7 +136,145 @@ impl Slots {
       .map(|(idx, _)| Self::make_unused_slot(map_players, idx))
       .collect();
 
-    Self { inner, map_players }
+    Self {
+      inner,
+      map_players,
+      reservations: BTreeMap::new(),
+      map_slots: vec![],
+      team_layout: None,
+    }
+  }
+
+  /// Creates the initial slot layout from the map's players and forces
+  pub fn from_map(map: &Map) -> Self {
+    let mut slots = Self::new(map.players.len());
+    slots.set_map(map);
+    for (idx, map_slot) in slots.map_slots.iter().enumerate() {
+      let slot = &mut slots.inner[idx];
+      if let Some(team) = map_slot.team {
+        slot.settings.team = team;
+      }
+      if let Some(race) = map_slot.race {
+        slot.settings.race = race;
+      }
+      if map_slot.computer {
+        slot.settings.status = SlotStatus::Occupied;
+        slot.settings.computer = Computer::Normal;
+        slot.settings.color = idx as i32;
+      }
+    }
+    slots
+  }
+
+  pub fn set_map(&mut self, map: &Map) {
+    self.map_slots = MapSlot::from_map(map);
+  }
+
+  /// Limits the number of players per team, joining players are assigned to the emptiest team
+  pub fn set_team_layout(&mut self, team_layout: Option<TeamLayout>) {
+    self.team_layout = team_layout;
+  }
+
+  pub fn team_layout(&self) -> Option<&TeamLayout> {
+    self.team_layout.as_ref()
+  }
+
+  /// Number of occupied player slots in the team, excluding `except_slot_index`
+  fn count_team_players(&self, team: i32, except_slot_index: Option<usize>) -> usize {
+    self
+      .inner
+      .iter()
+      .enumerate()
+      .filter(|(idx, slot)| {
+        Some(*idx) != except_slot_index
+          && slot.settings.status == SlotStatus::Occupied
+          && slot.settings.team == team
+      })
+      .count()
+  }
+
+  /// The team with the fewest players that is not full, `None` if all teams are full
+  fn find_emptiest_team(&self) -> Option<i32> {
+    let layout = self.team_layout.as_ref()?;
+    (0..layout.teams())
+      .filter_map(|team| {
+        let count = self.count_team_players(team as i32, None);
+        if count < layout.team_sizes[team] {
+          Some((count, team as i32))
+        } else {
+          None
+        }
+      })
+      .min()
+      .map(|(_, team)| team)
+  }
+
+  /// Checks the settings requested by a client for a slot,
+  /// before they are applied with [`Slots::update_slot_at`]
+  pub fn validate_update(&self, slot_index: i32, settings: &SlotSettings) -> Result<()> {
+    let slot = if slot_index >= 0 {
+      self.inner.get(slot_index as usize)
+    } else {
+      None
+    }
+    .ok_or_else(|| Error::GameSlotIndexInvalid)?;
+
+    if settings.team != 24 && (settings.team < 0 || settings.team >= self.map_players as i32) {
+      return Err(Error::PlayerTeamInvalid);
+    }
+
+    if settings.color < 0 || settings.color >= 24 {
+      return Err(Error::PlayerColorInvalid);
+    }
+
+    if SlotSettings::normalize_handicap(settings.handicap).is_none() {
+      return Err(Error::PlayerHandicapInvalid);
+    }
+
+    // player slots can't be opened or closed
+    if slot.player.is_some() && settings.status != SlotStatus::Occupied {
+      return Err(Error::GameSlotStatusInvalid);
+    }
+
+    if let Some(layout) = self.team_layout.as_ref() {
+      let joins_team =
+        settings.team != slot.settings.team || slot.settings.status != SlotStatus::Occupied;
+      if settings.team != 24 && settings.status == SlotStatus::Occupied && joins_team {
+        let team_size = layout
+          .team_size(settings.team)
+          .ok_or_else(|| Error::PlayerTeamInvalid)?;
+        if self.count_team_players(settings.team, Some(slot_index as usize)) >= team_size {
+          return Err(Error::GameTeamFull);
+        }
+      }
+    }
+
+    self.check_map_constraints(slot_index, settings)
+  }
+
+  /// Returns an error if the settings violate the constraints of the map
+  pub fn check_map_constraints(&self, slot_index: i32, settings: &SlotSettings) -> Result<()> {
+    let map_slot = match self.map_slots.get(slot_index as usize) {
+      Some(map_slot) if map_slot.fixed => map_slot,
+      _ => return Ok(()),
+    };
+    let slot = &self.inner[slot_index as usize];
+
+    if settings.team != slot.settings.team {
+      return Err(Error::GameSlotTeamFixedByMap);
+    }
+
+    if let Some(race) = map_slot.race {
+      if settings.race != race {
+        return Err(Error::GameSlotRaceFixedByMap);
+      }
+    }
+
+    if map_slot.computer && settings.status != SlotStatus::Occupied {
+      return Err(Error::GameSlotFixedByMap);
+    }
+
+    Ok(())
   }
 
   pub fn from_used(map_players: usize, slots: Vec<UsedSlot>) -> Self {
@@ -45,7 +297,106 @@ impl Slots {
         }
       })
       .collect();
-    Slots { map_players, inner }
+    let mut slots = Slots {
+      map_players,
+      inner,
+      reservations: BTreeMap::new(),
+      map_slots: vec![],
+      team_layout: None,
+    };
+    let repaired = slots.repair();
+    if !repaired.is_empty() {
+      tracing::warn!("repaired invalid slots: {:?}", repaired);
+    }
+    slots
+  }
+
+  /// Fixes slots breaking the invariants checked by [`Slots::validate`],
+  /// e.g. slots stored by older versions. Returns the indexes of the repaired slots
+  pub fn repair(&mut self) -> Vec<i32> {
+    let map_players = self.map_players;
+    let mut repaired = BTreeSet::new();
+    let mut player_ids = BTreeSet::new();
+
+    for (idx, slot) in self.inner.iter_mut().enumerate() {
+      let settings = &slot.settings;
+      let invalid_team = settings.team != 24
+        && (settings.team < 0 || settings.team >= map_players as i32 || idx >= map_players);
+      let keep = match slot.player.as_ref() {
+        Some(player) => player_ids.insert(player.id),
+        None => !invalid_team && !(settings.status == SlotStatus::Occupied && settings.team == 24),
+      };
+      if !keep {
+        *slot = Self::make_unused_slot(map_players, idx);
+        repaired.insert(idx as i32);
+        continue;
+      }
+      if slot.player.is_some() && (invalid_team || slot.settings.status != SlotStatus::Occupied) {
+        slot.settings.status = SlotStatus::Occupied;
+        if invalid_team {
+          slot.settings.team = 24;
+          slot.settings.color = 0;
+        }
+        repaired.insert(idx as i32);
+      }
+      if SlotSettings::normalize_handicap(slot.settings.handicap) != Some(slot.settings.handicap) {
+        slot.settings.handicap = SlotSettings::default().handicap;
+        repaired.insert(idx as i32);
+      }
+    }
+
+    // players beyond the map's player count become referees, computers are removed
+    let mut occupied_player_slots = 0;
+    let mut color_set = [false; 24];
+    let mut recolor = vec![];
+    for (idx, slot) in self.inner.iter_mut().enumerate() {
+      if slot.settings.status != SlotStatus::Occupied || slot.settings.team == 24 {
+        continue;
+      }
+      if occupied_player_slots >= map_players {
+        if slot.player.is_some() {
+          slot.settings.team = 24;
+          slot.settings.color = 0;
+        } else {
+          *slot = Self::make_unused_slot(map_players, idx);
+        }
+        repaired.insert(idx as i32);
+        continue;
+      }
+      occupied_player_slots += 1;
+      let color = slot.settings.color;
+      if color < 0 || color >= 24 || color_set[color as usize] {
+        recolor.push(idx);
+        repaired.insert(idx as i32);
+      } else {
+        color_set[color as usize] = true;
+      }
+    }
+
+    // duplicate or invalid colors take the first free colors
+    for idx in recolor {
+      let color = color_set.iter().position(|used| !*used).unwrap_or_default();
+      color_set[color] = true;
+      self.inner[idx].settings.color = color as i32;
+    }
+
+    repaired.into_iter().collect()
+  }
+
+  pub fn set_reservations(&mut self, reservations: BTreeMap<usize, i32>) {
+    self.reservations = reservations;
+  }
+
+  /// Returns `true` if the slot is reserved for a player other than `player_id`
+  fn is_reserved_for_other(
+    reservations: &BTreeMap<usize, i32>,
+    slot_index: usize,
+    player_id: Option<i32>,
+  ) -> bool {
+    match reservations.get(&slot_index) {
+      Some(id) => Some(*id) != player_id,
+      None => false,
+    }
   }
 
   pub fn as_used(&self) -> Vec<UsedSlot> {
@@ -67,6 +418,81 @@ impl Slots {
     self.inner
   }
 
+  /// Re-layout slots for a new map player count.
+  /// Slots that still exist on the new map and are not fixed by it are kept,
+  /// players and computers in other slots are moved to the next open slot.
+  /// Returns `None` if there are not enough slots for all players.
+  pub fn relayout(self, map: &Map) -> Option<Self> {
+    let map_players = map.players.len();
+    let mut next = Slots::from_map(map);
+    next.reservations = self.reservations;
+    next.team_layout = self.team_layout;
+    let mut moved = vec![];
+    for (idx, slot) in self.inner.into_iter().enumerate() {
+      if !slot.is_used() {
+        continue;
+      }
+      let fixed = next.map_slots.get(idx).map(|s| s.fixed).unwrap_or(false);
+      let keep = if slot.settings.team == 24 {
+        idx >= map_players
+      } else {
+        !fixed && idx < map_players && slot.settings.team < map_players as i32
+      };
+      if keep {
+        next.inner[idx] = slot;
+      } else if slot.player.is_some() || slot.settings.status == SlotStatus::Occupied {
+        moved.push(slot);
+      }
+    }
+
+    // kept slots can use the colors of the map's computer players
+    next.resolve_color_conflicts(-1).ok()?;
+
+    for slot in moved {
+      match slot.player {
+        Some(player) => {
+          next.join(&player)?;
+        }
+        None => next.move_computer(slot.settings),
+      }
+    }
+    next.debug_validate();
+    Some(next)
+  }
+
+  /// Put a computer player into the next open player slot,
+  /// the computer is dropped if there is none
+  fn move_computer(&mut self, settings: SlotSettings) {
+    let map_players = self.map_players;
+    let reservations = &self.reservations;
+    let map_slots = &self.map_slots;
+    let idx = self.inner.iter().enumerate().position(|(idx, slot)| {
+      idx < map_players
+        && slot.settings.status == SlotStatus::Open
+        && !reservations.contains_key(&idx)
+        && !map_slots.get(idx).map(|s| s.fixed).unwrap_or(false)
+    });
+    let idx = match idx {
+      Some(idx) => idx,
+      None => return,
+    };
+    let color = match self.get_color_set().iter().position(|v| !*v) {
+      Some(color) => color as i32,
+      None => return,
+    };
+    let slot = &mut self.inner[idx];
+    slot.settings = SlotSettings {
+      team: if settings.team < map_players as i32 {
+        settings.team
+      } else {
+        0
+      },
+      color,
+      status: SlotStatus::Occupied,
+      ..settings
+    };
+  }
+
   fn make_unused_slot(map_players: usize, idx: usize) -> Slot {
     Slot {
       settings: SlotSettings {
@@ -111,10 +537,85 @@ impl Slots {
   }
 
   pub fn join(&mut self, player: &PlayerRef) -> Option<&mut Slot> {
-    self.acquire_slot_mut().map(|s| {
-      s.player = Some(player.clone());
-      s
-    })
+    let idx = self.acquire_slot_index(player.id)?;
+    self.inner[idx].player = Some(player.clone());
+    self.debug_validate();
+    Some(&mut self.inner[idx])
+  }
+
+  /// Returns an error if the player can't join, checked before [`Slots::join`]
+  pub fn check_join(&self, player_id: i32) -> Result<()> {
+    if self.find_player_slot(player_id).is_some() {
+      return Err(Error::PlayerAlreadyInGame);
+    }
+    if self.is_full() {
+      return Err(Error::GameFull);
+    }
+    Ok(())
+  }
+
+  /// Removes the player, or all players if `player_id` is the host.
+  /// Returns the removed player ids and whether the game ends
+  pub fn leave(&mut self, player_id: i32, host_player_id: i32) -> (Vec<i32>, bool) {
+    if player_id == host_player_id {
+      return (self.release_all_player_slots(), true);
+    }
+    if self.release_player_slot(player_id) {
+      (vec![player_id], self.is_empty())
+    } else {
+      (vec![], false)
+    }
+  }
+
+  /// Applies a slot change requested by a player, only the host and the player
+  /// in the slot can change it. Returns the updated slot indexes
+  pub fn update_slot_as(
+    &mut self,
+    player_id: i32,
+    host_player_id: i32,
+    slot_index: i32,
+    settings: &SlotSettings,
+  ) -> Result<Vec<i32>> {
+    let info = SlotOwnerInfo {
+      host_player_id,
+      slot_player_id: self
+        .inner
+        .get(slot_index as usize)
+        .and_then(|slot| slot.player.as_ref().map(|p| p.id)),
+    };
+    if !info.is_slot_owner(player_id) {
+      return Err(Error::GameSlotUpdateDenied);
+    }
+    self.apply_update(slot_index, settings)
+  }
+
+  /// Validates and applies the settings of a slot, returns the updated slot indexes
+  pub fn apply_update(&mut self, slot_index: i32, settings: &SlotSettings) -> Result<Vec<i32>> {
+    self.validate_update(slot_index, settings)?;
+    Ok(
+      self
+        .update_slot_at(slot_index, settings)
+        .map(|updated| updated.into_iter().map(|(index, _)| index).collect())
+        .unwrap_or_default(),
+    )
+  }
+
+  /// Reserves a player slot for a player, or removes the reservation if `player_id` is `None`.
+  /// A player has at most one reserved slot
+  pub fn reserve(&mut self, slot_index: i32, player_id: Option<i32>) -> Result<()> {
+    if slot_index < 0 || slot_index as usize >= self.map_players {
+      return Err(Error::GameSlotUpdateDenied);
+    }
+    self.reservations.remove(&(slot_index as usize));
+    if let Some(player_id) = player_id {
+      self.reservations.retain(|_, id| *id != player_id);
+      self.reservations.insert(slot_index as usize, player_id);
+    }
+    Ok(())
+  }
+
+  pub fn reservations(&self) -> &BTreeMap<usize, i32> {
+    &self.reservations
   }
 
   pub fn find_player_slot(&self, player_id: i32) -> Option<&Slot> {
@@ -124,29 +625,45 @@ impl Slots {
       .find(|s| s.player.as_ref().map(|p| p.id) == Some(player_id))
   }
 
-  /// Find next open slot, update team, color and status then return it
-  pub fn acquire_slot_mut(&mut self) -> Option<&mut Slot> {
+  /// Find the slot reserved for the player or the next open slot,
+  /// update team, color and status then return it
+  pub fn acquire_slot_mut(&mut self, player_id: i32) -> Option<&mut Slot> {
+    let idx = self.acquire_slot_index(player_id)?;
+    Some(&mut self.inner[idx])
+  }
+
+  fn acquire_slot_index(&mut self, player_id: i32) -> Option<usize> {
     let mut open_slot_idx = None;
+    let mut reserved_slot_idx = None;
     let mut color_set = [false; 24];
     let mut occupied_player_slots = 0;
     for (i, slot) in self.inner.iter().enumerate() {
       match slot.settings.status {
         SlotStatus::Occupied => {
-          if slot.settings.color < 24 {
-            color_set[slot.settings.color as usize] = true;
-          }
           if slot.settings.team != 24 {
+            if slot.settings.color < 24 {
+              color_set[slot.settings.color as usize] = true;
+            }
             occupied_player_slots = occupied_player_slots + 1;
           }
         }
-        SlotStatus::Open => {
-          if let None = open_slot_idx {
-            open_slot_idx = Some(i)
+        SlotStatus::Open => match self.reservations.get(&i) {
+          Some(id) if *id == player_id => {
+            if let None = reserved_slot_idx {
+              reserved_slot_idx = Some(i)
+            }
           }
-        }
+          Some(_) => {}
+          None => {
+            if let None = open_slot_idx {
+              open_slot_idx = Some(i)
+            }
+          }
+        },
         SlotStatus::Closed => {}
       }
     }
+    let open_slot_idx = reserved_slot_idx.or(open_slot_idx);
     let mut color = 0;
     for i in 0..24 {
       if !color_set[i] {
@@ -156,20 +673,33 @@ impl Slots {
     }
 
     if let Some(idx) = open_slot_idx {
+      let map_slot = self.map_slots.get(idx).cloned();
+      // players joining into a slot beyond the map's player count become referees
+      let mut referee = occupied_player_slots >= self.map_players || idx >= self.map_players;
+      let map_team = map_slot.as_ref().and_then(|s| s.team);
+      let layout_team = if referee || map_team.is_some() || self.team_layout.is_none() {
+        None
+      } else {
+        // players become referees if all teams of the layout are full
+        let team = self.find_emptiest_team();
+        referee = team.is_none();
+        team
+      };
       let slot = &mut self.inner[idx];
-      slot.settings.team = if occupied_player_slots >= self.map_players {
+      slot.settings.team = if referee {
         24
+      } else if let Some(team) = map_team.or(layout_team) {
+        team
       } else {
         occupied_player_slots as i32
       };
-      slot.settings.color = if occupied_player_slots >= self.map_players {
-        0
-      } else {
-        color as i32
-      };
+      if let Some(race) = map_slot.as_ref().and_then(|s| s.race) {
+        slot.settings.race = race;
+      }
+      slot.settings.color = if referee { 0 } else { color as i32 };
       slot.settings.status = SlotStatus::Occupied;
       slot.settings.computer = Computer::Easy;
-      Some(slot)
+      Some(idx)
     } else {
       None
     }
@@ -177,15 +707,16 @@ impl Slots {
 
   /// Remove a players and reset the slot
   pub fn release_player_slot(&mut self, player_id: i32) -> bool {
-    let slot = self.inner.iter_mut().find(|s| {
+    let idx = self.inner.iter().position(|s| {
       s.player
         .as_ref()
         .map(|p| p.id == player_id)
         .unwrap_or_default()
     });
-    match slot {
-      Some(slot) => {
-        *slot = Default::default();
+    match idx {
+      Some(idx) => {
+        self.inner[idx] = Self::make_unused_slot(self.map_players, idx);
+        self.debug_validate();
         true
       }
       None => false,
@@ -195,12 +726,13 @@ impl Slots {
   /// Remove all players, return removed player ids
   pub fn release_all_player_slots(&mut self) -> Vec<i32> {
     let mut player_ids = vec![];
-    for slot in &mut self.inner {
+    for (idx, slot) in self.inner.iter_mut().enumerate() {
       if let Some(id) = slot.player.as_ref().map(|p| p.id) {
         player_ids.push(id);
-        *slot = Default::default();
+        *slot = Self::make_unused_slot(self.map_players, idx);
       }
     }
+    self.debug_validate();
     player_ids
   }
 
@@ -216,16 +748,19 @@ impl Slots {
       return None;
     }
 
-    let mut updated_slots = vec![];
-
     // handle team change first
     let target_index = {
-      if settings.team > self.map_players as i32 && settings.team != 24 {
+      if settings.team < 0 || (settings.team >= self.map_players as i32 && settings.team != 24) {
         return None;
       }
 
       let mut target_index = slot_index;
       let current_settings = self.inner[slot_index as usize].settings.clone();
+      let current_player_id = self.inner[slot_index as usize]
+        .player
+        .as_ref()
+        .map(|p| p.id);
+      let reservations = &self.reservations;
       let new_team = settings.team;
 
       if new_team != current_settings.team {
@@ -235,11 +770,12 @@ impl Slots {
           let next_color = color_set.iter().position(|v| !*v).map(|v| v as i32);
 
           // find an open player slot
-          if let Some((index, _player_slot)) = self
-            .inner
-            .iter_mut()
-            .enumerate()
-            .find(|(_index, s)| s.settings.team != 24 && s.settings.status == SlotStatus::Open)
+          if let Some((index, _player_slot)) =
+            self.inner.iter_mut().enumerate().find(|(index, s)| {
+              s.settings.team != 24
+                && s.settings.status == SlotStatus::Open
+                && !Self::is_reserved_for_other(reservations, *index, current_player_id)
+            })
           {
             target_index = index as i32;
             self.inner[index].player = self.inner[slot_index as usize].player.clone();
@@ -262,6 +798,11 @@ impl Slots {
         } else if current_settings.team != 24 && new_team == 24 {
           // players -> referees:
 
+          // computers can't be referees
+          if current_player_id.is_none() {
+            return None;
+          }
+
           // find an open referee slot
           if let Some((index, _player_slot)) = self
             .inner
@@ -276,7 +817,8 @@ impl Slots {
               status: SlotStatus::Occupied,
               ..Default::default()
             };
-            self.inner[slot_index as usize] = Default::default();
+            self.inner[slot_index as usize] =
+              Self::make_unused_slot(self.map_players, slot_index as usize);
           } else {
             return None;
           }
@@ -311,33 +853,251 @@ impl Slots {
                 }
               }
               slot.settings.color = color;
-              slot.settings.computer = settings.computer;
             }
           }
         }
+
+        // computer players
+        if slot.settings.status == SlotStatus::Occupied {
+          slot.settings.computer = settings.computer;
+        }
       }
 
       let new_color = settings.color;
-      if new_color < 24 && slot.settings.color != new_color {
+      if new_color >= 0 && new_color < 24 && slot.settings.color != new_color {
         if !color_set[new_color as usize] {
           slot.settings.color = new_color;
         }
       }
 
-      let new_handicap = settings.handicap;
-      if new_handicap >= 50 && new_handicap <= 100 {
-        slot.settings.handicap = new_handicap - (new_handicap % 10);
+      if let Some(handicap) = SlotSettings::normalize_handicap(settings.handicap) {
+        slot.settings.handicap = handicap;
       }
 
       slot.settings.race = settings.race;
     }
 
-    updated_slots.push((slot_index, &self.inner[slot_index as usize]));
-    if target_index != slot_index {
-      updated_slots.push((target_index, &self.inner[target_index as usize]))
+    let mut updated_indexes = BTreeSet::new();
+    updated_indexes.insert(slot_index);
+    updated_indexes.insert(target_index);
+    match self.resolve_color_conflicts(target_index) {
+      Ok(indexes) => updated_indexes.extend(indexes),
+      Err(err) => {
+        tracing::error!(slot_index, "resolve color conflicts: {}", err);
+        return None;
+      }
+    }
+    self.debug_validate();
+
+    Some(
+      updated_indexes
+        .into_iter()
+        .map(|index| (index, &self.inner[index as usize]))
+        .collect(),
+    )
+  }
+
+  /// Close or reopen an empty slot, return the updated slot
+  pub fn set_slot_closed(&mut self, slot_index: i32, closed: bool) -> Option<&Slot> {
+    let idx = slot_index as usize;
+    let slot = self.inner.get_mut(idx)?;
+
+    if slot.player.is_some() {
+      return None;
+    }
+
+    let team = slot.settings.team;
+    *slot = Slot {
+      settings: SlotSettings {
+        team,
+        status: if closed {
+          SlotStatus::Closed
+        } else {
+          SlotStatus::Open
+        },
+        ..Default::default()
+      },
+      ..Default::default()
+    };
+    self.debug_validate();
+
+    Some(&self.inner[idx])
+  }
+
+  /// Reassign duplicated player colors to the first free color,
+  /// the slot at `keep_index` keeps its color. Return updated slot indexes,
+  /// or an error if there is no free color left
+  pub fn resolve_color_conflicts(&mut self, keep_index: i32) -> Result<Vec<i32>> {
+    let mut color_set = [false; 24];
+    let mut conflicts = vec![];
+
+    let is_player_slot =
+      |slot: &Slot| slot.settings.status == SlotStatus::Occupied && slot.settings.team != 24;
+
+    if let Some(slot) = self.inner.get(keep_index as usize) {
+      if is_player_slot(slot) && slot.settings.color >= 0 && slot.settings.color < 24 {
+        color_set[slot.settings.color as usize] = true;
+      }
+    }
+
+    for (i, slot) in self.inner.iter().enumerate() {
+      if i as i32 == keep_index || !is_player_slot(slot) {
+        continue;
+      }
+      let color = slot.settings.color;
+      if color >= 0 && color < 24 && !color_set[color as usize] {
+        color_set[color as usize] = true;
+      } else {
+        conflicts.push(i);
+      }
+    }
+
+    let mut updated = vec![];
+    for i in conflicts {
+      let color = color_set
+        .iter()
+        .position(|v| !*v)
+        .ok_or_else(|| Error::PlayerColorConflict)?;
+      color_set[color] = true;
+      self.inner[i].settings.color = color as i32;
+      updated.push(i as i32);
+    }
+    Ok(updated)
+  }
+
+  /// Randomize races and/or teams of occupied player slots, return updated slot indexes.
+  /// Teams and races fixed by the map are left unchanged.
+  pub fn shuffle(&mut self, races: bool, teams: bool) -> Vec<i32> {
+    use rand::seq::SliceRandom;
+    const RACES: [Race; 4] = [Race::Human, Race::Orc, Race::NightElf, Race::Undead];
+
+    let mut rng = rand::thread_rng();
+    let mut updated = BTreeSet::new();
+    let indexes: Vec<usize> = self
+      .inner
+      .iter()
+      .enumerate()
+      .filter(|(_, s)| s.settings.status == SlotStatus::Occupied && s.settings.team != 24)
+      .map(|(idx, _)| idx)
+      .collect();
+    let map_slots = &self.map_slots;
+    let map_slot = |idx: usize| map_slots.get(idx).filter(|s| s.fixed);
+    let team_indexes: Vec<usize> = indexes
+      .iter()
+      .cloned()
+      .filter(|idx| map_slot(*idx).is_none())
+      .collect();
+    let race_indexes: Vec<usize> = indexes
+      .iter()
+      .cloned()
+      .filter(|idx| map_slot(*idx).and_then(|s| s.race).is_none())
+      .collect();
+
+    // team sizes are preserved
+    if teams {
+      let mut values: Vec<i32> = team_indexes
+        .iter()
+        .map(|idx| self.inner[*idx].settings.team)
+        .collect();
+      values.shuffle(&mut rng);
+      for (idx, team) in team_indexes.iter().zip(values) {
+        let settings = &mut self.inner[*idx].settings;
+        if settings.team != team {
+          settings.team = team;
+          updated.insert(*idx as i32);
+        }
+      }
+    }
+
+    if races {
+      for idx in &race_indexes {
+        let race = *RACES.choose(&mut rng).expect("not empty");
+        let settings = &mut self.inner[*idx].settings;
+        if settings.race != race {
+          settings.race = race;
+          updated.insert(*idx as i32);
+        }
+      }
+    }
+
+    self.debug_validate();
+    updated.into_iter().collect()
+  }
+
+  /// Check the invariants maintained by the slot operations
+  pub fn validate(&self) -> Result<()> {
+    let mut player_ids = BTreeSet::new();
+    let mut color_set = [false; 24];
+    let mut occupied_player_slots = 0;
+
+    for (idx, slot) in self.inner.iter().enumerate() {
+      let settings = &slot.settings;
+      let invalid = |msg: &str| Err(Error::GameSlotsInvalid(format!("slot {}: {}", idx, msg)));
+
+      if let Some(player) = slot.player.as_ref() {
+        if !player_ids.insert(player.id) {
+          return invalid("duplicate player");
+        }
+        if settings.status != SlotStatus::Occupied {
+          return invalid("player in a slot that is not occupied");
+        }
+      }
+
+      if settings.team != 24 && (settings.team < 0 || settings.team >= self.map_players as i32) {
+        return invalid("team out of range");
+      }
+
+      if idx >= self.map_players && settings.team != 24 {
+        return invalid("player team in a referee slot");
+      }
+
+      if SlotSettings::normalize_handicap(settings.handicap) != Some(settings.handicap) {
+        return invalid("invalid handicap");
+      }
+
+      if settings.status != SlotStatus::Occupied {
+        continue;
+      }
+
+      if settings.team == 24 {
+        if slot.player.is_none() {
+          return invalid("referee slot without player");
+        }
+        continue;
+      }
+
+      occupied_player_slots += 1;
+      if settings.color < 0 || settings.color >= 24 {
+        return invalid("color out of range");
+      }
+      if color_set[settings.color as usize] {
+        return invalid("duplicate color");
+      }
+      color_set[settings.color as usize] = true;
+    }
+
+    if occupied_player_slots > self.map_players {
+      return Err(Error::GameSlotsInvalid(format!(
+        "{} player slots occupied, map has {} players",
+        occupied_player_slots, self.map_players
+      )));
     }
 
-    Some(updated_slots)
+    Ok(())
+  }
+
+  /// Slots loaded with [`Slots::from_used`] are repaired first,
+  /// violations are only fatal in tests
+  #[inline]
+  fn debug_validate(&self) {
+    if cfg!(debug_assertions) {
+      if let Err(err) = self.validate() {
+        if cfg!(test) {
+          panic!("{}: {:?}", err, self.inner);
+        }
+        tracing::error!("{}: {:?}", err, self.inner);
+      }
+    }
   }
 
   fn get_color_set(&self) -> [bool; 24] {
@@ -412,3 +1172,525 @@ impl UsedSlotInfo {
     )
   }
 }
+
+#[cfg(test)]
+pub(crate) fn test_player(id: i32) -> PlayerRef {
+  use crate::player::PlayerSource;
+  PlayerRef {
+    id,
+    name: format!("player{}", id),
+    source: PlayerSource::Test,
+    realm: None,
+    clan_tag: None,
+  }
+}
+
+#[cfg(test)]
+pub(crate) fn test_map(name: &str, players: usize) -> Map {
+  use crate::map::{MapPlayer, MapSha1};
+  Map {
+    sha1: MapSha1([0; 20]),
+    checksum: 0,
+    name: name.to_string(),
+    description: String::new(),
+    author: String::new(),
+    path: format!("Maps/{}.w3x", name),
+    width: 64,
+    height: 64,
+    players: (0..players)
+      .map(|idx| MapPlayer {
+        name: format!("Player {}", idx + 1),
+        r#type: 1,
+        race: 0,
+        flags: 0,
+      })
+      .collect(),
+    forces: vec![],
+    flags: 0,
+  }
+}
+
+#[cfg(test)]
+pub(crate) fn test_game(id: i32, host: PlayerRef, map_players: usize) -> crate::game::Game {
+  use crate::game::{Game, GameMode, GameStatus};
+  use chrono::Utc;
+  let mut slots = Slots::new(map_players);
+  slots.join(&host);
+  Game {
+    id,
+    name: "test".to_string(),
+    status: GameStatus::Preparing,
+    map: test_map("test", map_players),
+    slots: slots.into_inner(),
+    node: None,
+    is_private: false,
+    secret: None,
+    is_live: false,
+    num_players: 1,
+    max_players: map_players as i32,
+    random_seed: 0,
+    created_by: host,
+    started_at: None,
+    ended_at: None,
+    created_at: Utc::now(),
+    updated_at: Utc::now(),
+    mask_player_names: false,
+    game_version: None,
+    game_mode: GameMode::Melee,
+    random_races: false,
+    random_teams: false,
+    equalize_ping: false,
+    step_min: None,
+    step_max: None,
+  }
+}
+
+#[cfg(test)]
+#[derive(Debug, Clone)]
+enum SlotOp {
+  Join(i32),
+  Leave(i32),
+  LeaveAll,
+  Update(i32, SlotSettings),
+  Close(i32, bool),
+  Shuffle(bool, bool),
+}
+
+#[cfg(test)]
+fn slot_op() -> impl proptest::strategy::Strategy<Value = SlotOp> {
+  use proptest::prelude::*;
+  use proptest::sample::select;
+
+  let settings = (
+    -1..26,
+    -1..26,
+    select(vec![Computer::Easy, Computer::Normal, Computer::Insane]),
+    40..110,
+    select(vec![
+      SlotStatus::Open,
+      SlotStatus::Closed,
+      SlotStatus::Occupied,
+    ]),
+    select(vec![
+      Race::Human,
+      Race::Orc,
+      Race::NightElf,
+      Race::Undead,
+      Race::Random,
+    ]),
+  )
+    .prop_map(
+      |(team, color, computer, handicap, status, race)| SlotSettings {
+        team,
+        color,
+        computer,
+        handicap,
+        status,
+        race,
+      },
+    );
+
+  prop_oneof![
+    (1..=12).prop_map(SlotOp::Join),
+    (1..=12).prop_map(SlotOp::Leave),
+    Just(SlotOp::LeaveAll),
+    (-1..25, settings).prop_map(|(slot_index, settings)| SlotOp::Update(slot_index, settings)),
+    (-1..25, any::<bool>()).prop_map(|(slot_index, closed)| SlotOp::Close(slot_index, closed)),
+    (any::<bool>(), any::<bool>()).prop_map(|(races, teams)| SlotOp::Shuffle(races, teams)),
+  ]
+}
+
+#[cfg(test)]
+proptest::proptest! {
+  #[test]
+  fn test_slots_invariants(
+    map_players in 1usize..=12,
+    reservations in proptest::collection::btree_map(0usize..24, 1..=12, 0..4),
+    ops in proptest::collection::vec(slot_op(), 1..64),
+  ) {
+    use proptest::prelude::*;
+
+    let mut slots = Slots::new(map_players);
+    slots.set_reservations(reservations);
+
+    for op in ops {
+      match op {
+        SlotOp::Join(player_id) => {
+          // callers reject players that are already in the game
+          if slots.find_player_slot(player_id).is_none() {
+            let joined = slots.join(&test_player(player_id)).is_some();
+            prop_assert_eq!(joined, slots.find_player_slot(player_id).is_some());
+          }
+        }
+        SlotOp::Leave(player_id) => {
+          let present = slots.find_player_slot(player_id).is_some();
+          prop_assert_eq!(slots.release_player_slot(player_id), present);
+          prop_assert!(slots.find_player_slot(player_id).is_none());
+        }
+        SlotOp::LeaveAll => {
+          slots.release_all_player_slots();
+          prop_assert!(slots.is_empty());
+        }
+        SlotOp::Update(slot_index, settings) => {
+          let player_ids = slots.get_player_ids().len();
+          slots.update_slot_at(slot_index, &settings);
+          prop_assert_eq!(slots.get_player_ids().len(), player_ids);
+        }
+        SlotOp::Close(slot_index, closed) => {
+          slots.set_slot_closed(slot_index, closed);
+        }
+        SlotOp::Shuffle(races, teams) => {
+          slots.shuffle(races, teams);
+        }
+      }
+
+      if let Err(err) = slots.validate() {
+        return Err(TestCaseError::fail(err.to_string()));
+      }
+    }
+  }
+}
+
+#[test]
+fn test_slots_validate_update() {
+  let mut slots = Slots::new(2);
+  slots.join(&test_player(1)).unwrap();
+  let settings = slots[0].settings.clone();
+  slots.validate_update(0, &settings).unwrap();
+
+  let invalid = |f: fn(&mut SlotSettings)| {
+    let mut settings = settings.clone();
+    f(&mut settings);
+    settings
+  };
+  assert!(matches!(
+    slots.validate_update(-1, &settings),
+    Err(Error::GameSlotIndexInvalid)
+  ));
+  assert!(matches!(
+    slots.validate_update(24, &settings),
+    Err(Error::GameSlotIndexInvalid)
+  ));
+  assert!(matches!(
+    slots.validate_update(0, &invalid(|s| s.team = 2)),
+    Err(Error::PlayerTeamInvalid)
+  ));
+  assert!(matches!(
+    slots.validate_update(0, &invalid(|s| s.color = 24)),
+    Err(Error::PlayerColorInvalid)
+  ));
+  assert!(matches!(
+    slots.validate_update(0, &invalid(|s| s.handicap = 40)),
+    Err(Error::PlayerHandicapInvalid)
+  ));
+  assert!(matches!(
+    slots.validate_update(0, &invalid(|s| s.status = SlotStatus::Closed)),
+    Err(Error::GameSlotStatusInvalid)
+  ));
+
+  // referees
+  slots.validate_update(0, &invalid(|s| s.team = 24)).unwrap();
+}
+
+#[test]
+fn test_team_layout() {
+  let layout: TeamLayout = "2v2".parse().unwrap();
+  assert_eq!(layout.teams(), 2);
+  assert_eq!(layout.team_size(1), Some(2));
+  assert_eq!(layout.team_size(2), None);
+  assert_eq!(layout.to_string(), "2v2");
+  layout.validate(4).unwrap();
+  assert!(layout.validate(3).is_err());
+
+  assert_eq!("1v1v1v1".parse::<TeamLayout>().unwrap().teams(), 4);
+  assert!("2".parse::<TeamLayout>().is_err());
+  assert!("0v2".parse::<TeamLayout>().is_err());
+  assert!("2vx".parse::<TeamLayout>().is_err());
+}
+
+#[test]
+fn test_slots_team_layout() {
+  let mut slots = Slots::new(6);
+  slots.set_team_layout(Some("2v2".parse().unwrap()));
+
+  for id in 1..=5 {
+    slots.join(&test_player(id)).unwrap();
+  }
+  let teams: Vec<_> = slots.iter().take(5).map(|s| s.settings.team).collect();
+  assert_eq!(teams, vec![0, 1, 0, 1, 24]);
+
+  let mut settings = slots[0].settings.clone();
+  settings.team = 1;
+  assert!(matches!(
+    slots.validate_update(0, &settings),
+    Err(Error::GameTeamFull)
+  ));
+  settings.team = 2;
+  assert!(matches!(
+    slots.validate_update(0, &settings),
+    Err(Error::PlayerTeamInvalid)
+  ));
+
+  slots.release_player_slot(2);
+  let mut settings = slots[0].settings.clone();
+  settings.team = 1;
+  slots.validate_update(0, &settings).unwrap();
+  assert_eq!(slots.join(&test_player(6)).unwrap().settings.team, 1);
+}
+
+#[test]
+fn test_slots_join_referee_slot() {
+  let mut slots = Slots::new(2);
+  slots.set_slot_closed(1, true).unwrap();
+  slots.join(&test_player(1)).unwrap();
+  let slot = slots.join(&test_player(2)).unwrap();
+  assert_eq!(slot.settings.team, 24);
+
+  // released referee slots stay referee slots
+  assert!(slots.release_player_slot(2));
+  assert_eq!(slots[2].settings.team, 24);
+  slots.validate().unwrap();
+}
+
+#[test]
+fn test_slots_relayout() {
+  let with_computer = |players: i32| {
+    let mut slots = Slots::new(4);
+    for id in 1..=players {
+      slots.join(&test_player(id)).unwrap();
+    }
+    let mut computer = slots[3].settings.clone();
+    computer.status = SlotStatus::Occupied;
+    computer.computer = Computer::Insane;
+    computer.team = 3;
+    slots.update_slot_at(3, &computer).unwrap();
+    Slots::from_used(4, slots.as_used())
+  };
+
+  // the computer is moved to the next open player slot
+  let next = with_computer(2).relayout(&test_map("small", 3)).unwrap();
+  next.validate().unwrap();
+  assert_eq!(next.find_player_slot(1).unwrap().settings.color, 0);
+  assert_eq!(next.find_player_slot(2).unwrap().settings.color, 1);
+  assert_eq!(next[2].settings.status, SlotStatus::Occupied);
+  assert_eq!(next[2].settings.computer, Computer::Insane);
+  assert_eq!(next[2].settings.team, 0);
+  assert_eq!(next[2].settings.color, 2);
+
+  // slots fixed by the new map are not overwritten
+  let mut map = test_map("fixed", 3);
+  map.flags = 0x0020;
+  map.players[0].r#type = 2;
+  let next = with_computer(3).relayout(&map).unwrap();
+  next.validate().unwrap();
+  assert!(next[0].player.is_none());
+  assert_eq!(next[0].settings.computer, Computer::Normal);
+  let mut player_ids = next.get_player_ids();
+  player_ids.sort();
+  assert_eq!(player_ids, vec![1, 2, 3]);
+  assert_eq!(next.find_player_slot(3).unwrap().settings.team, 24);
+  // no player slot left for the computer
+  assert!(next.iter().all(|s| s.settings.computer != Computer::Insane));
+}
+
+#[test]
+fn test_slots_shuffle_fixed_map() {
+  use crate::map::MapForce;
+  let mut map = test_map("fixed", 4);
+  map.flags = 0x0020 | 0x0040;
+  map.players[0].race = 1;
+  map.players[1].race = 2;
+  map.forces = vec![
+    MapForce {
+      name: "Team 1".to_string(),
+      flags: 0,
+      player_set: 0b0011,
+    },
+    MapForce {
+      name: "Team 2".to_string(),
+      flags: 0,
+      player_set: 0b1100,
+    },
+  ];
+
+  let mut slots = Slots::from_map(&map);
+  for id in 1..=4 {
+    slots.join(&test_player(id)).unwrap();
+  }
+  for _ in 0..16 {
+    slots.shuffle(true, true);
+    let teams: Vec<_> = slots.iter().take(4).map(|s| s.settings.team).collect();
+    assert_eq!(teams, vec![0, 0, 1, 1]);
+    assert_eq!(slots[0].settings.race, Race::Human);
+    assert_eq!(slots[1].settings.race, Race::Orc);
+  }
+}
+
+#[test]
+fn test_slots_resolve_color_conflicts() {
+  let mut slots = Slots::new(4);
+  for id in 1..=3 {
+    slots.join(&test_player(id)).unwrap();
+  }
+  slots.inner[2].settings.color = 0;
+  assert_eq!(slots.resolve_color_conflicts(2).unwrap(), vec![0]);
+  assert_eq!(slots[0].settings.color, 2);
+  assert_eq!(slots[1].settings.color, 1);
+  assert_eq!(slots[2].settings.color, 0);
+  slots.validate().unwrap();
+
+  // referees and open slots don't have a color
+  assert!(slots.resolve_color_conflicts(-1).unwrap().is_empty());
+}
+
+#[test]
+fn test_slots_from_fixed_map() {
+  use crate::map::MapForce;
+  let mut map = test_map("fixed", 3);
+  map.flags = 0x0020 | 0x0040;
+  map.players[0].race = 3;
+  map.players[2].r#type = 2;
+  map.forces = vec![
+    MapForce {
+      name: "Team 1".to_string(),
+      flags: 0,
+      player_set: 0b011,
+    },
+    MapForce {
+      name: "Team 2".to_string(),
+      flags: 0,
+      player_set: 0b100,
+    },
+  ];
+
+  let mut slots = Slots::from_map(&map);
+  slots.validate().unwrap();
+  assert_eq!(slots[2].settings.status, SlotStatus::Occupied);
+  assert_eq!(slots[2].settings.computer, Computer::Normal);
+  assert_eq!(slots[2].settings.team, 1);
+
+  let slot = slots.join(&test_player(1)).unwrap();
+  assert_eq!(slot.settings.team, 0);
+  assert_eq!(slot.settings.race, Race::Undead);
+  assert_eq!(slots.join(&test_player(2)).unwrap().settings.team, 0);
+  // the computer takes the last player slot
+  assert_eq!(slots.join(&test_player(3)).unwrap().settings.team, 24);
+  slots.validate().unwrap();
+
+  let settings = slots[0].settings.clone();
+  slots.validate_update(0, &settings).unwrap();
+  let mut update = settings.clone();
+  update.team = 1;
+  assert!(matches!(
+    slots.validate_update(0, &update),
+    Err(Error::GameSlotTeamFixedByMap)
+  ));
+  let mut update = settings.clone();
+  update.race = Race::Orc;
+  assert!(matches!(
+    slots.validate_update(0, &update),
+    Err(Error::GameSlotRaceFixedByMap)
+  ));
+  let mut update = slots[2].settings.clone();
+  update.status = SlotStatus::Open;
+  assert!(matches!(
+    slots.validate_update(2, &update),
+    Err(Error::GameSlotFixedByMap)
+  ));
+}
+
+#[test]
+fn test_slots_uneven_team_layout() {
+  let mut slots = Slots::new(4);
+  slots.set_team_layout(Some("2v1".parse().unwrap()));
+
+  for id in 1..=4 {
+    slots.join(&test_player(id)).unwrap();
+  }
+  let teams: Vec<_> = slots.iter().take(4).map(|s| s.settings.team).collect();
+  assert_eq!(teams, vec![0, 1, 0, 24]);
+  slots.validate().unwrap();
+
+  // the single player team is full
+  let mut settings = slots[0].settings.clone();
+  settings.team = 1;
+  assert!(matches!(
+    slots.validate_update(0, &settings),
+    Err(Error::GameTeamFull)
+  ));
+
+  slots.release_player_slot(2);
+  assert_eq!(slots.join(&test_player(5)).unwrap().settings.team, 1);
+}
+
+#[test]
+fn test_slots_leave_reserve() {
+  let mut slots = Slots::new(3);
+  slots.join(&test_player(1)).unwrap();
+  assert!(matches!(
+    slots.check_join(1),
+    Err(Error::PlayerAlreadyInGame)
+  ));
+
+  // a player has at most one reserved slot
+  slots.reserve(1, Some(2)).unwrap();
+  slots.reserve(2, Some(2)).unwrap();
+  assert_eq!(slots.reservations().get(&2), Some(&2));
+  assert_eq!(slots.reservations().len(), 1);
+  assert!(matches!(
+    slots.reserve(3, Some(3)),
+    Err(Error::GameSlotUpdateDenied)
+  ));
+
+  slots.join(&test_player(2)).unwrap();
+  slots.join(&test_player(3)).unwrap();
+  assert_eq!(slots[2].player.as_ref().map(|p| p.id), Some(2));
+
+  let settings = slots[2].settings.clone();
+  assert!(matches!(
+    slots.update_slot_as(3, 1, 2, &settings),
+    Err(Error::GameSlotUpdateDenied)
+  ));
+  slots.update_slot_as(1, 1, 2, &settings).unwrap();
+
+  assert_eq!(slots.leave(2, 1), (vec![2], false));
+  assert_eq!(slots.leave(2, 1), (vec![], false));
+  assert_eq!(slots.leave(1, 1), (vec![1, 3], true));
+}
+
+#[test]
+fn test_slots_repair() {
+  use crate::game::SlotClientStatus;
+
+  let player = |id: i32, team: i32, color: i32| {
+    let settings = SlotSettings {
+      team,
+      color,
+      status: SlotStatus::Occupied,
+      ..Default::default()
+    };
+    (settings, Some(test_player(id)))
+  };
+  let used = vec![
+    player(1, 0, 0),
+    player(2, 1, 0),
+    player(1, 1, 1),
+    player(3, 5, 2),
+  ]
+  .into_iter()
+  .enumerate()
+  .map(|(idx, (settings, player))| UsedSlot {
+    slot_index: idx as i32,
+    settings,
+    client_status: SlotClientStatus::Pending,
+    player,
+  })
+  .collect();
+
+  // loaded slots with duplicate colors and players and an out of range team
+  let slots = Slots::from_used(3, used);
+  slots.validate().unwrap();
+  assert_eq!(slots.get_player_ids(), vec![1, 2, 3]);
+  assert_eq!(slots[1].settings.color, 1);
+  assert_eq!(slots[3].settings.team, 24);
+}
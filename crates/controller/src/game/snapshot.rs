@@ -0,0 +1,173 @@
+use crate::game::db::{GameStatusFilter, QueryGame, QueryGameParams};
+use crate::game::{GameEntry, GameStatus};
+use arc_swap::ArcSwap;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+const DEFAULT_TAKE: i64 = 30;
+const MAX_TAKE: i64 = 100;
+
+/// Lock-free copy of the list entries of active games, updated by each [`GameActor`]
+/// after its mutations so that listing games never waits for a lobby write.
+///
+/// [`GameActor`]: crate::game::state::GameActor
+#[derive(Debug, Clone)]
+pub struct GameListSnapshot {
+  games: Arc<ArcSwap<BTreeMap<i32, GameEntry>>>,
+}
+
+impl GameListSnapshot {
+  pub fn new() -> Self {
+    Self {
+      games: Arc::new(ArcSwap::new(Arc::new(BTreeMap::new()))),
+    }
+  }
+
+  pub fn replace_all(&self, entries: Vec<GameEntry>) {
+    let map = entries.into_iter().map(|entry| (entry.id, entry)).collect();
+    self.games.store(Arc::new(map));
+  }
+
+  /// Inserts or replaces the entry, ended games are removed
+  pub fn update(&self, entry: GameEntry) {
+    if entry.status == GameStatus::Ended || entry.status == GameStatus::Terminated {
+      return self.remove(entry.id);
+    }
+    self.games.rcu(|games| {
+      let mut games = BTreeMap::clone(games);
+      games.insert(entry.id, entry.clone());
+      games
+    });
+  }
+
+  pub fn remove(&self, game_id: i32) {
+    if !self.games.load().contains_key(&game_id) {
+      return;
+    }
+    self.games.rcu(|games| {
+      let mut games = BTreeMap::clone(games);
+      games.remove(&game_id);
+      games
+    });
+  }
+
+  /// Same result as [`crate::game::db::query`] for the filters of active games.
+  /// Returns `None` for `All` and `Ended`, which list terminated or ended games,
+  /// these are only stored in the database.
+  pub fn query(&self, params: &QueryGameParams) -> Option<QueryGame> {
+    match params.status {
+      GameStatusFilter::All | GameStatusFilter::Ended => return None,
+      GameStatusFilter::Open | GameStatusFilter::Live | GameStatusFilter::Active => {}
+    }
+
+    let take = std::cmp::min(MAX_TAKE, params.take.unwrap_or(DEFAULT_TAKE)) as usize;
+    let keyword = params.keyword.as_ref().map(|v| v.trim().to_lowercase());
+    let is_private = params.is_private.unwrap_or(false);
+
+    let games = self.games.load();
+    let mut iter = games
+      .values()
+      .rev()
+      .filter(|entry| params.since_id.map(|id| entry.id < id).unwrap_or(true))
+      .filter(|entry| match params.status {
+        GameStatusFilter::Open => entry.status == GameStatus::Preparing,
+        GameStatusFilter::Live => entry.status == GameStatus::Running && !entry.is_private,
+        _ => true,
+      })
+      .filter(|entry| entry.is_private == is_private)
+      .filter(|entry| params.is_live.map(|v| entry.is_live == v).unwrap_or(true))
+      .filter(|entry| {
+        keyword
+          .as_ref()
+          .map(|keyword| {
+            entry.name.to_lowercase().contains(keyword)
+              || entry.map_name.to_lowercase().contains(keyword)
+          })
+          .unwrap_or(true)
+      });
+
+    let games: Vec<GameEntry> = iter.by_ref().take(take).cloned().collect();
+    let has_more = iter.next().is_some();
+
+    Some(QueryGame { games, has_more })
+  }
+}
+
+#[test]
+fn test_game_list_snapshot() {
+  use chrono::Utc;
+
+  let entry = |id: i32, name: &str, status: GameStatus, is_private: bool| GameEntry {
+    id,
+    name: name.to_string(),
+    map_name: "(2)BootyBay.w3m".to_string(),
+    status,
+    is_private,
+    is_live: false,
+    num_players: 1,
+    max_players: 2,
+    started_at: None,
+    ended_at: None,
+    created_at: Utc::now(),
+    updated_at: Utc::now(),
+    node: None,
+    created_by: None,
+  };
+  let ids = |r: QueryGame| -> Vec<i32> { r.games.into_iter().map(|g| g.id).collect() };
+
+  let snapshot = GameListSnapshot::new();
+  snapshot.replace_all(vec![
+    entry(1, "1v1 me", GameStatus::Preparing, false),
+    entry(2, "ladder", GameStatus::Running, false),
+    entry(3, "private", GameStatus::Preparing, true),
+  ]);
+  snapshot.update(entry(4, "FFA", GameStatus::Preparing, false));
+
+  let active = || QueryGameParams {
+    status: GameStatusFilter::Active,
+    ..Default::default()
+  };
+  let r = snapshot.query(&active()).unwrap();
+  assert_eq!(ids(r), vec![4, 2, 1]);
+
+  let r = snapshot
+    .query(&QueryGameParams {
+      status: GameStatusFilter::Open,
+      take: Some(1),
+      ..Default::default()
+    })
+    .unwrap();
+  assert!(r.has_more);
+  assert_eq!(ids(r), vec![4]);
+
+  let r = snapshot
+    .query(&QueryGameParams {
+      keyword: Some(" BOOTY ".to_string()),
+      since_id: Some(4),
+      ..active()
+    })
+    .unwrap();
+  assert_eq!(ids(r), vec![2, 1]);
+
+  let r = snapshot
+    .query(&QueryGameParams {
+      is_private: Some(true),
+      ..active()
+    })
+    .unwrap();
+  assert_eq!(ids(r), vec![3]);
+
+  snapshot.update(entry(2, "ladder", GameStatus::Ended, false));
+  snapshot.update(entry(1, "1v1 me", GameStatus::Terminated, false));
+  snapshot.remove(4);
+  let r = snapshot.query(&active()).unwrap();
+  assert!(r.games.is_empty());
+
+  assert!(snapshot.query(&QueryGameParams::default()).is_none());
+  assert!(snapshot
+    .query(&QueryGameParams {
+      status: GameStatusFilter::Ended,
+      ..Default::default()
+    })
+    .is_none());
+}
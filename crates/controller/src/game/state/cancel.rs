@@ -1,8 +1,7 @@
+use crate::db::ExecutorExt;
 use crate::error::*;
 use crate::game::state::GameActor;
 
-use crate::player::state::sender::PlayerFrames;
-
 use flo_net::packet::FloPacket;
 
 use flo_state::{async_trait, Context, Handler, Message};
@@ -26,20 +25,18 @@ impl Handler<CancelGame> for GameActor {
 
     self
       .db
-      .exec(move |conn| crate::game::db::cancel(conn, game_id, player_id))
+      .exec_traced(move |conn| crate::game::db::cancel(conn, game_id, player_id))
       .await
       .map_err(Error::from)?;
+    self.lobby.invalidate(game_id);
 
     self
       .player_reg
       .players_leave_game(self.players.clone(), game_id)
       .await?;
 
-    let packet_iter = self
-      .players
-      .iter()
-      .cloned()
-      .map(|player_id| {
+    self
+      .broadcast_to_game(|player_id| {
         use flo_net::proto::flo_connect::*;
         let frame_left = PacketGamePlayerLeave {
           game_id,
@@ -47,13 +44,9 @@ impl Handler<CancelGame> for GameActor {
           reason: PlayerLeaveReason::GameCancelled.into(),
         }
         .encode_as_frame()?;
-
-        Ok((player_id, PlayerFrames::from(frame_left)))
+        Ok(Some(frame_left.into()))
       })
-      .collect::<Result<Vec<_>>>()?
-      .into_iter();
-
-    self.player_reg.broadcast_map(packet_iter).await?;
+      .await?;
 
     Ok(())
   }
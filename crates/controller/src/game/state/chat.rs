@@ -0,0 +1,242 @@
+//! Lobby chat, relayed by the controller so the host can moderate it.
+//!
+//! Host commands:
+//! - `/mute <player>`: drops the chat messages of the player for everyone
+//! - `/unmute <player>`
+//! - `/clear`: clears the chat of all lobby players
+//!
+//! Players muted by an admin (chat ban) can't send messages either.
+//!
+//! Being muted and sending messages while muted count as offenses, players reaching
+//! [`ESCALATION_THRESHOLD`] are reported to moderators, see [`crate::player::report`],
+//! and with a [`ControllerEventType::PlayerChatEscalated`] event.
+
+use crate::db::ExecutorExt;
+use crate::error::*;
+use crate::event::{ControllerEvent, ControllerEventType};
+use crate::game::state::GameActor;
+use crate::game::GameStatus;
+use crate::player::report::{CreatePlayerReportParams, PlayerReportCategory};
+use crate::player::PlayerBanType;
+use flo_net::packet::FloPacket;
+use flo_net::proto::flo_connect::{PacketGameChat, PacketGameChatClear};
+use flo_state::{async_trait, Context, Handler, Message};
+use std::collections::{BTreeMap, BTreeSet};
+
+const MAX_MESSAGE_LEN: usize = 256;
+const ESCALATION_THRESHOLD: u32 = 3;
+
+#[derive(Debug, Default)]
+pub struct LobbyChatState {
+  muted_player_ids: BTreeSet<i32>,
+  offenses: BTreeMap<i32, u32>,
+  escalated_player_ids: BTreeSet<i32>,
+}
+
+impl LobbyChatState {
+  pub fn is_muted(&self, player_id: i32) -> bool {
+    self.muted_player_ids.contains(&player_id)
+  }
+
+  /// Returns `true` when the player reaches the escalation threshold, only once per player
+  fn add_offense(&mut self, player_id: i32) -> bool {
+    let count = self.offenses.entry(player_id).or_default();
+    *count += 1;
+    *count >= ESCALATION_THRESHOLD && self.escalated_player_ids.insert(player_id)
+  }
+}
+
+#[derive(Debug, PartialEq)]
+enum ChatCommand<'a> {
+  Message(&'a str),
+  Mute(&'a str),
+  Unmute(&'a str),
+  Clear,
+}
+
+impl<'a> ChatCommand<'a> {
+  fn parse(text: &'a str) -> Result<Self> {
+    let text = text.trim();
+    if !text.starts_with('/') {
+      return Ok(ChatCommand::Message(text));
+    }
+    let (cmd, arg) = match text.find(char::is_whitespace) {
+      Some(pos) => (&text[..pos], text[pos..].trim()),
+      None => (text, ""),
+    };
+    match (cmd, arg) {
+      ("/mute", name) if !name.is_empty() => Ok(ChatCommand::Mute(name)),
+      ("/unmute", name) if !name.is_empty() => Ok(ChatCommand::Unmute(name)),
+      ("/clear", "") => Ok(ChatCommand::Clear),
+      _ => Err(Error::ChatCommandInvalid),
+    }
+  }
+}
+
+pub struct SendChat {
+  pub player_id: i32,
+  pub message: String,
+}
+
+impl Message for SendChat {
+  type Result = Result<()>;
+}
+
+#[async_trait]
+impl Handler<SendChat> for GameActor {
+  async fn handle(
+    &mut self,
+    _: &mut Context<Self>,
+    SendChat { player_id, message }: SendChat,
+  ) -> Result<()> {
+    if !self.players.contains(&player_id) {
+      return Err(Error::PlayerNotInGame);
+    }
+
+    if self.status != GameStatus::Preparing || self.started() {
+      return Err(Error::GameStarted);
+    }
+
+    self.touch_player(player_id);
+
+    let game_id = self.game_id;
+    match ChatCommand::parse(&message)? {
+      ChatCommand::Message(text) => {
+        if text.is_empty() {
+          return Ok(());
+        }
+        if self.chat.is_muted(player_id) {
+          tracing::debug!(game_id, player_id, "muted chat message dropped");
+          self.add_chat_offense(player_id).await;
+          return Ok(());
+        }
+        if self.is_chat_banned(player_id).await? {
+          tracing::debug!(game_id, player_id, "chat banned message dropped");
+          return Ok(());
+        }
+        let frame = PacketGameChat {
+          game_id,
+          player_id,
+          message: text.chars().take(MAX_MESSAGE_LEN).collect(),
+        }
+        .encode_as_frame()?;
+        self
+          .player_reg
+          .broadcast(self.players.clone(), frame)
+          .await?;
+      }
+      ChatCommand::Mute(name) => {
+        let target_player_id = self.resolve_chat_target(player_id, name).await?;
+        if self.chat.muted_player_ids.insert(target_player_id) {
+          tracing::info!(game_id, player_id = target_player_id, "muted by host");
+          self.add_chat_offense(target_player_id).await;
+        }
+      }
+      ChatCommand::Unmute(name) => {
+        let target_player_id = self.resolve_chat_target(player_id, name).await?;
+        self.chat.muted_player_ids.remove(&target_player_id);
+      }
+      ChatCommand::Clear => {
+        if player_id != self.host_player {
+          return Err(Error::PlayerNotHost);
+        }
+        let frame = PacketGameChatClear { game_id }.encode_as_frame()?;
+        self
+          .player_reg
+          .broadcast(self.players.clone(), frame)
+          .await?;
+      }
+    }
+
+    Ok(())
+  }
+}
+
+impl GameActor {
+  /// Finds the lobby player targeted by a host command
+  async fn resolve_chat_target(&self, player_id: i32, name: &str) -> Result<i32> {
+    let host_player_id = self.host_player;
+    if player_id != host_player_id {
+      return Err(Error::PlayerNotHost);
+    }
+    let game = self.lobby.get(self.game_id).await?;
+    game
+      .slots
+      .iter()
+      .filter_map(|slot| slot.player.as_ref())
+      .find(|player| player.id != host_player_id && player.name.eq_ignore_ascii_case(name))
+      .map(|player| player.id)
+      .ok_or_else(|| Error::PlayerNotFound)
+  }
+
+  /// Global mutes imposed by admins, see [`crate::player::PlayerBanType::Chat`]
+  async fn is_chat_banned(&self, player_id: i32) -> Result<bool> {
+    let mut ban_list_map = self
+      .db
+      .exec_traced(move |conn| crate::player::db::get_ban_list_map(conn, &[player_id]))
+      .await?;
+    Ok(
+      ban_list_map
+        .remove(&player_id)
+        .map(|bans| bans.contains(&PlayerBanType::Chat))
+        .unwrap_or_default(),
+    )
+  }
+
+  async fn add_chat_offense(&mut self, player_id: i32) {
+    if !self.chat.add_offense(player_id) {
+      return;
+    }
+    let game_id = self.game_id;
+    tracing::info!(game_id, player_id, "chat offenses escalated");
+    self.events.publish(ControllerEvent::player(
+      ControllerEventType::PlayerChatEscalated,
+      player_id,
+      Some(game_id),
+    ));
+    let params = CreatePlayerReportParams {
+      reporter_player_id: None,
+      target_player_id: player_id,
+      game_id: Some(game_id),
+      category: PlayerReportCategory::Chat,
+      message: format!("Muted in lobby, {} chat offenses", ESCALATION_THRESHOLD),
+    };
+    if let Err(err) = self
+      .db
+      .exec_traced(move |conn| crate::player::report::create(conn, params))
+      .await
+    {
+      tracing::warn!(game_id, player_id, "create chat escalation report: {}", err);
+    }
+  }
+}
+
+#[test]
+fn test_chat_command_parse() {
+  assert_eq!(
+    ChatCommand::parse(" hello ").unwrap(),
+    ChatCommand::Message("hello")
+  );
+  assert_eq!(
+    ChatCommand::parse("/mute  Some Player ").unwrap(),
+    ChatCommand::Mute("Some Player")
+  );
+  assert_eq!(
+    ChatCommand::parse("/unmute player").unwrap(),
+    ChatCommand::Unmute("player")
+  );
+  assert_eq!(ChatCommand::parse("/clear").unwrap(), ChatCommand::Clear);
+  assert!(ChatCommand::parse("/mute").is_err());
+  assert!(ChatCommand::parse("/clear all").is_err());
+  assert!(ChatCommand::parse("/kick player").is_err());
+}
+
+#[test]
+fn test_chat_offense_escalation() {
+  let mut state = LobbyChatState::default();
+  assert!(!state.add_offense(1));
+  assert!(!state.add_offense(1));
+  assert!(state.add_offense(1));
+  assert!(!state.add_offense(1));
+  assert!(!state.add_offense(2));
+}
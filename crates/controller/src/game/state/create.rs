@@ -1,9 +1,13 @@
+use crate::db::ExecutorExt;
 use crate::error::{Error, Result};
 use crate::game::db::{CreateGameAsBotParams, CreateGameParams};
 use crate::game::state::registry::Register;
 use crate::game::state::GameRegistry;
 use crate::game::{Game, GameStatus};
+use flo_net::packet::FloPacket;
+use flo_net::proto::flo_connect::PacketGameInvite;
 use flo_state::{async_trait, Context, Handler, Message};
+use s2_grpc_utils::S2ProtoPack;
 
 pub struct CreateGame {
   pub params: CreateGameParams,
@@ -17,22 +21,25 @@ impl Message for CreateGame {
 impl Handler<CreateGame> for GameRegistry {
   async fn handle(
     &mut self,
-    _: &mut Context<Self>,
+    ctx: &mut Context<Self>,
     CreateGame { params }: CreateGame,
   ) -> <CreateGame as Message>::Result {
     let player_id = params.player_id;
     let game = self
       .db
-      .exec(move |conn| crate::game::db::create(conn, params))
+      .exec_traced(move |conn| crate::game::db::create(conn, params))
       .await?;
 
-    self.register(Register {
-      id: game.id,
-      status: GameStatus::Preparing,
-      host_player: game.created_by.id,
-      players: game.get_player_ids(),
-      node_id: None,
-    });
+    self.register(
+      ctx,
+      Register {
+        id: game.id,
+        status: GameStatus::Preparing,
+        host_player: game.created_by.id,
+        players: game.get_player_ids(),
+        node_id: None,
+      },
+    );
 
     self
       .players
@@ -57,7 +64,7 @@ impl Message for CreateGameAsBot {
 impl Handler<CreateGameAsBot> for GameRegistry {
   async fn handle(
     &mut self,
-    _: &mut Context<Self>,
+    ctx: &mut Context<Self>,
     CreateGameAsBot {
       api_client_id,
       api_player_id,
@@ -66,7 +73,7 @@ impl Handler<CreateGameAsBot> for GameRegistry {
   ) -> <CreateGameAsBot as Message>::Result {
     let (mut game, player_ids, mute_list_map) = self
       .db
-      .exec(move |conn| {
+      .exec_traced(move |conn| {
         let game = crate::game::db::create_as_bot(conn, api_client_id, api_player_id, params)?;
         let player_ids = game.get_player_ids();
         let mute_list_map = crate::player::db::get_mute_list_map(conn, &player_ids)?;
@@ -74,22 +81,18 @@ impl Handler<CreateGameAsBot> for GameRegistry {
       })
       .await?;
 
-    if game.mask_player_names {
-      for (idx, slot) in game.slots.iter_mut().enumerate() {
-        slot
-          .player
-          .as_mut()
-          .map(|v| v.name = format!("Player {}", idx + 1));
-      }
-    }
+    game.apply_player_name_mask();
 
-    self.register(Register {
-      id: game.id,
-      status: GameStatus::Preparing,
-      host_player: game.created_by.id,
-      players: player_ids.clone(),
-      node_id: game.node.as_ref().map(|v| v.id),
-    });
+    self.register(
+      ctx,
+      Register {
+        id: game.id,
+        status: GameStatus::Preparing,
+        host_player: game.created_by.id,
+        players: player_ids.clone(),
+        node_id: game.node.as_ref().map(|v| v.id),
+      },
+    );
 
     self
       .players
@@ -99,3 +102,73 @@ impl Handler<CreateGameAsBot> for GameRegistry {
     Ok(game)
   }
 }
+
+pub struct RehostGame {
+  pub game_id: i32,
+  pub player_id: i32,
+}
+
+pub struct RehostGameResult {
+  pub game: Game,
+  pub invited_player_ids: Vec<i32>,
+}
+
+impl Message for RehostGame {
+  type Result = Result<RehostGameResult>;
+}
+
+#[async_trait]
+impl Handler<RehostGame> for GameRegistry {
+  async fn handle(
+    &mut self,
+    ctx: &mut Context<Self>,
+    RehostGame { game_id, player_id }: RehostGame,
+  ) -> <RehostGame as Message>::Result {
+    let (game, invited_player_ids) = self
+      .db
+      .exec_traced(move |conn| crate::game::db::rehost(conn, game_id, player_id))
+      .await?;
+
+    self.register(
+      ctx,
+      Register {
+        id: game.id,
+        status: GameStatus::Preparing,
+        host_player: game.created_by.id,
+        players: game.get_player_ids(),
+        node_id: None,
+      },
+    );
+
+    self
+      .players
+      .player_replace_game(player_id, game.clone(), vec![])
+      .await?;
+
+    if !invited_player_ids.is_empty() {
+      let frame = PacketGameInvite {
+        game_id: game.id,
+        game_name: game.name.clone(),
+        host: Some(game.created_by.clone().pack()?),
+        previous_game_id: game_id,
+      }
+      .encode_as_frame()?;
+      self
+        .players
+        .broadcast(invited_player_ids.clone(), frame)
+        .await?;
+    }
+
+    tracing::info!(
+      game_id = game.id,
+      previous_game_id = game_id,
+      "game rehosted: {:?}",
+      invited_player_ids
+    );
+
+    Ok(RehostGameResult {
+      game,
+      invited_player_ids,
+    })
+  }
+}
@@ -0,0 +1,114 @@
+use crate::error::*;
+use crate::game::state::leave::{leave_game_lobby, PlayerLeaveResult};
+use crate::game::state::GameActor;
+use crate::game::GameStatus;
+use flo_net::proto::flo_connect::PlayerLeaveReason;
+use flo_state::{async_trait, Context, Handler, Message};
+use std::time::{Duration, Instant};
+
+/// Removes lobby players that have been disconnected and inactive for longer than `timeout`
+pub struct KickIdlePlayers {
+  pub timeout: Duration,
+}
+
+#[derive(Debug, Default)]
+pub struct KickIdlePlayersResult {
+  pub kicked_player_ids: Vec<i32>,
+  pub game_ended: bool,
+}
+
+impl Message for KickIdlePlayers {
+  type Result = Result<KickIdlePlayersResult>;
+}
+
+#[async_trait]
+impl Handler<KickIdlePlayers> for GameActor {
+  async fn handle(
+    &mut self,
+    _: &mut Context<Self>,
+    KickIdlePlayers { timeout }: KickIdlePlayers,
+  ) -> Result<KickIdlePlayersResult> {
+    let mut result = KickIdlePlayersResult::default();
+    if self.status != GameStatus::Preparing || self.started() {
+      return Ok(result);
+    }
+
+    let game_id = self.game_id;
+    let now = Instant::now();
+
+    // connected clients respond to keepalives
+    let online_player_ids = self
+      .player_reg
+      .get_online_players(self.players.clone())
+      .await?;
+    for player_id in online_player_ids {
+      self.player_activity.insert(player_id, now);
+    }
+
+    let activity = &mut self.player_activity;
+    let idle_player_ids: Vec<i32> = self
+      .players
+      .iter()
+      .cloned()
+      .filter(|player_id| {
+        // no activity recorded yet, e.g. after a controller restart
+        let last_activity = *activity.entry(*player_id).or_insert(now);
+        now.duration_since(last_activity) >= timeout
+      })
+      .collect();
+
+    for player_id in idle_player_ids {
+      self.player_activity.remove(&player_id);
+      self.players.retain(|id| *id != player_id);
+
+      match leave_game_lobby(self, game_id, player_id, PlayerLeaveReason::Timeout).await {
+        Ok(leave) => {
+          tracing::info!(game_id, player_id, "idle player removed");
+          result.kicked_player_ids.push(player_id);
+          if leave.game_ended {
+            result.game_ended = true;
+            break;
+          }
+        }
+        Err(err) => {
+          tracing::warn!(game_id, player_id, "remove idle player: {}", err);
+        }
+      }
+    }
+
+    Ok(result)
+  }
+}
+
+/// Removes a lobby player whose connection stopped answering heartbeats
+pub struct RemoveDeadPlayer {
+  pub player_id: i32,
+}
+
+impl Message for RemoveDeadPlayer {
+  type Result = Result<Option<PlayerLeaveResult>>;
+}
+
+#[async_trait]
+impl Handler<RemoveDeadPlayer> for GameActor {
+  async fn handle(
+    &mut self,
+    _: &mut Context<Self>,
+    RemoveDeadPlayer { player_id }: RemoveDeadPlayer,
+  ) -> Result<Option<PlayerLeaveResult>> {
+    // started games are left to the node, the player can still reconnect
+    if self.status != GameStatus::Preparing || self.started() {
+      return Ok(None);
+    }
+    if !self.players.contains(&player_id) {
+      return Ok(None);
+    }
+
+    let game_id = self.game_id;
+    self.player_activity.remove(&player_id);
+    self.players.retain(|id| *id != player_id);
+    let leave = leave_game_lobby(self, game_id, player_id, PlayerLeaveReason::Timeout).await?;
+    tracing::info!(game_id, player_id, "dead player removed");
+    Ok(Some(leave))
+  }
+}
@@ -1,7 +1,6 @@
 use crate::error::*;
 use crate::game::state::GameActor;
 use crate::game::Game;
-use diesel::prelude::*;
 use flo_net::packet::FloPacket;
 use flo_net::proto;
 use flo_state::{async_trait, Context, Handler, Message};
@@ -24,20 +23,10 @@ impl Handler<PlayerJoin> for GameActor {
     PlayerJoin { player_id }: PlayerJoin,
   ) -> Result<Game> {
     let game_id = self.game_id;
-    let (game, mute_list) = self
-      .db
-      .exec(move |conn| {
-        conn.transaction(|| {
-          crate::game::db::add_player(conn, game_id, player_id)?;
-          let game = crate::game::db::get_full(conn, game_id)?;
-          let mut mute_list_map =
-            crate::player::db::get_mute_list_map(conn, &game.get_player_ids())?;
-          Ok::<_, Error>((game, mute_list_map.remove(&player_id).unwrap_or_default()))
-        })
-      })
-      .await?;
+    let (game, mute_list) = self.lobby.join(game_id, player_id).await?;
 
     self.players.push(player_id);
+    self.touch_player(player_id);
 
     // send game info to joined player
     self
@@ -52,8 +41,6 @@ impl Handler<PlayerJoin> for GameActor {
       let player: proto::flo_connect::PlayerInfo = slot_info.player.clone().pack()?;
 
       // send notification to other players in this game
-      let mut players = game.get_player_ids();
-      players.retain(|id| *id != player_id);
       let frame = {
         use proto::flo_connect::*;
         PacketGamePlayerEnter {
@@ -68,9 +55,41 @@ impl Handler<PlayerJoin> for GameActor {
         }
       }
       .encode_as_frame()?;
-      self.player_reg.broadcast(players, frame).await?;
+      self
+        .broadcast_to_game(|id| Ok((id != player_id).then(|| frame.clone().into())))
+        .await?;
+    }
+
+    if let Err(err) = self.broadcast_slot_ping().await {
+      tracing::warn!(game_id, "broadcast slot ping: {}", err);
     }
 
     Ok(game)
   }
 }
+
+/// Reattaches a player reconnecting to a game they joined,
+/// e.g. after a client restart
+pub struct PlayerRejoin {
+  pub player_id: i32,
+}
+
+impl Message for PlayerRejoin {
+  type Result = Result<()>;
+}
+
+#[async_trait]
+impl Handler<PlayerRejoin> for GameActor {
+  async fn handle(
+    &mut self,
+    _: &mut Context<Self>,
+    PlayerRejoin { player_id }: PlayerRejoin,
+  ) -> Result<()> {
+    if !self.players.contains(&player_id) {
+      tracing::debug!(game_id = self.game_id, player_id, "rejoin: player restored");
+      self.players.push(player_id);
+    }
+    self.touch_player(player_id);
+    Ok(())
+  }
+}
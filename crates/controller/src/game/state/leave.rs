@@ -2,14 +2,12 @@ use crate::error::*;
 use crate::game::state::GameActor;
 use crate::game::{GameStatus, SlotClientStatus};
 use crate::node::{messages as node_messages, PlayerLeaveResponse};
-use crate::player::state::sender::PlayerFrames;
 use crate::state::ActorMapExt;
-use diesel::prelude::*;
 use flo_net::packet::FloPacket;
 use flo_net::proto;
+use flo_net::proto::flo_connect::PlayerLeaveReason;
 use flo_state::{async_trait, Context, Handler, Message};
 use s2_grpc_utils::S2ProtoEnum;
-use std::collections::BTreeMap;
 
 pub struct PlayerLeave {
   pub player_id: i32,
@@ -33,7 +31,9 @@ impl Handler<PlayerLeave> for GameActor {
   ) -> Result<PlayerLeaveResult> {
     let game_id = self.game_id;
     let result = match self.status {
-      GameStatus::Preparing => leave_game_lobby(self, game_id, player_id).await?,
+      GameStatus::Preparing => {
+        leave_game_lobby(self, game_id, player_id, PlayerLeaveReason::Left).await?
+      }
       GameStatus::Created | GameStatus::Running | GameStatus::Paused => {
         if let Some(node_id) = self.selected_node_id.clone() {
           leave_game_abort(self, game_id, player_id, node_id).await?
@@ -66,15 +66,13 @@ impl Handler<PlayerLeave> for GameActor {
 }
 
 #[tracing::instrument(skip(state))]
-async fn leave_game_lobby(
+pub(super) async fn leave_game_lobby(
   state: &mut GameActor,
   game_id: i32,
   player_id: i32,
+  reason: PlayerLeaveReason,
 ) -> Result<PlayerLeaveResult> {
-  let leave = state
-    .db
-    .exec(move |conn| crate::game::db::remove_player(conn, game_id, player_id))
-    .await?;
+  let leave = state.lobby.leave_lobby(game_id, player_id).await?;
 
   let recipient_player_ids: Vec<i32> = leave
     .slots
@@ -89,9 +87,23 @@ async fn leave_game_lobby(
     leave.game_ended,
     &leave.removed_players,
     &recipient_player_ids,
+    reason,
   )
   .await?;
 
+  let vote_frame = match state.map_vote.as_mut() {
+    Some(vote) if vote.remove_voter(player_id) && !leave.game_ended => {
+      Some(vote.to_packet(game_id).encode_as_frame()?)
+    }
+    _ => None,
+  };
+  if let Some(frame) = vote_frame {
+    state
+      .player_reg
+      .broadcast(recipient_player_ids, frame)
+      .await?;
+  }
+
   Ok(PlayerLeaveResult {
     game_ended: leave.game_ended,
   })
@@ -105,15 +117,7 @@ async fn leave_game_abort(
   player_id: i32,
   node_id: i32,
 ) -> Result<PlayerLeaveResult> {
-  let active_player_ids = state
-    .db
-    .exec(move |conn| {
-      conn.transaction(|| {
-        crate::game::db::leave_node(conn, game_id, player_id)?;
-        crate::game::db::get_node_active_player_ids(conn, game_id)
-      })
-    })
-    .await?;
+  let active_player_ids = state.lobby.leave_node(game_id, player_id).await?;
 
   let res = state
     .nodes
@@ -181,6 +185,7 @@ async fn leave_game_abort(
     false, // only change game status by node packet
     &[player_id],
     &active_player_ids,
+    PlayerLeaveReason::Left,
   )
   .await?;
 
@@ -194,6 +199,7 @@ async fn broadcast(
   ended: bool,
   left_players: &[i32],
   recipient_players: &[i32],
+  reason: PlayerLeaveReason,
 ) -> Result<()> {
   if ended {
     state
@@ -201,8 +207,6 @@ async fn broadcast(
       .players_leave_game(left_players.to_vec(), game_id)
       .await?;
   } else {
-    let mut frame_map = BTreeMap::<i32, PlayerFrames>::new();
-
     state
       .player_reg
       .player_leave_game(player_id, game_id)
@@ -211,15 +215,19 @@ async fn broadcast(
     let frame_player_leave = proto::flo_connect::PacketGamePlayerLeave {
       game_id,
       player_id,
-      reason: proto::flo_connect::PlayerLeaveReason::Left.into(),
+      reason: reason.into(),
     }
     .encode_as_frame()?;
 
-    for id in recipient_players {
-      frame_map.insert(*id, frame_player_leave.clone().into());
-    }
-
-    state.player_reg.broadcast_map(frame_map).await?;
+    state
+      .broadcast_to_game(|id| {
+        Ok(
+          recipient_players
+            .contains(&id)
+            .then(|| frame_player_leave.clone().into()),
+        )
+      })
+      .await?;
   }
   Ok(())
 }
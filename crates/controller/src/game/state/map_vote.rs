@@ -0,0 +1,222 @@
+use crate::db::ExecutorExt;
+use crate::error::*;
+use crate::game::state::GameActor;
+use crate::game::GameEntry;
+use crate::map::Map;
+use flo_net::packet::FloPacket;
+use flo_net::proto;
+use flo_state::{async_trait, Context, Handler, Message};
+use std::collections::BTreeMap;
+
+#[derive(Debug)]
+pub struct MapVoteState {
+  options: Vec<Map>,
+  votes: BTreeMap<i32, usize>,
+}
+
+impl MapVoteState {
+  /// Returns the option with the most votes,
+  /// ties are resolved in favor of the earlier proposed map.
+  pub fn winner(self) -> Option<Map> {
+    let mut counts = vec![0_usize; self.options.len()];
+    for index in self.votes.values() {
+      counts[*index] += 1;
+    }
+    let mut winner: Option<(usize, usize)> = None;
+    for (index, count) in counts.into_iter().enumerate() {
+      if count > 0 && winner.map(|(_, max)| count > max).unwrap_or(true) {
+        winner = Some((index, count));
+      }
+    }
+    let (index, _) = winner?;
+    self.options.into_iter().nth(index)
+  }
+
+  /// Removes the vote of a player who left the game, returns `true` if the tally changed
+  pub fn remove_voter(&mut self, player_id: i32) -> bool {
+    self.votes.remove(&player_id).is_some()
+  }
+
+  pub(crate) fn to_packet(&self, game_id: i32) -> proto::flo_connect::PacketGameMapVote {
+    proto::flo_connect::PacketGameMapVote {
+      game_id,
+      options: self
+        .options
+        .iter()
+        .enumerate()
+        .map(|(index, map)| proto::flo_connect::MapVoteOption {
+          name: map.name.clone(),
+          map: Some(proto::flo_connect::Map {
+            sha1: map.sha1.to_vec(),
+            checksum: map.checksum,
+            path: map.path.clone(),
+          }),
+          player_ids: self
+            .votes
+            .iter()
+            .filter_map(|(player_id, v)| if *v == index { Some(*player_id) } else { None })
+            .collect(),
+        })
+        .collect(),
+    }
+  }
+}
+
+pub struct StartMapVote {
+  pub player_id: i32,
+  pub maps: Vec<Map>,
+}
+
+impl Message for StartMapVote {
+  type Result = Result<()>;
+}
+
+#[async_trait]
+impl Handler<StartMapVote> for GameActor {
+  async fn handle(
+    &mut self,
+    _: &mut Context<Self>,
+    StartMapVote { player_id, maps }: StartMapVote,
+  ) -> Result<()> {
+    if self.host_player != player_id {
+      return Err(Error::PlayerNotHost);
+    }
+
+    if self.started() {
+      return Err(Error::GameStarted);
+    }
+
+    if maps.is_empty() {
+      return Err(Error::MapVoteOptionInvalid);
+    }
+
+    if maps.iter().any(|map| map.players.is_empty()) {
+      return Err(Error::MapHasNoPlayer);
+    }
+
+    let game_id = self.game_id;
+    let maps = self
+      .db
+      .exec_traced(move |conn| {
+        crate::game::db::validate_map_vote_options(conn, game_id, &maps)?;
+        Ok::<_, Error>(maps)
+      })
+      .await?;
+
+    let state = MapVoteState {
+      options: maps,
+      votes: BTreeMap::new(),
+    };
+    let frame = state.to_packet(self.game_id).encode_as_frame()?;
+    self.map_vote.replace(state);
+
+    self
+      .player_reg
+      .broadcast(self.players.clone(), frame)
+      .await?;
+
+    Ok(())
+  }
+}
+
+pub struct CastMapVote {
+  pub player_id: i32,
+  pub option_index: i32,
+}
+
+impl Message for CastMapVote {
+  type Result = Result<()>;
+}
+
+#[async_trait]
+impl Handler<CastMapVote> for GameActor {
+  async fn handle(
+    &mut self,
+    _: &mut Context<Self>,
+    CastMapVote {
+      player_id,
+      option_index,
+    }: CastMapVote,
+  ) -> Result<()> {
+    if !self.players.contains(&player_id) {
+      return Err(Error::PlayerNotInGame);
+    }
+
+    if self.started() {
+      return Err(Error::GameStarted);
+    }
+
+    self.touch_player(player_id);
+
+    let game_id = self.game_id;
+    let frame = {
+      let state = self
+        .map_vote
+        .as_mut()
+        .ok_or_else(|| Error::MapVoteNotFound)?;
+      if option_index < 0 || option_index as usize >= state.options.len() {
+        return Err(Error::MapVoteOptionInvalid);
+      }
+      state.votes.insert(player_id, option_index as usize);
+      state.to_packet(game_id).encode_as_frame()?
+    };
+
+    self
+      .player_reg
+      .broadcast(self.players.clone(), frame)
+      .await?;
+
+    Ok(())
+  }
+}
+
+impl GameActor {
+  /// Applies the winning map of the current vote, if any,
+  /// and sends the updated game to all players.
+  pub(crate) async fn apply_map_vote(&mut self) -> Result<()> {
+    let game_id = self.game_id;
+
+    let map = match self.map_vote.take().and_then(|state| state.winner()) {
+      Some(map) => map,
+      None => return Ok(()),
+    };
+
+    tracing::info!(game_id, "map vote winner: {}", map.name);
+
+    self.lobby.flush(game_id).await?;
+
+    let (game, mute_list_map) = self
+      .db
+      .exec_traced(move |conn| {
+        let game = crate::game::db::update_map(conn, game_id, map)?;
+        let mute_list_map = crate::player::db::get_mute_list_map(conn, &game.get_player_ids())?;
+        Ok::<_, Error>((game, mute_list_map))
+      })
+      .await?;
+    self.lobby.invalidate(game_id);
+    self.game_list.update(GameEntry::from(&game));
+
+    self
+      .player_reg
+      .players_replace_game(self.players.clone(), game, mute_list_map)
+      .await?;
+
+    Ok(())
+  }
+}
+
+#[test]
+fn test_map_vote_remove_voter() {
+  use crate::game::slots::test_map;
+  let mut state = MapVoteState {
+    options: vec![test_map("a", 2), test_map("b", 2)],
+    votes: BTreeMap::new(),
+  };
+  state.votes.insert(1, 1);
+  state.votes.insert(2, 1);
+  state.votes.insert(3, 0);
+  assert!(state.remove_voter(1));
+  assert!(!state.remove_voter(1));
+  assert!(state.remove_voter(2));
+  assert_eq!(state.winner().unwrap().name, "a");
+}
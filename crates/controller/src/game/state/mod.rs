@@ -1,9 +1,14 @@
 pub mod cancel;
+pub mod chat;
 pub mod create;
+pub mod idle;
 pub mod join;
 pub mod leave;
+pub mod map_vote;
+pub mod mute;
 pub mod node;
 pub mod player;
+pub mod referee;
 pub mod registry;
 pub mod slot;
 pub mod start;
@@ -11,43 +16,63 @@ pub mod status;
 
 pub use status::{GameSlotClientStatusUpdate, GameStatusUpdate};
 
+use crate::db::ExecutorExt;
 use crate::error::*;
-use crate::game::db::{get_all_active_game_state, get_expired_games};
+use crate::event::EventHub;
+use crate::game::db::{get_all_active_game_state, get_entries, get_expired_games};
+use crate::game::export::ResultExporter;
+use crate::game::snapshot::GameListSnapshot;
+use crate::game::store::LobbyStoreRef;
 use crate::game::{GameStatus, SlotClientStatus};
 use crate::node::{NodeRegistry, PlayerToken};
-use crate::player::state::sender::PlayerRegistryHandle;
+use crate::player::state::sender::{PlayerFrames, PlayerRegistryHandle};
 
 use crate::game::state::cancel::CancelGame;
-use crate::game::state::registry::Remove;
-use crate::player::state::PlayerRegistry;
+use crate::game::state::chat::LobbyChatState;
+use crate::game::state::idle::KickIdlePlayers;
+use crate::game::state::registry::{Remove, RemoveGamePlayer};
 use crate::state::{Data, GetActorEntry};
 use bs_diesel_utils::ExecutorRef;
 use flo_state::*;
+use map_vote::MapVoteState;
 use start::StartGameState;
 use std::collections::BTreeMap;
 use std::collections::HashMap;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use tokio::time::sleep;
 
 const GAME_INACTIVE_CHECK_INTERVAL: Duration = Duration::from_secs(3600 * 30);
+const LOBBY_IDLE_CHECK_INTERVAL: Duration = Duration::from_secs(30);
 
 pub struct GameRegistry {
   db: ExecutorRef,
+  lobby: LobbyStoreRef,
   players: PlayerRegistryHandle,
   nodes: Addr<NodeRegistry>,
   map: BTreeMap<i32, Owner<GameActor>>,
   player_games_map: BTreeMap<i32, Vec<i32>>,
   game_players_map: BTreeMap<i32, Vec<i32>>,
   game_node_map: BTreeMap<i32, i32>,
+  exporter: ResultExporter,
+  events: EventHub,
+  game_list: GameListSnapshot,
 }
 
 impl GameRegistry {
   pub async fn init(
     db: ExecutorRef,
+    lobby: LobbyStoreRef,
     player_packet_sender: PlayerRegistryHandle,
     nodes: Addr<NodeRegistry>,
+    events: EventHub,
+    game_list: GameListSnapshot,
   ) -> Result<GameRegistry> {
-    let games = db.exec(|conn| get_all_active_game_state(conn)).await?;
+    let games = db.exec_traced(|conn| get_all_active_game_state(conn)).await?;
+    let game_ids: Vec<i32> = games.iter().map(|game| game.id).collect();
+    let entries = db
+      .exec_traced(move |conn| get_entries(conn, &game_ids))
+      .await?;
+    game_list.replace_all(entries);
     let mut map = BTreeMap::new();
     let mut player_games_map = BTreeMap::new();
     let mut game_players_map = BTreeMap::new();
@@ -78,6 +103,7 @@ impl GameRegistry {
         Owner::new(GameActor {
           game_id: game.id,
           db: db.clone(),
+          lobby: lobby.clone(),
           player_reg: player_packet_sender.clone(),
           nodes: nodes.clone(),
           status: game.status,
@@ -87,25 +113,35 @@ impl GameRegistry {
           start_state: None,
           player_tokens,
           player_client_status_map: Default::default(),
+          map_vote: None,
+          events: events.clone(),
+          player_activity: Default::default(),
+          chat: Default::default(),
+          player_mute_lists: Default::default(),
+          game_list: game_list.clone(),
         }),
       );
     }
 
     let state = GameRegistry {
       db: db.clone(),
+      lobby,
       players: player_packet_sender.clone(),
       nodes: nodes.clone(),
       map,
       player_games_map,
       game_players_map,
       game_node_map,
+      exporter: ResultExporter::from_config(),
+      events,
+      game_list,
     };
 
     Ok(state)
   }
 
   async fn remove_expired_games(&mut self, ctx: &mut Context<Self>) -> Result<()> {
-    let ids = self.db.exec(|conn| get_expired_games(conn)).await?;
+    let ids = self.db.exec_traced(|conn| get_expired_games(conn)).await?;
 
     let mut cancelled = vec![];
     for id in ids {
@@ -137,6 +173,7 @@ impl GameRegistry {
 impl Actor for GameRegistry {
   async fn started(&mut self, ctx: &mut Context<Self>) {
     self.handle(ctx, RemoveExpiredGames).await;
+    self.handle(ctx, KickIdleLobbyPlayers).await;
   }
 }
 
@@ -145,9 +182,17 @@ impl Service<Data> for GameRegistry {
   type Error = Error;
 
   async fn create(registry: &mut RegistryRef<Data>) -> Result<Self, Self::Error> {
-    let players = registry.resolve::<PlayerRegistry>().await?;
     let nodes = registry.resolve::<NodeRegistry>().await?;
-    Self::init(registry.data().db.clone(), players.into(), nodes).await
+    let data = registry.data();
+    Self::init(
+      data.db.clone(),
+      data.lobby.clone(),
+      data.players.clone(),
+      nodes,
+      data.events.clone(),
+      data.game_list.clone(),
+    )
+    .await
   }
 }
 
@@ -186,9 +231,60 @@ impl Handler<RemoveExpiredGames> for GameRegistry {
   }
 }
 
+struct KickIdleLobbyPlayers;
+
+impl Message for KickIdleLobbyPlayers {
+  type Result = ();
+}
+
+#[async_trait]
+impl Handler<KickIdleLobbyPlayers> for GameRegistry {
+  async fn handle(&mut self, ctx: &mut Context<Self>, _: KickIdleLobbyPlayers) {
+    let timeout = match *crate::config::LOBBY_IDLE_TIMEOUT {
+      Some(timeout) => timeout,
+      None => return,
+    };
+    let games: Vec<_> = self
+      .map
+      .iter()
+      .map(|(id, owner)| (*id, owner.addr()))
+      .collect();
+    let addr = ctx.addr();
+    ctx.spawn(async move {
+      for (game_id, game) in games {
+        let res = game
+          .send(KickIdlePlayers { timeout })
+          .await
+          .map_err(Error::from)
+          .and_then(|res| res);
+        match res {
+          Ok(res) => {
+            if res.game_ended {
+              addr.notify(Remove { game_id }).await.ok();
+            } else {
+              for player_id in res.kicked_player_ids {
+                addr
+                  .notify(RemoveGamePlayer { game_id, player_id })
+                  .await
+                  .ok();
+              }
+            }
+          }
+          Err(err) => {
+            tracing::warn!(game_id, "kick idle players: {}", err);
+          }
+        }
+      }
+      sleep(LOBBY_IDLE_CHECK_INTERVAL).await;
+      addr.notify(KickIdleLobbyPlayers).await.ok();
+    });
+  }
+}
+
 pub struct GameActor {
   pub game_id: i32,
   pub db: ExecutorRef,
+  pub lobby: LobbyStoreRef,
   pub player_reg: PlayerRegistryHandle,
   pub nodes: Addr<NodeRegistry>,
   pub status: GameStatus,
@@ -198,6 +294,14 @@ pub struct GameActor {
   pub start_state: Option<Owner<StartGameState>>,
   pub player_tokens: HashMap<i32, [u8; 16]>,
   pub player_client_status_map: HashMap<i32, SlotClientStatus>,
+  pub map_vote: Option<MapVoteState>,
+  pub events: EventHub,
+  /// Last lobby activity of each player, see [`KickIdlePlayers`]
+  pub player_activity: HashMap<i32, Instant>,
+  pub chat: LobbyChatState,
+  /// Players muted by each player for this game only, sent again when they reconnect
+  pub player_mute_lists: HashMap<i32, Vec<i32>>,
+  pub game_list: GameListSnapshot,
 }
 
 impl Actor for GameActor {}
@@ -206,4 +310,183 @@ impl GameActor {
   fn started(&self) -> bool {
     self.start_state.is_some() || !self.player_tokens.is_empty()
   }
+
+  /// Records a lobby action of the player, e.g. a slot change
+  fn touch_player(&mut self, player_id: i32) {
+    self.player_activity.insert(player_id, Instant::now());
+  }
+
+  /// Sends the frames returned by `f` to each player of the game in a single registry message,
+  /// players `f` returns `None` for are skipped
+  pub(crate) async fn broadcast_to_game<F>(&self, mut f: F) -> Result<()>
+  where
+    F: FnMut(i32) -> Result<Option<PlayerFrames>>,
+  {
+    let mut map = BTreeMap::new();
+    for player_id in self.players.iter().cloned() {
+      if let Some(frames) = f(player_id)? {
+        map.insert(player_id, frames);
+      }
+    }
+    if map.is_empty() {
+      return Ok(());
+    }
+    self.player_reg.broadcast_map(map).await
+  }
+}
+
+/// Game actor reading the lobby from `lobby`, the actor still holds a database executor
+/// and the node registry, so `DATABASE_URL` has to point to a migrated database
+#[cfg(test)]
+async fn test_game_actor(
+  lobby: std::sync::Arc<crate::game::store::MemoryLobbyStore>,
+  game: &crate::game::Game,
+) -> (Registry<Data>, Owner<GameActor>) {
+  let db = bs_diesel_utils::Executor::env().into_ref();
+  let events = EventHub::new();
+  let players = PlayerRegistryHandle::start(events.clone(), db.clone())
+    .await
+    .unwrap();
+  let game_list = GameListSnapshot::new();
+  let registry = Registry::with_data(Data {
+    db: db.clone(),
+    lobby: lobby.clone(),
+    events: events.clone(),
+    game_list: game_list.clone(),
+    players: players.clone(),
+  });
+  let nodes = registry.resolve().await.unwrap();
+  let actor = Owner::new(GameActor {
+    game_id: game.id,
+    db,
+    lobby,
+    player_reg: players,
+    nodes,
+    status: game.status,
+    host_player: game.created_by.id,
+    players: game.get_player_ids(),
+    selected_node_id: None,
+    start_state: None,
+    player_tokens: Default::default(),
+    player_client_status_map: Default::default(),
+    map_vote: None,
+    events,
+    player_activity: Default::default(),
+    chat: Default::default(),
+    player_mute_lists: Default::default(),
+    game_list,
+  });
+  (registry, actor)
+}
+
+#[tokio::test]
+#[ignore]
+async fn test_game_actor_join_leave() {
+  use crate::game::slots::{test_game, test_player};
+  use crate::game::store::MemoryLobbyStore;
+  use join::PlayerJoin;
+  use leave::PlayerLeave;
+
+  let lobby = std::sync::Arc::new(MemoryLobbyStore::new());
+  let game = test_game(1, test_player(1), 2);
+  lobby.insert_game(game.clone());
+  lobby.insert_player(test_player(2));
+  lobby.insert_player(test_player(3));
+  let (_registry, actor) = test_game_actor(lobby.clone(), &game).await;
+
+  let joined = actor
+    .send(PlayerJoin { player_id: 2 })
+    .await
+    .unwrap()
+    .unwrap();
+  assert_eq!(joined.get_player_ids(), vec![1, 2]);
+  assert!(matches!(
+    actor.send(PlayerJoin { player_id: 3 }).await.unwrap(),
+    Err(Error::GameFull)
+  ));
+
+  let res = actor
+    .send(PlayerLeave { player_id: 2 })
+    .await
+    .unwrap()
+    .unwrap();
+  assert!(!res.game_ended);
+  assert_eq!(lobby.get_game(1).unwrap().get_player_ids(), vec![1]);
+
+  // host left
+  let res = actor
+    .send(PlayerLeave { player_id: 1 })
+    .await
+    .unwrap()
+    .unwrap();
+  assert!(res.game_ended);
+  assert_eq!(lobby.get_game(1).unwrap().status, GameStatus::Ended);
+}
+
+#[tokio::test]
+#[ignore]
+async fn test_game_actor_update_slot() {
+  use crate::game::slots::{test_game, test_player};
+  use crate::game::store::MemoryLobbyStore;
+  use crate::game::SlotSettings;
+  use join::PlayerJoin;
+  use slot::UpdateSlot;
+
+  let lobby = std::sync::Arc::new(MemoryLobbyStore::new());
+  let game = test_game(1, test_player(1), 4);
+  lobby.insert_game(game.clone());
+  lobby.insert_player(test_player(2));
+  lobby.insert_player(test_player(3));
+  let (_registry, actor) = test_game_actor(lobby.clone(), &game).await;
+
+  actor
+    .send(PlayerJoin { player_id: 2 })
+    .await
+    .unwrap()
+    .unwrap();
+  actor
+    .send(PlayerJoin { player_id: 3 })
+    .await
+    .unwrap()
+    .unwrap();
+  let slot_index = lobby
+    .get_game(1)
+    .unwrap()
+    .find_player_slot_index(2)
+    .unwrap() as i32;
+  let settings = SlotSettings {
+    team: 3,
+    ..lobby.get_game(1).unwrap().slots[slot_index as usize]
+      .settings
+      .clone()
+  };
+
+  // only the host and the player in the slot can change it
+  assert!(matches!(
+    actor
+      .send(UpdateSlot {
+        player_id: 3,
+        slot_index,
+        settings: settings.clone(),
+      })
+      .await
+      .unwrap(),
+    Err(Error::GameSlotUpdateDenied)
+  ));
+  let slots = actor
+    .send(UpdateSlot {
+      player_id: 2,
+      slot_index,
+      settings,
+    })
+    .await
+    .unwrap()
+    .unwrap();
+  assert_eq!(slots[slot_index as usize].settings.team, 3);
+  assert_eq!(
+    lobby.get_game(1).unwrap().slots[slot_index as usize]
+      .settings
+      .team,
+    3
+  );
 }
@@ -0,0 +1,61 @@
+use crate::error::*;
+use crate::game::state::GameActor;
+
+use flo_state::{async_trait, Context, Handler, Message};
+
+/// Replaces the players muted by `player_id` for this game only
+pub struct UpdateGameMuteList {
+  pub player_id: i32,
+  pub mute_list: Vec<i32>,
+}
+
+impl Message for UpdateGameMuteList {
+  type Result = Result<()>;
+}
+
+#[async_trait]
+impl Handler<UpdateGameMuteList> for GameActor {
+  async fn handle(
+    &mut self,
+    _: &mut Context<Self>,
+    UpdateGameMuteList {
+      player_id,
+      mute_list,
+    }: UpdateGameMuteList,
+  ) -> Result<()> {
+    if !self.players.contains(&player_id) {
+      return Err(Error::PlayerNotInGame);
+    }
+    if mute_list.is_empty() {
+      self.player_mute_lists.remove(&player_id);
+    } else {
+      self.player_mute_lists.insert(player_id, mute_list);
+    }
+    Ok(())
+  }
+}
+
+pub struct GetGameMuteList {
+  pub player_id: i32,
+}
+
+impl Message for GetGameMuteList {
+  type Result = Result<Vec<i32>>;
+}
+
+#[async_trait]
+impl Handler<GetGameMuteList> for GameActor {
+  async fn handle(
+    &mut self,
+    _: &mut Context<Self>,
+    GetGameMuteList { player_id }: GetGameMuteList,
+  ) -> Result<Vec<i32>> {
+    Ok(
+      self
+        .player_mute_lists
+        .get(&player_id)
+        .cloned()
+        .unwrap_or_default(),
+    )
+  }
+}
@@ -1,9 +1,12 @@
+use crate::db::ExecutorExt;
 use crate::error::*;
 use crate::game::state::GameActor;
+use crate::game::GameStatus;
 
 use flo_net::packet::FloPacket;
 use flo_net::proto;
 use flo_state::{async_trait, Context, Handler, Message};
+use s2_grpc_utils::S2ProtoPack;
 
 pub struct SelectNode {
   pub node_id: Option<i32>,
@@ -29,8 +32,9 @@ impl Handler<SelectNode> for GameActor {
 
     self
       .db
-      .exec(move |conn| crate::game::db::select_node(conn, game_id, player_id, node_id))
+      .exec_traced(move |conn| crate::game::db::select_node(conn, game_id, player_id, node_id))
       .await?;
+    self.lobby.invalidate(game_id);
 
     self.selected_node_id = node_id;
 
@@ -40,6 +44,80 @@ impl Handler<SelectNode> for GameActor {
       .broadcast(self.players.clone(), frame)
       .await?;
 
+    if let Err(err) = self.broadcast_slot_ping().await {
+      tracing::warn!(game_id, "broadcast slot ping: {}", err);
+    }
+
+    Ok(())
+  }
+}
+
+/// Sends the lobby players' ping to the selected node to everyone in the lobby
+pub struct BroadcastSlotPing;
+
+impl Message for BroadcastSlotPing {
+  type Result = ();
+}
+
+#[async_trait]
+impl Handler<BroadcastSlotPing> for GameActor {
+  async fn handle(&mut self, _: &mut Context<Self>, _: BroadcastSlotPing) {
+    if let Err(err) = self.broadcast_slot_ping().await {
+      tracing::warn!(game_id = self.game_id, "broadcast slot ping: {}", err);
+    }
+  }
+}
+
+impl GameActor {
+  pub(super) async fn broadcast_slot_ping(&self) -> Result<()> {
+    let game_id = self.game_id;
+
+    if self.status != GameStatus::Preparing {
+      return Ok(());
+    }
+
+    let node_id = self.selected_node_id;
+    let mut slots = vec![];
+    if let Some(node_id) = node_id {
+      let player_slots = self
+        .db
+        .exec_traced(move |conn| crate::game::db::get_player_slot_indexes(conn, game_id))
+        .await?;
+      let snapshot = self
+        .player_reg
+        .get_ping_snapshot(
+          player_slots
+            .iter()
+            .map(|(player_id, _)| *player_id)
+            .collect(),
+        )
+        .await?;
+      for (player_id, slot_index) in player_slots {
+        if let Some(ping) = snapshot
+          .map
+          .get(&player_id)
+          .and_then(|map| map.get(&node_id))
+        {
+          slots.push(proto::flo_connect::SlotPing {
+            slot_index,
+            player_id,
+            ping: Some(ping.clone().pack()?),
+          });
+        }
+      }
+    }
+
+    let frame = proto::flo_connect::PacketGameSlotPingUpdate {
+      game_id,
+      node_id,
+      slots,
+    }
+    .encode_as_frame()?;
+    self
+      .player_reg
+      .broadcast(self.players.clone(), frame)
+      .await?;
+
     Ok(())
   }
 }
@@ -0,0 +1,49 @@
+use crate::db::ExecutorExt;
+use crate::error::*;
+use crate::game::state::GameActor;
+use flo_net::packet::FloPacket;
+use flo_net::proto::flo_connect::PacketGameRefereeUpdate;
+use flo_state::{async_trait, Context, Handler, Message};
+
+/// Grants or revokes the referee role of an observer and sends the new referees to the lobby
+pub struct SetReferee {
+  pub player_id: i32,
+  pub referee: bool,
+}
+
+impl Message for SetReferee {
+  type Result = Result<Vec<i32>>;
+}
+
+#[async_trait]
+impl Handler<SetReferee> for GameActor {
+  async fn handle(
+    &mut self,
+    _: &mut Context<Self>,
+    SetReferee { player_id, referee }: SetReferee,
+  ) -> Result<Vec<i32>> {
+    if self.started() {
+      return Err(Error::GameStarted);
+    }
+
+    let game_id = self.game_id;
+    // the observer check reads the team of the player from the database
+    self.lobby.flush(game_id).await?;
+    let referee_player_ids = self
+      .db
+      .exec_traced(move |conn| crate::game::db::set_referee(conn, game_id, player_id, referee))
+      .await?;
+
+    let frame = PacketGameRefereeUpdate {
+      game_id,
+      referee_player_ids: referee_player_ids.clone(),
+    }
+    .encode_as_frame()?;
+    self
+      .player_reg
+      .broadcast(self.players.clone(), frame)
+      .await?;
+
+    Ok(referee_player_ids)
+  }
+}
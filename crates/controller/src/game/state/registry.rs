@@ -1,6 +1,17 @@
+use crate::db::ExecutorExt;
 use crate::error::*;
-use crate::game::state::{GameActor, GameRegistry};
-use crate::game::GameStatus;
+use crate::event::{ControllerEvent, ControllerEventType};
+use crate::game::state::node::BroadcastSlotPing;
+use crate::game::state::{GameActor, GameRegistry, GameStatusUpdate};
+use crate::game::{GameEntry, GameStatus, SlotClientStatus};
+use crate::node::messages::NodeUpdatePlayerBans;
+use crate::player::state::sender::PlayerFrames;
+use crate::state::ActorMapExt;
+use flo_net::packet::FloPacket;
+use flo_net::proto::flo_node::{
+  NodeGameSnapshot, NodeGameStatus, PacketNodeActionIncident, PacketNodeGameChatLog,
+  PacketNodeGameStats, PacketNodeGameStatusUpdate,
+};
 use flo_state::{async_trait, Context, Handler, Message, Owner};
 use std::collections::btree_map::Entry;
 
@@ -19,14 +30,15 @@ impl Message for Register {
 
 #[async_trait]
 impl Handler<Register> for GameRegistry {
-  async fn handle(&mut self, _: &mut Context<Self>, message: Register) {
-    self.register(message)
+  async fn handle(&mut self, ctx: &mut Context<Self>, message: Register) {
+    self.register(ctx, message)
   }
 }
 
 impl GameRegistry {
   pub(crate) fn register(
     &mut self,
+    ctx: &mut Context<Self>,
     Register {
       id,
       status,
@@ -43,6 +55,7 @@ impl GameRegistry {
       Owner::new(GameActor {
         game_id: id,
         db: self.db.clone(),
+        lobby: self.lobby.clone(),
         player_reg: self.players.clone(),
         nodes: self.nodes.clone(),
         status,
@@ -52,8 +65,20 @@ impl GameRegistry {
         start_state: None,
         player_tokens: Default::default(),
         player_client_status_map: Default::default(),
+        map_vote: None,
+        events: self.events.clone(),
+        player_activity: Default::default(),
+        chat: Default::default(),
+        player_mute_lists: Default::default(),
+        game_list: self.game_list.clone(),
       }),
     );
+    self.refresh_game_list_entry(ctx, id);
+    if status == GameStatus::Preparing {
+      self
+        .events
+        .publish(ControllerEvent::game(ControllerEventType::GameCreated, id));
+    }
   }
 }
 
@@ -72,10 +97,16 @@ impl Handler<Remove> for GameRegistry {
     if let Some(owner) = self.map.remove(&id) {
       self.game_players_map.remove(&id);
       self.game_node_map.remove(&id);
+      self.game_list.remove(id);
+      self.lobby.invalidate(id);
 
       let addr = ctx.addr();
+      let game_list = self.game_list.clone();
       ctx.spawn(async move {
-        match tokio::time::timeout(std::time::Duration::from_secs(3), owner.shutdown()).await {
+        let res = tokio::time::timeout(std::time::Duration::from_secs(3), owner.shutdown()).await;
+        // the actor could have refreshed the entry before it stopped
+        game_list.remove(id);
+        match res {
           Ok(Ok(state)) => {
             let players = state.players;
             for player_id in players {
@@ -114,10 +145,11 @@ impl Message for AddGamePlayer {
 impl Handler<AddGamePlayer> for GameRegistry {
   async fn handle(
     &mut self,
-    _: &mut Context<Self>,
+    ctx: &mut Context<Self>,
     AddGamePlayer { game_id, player_id }: AddGamePlayer,
   ) {
-    self.add_game_player(game_id, player_id)
+    self.add_game_player(game_id, player_id);
+    self.refresh_game_list_entry(ctx, game_id);
   }
 }
 
@@ -134,10 +166,11 @@ impl Message for RemoveGamePlayer {
 impl Handler<RemoveGamePlayer> for GameRegistry {
   async fn handle(
     &mut self,
-    _: &mut Context<Self>,
+    ctx: &mut Context<Self>,
     RemoveGamePlayer { game_id, player_id }: RemoveGamePlayer,
   ) {
-    self.remove_game_player(game_id, player_id)
+    self.remove_game_player(game_id, player_id);
+    self.refresh_game_list_entry(ctx, game_id);
   }
 }
 
@@ -154,7 +187,7 @@ impl Message for UpdateGameNodeCache {
 impl Handler<UpdateGameNodeCache> for GameRegistry {
   async fn handle(
     &mut self,
-    _: &mut Context<Self>,
+    ctx: &mut Context<Self>,
     UpdateGameNodeCache { game_id, node_id }: UpdateGameNodeCache,
   ) {
     if let Some(node_id) = node_id {
@@ -162,6 +195,55 @@ impl Handler<UpdateGameNodeCache> for GameRegistry {
     } else {
       self.game_node_map.remove(&game_id);
     }
+    self.refresh_game_list_entry(ctx, game_id);
+  }
+}
+
+/// Reloads the game list entry of a game, e.g. after a status change
+pub struct RefreshGameListEntry {
+  pub game_id: i32,
+}
+
+impl Message for RefreshGameListEntry {
+  type Result = ();
+}
+
+#[async_trait]
+impl Handler<RefreshGameListEntry> for GameRegistry {
+  async fn handle(
+    &mut self,
+    ctx: &mut Context<Self>,
+    RefreshGameListEntry { game_id }: RefreshGameListEntry,
+  ) {
+    self.refresh_game_list_entry(ctx, game_id);
+  }
+}
+
+/// Asks the game actor to update its game list entry
+struct RefreshListEntry;
+
+impl Message for RefreshListEntry {
+  type Result = ();
+}
+
+#[async_trait]
+impl Handler<RefreshListEntry> for GameActor {
+  async fn handle(&mut self, _: &mut Context<Self>, _: RefreshListEntry) {
+    self.refresh_game_list_entry().await;
+  }
+}
+
+impl GameActor {
+  /// Builds the entry from the cached lobby, so only invalidated games are loaded
+  /// from the database. Refreshes of a game run one at a time in its actor and read
+  /// the current state, a refresh can't overwrite a newer entry
+  pub(crate) async fn refresh_game_list_entry(&self) {
+    match self.lobby.get(self.game_id).await {
+      Ok(game) => self.game_list.update(GameEntry::from(&game)),
+      Err(err) => {
+        tracing::warn!(game_id = self.game_id, "refresh game list entry: {}", err);
+      }
+    }
   }
 }
 
@@ -209,7 +291,338 @@ impl Handler<ResolveGamePlayerPingBroadcastTargets> for GameRegistry {
   }
 }
 
+/// Refreshes the slot ping of the games hosted on one of the nodes the player reported
+pub struct NotifyGamePlayerPingUpdate {
+  pub player_id: i32,
+  pub node_ids: Vec<i32>,
+}
+
+impl Message for NotifyGamePlayerPingUpdate {
+  type Result = ();
+}
+
+#[async_trait]
+impl Handler<NotifyGamePlayerPingUpdate> for GameRegistry {
+  async fn handle(
+    &mut self,
+    _: &mut Context<Self>,
+    NotifyGamePlayerPingUpdate {
+      player_id,
+      node_ids,
+    }: NotifyGamePlayerPingUpdate,
+  ) {
+    let games = match self.player_games_map.get(&player_id) {
+      Some(v) => v,
+      None => return,
+    };
+
+    for game_id in games {
+      let selected = self
+        .game_node_map
+        .get(game_id)
+        .map(|node_id| node_ids.contains(node_id))
+        .unwrap_or_default();
+      if !selected {
+        continue;
+      }
+      if let Some(addr) = self.map.get(game_id).map(|v| v.addr()) {
+        addr.notify(BroadcastSlotPing).await.ok();
+      }
+    }
+  }
+}
+
+/// Players sharing a game with the player, excluding the player
+pub struct ResolveGamePlayerPeers {
+  pub player_id: i32,
+}
+
+impl Message for ResolveGamePlayerPeers {
+  type Result = Vec<i32>;
+}
+
+#[async_trait]
+impl Handler<ResolveGamePlayerPeers> for GameRegistry {
+  async fn handle(
+    &mut self,
+    _: &mut Context<Self>,
+    ResolveGamePlayerPeers { player_id }: ResolveGamePlayerPeers,
+  ) -> Vec<i32> {
+    let games = match self.player_games_map.get(&player_id) {
+      Some(v) => v,
+      None => return vec![],
+    };
+
+    let mut player_ids: Vec<i32> = games
+      .into_iter()
+      .filter_map(|game_id| self.game_players_map.get(game_id).cloned())
+      .flatten()
+      .collect();
+    player_ids.sort();
+    player_ids.dedup();
+    player_ids.retain(|v| *v != player_id);
+
+    player_ids
+  }
+}
+
+/// Pushes the ban list of a player to the nodes running the games of the player,
+/// bans are otherwise only sent when the game is created
+pub struct UpdatePlayerBans {
+  pub player_id: i32,
+}
+
+impl Message for UpdatePlayerBans {
+  type Result = ();
+}
+
+#[async_trait]
+impl Handler<UpdatePlayerBans> for GameRegistry {
+  async fn handle(
+    &mut self,
+    ctx: &mut Context<Self>,
+    UpdatePlayerBans { player_id }: UpdatePlayerBans,
+  ) {
+    let targets: Vec<(i32, i32)> = match self.player_games_map.get(&player_id) {
+      Some(games) => games
+        .iter()
+        .filter_map(|game_id| {
+          self
+            .game_node_map
+            .get(game_id)
+            .map(|node_id| (*game_id, *node_id))
+        })
+        .collect(),
+      None => return,
+    };
+    if targets.is_empty() {
+      return;
+    }
+
+    let db = self.db.clone();
+    let nodes = self.nodes.clone();
+    ctx.spawn(async move {
+      let ban_list = db
+        .exec_traced(move |conn| {
+          let mut ban_list_map = crate::player::db::get_ban_list_map(conn, &[player_id])?;
+          let reputations = crate::player::reputation::get_map(conn, &[player_id])?;
+          crate::player::reputation::apply_to_ban_list_map(&reputations, &mut ban_list_map);
+          Ok::<_, Error>(ban_list_map)
+        })
+        .await
+        .map(|mut map| map.remove(&player_id).unwrap_or_default());
+      let ban_list = match ban_list {
+        Ok(v) => v,
+        Err(err) => {
+          tracing::warn!(player_id, "load player bans: {}", err);
+          return;
+        }
+      };
+      for (game_id, node_id) in targets {
+        let res = nodes
+          .send_to(
+            node_id,
+            NodeUpdatePlayerBans {
+              game_id,
+              player_id,
+              ban_list: ban_list.clone(),
+            },
+          )
+          .await;
+        if let Err(err) = res {
+          tracing::warn!(game_id, node_id, player_id, "update player bans: {}", err);
+        }
+      }
+    });
+  }
+}
+
+pub struct SaveGameStats {
+  pub node_id: i32,
+  pub stats: PacketNodeGameStats,
+}
+
+impl Message for SaveGameStats {
+  type Result = ();
+}
+
+#[async_trait]
+impl Handler<SaveGameStats> for GameRegistry {
+  async fn handle(
+    &mut self,
+    ctx: &mut Context<Self>,
+    SaveGameStats { node_id, stats }: SaveGameStats,
+  ) {
+    let db = self.db.clone();
+    let players = self.players.clone();
+    let exporter = self.exporter.clone();
+    ctx.spawn(async move {
+      let game_id = stats.game_id;
+      let summaries = crate::game::stats::summary_packets(&stats);
+      let export_stats = if exporter.is_empty() {
+        None
+      } else {
+        Some(stats.clone())
+      };
+      if let Err(err) = db
+        .exec_traced(move |conn| crate::game::stats::save(conn, node_id, &stats))
+        .await
+      {
+        tracing::warn!(game_id, node_id, "save game stats: {}", err);
+        return;
+      }
+
+      let frames = summaries
+        .into_iter()
+        .map(|(player_id, pkt)| Ok((player_id, PlayerFrames::from(pkt.encode_as_frame()?))))
+        .collect::<Result<Vec<_>>>();
+      let res = match frames {
+        Ok(frames) => players.broadcast_map(frames).await,
+        Err(err) => Err(err),
+      };
+      if let Err(err) = res {
+        tracing::warn!(game_id, "send game summary: {}", err);
+      }
+
+      if let Some(stats) = export_stats {
+        exporter.export(db, stats).await;
+      }
+    });
+  }
+}
+
+/// Closes a game interrupted by a node restart,
+/// the stats of the last snapshot are saved as the game's result
+pub struct CloseOrphanedGame {
+  pub node_id: i32,
+  pub snapshot: NodeGameSnapshot,
+}
+
+impl Message for CloseOrphanedGame {
+  type Result = ();
+}
+
+#[async_trait]
+impl Handler<CloseOrphanedGame> for GameRegistry {
+  async fn handle(
+    &mut self,
+    ctx: &mut Context<Self>,
+    CloseOrphanedGame { node_id, snapshot }: CloseOrphanedGame,
+  ) {
+    let game_id = snapshot.game_id;
+    let game_addr = match self.map.get(&game_id) {
+      Some(owner) if self.game_node_map.get(&game_id) == Some(&node_id) => owner.addr(),
+      _ => {
+        tracing::debug!(game_id, node_id, "orphaned game discarded");
+        return;
+      }
+    };
+
+    let mut pkt = PacketNodeGameStatusUpdate {
+      game_id,
+      updated_player_game_client_status_map: snapshot.player_client_status_map,
+      ..Default::default()
+    };
+    pkt.set_status(NodeGameStatus::Ended);
+    let mut update = GameStatusUpdate::from(pkt);
+    for status in update.updated_player_game_client_status_map.values_mut() {
+      if *status != SlotClientStatus::Left {
+        *status = SlotClientStatus::Disconnected;
+      }
+    }
+
+    let addr = ctx.addr();
+    let stats = snapshot.stats;
+    ctx.spawn(async move {
+      if let Some(stats) = stats {
+        addr.notify(SaveGameStats { node_id, stats }).await.ok();
+      }
+      match game_addr.send(update).await {
+        Ok(Ok(_)) => {}
+        Ok(Err(err)) => tracing::warn!(game_id, "close orphaned game: {}", err),
+        Err(err) => tracing::warn!(game_id, "close orphaned game: {:?}", err),
+      }
+      addr.notify(Remove { game_id }).await.ok();
+    });
+  }
+}
+
+pub struct SaveGameChatLog {
+  pub node_id: i32,
+  pub log: PacketNodeGameChatLog,
+}
+
+impl Message for SaveGameChatLog {
+  type Result = ();
+}
+
+#[async_trait]
+impl Handler<SaveGameChatLog> for GameRegistry {
+  async fn handle(
+    &mut self,
+    ctx: &mut Context<Self>,
+    SaveGameChatLog { node_id, log }: SaveGameChatLog,
+  ) {
+    let db = self.db.clone();
+    ctx.spawn(async move {
+      let game_id = log.game_id;
+      if let Err(err) = db
+        .exec_traced(move |conn| crate::game::chat_log::save(conn, node_id, &log))
+        .await
+      {
+        tracing::warn!(game_id, node_id, "save game chat log: {}", err);
+      }
+    });
+  }
+}
+
+pub struct SaveActionIncident {
+  pub node_id: i32,
+  pub incident: PacketNodeActionIncident,
+}
+
+impl Message for SaveActionIncident {
+  type Result = ();
+}
+
+#[async_trait]
+impl Handler<SaveActionIncident> for GameRegistry {
+  async fn handle(
+    &mut self,
+    ctx: &mut Context<Self>,
+    SaveActionIncident { node_id, incident }: SaveActionIncident,
+  ) {
+    tracing::warn!(
+      game_id = incident.game_id,
+      player_id = incident.player_id,
+      node_id,
+      "action incident: {:?}: {}",
+      incident.kind(),
+      incident.detail
+    );
+    let db = self.db.clone();
+    ctx.spawn(async move {
+      let game_id = incident.game_id;
+      if let Err(err) = db
+        .exec_traced(move |conn| crate::game::incident::create(conn, node_id, &incident))
+        .await
+      {
+        tracing::warn!(game_id, node_id, "save action incident: {}", err);
+      }
+    });
+  }
+}
+
 impl GameRegistry {
+  fn refresh_game_list_entry(&self, ctx: &mut Context<Self>, game_id: i32) {
+    if let Some(owner) = self.map.get(&game_id) {
+      let addr = owner.addr();
+      ctx.spawn(async move {
+        addr.notify(RefreshListEntry).await.ok();
+      });
+    }
+  }
+
   fn add_game_player(&mut self, game_id: i32, player_id: i32) {
     self
       .player_games_map
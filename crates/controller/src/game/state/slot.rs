@@ -1,12 +1,14 @@
 use crate::error::*;
 use crate::game::db::UpdateSlotSettings;
-use crate::game::state::GameActor;
+use crate::game::state::{GameActor, GameRegistry};
 use crate::game::{Slot, SlotSettings};
-use diesel::prelude::*;
+use crate::player::state::sender::PlayerFrames;
+use crate::state::ActorMapExt;
 use flo_net::packet::FloPacket;
 use flo_net::proto;
-use flo_state::{async_trait, Context, Handler, Message};
+use flo_state::{async_trait, Addr, Context, Handler, Message};
 use s2_grpc_utils::S2ProtoPack;
+use tokio::sync::mpsc::UnboundedReceiver;
 
 pub struct UpdateSlot {
   pub player_id: i32,
@@ -31,29 +33,239 @@ impl Handler<UpdateSlot> for GameActor {
   ) -> Result<Vec<Slot>> {
     let game_id = self.game_id;
 
+    let res = self
+      .lobby
+      .update_slot(game_id, player_id, slot_index, settings)
+      .await;
+    let UpdateSlotSettings {
+      slots,
+      updated_indexes,
+    } = match res {
+      Ok(update) => update,
+      Err(Error::GameVersionConflict { version, slots }) => {
+        // the slots were changed by another request, resync the clients
+        let indexes = (0..slots.len() as i32).collect();
+        self.broadcast_slot_updates(&slots, indexes).await?;
+        return Err(Error::GameVersionConflict { version, slots });
+      }
+      Err(err) => return Err(err),
+    };
+    self.touch_player(player_id);
+
+    self.broadcast_slot_updates(&slots, updated_indexes).await?;
+
+    Ok(slots)
+  }
+}
+
+pub struct SetSlotClosed {
+  pub player_id: i32,
+  pub slot_index: i32,
+  pub closed: bool,
+}
+
+impl Message for SetSlotClosed {
+  type Result = Result<Vec<Slot>>;
+}
+
+#[async_trait]
+impl Handler<SetSlotClosed> for GameActor {
+  async fn handle(
+    &mut self,
+    _: &mut Context<Self>,
+    SetSlotClosed {
+      player_id,
+      slot_index,
+      closed,
+    }: SetSlotClosed,
+  ) -> Result<Vec<Slot>> {
+    if self.host_player != player_id {
+      return Err(Error::PlayerNotHost);
+    }
+
+    let game_id = self.game_id;
+
     let UpdateSlotSettings {
       slots,
       updated_indexes,
     } = self
-      .db
-      .exec(move |conn| {
-        conn.transaction(|| {
-          let info = crate::game::db::get_slot_owner_info(conn, game_id, slot_index)?;
-          if !info.is_slot_owner(player_id) {
-            return Err(Error::GameSlotUpdateDenied);
-          }
-          crate::game::db::update_slot_settings(conn, game_id, slot_index, settings)
-        })
-      })
+      .lobby
+      .set_slot_closed(game_id, slot_index, closed)
+      .await?;
+
+    self.broadcast_slot_updates(&slots, updated_indexes).await?;
+
+    Ok(slots)
+  }
+}
+
+pub struct ReserveSlot {
+  pub player_id: i32,
+  pub slot_index: i32,
+  pub reserved_player_id: Option<i32>,
+}
+
+impl Message for ReserveSlot {
+  type Result = Result<()>;
+}
+
+#[async_trait]
+impl Handler<ReserveSlot> for GameActor {
+  async fn handle(
+    &mut self,
+    _: &mut Context<Self>,
+    ReserveSlot {
+      player_id,
+      slot_index,
+      reserved_player_id,
+    }: ReserveSlot,
+  ) -> Result<()> {
+    if self.host_player != player_id {
+      return Err(Error::PlayerNotHost);
+    }
+
+    let game_id = self.game_id;
+
+    self
+      .lobby
+      .reserve_slot(game_id, slot_index, reserved_player_id)
+      .await?;
+
+    tracing::info!(
+      game_id,
+      slot_index,
+      "slot reserved: {:?}",
+      reserved_player_id
+    );
+
+    Ok(())
+  }
+}
+
+pub struct ShuffleSlots {
+  pub player_id: i32,
+  pub races: bool,
+  pub teams: bool,
+}
+
+impl Message for ShuffleSlots {
+  type Result = Result<Vec<Slot>>;
+}
+
+#[async_trait]
+impl Handler<ShuffleSlots> for GameActor {
+  async fn handle(
+    &mut self,
+    _: &mut Context<Self>,
+    ShuffleSlots {
+      player_id,
+      races,
+      teams,
+    }: ShuffleSlots,
+  ) -> Result<Vec<Slot>> {
+    if self.host_player != player_id {
+      return Err(Error::PlayerNotHost);
+    }
+
+    if self.started() {
+      return Err(Error::GameStarted);
+    }
+
+    let game_id = self.game_id;
+
+    let UpdateSlotSettings {
+      slots,
+      updated_indexes,
+    } = self.lobby.shuffle_slots(game_id, races, teams).await?;
+
+    self.broadcast_slot_updates(&slots, updated_indexes).await?;
+
+    Ok(slots)
+  }
+}
+
+/// Broadcasts the slots stored in the database, after the lobby store lost slot changes
+pub struct ResyncSlots;
+
+impl Message for ResyncSlots {
+  type Result = Result<()>;
+}
+
+#[async_trait]
+impl Handler<ResyncSlots> for GameActor {
+  async fn handle(&mut self, _: &mut Context<Self>, _: ResyncSlots) -> Result<()> {
+    let game = self.lobby.get(self.game_id).await?;
+    let indexes = (0..game.slots.len() as i32).collect();
+    self.broadcast_slot_updates(&game.slots, indexes).await
+  }
+}
+
+/// Writes the pending slot changes of the game for readers outside of the actor,
+/// `invalidate` also drops the cached game, e.g. after a player was renamed
+pub struct FlushLobby {
+  pub invalidate: bool,
+}
+
+impl Message for FlushLobby {
+  type Result = Result<()>;
+}
+
+#[async_trait]
+impl Handler<FlushLobby> for GameActor {
+  async fn handle(
+    &mut self,
+    _: &mut Context<Self>,
+    FlushLobby { invalidate }: FlushLobby,
+  ) -> Result<()> {
+    let res = self.lobby.flush(self.game_id).await;
+    if invalidate {
+      self.lobby.invalidate(self.game_id);
+    }
+    res
+  }
+}
+
+/// Forwards the games sent to the resync channel of the lobby store to their actors
+pub async fn resync_slots(games: Addr<GameRegistry>, mut rx: UnboundedReceiver<i32>) {
+  while let Some(game_id) = rx.recv().await {
+    match games.send_to(game_id, ResyncSlots).await {
+      Ok(_) | Err(Error::ActorNotFound) => {}
+      Err(err) => tracing::error!(game_id, "resync slots: {}", err),
+    }
+  }
+}
+
+impl GameActor {
+  /// Applies the random race / team options of the game before it's created on the node,
+  /// and sends the final slot assignment to all players.
+  pub(crate) async fn shuffle_slots_on_start(&mut self) -> Result<()> {
+    let game_id = self.game_id;
+
+    let (mut game, mute_list_map) = match self.lobby.shuffle_slots_on_start(game_id).await? {
+      Some(v) => v,
+      None => return Ok(()),
+    };
+
+    tracing::info!(game_id, "slots shuffled");
+
+    game.apply_player_name_mask();
+
+    self
+      .player_reg
+      .players_replace_game(self.players.clone(), game, mute_list_map)
       .await?;
 
+    Ok(())
+  }
+
+  async fn broadcast_slot_updates(&self, slots: &[Slot], updated_indexes: Vec<i32>) -> Result<()> {
     let mut frames_slot_update = Vec::with_capacity(updated_indexes.len());
 
     for index in updated_indexes {
       let slot = &slots[index as usize];
       let settings: proto::flo_connect::SlotSettings = slot.settings.clone().pack()?;
       let frame = proto::flo_connect::PacketGameSlotUpdate {
-        game_id,
+        game_id: self.game_id,
         slot_index: index,
         slot_settings: settings.into(),
         player: slot.player.clone().map(|p| p.pack()).transpose()?,
@@ -62,15 +274,9 @@ impl Handler<UpdateSlot> for GameActor {
       frames_slot_update.push(frame);
     }
 
-    let players = slots
-      .iter()
-      .filter_map(|s| s.player.as_ref().map(|p| p.id))
-      .collect();
-    self
-      .player_reg
-      .broadcast(players, frames_slot_update)
-      .await?;
+    let frames = PlayerFrames::from(frames_slot_update);
+    self.broadcast_to_game(|_| Ok(Some(frames.clone()))).await?;
 
-    Ok(slots)
+    Ok(())
   }
 }
@@ -1,15 +1,18 @@
+use crate::db::ExecutorExt;
 use crate::error::*;
 use crate::game::state::GameActor;
 use crate::game::{GameStatus, SlotClientStatus};
-use crate::node::messages::NodeCreateGame;
-use crate::player::state::sender::PlayerFrames;
+use crate::node::messages::{GetNodeUtilizationMap, ListNode, NodeCreateGame, NodeRelayPlayers};
+use crate::node::{Node, NodeRef, PlayerToken};
 use crate::state::ActorMapExt;
 use flo_net::packet::FloPacket;
 use flo_net::proto;
 use flo_state::{async_trait, Actor, Addr, Context, Handler, Message};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::time::Duration;
 use tokio::sync::oneshot;
+use tracing::Span;
+use tracing_futures::Instrument;
 
 use tokio::time::sleep;
 
@@ -17,6 +20,7 @@ const TIMEOUT: Duration = Duration::from_secs(10);
 
 pub struct StartGameCheck {
   pub player_id: i32,
+  pub span: Span,
 }
 
 impl Message for StartGameCheck {
@@ -28,7 +32,7 @@ impl Handler<StartGameCheck> for GameActor {
   async fn handle(
     &mut self,
     ctx: &mut Context<Self>,
-    StartGameCheck { player_id }: StartGameCheck,
+    StartGameCheck { player_id, span }: StartGameCheck,
   ) -> Result<()> {
     let game_id = self.game_id;
 
@@ -45,7 +49,9 @@ impl Handler<StartGameCheck> for GameActor {
       return Err(Error::GameStarted);
     }
 
-    self.start_state = StartGameState::new(game_id, ctx.addr(), players, None)
+    self.apply_map_vote().await?;
+
+    self.start_state = StartGameState::new(game_id, ctx.addr(), players, None, span)
       .start()
       .into();
 
@@ -111,27 +117,97 @@ impl GameActor {
       return Ok(Err(pkt));
     }
 
-    let (game, ban_list_map) = self
+    self.lobby.flush(game_id).await?;
+    self.shuffle_slots_on_start().await?;
+
+    let (mut game, ban_list_map, referee_player_ids) = self
       .db
-      .exec(move |conn| {
+      .exec_traced(move |conn| {
         let game = crate::game::db::get_full(conn, game_id)?;
         let players = game.get_player_ids();
-        Ok::<_, Error>((game, crate::player::db::get_ban_list_map(conn, &players)?))
+        let mut ban_list_map = crate::player::db::get_ban_list_map(conn, &players)?;
+        let reputations = crate::player::reputation::get_map(conn, &players)?;
+        crate::player::reputation::apply_to_ban_list_map(&reputations, &mut ban_list_map);
+        Ok::<_, Error>((
+          game,
+          ban_list_map,
+          crate::game::db::get_referee_player_ids(conn, game_id)?,
+        ))
       })
       .await?;
 
-    let node_id = if let Some(id) = game.node.as_ref().map(|node| node.id) {
+    let mut node_id = if let Some(id) = game.node.as_ref().map(|node| node.id) {
       id
     } else {
       return Err(Error::GameNodeNotSelected);
     };
 
-    let created = self
-      .nodes
-      .send_to(node_id, NodeCreateGame { game, ban_list_map })
-      .await?
-      .await
-      .or_cancelled();
+    let max_attempts = *crate::config::GAME_START_MAX_ATTEMPTS;
+    let mut tried_node_ids = vec![];
+    let created = loop {
+      tried_node_ids.push(node_id);
+
+      let created = match self
+        .nodes
+        .send_to(
+          node_id,
+          NodeCreateGame {
+            game: game.clone(),
+            ban_list_map: ban_list_map.clone(),
+            referee_player_ids: referee_player_ids.clone(),
+            span: Span::current(),
+          },
+        )
+        .await
+      {
+        Ok(reply) => reply.await.or_cancelled(),
+        Err(err) => Err(err),
+      };
+
+      let err = match created {
+        Ok(created) => break Ok(created),
+        Err(err) => err,
+      };
+
+      let next_node = if tried_node_ids.len() < max_attempts && is_retryable_create_error(&err) {
+        self.find_alternative_node(node_id, &tried_node_ids).await?
+      } else {
+        None
+      };
+
+      let next_node = if let Some(node) = next_node {
+        node
+      } else {
+        break Err(err);
+      };
+
+      tracing::warn!(
+        game_id,
+        node_id,
+        next_node_id = next_node.id,
+        "create game failed: {}, retrying on another node",
+        err
+      );
+
+      node_id = next_node.id;
+      self
+        .db
+        .exec_traced(move |conn| crate::game::db::replace_node(conn, game_id, node_id))
+        .await?;
+      self.lobby.invalidate(game_id);
+      self.selected_node_id = Some(node_id);
+      game.node = Some(NodeRef::from(next_node));
+
+      let frame = proto::flo_connect::PacketGameSelectNode {
+        game_id,
+        node_id: Some(node_id),
+      }
+      .encode_as_frame()?;
+      self
+        .player_reg
+        .broadcast(self.players.clone(), frame)
+        .await?;
+    };
 
     let created = match created {
       Ok(created) => created,
@@ -158,6 +234,9 @@ impl GameActor {
                 ControllerCreateGameRejectReason::Maintenance => {
                   format!("Create game request rejected: Server Maintenance.")
                 }
+                ControllerCreateGameRejectReason::NodeFull => {
+                  format!("Create game request rejected: Server Full.")
+                }
               },
               ..Default::default()
             }
@@ -190,40 +269,196 @@ impl GameActor {
       .map(|token| (token.player_id, token))
       .collect::<HashMap<_, _>>();
 
-    let packet_iter = self
-      .players
-      .iter()
-      .filter_map(|player_id| {
-        let pkt = if let Some(token) = token_map.get(player_id) {
-          Some(proto::flo_connect::PacketGamePlayerToken {
-            node_id,
-            game_id,
-            player_id: *player_id,
-            player_token: token.to_vec(),
-          })
-        } else {
-          tracing::error!(game_id, player_id, "player token was not found");
-          None
-        }?;
-        Some((*player_id, pkt))
-      })
-      .map(|(player_id, pkt)| Ok((player_id, PlayerFrames::from(pkt.encode_as_frame()?))))
-      .collect::<Result<Vec<_>>>()?;
+    let relay_map = self.setup_relays(node_id, &token_map).await;
 
     self.player_tokens = token_map
       .iter()
       .map(|(player_id, token)| (*player_id, token.bytes))
       .collect();
-    self.player_reg.broadcast_map(packet_iter).await?;
+    self
+      .broadcast_to_game(|player_id| {
+        let token = if let Some(token) = token_map.get(&player_id) {
+          token
+        } else {
+          tracing::error!(game_id, player_id, "player token was not found");
+          return Ok(None);
+        };
+        let frame = proto::flo_connect::PacketGamePlayerToken {
+          node_id,
+          game_id,
+          player_id,
+          player_token: token.to_vec(),
+          relay_node_id: relay_map.get(&player_id).cloned(),
+        }
+        .encode_as_frame()?;
+        Ok(Some(frame.into()))
+      })
+      .await?;
 
     self
       .db
-      .exec(move |conn| crate::game::db::update_created(conn, game_id, agreed_version, token_map))
+      .exec_traced(move |conn| {
+        crate::game::db::update_created(conn, game_id, agreed_version, token_map)
+      })
       .await?;
+    self.lobby.invalidate(game_id);
     self.status = GameStatus::Created;
 
     Ok(Ok(()))
   }
+
+  /// Registers the players much closer to another node than to the game's node on that node,
+  /// they connect to it and get forwarded to the game's node.
+  /// Returns the relay node of each relayed player, players of failed relays connect directly
+  async fn setup_relays(
+    &self,
+    node_id: i32,
+    token_map: &HashMap<i32, PlayerToken>,
+  ) -> BTreeMap<i32, i32> {
+    let game_id = self.game_id;
+    let min_gain = if let Some(v) = *crate::config::RELAY_MIN_GAIN_MS {
+      v
+    } else {
+      return BTreeMap::new();
+    };
+
+    let (target_addr, relays) = match self.select_relays(node_id, token_map, min_gain).await {
+      Ok(Some(v)) => v,
+      Ok(None) => return BTreeMap::new(),
+      Err(err) => {
+        tracing::error!(game_id, "select relays: {}", err);
+        return BTreeMap::new();
+      }
+    };
+
+    let mut relay_map = BTreeMap::new();
+    for (relay_node_id, tokens) in relays {
+      let player_ids: Vec<i32> = tokens.iter().map(|token| token.player_id).collect();
+      let res = match self
+        .nodes
+        .send_to(
+          relay_node_id,
+          NodeRelayPlayers {
+            game_id,
+            target_addr: target_addr.clone(),
+            tokens,
+          },
+        )
+        .await
+      {
+        Ok(reply) => reply.await.or_cancelled(),
+        Err(err) => Err(err),
+      };
+      match res {
+        Ok(_) => {
+          tracing::info!(game_id, relay_node_id, "relay players: {:?}", player_ids);
+          relay_map.extend(player_ids.into_iter().map(|id| (id, relay_node_id)));
+        }
+        Err(err) => {
+          tracing::warn!(game_id, relay_node_id, "relay players: {}", err);
+        }
+      }
+    }
+    relay_map
+  }
+
+  /// Groups the players by relay node, with the address of the game's node
+  async fn select_relays(
+    &self,
+    node_id: i32,
+    token_map: &HashMap<i32, PlayerToken>,
+    min_gain: u32,
+  ) -> Result<Option<(String, BTreeMap<i32, Vec<PlayerToken>>)>> {
+    let nodes = self.nodes.send(ListNode).await?;
+    let utilization_map = self.nodes.send(GetNodeUtilizationMap).await?;
+    let target_addr = if let Some(node) = nodes.iter().find(|node| node.id == node_id) {
+      node.ip_addr.clone()
+    } else {
+      return Ok(None);
+    };
+    let candidates: Vec<i32> = nodes
+      .iter()
+      .filter(|node| !node.disabled && node.id != node_id)
+      .filter(|node| {
+        !utilization_map
+          .get(&node.id)
+          .map(|v| v.is_overloaded())
+          .unwrap_or_default()
+      })
+      .map(|node| node.id)
+      .collect();
+
+    let snapshot = self
+      .player_reg
+      .get_ping_snapshot(token_map.keys().cloned().collect())
+      .await?;
+
+    let mut relays = BTreeMap::<i32, Vec<PlayerToken>>::new();
+    for (player_id, ping_map) in snapshot.map {
+      let direct = if let Some(v) = ping_map.get(&node_id).and_then(|stats| stats.avg) {
+        v
+      } else {
+        continue;
+      };
+      let best = candidates
+        .iter()
+        .filter_map(|id| Some((*id, ping_map.get(id)?.avg?)))
+        .min_by_key(|(_, avg)| *avg);
+      if let Some((relay_node_id, avg)) = best {
+        if direct.saturating_sub(avg) >= min_gain {
+          if let Some(token) = token_map.get(&player_id) {
+            relays.entry(relay_node_id).or_default().push(token.clone());
+          }
+        }
+      }
+    }
+
+    Ok(Some((target_addr, relays)))
+  }
+
+  /// Picks an enabled node that has not been tried yet and isn't overloaded,
+  /// preferring nodes in the same country as the failed one, then the least loaded
+  async fn find_alternative_node(
+    &self,
+    failed_node_id: i32,
+    tried_node_ids: &[i32],
+  ) -> Result<Option<Node>> {
+    let nodes = self.nodes.send(ListNode).await?;
+    let utilization_map = self.nodes.send(GetNodeUtilizationMap).await?;
+    let country_id = nodes
+      .iter()
+      .find(|node| node.id == failed_node_id)
+      .map(|node| node.country_id.clone());
+    let mut candidates: Vec<(Node, u32)> = nodes
+      .into_iter()
+      .filter(|node| !node.disabled && !tried_node_ids.contains(&node.id))
+      .filter_map(|node| match utilization_map.get(&node.id) {
+        Some(utilization) if utilization.is_overloaded() => None,
+        Some(utilization) => Some((node, utilization.games())),
+        None => Some((node, 0)),
+      })
+      .collect();
+    candidates.sort_by_key(|(node, games)| (Some(&node.country_id) != country_id.as_ref(), *games));
+    Ok(candidates.into_iter().next().map(|(node, _)| node))
+  }
+}
+
+/// Failures caused by the node rather than the game or its players.
+/// Requests that timed out or were cancelled are not retried,
+/// the game might have been created on the node anyway and there is no way to cancel it.
+fn is_retryable_create_error(err: &Error) -> bool {
+  use proto::flo_node::ControllerCreateGameRejectReason;
+  match err {
+    Error::ActorNotFound | Error::NodeNotReady => true,
+    Error::GameCreateReject(reason) => match reason {
+      ControllerCreateGameRejectReason::Unknown
+      | ControllerCreateGameRejectReason::Maintenance
+      | ControllerCreateGameRejectReason::NodeFull => true,
+      ControllerCreateGameRejectReason::GameExists
+      | ControllerCreateGameRejectReason::PlayerBusy => false,
+    },
+    _ => false,
+  }
 }
 
 pub struct StartGameCheckTimeout {
@@ -293,6 +528,8 @@ pub struct StartGameState {
   player_ack_map: Option<HashMap<i32, ClientInfoAck>>,
   game_addr: Addr<GameActor>,
   api_tx: Option<oneshot::Sender<StartGameCheckAsBotResult>>,
+  // span of the start request, parent of the node create game request
+  span: Span,
 }
 
 #[async_trait]
@@ -314,6 +551,7 @@ impl StartGameState {
     game_addr: Addr<GameActor>,
     player_ids: Vec<i32>,
     api_tx: Option<oneshot::Sender<StartGameCheckAsBotResult>>,
+    span: Span,
   ) -> Self {
     StartGameState {
       done: false,
@@ -326,6 +564,7 @@ impl StartGameState {
       ),
       game_addr,
       api_tx,
+      span,
     }
   }
 
@@ -466,6 +705,7 @@ impl Handler<StartGamePlayerAck> for GameActor {
     _: &mut Context<Self>,
     StartGamePlayerAck(message): StartGamePlayerAck,
   ) -> <StartGamePlayerAck as Message>::Result {
+    self.touch_player(message.player_id);
     let res = self
       .start_state
       .as_ref()
@@ -480,7 +720,8 @@ impl Handler<StartGamePlayerAck> for GameActor {
         .ok_or_else(|| Error::GameNotStarting)?;
       let start_state = start_state.shutdown().await?;
 
-      match self.start_game_proceed(proceed).await {
+      let span = start_state.span.clone();
+      match self.start_game_proceed(proceed).instrument(span).await {
         Ok(Ok(_)) => {
           if start_state.by_api() {
             let map = start_state.get_map();
@@ -549,6 +790,7 @@ pub enum StartGameCheckAsBotResult {
 
 pub struct StartGameCheckAsBot {
   pub tx: oneshot::Sender<StartGameCheckAsBotResult>,
+  pub span: Span,
 }
 
 impl Message for StartGameCheckAsBot {
@@ -560,7 +802,7 @@ impl Handler<StartGameCheckAsBot> for GameActor {
   async fn handle(
     &mut self,
     ctx: &mut Context<Self>,
-    StartGameCheckAsBot { tx }: StartGameCheckAsBot,
+    StartGameCheckAsBot { tx, span }: StartGameCheckAsBot,
   ) -> <StartGameCheckAsBot as Message>::Result {
     let game_id = self.game_id;
 
@@ -573,7 +815,7 @@ impl Handler<StartGameCheckAsBot> for GameActor {
       return Err(Error::GameStarted);
     }
 
-    self.start_state = StartGameState::new(game_id, ctx.addr(), players, Some(tx))
+    self.start_state = StartGameState::new(game_id, ctx.addr(), players, Some(tx), span)
       .start()
       .into();
 
@@ -1,7 +1,8 @@
+use crate::db::ExecutorExt;
 use crate::error::*;
+use crate::event::{ControllerEvent, ControllerEventType};
 use crate::game::state::GameActor;
 use crate::game::{db, GameStatus, NodeGameStatus, SlotClientStatus};
-use crate::player::state::sender::PlayerFrames;
 use flo_net::packet::FloPacket;
 use flo_net::proto;
 use flo_state::{async_trait, Context, Handler, Message};
@@ -41,8 +42,9 @@ impl Handler<GameSlotClientStatusUpdate> for GameActor {
 
     self
       .db
-      .exec(move |conn| db::update_slot_client_status(conn, game_id, player_id, status))
+      .exec_traced(move |conn| db::update_slot_client_status(conn, game_id, player_id, status))
       .await?;
+    self.lobby.invalidate(game_id);
 
     let mut pkt = proto::flo_connect::PacketGameSlotClientStatusUpdate {
       player_id,
@@ -82,7 +84,7 @@ impl Handler<GameStatusUpdate> for GameActor {
   ) -> Result<GameStatus> {
     self
       .db
-      .exec({
+      .exec_traced({
         let message = message.clone();
         move |conn| -> Result<_> {
           db::update_status(conn, &message)?;
@@ -90,8 +92,10 @@ impl Handler<GameStatusUpdate> for GameActor {
         }
       })
       .await?;
+    self.lobby.invalidate(self.game_id);
 
     let frame_game_status = message.to_packet().encode_as_frame()?;
+    let prev_status = self.status;
     self.status = GameStatus::from(message.status);
 
     let ended = match self.status {
@@ -99,17 +103,28 @@ impl Handler<GameStatusUpdate> for GameActor {
       _ => false,
     };
 
-    let frame_iter = self
-      .players
-      .iter()
-      .map(|player_id| (*player_id, PlayerFrames::from(frame_game_status.clone())))
-      .collect::<Vec<_>>();
+    if self.status != prev_status {
+      let event_type = match self.status {
+        GameStatus::Running if prev_status != GameStatus::Paused => {
+          Some(ControllerEventType::GameStarted)
+        }
+        _ if ended => Some(ControllerEventType::GameEnded),
+        _ => None,
+      };
+      if let Some(event_type) = event_type {
+        self
+          .events
+          .publish(ControllerEvent::game(event_type, self.game_id));
+      }
+    }
 
     self
       .player_client_status_map
       .extend(message.updated_player_game_client_status_map);
 
-    self.player_reg.broadcast_map(frame_iter).await?;
+    self
+      .broadcast_to_game(|_| Ok(Some(frame_game_status.clone().into())))
+      .await?;
 
     if ended {
       self
@@ -0,0 +1,224 @@
+//! Per-player summary of finished games, computed by the node at game end.
+
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use flo_net::proto::flo_common::PlayerGameResult;
+use flo_net::proto::flo_connect::PacketGameSummary;
+use flo_net::proto::flo_node::PacketNodeGameStats;
+use s2_grpc_utils::S2ProtoPack;
+use serde::Serialize;
+
+use crate::db::DbConn;
+use crate::error::*;
+use crate::schema::{game, game_player_stats};
+
+#[derive(Debug, Clone, Queryable, Serialize, S2ProtoPack)]
+#[s2_grpc(message_type = "flo_grpc::game::PlayerGameStats")]
+pub struct PlayerGameStats {
+  pub game_id: i32,
+  pub player_id: i32,
+  pub actions: i32,
+  pub apm: i32,
+  pub pauses: i32,
+  pub left_at_ms: Option<i32>,
+  pub first_leaver: bool,
+  pub created_at: DateTime<Utc>,
+}
+
+pub(crate) type PlayerGameStatsColumns = (
+  game_player_stats::game_id,
+  game_player_stats::player_id,
+  game_player_stats::actions,
+  game_player_stats::apm,
+  game_player_stats::pauses,
+  game_player_stats::left_at_ms,
+  game_player_stats::first_leaver,
+  game_player_stats::created_at,
+);
+
+impl PlayerGameStats {
+  pub(crate) const COLUMNS: PlayerGameStatsColumns = (
+    game_player_stats::game_id,
+    game_player_stats::player_id,
+    game_player_stats::actions,
+    game_player_stats::apm,
+    game_player_stats::pauses,
+    game_player_stats::left_at_ms,
+    game_player_stats::first_leaver,
+    game_player_stats::created_at,
+  );
+}
+
+#[derive(Debug)]
+pub struct GameStats {
+  pub duration_ms: Option<i32>,
+  pub players: Vec<PlayerGameStats>,
+}
+
+/// Stores the stats reported by `node_id`,
+/// the game must be hosted by this node.
+/// Guest players are not persisted
+pub fn save(conn: &DbConn, node_id: i32, stats: &PacketNodeGameStats) -> Result<()> {
+  use game_player_stats::dsl;
+
+  let game_id = stats.game_id;
+  conn.transaction(|| {
+    let updated =
+      diesel::update(game::table.filter(game::id.eq(game_id).and(game::node_id.eq(node_id))))
+        .set(game::duration_ms.eq(stats.duration_ms as i32))
+        .execute(conn)?;
+    if updated == 0 {
+      return Err(Error::GameNotFound);
+    }
+
+    let player_ids: Vec<i32> = stats.players.iter().map(|p| p.player_id).collect();
+    let guest_ids = crate::player::db::filter_guest_ids(conn, &player_ids)?;
+
+    let inserts: Vec<_> = stored_players(stats, &guest_ids)
+      .map(|p| {
+        (
+          dsl::game_id.eq(game_id),
+          dsl::player_id.eq(p.player_id),
+          dsl::actions.eq(p.actions as i32),
+          dsl::apm.eq(p.apm as i32),
+          dsl::pauses.eq(p.pauses as i32),
+          dsl::left_at_ms.eq(p.left_at_ms.map(|v| v as i32)),
+          dsl::first_leaver.eq(p.first_leaver),
+        )
+      })
+      .collect();
+
+    diesel::delete(game_player_stats::table.filter(dsl::game_id.eq(game_id))).execute(conn)?;
+    diesel::insert_into(game_player_stats::table)
+      .values(&inserts)
+      .execute(conn)?;
+    Ok(())
+  })
+}
+
+/// Stats of the players that are persisted, guests are left out
+fn stored_players<'a>(
+  stats: &'a PacketNodeGameStats,
+  guest_ids: &'a [i32],
+) -> impl Iterator<Item = &'a flo_net::proto::flo_node::PlayerGameStats> {
+  stats
+    .players
+    .iter()
+    .filter(move |p| !guest_ids.contains(&p.player_id))
+}
+
+/// Builds the end-of-game summary sent to each participant
+pub fn summary_packets(stats: &PacketNodeGameStats) -> Vec<(i32, PacketGameSummary)> {
+  let winner_player_ids: Vec<i32> = stats
+    .players
+    .iter()
+    .filter(|p| p.result() == PlayerGameResult::Won)
+    .map(|p| p.player_id)
+    .collect();
+  stats
+    .players
+    .iter()
+    .map(|p| {
+      (
+        p.player_id,
+        PacketGameSummary {
+          game_id: stats.game_id,
+          duration_ms: stats.duration_ms,
+          result: p.result,
+          winner_player_ids: winner_player_ids.clone(),
+          apm: p.apm,
+        },
+      )
+    })
+    .collect()
+}
+
+pub fn get(conn: &DbConn, game_id: i32) -> Result<GameStats> {
+  let duration_ms = game::table
+    .find(game_id)
+    .select(game::duration_ms)
+    .first(conn)
+    .optional()?
+    .ok_or_else(|| Error::GameNotFound)?;
+  let players = game_player_stats::table
+    .select(PlayerGameStats::COLUMNS)
+    .filter(game_player_stats::game_id.eq(game_id))
+    .order(game_player_stats::player_id)
+    .load(conn)?;
+  Ok(GameStats {
+    duration_ms,
+    players,
+  })
+}
+
+pub fn get_by_player(conn: &DbConn, player_id: i32) -> Result<Vec<PlayerGameStats>> {
+  game_player_stats::table
+    .select(PlayerGameStats::COLUMNS)
+    .filter(game_player_stats::player_id.eq(player_id))
+    .order(game_player_stats::game_id)
+    .load(conn)
+    .map_err(Into::into)
+}
+
+pub fn get_by_games(conn: &DbConn, game_ids: &[i32]) -> Result<Vec<PlayerGameStats>> {
+  game_player_stats::table
+    .select(PlayerGameStats::COLUMNS)
+    .filter(game_player_stats::game_id.eq_any(game_ids))
+    .order((game_player_stats::game_id, game_player_stats::player_id))
+    .load(conn)
+    .map_err(Into::into)
+}
+
+#[derive(Debug, Clone, QueryableByName, Serialize)]
+pub struct LeaderboardEntry {
+  #[sql_type = "diesel::sql_types::Integer"]
+  pub player_id: i32,
+  #[sql_type = "diesel::sql_types::BigInt"]
+  pub games: i64,
+  #[sql_type = "diesel::sql_types::Integer"]
+  pub avg_apm: i32,
+  #[sql_type = "diesel::sql_types::BigInt"]
+  pub first_leaves: i64,
+}
+
+/// Ranks players by the number of finished games with stats, then by average APM
+pub fn leaderboard(conn: &DbConn, take: i64) -> Result<Vec<LeaderboardEntry>> {
+  use diesel::sql_types::BigInt;
+  diesel::sql_query(
+    r#"
+    select
+      player_id,
+      count(*) as games,
+      avg(apm)::int4 as avg_apm,
+      count(*) filter (where first_leaver) as first_leaves
+    from game_player_stats
+    group by player_id
+    order by games desc, avg_apm desc, player_id
+    limit $1
+  "#,
+  )
+  .bind::<BigInt, _>(take)
+  .load(conn)
+  .map_err(Into::into)
+}
+
+#[test]
+fn test_stored_players_skip_guests() {
+  use flo_net::proto::flo_node::PlayerGameStats;
+
+  let stats = PacketNodeGameStats {
+    game_id: 1,
+    duration_ms: 60_000,
+    players: (1..=3)
+      .map(|player_id| PlayerGameStats {
+        player_id,
+        ..Default::default()
+      })
+      .collect(),
+  };
+  let ids: Vec<i32> = stored_players(&stats, &[2]).map(|p| p.player_id).collect();
+  assert_eq!(ids, vec![1, 3]);
+
+  // guests still get their summary
+  assert_eq!(summary_packets(&stats).len(), 3);
+}
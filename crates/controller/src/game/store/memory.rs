@@ -0,0 +1,420 @@
+use chrono::Utc;
+use flo_state::async_trait;
+use parking_lot::{MutexGuard, RwLock};
+use std::collections::BTreeMap;
+
+use super::shards::Shards;
+use super::LobbyStore;
+use crate::error::*;
+use crate::game::db::{check_lobby_status, LeaveGame, UpdateSlotSettings};
+use crate::game::slots::UsedSlot;
+use crate::game::{Game, GameStatus, SlotClientStatus, SlotSettings, Slots, TeamLayout};
+use crate::player::PlayerRef;
+
+/// Lobby store without a database, games and players have to be inserted first.
+/// Clan restrictions are not checked.
+#[derive(Debug)]
+pub struct MemoryLobbyStore {
+  games: Shards<MemoryGame>,
+  players: RwLock<BTreeMap<i32, PlayerRef>>,
+  mute_lists: RwLock<BTreeMap<i32, Vec<i32>>>,
+}
+
+impl Default for MemoryLobbyStore {
+  fn default() -> Self {
+    Self {
+      games: Shards::default(),
+      players: RwLock::new(BTreeMap::new()),
+      mute_lists: RwLock::new(BTreeMap::new()),
+    }
+  }
+}
+
+#[derive(Debug)]
+struct MemoryGame {
+  game: Game,
+  locked: bool,
+  reservations: BTreeMap<usize, i32>,
+  team_layout: Option<TeamLayout>,
+}
+
+impl MemoryGame {
+  fn check_lobby_status(&self) -> Result<()> {
+    check_lobby_status(self.game.status, self.locked)
+  }
+
+  fn slots(&self) -> Slots {
+    let used = self
+      .game
+      .slots
+      .iter()
+      .enumerate()
+      .filter(|(_, slot)| slot.is_used())
+      .map(UsedSlot::from)
+      .collect();
+    let mut slots = Slots::from_used(self.game.max_players as usize, used);
+    slots.set_map(&self.game.map);
+    slots.set_reservations(self.reservations.clone());
+    slots.set_team_layout(self.team_layout.clone());
+    slots
+  }
+
+  fn set_slots(&mut self, slots: Slots) -> Vec<crate::game::Slot> {
+    self.game.slots = slots.into_inner();
+    self.game.num_players = self
+      .game
+      .slots
+      .iter()
+      .filter(|slot| slot.player.is_some())
+      .count() as i32;
+    self.game.slots.clone()
+  }
+
+  fn end(&mut self) {
+    self.game.status = GameStatus::Ended;
+    self.game.ended_at = Some(Utc::now());
+  }
+}
+
+impl MemoryLobbyStore {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Adds or replaces a game, its creator is registered as a player
+  pub fn insert_game(&self, game: Game) {
+    {
+      let mut players = self.players.write();
+      for player in game
+        .slots
+        .iter()
+        .filter_map(|slot| slot.player.as_ref())
+        .chain(Some(&game.created_by))
+      {
+        players.insert(player.id, player.clone());
+      }
+    }
+    self.lock_games(game.id).insert(
+      game.id,
+      MemoryGame {
+        game,
+        locked: false,
+        reservations: BTreeMap::new(),
+        team_layout: None,
+      },
+    );
+  }
+
+  pub fn insert_player(&self, player: PlayerRef) {
+    self.players.write().insert(player.id, player);
+  }
+
+  pub fn set_mute_list(&self, player_id: i32, mute_player_ids: Vec<i32>) {
+    self.mute_lists.write().insert(player_id, mute_player_ids);
+  }
+
+  pub fn set_team_layout(&self, game_id: i32, team_layout: Option<TeamLayout>) {
+    if let Some(game) = self.lock_games(game_id).get_mut(&game_id) {
+      game.team_layout = team_layout;
+    }
+  }
+
+  /// Locked games reject all slot changes, like games being started
+  pub fn set_locked(&self, game_id: i32, locked: bool) {
+    if let Some(game) = self.lock_games(game_id).get_mut(&game_id) {
+      game.locked = locked;
+    }
+  }
+
+  pub fn get_game(&self, game_id: i32) -> Option<Game> {
+    self
+      .lock_games(game_id)
+      .get(&game_id)
+      .map(|game| game.game.clone())
+  }
+
+  fn lock_games(&self, game_id: i32) -> MutexGuard<'_, BTreeMap<i32, MemoryGame>> {
+    self.games.lock(game_id)
+  }
+
+  /// The game shard is always locked before the players
+  fn with_game<T, F>(&self, game_id: i32, f: F) -> Result<T>
+  where
+    F: FnOnce(&mut MemoryGame, &BTreeMap<i32, PlayerRef>) -> Result<T>,
+  {
+    let mut games = self.lock_games(game_id);
+    let game = games.get_mut(&game_id).ok_or_else(|| Error::GameNotFound)?;
+    let players = self.players.read();
+    f(game, &players)
+  }
+
+  fn mute_list_map(&self, player_ids: &[i32]) -> BTreeMap<i32, Vec<i32>> {
+    let mute_lists = self.mute_lists.read();
+    player_ids
+      .iter()
+      .filter_map(|id| mute_lists.get(id).map(|list| (*id, list.clone())))
+      .collect()
+  }
+}
+
+#[async_trait]
+impl LobbyStore for MemoryLobbyStore {
+  async fn join(&self, game_id: i32, player_id: i32) -> Result<(Game, Vec<i32>)> {
+    let game = self.with_game(game_id, |game, players| {
+      game.check_lobby_status()?;
+      let mut slots = game.slots();
+      slots.check_join(player_id)?;
+      let player = players
+        .get(&player_id)
+        .ok_or_else(|| Error::PlayerNotFound)?;
+      slots.join(player).ok_or_else(|| Error::GameFull)?;
+      game.set_slots(slots);
+      Ok(game.game.clone())
+    })?;
+    let mute_list = self
+      .mute_list_map(&[player_id])
+      .remove(&player_id)
+      .unwrap_or_default();
+    Ok((game, mute_list))
+  }
+
+  async fn get(&self, game_id: i32) -> Result<Game> {
+    self.with_game(game_id, |game, _| Ok(game.game.clone()))
+  }
+
+  async fn leave_lobby(&self, game_id: i32, player_id: i32) -> Result<LeaveGame> {
+    self.with_game(game_id, |game, _| {
+      game.check_lobby_status()?;
+      let mut slots = game.slots();
+      let (removed_players, game_ended) = slots.leave(player_id, game.game.created_by.id);
+      let slots = game.set_slots(slots);
+      if game_ended {
+        game.end();
+      }
+      Ok(LeaveGame {
+        game_ended,
+        removed_players,
+        slots,
+      })
+    })
+  }
+
+  async fn leave_node(&self, game_id: i32, player_id: i32) -> Result<Vec<i32>> {
+    self.with_game(game_id, |game, _| {
+      for slot in &mut game.game.slots {
+        if slot.player.as_ref().map(|p| p.id) == Some(player_id) {
+          slot.client_status = SlotClientStatus::Left;
+        }
+      }
+      Ok(
+        game
+          .game
+          .slots
+          .iter()
+          .filter(|slot| slot.client_status != SlotClientStatus::Left)
+          .filter_map(|slot| slot.player.as_ref().map(|p| p.id))
+          .collect(),
+      )
+    })
+  }
+
+  async fn update_slot(
+    &self,
+    game_id: i32,
+    player_id: i32,
+    slot_index: i32,
+    settings: SlotSettings,
+  ) -> Result<UpdateSlotSettings> {
+    self.with_game(game_id, |game, _| {
+      game.check_lobby_status()?;
+      let mut slots = game.slots();
+      let updated_indexes =
+        slots.update_slot_as(player_id, game.game.created_by.id, slot_index, &settings)?;
+      Ok(UpdateSlotSettings {
+        slots: game.set_slots(slots),
+        updated_indexes,
+      })
+    })
+  }
+
+  async fn set_slot_closed(
+    &self,
+    game_id: i32,
+    slot_index: i32,
+    closed: bool,
+  ) -> Result<UpdateSlotSettings> {
+    self.with_game(game_id, |game, _| {
+      game.check_lobby_status()?;
+      let mut slots = game.slots();
+      slots
+        .set_slot_closed(slot_index, closed)
+        .ok_or_else(|| Error::GameSlotUpdateDenied)?;
+      Ok(UpdateSlotSettings {
+        slots: game.set_slots(slots),
+        updated_indexes: vec![slot_index],
+      })
+    })
+  }
+
+  async fn reserve_slot(
+    &self,
+    game_id: i32,
+    slot_index: i32,
+    reserved_player_id: Option<i32>,
+  ) -> Result<()> {
+    self.with_game(game_id, |game, players| {
+      game.check_lobby_status()?;
+      let mut slots = game.slots();
+      slots.reserve(slot_index, reserved_player_id)?;
+      if let Some(player_id) = reserved_player_id {
+        if !players.contains_key(&player_id) {
+          return Err(Error::PlayerNotFound);
+        }
+      }
+      game.reservations = slots.reservations().clone();
+      Ok(())
+    })
+  }
+
+  async fn shuffle_slots(
+    &self,
+    game_id: i32,
+    races: bool,
+    teams: bool,
+  ) -> Result<UpdateSlotSettings> {
+    self.with_game(game_id, |game, _| {
+      if game.game.status != GameStatus::Preparing {
+        return Err(Error::GameStarted);
+      }
+      let mut slots = game.slots();
+      let updated_indexes = slots.shuffle(races, teams);
+      Ok(UpdateSlotSettings {
+        slots: game.set_slots(slots),
+        updated_indexes,
+      })
+    })
+  }
+
+  async fn shuffle_slots_on_start(
+    &self,
+    game_id: i32,
+  ) -> Result<Option<(Game, BTreeMap<i32, Vec<i32>>)>> {
+    let (races, teams) = self.with_game(game_id, |game, _| {
+      Ok((game.game.random_races, game.game.random_teams))
+    })?;
+    if !races && !teams {
+      return Ok(None);
+    }
+    self.shuffle_slots(game_id, races, teams).await?;
+    let game = self.get_game(game_id).ok_or_else(|| Error::GameNotFound)?;
+    let mute_list_map = self.mute_list_map(&game.get_player_ids());
+    Ok(Some((game, mute_list_map)))
+  }
+
+  async fn flush(&self, _game_id: i32) -> Result<()> {
+    Ok(())
+  }
+
+  fn invalidate(&self, _game_id: i32) {}
+}
+
+#[test]
+fn test_memory_lobby_join_leave() {
+  use crate::game::slots::{test_game, test_player};
+  use futures::executor::block_on;
+
+  let store = MemoryLobbyStore::new();
+  store.insert_game(test_game(1, test_player(1), 2));
+  store.insert_player(test_player(2));
+  store.insert_player(test_player(3));
+  store.set_mute_list(2, vec![1]);
+
+  let (game, mute_list) = block_on(store.join(1, 2)).unwrap();
+  assert_eq!(game.get_player_ids(), vec![1, 2]);
+  assert_eq!(game.num_players, 2);
+  assert_eq!(mute_list, vec![1]);
+
+  assert!(matches!(
+    block_on(store.join(1, 2)),
+    Err(Error::PlayerAlreadyInGame)
+  ));
+  assert!(matches!(
+    block_on(store.join(1, 4)),
+    Err(Error::PlayerNotFound)
+  ));
+
+  let leave = block_on(store.leave_lobby(1, 2)).unwrap();
+  assert!(!leave.game_ended);
+  assert_eq!(leave.removed_players, vec![2]);
+
+  block_on(store.join(1, 3)).unwrap();
+  let leave = block_on(store.leave_lobby(1, 1)).unwrap();
+  assert!(leave.game_ended);
+  assert_eq!(leave.removed_players, vec![1, 3]);
+  assert_eq!(store.get_game(1).unwrap().status, GameStatus::Ended);
+  assert!(matches!(
+    block_on(store.join(1, 2)),
+    Err(Error::GameStarted)
+  ));
+}
+
+#[test]
+fn test_memory_lobby_slots() {
+  use crate::game::slots::{test_game, test_player};
+  use futures::executor::block_on;
+
+  let store = MemoryLobbyStore::new();
+  store.insert_game(test_game(1, test_player(1), 4));
+  store.insert_player(test_player(2));
+  store.insert_player(test_player(3));
+
+  // reserved slots are skipped by other players
+  block_on(store.reserve_slot(1, 1, Some(3))).unwrap();
+  let (game, _) = block_on(store.join(1, 2)).unwrap();
+  assert_eq!(game.find_player_slot_index(2), Some(2));
+  let (game, _) = block_on(store.join(1, 3)).unwrap();
+  assert_eq!(game.find_player_slot_index(3), Some(1));
+
+  let settings = SlotSettings {
+    team: 3,
+    ..game.slots[2].settings.clone()
+  };
+  assert!(matches!(
+    block_on(store.update_slot(1, 3, 2, settings.clone())),
+    Err(Error::GameSlotUpdateDenied)
+  ));
+  let update = block_on(store.update_slot(1, 2, 2, settings)).unwrap();
+  assert!(update.updated_indexes.contains(&2));
+  assert_eq!(update.slots[2].settings.team, 3);
+
+  let update = block_on(store.set_slot_closed(1, 3, true)).unwrap();
+  assert_eq!(update.updated_indexes, vec![3]);
+  assert!(update.slots[3].is_used());
+
+  store.set_locked(1, true);
+  assert!(matches!(
+    block_on(store.set_slot_closed(1, 3, false)),
+    Err(Error::GameSlotUpdateDenied)
+  ));
+
+  let active = block_on(store.leave_node(1, 2)).unwrap();
+  assert_eq!(active, vec![1, 3]);
+}
+
+#[test]
+fn test_memory_lobby_shards() {
+  use crate::game::slots::{test_game, test_player};
+
+  let store = MemoryLobbyStore::new();
+  store.insert_game(test_game(1, test_player(1), 2));
+  store.insert_game(test_game(2, test_player(2), 2));
+
+  // the shard of game 2 is not held while game 1 is updated
+  let game = store
+    .with_game(1, |_, players| {
+      assert_eq!(players.len(), 2);
+      Ok(store.get_game(2))
+    })
+    .unwrap();
+  assert_eq!(game.map(|game| game.id), Some(2));
+}
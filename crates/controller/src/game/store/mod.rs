@@ -0,0 +1,597 @@
+//! Storage of the lobby state changed by `GameActor`: players joining or leaving and slot updates.
+//! Each method runs as a single unit of work, the Postgres store wraps them in a transaction.
+//! `MemoryLobbyStore` keeps everything in memory, to test the lobby logic without a database.
+//! `PgLobbyStore` caches the full game of each lobby, writes update the cached slots
+//! so the game is only loaded again after `invalidate`.
+//! Slot settings changes are the exception: they are applied in memory and written
+//! after `SLOT_WRITE_DELAY`, the other methods write them first, see `LobbyStore::flush`.
+//! If a write fails, the game is dropped from the cache and its id is sent to the resync
+//! channel, so the game actor broadcasts the slots stored in the database.
+//! Both stores shard their in-memory state by game id.
+
+mod memory;
+mod shards;
+
+use diesel::prelude::*;
+use flo_state::async_trait;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Debug;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc::UnboundedSender;
+use tokio::time::sleep;
+
+use crate::db::{ExecutorExt, ExecutorRef};
+use crate::error::*;
+use crate::game::db::{GetSlots, LeaveGame, UpdateSlotSettings};
+use crate::game::slots::UsedSlot;
+use crate::game::{Game, Slot, SlotSettings, Slots};
+
+pub use memory::MemoryLobbyStore;
+use shards::Shards;
+
+pub type LobbyStoreRef = Arc<dyn LobbyStore>;
+
+#[async_trait]
+pub trait LobbyStore: Debug + Send + Sync + 'static {
+  /// Adds the player to the game, returns the updated game and the mute list of the player
+  async fn join(&self, game_id: i32, player_id: i32) -> Result<(Game, Vec<i32>)>;
+
+  /// Returns the current state of the game
+  async fn get(&self, game_id: i32) -> Result<Game>;
+
+  /// Removes the player from a game that has not been created on a node yet
+  async fn leave_lobby(&self, game_id: i32, player_id: i32) -> Result<LeaveGame>;
+
+  /// Marks the player as left on the node, returns the players still in the game
+  async fn leave_node(&self, game_id: i32, player_id: i32) -> Result<Vec<i32>>;
+
+  /// Updates the settings of a slot, only allowed to the slot owner
+  async fn update_slot(
+    &self,
+    game_id: i32,
+    player_id: i32,
+    slot_index: i32,
+    settings: SlotSettings,
+  ) -> Result<UpdateSlotSettings>;
+
+  async fn set_slot_closed(
+    &self,
+    game_id: i32,
+    slot_index: i32,
+    closed: bool,
+  ) -> Result<UpdateSlotSettings>;
+
+  async fn reserve_slot(
+    &self,
+    game_id: i32,
+    slot_index: i32,
+    reserved_player_id: Option<i32>,
+  ) -> Result<()>;
+
+  async fn shuffle_slots(
+    &self,
+    game_id: i32,
+    races: bool,
+    teams: bool,
+  ) -> Result<UpdateSlotSettings>;
+
+  /// Applies the random race / team options of the game,
+  /// returns `None` if the game doesn't have any
+  async fn shuffle_slots_on_start(
+    &self,
+    game_id: i32,
+  ) -> Result<Option<(Game, BTreeMap<i32, Vec<i32>>)>>;
+
+  /// Writes the slot changes not saved yet, before the slots are read outside of the store
+  async fn flush(&self, game_id: i32) -> Result<()>;
+
+  /// Drops the cached state of the game, called after the game is updated outside of the store
+  fn invalidate(&self, game_id: i32);
+}
+
+#[derive(Debug)]
+pub struct PgLobbyStore {
+  db: ExecutorRef,
+  cache: Arc<LobbyCache>,
+}
+
+impl PgLobbyStore {
+  /// Ids of games whose slot changes could not be written are sent to `resync`
+  pub fn new(db: ExecutorRef, resync: UnboundedSender<i32>) -> Self {
+    Self {
+      db,
+      cache: Arc::new(LobbyCache::new(resync)),
+    }
+  }
+
+  pub fn into_ref(self) -> LobbyStoreRef {
+    Arc::new(self)
+  }
+}
+
+/// Slots of a lobby with changes not written yet
+#[derive(Debug)]
+struct PendingSlots {
+  host_player_id: i32,
+  version: i32,
+  slots: Slots,
+  dirty: BTreeSet<i32>,
+  // held while the changes are written, so writes of the same game don't overlap
+  write_lock: Arc<tokio::sync::Mutex<()>>,
+}
+
+/// Games and slot changes kept in memory by `PgLobbyStore`, both sharded by game id
+#[derive(Debug)]
+struct LobbyCache {
+  games: Shards<Game>,
+  pending_slots: Shards<PendingSlots>,
+  resync: UnboundedSender<i32>,
+}
+
+impl LobbyCache {
+  fn new(resync: UnboundedSender<i32>) -> Self {
+    Self {
+      games: Shards::default(),
+      pending_slots: Shards::default(),
+      resync,
+    }
+  }
+
+  fn get(&self, game_id: i32) -> Option<Game> {
+    self.games.lock(game_id).get(&game_id).cloned()
+  }
+
+  fn set(&self, game: &Game) {
+    self.games.lock(game.id).insert(game.id, game.clone());
+  }
+
+  fn update_slots(&self, game_id: i32, slots: &[Slot]) {
+    if let Some(game) = self.games.lock(game_id).get_mut(&game_id) {
+      set_slots(game, slots.to_vec());
+    }
+  }
+
+  fn invalidate(&self, game_id: i32) {
+    self.games.lock(game_id).remove(&game_id);
+  }
+
+  /// The slot changes in memory are lost, the game is loaded from the database again
+  /// and its actor broadcasts the stored slots
+  fn write_failed(&self, game_id: i32) {
+    self.pending_slots.lock(game_id).remove(&game_id);
+    self.invalidate(game_id);
+    self.resync.send(game_id).ok();
+  }
+
+  fn has_pending_slots(&self, game_id: i32) -> bool {
+    self.pending_slots.lock(game_id).contains_key(&game_id)
+  }
+
+  /// Applies a slot settings change in memory, `loaded` are the slots read from the database
+  /// if the game has no pending changes. Returns the slots, the updated indexes and whether
+  /// a write has to be scheduled
+  fn update_pending_slot(
+    &self,
+    game_id: i32,
+    loaded: Option<GetSlots>,
+    player_id: i32,
+    slot_index: i32,
+    settings: &SlotSettings,
+  ) -> Result<(Vec<Slot>, Vec<i32>, bool)> {
+    let (slots, updated_indexes, schedule_write) = {
+      let mut map = self.pending_slots.lock(game_id);
+      if let Some(loaded) = loaded {
+        map.entry(game_id).or_insert_with(|| PendingSlots {
+          host_player_id: loaded.host_player_id,
+          version: loaded.version,
+          slots: loaded.slots,
+          dirty: BTreeSet::new(),
+          write_lock: Default::default(),
+        });
+      }
+      let pending = map.get_mut(&game_id).ok_or_else(|| Error::GameNotFound)?;
+
+      let updated_indexes =
+        pending
+          .slots
+          .update_slot_as(player_id, pending.host_player_id, slot_index, settings)?;
+      let schedule_write = pending.dirty.is_empty() && !updated_indexes.is_empty();
+      pending.dirty.extend(updated_indexes.iter().cloned());
+      (pending.slots.to_vec(), updated_indexes, schedule_write)
+    };
+    self.update_slots(game_id, &slots);
+    Ok((slots, updated_indexes, schedule_write))
+  }
+}
+
+fn set_slots(game: &mut Game, slots: Vec<Slot>) {
+  game.num_players = slots.iter().filter(|s| s.player.is_some()).count() as i32;
+  game.slots = slots;
+}
+
+/// Writes the dirty slots of the game, `remove` drops the pending state once it's saved
+/// so the next slot change loads the slots from the database again.
+/// See `LobbyCache::write_failed` for failed writes
+async fn write_pending_slots(
+  db: &ExecutorRef,
+  cache: &LobbyCache,
+  game_id: i32,
+  remove: bool,
+) -> Result<()> {
+  let db = db.clone();
+  write_slots(
+    cache,
+    game_id,
+    remove,
+    move |version, updated, used| async move {
+      db.exec_traced(move |conn| {
+        crate::game::db::save_slots(conn, game_id, version, &updated, used)
+      })
+      .await
+      .map_err(Error::from)
+    },
+  )
+  .await
+}
+
+/// `save` writes the updated slots with the version they were read with
+/// and returns the new version
+async fn write_slots<F, Fut>(cache: &LobbyCache, game_id: i32, remove: bool, save: F) -> Result<()>
+where
+  F: FnOnce(i32, Vec<(i32, Slot)>, Vec<UsedSlot>) -> Fut,
+  Fut: Future<Output = Result<i32>>,
+{
+  let write_lock = match cache.pending_slots.lock(game_id).get(&game_id) {
+    Some(pending) => pending.write_lock.clone(),
+    None => return Ok(()),
+  };
+  let _guard = write_lock.lock().await;
+
+  let (version, updated, used) = {
+    let mut map = cache.pending_slots.lock(game_id);
+    let pending = match map.get_mut(&game_id) {
+      Some(pending) => pending,
+      None => return Ok(()),
+    };
+    let version = pending.version;
+    let updated: Vec<(i32, Slot)> = std::mem::take(&mut pending.dirty)
+      .into_iter()
+      .map(|index| (index, pending.slots[index as usize].clone()))
+      .collect();
+    let used = pending.slots.as_used();
+    if updated.is_empty() {
+      if remove {
+        map.remove(&game_id);
+      }
+      return Ok(());
+    }
+    (version, updated, used)
+  };
+
+  match save(version, updated, used).await {
+    Ok(version) => {
+      let mut map = cache.pending_slots.lock(game_id);
+      if let Some(pending) = map.get_mut(&game_id) {
+        pending.version = version;
+        // changes made while saving are written by the write they scheduled
+        if remove && pending.dirty.is_empty() {
+          map.remove(&game_id);
+        }
+      }
+      Ok(())
+    }
+    Err(err) => {
+      cache.write_failed(game_id);
+      Err(err)
+    }
+  }
+}
+
+#[async_trait]
+impl LobbyStore for PgLobbyStore {
+  async fn join(&self, game_id: i32, player_id: i32) -> Result<(Game, Vec<i32>)> {
+    self.flush(game_id).await?;
+    let cached = self.cache.get(game_id);
+    let (game, mute_list) = self
+      .db
+      .exec_traced(move |conn| {
+        conn.transaction(|| {
+          let slots = crate::game::db::add_player(conn, game_id, player_id)?;
+          let game = match cached {
+            Some(mut game) => {
+              set_slots(&mut game, slots);
+              game
+            }
+            None => crate::game::db::get_full(conn, game_id)?,
+          };
+          let mut mute_list_map =
+            crate::player::db::get_mute_list_map(conn, &game.get_player_ids())?;
+          Ok::<_, Error>((game, mute_list_map.remove(&player_id).unwrap_or_default()))
+        })
+      })
+      .await?;
+    self.cache.set(&game);
+    Ok((game, mute_list))
+  }
+
+  async fn get(&self, game_id: i32) -> Result<Game> {
+    if let Some(game) = self.cache.get(game_id) {
+      return Ok(game);
+    }
+    let game = self
+      .db
+      .exec_traced(move |conn| crate::game::db::get_full(conn, game_id))
+      .await?;
+    self.cache.set(&game);
+    Ok(game)
+  }
+
+  async fn leave_lobby(&self, game_id: i32, player_id: i32) -> Result<LeaveGame> {
+    self.flush(game_id).await?;
+    let leave = self
+      .db
+      .exec_traced(move |conn| crate::game::db::remove_player(conn, game_id, player_id))
+      .await?;
+    if leave.game_ended {
+      self.invalidate(game_id);
+    } else {
+      self.cache.update_slots(game_id, &leave.slots);
+    }
+    Ok(leave)
+  }
+
+  async fn leave_node(&self, game_id: i32, player_id: i32) -> Result<Vec<i32>> {
+    self.flush(game_id).await?;
+    self.invalidate(game_id);
+    self
+      .db
+      .exec_traced(move |conn| {
+        conn.transaction(|| {
+          crate::game::db::leave_node(conn, game_id, player_id)?;
+          crate::game::db::get_node_active_player_ids(conn, game_id)
+        })
+      })
+      .await
+      .map_err(Into::into)
+  }
+
+  async fn update_slot(
+    &self,
+    game_id: i32,
+    player_id: i32,
+    slot_index: i32,
+    settings: SlotSettings,
+  ) -> Result<UpdateSlotSettings> {
+    let loaded = if self.cache.has_pending_slots(game_id) {
+      None
+    } else {
+      let loaded = self
+        .db
+        .exec_traced(move |conn| crate::game::db::get_lobby_slots(conn, game_id))
+        .await?;
+      Some(loaded)
+    };
+
+    let (slots, updated_indexes, schedule_write) = self
+      .cache
+      .update_pending_slot(game_id, loaded, player_id, slot_index, &settings)?;
+
+    if schedule_write {
+      let delay = *crate::config::SLOT_WRITE_DELAY;
+      if delay == Duration::from_secs(0) {
+        write_pending_slots(&self.db, &self.cache, game_id, false).await?;
+      } else {
+        let db = self.db.clone();
+        let cache = self.cache.clone();
+        tokio::spawn(async move {
+          sleep(delay).await;
+          if let Err(err) = write_pending_slots(&db, &cache, game_id, false).await {
+            tracing::error!(game_id, "write slot changes: {}", err);
+          }
+        });
+      }
+    }
+
+    Ok(UpdateSlotSettings {
+      slots,
+      updated_indexes,
+    })
+  }
+
+  async fn set_slot_closed(
+    &self,
+    game_id: i32,
+    slot_index: i32,
+    closed: bool,
+  ) -> Result<UpdateSlotSettings> {
+    self.flush(game_id).await?;
+    let update = self
+      .db
+      .exec_traced(move |conn| {
+        conn.transaction(|| crate::game::db::set_slot_closed(conn, game_id, slot_index, closed))
+      })
+      .await?;
+    self.cache.update_slots(game_id, &update.slots);
+    Ok(update)
+  }
+
+  async fn reserve_slot(
+    &self,
+    game_id: i32,
+    slot_index: i32,
+    reserved_player_id: Option<i32>,
+  ) -> Result<()> {
+    self.flush(game_id).await?;
+    self
+      .db
+      .exec_traced(move |conn| {
+        conn.transaction(|| {
+          crate::game::db::reserve_slot(conn, game_id, slot_index, reserved_player_id)
+        })
+      })
+      .await?;
+    self.invalidate(game_id);
+    Ok(())
+  }
+
+  async fn shuffle_slots(
+    &self,
+    game_id: i32,
+    races: bool,
+    teams: bool,
+  ) -> Result<UpdateSlotSettings> {
+    self.flush(game_id).await?;
+    let update = self
+      .db
+      .exec_traced(move |conn| {
+        conn.transaction(|| crate::game::db::shuffle_slots(conn, game_id, races, teams))
+      })
+      .await?;
+    self.cache.update_slots(game_id, &update.slots);
+    Ok(update)
+  }
+
+  async fn shuffle_slots_on_start(
+    &self,
+    game_id: i32,
+  ) -> Result<Option<(Game, BTreeMap<i32, Vec<i32>>)>> {
+    self.flush(game_id).await?;
+    let res = self
+      .db
+      .exec_traced(move |conn| {
+        let game = match crate::game::db::shuffle_slots_on_start(conn, game_id)? {
+          Some(game) => game,
+          None => return Ok(None),
+        };
+        let mute_list_map = crate::player::db::get_mute_list_map(conn, &game.get_player_ids())?;
+        Ok::<_, Error>(Some((game, mute_list_map)))
+      })
+      .await?;
+    if let Some((ref game, _)) = res {
+      self.cache.set(game);
+    }
+    Ok(res)
+  }
+
+  async fn flush(&self, game_id: i32) -> Result<()> {
+    write_pending_slots(&self.db, &self.cache, game_id, true).await
+  }
+
+  fn invalidate(&self, game_id: i32) {
+    self.cache.invalidate(game_id);
+  }
+}
+
+#[test]
+fn test_lobby_cache_contention() {
+  use crate::game::slots::{test_game, test_player};
+
+  let (resync, _) = tokio::sync::mpsc::unbounded_channel();
+  let cache = Arc::new(LobbyCache::new(resync));
+  for id in 1..=8 {
+    cache.set(&test_game(id, test_player(id), 2));
+  }
+
+  // games of other shards are read and updated while the shard of game 1 is held
+  let guard = cache.games.lock(1);
+  let threads: Vec<_> = (2..=8)
+    .map(|id| {
+      let cache = cache.clone();
+      std::thread::spawn(move || {
+        for _ in 0..100 {
+          let game = cache.get(id).unwrap();
+          cache.update_slots(id, &game.slots);
+        }
+        cache.get(id).unwrap()
+      })
+    })
+    .collect();
+  for (id, thread) in (2..=8).zip(threads) {
+    let game = thread.join().unwrap();
+    assert_eq!(game.id, id);
+    assert_eq!(game.num_players, 1);
+  }
+  drop(guard);
+
+  cache.invalidate(1);
+  assert!(cache.get(1).is_none());
+  assert!(cache.get(2).is_some());
+}
+
+#[test]
+fn test_lobby_cache_write_failure() {
+  use crate::game::slots::{test_game, test_player};
+  use futures::executor::block_on;
+
+  let (resync, mut resync_rx) = tokio::sync::mpsc::unbounded_channel();
+  let cache = LobbyCache::new(resync);
+  let game = test_game(1, test_player(1), 2);
+  cache.set(&game);
+
+  let mut slots = Slots::new(2);
+  let current = slots.join(&test_player(1)).unwrap().settings.clone();
+  let loaded = GetSlots {
+    host_player_id: 1,
+    version: 0,
+    slots,
+  };
+  let team = (current.team + 1) % 2;
+  let settings = SlotSettings { team, ..current };
+  let (_, updated_indexes, schedule_write) = cache
+    .update_pending_slot(1, Some(loaded), 1, 0, &settings)
+    .unwrap();
+  assert_eq!(updated_indexes, vec![0]);
+  assert!(schedule_write);
+  assert_eq!(cache.get(1).unwrap().slots[0].settings.team, team);
+
+  let res = block_on(write_slots(&cache, 1, false, |_, _, _| async {
+    Err::<i32, _>(Error::GameVersionConflict {
+      version: 1,
+      slots: vec![],
+    })
+  }));
+  assert!(res.is_err());
+
+  // the lost changes are dropped and the game actor is asked to broadcast the stored slots
+  assert!(!cache.has_pending_slots(1));
+  assert!(cache.get(1).is_none());
+  assert_eq!(resync_rx.try_recv().ok(), Some(1));
+}
+
+#[test]
+fn test_lobby_cache_flush() {
+  use crate::game::slots::{test_game, test_player};
+  use futures::executor::block_on;
+
+  let (resync, _) = tokio::sync::mpsc::unbounded_channel();
+  let cache = LobbyCache::new(resync);
+  cache.set(&test_game(1, test_player(1), 2));
+
+  let mut slots = Slots::new(2);
+  let current = slots.join(&test_player(1)).unwrap().settings.clone();
+  let loaded = GetSlots {
+    host_player_id: 1,
+    version: 0,
+    slots,
+  };
+  let settings = SlotSettings {
+    team: (current.team + 1) % 2,
+    ..current
+  };
+  cache
+    .update_pending_slot(1, Some(loaded), 1, 0, &settings)
+    .unwrap();
+
+  // the pending slots are kept until they are saved
+  let res = block_on(write_slots(&cache, 1, true, |version, updated, _| {
+    assert_eq!(version, 0);
+    assert_eq!(updated.len(), 1);
+    assert!(cache.has_pending_slots(1));
+    async { Ok(1) }
+  }));
+  assert!(res.is_ok());
+  assert!(!cache.has_pending_slots(1));
+  assert!(cache.get(1).is_some());
+}
@@ -0,0 +1,32 @@
+use parking_lot::{Mutex, MutexGuard};
+use std::collections::BTreeMap;
+use std::time::Instant;
+
+/// Games are split in shards by id, so lobbies only contend with the lobbies of the same shard
+const SHARDS: usize = 16;
+
+/// Values keyed by game id, with one lock per shard
+#[derive(Debug)]
+pub struct Shards<V> {
+  shards: Vec<Mutex<BTreeMap<i32, V>>>,
+}
+
+impl<V> Default for Shards<V> {
+  fn default() -> Self {
+    Self {
+      shards: (0..SHARDS).map(|_| Mutex::new(BTreeMap::new())).collect(),
+    }
+  }
+}
+
+impl<V> Shards<V> {
+  /// Locks the shard of the game, the wait time is recorded in
+  /// [`LOBBY_STORE_LOCK_WAIT`](crate::metrics::LOBBY_STORE_LOCK_WAIT)
+  pub fn lock(&self, game_id: i32) -> MutexGuard<'_, BTreeMap<i32, V>> {
+    let shard = &self.shards[game_id.rem_euclid(SHARDS as i32) as usize];
+    let t = Instant::now();
+    let guard = shard.lock();
+    crate::metrics::LOBBY_STORE_LOCK_WAIT.observe(t.elapsed().as_secs_f64());
+    guard
+  }
+}
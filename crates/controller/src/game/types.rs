@@ -31,12 +31,20 @@ pub struct Game {
   pub updated_at: DateTime<Utc>,
   pub mask_player_names: bool,
   pub game_version: Option<String>,
+  #[s2_grpc(proto_enum)]
+  pub game_mode: GameMode,
+  pub random_races: bool,
+  pub random_teams: bool,
+  pub equalize_ping: bool,
+  pub step_min: Option<i32>,
+  pub step_max: Option<i32>,
 }
 
 impl S2ProtoPack<flo_net::proto::flo_connect::GameInfo> for Game {
   fn pack(self) -> Result<flo_net::proto::flo_connect::GameInfo, s2_grpc_utils::result::Error> {
     use flo_net::proto::flo_connect::*;
     let status: flo_net::proto::flo_connect::GameStatus = self.status.into_proto_enum();
+    let game_mode: flo_net::proto::flo_common::GameMode = self.game_mode.into_proto_enum();
     Ok(GameInfo {
       id: self.id,
       name: self.name,
@@ -52,6 +60,9 @@ impl S2ProtoPack<flo_net::proto::flo_connect::GameInfo> for Game {
       is_live: self.is_live,
       random_seed: self.random_seed,
       created_by: self.created_by.pack()?,
+      game_mode: game_mode.into(),
+      random_races: self.random_races,
+      random_teams: self.random_teams,
     })
   }
 }
@@ -75,6 +86,18 @@ impl Game {
     })
   }
 
+  /// Replaces player names with slot numbers if `mask_player_names` is set
+  pub fn apply_player_name_mask(&mut self) {
+    if self.mask_player_names {
+      for (idx, slot) in self.slots.iter_mut().enumerate() {
+        slot
+          .player
+          .as_mut()
+          .map(|v| v.name = format!("Player {}", idx + 1));
+      }
+    }
+  }
+
   pub fn get_player_slot_info(&self, player_id: i32) -> Option<PlayerSlotInfo> {
     let slot_index = self.find_player_slot_index(player_id)?;
 
@@ -95,7 +118,7 @@ pub struct PlayerSlotInfo<'a> {
   pub player: &'a PlayerRef,
 }
 
-#[derive(Debug, Serialize, Deserialize, S2ProtoPack, S2ProtoUnpack, Queryable)]
+#[derive(Debug, Serialize, Deserialize, S2ProtoPack, S2ProtoUnpack, Queryable, Clone)]
 #[s2_grpc(message_type(flo_grpc::game::GameEntry))]
 pub struct GameEntry {
   pub id: i32,
@@ -153,6 +176,27 @@ impl GameEntry {
   }
 }
 
+impl From<&Game> for GameEntry {
+  fn from(game: &Game) -> Self {
+    GameEntry {
+      id: game.id,
+      name: game.name.clone(),
+      map_name: game.map.name.clone(),
+      status: game.status,
+      is_private: game.is_private,
+      is_live: game.is_live,
+      num_players: game.num_players,
+      max_players: game.max_players,
+      started_at: game.started_at,
+      ended_at: game.ended_at,
+      created_at: game.created_at,
+      updated_at: game.updated_at,
+      node: game.node.clone(),
+      created_by: Some(game.created_by.clone()),
+    }
+  }
+}
+
 #[derive(Debug, Serialize, Deserialize, Copy, Clone, PartialEq, BSDieselEnum, S2ProtoEnum)]
 #[repr(i32)]
 #[s2_grpc(proto_enum_type(flo_grpc::game::GameStatus, flo_net::proto::flo_connect::GameStatus))]
@@ -175,6 +219,28 @@ impl GameStatus {
   }
 }
 
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, PartialEq, BSDieselEnum, S2ProtoEnum)]
+#[repr(i32)]
+#[s2_grpc(proto_enum_type(flo_grpc::game::GameMode, flo_net::proto::flo_common::GameMode))]
+pub enum GameMode {
+  Melee = 0,
+  FFA = 1,
+  Custom = 2,
+}
+
+impl Default for GameMode {
+  fn default() -> Self {
+    GameMode::Melee
+  }
+}
+
+impl GameMode {
+  /// Custom games never affect ladder ratings
+  pub fn is_rated(&self) -> bool {
+    !matches!(self, GameMode::Custom)
+  }
+}
+
 #[derive(Debug, Serialize, Deserialize, Copy, Clone, PartialEq, S2ProtoEnum)]
 #[repr(i32)]
 #[s2_grpc(proto_enum_type(flo_net::proto::flo_node::NodeGameStatus))]
@@ -264,6 +330,11 @@ impl SlotSettings {
     game_used_slot::dsl::status,
     game_used_slot::dsl::race,
   );
+
+  /// W3GS only accepts handicap values of 50 to 100 in steps of 10
+  pub fn normalize_handicap(value: i32) -> Option<i32> {
+    flo_types::game::SlotSettings::normalize_handicap(value)
+  }
 }
 
 impl Default for SlotSettings {
@@ -0,0 +1,604 @@
+//! Read-only GraphQL view of the lobby data (games, slots, players, results and leaderboards),
+//! so websites can fetch the projections they need in a single request.
+//! Players and game stats are batched with data loaders to avoid N+1 queries.
+
+use async_graphql::dataloader::{DataLoader, Loader};
+use async_graphql::http::{playground_source, GraphQLPlaygroundConfig};
+use async_graphql::{Context, EmptyMutation, EmptySubscription, Enum, Object, Schema};
+use async_graphql_axum::{GraphQLRequest, GraphQLResponse};
+use axum::response::{self, IntoResponse};
+use axum::routing::get;
+use axum::{extract, AddExtensionLayer, Router, Server};
+use chrono::{DateTime, Utc};
+use flo_state::async_trait;
+use std::collections::HashMap;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+
+use crate::db::ExecutorExt;
+use crate::error::*;
+use crate::game::db::{PlayerGameRecord, QueryGameParams};
+use crate::game::stats::{LeaderboardEntry, PlayerGameStats};
+use crate::game::{self, GameEntry};
+use crate::node::NodeRef;
+use crate::player::PlayerRef;
+use crate::state::ControllerStateRef;
+
+const MAX_TAKE: i64 = 100;
+const MAX_QUERY_DEPTH: usize = 8;
+
+pub type ControllerSchema = Schema<QueryRoot, EmptyMutation, EmptySubscription>;
+
+pub fn schema(state: ControllerStateRef) -> ControllerSchema {
+  Schema::build(QueryRoot, EmptyMutation, EmptySubscription)
+    .limit_depth(MAX_QUERY_DEPTH)
+    .data(DataLoader::new(PlayerLoader(state.clone()), tokio::spawn))
+    .data(DataLoader::new(
+      GameStatsLoader(state.clone()),
+      tokio::spawn,
+    ))
+    .data(state)
+    .finish()
+}
+
+pub async fn serve(state: ControllerStateRef) -> Result<()> {
+  let addr = SocketAddr::from((
+    Ipv4Addr::UNSPECIFIED,
+    flo_constants::CONTROLLER_GRAPHQL_PORT,
+  ));
+  let app = Router::new()
+    .route("/", get(graphql_playground).post(graphql_handler))
+    .layer(AddExtensionLayer::new(schema(state)));
+  tracing::info!("graphql listening on {}", addr);
+  Server::bind(&addr).serve(app.into_make_service()).await?;
+  Ok(())
+}
+
+async fn graphql_handler(
+  schema: extract::Extension<ControllerSchema>,
+  req: GraphQLRequest,
+) -> GraphQLResponse {
+  schema.execute(req.into_inner()).await.into()
+}
+
+async fn graphql_playground() -> impl IntoResponse {
+  response::Html(playground_source(GraphQLPlaygroundConfig::new("/")))
+}
+
+pub struct QueryRoot;
+
+#[Object]
+impl QueryRoot {
+  /// Private games are not exposed
+  async fn game(&self, ctx: &Context<'_>, id: i32) -> async_graphql::Result<Option<Game>> {
+    let state = ctx.data_unchecked::<ControllerStateRef>();
+    let game = state
+      .db
+      .exec_traced(move |conn| match game::db::get_full(conn, id) {
+        Ok(game) => Ok(Some(game)),
+        Err(Error::GameNotFound) => Ok(None),
+        Err(err) => Err(err),
+      })
+      .await
+      .map_err(Error::from)?;
+    Ok(game.filter(|game| !game.is_private).map(Game))
+  }
+
+  async fn games(
+    &self,
+    ctx: &Context<'_>,
+    keyword: Option<String>,
+    #[graphql(default_with = "GameStatusFilter::All")] status: GameStatusFilter,
+    is_live: Option<bool>,
+    take: Option<i64>,
+    since_id: Option<i32>,
+  ) -> async_graphql::Result<GamePage> {
+    let state = ctx.data_unchecked::<ControllerStateRef>();
+    let params = QueryGameParams {
+      keyword,
+      status: status.into(),
+      is_private: Some(false),
+      is_live,
+      take: take.map(|v| v.min(MAX_TAKE)),
+      since_id,
+    };
+    let res = state
+      .db
+      .exec_traced(move |conn| game::db::query(conn, &params))
+      .await
+      .map_err(Error::from)?;
+    Ok(GamePage {
+      games: res.games.into_iter().map(GameListEntry).collect(),
+      has_more: res.has_more,
+    })
+  }
+
+  async fn player(&self, ctx: &Context<'_>, id: i32) -> async_graphql::Result<Option<Player>> {
+    let loader = ctx.data_unchecked::<DataLoader<PlayerLoader>>();
+    Ok(loader.load_one(id).await?.map(Player))
+  }
+
+  async fn leaderboard(
+    &self,
+    ctx: &Context<'_>,
+    #[graphql(default = 50)] take: i64,
+  ) -> async_graphql::Result<Vec<LeaderboardItem>> {
+    let state = ctx.data_unchecked::<ControllerStateRef>();
+    let take = take.max(0).min(MAX_TAKE);
+    let entries = state
+      .db
+      .exec_traced(move |conn| game::stats::leaderboard(conn, take))
+      .await
+      .map_err(Error::from)?;
+    Ok(entries.into_iter().map(LeaderboardItem).collect())
+  }
+}
+
+pub struct GamePage {
+  games: Vec<GameListEntry>,
+  has_more: bool,
+}
+
+#[Object]
+impl GamePage {
+  async fn games(&self) -> &[GameListEntry] {
+    &self.games
+  }
+
+  async fn has_more(&self) -> bool {
+    self.has_more
+  }
+}
+
+pub struct GameListEntry(GameEntry);
+
+#[Object(name = "GameListEntry")]
+impl GameListEntry {
+  async fn id(&self) -> i32 {
+    self.0.id
+  }
+
+  async fn name(&self) -> &str {
+    &self.0.name
+  }
+
+  async fn map_name(&self) -> &str {
+    &self.0.map_name
+  }
+
+  async fn status(&self) -> GameStatus {
+    self.0.status.into()
+  }
+
+  async fn is_live(&self) -> bool {
+    self.0.is_live
+  }
+
+  async fn num_players(&self) -> i32 {
+    self.0.num_players
+  }
+
+  async fn max_players(&self) -> i32 {
+    self.0.max_players
+  }
+
+  async fn node(&self) -> Option<Node> {
+    self.0.node.clone().map(Node)
+  }
+
+  async fn created_by(&self) -> Option<Player> {
+    self.0.created_by.clone().map(Player)
+  }
+
+  async fn created_at(&self) -> DateTime<Utc> {
+    self.0.created_at
+  }
+
+  async fn started_at(&self) -> Option<DateTime<Utc>> {
+    self.0.started_at
+  }
+
+  async fn ended_at(&self) -> Option<DateTime<Utc>> {
+    self.0.ended_at
+  }
+}
+
+pub struct Game(game::Game);
+
+impl Game {
+  /// Names stay hidden until the game is over if the creator asked for it
+  fn masks_players(&self) -> bool {
+    self.0.mask_player_names && self.0.status.is_active()
+  }
+}
+
+#[Object(name = "Game")]
+impl Game {
+  async fn id(&self) -> i32 {
+    self.0.id
+  }
+
+  async fn name(&self) -> &str {
+    &self.0.name
+  }
+
+  async fn status(&self) -> GameStatus {
+    self.0.status.into()
+  }
+
+  async fn map_name(&self) -> &str {
+    &self.0.map.name
+  }
+
+  async fn map_sha1(&self) -> String {
+    hex::encode(&self.0.map.sha1.0)
+  }
+
+  async fn slots(&self) -> Vec<Slot> {
+    let masked = self.masks_players();
+    self
+      .0
+      .slots
+      .iter()
+      .enumerate()
+      .filter(|(_, slot)| slot.is_used())
+      .map(|(index, slot)| Slot {
+        index: index as i32,
+        slot: slot.clone(),
+        masked,
+      })
+      .collect()
+  }
+
+  async fn node(&self) -> Option<Node> {
+    self.0.node.clone().map(Node)
+  }
+
+  async fn is_live(&self) -> bool {
+    self.0.is_live
+  }
+
+  async fn num_players(&self) -> i32 {
+    self.0.num_players
+  }
+
+  async fn max_players(&self) -> i32 {
+    self.0.max_players
+  }
+
+  async fn created_by(&self) -> Option<Player> {
+    if self.masks_players() {
+      return None;
+    }
+    Some(Player(self.0.created_by.clone()))
+  }
+
+  async fn created_at(&self) -> DateTime<Utc> {
+    self.0.created_at
+  }
+
+  async fn started_at(&self) -> Option<DateTime<Utc>> {
+    self.0.started_at
+  }
+
+  async fn ended_at(&self) -> Option<DateTime<Utc>> {
+    self.0.ended_at
+  }
+
+  async fn game_version(&self) -> Option<&str> {
+    self.0.game_version.as_deref()
+  }
+
+  /// Empty until the node reported the results
+  async fn stats(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<PlayerStats>> {
+    let loader = ctx.data_unchecked::<DataLoader<GameStatsLoader>>();
+    Ok(
+      loader
+        .load_one(self.0.id)
+        .await?
+        .unwrap_or_default()
+        .into_iter()
+        .map(PlayerStats)
+        .collect(),
+    )
+  }
+}
+
+pub struct Slot {
+  index: i32,
+  slot: game::Slot,
+  masked: bool,
+}
+
+#[Object]
+impl Slot {
+  async fn index(&self) -> i32 {
+    self.index
+  }
+
+  async fn player(&self) -> Option<Player> {
+    if self.masked {
+      return None;
+    }
+    self.slot.player.clone().map(Player)
+  }
+
+  async fn team(&self) -> i32 {
+    self.slot.settings.team
+  }
+
+  async fn color(&self) -> i32 {
+    self.slot.settings.color
+  }
+
+  async fn race(&self) -> Race {
+    self.slot.settings.race.into()
+  }
+
+  async fn computer(&self) -> Option<Computer> {
+    if self.slot.player.is_some() {
+      return None;
+    }
+    Some(self.slot.settings.computer.into())
+  }
+
+  async fn handicap(&self) -> i32 {
+    self.slot.settings.handicap
+  }
+}
+
+pub struct Node(NodeRef);
+
+#[Object(name = "Node")]
+impl Node {
+  async fn id(&self) -> i32 {
+    self.0.id
+  }
+
+  async fn name(&self) -> &str {
+    &self.0.name
+  }
+
+  async fn location(&self) -> &str {
+    &self.0.location
+  }
+
+  async fn country_id(&self) -> &str {
+    &self.0.country_id
+  }
+}
+
+pub struct Player(PlayerRef);
+
+#[Object(name = "Player")]
+impl Player {
+  async fn id(&self) -> i32 {
+    self.0.id
+  }
+
+  async fn name(&self) -> &str {
+    &self.0.name
+  }
+
+  async fn realm(&self) -> Option<&str> {
+    self.0.realm.as_deref()
+  }
+
+  async fn clan_tag(&self) -> Option<&str> {
+    self.0.clan_tag.as_deref()
+  }
+
+  /// Games this player occupied a slot in, latest first
+  async fn games(
+    &self,
+    ctx: &Context<'_>,
+    #[graphql(default = 30)] take: i64,
+  ) -> async_graphql::Result<Vec<PlayerGame>> {
+    let state = ctx.data_unchecked::<ControllerStateRef>();
+    let player_id = self.0.id;
+    let records = state
+      .db
+      .exec_traced(move |conn| game::db::get_player_game_records(conn, player_id))
+      .await
+      .map_err(Error::from)?;
+    let take = take.max(0).min(MAX_TAKE) as usize;
+    Ok(
+      records
+        .into_iter()
+        .rev()
+        .take(take)
+        .map(PlayerGame)
+        .collect(),
+    )
+  }
+}
+
+pub struct PlayerGame(PlayerGameRecord);
+
+#[Object]
+impl PlayerGame {
+  async fn game_id(&self) -> i32 {
+    self.0.game_id
+  }
+
+  async fn name(&self) -> &str {
+    &self.0.name
+  }
+
+  async fn map_name(&self) -> &str {
+    &self.0.map_name
+  }
+
+  async fn status(&self) -> GameStatus {
+    self.0.status.into()
+  }
+
+  async fn slot_index(&self) -> i32 {
+    self.0.slot_index
+  }
+
+  async fn team(&self) -> i32 {
+    self.0.settings.team
+  }
+
+  async fn race(&self) -> Race {
+    self.0.settings.race.into()
+  }
+
+  async fn created_at(&self) -> DateTime<Utc> {
+    self.0.created_at
+  }
+
+  async fn started_at(&self) -> Option<DateTime<Utc>> {
+    self.0.started_at
+  }
+
+  async fn ended_at(&self) -> Option<DateTime<Utc>> {
+    self.0.ended_at
+  }
+
+  async fn duration_ms(&self) -> Option<i32> {
+    self.0.duration_ms
+  }
+
+  async fn stats(&self) -> Option<PlayerStats> {
+    self.0.stats.clone().map(PlayerStats)
+  }
+}
+
+pub struct PlayerStats(PlayerGameStats);
+
+#[Object]
+impl PlayerStats {
+  async fn game_id(&self) -> i32 {
+    self.0.game_id
+  }
+
+  async fn player(&self, ctx: &Context<'_>) -> async_graphql::Result<Option<Player>> {
+    let loader = ctx.data_unchecked::<DataLoader<PlayerLoader>>();
+    Ok(loader.load_one(self.0.player_id).await?.map(Player))
+  }
+
+  async fn actions(&self) -> i32 {
+    self.0.actions
+  }
+
+  async fn apm(&self) -> i32 {
+    self.0.apm
+  }
+
+  async fn pauses(&self) -> i32 {
+    self.0.pauses
+  }
+
+  async fn left_at_ms(&self) -> Option<i32> {
+    self.0.left_at_ms
+  }
+
+  async fn first_leaver(&self) -> bool {
+    self.0.first_leaver
+  }
+}
+
+pub struct LeaderboardItem(LeaderboardEntry);
+
+#[Object]
+impl LeaderboardItem {
+  async fn player(&self, ctx: &Context<'_>) -> async_graphql::Result<Option<Player>> {
+    let loader = ctx.data_unchecked::<DataLoader<PlayerLoader>>();
+    Ok(loader.load_one(self.0.player_id).await?.map(Player))
+  }
+
+  async fn games(&self) -> i64 {
+    self.0.games
+  }
+
+  async fn avg_apm(&self) -> i32 {
+    self.0.avg_apm
+  }
+
+  async fn first_leaves(&self) -> i64 {
+    self.0.first_leaves
+  }
+}
+
+#[derive(Enum, Copy, Clone, Eq, PartialEq)]
+#[graphql(remote = "crate::game::GameStatus")]
+pub enum GameStatus {
+  Preparing,
+  Created,
+  Running,
+  Ended,
+  Paused,
+  Terminated,
+}
+
+#[derive(Enum, Copy, Clone, Eq, PartialEq)]
+#[graphql(remote = "crate::game::db::GameStatusFilter")]
+pub enum GameStatusFilter {
+  All,
+  Open,
+  Live,
+  Ended,
+  Active,
+}
+
+#[derive(Enum, Copy, Clone, Eq, PartialEq)]
+#[graphql(remote = "crate::game::Race")]
+pub enum Race {
+  Human,
+  Orc,
+  NightElf,
+  Undead,
+  Random,
+}
+
+#[derive(Enum, Copy, Clone, Eq, PartialEq)]
+#[graphql(remote = "crate::game::Computer")]
+pub enum Computer {
+  Easy,
+  Normal,
+  Insane,
+}
+
+pub struct PlayerLoader(ControllerStateRef);
+
+#[async_trait]
+impl Loader<i32> for PlayerLoader {
+  type Value = PlayerRef;
+  type Error = Arc<Error>;
+
+  async fn load(&self, keys: &[i32]) -> Result<HashMap<i32, PlayerRef>, Self::Error> {
+    let ids = keys.to_vec();
+    let players = self
+      .0
+      .db
+      .exec_traced(move |conn| crate::player::db::get_refs_by_ids(conn, &ids))
+      .await
+      .map_err(|err| Arc::new(Error::from(err)))?;
+    Ok(players.into_iter().map(|p| (p.id, p)).collect())
+  }
+}
+
+pub struct GameStatsLoader(ControllerStateRef);
+
+#[async_trait]
+impl Loader<i32> for GameStatsLoader {
+  type Value = Vec<PlayerGameStats>;
+  type Error = Arc<Error>;
+
+  async fn load(&self, keys: &[i32]) -> Result<HashMap<i32, Self::Value>, Self::Error> {
+    let ids = keys.to_vec();
+    let stats = self
+      .0
+      .db
+      .exec_traced(move |conn| game::stats::get_by_games(conn, &ids))
+      .await
+      .map_err(|err| Arc::new(Error::from(err)))?;
+    let mut map: HashMap<i32, Self::Value> = HashMap::new();
+    for item in stats {
+      map.entry(item.game_id).or_default().push(item);
+    }
+    Ok(map)
+  }
+}
@@ -1,24 +1,48 @@
-use crate::config::{ApiRequestExt, GetInterceptor};
+use crate::audit::{AuditAction, AuditActor};
+use crate::config::{ApiRequestExt, GetInterceptor, REQUEST_META_SECRET};
+use crate::db::ExecutorExt;
 use crate::error::{Error, Result};
+use crate::event::{ControllerEventType, EventFilter, EventSigner};
 use crate::game::db::{CreateGameAsBotParams, CreateGameParams};
-use crate::game::messages::{CreateGame, PlayerJoin, PlayerLeave};
+use crate::game::messages::{
+  CreateGame, PlayerJoin, PlayerLeave, RehostGame, SetReferee, StartMapVote,
+};
 use crate::game::state::cancel::CancelGame;
 use crate::game::state::create::CreateGameAsBot;
 use crate::game::state::node::SelectNode;
-use crate::game::state::registry::{AddGamePlayer, Remove, RemoveGamePlayer, UpdateGameNodeCache};
+use crate::game::state::registry::{
+  AddGamePlayer, Remove, RemoveGamePlayer, ResolveGamePlayerPeers, UpdateGameNodeCache,
+  UpdatePlayerBans,
+};
+use crate::game::state::slot::FlushLobby;
 use crate::game::state::start::{StartGameCheckAsBot, StartGameCheckAsBotResult};
-use crate::node::messages::ListNode;
-use crate::player::state::ping::GetPlayersPingSnapshot;
-use crate::player::{PlayerBanType, PlayerSource, SourceState};
-use crate::state::{ActorMapExt, ControllerStateRef};
+use crate::map::Map;
+use crate::node::capacity::NodeCapacity;
+use crate::node::db::RegisterNodeParams;
+use crate::node::messages::{GetNodeUtilizationMap, ListNode};
+use crate::player::auth::AuthCredentials;
+use crate::player::report::{CreatePlayerReportParams, PlayerReportCategory, PlayerReportStatus};
+use crate::player::{PlayerBanType, PlayerRef, PlayerSource, SourceState};
+use crate::state::{ActorMapExt, ControllerStateRef, Reload};
 use bs_diesel_utils::executor::ExecutorError;
 use chrono::{DateTime, Utc};
+use diesel::Connection;
 use flo_grpc::controller::flo_controller_server::*;
 use flo_grpc::controller::*;
+use flo_net::packet::FloPacket;
+use flo_net::proto::flo_connect::PacketPlayerInfoUpdate;
 use s2_grpc_utils::{S2ProtoEnum, S2ProtoPack, S2ProtoUnpack};
 use std::net::{Ipv4Addr, SocketAddrV4};
+use std::pin::Pin;
+use std::time::Duration;
+use tokio_stream::wrappers::errors::BroadcastStreamRecvError;
+use tokio_stream::wrappers::BroadcastStream;
+use tokio_stream::{Stream, StreamExt};
 use tonic::transport::Server;
 use tonic::{Request, Response, Status};
+use tonic_health::server::HealthReporter;
+
+const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(10);
 
 pub async fn serve(state: ControllerStateRef) -> Result<()> {
   let addr = SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, flo_constants::CONTROLLER_GRPC_PORT);
@@ -26,11 +50,67 @@ pub async fn serve(state: ControllerStateRef) -> Result<()> {
 
   let interceptor = state.config.send(GetInterceptor).await?;
   let server = FloControllerServer::with_interceptor(server_impl, interceptor);
-  let server = Server::builder().add_service(server);
+
+  // health and reflection do not require an API key
+  let (mut health_reporter, health_service) = tonic_health::server::health_reporter();
+  health_reporter
+    .set_serving::<FloControllerServer<FloControllerService>>()
+    .await;
+  tokio::spawn(report_health(state.clone(), health_reporter));
+
+  let reflection_service = tonic_reflection::server::Builder::configure()
+    .register_encoded_file_descriptor_set(flo_grpc::FILE_DESCRIPTOR_SET)
+    .register_encoded_file_descriptor_set(tonic_health::proto::GRPC_HEALTH_V1_FILE_DESCRIPTOR_SET)
+    .build()?;
+  let server = Server::builder()
+    .trace_fn(|req| {
+      let span = tracing::info_span!("grpc", path = %req.uri().path());
+      if let Some(traceparent) = req
+        .headers()
+        .get("traceparent")
+        .and_then(|v| v.to_str().ok())
+      {
+        flo_log::trace_context::set_parent(&span, traceparent);
+      }
+      span
+    })
+    .add_service(health_service)
+    .add_service(reflection_service)
+    .add_service(server);
   server.serve(addr.into()).await?;
   Ok(())
 }
 
+/// Reports the controller service as not serving while the database is unreachable
+async fn report_health(state: ControllerStateRef, mut reporter: HealthReporter) {
+  use diesel::RunQueryDsl;
+
+  let mut interval = tokio::time::interval(HEALTH_CHECK_INTERVAL);
+  let mut serving = true;
+  loop {
+    interval.tick().await;
+    let res = state
+      .db
+      .exec(|conn| diesel::sql_query("SELECT 1").execute(conn))
+      .await;
+    if res.is_ok() == serving {
+      continue;
+    }
+    serving = res.is_ok();
+    if serving {
+      tracing::info!("database reachable, reporting serving");
+      reporter
+        .set_serving::<FloControllerServer<FloControllerService>>()
+        .await;
+    } else {
+      tracing::warn!("database unreachable, reporting not serving");
+      reporter
+        .set_not_serving::<FloControllerServer<FloControllerService>>()
+        .await;
+    }
+  }
+}
+
 pub struct FloControllerService {
   state: ControllerStateRef,
 }
@@ -39,6 +119,45 @@ impl FloControllerService {
   pub fn new(state: ControllerStateRef) -> Self {
     FloControllerService { state }
   }
+
+  /// Sends updated player info to the player and players sharing a game with them
+  async fn push_player_info(&self, player: PlayerRef) -> Result<(), Status> {
+    let player_id = player.id;
+    let mut targets = self
+      .state
+      .games
+      .send(ResolveGamePlayerPeers { player_id })
+      .await
+      .map_err(Error::from)?;
+    targets.push(player_id);
+
+    let frame = PacketPlayerInfoUpdate {
+      player: player.pack().map_err(Error::from)?,
+    }
+    .encode_as_frame()
+    .map_err(Error::from)?;
+    self
+      .state
+      .player_packet_sender
+      .broadcast(targets, frame)
+      .await?;
+    Ok(())
+  }
+
+  async fn audit(
+    &self,
+    actor: AuditActor,
+    action: AuditAction,
+    target_id: Option<i32>,
+  ) -> Result<(), Status> {
+    self
+      .state
+      .db
+      .exec_traced(move |conn| crate::audit::db::append(conn, &actor, action, target_id))
+      .await
+      .map_err(Error::from)?;
+    Ok(())
+  }
 }
 
 #[tonic::async_trait]
@@ -51,7 +170,7 @@ impl FloController for FloControllerService {
     let player = self
       .state
       .db
-      .exec(move |conn| crate::player::db::get(conn, player_id))
+      .exec_traced(move |conn| crate::player::db::get(conn, player_id))
       .await
       .map_err(Error::from)?;
     Ok(Response::new(GetPlayerReply {
@@ -68,7 +187,7 @@ impl FloController for FloControllerService {
     let player = self
       .state
       .db
-      .exec(move |conn| crate::player::db::get(conn, player_id))
+      .exec_traced(move |conn| crate::player::db::get(conn, player_id))
       .await
       .map_err(Error::from)?;
     Ok(Response::new(GetPlayerReply {
@@ -102,7 +221,7 @@ impl FloController for FloControllerService {
     let player = self
       .state
       .db
-      .exec(move |conn| db::upsert(conn, &upsert))
+      .exec_traced(move |conn| db::upsert(conn, &upsert))
       .await
       .map_err(Error::from)?;
     let token = crate::player::token::create_player_token(player.id)?;
@@ -112,6 +231,88 @@ impl FloController for FloControllerService {
     }))
   }
 
+  async fn login_player(
+    &self,
+    request: Request<LoginPlayerRequest>,
+  ) -> Result<Response<LoginPlayerReply>, Status> {
+    let api_client_id = request.get_api_client_id();
+    let LoginPlayerRequest {
+      provider,
+      code,
+      redirect_uri,
+    } = request.into_inner();
+    let (player, token) = self
+      .state
+      .auth
+      .login(
+        &self.state.db,
+        api_client_id,
+        &provider,
+        &AuthCredentials { code, redirect_uri },
+      )
+      .await?;
+    Ok(Response::new(LoginPlayerReply {
+      player: player.pack().map_err(Status::internal)?,
+      token,
+    }))
+  }
+
+  async fn create_guest_player(
+    &self,
+    request: Request<()>,
+  ) -> Result<Response<CreateGuestPlayerReply>, Status> {
+    let api_client_id = request.get_api_client_id();
+    let player = self
+      .state
+      .db
+      .exec_traced(move |conn| crate::player::db::create_guest(conn, api_client_id))
+      .await
+      .map_err(Error::from)?;
+    let token = crate::player::token::create_guest_player_token(player.id)?;
+    Ok(Response::new(CreateGuestPlayerReply {
+      player: player.pack().map_err(Status::internal)?,
+      token,
+    }))
+  }
+
+  async fn rename_player(
+    &self,
+    request: Request<RenamePlayerRequest>,
+  ) -> Result<Response<RenamePlayerReply>, Status> {
+    let api_client_id = request.get_api_client_id();
+    let RenamePlayerRequest { player_id, name } = request.into_inner();
+    let (player, game_ids) = self
+      .state
+      .db
+      .exec_traced(move |conn| {
+        crate::player::db::check_player_api_client_id(conn, api_client_id, player_id)?;
+        crate::player::db::rename(conn, player_id, &name)
+      })
+      .await
+      .map_err(Error::from)?;
+
+    // the cached games and pending slots still have the old name
+    for game_id in game_ids {
+      match self
+        .state
+        .games
+        .send_to(game_id, FlushLobby { invalidate: true })
+        .await
+      {
+        Ok(()) | Err(Error::ActorNotFound) => {}
+        Err(err) => {
+          tracing::warn!(game_id, player_id, "rename player: flush slots: {}", err);
+        }
+      }
+    }
+
+    self.push_player_info(player.clone()).await?;
+
+    Ok(Response::new(RenamePlayerReply {
+      player: player.pack().map_err(Status::internal)?,
+    }))
+  }
+
   async fn list_nodes(&self, _request: Request<()>) -> Result<Response<ListNodesReply>, Status> {
     let nodes = self.state.nodes.send(ListNode).await.map_err(Error::from)?;
     Ok(Response::new(ListNodesReply {
@@ -119,18 +320,109 @@ impl FloController for FloControllerService {
     }))
   }
 
+  async fn list_node_utilization(
+    &self,
+    _request: Request<()>,
+  ) -> Result<Response<ListNodeUtilizationReply>, Status> {
+    let map = self
+      .state
+      .nodes
+      .send(GetNodeUtilizationMap)
+      .await
+      .map_err(Error::from)?;
+    Ok(Response::new(ListNodeUtilizationReply {
+      nodes: map
+        .into_values()
+        .collect::<Vec<_>>()
+        .pack()
+        .map_err(Error::from)?,
+    }))
+  }
+
+  async fn get_node_capacity(
+    &self,
+    _request: Request<()>,
+  ) -> Result<Response<GetNodeCapacityReply>, Status> {
+    let nodes = self.state.nodes.send(ListNode).await.map_err(Error::from)?;
+    let utilization_map = self
+      .state
+      .nodes
+      .send(GetNodeUtilizationMap)
+      .await
+      .map_err(Error::from)?;
+    let (queued_games, active_games) = self
+      .state
+      .db
+      .exec_traced(|conn| crate::node::db::count_games(conn))
+      .await
+      .map_err(Error::from)?;
+    let capacity = NodeCapacity::new(
+      &nodes,
+      &utilization_map,
+      queued_games,
+      active_games,
+      *crate::config::AUTOSCALE_GAMES_PER_NODE,
+    );
+    Ok(Response::new(GetNodeCapacityReply {
+      capacity: capacity.pack().map_err(Error::from)?,
+    }))
+  }
+
+  async fn register_node(
+    &self,
+    request: Request<RegisterNodeRequest>,
+  ) -> Result<Response<RegisterNodeReply>, Status> {
+    let actor = request.get_audit_actor();
+    let params = RegisterNodeParams::unpack(request.into_inner()).map_err(Status::internal)?;
+    let node = self
+      .state
+      .db
+      .exec_traced(move |conn| crate::node::db::register_node(conn, params))
+      .await
+      .map_err(Error::from)?;
+    self
+      .audit(actor, AuditAction::RegisterNode, Some(node.id))
+      .await?;
+    self.state.nodes.send(Reload).await.map_err(Error::from)??;
+    Ok(Response::new(RegisterNodeReply {
+      node: node.pack().map_err(Error::from)?,
+    }))
+  }
+
+  async fn deregister_node(
+    &self,
+    request: Request<DeregisterNodeRequest>,
+  ) -> Result<Response<()>, Status> {
+    let actor = request.get_audit_actor();
+    let DeregisterNodeRequest { node_id } = request.into_inner();
+    self
+      .state
+      .db
+      .exec_traced(move |conn| crate::node::db::deregister_node(conn, node_id))
+      .await
+      .map_err(Error::from)?;
+    self
+      .audit(actor, AuditAction::DeregisterNode, Some(node_id))
+      .await?;
+    self.state.nodes.send(Reload).await.map_err(Error::from)??;
+    Ok(Response::new(()))
+  }
+
   async fn list_games(
     &self,
     request: Request<ListGamesRequest>,
   ) -> Result<Response<ListGamesReply>, Status> {
     let params =
       crate::game::db::QueryGameParams::unpack(request.into_inner()).map_err(Status::internal)?;
-    let r = self
-      .state
-      .db
-      .exec(move |conn| crate::game::db::query(conn, &params))
-      .await
-      .map_err(|e| Status::internal(e.to_string()))?;
+    let r = match self.state.game_list.query(&params) {
+      Some(r) => r,
+      None => self
+        .state
+        .db
+        .exec_traced(move |conn| crate::game::db::query(conn, &params))
+        .await
+        .map_err(|e| Status::internal(e.to_string()))?,
+    };
 
     Ok(Response::new(r.pack().map_err(Error::from)?))
   }
@@ -140,10 +432,20 @@ impl FloController for FloControllerService {
     request: Request<GetGameRequest>,
   ) -> Result<Response<GetGameReply>, Status> {
     let game_id = request.into_inner().game_id;
+    // lobbies can have slot changes not written yet
+    match self
+      .state
+      .games
+      .send_to(game_id, FlushLobby { invalidate: false })
+      .await
+    {
+      Ok(()) | Err(Error::ActorNotFound) => {}
+      Err(err) => return Err(err.into()),
+    }
     let game = self
       .state
       .db
-      .exec(move |conn| crate::game::db::get_full(conn, game_id))
+      .exec_traced(move |conn| crate::game::db::get_full(conn, game_id))
       .await
       .map_err(|e| match e {
         ExecutorError::Task(Error::GameNotFound) => Status::invalid_argument(e.to_string()),
@@ -172,32 +474,66 @@ impl FloController for FloControllerService {
     }))
   }
 
+  async fn rehost_game(
+    &self,
+    request: Request<RehostGameRequest>,
+  ) -> Result<Response<RehostGameReply>, Status> {
+    let api_client_id = request.get_api_client_id();
+    let RehostGameRequest { game_id, player_id } = request.into_inner();
+    self
+      .state
+      .db
+      .exec_traced(move |conn| {
+        crate::player::db::check_player_api_client_id(conn, api_client_id, player_id)
+      })
+      .await
+      .map_err(Error::from)?;
+    let res = self
+      .state
+      .games
+      .send(RehostGame { game_id, player_id })
+      .await
+      .map_err(Error::from)??;
+    Ok(Response::new(RehostGameReply {
+      game: res.game.pack().map_err(Status::internal)?,
+      invited_player_ids: res.invited_player_ids,
+    }))
+  }
+
   async fn join_game(
     &self,
     request: Request<JoinGameRequest>,
   ) -> Result<Response<JoinGameReply>, Status> {
     let params = request.into_inner();
+    let game_id = params.game_id;
+    let state = &self.state;
 
-    let game = self
-      .state
-      .games
-      .send_to(
-        params.game_id,
-        PlayerJoin {
-          player_id: params.player_id,
-        },
-      )
-      .await?;
+    let game = state
+      .requests
+      .join_game
+      .run(params.player_id, game_id, &params.request_id, || async {
+        let game = state
+          .games
+          .send_to(
+            game_id,
+            PlayerJoin {
+              player_id: params.player_id,
+            },
+          )
+          .await?;
 
-    self
-      .state
-      .games
-      .send(AddGamePlayer {
-        game_id: params.game_id,
-        player_id: params.player_id,
+        state
+          .games
+          .send(AddGamePlayer {
+            game_id,
+            player_id: params.player_id,
+          })
+          .await
+          .map_err(Error::from)?;
+
+        Ok::<_, Error>(game)
       })
-      .await
-      .map_err(Error::from)?;
+      .await?;
 
     Ok(Response::new(JoinGameReply {
       game: game.pack().map_err(Error::from)?,
@@ -214,7 +550,7 @@ impl FloController for FloControllerService {
     let game = self
       .state
       .db
-      .exec(move |conn| crate::game::db::get(conn, game_id))
+      .exec_traced(move |conn| crate::game::db::get(conn, game_id))
       .await
       .map_err(Error::from)?;
 
@@ -233,27 +569,35 @@ impl FloController for FloControllerService {
   ) -> Result<Response<JoinGameReply>, Status> {
     let params = request.into_inner();
     let join_token = crate::game::token::validate_join_token(&params.token)?;
+    let game_id = join_token.game_id;
+    let state = &self.state;
 
-    let game = self
-      .state
-      .games
-      .send_to(
-        join_token.game_id,
-        PlayerJoin {
-          player_id: params.player_id,
-        },
-      )
-      .await?;
+    let game = state
+      .requests
+      .join_game
+      .run(params.player_id, game_id, &params.request_id, || async {
+        let game = state
+          .games
+          .send_to(
+            game_id,
+            PlayerJoin {
+              player_id: params.player_id,
+            },
+          )
+          .await?;
 
-    self
-      .state
-      .games
-      .send(AddGamePlayer {
-        game_id: join_token.game_id,
-        player_id: params.player_id,
+        state
+          .games
+          .send(AddGamePlayer {
+            game_id,
+            player_id: params.player_id,
+          })
+          .await
+          .map_err(Error::from)?;
+
+        Ok::<_, Error>(game)
       })
-      .await
-      .map_err(Error::from)?;
+      .await?;
 
     Ok(Response::new(JoinGameReply {
       game: game.pack().map_err(Error::from)?,
@@ -262,43 +606,39 @@ impl FloController for FloControllerService {
 
   async fn leave_game(&self, request: Request<LeaveGameRequest>) -> Result<Response<()>, Status> {
     let params = request.into_inner();
+    let game_id = params.game_id;
+    let state = &self.state;
 
-    let res = self
-      .state
-      .games
-      .send_to(
-        params.game_id,
-        PlayerLeave {
-          player_id: params.player_id,
-        },
-      )
-      .await
-      .map_err(Error::from)?;
+    state
+      .requests
+      .leave_game
+      .run(params.player_id, game_id, &params.request_id, || async {
+        let res = state
+          .games
+          .send_to(
+            game_id,
+            PlayerLeave {
+              player_id: params.player_id,
+            },
+          )
+          .await?;
 
-    if res.game_ended {
-      tracing::debug!(
-        game_id = params.game_id,
-        "shutting down: reason: PlayerLeave"
-      );
-      self
-        .state
-        .games
-        .send(Remove {
-          game_id: params.game_id,
-        })
-        .await
-        .map_err(Error::from)?;
-    } else {
-      self
-        .state
-        .games
-        .send(RemoveGamePlayer {
-          game_id: params.game_id,
-          player_id: params.player_id,
-        })
-        .await
-        .map_err(Error::from)?;
-    }
+        if res.game_ended {
+          tracing::debug!(game_id, "shutting down: reason: PlayerLeave");
+          state.games.send(Remove { game_id }).await?;
+        } else {
+          state
+            .games
+            .send(RemoveGamePlayer {
+              game_id,
+              player_id: params.player_id,
+            })
+            .await?;
+        }
+
+        Ok::<_, Error>(())
+      })
+      .await?;
 
     Ok(Response::new(()))
   }
@@ -335,7 +675,29 @@ impl FloController for FloControllerService {
     Ok(Response::new(()))
   }
 
+  async fn start_game_map_vote(
+    &self,
+    request: Request<StartGameMapVoteRequest>,
+  ) -> Result<Response<()>, Status> {
+    let StartGameMapVoteRequest {
+      game_id,
+      player_id,
+      maps,
+    } = request.into_inner();
+
+    let maps = Vec::<Map>::unpack(maps).map_err(Error::from)?;
+
+    self
+      .state
+      .games
+      .send_to(game_id, StartMapVote { player_id, maps })
+      .await?;
+
+    Ok(Response::new(()))
+  }
+
   async fn cancel_game(&self, request: Request<CancelGameRequest>) -> Result<Response<()>, Status> {
+    let actor = request.get_audit_actor();
     let req = request.into_inner();
     let game_id = req.game_id;
     let player_id = req.player_id;
@@ -359,6 +721,10 @@ impl FloController for FloControllerService {
       .await
       .map_err(Error::from)?;
 
+    self
+      .audit(actor, AuditAction::CancelGame, Some(game_id))
+      .await?;
+
     Ok(Response::new(()))
   }
 
@@ -371,7 +737,7 @@ impl FloController for FloControllerService {
     let updated = self
       .state
       .db
-      .exec(move |conn| crate::map::db::import(conn, items))
+      .exec_traced(move |conn| crate::map::db::import(conn, items))
       .await
       .map_err(Error::from)?;
     Ok(Response::new(ImportMapChecksumsReply {
@@ -387,7 +753,7 @@ impl FloController for FloControllerService {
     let checksum = self
       .state
       .db
-      .exec(move |conn| crate::map::db::search_checksum(conn, sha1))
+      .exec_traced(move |conn| crate::map::db::search_checksum(conn, sha1))
       .await
       .map_err(Error::from)?;
     Ok(Response::new(SearchMapChecksumReply { checksum }))
@@ -402,7 +768,7 @@ impl FloController for FloControllerService {
     let map = self
       .state
       .db
-      .exec(move |conn| {
+      .exec_traced(move |conn| {
         crate::player::db::get_player_map_by_api_source_ids(conn, api_client_id, source_ids)
       })
       .await
@@ -422,10 +788,9 @@ impl FloController for FloControllerService {
     let ids = request.into_inner().ids;
     let snapshot = self
       .state
-      .players
-      .send(GetPlayersPingSnapshot { players: ids })
-      .await
-      .map_err(Error::from)?;
+      .player_packet_sender
+      .get_ping_snapshot(ids)
+      .await?;
 
     Ok(Response::new(GetPlayerPingMapsReply {
       ping_maps: snapshot
@@ -494,7 +859,13 @@ impl FloController for FloControllerService {
     self
       .state
       .games
-      .send_to(request.into_inner().game_id, StartGameCheckAsBot { tx })
+      .send_to(
+        request.into_inner().game_id,
+        StartGameCheckAsBot {
+          tx,
+          span: tracing::Span::current(),
+        },
+      )
       .await?;
     match rx.await {
       Ok(res) => match res {
@@ -518,18 +889,95 @@ impl FloController for FloControllerService {
     request: Request<CancelGameAsBotRequest>,
   ) -> Result<Response<()>, Status> {
     let player_id = request.get_api_player_id();
+    let mut cancel = Request::new(CancelGameRequest {
+      game_id: request.get_ref().game_id,
+      player_id,
+    });
+    *cancel.metadata_mut() = request.metadata().clone();
+    self.cancel_game(cancel).await?;
+
+    Ok(Response::new(()))
+  }
+
+  async fn set_game_referee(
+    &self,
+    request: Request<SetGameRefereeRequest>,
+  ) -> Result<Response<SetGameRefereeReply>, Status> {
+    let api_client_id = request.get_api_client_id();
+    let SetGameRefereeRequest {
+      game_id,
+      player_id,
+      referee,
+    } = request.into_inner();
     self
-      .cancel_game(Request::new(CancelGameRequest {
-        game_id: request.into_inner().game_id,
-        player_id,
-      }))
+      .state
+      .db
+      .exec_traced(move |conn| {
+        crate::player::db::check_player_api_client_id(conn, api_client_id, player_id)
+      })
+      .await
+      .map_err(Error::from)?;
+    let referee_player_ids = self
+      .state
+      .games
+      .send_to(game_id, SetReferee { player_id, referee })
       .await?;
+    Ok(Response::new(SetGameRefereeReply { referee_player_ids }))
+  }
+
+  async fn get_game_stats(
+    &self,
+    request: Request<GetGameStatsRequest>,
+  ) -> Result<Response<GetGameStatsReply>, Status> {
+    let game_id = request.into_inner().game_id;
+    let stats = self
+      .state
+      .db
+      .exec_traced(move |conn| crate::game::stats::get(conn, game_id))
+      .await
+      .map_err(Error::from)?;
+    Ok(Response::new(GetGameStatsReply {
+      duration_ms: stats.duration_ms,
+      players: stats.players.pack().map_err(Status::internal)?,
+    }))
+  }
+
+  async fn get_game_replay_url(
+    &self,
+    request: Request<GetGameReplayUrlRequest>,
+  ) -> Result<Response<GetGameReplayUrlReply>, Status> {
+    let game_id = request.into_inner().game_id;
+    let replay = self
+      .state
+      .db
+      .exec_traced(move |conn| crate::game::replay::get(conn, game_id))
+      .await
+      .map_err(Error::from)?;
+    Ok(Response::new(GetGameReplayUrlReply {
+      url: replay.url,
+      expires_at: replay.expires_at.map(|v| v.timestamp()),
+    }))
+  }
 
+  async fn update_game_archive_url(
+    &self,
+    request: Request<UpdateGameArchiveUrlRequest>,
+  ) -> Result<Response<()>, Status> {
+    let UpdateGameArchiveUrlRequest { game_id, url } = request.into_inner();
+    self
+      .state
+      .db
+      .exec_traced(move |conn| crate::game::db::update_archive_url(conn, game_id, &url))
+      .await
+      .map_err(Error::from)?;
     Ok(Response::new(()))
   }
 
-  async fn reload(&self, _request: Request<()>) -> Result<Response<()>, Status> {
+  async fn reload(&self, request: Request<()>) -> Result<Response<()>, Status> {
     self.state.reload().await?;
+    self
+      .audit(request.get_audit_actor(), AuditAction::Reload, None)
+      .await?;
     Ok(Response::new(()))
   }
 
@@ -542,7 +990,7 @@ impl FloController for FloControllerService {
     let res = self
       .state
       .db
-      .exec(move |conn| {
+      .exec_traced(move |conn| {
         crate::player::db::list_ban(conn, api_client_id, params.query.as_deref(), params.next_id)
       })
       .await
@@ -558,6 +1006,7 @@ impl FloController for FloControllerService {
     request: Request<CreatePlayerBanRequest>,
   ) -> Result<Response<()>, Status> {
     let api_client_id = request.get_api_client_id();
+    let actor = request.get_audit_actor();
     let params = request.into_inner();
     let ban_expires_at = params
       .ban_expires_at
@@ -565,20 +1014,35 @@ impl FloController for FloControllerService {
       .map(|t| DateTime::<Utc>::unpack(t))
       .transpose()
       .map_err(Status::internal)?;
+    let player_id = params.player_id;
     self
       .state
       .db
-      .exec(move |conn| {
+      .exec_traced(move |conn| {
         crate::player::db::check_player_api_client_id(conn, api_client_id, params.player_id)?;
-        crate::player::db::create_ban(
-          conn,
-          params.player_id,
-          PlayerBanType::unpack_enum(params.ban_type()),
-          ban_expires_at,
-        )
+        conn.transaction(|| {
+          crate::player::db::create_ban(
+            conn,
+            params.player_id,
+            PlayerBanType::unpack_enum(params.ban_type()),
+            ban_expires_at,
+          )?;
+          crate::audit::db::append(
+            conn,
+            &actor,
+            AuditAction::CreatePlayerBan,
+            Some(params.player_id),
+          )
+        })
       })
       .await
       .map_err(Error::from)?;
+    self
+      .state
+      .games
+      .notify(UpdatePlayerBans { player_id })
+      .await
+      .map_err(Error::from)?;
     Ok(Response::new(()))
   }
 
@@ -587,16 +1051,511 @@ impl FloController for FloControllerService {
     request: Request<RemovePlayerBanRequest>,
   ) -> Result<Response<()>, Status> {
     let api_client_id = request.get_api_client_id();
+    let actor = request.get_audit_actor();
     let params = request.into_inner();
-    self
+    let player_id = self
       .state
       .db
-      .exec(move |conn| {
+      .exec_traced(move |conn| -> Result<_> {
         crate::player::db::check_ban_api_client_id(conn, api_client_id, params.id)?;
-        crate::player::db::remove_ban(conn, params.id)
+        let ban = crate::player::db::get_ban(conn, params.id)?;
+        conn.transaction(|| {
+          crate::player::db::remove_ban(conn, params.id)?;
+          crate::audit::db::append(
+            conn,
+            &actor,
+            AuditAction::RemovePlayerBan,
+            Some(ban.player.id),
+          )
+        })?;
+        Ok(ban.player.id)
       })
       .await
       .map_err(Error::from)?;
+    self
+      .state
+      .games
+      .notify(UpdatePlayerBans { player_id })
+      .await
+      .map_err(Error::from)?;
     Ok(Response::new(()))
   }
+
+  async fn list_audit_logs(
+    &self,
+    request: Request<ListAuditLogsRequest>,
+  ) -> Result<Response<ListAuditLogsReply>, Status> {
+    let api_client_id = request.get_api_client_id();
+    let params = request.into_inner();
+    let action = params
+      .action
+      .map(|v| {
+        flo_grpc::controller::AuditAction::from_i32(v)
+          .map(AuditAction::unpack_enum)
+          .ok_or_else(|| Status::invalid_argument("invalid audit action"))
+      })
+      .transpose()?;
+    let res = self
+      .state
+      .db
+      .exec_traced(move |conn| {
+        crate::audit::db::list(
+          conn,
+          api_client_id,
+          action,
+          params.target_id,
+          params.next_id,
+        )
+      })
+      .await
+      .map_err(Error::from)?;
+    Ok(Response::new(ListAuditLogsReply {
+      audit_logs: res.audit_logs.pack().map_err(Status::internal)?,
+      next_id: res.next_id,
+    }))
+  }
+
+  async fn get_game_chat_log(
+    &self,
+    request: Request<GetGameChatLogRequest>,
+  ) -> Result<Response<GetGameChatLogReply>, Status> {
+    let api_client_id = request.get_api_client_id();
+    let actor = request.get_audit_actor();
+    let GetGameChatLogRequest { game_id, player_id } = request.into_inner();
+    let entries = self
+      .state
+      .db
+      .exec_traced(move |conn| {
+        if let Some(player_id) = player_id {
+          crate::player::db::check_player_api_client_id(conn, api_client_id, player_id)?;
+          crate::game::chat_log::get_for_player(conn, game_id, player_id)
+        } else {
+          // moderator access, recorded in the audit log
+          crate::game::chat_log::check_game_api_client_id(conn, api_client_id, game_id)?;
+          let entries = crate::game::chat_log::get(conn, game_id)?;
+          crate::audit::db::append(conn, &actor, AuditAction::GetGameChatLog, Some(game_id))?;
+          Ok(entries)
+        }
+      })
+      .await
+      .map_err(Error::from)?;
+    Ok(Response::new(GetGameChatLogReply {
+      entries: entries.pack().map_err(Status::internal)?,
+    }))
+  }
+
+  async fn get_game_history(
+    &self,
+    request: Request<GetGameHistoryRequest>,
+  ) -> Result<Response<GetGameHistoryReply>, Status> {
+    let api_client_id = request.get_api_client_id();
+    let actor = request.get_audit_actor();
+    let GetGameHistoryRequest { game_id } = request.into_inner();
+    let records = self
+      .state
+      .db
+      .exec_traced(move |conn| {
+        crate::game::history::check_api_client_id(conn, api_client_id, game_id)?;
+        let records = crate::game::history::list(conn, game_id)?;
+        crate::audit::db::append(conn, &actor, AuditAction::GetGameHistory, Some(game_id))?;
+        Ok::<_, Error>(records)
+      })
+      .await
+      .map_err(Error::from)?;
+    let state = crate::game::history::replay(records.iter().map(|r| &r.event))
+      .map(|state| serde_json::to_string(&state))
+      .transpose()
+      .map_err(Error::from)?;
+    let events = records
+      .iter()
+      .map(|r| r.to_entry())
+      .collect::<Result<Vec<_>>>()?;
+    Ok(Response::new(GetGameHistoryReply {
+      events: events.pack().map_err(Status::internal)?,
+      state: state.unwrap_or_default(),
+    }))
+  }
+
+  async fn list_action_incidents(
+    &self,
+    request: Request<ListActionIncidentsRequest>,
+  ) -> Result<Response<ListActionIncidentsReply>, Status> {
+    let api_client_id = request.get_api_client_id();
+    let ListActionIncidentsRequest {
+      player_id,
+      game_id,
+      next_id,
+    } = request.into_inner();
+    let res = self
+      .state
+      .db
+      .exec_traced(move |conn| {
+        crate::player::db::check_player_api_client_id(conn, api_client_id, player_id)?;
+        crate::game::incident::list(conn, player_id, game_id, next_id)
+      })
+      .await
+      .map_err(Error::from)?;
+    Ok(Response::new(ListActionIncidentsReply {
+      incidents: res.incidents.pack().map_err(Status::internal)?,
+      next_id: res.next_id,
+    }))
+  }
+
+  async fn report_player(
+    &self,
+    request: Request<ReportPlayerRequest>,
+  ) -> Result<Response<ReportPlayerReply>, Status> {
+    let api_client_id = request.get_api_client_id();
+    let params = request.into_inner();
+    let category = PlayerReportCategory::unpack_enum(params.category());
+    let report = self
+      .state
+      .db
+      .exec_traced(move |conn| {
+        crate::player::db::check_player_api_client_id(
+          conn,
+          api_client_id,
+          params.reporter_player_id,
+        )?;
+        crate::player::db::check_player_api_client_id(
+          conn,
+          api_client_id,
+          params.target_player_id,
+        )?;
+        crate::player::report::create(
+          conn,
+          CreatePlayerReportParams {
+            reporter_player_id: Some(params.reporter_player_id),
+            target_player_id: params.target_player_id,
+            game_id: params.game_id,
+            category,
+            message: params.message,
+          },
+        )
+      })
+      .await
+      .map_err(Error::from)?;
+    Ok(Response::new(ReportPlayerReply {
+      report: report.pack().map_err(Status::internal)?,
+    }))
+  }
+
+  async fn list_player_reports(
+    &self,
+    request: Request<ListPlayerReportsRequest>,
+  ) -> Result<Response<ListPlayerReportsReply>, Status> {
+    let api_client_id = request.get_api_client_id();
+    let params = request.into_inner();
+    let status = params
+      .status
+      .map(|v| {
+        flo_grpc::player::PlayerReportStatus::from_i32(v)
+          .map(PlayerReportStatus::unpack_enum)
+          .ok_or_else(|| Status::invalid_argument("invalid player report status"))
+      })
+      .transpose()?;
+    let res = self
+      .state
+      .db
+      .exec_traced(move |conn| {
+        crate::player::report::list(
+          conn,
+          api_client_id,
+          status,
+          params.target_player_id,
+          params.next_id,
+        )
+      })
+      .await
+      .map_err(Error::from)?;
+    Ok(Response::new(ListPlayerReportsReply {
+      reports: res.reports.pack().map_err(Status::internal)?,
+      next_id: res.next_id,
+    }))
+  }
+
+  async fn get_player_report(
+    &self,
+    request: Request<GetPlayerReportRequest>,
+  ) -> Result<Response<GetPlayerReportReply>, Status> {
+    let api_client_id = request.get_api_client_id();
+    let actor = request.get_audit_actor();
+    let GetPlayerReportRequest { id } = request.into_inner();
+    let (report, chat_log) = self
+      .state
+      .db
+      .exec_traced(move |conn| -> Result<_> {
+        crate::player::report::check_api_client_id(conn, api_client_id, id)?;
+        let report = crate::player::report::get(conn, id)?;
+        let chat_log = crate::player::report::get_chat_log(conn, &report)?;
+        // moderator access to the chat log, recorded in the audit log
+        if !chat_log.is_empty() {
+          crate::audit::db::append(conn, &actor, AuditAction::GetGameChatLog, report.game_id)?;
+        }
+        Ok((report, chat_log))
+      })
+      .await
+      .map_err(Error::from)?;
+    Ok(Response::new(GetPlayerReportReply {
+      report: report.pack().map_err(Status::internal)?,
+      chat_log: chat_log.pack().map_err(Status::internal)?,
+    }))
+  }
+
+  async fn resolve_player_report(
+    &self,
+    request: Request<ResolvePlayerReportRequest>,
+  ) -> Result<Response<ResolvePlayerReportReply>, Status> {
+    let api_client_id = request.get_api_client_id();
+    let actor = request.get_audit_actor();
+    let params = request.into_inner();
+    let status = PlayerReportStatus::unpack_enum(params.status());
+    let report = self
+      .state
+      .db
+      .exec_traced(move |conn| {
+        crate::player::report::check_api_client_id(conn, api_client_id, params.id)?;
+        conn.transaction(|| -> Result<_> {
+          let report =
+            crate::player::report::resolve(conn, params.id, status, params.resolution.as_deref())?;
+          crate::audit::db::append(
+            conn,
+            &actor,
+            AuditAction::ResolvePlayerReport,
+            Some(params.id),
+          )?;
+          Ok(report)
+        })
+      })
+      .await
+      .map_err(Error::from)?;
+    Ok(Response::new(ResolvePlayerReportReply {
+      report: report.pack().map_err(Status::internal)?,
+    }))
+  }
+
+  async fn get_player_reputation(
+    &self,
+    request: Request<GetPlayerReputationRequest>,
+  ) -> Result<Response<GetPlayerReputationReply>, Status> {
+    let api_client_id = request.get_api_client_id();
+    let GetPlayerReputationRequest { player_id } = request.into_inner();
+    let reputation = self
+      .state
+      .db
+      .exec_traced(move |conn| {
+        crate::player::db::check_player_api_client_id(conn, api_client_id, player_id)?;
+        crate::player::reputation::get(conn, player_id)
+      })
+      .await
+      .map_err(Error::from)?;
+    Ok(Response::new(GetPlayerReputationReply {
+      reputation: reputation.pack().map_err(Status::internal)?,
+    }))
+  }
+
+  async fn export_player_data(
+    &self,
+    request: Request<ExportPlayerDataRequest>,
+  ) -> Result<Response<ExportPlayerDataReply>, Status> {
+    let api_client_id = request.get_api_client_id();
+    let player_id = request.into_inner().player_id;
+    let data = self
+      .state
+      .db
+      .exec_traced(move |conn| {
+        crate::player::db::check_player_api_client_id(conn, api_client_id, player_id)?;
+        crate::player::db::export(conn, player_id)
+      })
+      .await
+      .map_err(Error::from)?;
+    Ok(Response::new(ExportPlayerDataReply {
+      json: serde_json::to_string(&data).map_err(Error::from)?,
+    }))
+  }
+
+  async fn delete_player(
+    &self,
+    request: Request<DeletePlayerRequest>,
+  ) -> Result<Response<()>, Status> {
+    let api_client_id = request.get_api_client_id();
+    let actor = request.get_audit_actor();
+    let player_id = request.into_inner().player_id;
+    self
+      .state
+      .db
+      .exec_traced(move |conn| {
+        crate::player::db::check_player_api_client_id(conn, api_client_id, player_id)?;
+        conn.transaction(|| {
+          crate::player::db::anonymize(conn, player_id)?;
+          crate::audit::db::append(conn, &actor, AuditAction::DeletePlayer, Some(player_id))
+        })
+      })
+      .await
+      .map_err(Error::from)?;
+    Ok(Response::new(()))
+  }
+
+  async fn get_clan(
+    &self,
+    request: Request<GetClanRequest>,
+  ) -> Result<Response<GetClanReply>, Status> {
+    let api_client_id = request.get_api_client_id();
+    let id = request.into_inner().id;
+    let clan = self
+      .state
+      .db
+      .exec_traced(move |conn| crate::clan::db::get(conn, api_client_id, id))
+      .await
+      .map_err(Error::from)?;
+    Ok(Response::new(GetClanReply {
+      clan: clan.pack().map_err(Status::internal)?,
+    }))
+  }
+
+  async fn create_clan(
+    &self,
+    request: Request<CreateClanRequest>,
+  ) -> Result<Response<CreateClanReply>, Status> {
+    let api_client_id = request.get_api_client_id();
+    let CreateClanRequest { name, tag } = request.into_inner();
+    let clan = self
+      .state
+      .db
+      .exec_traced(move |conn| crate::clan::db::create(conn, api_client_id, &name, &tag))
+      .await
+      .map_err(Error::from)?;
+    Ok(Response::new(CreateClanReply {
+      clan: clan.pack().map_err(Status::internal)?,
+    }))
+  }
+
+  async fn update_clan(
+    &self,
+    request: Request<UpdateClanRequest>,
+  ) -> Result<Response<UpdateClanReply>, Status> {
+    let api_client_id = request.get_api_client_id();
+    let UpdateClanRequest { id, name, tag } = request.into_inner();
+    let (clan, members) = self
+      .state
+      .db
+      .exec_traced(move |conn| -> Result<_> {
+        let clan = crate::clan::db::update(conn, api_client_id, id, &name, &tag)?;
+        let members = crate::clan::db::get(conn, api_client_id, id)?.members;
+        Ok((clan, members))
+      })
+      .await
+      .map_err(Error::from)?;
+    for member in members {
+      self.push_player_info(member).await?;
+    }
+    Ok(Response::new(UpdateClanReply {
+      clan: clan.pack().map_err(Status::internal)?,
+    }))
+  }
+
+  async fn delete_clan(&self, request: Request<DeleteClanRequest>) -> Result<Response<()>, Status> {
+    let api_client_id = request.get_api_client_id();
+    let id = request.into_inner().id;
+    let members = self
+      .state
+      .db
+      .exec_traced(move |conn| -> Result<_> {
+        let members = crate::clan::db::get(conn, api_client_id, id)?.members;
+        crate::clan::db::delete(conn, api_client_id, id)?;
+        crate::player::db::get_refs_by_ids(
+          conn,
+          &members.into_iter().map(|p| p.id).collect::<Vec<_>>(),
+        )
+      })
+      .await
+      .map_err(Error::from)?;
+    for member in members {
+      self.push_player_info(member).await?;
+    }
+    Ok(Response::new(()))
+  }
+
+  async fn add_clan_member(
+    &self,
+    request: Request<AddClanMemberRequest>,
+  ) -> Result<Response<()>, Status> {
+    let api_client_id = request.get_api_client_id();
+    let AddClanMemberRequest { clan_id, player_id } = request.into_inner();
+    let player = self
+      .state
+      .db
+      .exec_traced(move |conn| {
+        crate::player::db::check_player_api_client_id(conn, api_client_id, player_id)?;
+        crate::clan::db::add_member(conn, api_client_id, clan_id, player_id)?;
+        crate::player::db::get_ref(conn, player_id)
+      })
+      .await
+      .map_err(Error::from)?;
+    self.push_player_info(player).await?;
+    Ok(Response::new(()))
+  }
+
+  async fn remove_clan_member(
+    &self,
+    request: Request<RemoveClanMemberRequest>,
+  ) -> Result<Response<()>, Status> {
+    let api_client_id = request.get_api_client_id();
+    let RemoveClanMemberRequest { clan_id, player_id } = request.into_inner();
+    let player = self
+      .state
+      .db
+      .exec_traced(move |conn| {
+        crate::clan::db::remove_member(conn, api_client_id, clan_id, player_id)?;
+        crate::player::db::get_ref(conn, player_id)
+      })
+      .await
+      .map_err(Error::from)?;
+    self.push_player_info(player).await?;
+    Ok(Response::new(()))
+  }
+
+  type SubscribeEventsStream =
+    Pin<Box<dyn Stream<Item = Result<ControllerEvent, Status>> + Send + 'static>>;
+
+  async fn subscribe_events(
+    &self,
+    request: Request<SubscribeEventsRequest>,
+  ) -> Result<Response<Self::SubscribeEventsStream>, Status> {
+    // events are signed with the subscribing API client's secret
+    let signer = EventSigner::new(
+      request
+        .metadata()
+        .get(REQUEST_META_SECRET)
+        .map(|v| v.as_bytes())
+        .unwrap_or_default(),
+    );
+    let req = request.into_inner();
+    let filter = EventFilter {
+      types: req
+        .types
+        .into_iter()
+        .filter_map(flo_grpc::controller::ControllerEventType::from_i32)
+        .map(ControllerEventType::unpack_enum)
+        .collect(),
+      game_ids: req.game_ids,
+      player_ids: req.player_ids,
+    };
+    let stream = BroadcastStream::new(self.state.events.subscribe()).filter_map(move |res| {
+      match res {
+        Ok(event) if filter.matches(&event) => Some(
+          signer
+            .sign(event)
+            .map_err(|err| Status::internal(err.to_string())),
+        ),
+        Ok(_) => None,
+        // the stream ends after the first error
+        Err(BroadcastStreamRecvError::Lagged(n)) => Some(Err(Status::resource_exhausted(format!(
+          "subscriber lagged behind by {} events",
+          n
+        )))),
+      }
+    });
+    Ok(Response::new(Box::pin(stream)))
+  }
 }
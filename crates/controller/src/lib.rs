@@ -1,7 +1,7 @@
-#[cfg(not(debug_assertions))]
+#[cfg(any(not(debug_assertions), feature = "standalone"))]
 #[macro_use]
 extern crate diesel_migrations;
-#[cfg(not(debug_assertions))]
+#[cfg(any(not(debug_assertions), feature = "standalone"))]
 pub mod migration;
 
 #[macro_use]
@@ -14,17 +14,27 @@ extern crate diesel;
 mod db;
 mod schema;
 
+mod audit;
+mod clan;
 mod client;
 mod config;
 pub mod error;
+pub mod event;
 pub mod game;
+#[cfg(feature = "graphql")]
+pub mod graphql;
 mod grpc;
 pub mod host;
 pub mod map;
+mod metrics;
 pub mod node;
 pub mod player;
+#[cfg(feature = "standalone")]
+pub mod standalone;
 mod state;
 
 pub use client::serve as serve_socket;
+#[cfg(feature = "graphql")]
+pub use graphql::serve as serve_graphql;
 pub use grpc::serve as serve_grpc;
 pub use state::{ControllerState, ControllerStateRef};
@@ -4,6 +4,7 @@ use serde::Deserialize;
 
 use crate::db::DbConn;
 use crate::error::*;
+use crate::map::Map;
 use crate::schema::map_checksum;
 
 pub fn search_checksum(conn: &DbConn, sha1: String) -> Result<Option<u32>> {
@@ -25,6 +26,12 @@ pub fn search_checksum(conn: &DbConn, sha1: String) -> Result<Option<u32>> {
   Ok(value)
 }
 
+/// The map pool is made of the maps with an imported checksum
+pub fn is_in_pool(conn: &DbConn, map: &Map) -> Result<bool> {
+  let checksum = search_checksum(conn, hex::encode(map.sha1.0))?;
+  Ok(checksum == Some(map.checksum))
+}
+
 #[derive(Debug, Deserialize, S2ProtoUnpack)]
 #[s2_grpc(message_type = "flo_grpc::game::MapChecksumImportItem")]
 pub struct ImportItem {
@@ -17,6 +17,21 @@ pub struct Map {
   pub height: u32,
   pub players: Vec<MapPlayer>,
   pub forces: Vec<MapForce>,
+  #[serde(default)]
+  pub flags: u32,
+}
+
+impl Map {
+  const FLAG_FIXED_PLAYER_SETTINGS: u32 = 0x0020;
+  const FLAG_CUSTOM_FORCES: u32 = 0x0040;
+
+  pub fn fixed_player_settings(&self) -> bool {
+    self.flags & Self::FLAG_FIXED_PLAYER_SETTINGS != 0
+  }
+
+  pub fn custom_forces(&self) -> bool {
+    self.flags & Self::FLAG_CUSTOM_FORCES != 0
+  }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -0,0 +1,35 @@
+use once_cell::sync::Lazy;
+use prometheus::{register_histogram, register_int_counter, Histogram, IntCounter};
+
+pub static PLAYER_SEND_COALESCED: Lazy<IntCounter> = Lazy::new(|| {
+  register_int_counter!(
+    "flocontroller_player_send_coalesced",
+    "Frames replaced by a newer frame in the send buffer of a slow player connection"
+  )
+  .unwrap()
+});
+
+pub static PLAYER_SEND_DROPPED: Lazy<IntCounter> = Lazy::new(|| {
+  register_int_counter!(
+    "flocontroller_player_send_dropped",
+    "Low priority frames dropped for slow player connections"
+  )
+  .unwrap()
+});
+
+pub static PLAYER_SEND_OVERFLOWS: Lazy<IntCounter> = Lazy::new(|| {
+  register_int_counter!(
+    "flocontroller_player_send_overflows",
+    "Player connections dropped because their send buffer was full"
+  )
+  .unwrap()
+});
+
+pub static LOBBY_STORE_LOCK_WAIT: Lazy<Histogram> = Lazy::new(|| {
+  register_histogram!(
+    "flocontroller_lobby_store_lock_wait_seconds",
+    "Time spent waiting for the lock of a lobby store shard",
+    vec![0.00001, 0.0001, 0.0005, 0.001, 0.005, 0.01, 0.05, 0.1]
+  )
+  .unwrap()
+});
@@ -0,0 +1,115 @@
+//! Game capacity of the node fleet, for external autoscalers.
+
+use s2_grpc_utils::S2ProtoPack;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+use crate::node::utilization::NodeUtilization;
+use crate::node::Node;
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, S2ProtoPack)]
+#[s2_grpc(message_type = "flo_grpc::node::NodeCapacity")]
+pub struct NodeCapacity {
+  /// Enabled nodes
+  pub nodes: i32,
+  /// Lobbies waiting to start
+  pub queued_games: i32,
+  /// Games created on a node and not ended yet
+  pub active_games: i32,
+  /// Games the nodes can still accept
+  pub available_slots: i32,
+  /// Nodes needed to host the active and queued games
+  pub desired_nodes: i32,
+}
+
+impl NodeCapacity {
+  /// Nodes without a reported game limit are assumed to accept `games_per_node` games
+  pub fn new(
+    nodes: &[Node],
+    utilization_map: &BTreeMap<i32, NodeUtilization>,
+    queued_games: i32,
+    active_games: i32,
+    games_per_node: u32,
+  ) -> Self {
+    let nodes: Vec<&Node> = nodes.iter().filter(|node| !node.disabled).collect();
+
+    let mut total_slots = 0;
+    let mut available_slots = 0;
+    for node in &nodes {
+      let utilization = utilization_map.get(&node.id);
+      let max_games = utilization
+        .and_then(|v| v.max_games())
+        .unwrap_or(games_per_node) as i32;
+      let games = utilization.map(|v| v.games()).unwrap_or_default() as i32;
+      total_slots += max_games;
+      available_slots += (max_games - games).max(0);
+    }
+
+    let slots_per_node = if nodes.is_empty() || total_slots == 0 {
+      games_per_node.max(1) as i32
+    } else {
+      (total_slots / nodes.len() as i32).max(1)
+    };
+    let needed_slots = active_games + queued_games;
+    let desired_nodes = ((needed_slots + slots_per_node - 1) / slots_per_node).max(1);
+
+    NodeCapacity {
+      nodes: nodes.len() as i32,
+      queued_games,
+      active_games,
+      available_slots,
+      desired_nodes,
+    }
+  }
+}
+
+#[test]
+fn test_node_capacity() {
+  use crate::node::utilization::NodeUtilizationSample;
+  use chrono::Utc;
+
+  let node = |id: i32, disabled: bool| Node {
+    id,
+    name: id.to_string(),
+    location: "".to_string(),
+    secret: "".to_string(),
+    ip_addr: "127.0.0.1".to_string(),
+    created_at: Utc::now(),
+    updated_at: Utc::now(),
+    country_id: "US".to_string(),
+    disabled,
+    ip_addr_v6: None,
+  };
+  let nodes = vec![node(1, false), node(2, false), node(3, true)];
+
+  let mut utilization = NodeUtilization::new(1);
+  utilization.push(NodeUtilizationSample {
+    cpu_usage: None,
+    memory_usage: None,
+    games: 15,
+    player_connections: 0,
+    bytes_in_per_sec: 0,
+    bytes_out_per_sec: 0,
+    max_games: Some(20),
+    received_at: Utc::now(),
+  });
+  let utilization_map = vec![(1, utilization)].into_iter().collect();
+
+  assert_eq!(
+    NodeCapacity::new(&nodes, &utilization_map, 50, 15, 10),
+    NodeCapacity {
+      nodes: 2,
+      queued_games: 50,
+      active_games: 15,
+      available_slots: 15,
+      desired_nodes: 5,
+    }
+  );
+  assert_eq!(
+    NodeCapacity::new(&[], &BTreeMap::new(), 0, 0, 10),
+    NodeCapacity {
+      desired_nodes: 1,
+      ..Default::default()
+    }
+  );
+}
@@ -1,9 +1,12 @@
+use diesel::pg::expression::dsl::any;
 use diesel::prelude::*;
+use s2_grpc_utils::S2ProtoUnpack;
 
 use crate::db::DbConn;
 use crate::error::*;
+use crate::game::GameStatus;
 use crate::node::types::Node;
-use crate::schema::node;
+use crate::schema::{game, node};
 
 pub fn get_all_nodes(conn: &DbConn) -> Result<Vec<Node>> {
   use node::dsl;
@@ -22,3 +25,66 @@ pub fn get_node(conn: &DbConn, node_id: i32) -> Result<Node> {
     .ok_or_else(|| Error::NodeNotFound)
     .map_err(Into::into)
 }
+
+#[derive(Debug, S2ProtoUnpack)]
+#[s2_grpc(message_type = "flo_grpc::controller::RegisterNodeRequest")]
+pub struct RegisterNodeParams {
+  pub name: String,
+  pub location: String,
+  pub secret: String,
+  pub ip_addr: String,
+  pub country_id: String,
+  pub ip_addr_v6: Option<String>,
+}
+
+pub fn register_node(conn: &DbConn, params: RegisterNodeParams) -> Result<Node> {
+  use node::dsl;
+  crate::node::state::conn::parse_addr(&params.ip_addr)?;
+  diesel::insert_into(node::table)
+    .values((
+      dsl::name.eq(&params.name),
+      dsl::location.eq(&params.location),
+      dsl::secret.eq(&params.secret),
+      dsl::ip_addr.eq(&params.ip_addr),
+      dsl::country_id.eq(&params.country_id),
+      dsl::ip_addr_v6.eq(params.ip_addr_v6.as_ref()),
+    ))
+    .get_result(conn)
+    .map_err(Into::into)
+}
+
+/// Disables a node, nodes still hosting games can't be removed
+pub fn deregister_node(conn: &DbConn, node_id: i32) -> Result<()> {
+  use node::dsl;
+  let active_games: i64 = game::table
+    .filter(
+      game::node_id
+        .eq(node_id)
+        .and(game::status.eq(any(&[GameStatus::Created, GameStatus::Running] as &[_]))),
+    )
+    .count()
+    .get_result(conn)?;
+  if active_games > 0 {
+    return Err(Error::NodeHasActiveGames);
+  }
+  let updated = diesel::update(node::table.find(node_id))
+    .set(dsl::disabled.eq(true))
+    .execute(conn)?;
+  if updated == 0 {
+    return Err(Error::NodeNotFound);
+  }
+  Ok(())
+}
+
+/// Returns the number of lobbies and the number of games created on a node
+pub fn count_games(conn: &DbConn) -> Result<(i32, i32)> {
+  let queued: i64 = game::table
+    .filter(game::status.eq(GameStatus::Preparing))
+    .count()
+    .get_result(conn)?;
+  let active: i64 = game::table
+    .filter(game::status.eq(any(&[GameStatus::Created, GameStatus::Running] as &[_])))
+    .count()
+    .get_result(conn)?;
+  Ok((queued as i32, active as i32))
+}
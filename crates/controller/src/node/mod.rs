@@ -1,12 +1,16 @@
+pub mod capacity;
 pub mod db;
 mod state;
 mod types;
+pub mod utilization;
 
 pub use state::conn::NodeConnActor;
 pub use state::request::PlayerLeaveResponse;
 pub use state::NodeRegistry;
 pub use types::*;
 pub mod messages {
-  pub use crate::node::state::conn::{NodeCreateGame, NodePlayerLeave};
-  pub use crate::node::state::ListNode;
+  pub use crate::node::state::conn::{
+    NodeCreateGame, NodePlayerLeave, NodeRelayPlayers, NodeUpdatePlayerBans,
+  };
+  pub use crate::node::state::{GetNodeUtilizationMap, ListNode};
 }
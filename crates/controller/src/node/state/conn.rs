@@ -3,10 +3,12 @@ use crate::game::state::GameRegistry;
 use crate::game::state::{GameSlotClientStatusUpdate, GameStatusUpdate};
 use crate::game::{Game, GameStatus};
 use crate::node::state::request::{CreatedGameInfo, NodeRequestActor, NodeRequestExt};
-use crate::node::{NodeConnConfig, PlayerLeaveResponse};
+use crate::node::state::{NodeRegistry, UpdateNodeUtilization};
+use crate::node::{NodeConnConfig, PlayerLeaveResponse, PlayerToken};
 use crate::state::ActorMapExt;
 use backoff::backoff::Backoff;
 use backoff::ExponentialBackoff;
+use flo_net::capability::{Capabilities, Feature};
 use flo_net::packet::*;
 use flo_net::proto::flo_node::*;
 use flo_net::stream::FloStream;
@@ -15,11 +17,14 @@ use flo_state::{async_trait, Actor, Addr, Context, Handler, Message, Owner};
 use s2_grpc_utils::{S2ProtoEnum, S2ProtoUnpack};
 use std::collections::BTreeMap;
 
-use crate::game::state::registry::Remove;
+use crate::game::state::registry::{
+  CloseOrphanedGame, RefreshGameListEntry, Remove, SaveActionIncident, SaveGameChatLog,
+  SaveGameStats,
+};
 use crate::player::PlayerBanType;
 use flo_net::ping::{PingMsg, PingStream};
 use futures::StreamExt;
-use std::net::{Ipv4Addr, SocketAddrV4};
+use std::net::SocketAddr;
 use std::time::Duration;
 use tokio::sync::mpsc;
 use tokio::time::sleep;
@@ -29,20 +34,28 @@ const MAX_BACKOFF: Duration = Duration::from_secs(60);
 
 pub struct NodeConnActor {
   config: NodeConnConfig,
+  capabilities: Capabilities,
   reconnect_backoff: Option<ExponentialBackoff>,
   status: NodeConnStatus,
   request_actor: Option<Owner<NodeRequestActor>>,
   game_reg_addr: Addr<GameRegistry>,
+  node_reg_addr: Addr<NodeRegistry>,
 }
 
 impl NodeConnActor {
-  pub fn new(config: NodeConnConfig, game_reg_addr: Addr<GameRegistry>) -> Self {
+  pub fn new(
+    config: NodeConnConfig,
+    game_reg_addr: Addr<GameRegistry>,
+    node_reg_addr: Addr<NodeRegistry>,
+  ) -> Self {
     Self {
       config,
+      capabilities: Capabilities::default(),
       status: NodeConnStatus::Connecting,
       reconnect_backoff: None,
       request_actor: None,
       game_reg_addr,
+      node_reg_addr,
     }
   }
 
@@ -100,17 +113,16 @@ impl NodeConnActor {
 
   async fn connect(
     node_id: i32,
-    ip: Ipv4Addr,
-    port: u16,
+    addr: SocketAddr,
     secret: &str,
   ) -> Result<FloStream, NodeConnectError> {
-    let addr = SocketAddrV4::new(ip, port);
     let mut stream = FloStream::connect(addr).await?;
 
     stream
       .send(PacketControllerConnect {
         lobby_version: Some(crate::version::FLO_LOBBY_VERSION.into()),
         secret: secret.to_string(),
+        capabilities: Some(Capabilities::local().into()),
       })
       .await?;
 
@@ -120,6 +132,7 @@ impl NodeConnActor {
       res => {
         packet: PacketControllerConnectAccept => {
           tracing::info!(node_id, "node connected: version = {:?}", packet.version);
+          stream.set_capabilities(Capabilities::local().negotiate(packet.capabilities.as_ref()));
         }
         packet: PacketControllerConnectReject => {
           tracing::error!(node_id, "node connect rejected: reason = {:?}", packet.reason());
@@ -209,7 +222,7 @@ impl Handler<Connect> for NodeConnActor {
       return;
     }
 
-    let (ip, port) = match parse_addr(&self.config.addr) {
+    let addr = match parse_addr(&self.config.addr) {
       Ok(v) => v,
       Err(err) => {
         self.status = NodeConnStatus::Error;
@@ -219,7 +232,7 @@ impl Handler<Connect> for NodeConnActor {
     };
     let node_id = self.config.id;
     let secret = self.config.secret.clone();
-    let stream = match Self::connect(node_id, ip, port, &secret).await {
+    let stream = match Self::connect(node_id, addr, &secret).await {
       Ok(stream) => stream,
       Err(NodeConnectError::Retry(err)) => {
         tracing::error!(node_id, "error: {}", err);
@@ -232,6 +245,7 @@ impl Handler<Connect> for NodeConnActor {
         return;
       }
     };
+    self.capabilities = stream.capabilities().clone();
     let (tx, rx) = mpsc::channel(32);
     ctx.spawn(
       Self::stream_worker(ctx.addr(), rx, stream)
@@ -274,6 +288,11 @@ impl Handler<IncomingFrame> for NodeConnActor {
       Response(RequestDone),
       GameSlotClientStatusUpdate(GameSlotClientStatusUpdate),
       GameStatusUpdate(Vec<GameStatusUpdate>),
+      GameStats(PacketNodeGameStats),
+      ActionIncident(PacketNodeActionIncident),
+      GameChatLog(PacketNodeGameChatLog),
+      Utilization(PacketNodeUtilization),
+      OrphanedGames(PacketNodeOrphanedGames),
     }
 
     let parsed = flo_net::try_flo_packet! {
@@ -322,6 +341,22 @@ impl Handler<IncomingFrame> for NodeConnActor {
             )
           )
         }
+        packet: PacketControllerRelayPlayersAccept => {
+          Parsed::Response(
+            RequestDone::new(
+              RequestId::RelayPlayers(packet.game_id),
+              Ok(Response::RelayPlayers)
+            )
+          )
+        }
+        packet: PacketControllerRelayPlayersReject => {
+          Parsed::Response(
+            RequestDone::new(
+              RequestId::RelayPlayers(packet.game_id),
+              Err(Error::NodeRelayRejected(packet.message))
+            )
+          )
+        }
         packet: PacketClientUpdateSlotClientStatus => {
           Parsed::GameSlotClientStatusUpdate(S2ProtoUnpack::unpack(packet)?)
         }
@@ -331,6 +366,21 @@ impl Handler<IncomingFrame> for NodeConnActor {
         packet: PacketNodeGameStatusUpdateBulk => {
           Parsed::GameStatusUpdate(packet.games.into_iter().map(Into::into).collect())
         }
+        packet: PacketNodeGameStats => {
+          Parsed::GameStats(packet)
+        }
+        packet: PacketNodeActionIncident => {
+          Parsed::ActionIncident(packet)
+        }
+        packet: PacketNodeGameChatLog => {
+          Parsed::GameChatLog(packet)
+        }
+        packet: PacketNodeUtilization => {
+          Parsed::Utilization(packet)
+        }
+        packet: PacketNodeOrphanedGames => {
+          Parsed::OrphanedGames(packet)
+        }
       }
     };
 
@@ -370,11 +420,69 @@ impl Handler<IncomingFrame> for NodeConnActor {
                 if let Err(err) = addr.send(Remove { game_id }).await {
                   tracing::warn!(game_id, "remove game: {:?}", err);
                 }
+              } else {
+                addr.notify(RefreshGameListEntry { game_id }).await.ok();
               }
             }
           }
         });
       }
+      Parsed::GameStats(stats) => {
+        let addr = self.game_reg_addr.clone();
+        let node_id = self.config.id;
+        ctx.spawn(async move {
+          let game_id = stats.game_id;
+          if let Err(err) = addr.send(SaveGameStats { node_id, stats }).await {
+            tracing::warn!(game_id, "save game stats: {:?}", err);
+          }
+        });
+      }
+      Parsed::ActionIncident(incident) => {
+        let addr = self.game_reg_addr.clone();
+        let node_id = self.config.id;
+        ctx.spawn(async move {
+          let game_id = incident.game_id;
+          if let Err(err) = addr.send(SaveActionIncident { node_id, incident }).await {
+            tracing::warn!(game_id, "save action incident: {:?}", err);
+          }
+        });
+      }
+      Parsed::GameChatLog(log) => {
+        let addr = self.game_reg_addr.clone();
+        let node_id = self.config.id;
+        ctx.spawn(async move {
+          let game_id = log.game_id;
+          if let Err(err) = addr.send(SaveGameChatLog { node_id, log }).await {
+            tracing::warn!(game_id, "save game chat log: {:?}", err);
+          }
+        });
+      }
+      Parsed::OrphanedGames(packet) => {
+        let addr = self.game_reg_addr.clone();
+        let node_id = self.config.id;
+        ctx.spawn(async move {
+          for snapshot in packet.games {
+            let game_id = snapshot.game_id;
+            tracing::warn!(game_id, node_id, "orphaned game reported");
+            if let Err(err) = addr.send(CloseOrphanedGame { node_id, snapshot }).await {
+              tracing::warn!(game_id, "close orphaned game: {:?}", err);
+            }
+          }
+        });
+      }
+      Parsed::Utilization(packet) => {
+        let message = UpdateNodeUtilization {
+          node_id: self.config.id,
+          sample: packet.into(),
+        };
+        if let Err(err) = self.node_reg_addr.notify(message).await {
+          tracing::warn!(
+            node_id = self.config.id,
+            "update node utilization: {:?}",
+            err
+          );
+        }
+      }
     }
 
     Ok(())
@@ -384,6 +492,8 @@ impl Handler<IncomingFrame> for NodeConnActor {
 pub struct NodeCreateGame {
   pub game: Game,
   pub ban_list_map: BTreeMap<i32, Vec<PlayerBanType>>,
+  pub referee_player_ids: Vec<i32>,
+  pub span: tracing::Span,
 }
 
 impl Message for NodeCreateGame {
@@ -395,7 +505,12 @@ impl Handler<NodeCreateGame> for NodeConnActor {
   async fn handle(
     &mut self,
     ctx: &mut Context<Self>,
-    NodeCreateGame { game, ban_list_map }: NodeCreateGame,
+    NodeCreateGame {
+      game,
+      ban_list_map,
+      referee_player_ids,
+      span,
+    }: NodeCreateGame,
   ) -> Result<FutureReply<Result<CreatedGameInfo>>> {
     let addr = self
       .request_actor
@@ -404,7 +519,52 @@ impl Handler<NodeCreateGame> for NodeConnActor {
       .ok_or_else(|| Error::NodeNotReady)?;
     let (tx, rx) = FutureReply::channel();
     ctx.spawn(async move {
-      tx.send(addr.create_game(game, ban_list_map).await).ok();
+      tx.send(
+        addr
+          .create_game(game, ban_list_map, referee_player_ids)
+          .instrument(span)
+          .await,
+      )
+      .ok();
+    });
+    Ok(rx)
+  }
+}
+
+/// Lets the node forward the players to the node at `target_addr`
+pub struct NodeRelayPlayers {
+  pub game_id: i32,
+  pub target_addr: String,
+  pub tokens: Vec<PlayerToken>,
+}
+
+impl Message for NodeRelayPlayers {
+  type Result = Result<FutureReply<Result<()>>>;
+}
+
+#[async_trait]
+impl Handler<NodeRelayPlayers> for NodeConnActor {
+  async fn handle(
+    &mut self,
+    ctx: &mut Context<Self>,
+    NodeRelayPlayers {
+      game_id,
+      target_addr,
+      tokens,
+    }: NodeRelayPlayers,
+  ) -> Result<FutureReply<Result<()>>> {
+    if !self.capabilities.supports(Feature::Relay) {
+      return Err(Error::NodeRelayUnsupported);
+    }
+    let addr = self
+      .request_actor
+      .as_ref()
+      .map(|v| v.addr())
+      .ok_or_else(|| Error::NodeNotReady)?;
+    let (tx, rx) = FutureReply::channel();
+    ctx.spawn(async move {
+      tx.send(addr.relay_players(game_id, target_addr, tokens).await)
+        .ok();
     });
     Ok(rx)
   }
@@ -440,6 +600,40 @@ impl Handler<NodePlayerLeave> for NodeConnActor {
   }
 }
 
+/// Pushes the current ban list of a player to the node running the game
+pub struct NodeUpdatePlayerBans {
+  pub game_id: i32,
+  pub player_id: i32,
+  pub ban_list: Vec<PlayerBanType>,
+}
+
+impl Message for NodeUpdatePlayerBans {
+  type Result = Result<()>;
+}
+
+#[async_trait]
+impl Handler<NodeUpdatePlayerBans> for NodeConnActor {
+  async fn handle(
+    &mut self,
+    _: &mut Context<Self>,
+    NodeUpdatePlayerBans {
+      game_id,
+      player_id,
+      ban_list,
+    }: NodeUpdatePlayerBans,
+  ) -> Result<()> {
+    if !self.capabilities.supports(Feature::PlayerBanUpdate) {
+      return Err(Error::NodeBanUpdateUnsupported);
+    }
+    let addr = self
+      .request_actor
+      .as_ref()
+      .map(|v| v.addr())
+      .ok_or_else(|| Error::NodeNotReady)?;
+    addr.update_player_bans(game_id, player_id, ban_list).await
+  }
+}
+
 #[derive(Debug, Copy, Clone, PartialEq)]
 enum NodeConnStatus {
   Connecting,
@@ -447,26 +641,11 @@ enum NodeConnStatus {
   Error,
 }
 
-fn parse_addr(addr: &str) -> Result<(Ipv4Addr, u16)> {
-  let (ip, port) = if addr.contains(":") {
-    let addr = if let Some(addr) = addr.parse::<SocketAddrV4>().ok() {
-      addr
-    } else {
-      return Err(Error::InvalidNodeAddress(addr.to_string()));
-    };
-
-    (
-      addr.ip().clone(),
-      addr.port() + flo_constants::NODE_CONTROLLER_PORT_OFFSET,
-    )
-  } else {
-    let addr: Ipv4Addr = if let Some(addr) = addr.parse::<Ipv4Addr>().ok() {
-      addr
-    } else {
-      return Err(Error::InvalidNodeAddress(addr.to_string()));
-    };
-    let port = flo_constants::NODE_CONTROLLER_PORT;
-    (addr, port)
-  };
-  Ok((ip, port))
+pub(crate) fn parse_addr(addr: &str) -> Result<SocketAddr> {
+  flo_net::addr::parse_node_addr(
+    addr,
+    flo_constants::NODE_CONTROLLER_PORT,
+    flo_constants::NODE_CONTROLLER_PORT_OFFSET,
+  )
+  .ok_or_else(|| Error::InvalidNodeAddress(addr.to_string()))
 }
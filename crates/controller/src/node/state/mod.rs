@@ -1,9 +1,10 @@
 pub mod conn;
 pub mod request;
 
-use crate::db::ExecutorRef;
+use crate::db::{ExecutorExt, ExecutorRef};
 use crate::error::*;
 use crate::game::state::GameRegistry;
+use crate::node::utilization::{NodeUtilization, NodeUtilizationSample};
 use crate::node::{Node, NodeConnConfig};
 use crate::player::state::sender::PlayerRegistryHandle;
 use crate::state::{Data, GetActorEntry, Reload};
@@ -21,6 +22,7 @@ pub struct NodeRegistry {
   player_reg_handle: PlayerRegistryHandle,
   map: BTreeMap<i32, Owner<NodeConnActor>>,
   nodes_snapshot: ArcSwap<Vec<Node>>,
+  utilization: BTreeMap<i32, NodeUtilization>,
 }
 
 #[async_trait]
@@ -29,28 +31,28 @@ impl Service<Data> for NodeRegistry {
 
   async fn create(registry: &mut RegistryRef<Data>) -> Result<Self, Self::Error> {
     let game_reg_addr = registry.deferred::<GameRegistry>();
-    let player_reg_addr = registry.resolve().await?;
     Ok(Self {
       db: registry.data().db.clone(),
       game_reg_addr,
-      player_reg_handle: PlayerRegistryHandle::from(player_reg_addr),
+      player_reg_handle: registry.data().players.clone(),
       map: BTreeMap::new(),
       nodes_snapshot: ArcSwap::new(Arc::new(vec![])),
+      utilization: BTreeMap::new(),
     })
   }
 }
 
 #[async_trait]
 impl Actor for NodeRegistry {
-  async fn started(&mut self, _ctx: &mut Context<Self>) {
-    if let Err(err) = self.init().await {
+  async fn started(&mut self, ctx: &mut Context<Self>) {
+    if let Err(err) = self.init(ctx).await {
       tracing::error!("init: {}", err);
     }
   }
 }
 
 impl NodeRegistry {
-  async fn init(&mut self) -> Result<()> {
+  async fn init(&mut self, ctx: &mut Context<Self>) -> Result<()> {
     let game_reg_addr = self.game_reg_addr.resolve().await?;
     let nodes = self.load_snapshot().await?;
 
@@ -58,7 +60,7 @@ impl NodeRegistry {
       tracing::debug!(node_id = node.id, "added");
       self.map.insert(
         node.id,
-        NodeConnActor::new(node.into(), game_reg_addr.clone()).start(),
+        NodeConnActor::new(node.into(), game_reg_addr.clone(), ctx.addr()).start(),
       );
     }
 
@@ -70,7 +72,7 @@ impl NodeRegistry {
   async fn load_snapshot(&mut self) -> Result<Vec<Node>> {
     let nodes = self
       .db
-      .exec(|conn| crate::node::db::get_all_nodes(conn))
+      .exec_traced(|conn| crate::node::db::get_all_nodes(conn))
       .await?;
     Ok(nodes)
   }
@@ -89,7 +91,7 @@ impl Handler<GetActorEntry<NodeConnActor>> for NodeRegistry {
 
 #[async_trait]
 impl Handler<Reload> for NodeRegistry {
-  async fn handle(&mut self, _: &mut Context<Self>, _: Reload) -> Result<()> {
+  async fn handle(&mut self, ctx: &mut Context<Self>, _: Reload) -> Result<()> {
     use flo_net::packet::FloPacket;
     use flo_net::proto::flo_connect::{PacketAddNode, PacketRemoveNode};
     use s2_grpc_utils::S2ProtoPack;
@@ -103,6 +105,7 @@ impl Handler<Reload> for NodeRegistry {
       for id in self.map.keys().cloned().collect::<Vec<i32>>() {
         if !new_ids.contains(&id) {
           self.map.remove(&id);
+          self.utilization.remove(&id);
           broadcast_frames.push(PacketRemoveNode { node_id: id }.encode_as_frame()?);
           tracing::info!(id, "node removed");
         }
@@ -114,7 +117,7 @@ impl Handler<Reload> for NodeRegistry {
         tracing::info!(id = config.id, "node added: {}", config.addr);
         self.map.insert(
           config.id,
-          NodeConnActor::new(config, self.game_reg_addr.resolve().await?).start(),
+          NodeConnActor::new(config, self.game_reg_addr.resolve().await?, ctx.addr()).start(),
         );
         broadcast_frames.push(
           PacketAddNode {
@@ -150,3 +153,48 @@ impl Handler<ListNode> for NodeRegistry {
     Vec::<_>::clone(&self.nodes_snapshot.load())
   }
 }
+
+pub(crate) struct UpdateNodeUtilization {
+  pub node_id: i32,
+  pub sample: NodeUtilizationSample,
+}
+
+impl Message for UpdateNodeUtilization {
+  type Result = ();
+}
+
+#[async_trait]
+impl Handler<UpdateNodeUtilization> for NodeRegistry {
+  async fn handle(
+    &mut self,
+    _: &mut Context<Self>,
+    UpdateNodeUtilization { node_id, sample }: UpdateNodeUtilization,
+  ) {
+    if !self.map.contains_key(&node_id) {
+      return;
+    }
+    self
+      .utilization
+      .entry(node_id)
+      .or_insert_with(|| NodeUtilization::new(node_id))
+      .push(sample);
+  }
+}
+
+/// Recent load of the nodes, nodes that never reported are missing
+pub struct GetNodeUtilizationMap;
+
+impl Message for GetNodeUtilizationMap {
+  type Result = BTreeMap<i32, NodeUtilization>;
+}
+
+#[async_trait]
+impl Handler<GetNodeUtilizationMap> for NodeRegistry {
+  async fn handle(
+    &mut self,
+    _: &mut Context<Self>,
+    _: GetNodeUtilizationMap,
+  ) -> BTreeMap<i32, NodeUtilization> {
+    self.utilization.clone()
+  }
+}
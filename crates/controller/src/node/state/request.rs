@@ -54,12 +54,14 @@ impl Drop for PendingRequest {
 pub enum RequestId {
   CreateGame(i32),
   PlayerLeave(PlayerLeaveRequestId),
+  RelayPlayers(i32),
 }
 
 #[derive(Debug)]
 pub enum Response {
   GameCreated(CreatedGameInfo),
   PlayerLeave(PlayerLeaveResponse),
+  RelayPlayers,
 }
 
 #[derive(Debug, S2ProtoUnpack)]
@@ -175,6 +177,25 @@ impl Handler<Request> for NodeRequestActor {
   }
 }
 
+/// Frames without a response
+struct SendFrame(Frame);
+
+impl Message for SendFrame {
+  type Result = Result<()>;
+}
+
+#[async_trait]
+impl Handler<SendFrame> for NodeRequestActor {
+  async fn handle(&mut self, _ctx: &mut Context<Self>, SendFrame(frame): SendFrame) -> Result<()> {
+    self
+      .frame_tx
+      .send(frame)
+      .await
+      .map_err(|_| Error::NodeRequestCancelled)?;
+    Ok(())
+  }
+}
+
 async fn request_callback(addr: &Addr<NodeRequestActor>, id: RequestId, result: Result<Response>) {
   if addr.notify(RequestDone { id, result }).await.is_err() {
     tracing::debug!("RequestDone: cancelled: request_id = {:?}", id);
@@ -231,8 +252,21 @@ pub trait NodeRequestExt {
     &self,
     game: Game,
     ban_list_map: BTreeMap<i32, Vec<PlayerBanType>>,
+    referee_player_ids: Vec<i32>,
   ) -> Result<CreatedGameInfo>;
   async fn player_force_leave(&self, game_id: i32, player_id: i32) -> Result<PlayerLeaveResponse>;
+  async fn relay_players(
+    &self,
+    game_id: i32,
+    target_addr: String,
+    tokens: Vec<PlayerToken>,
+  ) -> Result<()>;
+  async fn update_player_bans(
+    &self,
+    game_id: i32,
+    player_id: i32,
+    ban_list: Vec<PlayerBanType>,
+  ) -> Result<()>;
 }
 
 #[async_trait]
@@ -241,6 +275,7 @@ impl NodeRequestExt for Addr<NodeRequestActor> {
     &self,
     game: Game,
     mut ban_list_map: BTreeMap<i32, Vec<PlayerBanType>>,
+    referee_player_ids: Vec<i32>,
   ) -> Result<CreatedGameInfo> {
     let game_id = game.id;
 
@@ -265,6 +300,12 @@ impl NodeRequestExt for Addr<NodeRequestActor> {
           }),
           settings: Some(slot.settings.clone().pack()?),
           client_status: Default::default(),
+          referee: slot.settings.team == 24
+            && slot
+              .player
+              .as_ref()
+              .map(|player| referee_player_ids.contains(&player.id))
+              .unwrap_or_default(),
         });
       }
     }
@@ -276,10 +317,15 @@ impl NodeRequestExt for Addr<NodeRequestActor> {
           map_path: game.map.path.clone(),
           map_sha1: game.map.sha1.to_vec(),
           map_checksum: game.map.checksum,
+          game_mode: game.game_mode as i32,
+          equalize_ping: game.equalize_ping,
+          step_min: game.step_min.map(|v| v as u32),
+          step_max: game.step_max.map(|v| v as u32),
         }),
         slots,
         status: Default::default(),
       }),
+      trace_context: flo_log::trace_context::current().unwrap_or_default(),
     };
 
     let req = Request {
@@ -322,4 +368,54 @@ impl NodeRequestExt for Addr<NodeRequestActor> {
       }
     }
   }
+
+  async fn relay_players(
+    &self,
+    game_id: i32,
+    target_addr: String,
+    tokens: Vec<PlayerToken>,
+  ) -> Result<()> {
+    let req_id = RequestId::RelayPlayers(game_id);
+
+    let pkt = PacketControllerRelayPlayers {
+      game_id,
+      target_addr,
+      players: tokens
+        .into_iter()
+        .map(|token| flo_net::proto::flo_node::PlayerToken {
+          player_id: token.player_id,
+          token: token.to_vec(),
+        })
+        .collect(),
+    };
+
+    let req = Request {
+      id: req_id,
+      frame: pkt.encode_as_frame()?,
+    };
+
+    let res = self.send(req).await??;
+    match res.await? {
+      Response::RelayPlayers => Ok(()),
+      other => {
+        tracing::error!(game_id, "unexpected node response: {:?}", other);
+        Err(Error::NodeResponseUnexpected)
+      }
+    }
+  }
+
+  async fn update_player_bans(
+    &self,
+    game_id: i32,
+    player_id: i32,
+    ban_list: Vec<PlayerBanType>,
+  ) -> Result<()> {
+    let pkt = PacketControllerUpdatePlayerBans {
+      game_id,
+      player_id,
+      ban_list: ban_list.into_iter().map(|v| v as i32).collect(),
+    };
+    self.send(SendFrame(pkt.encode_as_frame()?)).await??;
+    Ok(())
+  }
 }
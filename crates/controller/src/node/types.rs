@@ -20,6 +20,7 @@ pub struct Node {
   pub country_id: String,
   #[s2_grpc(skip_pack)]
   pub disabled: bool,
+  pub ip_addr_v6: Option<String>,
 }
 
 pub type NodeRefColumns = (
@@ -28,6 +29,7 @@ pub type NodeRefColumns = (
   node::dsl::location,
   node::dsl::ip_addr,
   node::dsl::country_id,
+  node::dsl::ip_addr_v6,
 );
 
 #[derive(Debug, Serialize, Deserialize, Clone, S2ProtoPack, S2ProtoUnpack, Queryable)]
@@ -38,6 +40,7 @@ pub struct NodeRef {
   pub location: String,
   pub ip_addr: String,
   pub country_id: String,
+  pub ip_addr_v6: Option<String>,
 }
 
 impl NodeRef {
@@ -47,6 +50,7 @@ impl NodeRef {
     node::dsl::location,
     node::dsl::ip_addr,
     node::dsl::country_id,
+    node::dsl::ip_addr_v6,
   );
 }
 
@@ -58,6 +62,7 @@ impl From<Node> for NodeRef {
       location: node.location,
       ip_addr: node.ip_addr,
       country_id: node.country_id,
+      ip_addr_v6: node.ip_addr_v6,
     }
   }
 }
@@ -0,0 +1,126 @@
+//! Load reported by nodes, see `PacketNodeUtilization`.
+
+use chrono::{DateTime, Duration, Utc};
+use flo_net::proto::flo_node::PacketNodeUtilization;
+use s2_grpc_utils::S2ProtoPack;
+use serde::Serialize;
+
+/// Nodes report every 10 seconds, about 5 minutes of history
+const WINDOW_SIZE: usize = 30;
+/// Nodes that stopped reporting are considered idle after this duration
+const STALE_AFTER_SECS: i64 = 60;
+
+#[derive(Debug, Clone, Serialize, S2ProtoPack)]
+#[s2_grpc(message_type = "flo_grpc::node::NodeUtilizationSample")]
+pub struct NodeUtilizationSample {
+  pub cpu_usage: Option<f32>,
+  pub memory_usage: Option<f32>,
+  pub games: u32,
+  pub player_connections: u32,
+  pub bytes_in_per_sec: u64,
+  pub bytes_out_per_sec: u64,
+  pub max_games: Option<u32>,
+  pub received_at: DateTime<Utc>,
+}
+
+impl From<PacketNodeUtilization> for NodeUtilizationSample {
+  fn from(packet: PacketNodeUtilization) -> Self {
+    Self {
+      cpu_usage: packet.cpu_usage,
+      memory_usage: packet.memory_usage,
+      games: packet.games,
+      player_connections: packet.player_connections,
+      bytes_in_per_sec: packet.bytes_in_per_sec,
+      bytes_out_per_sec: packet.bytes_out_per_sec,
+      max_games: packet.max_games,
+      received_at: Utc::now(),
+    }
+  }
+}
+
+/// The last samples of a node, oldest first
+#[derive(Debug, Default, Clone, Serialize, S2ProtoPack)]
+#[s2_grpc(message_type = "flo_grpc::node::NodeUtilization")]
+pub struct NodeUtilization {
+  pub node_id: i32,
+  pub samples: Vec<NodeUtilizationSample>,
+}
+
+impl NodeUtilization {
+  pub fn new(node_id: i32) -> Self {
+    Self {
+      node_id,
+      samples: Vec::with_capacity(WINDOW_SIZE),
+    }
+  }
+
+  pub fn push(&mut self, sample: NodeUtilizationSample) {
+    if self.samples.len() == WINDOW_SIZE {
+      self.samples.remove(0);
+    }
+    self.samples.push(sample);
+  }
+
+  fn recent(&self) -> impl Iterator<Item = &NodeUtilizationSample> {
+    let since = Utc::now() - Duration::seconds(STALE_AFTER_SECS);
+    self.samples.iter().filter(move |v| v.received_at > since)
+  }
+
+  /// Average CPU usage of the recent samples
+  pub fn cpu_usage(&self) -> Option<f32> {
+    let values: Vec<f32> = self.recent().filter_map(|v| v.cpu_usage).collect();
+    if values.is_empty() {
+      return None;
+    }
+    Some(values.iter().sum::<f32>() / values.len() as f32)
+  }
+
+  /// Active games of the last recent sample
+  pub fn games(&self) -> u32 {
+    self.recent().last().map(|v| v.games).unwrap_or_default()
+  }
+
+  /// Game limit of the last recent sample, `None` if unlimited or unknown
+  pub fn max_games(&self) -> Option<u32> {
+    self.recent().last().and_then(|v| v.max_games)
+  }
+
+  pub fn is_overloaded(&self) -> bool {
+    match (*crate::config::NODE_MAX_CPU_USAGE, self.cpu_usage()) {
+      (Some(max), Some(value)) => value >= max,
+      _ => false,
+    }
+  }
+}
+
+#[test]
+fn test_node_utilization() {
+  let sample = |cpu_usage: Option<f32>, games: u32, age_secs: i64| NodeUtilizationSample {
+    cpu_usage,
+    memory_usage: None,
+    games,
+    player_connections: 0,
+    bytes_in_per_sec: 0,
+    bytes_out_per_sec: 0,
+    max_games: None,
+    received_at: Utc::now() - Duration::seconds(age_secs),
+  };
+
+  let mut value = NodeUtilization::new(1);
+  assert_eq!(value.cpu_usage(), None);
+  assert_eq!(value.games(), 0);
+
+  value.push(sample(Some(90.), 1, 120));
+  assert_eq!(value.cpu_usage(), None);
+  value.push(sample(Some(10.), 2, 20));
+  value.push(sample(None, 3, 10));
+  value.push(sample(Some(30.), 4, 0));
+  assert_eq!(value.cpu_usage(), Some(20.));
+  assert_eq!(value.games(), 4);
+
+  for _ in 0..WINDOW_SIZE {
+    value.push(sample(Some(50.), 5, 0));
+  }
+  assert_eq!(value.samples.len(), WINDOW_SIZE);
+  assert_eq!(value.cpu_usage(), Some(50.));
+}
@@ -0,0 +1,248 @@
+//! Player identity providers.
+//!
+//! An [`AuthProvider`] turns credentials issued by an external service into a
+//! [`PlayerIdentity`] and issues the tokens of its players. [`AuthProviders::login`]
+//! upserts the matching player, so communities don't have to pre-provision player ids.
+
+use bs_diesel_utils::ExecutorRef;
+use flo_state::async_trait;
+use hyper::client::HttpConnector;
+use hyper::header::{ACCEPT, AUTHORIZATION, CONTENT_TYPE};
+use hyper::{Body, Client, Request, Response};
+use hyper_tls::HttpsConnector;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::config::AuthProviderConfig;
+use crate::db::ExecutorExt;
+use crate::error::*;
+use crate::player::db::UpsertPlayer;
+use crate::player::token::create_player_token;
+use crate::player::{Player, PlayerSource};
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug)]
+pub struct AuthCredentials {
+  /// OAuth2 authorization code
+  pub code: String,
+  /// Redirect URI used to obtain `code`
+  pub redirect_uri: String,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct PlayerIdentity {
+  /// Stable id of the user at the provider
+  pub subject: String,
+  pub name: String,
+}
+
+#[async_trait]
+pub trait AuthProvider: Send + Sync {
+  fn name(&self) -> &str;
+  async fn authenticate(&self, credentials: &AuthCredentials) -> Result<PlayerIdentity>;
+
+  /// Issues the token the player connects with, a regular player token by default
+  fn issue_token(&self, player_id: i32) -> Result<String> {
+    create_player_token(player_id)
+  }
+}
+
+/// OAuth2 authorization code flow, the identity is read from the userinfo endpoint.
+///
+/// Works with OpenID Connect providers (Google, Battle.net) and
+/// OAuth2 services exposing a user endpoint (Discord).
+pub struct OAuth2Provider {
+  config: AuthProviderConfig,
+  client: Client<HttpsConnector<HttpConnector>, Body>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+  access_token: String,
+}
+
+impl OAuth2Provider {
+  pub fn new(config: AuthProviderConfig) -> Self {
+    OAuth2Provider {
+      config,
+      client: Client::builder().build(HttpsConnector::new()),
+    }
+  }
+
+  async fn request(&self, req: Request<Body>) -> Result<Vec<u8>> {
+    let res: Response<Body> = tokio::time::timeout(REQUEST_TIMEOUT, self.client.request(req))
+      .await
+      .map_err(|err| Error::Timeout(err.into()))??;
+    let status = res.status();
+    let body = hyper::body::to_bytes(res.into_body()).await?;
+    if !status.is_success() {
+      return Err(Error::AuthFailed(format!(
+        "{}: unexpected status: {}",
+        self.config.name, status
+      )));
+    }
+    Ok(body.to_vec())
+  }
+
+  async fn exchange_code(&self, credentials: &AuthCredentials) -> Result<String> {
+    let form = serde_urlencoded::to_string(&[
+      ("grant_type", "authorization_code"),
+      ("code", &credentials.code),
+      ("redirect_uri", &credentials.redirect_uri),
+      ("client_id", &self.config.client_id),
+      ("client_secret", &self.config.client_secret),
+    ])
+    .map_err(|err| Error::AuthFailed(err.to_string()))?;
+    let req = Request::post(&self.config.token_url)
+      .header(CONTENT_TYPE, "application/x-www-form-urlencoded")
+      .header(ACCEPT, "application/json")
+      .body(Body::from(form))
+      .map_err(|err| Error::AuthFailed(err.to_string()))?;
+    let res: TokenResponse = serde_json::from_slice(&self.request(req).await?)?;
+    Ok(res.access_token)
+  }
+
+  async fn get_userinfo(&self, access_token: &str) -> Result<Value> {
+    let req = Request::get(&self.config.userinfo_url)
+      .header(AUTHORIZATION, format!("Bearer {}", access_token))
+      .header(ACCEPT, "application/json")
+      .body(Body::empty())
+      .map_err(|err| Error::AuthFailed(err.to_string()))?;
+    Ok(serde_json::from_slice(&self.request(req).await?)?)
+  }
+
+  fn identity_from_userinfo(&self, userinfo: &Value) -> Result<PlayerIdentity> {
+    fn get_string(value: &Value, field: &str) -> Option<String> {
+      match value.get(field)? {
+        Value::String(v) if !v.is_empty() => Some(v.clone()),
+        Value::Number(v) => Some(v.to_string()),
+        _ => None,
+      }
+    }
+
+    let subject = get_string(userinfo, &self.config.subject_field).ok_or_else(|| {
+      Error::AuthFailed(format!(
+        "{}: userinfo field `{}` not found",
+        self.config.name, self.config.subject_field
+      ))
+    })?;
+    let name = get_string(userinfo, &self.config.name_field).unwrap_or_else(|| subject.clone());
+    Ok(PlayerIdentity { subject, name })
+  }
+}
+
+#[async_trait]
+impl AuthProvider for OAuth2Provider {
+  fn name(&self) -> &str {
+    &self.config.name
+  }
+
+  async fn authenticate(&self, credentials: &AuthCredentials) -> Result<PlayerIdentity> {
+    let access_token = self.exchange_code(credentials).await?;
+    let userinfo = self.get_userinfo(&access_token).await?;
+    self.identity_from_userinfo(&userinfo)
+  }
+}
+
+/// Auth providers by name
+#[derive(Clone, Default)]
+pub struct AuthProviders {
+  providers: Arc<BTreeMap<String, Box<dyn AuthProvider>>>,
+}
+
+impl AuthProviders {
+  pub fn new(providers: Vec<Box<dyn AuthProvider>>) -> Self {
+    AuthProviders {
+      providers: Arc::new(
+        providers
+          .into_iter()
+          .map(|provider| (provider.name().to_string(), provider))
+          .collect(),
+      ),
+    }
+  }
+
+  /// Creates an [`OAuth2Provider`] for each entry in [`crate::config::AUTH_PROVIDERS`]
+  pub fn from_config() -> Self {
+    Self::new(
+      crate::config::AUTH_PROVIDERS
+        .iter()
+        .cloned()
+        .map(|config| Box::new(OAuth2Provider::new(config)) as Box<dyn AuthProvider>)
+        .collect(),
+    )
+  }
+
+  /// Authenticates the credentials with `provider` and returns the player and the token
+  /// issued by the provider.
+  ///
+  /// Players are owned by the calling API client, with the `OAuth` source
+  /// and source id `<provider>:<subject>`.
+  pub async fn login(
+    &self,
+    db: &ExecutorRef,
+    api_client_id: i32,
+    provider: &str,
+    credentials: &AuthCredentials,
+  ) -> Result<(Player, String)> {
+    let provider = self
+      .providers
+      .get(provider)
+      .ok_or_else(|| Error::AuthProviderNotFound)?;
+    let identity = provider.authenticate(credentials).await?;
+    let upsert = UpsertPlayer {
+      api_client_id,
+      name: identity.name,
+      source: PlayerSource::OAuth,
+      source_id: format!("{}:{}", provider.name(), identity.subject),
+      source_state: None,
+      realm: Some(provider.name().to_string()),
+    };
+    let player = db
+      .exec_traced(move |conn| crate::player::db::upsert(conn, &upsert))
+      .await?;
+    let token = provider.issue_token(player.id)?;
+    Ok((player, token))
+  }
+}
+
+#[test]
+fn test_identity_from_userinfo() {
+  let provider = OAuth2Provider::new(AuthProviderConfig {
+    name: "discord".to_string(),
+    client_id: "id".to_string(),
+    client_secret: "secret".to_string(),
+    token_url: "https://discord.com/api/oauth2/token".to_string(),
+    userinfo_url: "https://discord.com/api/users/@me".to_string(),
+    subject_field: "id".to_string(),
+    name_field: "username".to_string(),
+  });
+
+  let identity = provider
+    .identity_from_userinfo(&serde_json::json!({
+      "id": "80351110224678912",
+      "username": "Nelly",
+    }))
+    .unwrap();
+  assert_eq!(
+    identity,
+    PlayerIdentity {
+      subject: "80351110224678912".to_string(),
+      name: "Nelly".to_string(),
+    }
+  );
+
+  let identity = provider
+    .identity_from_userinfo(&serde_json::json!({ "id": 12345 }))
+    .unwrap();
+  assert_eq!(identity.subject, "12345");
+  assert_eq!(identity.name, "12345");
+
+  assert!(provider
+    .identity_from_userinfo(&serde_json::json!({ "sub": "12345" }))
+    .is_err());
+}
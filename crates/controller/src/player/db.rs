@@ -1,15 +1,26 @@
 use crate::db::DbConn;
 use crate::error::*;
+use crate::game::chat_log::ChatLogEntry;
+use crate::game::db::PlayerGameRecord;
+use crate::game::incident::ActionIncident;
+use crate::game::stats::PlayerGameStats;
+use crate::player::report::PlayerReport;
 use crate::player::{Player, PlayerBan, PlayerBanType, PlayerRef, PlayerSource, SourceState};
-use crate::schema::{player, player_ban, player_mute};
+use crate::schema::{
+  action_incident, clan_member, game_chat_log, game_player_stats, game_slot_reservation, player,
+  player_ban, player_mute, player_mute_pattern, player_pending_frame, player_report,
+};
 use chrono::{DateTime, Utc};
+use diesel::dsl::sql;
 use diesel::prelude::*;
+use serde::Serialize;
 use serde_json::Value;
 use std::collections::{BTreeMap, HashMap};
 
 pub fn get(conn: &DbConn, id: i32) -> Result<Player> {
   player::table
     .find(id)
+    .filter(player::deleted_at.is_null())
     .first::<Row>(conn)
     .optional()?
     .ok_or_else(|| Error::PlayerNotFound)
@@ -21,7 +32,8 @@ pub fn get_ref(conn: &DbConn, id: i32) -> Result<PlayerRef> {
   use player::dsl;
   player::table
     .find(id)
-    .select((dsl::id, dsl::name, dsl::source, dsl::realm))
+    .filter(dsl::deleted_at.is_null())
+    .select(PlayerRef::COLUMNS)
     .first::<PlayerRef>(conn)
     .optional()?
     .ok_or_else(|| Error::PlayerNotFound)
@@ -32,7 +44,7 @@ pub fn get_refs_by_ids(conn: &DbConn, ids: &[i32]) -> Result<Vec<PlayerRef>> {
   use player::dsl;
   player::table
     .filter(dsl::id.eq_any(ids))
-    .select((dsl::id, dsl::name, dsl::source, dsl::realm))
+    .select(PlayerRef::COLUMNS)
     .load(conn)
     .map_err(Into::into)
 }
@@ -46,7 +58,7 @@ pub fn get_client_refs_by_ids(
   player::table
     .filter(dsl::api_client_id.eq(api_client_id))
     .filter(dsl::id.eq_any(ids))
-    .select((dsl::id, dsl::name, dsl::source, dsl::realm))
+    .select(PlayerRef::COLUMNS)
     .load(conn)
     .map_err(Into::into)
 }
@@ -64,10 +76,7 @@ pub fn get_player_map_by_api_source_ids(
         .and(dsl::api_client_id.eq(api_client_id)),
     )
     .filter(dsl::source_id.eq_any(ids))
-    .select((
-      dsl::source_id,
-      (dsl::id, dsl::name, dsl::source, dsl::realm),
-    ))
+    .select((dsl::source_id, PlayerRef::COLUMNS))
     .load::<(String, PlayerRef)>(conn)?;
   Ok(pairs.into_iter().collect())
 }
@@ -104,6 +113,55 @@ pub fn upsert(conn: &DbConn, data: &UpsertPlayer) -> Result<Player> {
     .map_err(Into::into)
 }
 
+/// Creates an anonymous player owned by the API client, with a random source id
+pub fn create_guest(conn: &DbConn, api_client_id: i32) -> Result<Player> {
+  let source_id = hex::encode(rand::random::<[u8; 16]>());
+  let insert = UpsertPlayer {
+    api_client_id,
+    name: format!("Guest-{}", &source_id[..6]),
+    source: PlayerSource::Guest,
+    source_id,
+    source_state: None,
+    realm: None,
+  };
+  diesel::insert_into(player::table)
+    .values(&insert)
+    .get_result::<Row>(conn)
+    .map(Into::into)
+    .map_err(Into::into)
+}
+
+/// Deletes the guest players created before `created_before`, like [`anonymize`].
+/// Guests still in an active game are skipped, returns the number of deleted guests
+pub fn delete_expired_guests(conn: &DbConn, created_before: DateTime<Utc>) -> Result<usize> {
+  let ids: Vec<i32> = player::table
+    .filter(player::source.eq(PlayerSource::Guest))
+    .filter(player::deleted_at.is_null())
+    .filter(player::created_at.lt(created_before))
+    .select(player::id)
+    .load(conn)?;
+  let mut deleted = 0;
+  for id in ids {
+    match anonymize(conn, id) {
+      Ok(_) => deleted += 1,
+      Err(Error::PlayerInActiveGame) => {}
+      Err(err) => return Err(err),
+    }
+  }
+  Ok(deleted)
+}
+
+/// Returns the ids of the guest players in `ids`
+pub fn filter_guest_ids(conn: &DbConn, ids: &[i32]) -> Result<Vec<i32>> {
+  use player::dsl;
+  player::table
+    .filter(dsl::id.eq_any(ids))
+    .filter(dsl::source.eq(PlayerSource::Guest))
+    .select(dsl::id)
+    .load(conn)
+    .map_err(Into::into)
+}
+
 pub fn add_mute(conn: &DbConn, player_id: i32, mute_player_id: i32) -> Result<()> {
   #[derive(Insertable)]
   #[table_name = "player_mute"]
@@ -137,6 +195,58 @@ pub fn remove_mute(conn: &DbConn, player_id: i32, mute_player_id: i32) -> Result
   Ok(())
 }
 
+/// `pattern` must be normalized, see [`flo_util::name_pattern::normalize`]
+const MAX_MUTE_PATTERNS: i64 = 20;
+
+/// Adding a pattern the player already has is a no-op, new patterns are limited to
+/// `MAX_MUTE_PATTERNS` per player
+pub fn add_mute_pattern(conn: &DbConn, player_id: i32, pattern: &str) -> Result<()> {
+  conn.transaction(|| {
+    // serializes concurrent adds of the same player
+    player::table
+      .find(player_id)
+      .select(player::id)
+      .for_update()
+      .first::<i32>(conn)
+      .optional()?
+      .ok_or_else(|| Error::PlayerNotFound)?;
+
+    let patterns: Vec<String> = player_mute_pattern::table
+      .filter(player_mute_pattern::player_id.eq(player_id))
+      .select(player_mute_pattern::pattern)
+      .load(conn)?;
+    if patterns.iter().any(|p| p == pattern) {
+      return Ok(());
+    }
+    if patterns.len() as i64 >= MAX_MUTE_PATTERNS {
+      return Err(Error::PlayerMutePatternLimit);
+    }
+
+    diesel::insert_into(player_mute_pattern::table)
+      .values((
+        player_mute_pattern::player_id.eq(player_id),
+        player_mute_pattern::pattern.eq(pattern),
+      ))
+      .execute(conn)?;
+
+    Ok(())
+  })
+}
+
+pub fn remove_mute_pattern(conn: &DbConn, player_id: i32, pattern: &str) -> Result<()> {
+  diesel::delete(
+    player_mute_pattern::table.filter(
+      player_mute_pattern::player_id
+        .eq(player_id)
+        .and(player_mute_pattern::pattern.eq(pattern)),
+    ),
+  )
+  .execute(conn)?;
+
+  Ok(())
+}
+
+/// Muted players of each player, including the players whose name matches one of their mute patterns
 pub fn get_mute_list_map(conn: &DbConn, player_ids: &[i32]) -> Result<BTreeMap<i32, Vec<i32>>> {
   use diesel::pg::expression::dsl::any;
   let pairs: Vec<(i32, i32)> = player_mute::table
@@ -154,6 +264,27 @@ pub fn get_mute_list_map(conn: &DbConn, player_ids: &[i32]) -> Result<BTreeMap<i
       .or_insert_with(|| vec![])
       .push(mute_player_id);
   }
+
+  let patterns: Vec<(i32, String)> = player_mute_pattern::table
+    .select((player_mute_pattern::player_id, player_mute_pattern::pattern))
+    .filter(player_mute_pattern::player_id.eq(any(player_ids)))
+    .load(conn)?;
+  if !patterns.is_empty() {
+    let names: Vec<(i32, String)> = player::table
+      .select((player::id, player::name))
+      .filter(player::id.eq(any(player_ids)))
+      .load(conn)?;
+    for (player_id, pattern) in patterns {
+      let list = map.entry(player_id).or_insert_with(|| vec![]);
+      for (id, name) in &names {
+        if *id != player_id && !list.contains(id) && flo_util::name_pattern::matches(&pattern, name)
+        {
+          list.push(*id);
+        }
+      }
+    }
+  }
+
   Ok(map)
 }
 
@@ -312,6 +443,229 @@ pub fn check_player_api_client_id(conn: &DbConn, api_client_id: i32, player_id:
   Ok(())
 }
 
+/// Returns the renamed player and the active games showing the old name
+pub fn rename(conn: &DbConn, player_id: i32, name: &str) -> Result<(PlayerRef, Vec<i32>)> {
+  use player::dsl;
+
+  if name.trim().is_empty() {
+    return Err(Error::PlayerNameInvalid);
+  }
+
+  conn.transaction(|| {
+    let player = diesel::update(
+      player::table
+        .find(player_id)
+        .filter(dsl::deleted_at.is_null()),
+    )
+    .set(dsl::name.eq(name))
+    .returning(PlayerRef::COLUMNS)
+    .get_result::<PlayerRef>(conn)
+    .optional()?
+    .ok_or_else(|| Error::PlayerNotFound)?;
+    crate::game::db::rename_game_creator(conn, player_id, name)?;
+    let game_ids = crate::game::db::get_player_active_game_ids(conn, player_id)?;
+    Ok((player, game_ids))
+  })
+}
+
+/// All data stored about a player
+#[derive(Debug, Serialize)]
+pub struct PlayerDataExport {
+  pub player: Player,
+  pub games: Vec<PlayerGameRecord>,
+  pub bans: Vec<PlayerBan>,
+  pub mute_player_ids: Vec<i32>,
+  pub mute_patterns: Vec<String>,
+  pub game_stats: Vec<PlayerGameStats>,
+  pub action_incidents: Vec<ActionIncident>,
+  /// Chat messages sent by the player
+  pub chat_messages: Vec<ChatLogEntry>,
+  /// Reports filed by the player
+  pub reports: Vec<PlayerReport>,
+  /// Reports about the player, without the reporter
+  pub received_reports: Vec<PlayerReport>,
+}
+
+pub fn export(conn: &DbConn, player_id: i32) -> Result<PlayerDataExport> {
+  let player = get(conn, player_id)?;
+  let games = crate::game::db::get_player_game_records(conn, player_id)?;
+  let bans = player_ban::table
+    .inner_join(player::table)
+    .select(PlayerBan::COLUMNS)
+    .filter(player_ban::player_id.eq(player_id))
+    .order(player_ban::id)
+    .load(conn)?;
+  let mute_player_ids = player_mute::table
+    .select(player_mute::mute_player_id)
+    .filter(player_mute::player_id.eq(player_id))
+    .order(player_mute::id)
+    .load(conn)?;
+  let mute_patterns = player_mute_pattern::table
+    .select(player_mute_pattern::pattern)
+    .filter(player_mute_pattern::player_id.eq(player_id))
+    .order(player_mute_pattern::id)
+    .load(conn)?;
+  let game_stats = game_player_stats::table
+    .select(PlayerGameStats::COLUMNS)
+    .filter(game_player_stats::player_id.eq(player_id))
+    .order(game_player_stats::game_id)
+    .load(conn)?;
+  let action_incidents = action_incident::table
+    .select(ActionIncident::COLUMNS)
+    .filter(action_incident::player_id.eq(player_id))
+    .order(action_incident::id)
+    .load(conn)?;
+  let chat_messages = game_chat_log::table
+    .select(ChatLogEntry::COLUMNS)
+    .filter(game_chat_log::player_id.eq(player_id))
+    .order(game_chat_log::id)
+    .load(conn)?;
+  let reports = player_report::table
+    .select(PlayerReport::COLUMNS)
+    .filter(player_report::reporter_player_id.eq(player_id))
+    .order(player_report::id)
+    .load(conn)?;
+  let received_reports = player_report::table
+    .select(PlayerReport::COLUMNS)
+    .filter(player_report::target_player_id.eq(player_id))
+    .order(player_report::id)
+    .load::<PlayerReport>(conn)?
+    .into_iter()
+    .map(|report| PlayerReport {
+      reporter_player_id: None,
+      ..report
+    })
+    .collect();
+  Ok(PlayerDataExport {
+    player,
+    games,
+    bans,
+    mute_player_ids,
+    mute_patterns,
+    game_stats,
+    action_incidents,
+    chat_messages,
+    reports,
+    received_reports,
+  })
+}
+
+/// Removes the personal data of a player.
+/// The row is kept with an anonymized name so game history stays consistent,
+/// a deleted player can no longer connect.
+/// Reports about the player are kept for moderation,
+/// reports filed and chat messages sent by the player are removed.
+pub fn anonymize(conn: &DbConn, player_id: i32) -> Result<()> {
+  let name = format!("Deleted#{}", player_id);
+  conn.transaction(|| {
+    if !crate::game::db::get_player_active_slots(conn, player_id)?.is_empty() {
+      return Err(Error::PlayerInActiveGame);
+    }
+
+    let n = diesel::update(
+      player::table
+        .find(player_id)
+        .filter(player::deleted_at.is_null()),
+    )
+    .set((
+      player::name.eq(&name),
+      player::source_id.eq(format!("deleted:{}", player_id)),
+      player::source_state.eq(Option::<Value>::None),
+      player::realm.eq(Option::<String>::None),
+      player::clan_tag.eq(Option::<String>::None),
+      player::deleted_at.eq(sql("now()")),
+    ))
+    .execute(conn)?;
+    if n == 0 {
+      return Err(Error::PlayerNotFound);
+    }
+
+    diesel::delete(player_ban::table.filter(player_ban::player_id.eq(player_id))).execute(conn)?;
+    diesel::delete(
+      player_mute::table.filter(
+        player_mute::player_id
+          .eq(player_id)
+          .or(player_mute::mute_player_id.eq(player_id)),
+      ),
+    )
+    .execute(conn)?;
+    diesel::delete(
+      game_slot_reservation::table.filter(game_slot_reservation::player_id.eq(player_id)),
+    )
+    .execute(conn)?;
+    diesel::delete(clan_member::table.filter(clan_member::player_id.eq(player_id)))
+      .execute(conn)?;
+    diesel::delete(player_mute_pattern::table.filter(player_mute_pattern::player_id.eq(player_id)))
+      .execute(conn)?;
+    diesel::delete(game_player_stats::table.filter(game_player_stats::player_id.eq(player_id)))
+      .execute(conn)?;
+    diesel::delete(action_incident::table.filter(action_incident::player_id.eq(player_id)))
+      .execute(conn)?;
+    diesel::delete(player_report::table.filter(player_report::reporter_player_id.eq(player_id)))
+      .execute(conn)?;
+    diesel::delete(game_chat_log::table.filter(game_chat_log::player_id.eq(player_id)))
+      .execute(conn)?;
+
+    crate::game::db::rename_game_creator(conn, player_id, &name)
+  })
+}
+
+/// Stored frame of a disconnected player, see [`crate::player::state::pending`]
+#[derive(Debug, Queryable)]
+pub struct PendingFrameRow {
+  pub player_id: i32,
+  pub type_id: i16,
+  pub payload: Vec<u8>,
+  pub expires_at: DateTime<Utc>,
+}
+
+/// Deletes the expired queues, returns the frames of the others in queue order
+pub fn get_pending_frames(conn: &DbConn) -> Result<Vec<PendingFrameRow>> {
+  use player_pending_frame::dsl;
+  diesel::delete(player_pending_frame::table.filter(dsl::expires_at.le(Utc::now())))
+    .execute(conn)?;
+  player_pending_frame::table
+    .order(dsl::id)
+    .select((dsl::player_id, dsl::type_id, dsl::payload, dsl::expires_at))
+    .load(conn)
+    .map_err(Into::into)
+}
+
+#[derive(Debug, Insertable)]
+#[table_name = "player_pending_frame"]
+pub struct InsertPendingFrame<'a> {
+  pub player_id: i32,
+  pub type_id: i16,
+  pub payload: &'a [u8],
+  pub expires_at: DateTime<Utc>,
+}
+
+/// Replaces the stored queues of `player_ids` with `frames`, expired queues are deleted
+pub fn replace_pending_frames(
+  conn: &DbConn,
+  player_ids: &[i32],
+  frames: &[InsertPendingFrame],
+) -> Result<()> {
+  use player_pending_frame::dsl;
+  conn.transaction(|| {
+    diesel::delete(
+      player_pending_frame::table.filter(
+        dsl::player_id
+          .eq_any(player_ids)
+          .or(dsl::expires_at.le(Utc::now())),
+      ),
+    )
+    .execute(conn)?;
+    // stays under the bind parameter limit of postgres
+    for chunk in frames.chunks(1000) {
+      diesel::insert_into(player_pending_frame::table)
+        .values(chunk)
+        .execute(conn)?;
+    }
+    Ok(())
+  })
+}
+
 #[derive(Debug, Insertable)]
 #[table_name = "player"]
 struct Insert<'a> {
@@ -342,6 +696,8 @@ pub struct Row {
   pub created_at: DateTime<Utc>,
   pub updated_at: DateTime<Utc>,
   pub api_client_id: i32,
+  pub deleted_at: Option<DateTime<Utc>>,
+  pub clan_tag: Option<String>,
 }
 
 impl From<Row> for Player {
@@ -370,6 +726,7 @@ impl From<Row> for PlayerRef {
       name: p.name,
       source: p.source,
       realm: p.realm,
+      clan_tag: p.clan_tag,
     }
   }
 }
@@ -0,0 +1,24 @@
+use chrono::Utc;
+use std::time::Duration;
+
+use crate::db::{ExecutorExt, ExecutorRef};
+use crate::player::token::GUEST_TOKEN_EXPIRATION_SECS;
+
+const CLEANUP_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// Deletes guest players once their token expired, they can't sign in again
+pub async fn cleanup_expired(db: ExecutorRef) {
+  let mut interval = tokio::time::interval(CLEANUP_INTERVAL);
+  loop {
+    interval.tick().await;
+    let created_before = Utc::now() - chrono::Duration::seconds(GUEST_TOKEN_EXPIRATION_SECS);
+    match db
+      .exec_traced(move |conn| crate::player::db::delete_expired_guests(conn, created_before))
+      .await
+    {
+      Ok(0) => {}
+      Ok(n) => tracing::info!("deleted {} expired guest players", n),
+      Err(err) => tracing::error!("delete expired guest players: {}", err),
+    }
+  }
+}
@@ -1,4 +1,8 @@
+pub mod auth;
 pub mod db;
+pub mod guest;
+pub mod report;
+pub mod reputation;
 pub mod session;
 pub(crate) mod state;
 pub mod token;
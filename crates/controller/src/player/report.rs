@@ -0,0 +1,255 @@
+//! Player reports, reviewed by moderators through the admin API.
+
+use bs_diesel_utils::BSDieselEnum;
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use s2_grpc_utils::{S2ProtoEnum, S2ProtoPack};
+use serde::{Deserialize, Serialize};
+
+use crate::db::DbConn;
+use crate::error::*;
+use crate::game::chat_log::ChatLogEntry;
+use crate::schema::{game_used_slot, player, player_report};
+
+const MAX_MESSAGE_LEN: usize = 2000;
+
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, PartialEq, BSDieselEnum, S2ProtoEnum)]
+#[repr(i32)]
+#[s2_grpc(proto_enum_type(flo_grpc::player::PlayerReportCategory))]
+pub enum PlayerReportCategory {
+  Other = 0,
+  Chat = 1,
+  Cheating = 2,
+  Griefing = 3,
+  Leaving = 4,
+}
+
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, PartialEq, BSDieselEnum, S2ProtoEnum)]
+#[repr(i32)]
+#[s2_grpc(proto_enum_type(flo_grpc::player::PlayerReportStatus))]
+pub enum PlayerReportStatus {
+  Open = 0,
+  Resolved = 1,
+  Dismissed = 2,
+}
+
+#[derive(Debug, Queryable, Serialize, S2ProtoPack)]
+#[s2_grpc(message_type = "flo_grpc::player::PlayerReport")]
+pub struct PlayerReport {
+  pub id: i32,
+  /// `None` for reports created by the controller
+  pub reporter_player_id: Option<i32>,
+  pub target_player_id: i32,
+  pub game_id: Option<i32>,
+  #[s2_grpc(proto_enum)]
+  pub category: PlayerReportCategory,
+  pub message: String,
+  #[s2_grpc(proto_enum)]
+  pub status: PlayerReportStatus,
+  pub resolution: Option<String>,
+  pub resolved_at: Option<DateTime<Utc>>,
+  pub created_at: DateTime<Utc>,
+}
+
+pub(crate) type PlayerReportColumns = (
+  player_report::id,
+  player_report::reporter_player_id,
+  player_report::target_player_id,
+  player_report::game_id,
+  player_report::category,
+  player_report::message,
+  player_report::status,
+  player_report::resolution,
+  player_report::resolved_at,
+  player_report::created_at,
+);
+
+impl PlayerReport {
+  pub(crate) const COLUMNS: PlayerReportColumns = (
+    player_report::id,
+    player_report::reporter_player_id,
+    player_report::target_player_id,
+    player_report::game_id,
+    player_report::category,
+    player_report::message,
+    player_report::status,
+    player_report::resolution,
+    player_report::resolved_at,
+    player_report::created_at,
+  );
+}
+
+#[derive(Debug)]
+pub struct CreatePlayerReportParams {
+  pub reporter_player_id: Option<i32>,
+  pub target_player_id: i32,
+  pub game_id: Option<i32>,
+  pub category: PlayerReportCategory,
+  pub message: String,
+}
+
+/// Stores a report, players reported for a game must both have played it
+pub fn create(conn: &DbConn, params: CreatePlayerReportParams) -> Result<PlayerReport> {
+  use player_report::dsl;
+
+  if params.reporter_player_id == Some(params.target_player_id) {
+    return Err(Error::PlayerReportTargetInvalid);
+  }
+
+  if params.message.chars().count() > MAX_MESSAGE_LEN {
+    return Err(Error::PlayerReportMessageTooLong);
+  }
+
+  if let Some(game_id) = params.game_id {
+    let player_ids: Vec<Option<i32>> = game_used_slot::table
+      .filter(game_used_slot::game_id.eq(game_id))
+      .select(game_used_slot::player_id)
+      .load(conn)?;
+    if !player_ids.contains(&Some(params.target_player_id)) {
+      return Err(Error::PlayerReportTargetInvalid);
+    }
+    if let Some(reporter_player_id) = params.reporter_player_id {
+      if !player_ids.contains(&Some(reporter_player_id)) {
+        return Err(Error::PlayerNotInGame);
+      }
+    }
+  }
+
+  diesel::insert_into(player_report::table)
+    .values((
+      dsl::reporter_player_id.eq(params.reporter_player_id),
+      dsl::target_player_id.eq(params.target_player_id),
+      dsl::game_id.eq(params.game_id),
+      dsl::category.eq(params.category),
+      dsl::message.eq(&params.message),
+    ))
+    .returning(PlayerReport::COLUMNS)
+    .get_result(conn)
+    .map_err(Into::into)
+}
+
+pub fn get(conn: &DbConn, id: i32) -> Result<PlayerReport> {
+  player_report::table
+    .find(id)
+    .select(PlayerReport::COLUMNS)
+    .first(conn)
+    .optional()?
+    .ok_or_else(|| Error::PlayerReportNotFound)
+}
+
+/// Chat log of the reported game, empty if the game has no stored log or is still running
+pub fn get_chat_log(conn: &DbConn, report: &PlayerReport) -> Result<Vec<ChatLogEntry>> {
+  let game_id = if let Some(id) = report.game_id {
+    id
+  } else {
+    return Ok(vec![]);
+  };
+  match crate::game::chat_log::get(conn, game_id) {
+    Ok(entries) => Ok(entries),
+    Err(Error::GameNotFound) | Err(Error::GameNotEnded) => Ok(vec![]),
+    Err(err) => Err(err),
+  }
+}
+
+#[derive(Debug)]
+pub struct ListPlayerReport {
+  pub reports: Vec<PlayerReport>,
+  pub next_id: Option<i32>,
+}
+
+/// Lists the reports of the players of the API client, newest first
+pub fn list(
+  conn: &DbConn,
+  api_client_id: i32,
+  status: Option<PlayerReportStatus>,
+  target_player_id: Option<i32>,
+  next_id: Option<i32>,
+) -> Result<ListPlayerReport> {
+  const PAGE_SIZE: i64 = 100;
+  let mut q = player_report::table
+    .inner_join(player::table.on(player::id.eq(player_report::target_player_id)))
+    .select(PlayerReport::COLUMNS)
+    .filter(player::api_client_id.eq(api_client_id))
+    .order(player_report::id.desc())
+    .limit(PAGE_SIZE + 1)
+    .into_boxed();
+
+  if let Some(status) = status {
+    q = q.filter(player_report::status.eq(status));
+  }
+
+  if let Some(id) = target_player_id {
+    q = q.filter(player_report::target_player_id.eq(id));
+  }
+
+  if let Some(id) = next_id {
+    q = q.filter(player_report::id.le(id));
+  }
+
+  let mut rows = q.load::<PlayerReport>(conn)?;
+  let next_id = if rows.len() > PAGE_SIZE as usize {
+    let id = rows.last().map(|row| row.id);
+    rows.truncate(PAGE_SIZE as usize);
+    id
+  } else {
+    None
+  };
+
+  Ok(ListPlayerReport {
+    reports: rows,
+    next_id,
+  })
+}
+
+/// Closes an open report
+pub fn resolve(
+  conn: &DbConn,
+  id: i32,
+  status: PlayerReportStatus,
+  resolution: Option<&str>,
+) -> Result<PlayerReport> {
+  use diesel::dsl::sql;
+  use player_report::dsl;
+
+  if status == PlayerReportStatus::Open {
+    return Err(Error::PlayerReportStatusInvalid);
+  }
+
+  let report = diesel::update(
+    player_report::table.filter(dsl::id.eq(id).and(dsl::status.eq(PlayerReportStatus::Open))),
+  )
+  .set((
+    dsl::status.eq(status),
+    dsl::resolution.eq(resolution),
+    dsl::resolved_at.eq(sql("now()")),
+  ))
+  .returning(PlayerReport::COLUMNS)
+  .get_result(conn)
+  .optional()?;
+
+  match report {
+    Some(report) => Ok(report),
+    None => {
+      // distinguish a missing report from a closed one
+      get(conn, id)?;
+      Err(Error::PlayerReportResolved)
+    }
+  }
+}
+
+/// Checks that the reported player belongs to the API client
+pub fn check_api_client_id(conn: &DbConn, api_client_id: i32, id: i32) -> Result<()> {
+  let n = player_report::table
+    .inner_join(player::table.on(player::id.eq(player_report::target_player_id)))
+    .filter(
+      player::api_client_id
+        .eq(api_client_id)
+        .and(player_report::id.eq(id)),
+    )
+    .count()
+    .get_result::<i64>(conn)?;
+  if n == 0 {
+    return Err(Error::PlayerOwnerCheckFailed);
+  }
+  Ok(())
+}
@@ -0,0 +1,138 @@
+//! Behavior score of players, computed from upheld reports and early leaves
+//! over the last `FLO_REPUTATION_WINDOW_DAYS` days.
+//!
+//! Players above the configured thresholds get soft restrictions:
+//! - auto-mute: the player starts games muted, as with a chat ban
+//! - low priority: reported to API clients so matchmaking can deprioritize the player
+
+use chrono::{Duration, Utc};
+use diesel::pg::expression::dsl::any;
+use diesel::prelude::*;
+use s2_grpc_utils::S2ProtoPack;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+use crate::db::DbConn;
+use crate::error::*;
+use crate::player::report::PlayerReportStatus;
+use crate::player::PlayerBanType;
+use crate::schema::{game_player_stats, player_report};
+
+/// Points per upheld report
+const REPORT_PENALTY: i32 = 10;
+/// Points per game left first
+const EARLY_LEAVE_PENALTY: i32 = 3;
+
+#[derive(Debug, Default, Clone, Serialize, S2ProtoPack)]
+#[s2_grpc(message_type = "flo_grpc::player::PlayerReputation")]
+pub struct PlayerReputation {
+  pub player_id: i32,
+  pub resolved_reports: i32,
+  pub early_leaves: i32,
+  pub score: i32,
+  pub auto_mute: bool,
+  pub low_priority: bool,
+}
+
+impl PlayerReputation {
+  fn new(player_id: i32, resolved_reports: i32, early_leaves: i32) -> Self {
+    let score = resolved_reports * REPORT_PENALTY + early_leaves * EARLY_LEAVE_PENALTY;
+    let above = |threshold: Option<i32>| threshold.map(|v| score >= v).unwrap_or(false);
+    PlayerReputation {
+      player_id,
+      resolved_reports,
+      early_leaves,
+      score,
+      auto_mute: above(*crate::config::REPUTATION_AUTO_MUTE_SCORE),
+      low_priority: above(*crate::config::REPUTATION_LOW_PRIORITY_SCORE),
+    }
+  }
+}
+
+pub fn get_map(conn: &DbConn, player_ids: &[i32]) -> Result<BTreeMap<i32, PlayerReputation>> {
+  let since = Utc::now() - Duration::days(*crate::config::REPUTATION_WINDOW_DAYS);
+
+  let reported: Vec<i32> = player_report::table
+    .filter(
+      player_report::target_player_id
+        .eq(any(player_ids))
+        .and(player_report::status.eq(PlayerReportStatus::Resolved))
+        .and(player_report::resolved_at.gt(since)),
+    )
+    .select(player_report::target_player_id)
+    .load(conn)?;
+  let left: Vec<i32> = game_player_stats::table
+    .filter(
+      game_player_stats::player_id
+        .eq(any(player_ids))
+        .and(game_player_stats::first_leaver.eq(true))
+        .and(game_player_stats::created_at.gt(since)),
+    )
+    .select(game_player_stats::player_id)
+    .load(conn)?;
+
+  Ok(
+    player_ids
+      .iter()
+      .map(|id| {
+        let resolved_reports = reported.iter().filter(|v| *v == id).count() as i32;
+        let early_leaves = left.iter().filter(|v| *v == id).count() as i32;
+        (
+          *id,
+          PlayerReputation::new(*id, resolved_reports, early_leaves),
+        )
+      })
+      .collect(),
+  )
+}
+
+pub fn get(conn: &DbConn, player_id: i32) -> Result<PlayerReputation> {
+  Ok(
+    get_map(conn, &[player_id])?
+      .remove(&player_id)
+      .unwrap_or_default(),
+  )
+}
+
+/// Adds a chat ban to auto-muted players
+pub fn apply_to_ban_list_map(
+  reputations: &BTreeMap<i32, PlayerReputation>,
+  ban_list_map: &mut BTreeMap<i32, Vec<PlayerBanType>>,
+) {
+  for reputation in reputations.values().filter(|v| v.auto_mute) {
+    let ban_list = ban_list_map.entry(reputation.player_id).or_default();
+    if !ban_list.contains(&PlayerBanType::Chat) {
+      tracing::debug!(player_id = reputation.player_id, "auto-muted by reputation");
+      ban_list.push(PlayerBanType::Chat);
+    }
+  }
+}
+
+#[test]
+fn test_apply_to_ban_list_map() {
+  let reputations = vec![
+    PlayerReputation {
+      player_id: 1,
+      auto_mute: true,
+      ..Default::default()
+    },
+    PlayerReputation {
+      player_id: 2,
+      auto_mute: true,
+      ..Default::default()
+    },
+    PlayerReputation {
+      player_id: 3,
+      ..Default::default()
+    },
+  ]
+  .into_iter()
+  .map(|v| (v.player_id, v))
+  .collect();
+  let mut ban_list_map = BTreeMap::new();
+  ban_list_map.insert(2, vec![PlayerBanType::Chat]);
+  apply_to_ban_list_map(&reputations, &mut ban_list_map);
+  assert_eq!(ban_list_map.get(&1), Some(&vec![PlayerBanType::Chat]));
+  assert_eq!(ban_list_map.get(&2), Some(&vec![PlayerBanType::Chat]));
+  assert_eq!(ban_list_map.get(&3), None);
+}
@@ -8,5 +8,30 @@ pub(super) fn get_session_update_packet(game_id: Option<i32>) -> PacketPlayerSes
       PlayerStatus::Idle.into()
     },
     game_id,
+    read_only: None,
+  }
+}
+
+/// What happens when a player connects while already connected
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MultiSessionPolicy {
+  /// The new connection replaces the old one, which is told where the new one came from
+  EvictOld,
+  /// The new connection is rejected until the old one is gone
+  RejectNew,
+  /// The new connection receives updates, but its requests are ignored
+  ReadOnly,
+}
+
+impl std::str::FromStr for MultiSessionPolicy {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "evict_old" => Ok(MultiSessionPolicy::EvictOld),
+      "reject_new" => Ok(MultiSessionPolicy::RejectNew),
+      "read_only" => Ok(MultiSessionPolicy::ReadOnly),
+      other => Err(format!("unknown multi-session policy: {}", other)),
+    }
   }
 }
@@ -1,45 +1,172 @@
 use super::PlayerRegistry;
 use crate::client::PlayerSender;
+use crate::event::{ControllerEvent, ControllerEventType};
+use crate::player::session::{get_session_update_packet, MultiSessionPolicy};
+use crate::player::state::pending::uncovered_by_initial_state;
 use crate::player::state::PlayerState;
+use flo_net::packet::{FloPacket, Frame};
+use flo_net::proto::flo_connect::PacketPlayerSessionUpdate;
 use flo_state::{async_trait, Context, Handler, Message};
+use std::net::IpAddr;
 
 pub struct Connect {
   pub game_id: Option<i32>,
   pub sender: PlayerSender,
+  pub ip: IpAddr,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConnectResult {
+  Accepted,
+  ReadOnly,
+  Rejected,
 }
 
 impl Message for Connect {
-  type Result = ();
+  type Result = ConnectResult;
 }
 
 #[async_trait]
 impl Handler<Connect> for PlayerRegistry {
-  async fn handle(&mut self, _: &mut Context<Self>, message: Connect) {
+  async fn handle(&mut self, _: &mut Context<Self>, message: Connect) -> ConnectResult {
     let player_id = message.sender.player_id();
+    if let Some(state) = self.registry.get_mut(&player_id) {
+      match *crate::config::MULTI_SESSION_POLICY {
+        MultiSessionPolicy::EvictOld => {}
+        MultiSessionPolicy::RejectNew => return ConnectResult::Rejected,
+        MultiSessionPolicy::ReadOnly => {
+          message.sender.set_read_only(true);
+          state.read_only_senders.push(message.sender);
+          return ConnectResult::ReadOnly;
+        }
+      }
+    }
+
+    self.events.publish(ControllerEvent::player(
+      ControllerEventType::PlayerConnected,
+      player_id,
+      message.game_id,
+    ));
     let removed = self.registry.insert(
       player_id,
       PlayerState::new(player_id, message.game_id, message.sender),
     );
     if let Some(state) = removed {
-      state.shutdown().await;
+      state.shutdown(Some(message.ip.to_string())).await;
+    }
+    if let Some(frames) = self.take_pending(player_id) {
+      if let Some(state) = self.registry.get_mut(&player_id) {
+        tracing::debug!(player_id, "flush pending frames");
+        state.try_send_frames(frames);
+      }
     }
+    ConnectResult::Accepted
   }
 }
 
 pub struct Disconnect {
   pub player_id: i32,
+  pub session_id: u64,
+  /// Frames the client didn't acknowledge, queued for its next connection
+  pub unacked: Vec<Frame>,
 }
 
 impl Message for Disconnect {
-  type Result = ();
+  type Result = Option<DisconnectResult>;
+}
+
+/// Returned if the current session of the player was removed
+#[derive(Debug)]
+pub struct DisconnectResult {
+  pub game_id: Option<i32>,
 }
 
 #[async_trait]
 impl Handler<Disconnect> for PlayerRegistry {
-  async fn handle(&mut self, _: &mut Context<Self>, message: Disconnect) {
+  async fn handle(
+    &mut self,
+    _: &mut Context<Self>,
+    message: Disconnect,
+  ) -> Option<DisconnectResult> {
     let player_id = message.player_id;
-    if let Some(state) = self.registry.remove(&player_id) {
-      state.shutdown().await;
+    let session_id = message.session_id;
+    let state = match self.registry.get_mut(&player_id) {
+      Some(state) => state,
+      None => {
+        // evicted session of a player who disconnected since
+        self.push_pending(player_id, message.unacked.into());
+        return None;
+      }
+    };
+
+    // an evicted or read-only session must not remove the current one
+    if state.sender.session_id() != session_id {
+      let read_only = state
+        .read_only_senders
+        .iter()
+        .any(|sender| sender.session_id() == session_id);
+      if read_only {
+        state
+          .read_only_senders
+          .retain(|sender| sender.session_id() != session_id);
+      } else {
+        // the frames the evicted session didn't acknowledge go to the one that replaced it
+        state.try_send_frames(uncovered_by_initial_state(message.unacked).into());
+      }
+      return None;
+    }
+
+    // the oldest read-only session takes over, it already received the unacked frames
+    if !state.read_only_senders.is_empty() {
+      let sender = state.read_only_senders.remove(0);
+      sender.set_read_only(false);
+      state.sender = sender;
+      tracing::debug!(player_id, "read-only session promoted");
+      let update = PacketPlayerSessionUpdate {
+        read_only: Some(false),
+        ..get_session_update_packet(state.game_id)
+      };
+      match update.encode_as_frame() {
+        Ok(frame) => {
+          state.try_send_frames(frame.into());
+        }
+        Err(err) => tracing::error!("encode session update: {}", err),
+      }
+      return None;
     }
+
+    let state = self.registry.remove(&player_id)?;
+    let game_id = state.game_id;
+    self.events.publish(ControllerEvent::player(
+      ControllerEventType::PlayerDisconnected,
+      player_id,
+      game_id,
+    ));
+    state.shutdown(None).await;
+    self.start_pending(player_id, Some(message.unacked.into()));
+    Some(DisconnectResult { game_id })
+  }
+}
+
+/// Filters the players with an active connection
+pub struct GetOnlinePlayers {
+  pub player_ids: Vec<i32>,
+}
+
+impl Message for GetOnlinePlayers {
+  type Result = Vec<i32>;
+}
+
+#[async_trait]
+impl Handler<GetOnlinePlayers> for PlayerRegistry {
+  async fn handle(
+    &mut self,
+    _: &mut Context<Self>,
+    GetOnlinePlayers { player_ids }: GetOnlinePlayers,
+  ) -> Vec<i32> {
+    player_ids
+      .into_iter()
+      .filter(|id| self.registry.contains_key(id))
+      .collect()
   }
 }
@@ -1,46 +1,55 @@
 pub mod conn;
+pub mod pending;
 pub mod ping;
 pub mod sender;
 
 use crate::client::PlayerSender;
-use crate::error::Error;
-use crate::state::Data;
-use flo_state::{async_trait, Actor, RegistryRef, Service};
+use crate::event::EventHub;
+use flo_state::Actor;
 use flo_types::ping::PingStats;
 
 use crate::player::state::sender::PlayerFrames;
+use pending::{PendingFrames, PendingFramesUpdate};
 use std::collections::BTreeMap;
+use tokio::sync::mpsc;
 
+/// Number of `PlayerRegistry` actors, see [`PlayerRegistryHandle`](sender::PlayerRegistryHandle)
+const REGISTRY_SHARDS: usize = 16;
+
+/// Connections of the players of one shard
 #[derive(Debug)]
 pub struct PlayerRegistry {
   registry: BTreeMap<i32, PlayerState>,
+  pending: BTreeMap<i32, PendingFrames>,
+  pending_tx: mpsc::UnboundedSender<PendingFramesUpdate>,
+  events: EventHub,
 }
 
 impl PlayerRegistry {
-  pub fn new() -> Self {
+  pub fn new(
+    events: EventHub,
+    pending: BTreeMap<i32, PendingFrames>,
+    pending_tx: mpsc::UnboundedSender<PendingFramesUpdate>,
+  ) -> Self {
     Self {
       registry: Default::default(),
+      pending,
+      pending_tx,
+      events,
     }
   }
 }
 
 impl Actor for PlayerRegistry {}
 
-#[async_trait]
-impl Service<Data> for PlayerRegistry {
-  type Error = Error;
-
-  async fn create(_registry: &mut RegistryRef<Data>) -> Result<Self, Self::Error> {
-    Ok(PlayerRegistry::new())
-  }
-}
-
 #[derive(Debug)]
 pub struct PlayerState {
   pub player_id: i32,
   pub ping_map: BTreeMap<i32, PingStats>,
   pub game_id: Option<i32>,
   pub sender: PlayerSender,
+  /// Additional connections under [`MultiSessionPolicy::ReadOnly`](crate::player::session::MultiSessionPolicy)
+  pub read_only_senders: Vec<PlayerSender>,
 }
 
 impl PlayerState {
@@ -50,11 +59,17 @@ impl PlayerState {
       game_id,
       ping_map: Default::default(),
       sender,
+      read_only_senders: vec![],
     }
   }
 
+  /// Returns false if the primary connection is broken,
+  /// broken read-only connections are dropped
   fn try_send_frames(&mut self, frames: PlayerFrames) -> bool {
     for frame in frames {
+      self
+        .read_only_senders
+        .retain(|sender| sender.try_send(frame.clone()));
       if !self.sender.try_send(frame) {
         return false;
       }
@@ -62,7 +77,7 @@ impl PlayerState {
     true
   }
 
-  async fn shutdown(mut self) {
-    self.sender.disconnect_multi().await;
+  async fn shutdown(mut self, taken_over_by_ip: Option<String>) {
+    self.sender.disconnect_multi(taken_over_by_ip).await;
   }
 }
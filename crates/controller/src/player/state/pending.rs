@@ -0,0 +1,311 @@
+//! Frames sent to players whose connection dropped, delivered when they reconnect.
+//!
+//! A queue is started when the primary connection of a player goes away and is kept
+//! for [`PENDING_FRAMES_TTL`], holding up to [`PENDING_FRAMES_MAX`] frames, the oldest
+//! frames are dropped first. Reconnecting clients receive the state of their game first,
+//! then the queued frames the state doesn't cover, such as chat messages.
+//!
+//! Queues live in the player registry and are written to the database in the background,
+//! the registry loads them when the controller starts.
+
+use super::PlayerRegistry;
+use crate::db::{ExecutorExt, ExecutorRef};
+use crate::error::*;
+use crate::player::db::{InsertPendingFrame, PendingFrameRow};
+use crate::player::state::sender::PlayerFrames;
+use chrono::{DateTime, Utc};
+use flo_net::packet::{Frame, FramePayload, PacketTypeId};
+use std::collections::{BTreeMap, VecDeque};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+
+const PENDING_FRAMES_MAX: usize = 100;
+/// How long a player can take to reconnect, they keep their lobby slot meanwhile
+pub const PENDING_FRAMES_TTL: Duration = Duration::from_secs(60);
+
+/// Frames replaced by the state sent to reconnecting clients, see `send_initial_state`
+fn is_covered_by_initial_state(type_id: PacketTypeId) -> bool {
+  matches!(
+    type_id,
+    PacketTypeId::PlayerSessionUpdate
+      | PacketTypeId::ListNodes
+      | PacketTypeId::AddNode
+      | PacketTypeId::RemoveNode
+      | PacketTypeId::GameInfo
+      | PacketTypeId::GamePlayerEnter
+      | PacketTypeId::GamePlayerLeave
+      | PacketTypeId::GameSlotUpdate
+      | PacketTypeId::GameSelectNode
+      | PacketTypeId::GamePlayerToken
+      | PacketTypeId::PlayerMuteListUpdate
+  )
+}
+
+pub(super) fn uncovered_by_initial_state(frames: Vec<Frame>) -> Vec<Frame> {
+  frames
+    .into_iter()
+    .filter(|frame| !is_covered_by_initial_state(frame.type_id))
+    .collect()
+}
+
+#[derive(Debug)]
+pub struct PendingFrames {
+  expires_at: Instant,
+  frames: VecDeque<Frame>,
+  dropped: usize,
+}
+
+impl PendingFrames {
+  fn new(now: Instant) -> Self {
+    Self {
+      expires_at: now + PENDING_FRAMES_TTL,
+      frames: VecDeque::new(),
+      dropped: 0,
+    }
+  }
+
+  /// Queue loaded from the database, `None` if it expired
+  fn restore(expires_at: DateTime<Utc>, frames: Vec<Frame>) -> Option<Self> {
+    let ttl = (expires_at - Utc::now()).to_std().ok()?;
+    Some(Self {
+      expires_at: Instant::now() + ttl,
+      frames: frames.into(),
+      dropped: 0,
+    })
+  }
+
+  fn stored_expires_at(&self) -> DateTime<Utc> {
+    let ttl = self.expires_at.saturating_duration_since(Instant::now());
+    Utc::now() + chrono::Duration::from_std(ttl).unwrap_or_else(|_| chrono::Duration::zero())
+  }
+
+  fn is_expired(&self, now: Instant) -> bool {
+    now >= self.expires_at
+  }
+
+  fn push(&mut self, frames: PlayerFrames) {
+    for frame in frames {
+      if is_covered_by_initial_state(frame.type_id) {
+        continue;
+      }
+      if self.frames.len() == PENDING_FRAMES_MAX {
+        self.frames.pop_front();
+        self.dropped += 1;
+      }
+      self.frames.push_back(frame);
+    }
+  }
+}
+
+impl PlayerRegistry {
+  /// Starts queueing the frames of a player whose connection is gone
+  pub(super) fn start_pending(&mut self, player_id: i32, frames: Option<PlayerFrames>) {
+    let now = Instant::now();
+    self.pending.retain(|_, pending| !pending.is_expired(now));
+    let pending = self
+      .pending
+      .entry(player_id)
+      .or_insert_with(|| PendingFrames::new(now));
+    if let Some(frames) = frames {
+      pending.push(frames);
+    }
+    self.save_pending(player_id);
+  }
+
+  /// Frames of players without a queue are discarded
+  pub(super) fn push_pending(&mut self, player_id: i32, frames: PlayerFrames) {
+    if let Some(pending) = self.pending.get_mut(&player_id) {
+      if !pending.is_expired(Instant::now()) {
+        pending.push(frames);
+        self.save_pending(player_id);
+      }
+    }
+  }
+
+  /// Removes the queue of a reconnected player, must be sent after the initial state
+  pub(super) fn take_pending(&mut self, player_id: i32) -> Option<PlayerFrames> {
+    let pending = self.pending.remove(&player_id)?;
+    self.save_pending(player_id);
+    if pending.is_expired(Instant::now()) || pending.frames.is_empty() {
+      return None;
+    }
+    if pending.dropped > 0 {
+      tracing::debug!(
+        player_id,
+        dropped = pending.dropped,
+        "pending frames dropped"
+      );
+    }
+    Some(PlayerFrames::Multi(pending.frames.into_iter().collect()))
+  }
+
+  /// Sends the current queue of the player to [`save_pending_frames`]
+  fn save_pending(&self, player_id: i32) {
+    let stored = self
+      .pending
+      .get(&player_id)
+      .map(|pending| StoredPendingFrames {
+        expires_at: pending.stored_expires_at(),
+        frames: pending.frames.iter().cloned().collect(),
+      });
+    self.pending_tx.send((player_id, stored)).ok();
+  }
+}
+
+#[derive(Debug)]
+pub struct StoredPendingFrames {
+  expires_at: DateTime<Utc>,
+  frames: Vec<Frame>,
+}
+
+/// Latest queue of a player, `None` once it's gone
+pub type PendingFramesUpdate = (i32, Option<StoredPendingFrames>);
+
+/// Loads the queues stored before the controller (re)started
+pub async fn load_pending_frames(db: &ExecutorRef) -> Result<BTreeMap<i32, PendingFrames>> {
+  let rows = db
+    .exec_traced(|conn| crate::player::db::get_pending_frames(conn))
+    .await?;
+  Ok(restore_pending_frames(rows))
+}
+
+fn restore_pending_frames(rows: Vec<PendingFrameRow>) -> BTreeMap<i32, PendingFrames> {
+  let mut stored = BTreeMap::<i32, StoredPendingFrames>::new();
+  for row in rows {
+    let type_id = PacketTypeId::from(row.type_id as u8);
+    stored
+      .entry(row.player_id)
+      .or_insert_with(|| StoredPendingFrames {
+        expires_at: row.expires_at,
+        frames: vec![],
+      })
+      .frames
+      .push(Frame::new(type_id, row.payload));
+  }
+  stored
+    .into_iter()
+    .filter_map(|(player_id, stored)| {
+      PendingFrames::restore(stored.expires_at, stored.frames).map(|pending| (player_id, pending))
+    })
+    .collect()
+}
+
+/// Writes the queues of every registry shard, updates received while a write is
+/// running are merged so only the latest queue of each player is written
+pub async fn save_pending_frames(
+  db: ExecutorRef,
+  mut rx: mpsc::UnboundedReceiver<PendingFramesUpdate>,
+) {
+  while let Some((player_id, stored)) = rx.recv().await {
+    let mut updates = BTreeMap::new();
+    updates.insert(player_id, stored);
+    while let Ok((player_id, stored)) = rx.try_recv() {
+      updates.insert(player_id, stored);
+    }
+    let player_ids: Vec<i32> = updates.keys().cloned().collect();
+    if let Err(err) = db
+      .exec_traced(move |conn| {
+        crate::player::db::replace_pending_frames(conn, &player_ids, &stored_rows(&updates))
+      })
+      .await
+    {
+      tracing::error!("save pending frames: {}", err);
+    }
+  }
+}
+
+fn stored_rows(
+  updates: &BTreeMap<i32, Option<StoredPendingFrames>>,
+) -> Vec<InsertPendingFrame<'_>> {
+  let mut rows = vec![];
+  for (player_id, stored) in updates {
+    if let Some(stored) = stored {
+      for frame in &stored.frames {
+        // controller frames always hold their payload as bytes
+        if let FramePayload::Bytes(ref payload) = frame.payload {
+          rows.push(InsertPendingFrame {
+            player_id: *player_id,
+            type_id: u8::from(frame.type_id) as i16,
+            payload: payload.as_ref(),
+            expires_at: stored.expires_at,
+          });
+        }
+      }
+    }
+  }
+  rows
+}
+
+#[test]
+fn test_pending_frames() {
+  let now = Instant::now();
+  let mut pending = PendingFrames::new(now);
+  for _ in 0..PENDING_FRAMES_MAX {
+    pending.push(Frame::new_empty(PacketTypeId::Ping).into());
+  }
+  pending.push(PlayerFrames::Multi(vec![
+    Frame::new_empty(PacketTypeId::GameInfo),
+    Frame::new_empty(PacketTypeId::GameSlotUpdate),
+  ]));
+  assert_eq!(pending.dropped, 0);
+  pending.push(PlayerFrames::Multi(vec![
+    Frame::new_empty(PacketTypeId::Pong),
+    Frame::new_empty(PacketTypeId::Pong),
+  ]));
+  assert_eq!(pending.frames.len(), PENDING_FRAMES_MAX);
+  assert_eq!(pending.dropped, 2);
+  assert_eq!(
+    pending.frames.back().map(|frame| frame.type_id),
+    Some(PacketTypeId::Pong)
+  );
+  assert!(!pending.is_expired(now));
+  assert!(pending.is_expired(now + PENDING_FRAMES_TTL));
+}
+
+#[test]
+fn test_pending_frames_restore() {
+  let expires_at = Utc::now() + chrono::Duration::seconds(30);
+  let mut updates = BTreeMap::new();
+  updates.insert(
+    1,
+    Some(StoredPendingFrames {
+      expires_at,
+      frames: vec![
+        Frame::new(PacketTypeId::Ping, b"a"),
+        Frame::new(PacketTypeId::Pong, b"b"),
+      ],
+    }),
+  );
+  updates.insert(2, None);
+  updates.insert(
+    3,
+    Some(StoredPendingFrames {
+      expires_at: Utc::now() - chrono::Duration::seconds(1),
+      frames: vec![Frame::new_empty(PacketTypeId::Ping)],
+    }),
+  );
+
+  let rows: Vec<_> = stored_rows(&updates)
+    .into_iter()
+    .map(|row| PendingFrameRow {
+      player_id: row.player_id,
+      type_id: row.type_id,
+      payload: row.payload.to_vec(),
+      expires_at: row.expires_at,
+    })
+    .collect();
+  assert_eq!(rows.len(), 3);
+
+  let restored = restore_pending_frames(rows);
+  assert_eq!(restored.keys().cloned().collect::<Vec<_>>(), vec![1]);
+  let pending = &restored[&1];
+  assert!(!pending.is_expired(Instant::now()));
+  assert_eq!(
+    pending
+      .frames
+      .iter()
+      .map(|frame| frame.type_id)
+      .collect::<Vec<_>>(),
+    vec![PacketTypeId::Ping, PacketTypeId::Pong]
+  );
+}
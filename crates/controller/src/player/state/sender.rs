@@ -1,12 +1,19 @@
-use super::{PlayerRegistry, PlayerState};
+use super::conn::{Connect, ConnectResult, Disconnect, DisconnectResult, GetOnlinePlayers};
+use super::pending::{load_pending_frames, save_pending_frames, PendingFrames};
+use super::ping::{GetPlayersPingSnapshot, NodePlayersPingSnapshot, UpdatePing};
+use super::{PlayerRegistry, REGISTRY_SHARDS};
+use crate::db::ExecutorRef;
 use crate::error::*;
+use crate::event::EventHub;
 use crate::game::Game;
 use crate::player::session::get_session_update_packet;
 use flo_net::packet::{FloPacket, Frame};
-use flo_state::{async_trait, Addr, Context, Handler, Message};
+use flo_state::{async_trait, Actor, Addr, Context, Handler, Message, Owner};
 use s2_grpc_utils::S2ProtoPack;
 use std::collections::btree_map::Entry;
 use std::collections::BTreeMap;
+use std::sync::Arc;
+use tokio::sync::mpsc;
 
 #[derive(Debug)]
 struct Send {
@@ -21,7 +28,7 @@ impl Message for Send {
 #[async_trait]
 impl Handler<Send> for PlayerRegistry {
   async fn handle(&mut self, _: &mut Context<Self>, Send { player_id, frames }: Send) {
-    send_to_player(&mut self.registry, player_id, frames);
+    self.send_to_player(player_id, frames);
   }
 }
 
@@ -48,6 +55,7 @@ impl Handler<BroadcastToAll> for PlayerRegistry {
     }
     for id in remove_list {
       self.registry.remove(&id);
+      self.start_pending(id, Some(frames.clone()));
     }
   }
 }
@@ -66,7 +74,7 @@ impl Message for Broadcast {
 impl Handler<Broadcast> for PlayerRegistry {
   async fn handle(&mut self, _: &mut Context<Self>, Broadcast { players, frames }: Broadcast) {
     for player_id in players {
-      send_to_player(&mut self.registry, player_id, frames.clone());
+      self.send_to_player(player_id, frames.clone());
     }
   }
 }
@@ -84,7 +92,7 @@ impl Message for BroadcastMap {
 impl Handler<BroadcastMap> for PlayerRegistry {
   async fn handle(&mut self, _: &mut Context<Self>, BroadcastMap { map }: BroadcastMap) {
     for (player_id, frames) in map {
-      send_to_player(&mut self.registry, player_id, frames);
+      self.send_to_player(player_id, frames);
     }
   }
 }
@@ -116,7 +124,11 @@ impl Handler<PlayerReplaceGame> for PlayerRegistry {
     if let Entry::Occupied(mut entry) = self.registry.entry(player_id) {
       let frames = vec![
         get_session_update_packet(Some(game.id)).encode_as_frame()?,
-        PacketPlayerMuteListUpdate { mute_list }.encode_as_frame()?,
+        PacketPlayerMuteListUpdate {
+          mute_list,
+          game_mute_list: vec![],
+        }
+        .encode_as_frame()?,
         PacketGameInfo {
           game: Some(game.pack()?),
         }
@@ -125,6 +137,7 @@ impl Handler<PlayerReplaceGame> for PlayerRegistry {
       entry.get_mut().game_id = Some(game_id);
       if !entry.get_mut().try_send_frames(frames.into()) {
         entry.remove();
+        self.start_pending(player_id, None);
       }
     }
 
@@ -168,6 +181,7 @@ impl Handler<PlayersReplaceGame> for PlayerRegistry {
           frame_session_update.clone(),
           PacketPlayerMuteListUpdate {
             mute_list: mute_list_map.remove(&player_id).unwrap_or_default(),
+            game_mute_list: vec![],
           }
           .encode_as_frame()?,
           frame_game_info.clone(),
@@ -175,6 +189,7 @@ impl Handler<PlayersReplaceGame> for PlayerRegistry {
         entry.get_mut().game_id = Some(game_id);
         if !entry.get_mut().try_send_frames(frames.into()) {
           entry.remove();
+          self.start_pending(player_id, None);
         }
       }
     }
@@ -207,6 +222,7 @@ impl Handler<PlayerLeaveGame> for PlayerRegistry {
           .try_send(get_session_update_packet(None).encode_as_frame()?)
         {
           entry.remove();
+          self.start_pending(player_id, None);
         } else {
           entry.get_mut().game_id = None;
         }
@@ -306,30 +322,92 @@ impl Iterator for PlayerFramesIntoIterator {
   }
 }
 
-fn send_to_player(map: &mut BTreeMap<i32, PlayerState>, player_id: i32, frames: PlayerFrames) {
-  let remove = {
-    let entry = map.get_mut(&player_id);
-    if let Some(entry) = entry {
-      !entry.try_send_frames(frames)
+impl PlayerRegistry {
+  /// Frames of disconnected players are queued, see [`super::pending`]
+  fn send_to_player(&mut self, player_id: i32, frames: PlayerFrames) {
+    let remove = if let Some(entry) = self.registry.get_mut(&player_id) {
+      !entry.try_send_frames(frames.clone())
     } else {
-      false
+      self.push_pending(player_id, frames);
+      return;
+    };
+    if remove {
+      tracing::debug!(player_id, "remove broken player sender");
+      self.registry.remove(&player_id);
+      self.start_pending(player_id, Some(frames));
     }
-  };
-  if remove {
-    tracing::debug!(player_id, "remove broken player sender");
-    map.remove(&player_id);
   }
 }
 
+/// Players are split between registry shards by id, each shard is a separate actor,
+/// so sending to a player only waits for the players of the same shard
 #[derive(Clone)]
-pub struct PlayerRegistryHandle(Addr<PlayerRegistry>);
+pub struct PlayerRegistryHandle(Arc<Vec<Owner<PlayerRegistry>>>);
+
+impl std::fmt::Debug for PlayerRegistryHandle {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.debug_struct("PlayerRegistryHandle")
+      .field("shards", &self.0.len())
+      .finish()
+  }
+}
+
 impl PlayerRegistryHandle {
+  /// Loads the stored pending frames, see [`super::pending`]
+  pub async fn start(events: EventHub, db: ExecutorRef) -> Result<Self> {
+    let mut shards: Vec<BTreeMap<i32, PendingFrames>> =
+      (0..REGISTRY_SHARDS).map(|_| BTreeMap::new()).collect();
+    for (player_id, pending) in load_pending_frames(&db).await? {
+      shards[shard_index(player_id, REGISTRY_SHARDS)].insert(player_id, pending);
+    }
+
+    let (pending_tx, pending_rx) = mpsc::unbounded_channel();
+    tokio::spawn(save_pending_frames(db, pending_rx));
+
+    Ok(Self(Arc::new(
+      shards
+        .into_iter()
+        .map(|pending| PlayerRegistry::new(events.clone(), pending, pending_tx.clone()).start())
+        .collect(),
+    )))
+  }
+
+  fn shard(&self, player_id: i32) -> Addr<PlayerRegistry> {
+    self.0[shard_index(player_id, self.0.len())].addr()
+  }
+
+  /// Sends `f(player_ids)` to each shard with the players it holds
+  async fn send_split<M, F>(&self, player_ids: Vec<i32>, f: F) -> Result<Vec<M::Result>>
+  where
+    M: Message,
+    F: Fn(Vec<i32>) -> M,
+    PlayerRegistry: Handler<M>,
+  {
+    let mut results = vec![];
+    for (index, player_ids) in split_by_shard(player_ids, self.0.len()) {
+      results.push(self.0[index].addr().send(f(player_ids)).await?);
+    }
+    Ok(results)
+  }
+
+  pub async fn connect(&self, message: Connect) -> Result<ConnectResult> {
+    Ok(self.shard(message.sender.player_id()).send(message).await?)
+  }
+
+  pub async fn disconnect(&self, message: Disconnect) -> Result<Option<DisconnectResult>> {
+    Ok(self.shard(message.player_id).send(message).await?)
+  }
+
+  pub async fn update_ping(&self, message: UpdatePing) -> Result<()> {
+    Ok(self.shard(message.player_id).send(message).await?)
+  }
+
   pub async fn send<T>(&self, player_id: i32, frames: T) -> Result<()>
   where
     T: Into<PlayerFrames>,
   {
     self
-      .0
+      .shard(player_id)
       .send(Send {
         player_id,
         frames: frames.into(),
@@ -342,12 +420,15 @@ impl PlayerRegistryHandle {
   where
     T: Into<PlayerFrames>,
   {
-    self
-      .0
-      .send(BroadcastToAll {
-        frames: frames.into(),
-      })
-      .await?;
+    let frames = frames.into();
+    for shard in self.0.iter() {
+      shard
+        .addr()
+        .send(BroadcastToAll {
+          frames: frames.clone(),
+        })
+        .await?;
+    }
     Ok(())
   }
 
@@ -355,11 +436,11 @@ impl PlayerRegistryHandle {
   where
     T: Into<PlayerFrames>,
   {
+    let frames = frames.into();
     self
-      .0
-      .send(Broadcast {
+      .send_split(players, |players| Broadcast {
         players,
-        frames: frames.into(),
+        frames: frames.clone(),
       })
       .await?;
     Ok(())
@@ -369,15 +450,41 @@ impl PlayerRegistryHandle {
   where
     T: IntoIterator<Item = (i32, PlayerFrames)>,
   {
-    self
-      .0
-      .send(BroadcastMap {
-        map: iter.into_iter().collect(),
-      })
-      .await?;
+    let mut maps: BTreeMap<usize, BTreeMap<i32, PlayerFrames>> = BTreeMap::new();
+    for (player_id, frames) in iter {
+      maps
+        .entry(shard_index(player_id, self.0.len()))
+        .or_default()
+        .insert(player_id, frames);
+    }
+    for (index, map) in maps {
+      self.0[index].addr().send(BroadcastMap { map }).await?;
+    }
     Ok(())
   }
 
+  pub async fn get_ping_snapshot(&self, players: Vec<i32>) -> Result<NodePlayersPingSnapshot> {
+    let mut map = BTreeMap::new();
+    for snapshot in self
+      .send_split(players, |players| GetPlayersPingSnapshot { players })
+      .await?
+    {
+      map.extend(snapshot.map);
+    }
+    Ok(NodePlayersPingSnapshot { map })
+  }
+
+  pub async fn get_online_players(&self, player_ids: Vec<i32>) -> Result<Vec<i32>> {
+    Ok(
+      self
+        .send_split(player_ids, |player_ids| GetOnlinePlayers { player_ids })
+        .await?
+        .into_iter()
+        .flatten()
+        .collect(),
+    )
+  }
+
   pub async fn player_replace_game(
     &self,
     player_id: i32,
@@ -385,7 +492,7 @@ impl PlayerRegistryHandle {
     mute_list: Vec<i32>,
   ) -> Result<()> {
     self
-      .0
+      .shard(player_id)
       .send(PlayerReplaceGame {
         player_id,
         game,
@@ -401,39 +508,67 @@ impl PlayerRegistryHandle {
     game: Game,
     mute_list_map: BTreeMap<i32, Vec<i32>>,
   ) -> Result<()> {
-    self
-      .0
-      .send(PlayersReplaceGame {
-        player_ids,
-        game,
-        mute_list_map,
+    for result in self
+      .send_split(player_ids, |player_ids| {
+        let mute_list_map = player_ids
+          .iter()
+          .filter_map(|id| mute_list_map.get(id).map(|list| (*id, list.clone())))
+          .collect();
+        PlayersReplaceGame {
+          player_ids,
+          game: game.clone(),
+          mute_list_map,
+        }
       })
-      .await??;
+      .await?
+    {
+      result?;
+    }
     Ok(())
   }
 
   pub async fn players_leave_game(&self, player_ids: Vec<i32>, game_id: i32) -> Result<()> {
-    self
-      .0
-      .send(PlayersLeaveGame {
+    for result in self
+      .send_split(player_ids, |player_ids| PlayersLeaveGame {
         player_ids,
         game_id,
       })
-      .await??;
+      .await?
+    {
+      result?;
+    }
     Ok(())
   }
 
   pub async fn player_leave_game(&self, player_id: i32, game_id: i32) -> Result<()> {
     self
-      .0
+      .shard(player_id)
       .send(PlayerLeaveGame { player_id, game_id })
       .await??;
     Ok(())
   }
 }
 
-impl From<Addr<PlayerRegistry>> for PlayerRegistryHandle {
-  fn from(value: Addr<PlayerRegistry>) -> Self {
-    Self(value)
+fn shard_index(player_id: i32, shards: usize) -> usize {
+  player_id.rem_euclid(shards as i32) as usize
+}
+
+fn split_by_shard(player_ids: Vec<i32>, shards: usize) -> BTreeMap<usize, Vec<i32>> {
+  let mut map: BTreeMap<usize, Vec<i32>> = BTreeMap::new();
+  for player_id in player_ids {
+    map
+      .entry(shard_index(player_id, shards))
+      .or_default()
+      .push(player_id);
   }
+  map
+}
+
+#[test]
+fn test_split_by_shard() {
+  let map = split_by_shard(vec![1, 17, 2, -1, 33], 16);
+  assert_eq!(map.get(&1), Some(&vec![1, 17, 33]));
+  assert_eq!(map.get(&2), Some(&vec![2]));
+  assert_eq!(map.get(&15), Some(&vec![-1]));
+  assert_eq!(map.len(), 3);
 }
@@ -8,6 +8,8 @@ use crate::error::*;
 
 // 1 month
 const TOKEN_EXPIRATION_SECS: i64 = 3600 * 24 * 30;
+// 12 hours
+pub(crate) const GUEST_TOKEN_EXPIRATION_SECS: i64 = 3600 * 12;
 const TOKEN_SUB: &str = "flo";
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -18,12 +20,21 @@ pub struct PlayerToken {
 }
 
 pub fn create_player_token(player_id: i32) -> Result<String> {
+  create_token(player_id, TOKEN_EXPIRATION_SECS)
+}
+
+/// Guest identities are not meant to be reused, their tokens expire sooner
+pub fn create_guest_player_token(player_id: i32) -> Result<String> {
+  create_token(player_id, GUEST_TOKEN_EXPIRATION_SECS)
+}
+
+fn create_token(player_id: i32, expiration_secs: i64) -> Result<String> {
   static ENCODING_KEY: Lazy<EncodingKey> = Lazy::new(|| {
     EncodingKey::from_base64_secret(&crate::config::JWT_SECRET_BASE64)
       .expect("DecodingKey::from_base64_secret")
   });
 
-  let exp = Utc::now().timestamp() + TOKEN_EXPIRATION_SECS;
+  let exp = Utc::now().timestamp() + expiration_secs;
   let claims = PlayerToken {
     sub: TOKEN_SUB.to_string(),
     player_id,
@@ -30,6 +30,16 @@ pub enum PlayerSource {
   Test = 0,
   BNet = 1,
   Api = 2,
+  /// Short-lived anonymous player, see [`crate::player::db::create_guest`]
+  Guest = 3,
+  /// Signed in with an external provider, see [`crate::player::auth::AuthProviders::login`]
+  OAuth = 4,
+}
+
+impl PlayerSource {
+  pub fn is_guest(&self) -> bool {
+    *self == PlayerSource::Guest
+  }
 }
 
 #[derive(Debug, Serialize, Deserialize, S2ProtoPack, S2ProtoUnpack, Clone, Queryable)]
@@ -40,6 +50,8 @@ pub struct PlayerRef {
   #[s2_grpc(proto_enum)]
   pub source: PlayerSource,
   pub realm: Option<String>,
+  #[serde(default)]
+  pub clan_tag: Option<String>,
 }
 
 pub(crate) type PlayerRefColumns = (
@@ -47,6 +59,7 @@ pub(crate) type PlayerRefColumns = (
   player::dsl::name,
   player::dsl::source,
   player::dsl::realm,
+  player::dsl::clan_tag,
 );
 
 impl PlayerRef {
@@ -55,6 +68,7 @@ impl PlayerRef {
     player::dsl::name,
     player::dsl::source,
     player::dsl::realm,
+    player::dsl::clan_tag,
   );
 }
 
@@ -133,4 +147,4 @@ impl PlayerBan {
     player_ban::ban_expires_at,
     player_ban::created_at,
   );
-}
\ No newline at end of file
+}
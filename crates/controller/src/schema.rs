@@ -1,3 +1,16 @@
+table! {
+    action_incident (id) {
+        id -> Int4,
+        game_id -> Int4,
+        player_id -> Int4,
+        node_id -> Int4,
+        kind -> Int4,
+        time_ms -> Int4,
+        detail -> Text,
+        created_at -> Timestamptz,
+    }
+}
+
 table! {
     api_client (id) {
         id -> Int4,
@@ -7,6 +20,38 @@ table! {
     }
 }
 
+table! {
+    audit_log (id) {
+        id -> Int4,
+        api_client_id -> Int4,
+        actor -> Nullable<Text>,
+        action -> Int4,
+        target_id -> Nullable<Int4>,
+        reason -> Nullable<Text>,
+        created_at -> Timestamptz,
+    }
+}
+
+table! {
+    clan (id) {
+        id -> Int4,
+        api_client_id -> Int4,
+        name -> Text,
+        tag -> Text,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+    }
+}
+
+table! {
+    clan_member (id) {
+        id -> Int4,
+        clan_id -> Int4,
+        player_id -> Int4,
+        created_at -> Timestamptz,
+    }
+}
+
 table! {
     game (id) {
         id -> Int4,
@@ -28,6 +73,64 @@ table! {
         locked -> Bool,
         mask_player_names -> Bool,
         game_version -> Nullable<Text>,
+        game_mode -> Int4,
+        random_races -> Bool,
+        random_teams -> Bool,
+        clan_id -> Nullable<Int4>,
+        referee_player_ids -> Array<Int4>,
+        duration_ms -> Nullable<Int4>,
+        archive_url -> Nullable<Text>,
+        equalize_ping -> Bool,
+        step_min -> Nullable<Int4>,
+        step_max -> Nullable<Int4>,
+        allow_guests -> Bool,
+        team_layout -> Nullable<Text>,
+        version -> Int4,
+    }
+}
+
+table! {
+    game_chat_log (id) {
+        id -> Int8,
+        game_id -> Int4,
+        player_id -> Int4,
+        scope -> Int4,
+        to_player_id -> Nullable<Int4>,
+        time_ms -> Int4,
+        message -> Text,
+        created_at -> Timestamptz,
+    }
+}
+
+table! {
+    game_event (id) {
+        id -> Int8,
+        game_id -> Int4,
+        data -> Jsonb,
+        created_at -> Timestamptz,
+    }
+}
+
+table! {
+    game_player_stats (game_id, player_id) {
+        game_id -> Int4,
+        player_id -> Int4,
+        actions -> Int4,
+        apm -> Int4,
+        pauses -> Int4,
+        left_at_ms -> Nullable<Int4>,
+        first_leaver -> Bool,
+        created_at -> Timestamptz,
+    }
+}
+
+table! {
+    game_slot_reservation (id) {
+        id -> Int4,
+        game_id -> Int4,
+        player_id -> Int4,
+        slot_index -> Int4,
+        created_at -> Timestamptz,
     }
 }
 
@@ -70,6 +173,7 @@ table! {
         updated_at -> Timestamptz,
         country_id -> Text,
         disabled -> Bool,
+        ip_addr_v6 -> Nullable<Text>,
     }
 }
 
@@ -84,6 +188,8 @@ table! {
         created_at -> Timestamptz,
         updated_at -> Timestamptz,
         api_client_id -> Int4,
+        deleted_at -> Nullable<Timestamptz>,
+        clan_tag -> Nullable<Text>,
     }
 }
 
@@ -106,20 +212,83 @@ table! {
     }
 }
 
+table! {
+    player_mute_pattern (id) {
+        id -> Int4,
+        player_id -> Int4,
+        pattern -> Text,
+        created_at -> Timestamptz,
+    }
+}
+
+table! {
+    player_pending_frame (id) {
+        id -> Int8,
+        player_id -> Int4,
+        type_id -> Int2,
+        payload -> Bytea,
+        expires_at -> Timestamptz,
+        created_at -> Timestamptz,
+    }
+}
+
+table! {
+    player_report (id) {
+        id -> Int4,
+        reporter_player_id -> Nullable<Int4>,
+        target_player_id -> Int4,
+        game_id -> Nullable<Int4>,
+        category -> Int4,
+        message -> Text,
+        status -> Int4,
+        resolution -> Nullable<Text>,
+        resolved_at -> Nullable<Timestamptz>,
+        created_at -> Timestamptz,
+    }
+}
+
+joinable!(action_incident -> game (game_id));
+joinable!(action_incident -> node (node_id));
+joinable!(action_incident -> player (player_id));
+joinable!(audit_log -> api_client (api_client_id));
+joinable!(clan -> api_client (api_client_id));
+joinable!(clan_member -> clan (clan_id));
+joinable!(clan_member -> player (player_id));
+joinable!(game -> clan (clan_id));
 joinable!(game -> node (node_id));
 joinable!(game -> player (created_by));
+joinable!(game_chat_log -> game (game_id));
+joinable!(game_event -> game (game_id));
+joinable!(game_player_stats -> game (game_id));
+joinable!(game_player_stats -> player (player_id));
+joinable!(game_slot_reservation -> game (game_id));
+joinable!(game_slot_reservation -> player (player_id));
 joinable!(game_used_slot -> game (game_id));
 joinable!(game_used_slot -> player (player_id));
 joinable!(player -> api_client (api_client_id));
 joinable!(player_ban -> player (player_id));
+joinable!(player_mute_pattern -> player (player_id));
+joinable!(player_pending_frame -> player (player_id));
+joinable!(player_report -> game (game_id));
 
 allow_tables_to_appear_in_same_query!(
+    action_incident,
     api_client,
+    audit_log,
+    clan,
+    clan_member,
     game,
+    game_chat_log,
+    game_event,
+    game_player_stats,
+    game_slot_reservation,
     game_used_slot,
     map_checksum,
     node,
     player,
     player_ban,
     player_mute,
+    player_mute_pattern,
+    player_pending_frame,
+    player_report,
 );
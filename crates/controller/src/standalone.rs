@@ -0,0 +1,137 @@
+//! Seeding for running the controller, a node and clients in a single process.
+//!
+//! Creates an API client, a node record pointing at the in-process node,
+//! and a player for each name, then hands out player tokens.
+
+use bs_diesel_utils::Executor;
+use diesel::prelude::*;
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+
+use crate::db::{DbConn, ExecutorExt};
+use crate::error::*;
+use crate::player::db::UpsertPlayer;
+use crate::player::token::create_player_token;
+use crate::player::PlayerSource;
+use crate::schema::{api_client, node};
+
+const STANDALONE_NAME: &str = "standalone";
+
+#[derive(Debug)]
+pub struct StandaloneConfig {
+  /// Secret shared with the in-process node
+  pub node_secret: String,
+  /// Address the players connect to the node with
+  pub node_ip_addr: String,
+  pub player_names: Vec<String>,
+}
+
+#[derive(Debug)]
+pub struct StandalonePlayer {
+  pub id: i32,
+  pub name: String,
+  pub token: String,
+}
+
+/// Sets `FLO_NODE_SECRET` and `JWT_SECRET_BASE64` to random values if they are not set.
+/// Must be called before the controller or the node read them.
+pub fn init_secrets() {
+  for key in &["FLO_NODE_SECRET", "JWT_SECRET_BASE64"] {
+    if std::env::var_os(key).is_none() {
+      // alphanumeric strings with a length divisible by 4 are valid base64
+      std::env::set_var(key, random_string(64));
+    }
+  }
+}
+
+/// Runs migrations and creates the records of a standalone deployment,
+/// returns the players in the order of `config.player_names`
+pub async fn init(config: StandaloneConfig) -> Result<Vec<StandalonePlayer>> {
+  let db = Executor::env().into_ref();
+  let players = db
+    .exec_traced(move |conn| {
+      crate::migration::run(conn)?;
+      conn.transaction(|| {
+        let api_client_id = upsert_api_client(conn)?;
+        upsert_node(conn, &config.node_secret, &config.node_ip_addr)?;
+        config
+          .player_names
+          .iter()
+          .map(|name| {
+            let player = crate::player::db::upsert(
+              conn,
+              &UpsertPlayer {
+                api_client_id,
+                name: name.clone(),
+                source: PlayerSource::Api,
+                source_id: name.clone(),
+                source_state: None,
+                realm: None,
+              },
+            )?;
+            Ok(StandalonePlayer {
+              id: player.id,
+              token: create_player_token(player.id)?,
+              name: player.name,
+            })
+          })
+          .collect::<Result<Vec<_>>>()
+      })
+    })
+    .await?;
+  Ok(players)
+}
+
+fn upsert_api_client(conn: &DbConn) -> Result<i32> {
+  let id = api_client::table
+    .select(api_client::id)
+    .filter(api_client::name.eq(STANDALONE_NAME))
+    .first::<i32>(conn)
+    .optional()?;
+  if let Some(id) = id {
+    return Ok(id);
+  }
+  diesel::insert_into(api_client::table)
+    .values((
+      api_client::name.eq(STANDALONE_NAME),
+      api_client::secret_key.eq(random_string(32)),
+    ))
+    .returning(api_client::id)
+    .get_result(conn)
+    .map_err(Into::into)
+}
+
+fn upsert_node(conn: &DbConn, secret: &str, ip_addr: &str) -> Result<()> {
+  let id = node::table
+    .select(node::id)
+    .filter(node::name.eq(STANDALONE_NAME))
+    .first::<i32>(conn)
+    .optional()?;
+  if let Some(id) = id {
+    diesel::update(node::table.find(id))
+      .set((
+        node::secret.eq(secret),
+        node::ip_addr.eq(ip_addr),
+        node::disabled.eq(false),
+      ))
+      .execute(conn)?;
+  } else {
+    diesel::insert_into(node::table)
+      .values((
+        node::name.eq(STANDALONE_NAME),
+        node::location.eq(STANDALONE_NAME),
+        node::secret.eq(secret),
+        node::ip_addr.eq(ip_addr),
+      ))
+      .execute(conn)?;
+  }
+  Ok(())
+}
+
+fn random_string(len: usize) -> String {
+  rand::thread_rng()
+    .sample_iter(&Alphanumeric)
+    .take(len)
+    .map(char::from)
+    .collect()
+}
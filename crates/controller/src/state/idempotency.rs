@@ -0,0 +1,121 @@
+use crate::game::{Game, Slot};
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Results are kept long enough to cover the retries of a client after a timeout
+const REQUEST_ID_TTL: Duration = Duration::from_secs(300);
+
+/// Requests carrying a client generated request id, see [`IdempotencyCache`]
+#[derive(Debug, Default)]
+pub struct IdempotentRequests {
+  pub join_game: IdempotencyCache<Game>,
+  pub leave_game: IdempotencyCache<()>,
+  pub update_slot: IdempotencyCache<Vec<Slot>>,
+}
+
+/// Successful results by player, game and request id.
+/// A retry gets the result of the first request instead of running it again,
+/// or waits for it if it is still in progress. Failed requests are not cached.
+#[derive(Debug)]
+pub struct IdempotencyCache<T> {
+  entries: Mutex<HashMap<(i32, i32, String), CacheEntry<T>>>,
+}
+
+#[derive(Debug)]
+struct CacheEntry<T> {
+  created_at: Instant,
+  result: Arc<tokio::sync::Mutex<Option<T>>>,
+}
+
+impl<T> Default for IdempotencyCache<T> {
+  fn default() -> Self {
+    Self {
+      entries: Mutex::new(HashMap::new()),
+    }
+  }
+}
+
+impl<T> IdempotencyCache<T>
+where
+  T: Clone,
+{
+  /// Runs `f` once per `(player_id, game_id, request_id)`, requests without id always run
+  pub async fn run<F, Fut, E>(
+    &self,
+    player_id: i32,
+    game_id: i32,
+    request_id: &str,
+    f: F,
+  ) -> Result<T, E>
+  where
+    F: FnOnce() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+  {
+    if request_id.is_empty() {
+      return f().await;
+    }
+
+    let result = {
+      let mut entries = self.entries.lock();
+      let key = (player_id, game_id, request_id.to_string());
+      if !entries.contains_key(&key) {
+        entries.retain(|_, entry| entry.created_at.elapsed() < REQUEST_ID_TTL);
+      }
+      entries
+        .entry(key)
+        .or_insert_with(|| CacheEntry {
+          created_at: Instant::now(),
+          result: Default::default(),
+        })
+        .result
+        .clone()
+    };
+
+    let mut result = result.lock().await;
+    if let Some(ref value) = *result {
+      return Ok(value.clone());
+    }
+    let value = f().await?;
+    *result = Some(value.clone());
+    Ok(value)
+  }
+}
+
+#[test]
+fn test_idempotency_cache() {
+  use futures::executor::block_on;
+
+  let cache = IdempotencyCache::<i32>::default();
+  let mut calls = 0;
+  let mut run = |player_id: i32, game_id: i32, request_id: &str, value: i32| {
+    block_on(cache.run(player_id, game_id, request_id, || {
+      calls += 1;
+      async move {
+        if value < 0 {
+          Err(())
+        } else {
+          Ok(value)
+        }
+      }
+    }))
+  };
+
+  assert_eq!(run(1, 1, "a", 1), Ok(1));
+  // retry
+  assert_eq!(run(1, 1, "a", 2), Ok(1));
+  // other player
+  assert_eq!(run(2, 1, "a", 3), Ok(3));
+  // other game
+  assert_eq!(run(1, 2, "a", 4), Ok(4));
+  // no request id
+  assert_eq!(run(1, 1, "", 5), Ok(5));
+  assert_eq!(run(1, 1, "", 6), Ok(6));
+  // failed requests run again
+  assert_eq!(run(1, 1, "b", -1), Err(()));
+  assert_eq!(run(1, 1, "b", 7), Ok(7));
+  drop(run);
+  assert_eq!(calls, 8);
+}
@@ -1,33 +1,47 @@
 mod actor_map;
+mod idempotency;
 
 use bs_diesel_utils::{Executor, ExecutorRef};
 use flo_state::{Addr, Message, Registry};
 
 use std::sync::Arc;
 
+use crate::db::ExecutorExt;
 use crate::error::*;
+use crate::event::EventHub;
+use crate::game::snapshot::GameListSnapshot;
 use crate::game::state::GameRegistry;
+use crate::game::store::{LobbyStoreRef, PgLobbyStore};
 
 use crate::node::NodeRegistry;
-use crate::player::state::PlayerRegistry;
+use crate::player::auth::AuthProviders;
 
 use crate::config::ConfigStorage;
 use crate::player::state::sender::PlayerRegistryHandle;
 pub use actor_map::{ActorMapExt, GetActorEntry};
+pub use idempotency::IdempotentRequests;
 
 #[derive(Debug)]
 pub struct Data {
   pub db: ExecutorRef,
+  pub lobby: LobbyStoreRef,
+  pub events: EventHub,
+  pub game_list: GameListSnapshot,
+  pub players: PlayerRegistryHandle,
 }
 
 pub struct ControllerState {
   pub db: ExecutorRef,
+  pub lobby: LobbyStoreRef,
+  pub events: EventHub,
+  pub game_list: GameListSnapshot,
   pub registry: Registry<Data>,
   pub nodes: Addr<NodeRegistry>,
   pub games: Addr<GameRegistry>,
-  pub players: Addr<PlayerRegistry>,
   pub player_packet_sender: PlayerRegistryHandle,
   pub config: Addr<ConfigStorage>,
+  pub auth: AuthProviders,
+  pub requests: IdempotentRequests,
 }
 
 pub type ControllerStateRef = Arc<ControllerState>;
@@ -38,24 +52,44 @@ impl ControllerState {
 
     #[cfg(not(debug_assertions))]
     {
-      db.exec(|conn| crate::migration::run(conn)).await?;
+      db.exec_traced(|conn| crate::migration::run(conn)).await?;
     }
 
-    let registry = Registry::with_data(Data { db: db.clone() });
+    tokio::spawn(crate::player::guest::cleanup_expired(db.clone()));
+
+    let events = EventHub::new();
+    let game_list = GameListSnapshot::new();
+    let (resync_tx, resync_rx) = tokio::sync::mpsc::unbounded_channel();
+    let lobby = PgLobbyStore::new(db.clone(), resync_tx).into_ref();
+    let players = PlayerRegistryHandle::start(events.clone(), db.clone()).await?;
+    let registry = Registry::with_data(Data {
+      db: db.clone(),
+      lobby: lobby.clone(),
+      events: events.clone(),
+      game_list: game_list.clone(),
+      players: players.clone(),
+    });
 
     let nodes = registry.resolve().await?;
     let games = registry.resolve().await?;
-    let players = registry.resolve().await?;
+    tokio::spawn(crate::game::state::slot::resync_slots(
+      games.clone(),
+      resync_rx,
+    ));
     let config = registry.resolve().await?;
 
     Ok(ControllerState {
       db,
+      lobby,
+      events,
+      game_list,
       registry,
       nodes,
       games,
-      players: players.clone(),
-      player_packet_sender: PlayerRegistryHandle::from(players),
+      player_packet_sender: players,
       config,
+      auth: AuthProviders::from_config(),
+      requests: Default::default(),
     })
   }
 
@@ -7,5 +7,5 @@ mod proto {
 pub mod error;
 
 pub use self::game_info::GameInfo;
-pub use self::mdns::publisher::MdnsPublisher;
+pub use self::mdns::publisher::{MdnsPublisher, PublisherOptions};
 pub use self::mdns::search::{search_lan_games, LanGame};
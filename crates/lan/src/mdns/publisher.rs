@@ -10,6 +10,14 @@ use tracing_futures::Instrument;
 type GameInfoRef = Arc<RwLock<GameInfo>>;
 type UpdateTx = mpsc::Sender<oneshot::Sender<()>>;
 
+#[derive(Debug, Default, Clone)]
+pub struct PublisherOptions {
+  /// Re-publish the game info periodically
+  pub refresh_interval: Option<Duration>,
+  /// Network interface indexes to announce on, all interfaces if empty
+  pub interfaces: Vec<u32>,
+}
+
 #[derive(Debug)]
 pub struct MdnsPublisher {
   update_tx: UpdateTx,
@@ -18,12 +26,16 @@ pub struct MdnsPublisher {
 
 impl MdnsPublisher {
   pub async fn start(game_info: GameInfo) -> Result<Self> {
+    Self::start_with_options(game_info, PublisherOptions::default()).await
+  }
+
+  pub async fn start_with_options(game_info: GameInfo, options: PublisherOptions) -> Result<Self> {
     let game_name = game_info.name.to_string_lossy().to_string();
     let game_info = Arc::new(RwLock::new(game_info));
     let (update_tx, update_rx) = mpsc::channel::<oneshot::Sender<()>>(1);
 
     tokio::spawn(
-      Self::worker(game_info.clone(), game_name, update_rx)
+      Self::worker(game_info.clone(), game_name, options, update_rx)
         .map_err(|err| {
           tracing::error!("worker exited with error: {}", err);
         })
@@ -39,6 +51,7 @@ impl MdnsPublisher {
   async fn worker(
     game_info: GameInfoRef,
     game_name: String,
+    options: PublisherOptions,
     mut update_rx: mpsc::Receiver<oneshot::Sender<()>>,
   ) -> Result<()> {
     let name = if game_name.bytes().len() > 31 {
@@ -57,31 +70,66 @@ impl MdnsPublisher {
       game_name
     };
 
-    use async_dnssd::{register_extended, RegisterData, Type};
+    use async_dnssd::{register_extended, Interface, RegisterData, RegisterFlags, Type};
 
     let (port, data) = {
       let mut game_info = game_info.write();
       game_info.message_id = game_info.message_id + 1;
       (game_info.data.port, game_info.encode_to_bytes()?)
     };
-    let reg = register_extended(
-      super::REG_TYPE,
-      port,
-      RegisterData {
-        flags: async_dnssd::RegisterFlags::NO_AUTO_RENAME | async_dnssd::RegisterFlags::UNIQUE,
-        name: Some(&name),
-        ..Default::default()
-      },
-    )
-    .map_err(Error::BonjourRegister)?;
-
-    let record = reg
-      .add_record(Type(66), &data, 4500)
+
+    let mut interfaces: Vec<Interface> = options
+      .interfaces
+      .iter()
+      .filter_map(|index| std::num::NonZeroU32::new(*index).map(Interface::Index))
+      .collect();
+    if interfaces.is_empty() {
+      if !options.interfaces.is_empty() {
+        tracing::warn!(
+          "no valid interface index in {:?}, announcing on all interfaces",
+          options.interfaces
+        );
+      }
+      interfaces.push(Interface::Any);
+    }
+
+    // the same name is registered on every selected interface
+    let flags = if interfaces.len() == 1 {
+      RegisterFlags::NO_AUTO_RENAME | RegisterFlags::UNIQUE
+    } else {
+      RegisterFlags::NO_AUTO_RENAME
+    };
+
+    let mut registrations = Vec::with_capacity(interfaces.len());
+    for interface in interfaces {
+      let reg = register_extended(
+        super::REG_TYPE,
+        port,
+        RegisterData {
+          flags,
+          interface,
+          name: Some(&name),
+          ..Default::default()
+        },
+      )
       .map_err(Error::BonjourRegister)?;
 
-    let (_reg, res) = reg.await.map_err(Error::BonjourRegister)?;
+      let record = reg
+        .add_record(Type(66), &data, 4500)
+        .map_err(Error::BonjourRegister)?;
+
+      let (reg, res) = reg.await.map_err(Error::BonjourRegister)?;
+
+      tracing::debug!("register result: {:?}", res);
+
+      registrations.push((reg, record));
+    }
 
-    tracing::debug!("register result: {:?}", res);
+    let mut refresh_interval = options.refresh_interval.map(|duration| {
+      let mut interval = tokio::time::interval(duration);
+      interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+      interval
+    });
 
     loop {
       tokio::select! {
@@ -93,13 +141,33 @@ impl MdnsPublisher {
               game_info.message_id = game_info.message_id + 1;
               game_info.encode_to_bytes()?
             };
-            record.update_record(&data, 4500).map_err(|err| Error::BonjourUpdate(err.to_string()))?;
+            for (_, record) in &registrations {
+              record.update_record(&data, 4500).map_err(|err| Error::BonjourUpdate(err.to_string()))?;
+            }
             ack.send(()).ok();
           } else {
             tracing::debug!("update handle dropped");
             break;
           }
         },
+        _ = async {
+          match refresh_interval.as_mut() {
+            Some(interval) => {
+              interval.tick().await;
+            }
+            None => futures::future::pending::<()>().await,
+          }
+        } => {
+          tracing::debug!("refresh");
+          let data = {
+            let mut game_info = game_info.write();
+            game_info.message_id = game_info.message_id + 1;
+            game_info.encode_to_bytes()?
+          };
+          for (_, record) in &registrations {
+            record.update_record(&data, 4500).map_err(|err| Error::BonjourUpdate(err.to_string()))?;
+          }
+        }
       }
     }
 
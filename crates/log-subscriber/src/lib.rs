@@ -1,20 +1,102 @@
+pub mod report;
+
+use once_cell::sync::OnceCell;
 use std::sync::Once;
 pub use tracing::{debug, error, info, instrument, span, warn, Level};
 pub use tracing_futures::Instrument;
+use tracing_subscriber::fmt::Formatter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::reload::Handle;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::EnvFilter;
 
 static INIT: Once = Once::new();
+static FILTER_HANDLE: OnceCell<Handle<EnvFilter, Formatter>> = OnceCell::new();
 
 pub fn init() {
   INIT.call_once(|| {
-    #[cfg(debug_assertions)]
-    tracing_subscriber::fmt::init();
+    let builder = tracing_subscriber::fmt()
+      .with_env_filter(EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")))
+      .with_filter_reloading();
 
     #[cfg(not(debug_assertions))]
-    tracing_subscriber::fmt::fmt().with_ansi(false).init();
+    let builder = builder.with_ansi(false);
+
+    FILTER_HANDLE.set(builder.reload_handle()).ok();
+
+    let subscriber = builder.finish().with(report::layer());
+    #[cfg(feature = "otel")]
+    let subscriber = subscriber.with(otel::layer());
+    subscriber.init();
   });
 }
 
+/// Flushes pending spans and error reports
+pub fn shutdown() {
+  #[cfg(feature = "otel")]
+  opentelemetry::global::shutdown_tracer_provider();
+  report::shutdown();
+}
+
+#[cfg(feature = "otel")]
+mod otel {
+  use opentelemetry::sdk::propagation::TraceContextPropagator;
+  use opentelemetry::sdk::{trace, Resource};
+  use opentelemetry::KeyValue;
+  use tracing_opentelemetry::OpenTelemetryLayer;
+  use tracing_subscriber::registry::LookupSpan;
+
+  pub fn layer<S>() -> Option<OpenTelemetryLayer<S, opentelemetry::sdk::trace::Tracer>>
+  where
+    S: tracing::Subscriber + for<'span> LookupSpan<'span>,
+  {
+    std::env::var("OTEL_EXPORTER_OTLP_ENDPOINT").ok()?;
+
+    let service_name = std::env::var("OTEL_SERVICE_NAME")
+      .ok()
+      .or_else(|| {
+        std::env::current_exe()
+          .ok()?
+          .file_stem()?
+          .to_str()
+          .map(ToString::to_string)
+      })
+      .unwrap_or_else(|| "flo".to_string());
+
+    opentelemetry::global::set_text_map_propagator(TraceContextPropagator::new());
+
+    let tracer = opentelemetry_otlp::new_pipeline()
+      .tracing()
+      .with_exporter(opentelemetry_otlp::new_exporter().tonic().with_env())
+      .with_trace_config(
+        trace::config().with_resource(Resource::new(vec![KeyValue::new(
+          "service.name",
+          service_name,
+        )])),
+      )
+      .install_batch(opentelemetry::runtime::Tokio);
+
+    match tracer {
+      Ok(tracer) => Some(tracing_opentelemetry::layer().with_tracer(tracer)),
+      Err(err) => {
+        eprintln!("install otlp pipeline: {}", err);
+        None
+      }
+    }
+  }
+}
+
 pub fn init_env_override(env: &str) {
   std::env::set_var("RUST_LOG", env);
   init();
 }
+
+/// Replaces the `RUST_LOG` filter at runtime
+pub fn reload_filter(directives: &str) -> Result<(), String> {
+  let filter = EnvFilter::try_new(directives).map_err(|err| err.to_string())?;
+  FILTER_HANDLE
+    .get()
+    .ok_or_else(|| "log subscriber not initialized".to_string())?
+    .reload(filter)
+    .map_err(|err| err.to_string())
+}
@@ -0,0 +1,152 @@
+//! Opt-in crash and error reporting to a Sentry-compatible endpoint.
+//!
+//! Reporting is enabled by the `error-reporting` feature and a DSN set in
+//! `FLO_ERROR_REPORT_DSN`, either at runtime or at build time.
+//! Panics and `ERROR` events are reported, tagged with the fields of the
+//! enclosing spans, e.g. `game_id` and `player_id`.
+
+use tracing::Subscriber;
+use tracing_subscriber::layer::{Context, Layer};
+use tracing_subscriber::registry::LookupSpan;
+
+/// Enables reporting if a DSN is configured
+#[cfg(feature = "error-reporting")]
+pub fn init(release: &str) {
+  use once_cell::sync::OnceCell;
+  static GUARD: OnceCell<sentry::ClientInitGuard> = OnceCell::new();
+
+  let dsn = std::env::var("FLO_ERROR_REPORT_DSN")
+    .ok()
+    .or_else(|| option_env!("FLO_ERROR_REPORT_DSN").map(ToString::to_string))
+    .filter(|v| !v.is_empty());
+  let dsn = match dsn.map(|v| v.parse::<sentry::types::Dsn>()) {
+    Some(Ok(dsn)) => dsn,
+    Some(Err(err)) => {
+      tracing::error!("invalid error report dsn: {}", err);
+      return;
+    }
+    None => return,
+  };
+
+  let guard = sentry::init(sentry::ClientOptions {
+    dsn: Some(dsn),
+    release: Some(release.to_string().into()),
+    attach_stacktrace: true,
+    ..Default::default()
+  });
+  GUARD.set(guard).ok();
+}
+
+#[cfg(not(feature = "error-reporting"))]
+pub fn init(_release: &str) {}
+
+/// Sends pending reports
+pub(crate) fn shutdown() {
+  #[cfg(feature = "error-reporting")]
+  {
+    if let Some(client) = sentry::Hub::current().client() {
+      client.close(Some(std::time::Duration::from_secs(2)));
+    }
+  }
+}
+
+/// Reports `ERROR` events
+pub fn layer() -> ReportLayer {
+  ReportLayer(())
+}
+
+pub struct ReportLayer(());
+
+impl<S> Layer<S> for ReportLayer
+where
+  S: Subscriber + for<'a> LookupSpan<'a>,
+{
+  #[cfg(feature = "error-reporting")]
+  fn new_span(
+    &self,
+    attrs: &tracing::span::Attributes<'_>,
+    id: &tracing::span::Id,
+    ctx: Context<'_, S>,
+  ) {
+    let mut fields = imp::Fields::default();
+    attrs.record(&mut fields);
+    if let Some(span) = ctx.span(id) {
+      span.extensions_mut().insert(fields);
+    }
+  }
+
+  #[cfg(feature = "error-reporting")]
+  fn on_record(
+    &self,
+    id: &tracing::span::Id,
+    values: &tracing::span::Record<'_>,
+    ctx: Context<'_, S>,
+  ) {
+    if let Some(span) = ctx.span(id) {
+      if let Some(fields) = span.extensions_mut().get_mut::<imp::Fields>() {
+        values.record(fields);
+      }
+    }
+  }
+
+  #[cfg(feature = "error-reporting")]
+  fn on_event(&self, event: &tracing::Event<'_>, ctx: Context<'_, S>) {
+    if *event.metadata().level() != tracing::Level::ERROR {
+      return;
+    }
+    imp::capture(event, ctx);
+  }
+
+  #[cfg(not(feature = "error-reporting"))]
+  fn on_event(&self, _event: &tracing::Event<'_>, _ctx: Context<'_, S>) {}
+}
+
+#[cfg(feature = "error-reporting")]
+mod imp {
+  use std::collections::BTreeMap;
+  use std::fmt::Debug;
+  use tracing::field::{Field, Visit};
+  use tracing::Subscriber;
+  use tracing_subscriber::layer::Context;
+  use tracing_subscriber::registry::LookupSpan;
+
+  #[derive(Default)]
+  pub struct Fields(BTreeMap<String, String>);
+
+  impl Visit for Fields {
+    fn record_str(&mut self, field: &Field, value: &str) {
+      self.0.insert(field.name().to_string(), value.to_string());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+      self
+        .0
+        .insert(field.name().to_string(), format!("{:?}", value));
+    }
+  }
+
+  pub fn capture<S>(event: &tracing::Event<'_>, ctx: Context<'_, S>)
+  where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+  {
+    let mut tags = BTreeMap::new();
+    for span in ctx.scope() {
+      if let Some(fields) = span.extensions().get::<Fields>() {
+        tags.extend(fields.0.clone());
+      }
+    }
+
+    let mut fields = Fields::default();
+    event.record(&mut fields);
+    let message = fields.0.remove("message");
+    tags.extend(fields.0);
+
+    sentry::capture_event(sentry::protocol::Event {
+      level: sentry::Level::Error,
+      message,
+      logger: Some(event.metadata().target().to_string()),
+      tags,
+      ..Default::default()
+    });
+  }
+}
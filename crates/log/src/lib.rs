@@ -10,3 +10,6 @@ macro_rules! result_ok {
     }
   };
 }
+
+pub mod report;
+pub mod trace_context;
@@ -0,0 +1,15 @@
+//! Context attached to crash and error reports.
+//!
+//! Without the `error-reporting` feature, nothing is recorded.
+
+/// Sets or clears a tag on every report sent from now on
+#[cfg(feature = "error-reporting")]
+pub fn set_tag(key: &str, value: Option<String>) {
+  sentry::configure_scope(|scope| match value {
+    Some(value) => scope.set_tag(key, value),
+    None => scope.remove_tag(key),
+  });
+}
+
+#[cfg(not(feature = "error-reporting"))]
+pub fn set_tag(_key: &str, _value: Option<String>) {}
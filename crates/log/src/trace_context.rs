@@ -0,0 +1,42 @@
+//! W3C trace context propagation between flo processes.
+//!
+//! Without the `otel` feature, nothing is propagated.
+
+/// Returns the `traceparent` of the current span
+#[cfg(feature = "otel")]
+pub fn current() -> Option<String> {
+  use opentelemetry::propagation::TextMapPropagator;
+  use opentelemetry::sdk::propagation::TraceContextPropagator;
+  use std::collections::HashMap;
+  use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+  let cx = tracing::Span::current().context();
+  let mut carrier = HashMap::new();
+  TraceContextPropagator::new().inject_context(&cx, &mut carrier);
+  carrier.remove("traceparent")
+}
+
+#[cfg(not(feature = "otel"))]
+pub fn current() -> Option<String> {
+  None
+}
+
+/// Makes `span` a child of the remote span identified by `traceparent`
+#[cfg(feature = "otel")]
+pub fn set_parent(span: &tracing::Span, traceparent: &str) {
+  use opentelemetry::propagation::TextMapPropagator;
+  use opentelemetry::sdk::propagation::TraceContextPropagator;
+  use std::collections::HashMap;
+  use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+  if traceparent.is_empty() {
+    return;
+  }
+
+  let mut carrier = HashMap::new();
+  carrier.insert("traceparent".to_string(), traceparent.to_string());
+  span.set_parent(TraceContextPropagator::new().extract(&carrier));
+}
+
+#[cfg(not(feature = "otel"))]
+pub fn set_parent(_span: &tracing::Span, _traceparent: &str) {}
@@ -0,0 +1,26 @@
+//! Feeds the input into the frame codec, the first byte toggles the W3GS passthrough mode
+#![no_main]
+
+use bytes::BytesMut;
+use flo_net::packet::FramePayload;
+use flo_net::w3gs::W3GSFrameExt;
+use flo_net::FloFrameCodec;
+use libfuzzer_sys::fuzz_target;
+use tokio_util::codec::Decoder;
+
+fuzz_target!(|data: &[u8]| {
+  let (passthrough, data) = match data.split_first() {
+    Some((flag, rest)) => (flag & 1 == 1, rest),
+    None => return,
+  };
+
+  let mut codec = FloFrameCodec::new();
+  codec.set_passthrough(passthrough);
+
+  let mut buf = BytesMut::from(data);
+  while let Ok(Some(frame)) = codec.decode(&mut buf) {
+    if let FramePayload::W3GS { .. } = frame.payload {
+      frame.try_into_w3gs().ok();
+    }
+  }
+});
@@ -0,0 +1,82 @@
+use std::net::{IpAddr, SocketAddr, SocketAddrV6};
+
+/// Parses a node address in one of the following formats:
+/// `1.2.3.4`, `1.2.3.4:3549`, `2001:db8::1`, `[2001:db8::1]`, `[2001:db8::1]:3549`
+///
+/// If the address contains a port, `offset` is added to it,
+/// otherwise `default_port` is used.
+pub fn parse_node_addr(addr: &str, default_port: u16, offset: u16) -> Option<SocketAddr> {
+  let addr = addr.trim();
+
+  if let Ok(mut socket_addr) = addr.parse::<SocketAddr>() {
+    socket_addr.set_port(socket_addr.port().checked_add(offset)?);
+    return Some(socket_addr);
+  }
+
+  let ip = addr
+    .strip_prefix('[')
+    .and_then(|v| v.strip_suffix(']'))
+    .unwrap_or(addr);
+  ip.parse::<IpAddr>()
+    .ok()
+    .map(|ip| SocketAddr::new(ip, default_port))
+}
+
+/// Converts an IPv4 address to the IPv4-mapped IPv6 form,
+/// required to send datagrams from a dual-stack socket.
+pub fn to_dual_stack(addr: SocketAddr) -> SocketAddr {
+  match addr {
+    SocketAddr::V4(v4) => {
+      SocketAddr::V6(SocketAddrV6::new(v4.ip().to_ipv6_mapped(), v4.port(), 0, 0))
+    }
+    v6 => v6,
+  }
+}
+
+/// Converts an IPv4-mapped IPv6 address back to IPv4.
+pub fn from_dual_stack(addr: SocketAddr) -> SocketAddr {
+  match addr {
+    SocketAddr::V6(v6) => {
+      let segments = v6.ip().segments();
+      if segments[..5] == [0; 5] && segments[5] == 0xFFFF {
+        SocketAddr::new(
+          IpAddr::V4(v6.ip().to_ipv4().expect("ipv4-mapped")),
+          v6.port(),
+        )
+      } else {
+        addr
+      }
+    }
+    v4 => v4,
+  }
+}
+
+#[test]
+fn test_parse_node_addr() {
+  let cases = vec![
+    ("1.2.3.4", "1.2.3.4:100"),
+    ("1.2.3.4:3549", "1.2.3.4:3550"),
+    ("2001:db8::1", "[2001:db8::1]:100"),
+    ("[2001:db8::1]", "[2001:db8::1]:100"),
+    ("[2001:db8::1]:3549", "[2001:db8::1]:3550"),
+  ];
+  for (input, expected) in cases {
+    assert_eq!(
+      parse_node_addr(input, 100, 1),
+      Some(expected.parse().unwrap()),
+      "{}",
+      input
+    );
+  }
+  assert_eq!(parse_node_addr("example.com", 100, 1), None);
+}
+
+#[test]
+fn test_dual_stack() {
+  let v4: SocketAddr = "1.2.3.4:3549".parse().unwrap();
+  let mapped = to_dual_stack(v4);
+  assert_eq!(mapped, "[::ffff:1.2.3.4]:3549".parse().unwrap());
+  assert_eq!(from_dual_stack(mapped), v4);
+  let v6: SocketAddr = "[2001:db8::1]:3549".parse().unwrap();
+  assert_eq!(from_dual_stack(to_dual_stack(v6)), v6);
+}
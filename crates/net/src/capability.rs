@@ -0,0 +1,140 @@
+//! Protocol version and feature negotiation.
+//!
+//! Both peers send their capabilities in the connect handshake and keep the intersection
+//! on the stream, so new packet types can be introduced without assuming identical builds.
+//! Peers that predate negotiation send nothing, they are treated as protocol version 0
+//! without optional features.
+
+use std::collections::BTreeSet;
+
+use crate::proto::flo_common;
+
+/// Revision of the wire protocol, bumped on incompatible changes
+pub const PROTOCOL_VERSION: i32 = 1;
+
+/// Optional protocol features
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Feature {
+  /// `trace_context` of game start and create game packets
+  TraceContext,
+  /// `latest_release` and `upgrade_required` of the controller connect replies
+  ClientRelease,
+  /// `ip_addr_v6` of nodes
+  NodeAddrV6,
+  /// Nodes forwarding players to the node hosting their game
+  Relay,
+  /// Several node streams carried by a single connection, see [`crate::mux`]
+  Multiplex,
+  /// Player bans pushed to nodes while the game is running
+  PlayerBanUpdate,
+  /// Nodes reporting their load, see `PacketNodeUtilization`
+  NodeUtilization,
+  /// Nodes reporting games interrupted by a restart, see `PacketNodeOrphanedGames`
+  GameRecovery,
+  /// Critical lobby packets acknowledged by clients, see `PacketReliableMessage`
+  ReliableDelivery,
+}
+
+impl Feature {
+  /// Features supported by this build
+  pub const ALL: &'static [Feature] = &[
+    Feature::TraceContext,
+    Feature::ClientRelease,
+    Feature::NodeAddrV6,
+    Feature::Relay,
+    Feature::Multiplex,
+    Feature::PlayerBanUpdate,
+    Feature::NodeUtilization,
+    Feature::GameRecovery,
+    Feature::ReliableDelivery,
+  ];
+
+  pub fn name(&self) -> &'static str {
+    match *self {
+      Feature::TraceContext => "trace_context",
+      Feature::ClientRelease => "client_release",
+      Feature::NodeAddrV6 => "node_addr_v6",
+      Feature::Relay => "relay",
+      Feature::Multiplex => "multiplex",
+      Feature::PlayerBanUpdate => "player_ban_update",
+      Feature::NodeUtilization => "node_utilization",
+      Feature::GameRecovery => "game_recovery",
+      Feature::ReliableDelivery => "reliable_delivery",
+    }
+  }
+
+  pub fn from_name(name: &str) -> Option<Self> {
+    Self::ALL.iter().copied().find(|f| f.name() == name)
+  }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Capabilities {
+  pub protocol_version: i32,
+  features: BTreeSet<Feature>,
+}
+
+impl Capabilities {
+  /// Capabilities of this build
+  pub fn local() -> Self {
+    Capabilities {
+      protocol_version: PROTOCOL_VERSION,
+      features: Feature::ALL.iter().copied().collect(),
+    }
+  }
+
+  /// Capabilities shared with the peer, `remote` is `None` if the peer predates negotiation
+  pub fn negotiate(&self, remote: Option<&flo_common::Capabilities>) -> Self {
+    let remote = if let Some(remote) = remote {
+      remote
+    } else {
+      return Self::default();
+    };
+
+    Capabilities {
+      protocol_version: self.protocol_version.min(remote.protocol_version),
+      features: remote
+        .features
+        .iter()
+        .filter_map(|name| Feature::from_name(name))
+        .filter(|feature| self.features.contains(feature))
+        .collect(),
+    }
+  }
+
+  pub fn supports(&self, feature: Feature) -> bool {
+    self.features.contains(&feature)
+  }
+
+  pub fn features(&self) -> impl Iterator<Item = Feature> + '_ {
+    self.features.iter().copied()
+  }
+}
+
+impl From<Capabilities> for flo_common::Capabilities {
+  fn from(v: Capabilities) -> Self {
+    flo_common::Capabilities {
+      protocol_version: v.protocol_version,
+      features: v.features().map(|f| f.name().to_string()).collect(),
+    }
+  }
+}
+
+#[test]
+fn test_negotiate() {
+  let local = Capabilities::local();
+
+  assert_eq!(local.negotiate(None), Capabilities::default());
+
+  let remote = flo_common::Capabilities {
+    protocol_version: PROTOCOL_VERSION + 1,
+    features: vec!["trace_context".to_string(), "unknown".to_string()],
+  };
+  let negotiated = local.negotiate(Some(&remote));
+  assert_eq!(negotiated.protocol_version, PROTOCOL_VERSION);
+  assert_eq!(
+    negotiated.features().collect::<Vec<_>>(),
+    vec![Feature::TraceContext]
+  );
+  assert!(!negotiated.supports(Feature::ClientRelease));
+}
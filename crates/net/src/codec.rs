@@ -3,7 +3,7 @@ use tokio_util::codec::{Decoder, Encoder};
 
 use flo_util::binary::BinDecode;
 
-use crate::constants::MAX_PAYLOAD_LEN;
+use crate::constants::{MAX_MUX_PAYLOAD_LEN, MAX_PAYLOAD_LEN};
 use crate::error::Error;
 use crate::packet::{Frame, FramePayload, Header, PacketTypeId};
 use crate::w3gs::W3GSMetadata;
@@ -11,14 +11,22 @@ use crate::w3gs::W3GSMetadata;
 #[derive(Debug)]
 pub struct FloFrameCodec {
   decode_state: DecoderState,
+  passthrough: bool,
 }
 
 impl FloFrameCodec {
   pub fn new() -> Self {
     Self {
       decode_state: DecoderState::DecodingHeader,
+      passthrough: false,
     }
   }
+
+  /// In passthrough mode, W3GS metadata is not decoded,
+  /// frames keep their payload as received so they can be forwarded as is
+  pub fn set_passthrough(&mut self, value: bool) {
+    self.passthrough = value;
+  }
 }
 
 impl Decoder for FloFrameCodec {
@@ -31,17 +39,21 @@ impl Decoder for FloFrameCodec {
         if src.remaining() >= Header::MIN_SIZE {
           let header = Header::decode(src)?;
           let payload_len = header.payload_len as usize;
+          let max_payload_len = if header.type_id == PacketTypeId::Mux {
+            MAX_MUX_PAYLOAD_LEN
+          } else {
+            MAX_PAYLOAD_LEN
+          };
 
-          if payload_len > MAX_PAYLOAD_LEN {
+          if payload_len > max_payload_len {
             return Err(Error::PayloadTooLarge);
           }
 
           if src.remaining() >= payload_len {
             // payload received
-            Ok(Some(Self::frame(
-              header.type_id,
-              src.split_to(payload_len).freeze(),
-            )?))
+            Ok(Some(
+              self.frame(header.type_id, src.split_to(payload_len).freeze())?,
+            ))
           } else {
             // wait payload
             src.reserve(payload_len);
@@ -63,7 +75,7 @@ impl Decoder for FloFrameCodec {
         if src.remaining() >= payload_len {
           let header = header.take().expect("header");
           let payload = src.split_to(payload_len);
-          let frame = Self::frame(header.type_id, payload.freeze())?;
+          let frame = self.frame(header.type_id, payload.freeze())?;
           self.decode_state = DecoderState::DecodingHeader;
           Ok(Some(frame))
         } else {
@@ -95,10 +107,10 @@ impl Encoder<Frame> for FloFrameCodec {
 
 impl FloFrameCodec {
   #[inline]
-  fn frame(type_id: PacketTypeId, mut payload: Bytes) -> Result<Frame, Error> {
+  fn frame(&self, type_id: PacketTypeId, mut payload: Bytes) -> Result<Frame, Error> {
     Ok(Frame {
       type_id,
-      payload: if type_id == PacketTypeId::W3GS {
+      payload: if type_id == PacketTypeId::W3GS && !self.passthrough {
         let metadata = W3GSMetadata::decode(&mut payload)?;
         FramePayload::W3GS { metadata, payload }
       } else {
@@ -106,4 +118,42 @@ impl FloFrameCodec {
       },
     })
   }
+
+  /// Decodes a buffer holding exactly one frame
+  pub(crate) fn decode_bytes(&self, mut src: Bytes) -> Result<Frame, Error> {
+    if src.remaining() < Header::MIN_SIZE {
+      return Err(Error::PayloadTooSmall);
+    }
+    let header = Header::decode(&mut src)?;
+    if src.remaining() != header.payload_len as usize {
+      return Err(Error::PayloadTooSmall);
+    }
+    self.frame(header.type_id, src)
+  }
+}
+
+#[test]
+fn test_passthrough() {
+  use crate::packet::FramePayload;
+  use crate::w3gs::W3GSPacketTypeId;
+
+  let frame = Frame {
+    type_id: PacketTypeId::W3GS,
+    payload: FramePayload::W3GS {
+      metadata: W3GSMetadata::new(W3GSPacketTypeId::GameOver, 1234, None),
+      payload: Bytes::from_static(&[1, 2, 3, 4, 5]),
+    },
+  };
+  let mut buf = BytesMut::new();
+  frame.encode(&mut buf);
+  let encoded = buf.clone().freeze();
+
+  let mut codec = FloFrameCodec::new();
+  codec.set_passthrough(true);
+  let decoded = codec.decode(&mut buf).unwrap().unwrap();
+  assert!(matches!(decoded.payload, FramePayload::Bytes(_)));
+
+  let mut out = BytesMut::new();
+  codec.encode(decoded, &mut out).unwrap();
+  assert_eq!(out.freeze(), encoded);
 }
@@ -35,3 +35,20 @@ packet_type!(RemoveNode, PacketRemoveNode);
 packet_type!(PlayerMuteListUpdate, PacketPlayerMuteListUpdate);
 packet_type!(PlayerMuteAddRequest, PacketPlayerMuteAddRequest);
 packet_type!(PlayerMuteRemoveRequest, PacketPlayerMuteRemoveRequest);
+packet_type!(GameMapVote, PacketGameMapVote);
+packet_type!(GameMapVoteRequest, PacketGameMapVoteRequest);
+packet_type!(GameSlotShuffleRequest, PacketGameSlotShuffleRequest);
+packet_type!(GameSlotCloseRequest, PacketGameSlotCloseRequest);
+packet_type!(GameSlotReserveRequest, PacketGameSlotReserveRequest);
+packet_type!(PlayerInfoUpdate, PacketPlayerInfoUpdate);
+packet_type!(GameSummary, PacketGameSummary);
+packet_type!(GameRehostRequest, PacketGameRehostRequest);
+packet_type!(GameInvite, PacketGameInvite);
+packet_type!(GameSlotPingUpdate, PacketGameSlotPingUpdate);
+packet_type!(GameChatRequest, PacketGameChatRequest);
+packet_type!(GameChat, PacketGameChat);
+packet_type!(GameChatClear, PacketGameChatClear);
+packet_type!(ReliableMessage, PacketReliableMessage);
+packet_type!(ReliableMessageAck, PacketReliableMessageAck);
+packet_type!(GameMuteListUpdateRequest, PacketGameMuteListUpdateRequest);
+packet_type!(GameRefereeUpdate, PacketGameRefereeUpdate);
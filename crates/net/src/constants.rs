@@ -1,3 +1,6 @@
 pub const PING_INTERVAL_MS: u32 = 10 * 1000;
 pub const KEEP_ALIVE_TIMEOUT_MS: u32 = 30 * 1000;
 pub const MAX_PAYLOAD_LEN: usize = 16384;
+/// Largest `Mux` payload: a stream id followed by a whole frame
+pub const MAX_MUX_PAYLOAD_LEN: usize = MAX_PAYLOAD_LEN + 4 + 3;
+pub const MUX_CHANNEL_SIZE: usize = 250;
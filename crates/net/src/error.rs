@@ -13,6 +13,8 @@ pub enum Error {
   StreamTimeout,
   #[error("stream closed")]
   StreamClosed,
+  #[error("not supported by the stream transport")]
+  UnsupportedTransport,
   #[error("unexpected packet type: expected {expected:?}, got {got:?}")]
   UnexpectedPacketType {
     expected: PacketTypeId,
@@ -26,6 +28,10 @@ pub enum Error {
   Cancelled,
   #[error("invalid W3GS frame")]
   ReadW3GSFrame(ParseW3GSPacketError),
+  #[error("invalid proxy config: {0}")]
+  InvalidProxyConfig(String),
+  #[error("proxy: {0}")]
+  ProxyConnect(String),
   #[error("io: {0}")]
   Io(#[from] std::io::Error),
   #[error("decode: {0}")]
@@ -34,6 +40,9 @@ pub enum Error {
   ProtoBufDecode(#[from] prost::DecodeError),
   #[error("protobuf encode: {0}")]
   ProtoBufEncode(#[from] prost::EncodeError),
+  #[cfg(feature = "websocket")]
+  #[error("websocket: {0}")]
+  WebSocket(#[from] async_tungstenite::tungstenite::Error),
 }
 
 impl Error {
@@ -6,12 +6,21 @@ pub mod error;
 #[macro_use]
 pub mod packet;
 
+pub mod addr;
+pub mod capability;
 pub mod constants;
 pub mod listener;
+pub mod mux;
 pub mod ping;
+pub mod proxy;
 pub mod stream;
 pub mod time;
 pub mod w3gs;
+#[cfg(feature = "websocket")]
+mod ws;
+
+#[cfg(feature = "fuzzing")]
+pub use codec::FloFrameCodec;
 
 pub mod proto {
   pub mod flo_common {
@@ -1,10 +1,11 @@
 use futures::ready;
 
 use futures::stream::Stream;
-use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+use socket2::{Domain, Protocol, Socket, Type};
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4};
 use std::pin::Pin;
 use std::task::{Context, Poll};
-use tokio::net::TcpListener;
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
 
 use crate::error::*;
 
@@ -17,8 +18,35 @@ pub struct FloListener {
 }
 
 impl FloListener {
+  /// Binds a dual-stack listener, falls back to IPv4 if IPv6 is not available
+  pub async fn bind(port: u16) -> Result<Self, Error> {
+    match Self::bind_dual_stack(port) {
+      Ok(listener) => Ok(listener),
+      Err(err) => {
+        tracing::warn!("bind dual-stack listener: {}, falling back to IPv4", err);
+        Self::bind_v4(port).await
+      }
+    }
+  }
+
   pub async fn bind_v4(port: u16) -> Result<Self, Error> {
     let listener = TcpListener::bind(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, port)).await?;
+    Self::from_listener(listener)
+  }
+
+  /// Binds `[::]` with `IPV6_V6ONLY` disabled to accept both IPv4 and IPv6 connections
+  pub fn bind_dual_stack(port: u16) -> Result<Self, Error> {
+    let socket = Socket::new(Domain::IPV6, Type::STREAM, Some(Protocol::TCP))?;
+    socket.set_only_v6(false)?;
+    #[cfg(unix)]
+    socket.set_reuse_address(true)?;
+    socket.bind(&SocketAddr::from((Ipv6Addr::UNSPECIFIED, port)).into())?;
+    socket.listen(1024)?;
+    socket.set_nonblocking(true)?;
+    Self::from_listener(TcpListener::from_std(socket.into())?)
+  }
+
+  fn from_listener(listener: TcpListener) -> Result<Self, Error> {
     let local_addr = listener.local_addr()?;
     Ok(FloListener {
       listener,
@@ -37,6 +65,32 @@ impl FloListener {
   pub fn port(&self) -> u16 {
     self.local_addr.port()
   }
+
+  /// Accepts a raw socket, for connections that are not framed right away
+  pub async fn accept_socket(&mut self) -> Result<(TcpStream, SocketAddr), Error> {
+    self.listener.accept().await.map_err(Into::into)
+  }
+}
+
+/// Binds a dual-stack UDP socket, falls back to IPv4 if IPv6 is not available.
+///
+/// IPv4 peers of a dual-stack socket use IPv4-mapped addresses,
+/// see [`crate::addr::to_dual_stack`] and [`crate::addr::from_dual_stack`].
+pub async fn bind_udp(port: u16) -> Result<UdpSocket, Error> {
+  let bind_dual_stack = || -> std::io::Result<UdpSocket> {
+    let socket = Socket::new(Domain::IPV6, Type::DGRAM, Some(Protocol::UDP))?;
+    socket.set_only_v6(false)?;
+    socket.bind(&SocketAddr::from((Ipv6Addr::UNSPECIFIED, port)).into())?;
+    socket.set_nonblocking(true)?;
+    UdpSocket::from_std(socket.into())
+  };
+  match bind_dual_stack() {
+    Ok(socket) => Ok(socket),
+    Err(err) => {
+      tracing::warn!("bind dual-stack udp socket: {}, falling back to IPv4", err);
+      Ok(UdpSocket::bind(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, port)).await?)
+    }
+  }
 }
 
 pub struct Incoming<'a> {
@@ -0,0 +1,411 @@
+//! Multiplexing of logical streams over a single connection.
+//!
+//! Frames of a logical stream are wrapped in `Mux` frames: the stream id (u32 LE)
+//! followed by the encoded frame. The first frame with an unknown id opens a stream
+//! on the receiving side, and closing a stream sends a `MuxClose` frame with its id.
+//! Streams opened by the connecting side have odd ids, streams opened by the accepting side
+//! have even ids.
+//!
+//! Each logical stream is a [`FloStream`], handshakes and keep-alive work as on a dedicated
+//! connection. A logical stream that does not read its frames fast enough is closed
+//! instead of blocking the other streams of the connection.
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use futures::channel::mpsc;
+use futures::sink::{Sink, SinkExt};
+use futures::stream::{Stream, StreamExt};
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use crate::codec::FloFrameCodec;
+use crate::constants::MUX_CHANNEL_SIZE;
+use crate::error::*;
+use crate::packet::{Frame, FramePayload, PacketTypeId};
+use crate::stream::FloStream;
+
+const STREAM_ID_LEN: usize = 4;
+
+/// Owns a multiplexed connection, opens and accepts its logical streams
+#[derive(Debug)]
+pub struct Multiplexer {
+  tx: mpsc::Sender<Command>,
+  incoming: mpsc::Receiver<FloStream>,
+  next_stream_id: u32,
+  local_addr: SocketAddr,
+  peer_addr: SocketAddr,
+}
+
+impl Multiplexer {
+  /// Multiplexes a connection opened by this side
+  pub fn connect(stream: FloStream) -> Result<Self> {
+    Self::new(stream, None, 1)
+  }
+
+  /// Multiplexes an accepted connection, `first` is the `Mux` frame
+  /// read from the connection to detect multiplexing
+  pub fn accept(stream: FloStream, first: Frame) -> Result<Self> {
+    Self::new(stream, Some(first), 2)
+  }
+
+  fn new(stream: FloStream, first: Option<Frame>, next_stream_id: u32) -> Result<Self> {
+    let local_addr = stream.local_addr()?;
+    let peer_addr = stream.peer_addr()?;
+    let (tx, rx) = mpsc::channel(MUX_CHANNEL_SIZE);
+    let (incoming_tx, incoming) = mpsc::channel(MUX_CHANNEL_SIZE);
+
+    let worker = Worker {
+      stream,
+      tx: tx.clone(),
+      rx,
+      incoming_tx,
+      streams: BTreeMap::new(),
+      local_stream_id_parity: next_stream_id % 2,
+      detached: false,
+      local_addr,
+      peer_addr,
+    };
+    tokio::spawn(worker.run(first));
+
+    Ok(Self {
+      tx,
+      incoming,
+      next_stream_id,
+      local_addr,
+      peer_addr,
+    })
+  }
+
+  /// Opens a logical stream
+  pub async fn open(&mut self) -> Result<FloStream> {
+    let stream_id = self.next_stream_id;
+    self.next_stream_id = self.next_stream_id.wrapping_add(2);
+    let (tx, rx) = mpsc::channel(MUX_CHANNEL_SIZE);
+    self
+      .tx
+      .send(Command::Open(stream_id, tx))
+      .await
+      .map_err(|_| Error::StreamClosed)?;
+    Ok(FloStream::from_mux(MuxTransport {
+      stream_id,
+      tx: self.tx.clone(),
+      rx,
+      local_addr: self.local_addr,
+      peer_addr: self.peer_addr,
+      closed: false,
+    }))
+  }
+
+  /// Returns `true` if the connection is closed
+  pub fn is_closed(&self) -> bool {
+    self.tx.is_closed()
+  }
+
+  pub fn peer_addr(&self) -> SocketAddr {
+    self.peer_addr
+  }
+}
+
+/// Yields the logical streams opened by the peer, ends when the connection is closed
+impl Stream for Multiplexer {
+  type Item = FloStream;
+
+  fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+    self.incoming.poll_next_unpin(cx)
+  }
+}
+
+impl Drop for Multiplexer {
+  fn drop(&mut self) {
+    self.tx.try_send(Command::Detach).ok();
+  }
+}
+
+#[derive(Debug)]
+enum Command {
+  Open(u32, mpsc::Sender<Frame>),
+  Send(u32, Frame),
+  Close(u32),
+  /// The `Multiplexer` was dropped, the connection is closed with its last stream
+  Detach,
+}
+
+struct Worker {
+  stream: FloStream,
+  tx: mpsc::Sender<Command>,
+  rx: mpsc::Receiver<Command>,
+  incoming_tx: mpsc::Sender<FloStream>,
+  streams: BTreeMap<u32, mpsc::Sender<Frame>>,
+  local_stream_id_parity: u32,
+  detached: bool,
+  local_addr: SocketAddr,
+  peer_addr: SocketAddr,
+}
+
+impl Worker {
+  async fn run(mut self, first: Option<Frame>) {
+    if let Some(frame) = first {
+      if let Err(err) = self.handle_frame(frame).await {
+        tracing::debug!(peer_addr = %self.peer_addr, "mux: {}", err);
+        return;
+      }
+    }
+
+    loop {
+      tokio::select! {
+        res = self.stream.recv_frame() => {
+          let res = match res {
+            Ok(frame) => self.handle_frame(frame).await,
+            Err(err) => Err(err),
+          };
+          if let Err(err) = res {
+            tracing::debug!(peer_addr = %self.peer_addr, "mux: {}", err);
+            break;
+          }
+        }
+        Some(cmd) = self.rx.next() => {
+          if let Err(err) = self.handle_command(cmd).await {
+            tracing::debug!(peer_addr = %self.peer_addr, "mux: {}", err);
+            break;
+          }
+        }
+      }
+
+      if self.detached && self.streams.is_empty() {
+        self.stream.shutdown().await.ok();
+        break;
+      }
+    }
+  }
+
+  async fn handle_frame(&mut self, frame: Frame) -> Result<()> {
+    match frame.type_id {
+      PacketTypeId::Mux => {
+        let (stream_id, frame) = decode_mux_frame(frame)?;
+        if !self.streams.contains_key(&stream_id) {
+          if stream_id % 2 == self.local_stream_id_parity {
+            // frames sent before we closed the stream
+            return Ok(());
+          }
+          self.accept(stream_id).await?;
+        }
+
+        let res = match self.streams.get_mut(&stream_id) {
+          Some(tx) => tx.try_send(frame),
+          None => return Ok(()),
+        };
+        if let Err(err) = res {
+          if err.is_full() {
+            tracing::warn!(
+              peer_addr = %self.peer_addr,
+              stream_id,
+              "mux stream is not keeping up, closing"
+            );
+          }
+          self.close(stream_id).await?;
+        }
+      }
+      PacketTypeId::MuxClose => {
+        let stream_id = decode_mux_close(frame)?;
+        self.streams.remove(&stream_id);
+      }
+      other => return Err(Error::unexpected_packet_type_id(other)),
+    }
+    Ok(())
+  }
+
+  async fn accept(&mut self, stream_id: u32) -> Result<()> {
+    let (tx, rx) = mpsc::channel(MUX_CHANNEL_SIZE);
+    let stream = FloStream::from_mux(MuxTransport {
+      stream_id,
+      tx: self.tx.clone(),
+      rx,
+      local_addr: self.local_addr,
+      peer_addr: self.peer_addr,
+      closed: false,
+    });
+    if self.incoming_tx.try_send(stream).is_err() {
+      tracing::warn!(peer_addr = %self.peer_addr, stream_id, "mux stream rejected");
+      return self.send_close(stream_id).await;
+    }
+    self.streams.insert(stream_id, tx);
+    Ok(())
+  }
+
+  async fn handle_command(&mut self, cmd: Command) -> Result<()> {
+    match cmd {
+      Command::Open(stream_id, tx) => {
+        self.streams.insert(stream_id, tx);
+      }
+      Command::Send(stream_id, frame) => {
+        if self.streams.contains_key(&stream_id) {
+          self
+            .stream
+            .send_frame_timeout(encode_mux_frame(stream_id, &frame))
+            .await?;
+        }
+      }
+      Command::Close(stream_id) => {
+        self.close(stream_id).await?;
+      }
+      Command::Detach => {
+        self.detached = true;
+      }
+    }
+    Ok(())
+  }
+
+  async fn close(&mut self, stream_id: u32) -> Result<()> {
+    if self.streams.remove(&stream_id).is_some() {
+      self.send_close(stream_id).await?;
+    }
+    Ok(())
+  }
+
+  async fn send_close(&mut self, stream_id: u32) -> Result<()> {
+    let mut buf = BytesMut::with_capacity(STREAM_ID_LEN);
+    buf.put_u32_le(stream_id);
+    self
+      .stream
+      .send_frame_timeout(Frame::new_bytes(PacketTypeId::MuxClose, buf.freeze()))
+      .await
+  }
+}
+
+/// Transport of a logical stream, frames are written by the [`Multiplexer`]'s worker
+#[derive(Debug)]
+pub(crate) struct MuxTransport {
+  stream_id: u32,
+  tx: mpsc::Sender<Command>,
+  rx: mpsc::Receiver<Frame>,
+  pub(crate) local_addr: SocketAddr,
+  pub(crate) peer_addr: SocketAddr,
+  closed: bool,
+}
+
+impl Stream for MuxTransport {
+  type Item = Result<Frame>;
+
+  fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+    self.rx.poll_next_unpin(cx).map(|frame| frame.map(Ok))
+  }
+}
+
+impl Sink<Frame> for MuxTransport {
+  type Error = Error;
+
+  fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+    self.tx.poll_ready(cx).map_err(|_| Error::StreamClosed)
+  }
+
+  fn start_send(mut self: Pin<&mut Self>, item: Frame) -> Result<()> {
+    let stream_id = self.stream_id;
+    self
+      .tx
+      .start_send(Command::Send(stream_id, item))
+      .map_err(|_| Error::StreamClosed)
+  }
+
+  fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<()>> {
+    Poll::Ready(Ok(()))
+  }
+
+  fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+    if !self.closed {
+      futures::ready!(self.tx.poll_ready(cx)).map_err(|_| Error::StreamClosed)?;
+      let stream_id = self.stream_id;
+      self
+        .tx
+        .start_send(Command::Close(stream_id))
+        .map_err(|_| Error::StreamClosed)?;
+      self.closed = true;
+    }
+    Poll::Ready(Ok(()))
+  }
+}
+
+impl Drop for MuxTransport {
+  fn drop(&mut self) {
+    if !self.closed {
+      self.tx.try_send(Command::Close(self.stream_id)).ok();
+    }
+  }
+}
+
+fn encode_mux_frame(stream_id: u32, frame: &Frame) -> Frame {
+  let mut buf = BytesMut::with_capacity(STREAM_ID_LEN);
+  buf.put_u32_le(stream_id);
+  frame.encode(&mut buf);
+  Frame::new_bytes(PacketTypeId::Mux, buf.freeze())
+}
+
+fn decode_mux_frame(frame: Frame) -> Result<(u32, Frame)> {
+  let mut payload = mux_payload(frame)?;
+  let stream_id = payload.get_u32_le();
+  let frame = FloFrameCodec::new().decode_bytes(payload)?;
+  Ok((stream_id, frame))
+}
+
+fn decode_mux_close(frame: Frame) -> Result<u32> {
+  let mut payload = mux_payload(frame)?;
+  Ok(payload.get_u32_le())
+}
+
+fn mux_payload(frame: Frame) -> Result<Bytes> {
+  let payload = match frame.payload {
+    FramePayload::Bytes(bytes) => bytes,
+    FramePayload::W3GS { .. } => return Err(Error::unexpected_packet_type_id(frame.type_id)),
+  };
+  if payload.remaining() < STREAM_ID_LEN {
+    return Err(Error::PayloadTooSmall);
+  }
+  Ok(payload)
+}
+
+#[tokio::test]
+async fn test_multiplexer() {
+  use tokio::net::{TcpListener, TcpStream};
+
+  let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+  let addr = listener.local_addr().unwrap();
+
+  let server = tokio::spawn(async move {
+    let (socket, _) = listener.accept().await.unwrap();
+    let mut stream = FloStream::new(socket);
+    let first = stream.recv_frame().await.unwrap();
+    assert_eq!(first.type_id, PacketTypeId::Mux);
+    let mut mux = Multiplexer::accept(stream, first).unwrap();
+    for expected in &[b"a", b"b"] {
+      let mut stream = mux.next().await.unwrap();
+      let frame = stream.recv_frame().await.unwrap();
+      assert_eq!(frame.type_id, PacketTypeId::Ping);
+      assert!(matches!(frame.payload, FramePayload::Bytes(ref bytes) if bytes == &expected[..]));
+      stream
+        .send_frame(Frame::new(PacketTypeId::Pong, expected))
+        .await
+        .unwrap();
+      // closed by the client
+      assert!(matches!(
+        stream.recv_frame().await,
+        Err(Error::StreamClosed)
+      ));
+    }
+  });
+
+  let mut mux =
+    Multiplexer::connect(FloStream::new(TcpStream::connect(addr).await.unwrap())).unwrap();
+  for payload in &[b"a", b"b"] {
+    let mut stream = mux.open().await.unwrap();
+    assert!(stream.is_multiplexed());
+    stream
+      .send_frame(Frame::new(PacketTypeId::Ping, payload))
+      .await
+      .unwrap();
+    let frame = stream.recv_frame().await.unwrap();
+    assert_eq!(frame.type_id, PacketTypeId::Pong);
+    stream.shutdown().await.unwrap();
+  }
+
+  server.await.unwrap();
+}
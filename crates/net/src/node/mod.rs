@@ -16,6 +16,10 @@ packet_type!(ControllerCreateGame, PacketControllerCreateGame);
 packet_type!(ControllerCreateGameAccept, PacketControllerCreateGameAccept);
 packet_type!(ControllerCreateGameReject, PacketControllerCreateGameReject);
 packet_type!(ControllerQueryGameStatus, PacketControllerQueryGameStatus);
+packet_type!(ControllerRelayPlayers, PacketControllerRelayPlayers);
+packet_type!(ControllerRelayPlayersAccept, PacketControllerRelayPlayersAccept);
+packet_type!(ControllerRelayPlayersReject, PacketControllerRelayPlayersReject);
+packet_type!(ControllerUpdatePlayerBans, PacketControllerUpdatePlayerBans);
 packet_type!(ClientConnect, PacketClientConnect);
 packet_type!(ClientConnectAccept, PacketClientConnectAccept);
 packet_type!(ClientConnectReject, PacketClientConnectReject);
@@ -33,3 +37,8 @@ packet_type!(
 );
 packet_type!(NodeGameStatusUpdate, PacketNodeGameStatusUpdate);
 packet_type!(NodeGameStatusUpdateBulk, PacketNodeGameStatusUpdateBulk);
+packet_type!(NodeGameStats, PacketNodeGameStats);
+packet_type!(NodeActionIncident, PacketNodeActionIncident);
+packet_type!(NodeGameChatLog, PacketNodeGameChatLog);
+packet_type!(NodeUtilization, PacketNodeUtilization);
+packet_type!(NodeOrphanedGames, PacketNodeOrphanedGames);
@@ -236,6 +236,38 @@ pub enum PacketTypeId {
   PlayerMuteAddRequest,
   #[bin(value = 0x1F)]
   PlayerMuteRemoveRequest,
+  #[bin(value = 0x20)]
+  GameMapVote,
+  #[bin(value = 0x21)]
+  GameMapVoteRequest,
+  #[bin(value = 0x22)]
+  GameSlotShuffleRequest,
+  #[bin(value = 0x23)]
+  GameSlotCloseRequest,
+  #[bin(value = 0x24)]
+  GameSlotReserveRequest,
+  #[bin(value = 0x25)]
+  PlayerInfoUpdate,
+  #[bin(value = 0x26)]
+  GameSummary,
+  #[bin(value = 0x27)]
+  GameRehostRequest,
+  #[bin(value = 0x28)]
+  GameInvite,
+  #[bin(value = 0x29)]
+  GameSlotPingUpdate,
+  #[bin(value = 0x2A)]
+  GameChatRequest,
+  #[bin(value = 0x2B)]
+  GameChat,
+  #[bin(value = 0x2C)]
+  GameChatClear,
+  #[bin(value = 0x2D)]
+  ReliableMessage,
+  #[bin(value = 0x2E)]
+  ReliableMessageAck,
+  #[bin(value = 0x2F)]
+  GameMuteListUpdateRequest,
 
   // Lobby <-> Node
   #[bin(value = 0x30)]
@@ -258,6 +290,14 @@ pub enum PacketTypeId {
   ControllerUpdateSlotStatusReject,
   #[bin(value = 0x39)]
   ControllerQueryGameStatus,
+  #[bin(value = 0x3A)]
+  ControllerRelayPlayers,
+  #[bin(value = 0x3B)]
+  ControllerRelayPlayersAccept,
+  #[bin(value = 0x3C)]
+  ControllerRelayPlayersReject,
+  #[bin(value = 0x3D)]
+  ControllerUpdatePlayerBans,
 
   // Client <-> Node
   #[bin(value = 0x40)]
@@ -282,6 +322,16 @@ pub enum PacketTypeId {
   NodeGameStatusUpdate,
   #[bin(value = 0x51)]
   NodeGameStatusUpdateBulk,
+  #[bin(value = 0x52)]
+  NodeGameStats,
+  #[bin(value = 0x53)]
+  NodeActionIncident,
+  #[bin(value = 0x54)]
+  NodeGameChatLog,
+  #[bin(value = 0x55)]
+  NodeUtilization,
+  #[bin(value = 0x56)]
+  NodeOrphanedGames,
 
   // Client <-> Observer
   #[bin(value = 0x60)]
@@ -295,6 +345,16 @@ pub enum PacketTypeId {
   #[bin(value = 0x64)]
   ObserverDataEnd,
 
+  // Client <-> Lobby, continued
+  #[bin(value = 0x70)]
+  GameRefereeUpdate,
+
+  // Framing
+  #[bin(value = 0xF5)]
+  Mux,
+  #[bin(value = 0xF6)]
+  MuxClose,
+
   #[bin(value = 0xF7)]
   W3GS,
   UnknownValue(u8),
@@ -0,0 +1,324 @@
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+use crate::error::{Error, Result};
+
+const SOCKS5_VERSION: u8 = 0x05;
+const SOCKS5_AUTH_NONE: u8 = 0x00;
+const SOCKS5_AUTH_PASSWORD: u8 = 0x02;
+const SOCKS5_CMD_CONNECT: u8 = 0x01;
+const SOCKS5_ATYP_IPV4: u8 = 0x01;
+const SOCKS5_ATYP_DOMAIN: u8 = 0x03;
+const SOCKS5_ATYP_IPV6: u8 = 0x04;
+const HTTP_MAX_RESPONSE_HEADER_LEN: usize = 8 * 1024;
+
+/// Outbound proxy, parsed from `socks5://[user:password@]host[:port]`
+/// or `http://[user:password@]host[:port]`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProxyConfig {
+  pub kind: ProxyKind,
+  pub host: String,
+  pub port: u16,
+  pub credentials: Option<ProxyCredentials>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ProxyKind {
+  Socks5,
+  /// HTTP `CONNECT` tunnel
+  Http,
+}
+
+impl ProxyKind {
+  fn default_port(&self) -> u16 {
+    match *self {
+      ProxyKind::Socks5 => 1080,
+      ProxyKind::Http => 8080,
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProxyCredentials {
+  pub username: String,
+  pub password: String,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum ProxyTarget<'a> {
+  Addr(SocketAddr),
+  /// Resolved by the proxy
+  Domain(&'a str, u16),
+}
+
+impl From<SocketAddr> for ProxyTarget<'_> {
+  fn from(addr: SocketAddr) -> Self {
+    ProxyTarget::Addr(addr)
+  }
+}
+
+impl<'a> From<(&'a str, u16)> for ProxyTarget<'a> {
+  fn from((host, port): (&'a str, u16)) -> Self {
+    match host.parse::<IpAddr>() {
+      Ok(ip) => ProxyTarget::Addr(SocketAddr::new(ip, port)),
+      Err(_) => ProxyTarget::Domain(host, port),
+    }
+  }
+}
+
+impl std::fmt::Display for ProxyTarget<'_> {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match *self {
+      ProxyTarget::Addr(addr) => addr.fmt(f),
+      ProxyTarget::Domain(host, port) => write!(f, "{}:{}", host, port),
+    }
+  }
+}
+
+impl FromStr for ProxyConfig {
+  type Err = Error;
+
+  fn from_str(s: &str) -> Result<Self> {
+    let invalid = || Error::InvalidProxyConfig(s.to_string());
+
+    let s = s.trim();
+    let scheme_end = s.find("://").ok_or_else(invalid)?;
+    let kind = match s[..scheme_end].to_ascii_lowercase().as_str() {
+      "socks5" | "socks5h" => ProxyKind::Socks5,
+      "http" => ProxyKind::Http,
+      _ => return Err(invalid()),
+    };
+    let rest = s[(scheme_end + 3)..].trim_end_matches('/');
+
+    let (credentials, addr) = match rest.rfind('@') {
+      Some(idx) => {
+        let credentials = &rest[..idx];
+        let (username, password) = match credentials.find(':') {
+          Some(idx) => (&credentials[..idx], &credentials[(idx + 1)..]),
+          None => (credentials, ""),
+        };
+        (
+          Some(ProxyCredentials {
+            username: username.to_string(),
+            password: password.to_string(),
+          }),
+          &rest[(idx + 1)..],
+        )
+      }
+      None => (None, rest),
+    };
+
+    let (host, port) = if addr.starts_with('[') {
+      let end = addr.find(']').ok_or_else(invalid)?;
+      let port = match &addr[(end + 1)..] {
+        "" => None,
+        v => Some(v.strip_prefix(':').ok_or_else(invalid)?),
+      };
+      (&addr[1..end], port)
+    } else {
+      match addr.rfind(':') {
+        Some(idx) => (&addr[..idx], Some(&addr[(idx + 1)..])),
+        None => (addr, None),
+      }
+    };
+
+    if host.is_empty() {
+      return Err(invalid());
+    }
+
+    let port = match port {
+      Some(port) => port.parse().map_err(|_| invalid())?,
+      None => kind.default_port(),
+    };
+
+    Ok(ProxyConfig {
+      kind,
+      host: host.to_string(),
+      port,
+      credentials,
+    })
+  }
+}
+
+impl ProxyConfig {
+  /// Connects to `target` through the proxy
+  pub async fn connect(&self, target: ProxyTarget<'_>) -> Result<TcpStream> {
+    let mut socket = TcpStream::connect((self.host.as_str(), self.port)).await?;
+    match self.kind {
+      ProxyKind::Socks5 => self.socks5_connect(&mut socket, target).await?,
+      ProxyKind::Http => self.http_connect(&mut socket, target).await?,
+    }
+    Ok(socket)
+  }
+
+  async fn socks5_connect(&self, socket: &mut TcpStream, target: ProxyTarget<'_>) -> Result<()> {
+    let methods: &[u8] = if self.credentials.is_some() {
+      &[SOCKS5_AUTH_NONE, SOCKS5_AUTH_PASSWORD]
+    } else {
+      &[SOCKS5_AUTH_NONE]
+    };
+    let mut buf = vec![SOCKS5_VERSION, methods.len() as u8];
+    buf.extend_from_slice(methods);
+    socket.write_all(&buf).await?;
+
+    let mut reply = [0_u8; 2];
+    socket.read_exact(&mut reply).await?;
+    if reply[0] != SOCKS5_VERSION {
+      return Err(Error::ProxyConnect("invalid socks5 reply".to_string()));
+    }
+    match (reply[1], self.credentials.as_ref()) {
+      (SOCKS5_AUTH_NONE, _) => {}
+      (SOCKS5_AUTH_PASSWORD, Some(credentials)) => {
+        let username = credentials.username.as_bytes();
+        let password = credentials.password.as_bytes();
+        if username.len() > 255 || password.len() > 255 {
+          return Err(Error::ProxyConnect(
+            "socks5 credentials too long".to_string(),
+          ));
+        }
+        let mut buf = vec![0x01, username.len() as u8];
+        buf.extend_from_slice(username);
+        buf.push(password.len() as u8);
+        buf.extend_from_slice(password);
+        socket.write_all(&buf).await?;
+
+        socket.read_exact(&mut reply).await?;
+        if reply[1] != 0x00 {
+          return Err(Error::ProxyConnect(
+            "socks5 authentication failed".to_string(),
+          ));
+        }
+      }
+      _ => {
+        return Err(Error::ProxyConnect(
+          "socks5 authentication method not supported".to_string(),
+        ))
+      }
+    }
+
+    let mut buf = vec![SOCKS5_VERSION, SOCKS5_CMD_CONNECT, 0x00];
+    let port = match target {
+      ProxyTarget::Addr(SocketAddr::V4(addr)) => {
+        buf.push(SOCKS5_ATYP_IPV4);
+        buf.extend_from_slice(&addr.ip().octets());
+        addr.port()
+      }
+      ProxyTarget::Addr(SocketAddr::V6(addr)) => {
+        buf.push(SOCKS5_ATYP_IPV6);
+        buf.extend_from_slice(&addr.ip().octets());
+        addr.port()
+      }
+      ProxyTarget::Domain(host, port) => {
+        if host.len() > 255 {
+          return Err(Error::ProxyConnect(
+            "socks5 domain name too long".to_string(),
+          ));
+        }
+        buf.push(SOCKS5_ATYP_DOMAIN);
+        buf.push(host.len() as u8);
+        buf.extend_from_slice(host.as_bytes());
+        port
+      }
+    };
+    buf.extend_from_slice(&port.to_be_bytes());
+    socket.write_all(&buf).await?;
+
+    let mut reply = [0_u8; 4];
+    socket.read_exact(&mut reply).await?;
+    if reply[0] != SOCKS5_VERSION {
+      return Err(Error::ProxyConnect("invalid socks5 reply".to_string()));
+    }
+    if reply[1] != 0x00 {
+      return Err(Error::ProxyConnect(format!(
+        "socks5 connect to {} failed: reply code {}",
+        target, reply[1]
+      )));
+    }
+
+    // bound address
+    let addr_len = match reply[3] {
+      SOCKS5_ATYP_IPV4 => 4,
+      SOCKS5_ATYP_IPV6 => 16,
+      SOCKS5_ATYP_DOMAIN => socket.read_u8().await? as usize,
+      _ => return Err(Error::ProxyConnect("invalid socks5 reply".to_string())),
+    };
+    let mut bound = vec![0_u8; addr_len + 2];
+    socket.read_exact(&mut bound).await?;
+
+    Ok(())
+  }
+
+  async fn http_connect(&self, socket: &mut TcpStream, target: ProxyTarget<'_>) -> Result<()> {
+    let mut req = format!("CONNECT {0} HTTP/1.1\r\nHost: {0}\r\n", target);
+    if let Some(credentials) = self.credentials.as_ref() {
+      req.push_str(&format!(
+        "Proxy-Authorization: Basic {}\r\n",
+        base64::encode(format!("{}:{}", credentials.username, credentials.password))
+      ));
+    }
+    req.push_str("\r\n");
+    socket.write_all(req.as_bytes()).await?;
+
+    // read byte by byte to leave the tunneled data in the socket
+    let mut res = Vec::with_capacity(128);
+    while !res.ends_with(b"\r\n\r\n") {
+      if res.len() >= HTTP_MAX_RESPONSE_HEADER_LEN {
+        return Err(Error::ProxyConnect("http response too large".to_string()));
+      }
+      res.push(socket.read_u8().await?);
+    }
+
+    let status_line = String::from_utf8_lossy(&res);
+    let status_line = status_line.lines().next().unwrap_or_default();
+    let status = status_line.split_whitespace().nth(1);
+    if !status_line.starts_with("HTTP/1.") || status.map(|v| v.starts_with('2')) != Some(true) {
+      return Err(Error::ProxyConnect(format!(
+        "http connect to {} failed: {}",
+        target, status_line
+      )));
+    }
+
+    Ok(())
+  }
+}
+
+#[test]
+fn test_parse_proxy_config() {
+  assert_eq!(
+    "socks5://127.0.0.1:1080".parse::<ProxyConfig>().unwrap(),
+    ProxyConfig {
+      kind: ProxyKind::Socks5,
+      host: "127.0.0.1".to_string(),
+      port: 1080,
+      credentials: None,
+    }
+  );
+  assert_eq!(
+    "http://user:p@ss@proxy.example.com"
+      .parse::<ProxyConfig>()
+      .unwrap(),
+    ProxyConfig {
+      kind: ProxyKind::Http,
+      host: "proxy.example.com".to_string(),
+      port: 8080,
+      credentials: Some(ProxyCredentials {
+        username: "user".to_string(),
+        password: "p@ss".to_string(),
+      }),
+    }
+  );
+  assert_eq!(
+    "socks5://[::1]:9050/".parse::<ProxyConfig>().unwrap(),
+    ProxyConfig {
+      kind: ProxyKind::Socks5,
+      host: "::1".to_string(),
+      port: 9050,
+      credentials: None,
+    }
+  );
+  assert!("127.0.0.1:1080".parse::<ProxyConfig>().is_err());
+  assert!("ftp://127.0.0.1".parse::<ProxyConfig>().is_err());
+  assert!("socks5://127.0.0.1:port".parse::<ProxyConfig>().is_err());
+}
@@ -11,9 +11,14 @@ use tokio::net::{TcpStream, ToSocketAddrs};
 use tokio::time::timeout;
 use tokio_util::codec::Framed;
 
+use crate::capability::Capabilities;
 use crate::codec::FloFrameCodec;
 use crate::error::*;
+use crate::mux::MuxTransport;
 use crate::packet::{FloPacket, Frame};
+use crate::proxy::{ProxyConfig, ProxyTarget};
+#[cfg(feature = "websocket")]
+use crate::ws::WsTransport;
 use tokio::io::AsyncWriteExt;
 
 const DEFAULT_TIMEOUT: Duration = Duration::from_secs(15);
@@ -21,7 +26,8 @@ const DEFAULT_TIMEOUT: Duration = Duration::from_secs(15);
 #[derive(Debug)]
 pub struct FloStream {
   pub timeout: Duration,
-  pub(crate) transport: Framed<TcpStream, FloFrameCodec>,
+  pub(crate) transport: Transport,
+  capabilities: Capabilities,
 }
 
 impl FloStream {
@@ -33,10 +39,11 @@ impl FloStream {
     //TODO: not supported by current tokio
     //socket.set_keepalive(None).ok();
 
-    let transport = Framed::new(socket, FloFrameCodec::new());
+    let transport = Transport::Tcp(Framed::new(socket, FloFrameCodec::new()));
     Ok(FloStream {
       transport,
       timeout: DEFAULT_TIMEOUT,
+      capabilities: Capabilities::default(),
     })
   }
 
@@ -46,33 +53,115 @@ impl FloStream {
     // not supported by tokio atm
     //socket.set_keepalive(Some(Duration::from_secs(30)))?;
 
-    let transport = Framed::new(socket, FloFrameCodec::new());
+    let transport = Transport::Tcp(Framed::new(socket, FloFrameCodec::new()));
     Ok(FloStream {
       transport,
       timeout: DEFAULT_TIMEOUT,
+      capabilities: Capabilities::default(),
     })
   }
 
+  /// Connects to `target` through `proxy`, with `TCP_NODELAY` set
+  pub async fn connect_proxy<'a, T: Into<ProxyTarget<'a>>>(
+    proxy: &ProxyConfig,
+    target: T,
+  ) -> Result<Self> {
+    let socket = proxy.connect(target.into()).await?;
+
+    socket.set_nodelay(true).ok();
+
+    Ok(Self::new(socket))
+  }
+
   pub fn new(socket: TcpStream) -> Self {
     FloStream {
-      transport: Framed::new(socket, FloFrameCodec::new()),
+      transport: Transport::Tcp(Framed::new(socket, FloFrameCodec::new())),
+      timeout: DEFAULT_TIMEOUT,
+      capabilities: Capabilities::default(),
+    }
+  }
+
+  /// A logical stream of a multiplexed connection
+  pub(crate) fn from_mux(transport: MuxTransport) -> Self {
+    FloStream {
+      transport: Transport::Mux(transport),
       timeout: DEFAULT_TIMEOUT,
+      capabilities: Capabilities::default(),
     }
   }
 
+  /// Accepts a WebSocket connection, each binary message carries a frame
+  #[cfg(feature = "websocket")]
+  pub async fn accept_websocket(socket: TcpStream) -> Result<Self> {
+    socket.set_nodelay(true).ok();
+    Ok(FloStream {
+      transport: Transport::WebSocket(WsTransport::accept(socket).await?),
+      timeout: DEFAULT_TIMEOUT,
+      capabilities: Capabilities::default(),
+    })
+  }
+
+  /// Creates a connected pair of in-memory streams, for tests
+  #[cfg(feature = "test-util")]
+  pub fn pair() -> (Self, Self) {
+    let (a, b) = tokio::io::duplex(64 * 1024);
+    let wrap = |io| FloStream {
+      transport: Transport::Memory(Framed::new(io, FloFrameCodec::new())),
+      timeout: DEFAULT_TIMEOUT,
+      capabilities: Capabilities::default(),
+    };
+    (wrap(a), wrap(b))
+  }
+
+  /// Returns `true` if the stream is carried by a multiplexed connection
+  pub fn is_multiplexed(&self) -> bool {
+    matches!(self.transport, Transport::Mux(_))
+  }
+
   pub fn set_timeout(&mut self, duration: Duration) -> &mut Self {
     self.timeout = duration;
     self
   }
 
+  /// Capabilities negotiated in the handshake
+  pub fn capabilities(&self) -> &Capabilities {
+    &self.capabilities
+  }
+
+  pub fn set_capabilities(&mut self, capabilities: Capabilities) {
+    self.capabilities = capabilities;
+  }
+
+  /// Forwards W3GS frames without decoding their metadata, see [`FloFrameCodec::set_passthrough`].
+  /// Frames of multiplexed streams are always decoded.
+  pub fn set_passthrough(&mut self, value: bool) {
+    if let Transport::Tcp(ref mut transport) = self.transport {
+      transport.codec_mut().set_passthrough(value);
+    }
+  }
+
   #[inline]
   pub fn local_addr(&self) -> Result<SocketAddr> {
-    self.transport.get_ref().local_addr().map_err(Into::into)
+    match self.transport {
+      Transport::Tcp(ref transport) => transport.get_ref().local_addr().map_err(Into::into),
+      Transport::Mux(ref transport) => Ok(transport.local_addr),
+      #[cfg(feature = "websocket")]
+      Transport::WebSocket(ref transport) => Ok(transport.local_addr),
+      #[cfg(feature = "test-util")]
+      Transport::Memory(_) => Ok(SocketAddr::from(([127, 0, 0, 1], 0))),
+    }
   }
 
   #[inline]
   pub fn peer_addr(&self) -> Result<SocketAddr> {
-    self.transport.get_ref().peer_addr().map_err(Into::into)
+    match self.transport {
+      Transport::Tcp(ref transport) => transport.get_ref().peer_addr().map_err(Into::into),
+      Transport::Mux(ref transport) => Ok(transport.peer_addr),
+      #[cfg(feature = "websocket")]
+      Transport::WebSocket(ref transport) => Ok(transport.peer_addr),
+      #[cfg(feature = "test-util")]
+      Transport::Memory(_) => Ok(SocketAddr::from(([127, 0, 0, 1], 0))),
+    }
   }
 
   pub async fn send_frame_timeout(&mut self, frame: Frame) -> Result<()> {
@@ -150,18 +239,26 @@ impl FloStream {
 
   pub async fn flush(&mut self) -> Result<()> {
     poll_fn(|ctx| Pin::new(&mut self.transport).poll_flush(ctx)).await?;
-    self.transport.get_mut().flush().await?;
+    if let Transport::Tcp(ref mut transport) = self.transport {
+      transport.get_mut().flush().await?;
+    }
     Ok(())
   }
 
   pub async fn shutdown(&mut self) -> Result<()> {
     poll_fn(|ctx| Pin::new(&mut self.transport).poll_close(ctx)).await?;
-    self.transport.get_mut().shutdown().await?;
+    if let Transport::Tcp(ref mut transport) = self.transport {
+      transport.get_mut().shutdown().await?;
+    }
     Ok(())
   }
 
   pub async fn downgrade_to_binary_stream(self) -> Result<(Bytes, TcpStream)> {
-    let parts = self.transport.into_parts();
+    let transport = match self.transport {
+      Transport::Tcp(transport) => transport,
+      _ => return Err(Error::UnsupportedTransport),
+    };
+    let parts = transport.into_parts();
     let mut stream = parts.io;
     if !parts.write_buf.is_empty() {
       stream.write_all(parts.write_buf.as_ref()).await?;
@@ -178,6 +275,79 @@ impl Stream for FloStream {
   }
 }
 
+#[derive(Debug)]
+pub(crate) enum Transport {
+  Tcp(Framed<TcpStream, FloFrameCodec>),
+  Mux(MuxTransport),
+  #[cfg(feature = "websocket")]
+  WebSocket(WsTransport),
+  #[cfg(feature = "test-util")]
+  Memory(Framed<tokio::io::DuplexStream, FloFrameCodec>),
+}
+
+impl Stream for Transport {
+  type Item = Result<Frame>;
+
+  fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+    match self.get_mut() {
+      Transport::Tcp(transport) => Pin::new(transport).poll_next(cx),
+      Transport::Mux(transport) => Pin::new(transport).poll_next(cx),
+      #[cfg(feature = "websocket")]
+      Transport::WebSocket(transport) => Pin::new(transport).poll_next(cx),
+      #[cfg(feature = "test-util")]
+      Transport::Memory(transport) => Pin::new(transport).poll_next(cx),
+    }
+  }
+}
+
+impl Sink<Frame> for Transport {
+  type Error = Error;
+
+  fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+    match self.get_mut() {
+      Transport::Tcp(transport) => Pin::new(transport).poll_ready(cx),
+      Transport::Mux(transport) => Pin::new(transport).poll_ready(cx),
+      #[cfg(feature = "websocket")]
+      Transport::WebSocket(transport) => Pin::new(transport).poll_ready(cx),
+      #[cfg(feature = "test-util")]
+      Transport::Memory(transport) => Pin::new(transport).poll_ready(cx),
+    }
+  }
+
+  fn start_send(self: Pin<&mut Self>, item: Frame) -> Result<()> {
+    match self.get_mut() {
+      Transport::Tcp(transport) => Pin::new(transport).start_send(item),
+      Transport::Mux(transport) => Pin::new(transport).start_send(item),
+      #[cfg(feature = "websocket")]
+      Transport::WebSocket(transport) => Pin::new(transport).start_send(item),
+      #[cfg(feature = "test-util")]
+      Transport::Memory(transport) => Pin::new(transport).start_send(item),
+    }
+  }
+
+  fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+    match self.get_mut() {
+      Transport::Tcp(transport) => Pin::new(transport).poll_flush(cx),
+      Transport::Mux(transport) => Pin::new(transport).poll_flush(cx),
+      #[cfg(feature = "websocket")]
+      Transport::WebSocket(transport) => Pin::new(transport).poll_flush(cx),
+      #[cfg(feature = "test-util")]
+      Transport::Memory(transport) => Pin::new(transport).poll_flush(cx),
+    }
+  }
+
+  fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+    match self.get_mut() {
+      Transport::Tcp(transport) => Pin::new(transport).poll_close(cx),
+      Transport::Mux(transport) => Pin::new(transport).poll_close(cx),
+      #[cfg(feature = "websocket")]
+      Transport::WebSocket(transport) => Pin::new(transport).poll_close(cx),
+      #[cfg(feature = "test-util")]
+      Transport::Memory(transport) => Pin::new(transport).poll_close(cx),
+    }
+  }
+}
+
 #[test]
 fn test_lookup() {
   use std::net::ToSocketAddrs;
@@ -7,3 +7,13 @@ impl From<flo_constants::version::Version> for crate::proto::flo_common::Version
     }
   }
 }
+
+impl From<crate::proto::flo_common::Version> for flo_constants::version::Version {
+  fn from(v: crate::proto::flo_common::Version) -> Self {
+    flo_constants::version::Version {
+      major: v.major,
+      minor: v.minor,
+      patch: v.patch,
+    }
+  }
+}
@@ -0,0 +1,96 @@
+//! FloStream transport over WebSocket, so browsers can speak the flo protocol.
+//! Each binary message carries exactly one frame, other data messages are ignored.
+
+use async_tungstenite::tokio::{accept_async, TokioAdapter};
+use async_tungstenite::tungstenite::Message;
+use async_tungstenite::WebSocketStream;
+use bytes::{Bytes, BytesMut};
+use futures::{ready, Sink, Stream};
+use std::fmt;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::net::TcpStream;
+
+use crate::codec::FloFrameCodec;
+use crate::constants::MAX_PAYLOAD_LEN;
+use crate::error::*;
+use crate::packet::{Frame, Header};
+
+pub(crate) struct WsTransport {
+  inner: WebSocketStream<TokioAdapter<TcpStream>>,
+  codec: FloFrameCodec,
+  pub(crate) local_addr: SocketAddr,
+  pub(crate) peer_addr: SocketAddr,
+}
+
+impl WsTransport {
+  pub(crate) async fn accept(socket: TcpStream) -> Result<Self> {
+    let local_addr = socket.local_addr()?;
+    let peer_addr = socket.peer_addr()?;
+    let inner = accept_async(socket).await?;
+    Ok(Self {
+      inner,
+      codec: FloFrameCodec::new(),
+      local_addr,
+      peer_addr,
+    })
+  }
+}
+
+impl fmt::Debug for WsTransport {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("WsTransport")
+      .field("peer_addr", &self.peer_addr)
+      .finish()
+  }
+}
+
+impl Stream for WsTransport {
+  type Item = Result<Frame>;
+
+  fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+    loop {
+      let msg = match ready!(Pin::new(&mut self.inner).poll_next(cx)) {
+        Some(Ok(msg)) => msg,
+        Some(Err(err)) => return Poll::Ready(Some(Err(err.into()))),
+        None => return Poll::Ready(None),
+      };
+      match msg {
+        Message::Binary(data) => {
+          if data.len() > Header::MIN_SIZE + MAX_PAYLOAD_LEN {
+            return Poll::Ready(Some(Err(Error::PayloadTooLarge)));
+          }
+          return Poll::Ready(Some(self.codec.decode_bytes(Bytes::from(data))));
+        }
+        Message::Close(_) => return Poll::Ready(None),
+        // pings are answered by tungstenite
+        _ => continue,
+      }
+    }
+  }
+}
+
+impl Sink<Frame> for WsTransport {
+  type Error = Error;
+
+  fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+    Pin::new(&mut self.inner).poll_ready(cx).map_err(Into::into)
+  }
+
+  fn start_send(mut self: Pin<&mut Self>, item: Frame) -> Result<()> {
+    let mut buf = BytesMut::new();
+    item.encode(&mut buf);
+    Pin::new(&mut self.inner)
+      .start_send(Message::Binary(buf.to_vec()))
+      .map_err(Into::into)
+  }
+
+  fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+    Pin::new(&mut self.inner).poll_flush(cx).map_err(Into::into)
+  }
+
+  fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+    Pin::new(&mut self.inner).poll_close(cx).map_err(Into::into)
+  }
+}
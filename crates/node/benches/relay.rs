@@ -0,0 +1,120 @@
+use bytes::{Bytes, BytesMut};
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use futures::StreamExt;
+use tokio::sync::mpsc::{channel, Receiver, Sender};
+
+use flo_net::packet::Frame;
+use flo_net::w3gs::{W3GSAckQueue, W3GSFrameExt, W3GSMetadata, W3GSPacket};
+use flo_node::bench::{ActionTickStream, StepRange};
+use flo_w3gs::action::{IncomingAction, PlayerAction, TimeSlot};
+
+const PLAYER_COUNTS: &[usize] = &[2, 12, 24];
+const ACTION_COUNTS: &[usize] = &[0, 24, 480];
+
+fn actions(count: usize) -> Vec<PlayerAction> {
+  (0..count)
+    .map(|i| PlayerAction {
+      player_id: (i % 24) as u8 + 1,
+      data: Bytes::from(vec![0x16; 13]),
+    })
+    .collect()
+}
+
+/// Mirrors what the dispatcher does for each player of a broadcast
+struct Player {
+  ack_q: W3GSAckQueue,
+  tx: Sender<Frame>,
+  rx: Receiver<Frame>,
+}
+
+impl Player {
+  fn new() -> Self {
+    let (tx, rx) = channel(250);
+    Self {
+      ack_q: W3GSAckQueue::new(),
+      tx,
+      rx,
+    }
+  }
+
+  fn send_w3gs(&mut self, pkt: W3GSPacket) {
+    let sid = self.ack_q.gen_next_send_sid();
+    let ack_sid = self.ack_q.take_ack_received();
+    let meta = W3GSMetadata::new(pkt.type_id(), sid, ack_sid);
+    self.ack_q.push_send(meta.clone(), pkt.clone());
+    self.tx.try_send(Frame::from_w3gs(meta, pkt)).unwrap();
+  }
+
+  /// Encodes the queued frames like the peer worker does, then acks them
+  fn flush(&mut self, buf: &mut BytesMut) {
+    let mut last_sid = None;
+    while let Ok(frame) = self.rx.try_recv() {
+      last_sid = frame.w3gs_sid();
+      frame.encode(buf);
+    }
+    if let Some(sid) = last_sid {
+      self.ack_q.ack_sent(sid);
+    }
+  }
+}
+
+fn broadcast(c: &mut Criterion) {
+  let mut group = c.benchmark_group("node_broadcast");
+  for &players in PLAYER_COUNTS {
+    let pkt = W3GSPacket::with_payload(IncomingAction(TimeSlot {
+      time_increment_ms: 30,
+      actions: actions(24),
+    }))
+    .unwrap();
+    group.bench_with_input(
+      BenchmarkId::new("fan_out", players),
+      &players,
+      |b, &players| {
+        let mut players: Vec<_> = (0..players).map(|_| Player::new()).collect();
+        let mut buf = BytesMut::with_capacity(4096);
+        b.iter(|| {
+          for player in &mut players {
+            player.send_w3gs(pkt.clone());
+          }
+          for player in &mut players {
+            buf.clear();
+            player.flush(&mut buf);
+            black_box(&buf);
+          }
+        })
+      },
+    );
+  }
+  group.finish();
+}
+
+fn tick(c: &mut Criterion) {
+  let rt = tokio::runtime::Builder::new_current_thread()
+    .enable_time()
+    .start_paused(true)
+    .build()
+    .unwrap();
+
+  let mut group = c.benchmark_group("node_action_tick");
+  for &count in ACTION_COUNTS {
+    let actions = actions(count);
+    for &max_player_actions in &[0, 16] {
+      let id = format!("{}_actions/max_{}", count, max_player_actions);
+      group.bench_function(id, |b| {
+        let mut stream = rt.block_on(async { ActionTickStream::new(30, StepRange::default()) });
+        stream.set_max_player_actions(max_player_actions);
+        b.iter(|| {
+          for action in &actions {
+            stream.add_action(action.clone());
+          }
+          // time is paused, the runtime skips to the next tick right away
+          black_box(rt.block_on(stream.next()).unwrap())
+        })
+      });
+    }
+  }
+  group.finish();
+}
+
+criterion_group!(benches, broadcast, tick);
+criterion_main!(benches);
@@ -1,95 +1,149 @@
 use futures::stream::StreamExt;
+use std::time::Duration;
 
 use flo_constants::NODE_CLIENT_PORT;
+use flo_net::capability::Capabilities;
 use flo_net::listener::FloListener;
+use flo_net::mux::Multiplexer;
+use flo_net::packet::{Frame, PacketTypeId};
 use flo_net::proto::flo_node::*;
 use flo_net::stream::FloStream;
 
 use crate::error::*;
+use crate::relay::{serve_relay, RelayTarget};
 use crate::state::{GlobalState, GlobalStateRef, PlayerToken};
 use flo_w3gs::constants::LeaveReason;
 
+const RECV_TIMEOUT: Duration = Duration::from_secs(3);
+
 pub async fn serve_client(state: GlobalStateRef) -> Result<()> {
-  let mut listener = FloListener::bind_v4(NODE_CLIENT_PORT).await?;
+  let mut listener = FloListener::bind(NODE_CLIENT_PORT).await?;
 
   while let Some(incoming) = listener.incoming().next().await {
-    if let Ok(mut stream) = incoming {
-      let state = state.clone();
-      tokio::spawn(async move {
-        let claim = match handshake(&state, &mut stream).await {
-          Ok(claim) => claim,
-          Err(err) => {
-            let reason = match &err {
-              Error::InvalidToken => ClientConnectRejectReason::InvalidToken,
-              _ => ClientConnectRejectReason::Unknown,
-            };
-            stream
-              .send(PacketClientConnectReject {
-                reason: reason.into(),
-                message: format!("{}", err),
-              })
-              .await
-              .ok();
-            return;
-          }
-        };
-
-        tracing::debug!(
-          game_id = claim.game_id,
-          player_id = claim.player_id,
-          "connected"
-        );
-
-        let session = match state.get_game(claim.game_id) {
-          Some(session) => session,
-          None => {
-            stream
-              .send(PacketClientConnectReject {
-                reason: ClientConnectRejectReason::Unknown.into(),
-                message: format!("Game session was not found."),
-              })
-              .await
-              .ok();
-            return;
-          }
-        };
-
-        if claim.shutdown_retry {
-          if let Err(err) = session
-            .retry_shutdown(claim.player_id, claim.leave_reason, &mut stream)
-            .await
-          {
-            tracing::error!(
-              game_id = claim.game_id,
-              player_id = claim.player_id,
-              "retry_shutdown: {}",
-              err
-            );
-            reject(&mut stream, err).await.ok();
-          }
-        } else {
-          if let Err((stream, err)) = session
-            .register_player_stream(claim.player_id, stream)
-            .await
-          {
-            tracing::error!(
-              game_id = claim.game_id,
-              player_id = claim.player_id,
-              "register player stream: {}",
-              err
-            );
-            if let Some(mut stream) = stream {
-              reject(&mut stream, err).await.ok();
-            }
-          }
-        }
-      });
+    if let Ok(stream) = incoming {
+      tokio::spawn(serve_connection(state.clone(), stream));
     }
   }
 
   Ok(())
 }
 
+/// A connection carries a single player stream,
+/// or several of them if its first frame is a `Mux` frame
+async fn serve_connection(state: GlobalStateRef, mut stream: FloStream) {
+  let frame = match tokio::time::timeout(RECV_TIMEOUT, stream.recv_frame()).await {
+    Ok(Ok(frame)) => frame,
+    Ok(Err(err)) => {
+      tracing::debug!("recv first frame: {}", err);
+      return;
+    }
+    Err(_) => {
+      reject_handshake(&mut stream, flo_net::error::Error::StreamTimeout.into()).await;
+      return;
+    }
+  };
+
+  if frame.type_id != PacketTypeId::Mux {
+    return serve_stream(state, stream, Some(frame)).await;
+  }
+
+  let mut mux = match Multiplexer::accept(stream, frame) {
+    Ok(mux) => mux,
+    Err(err) => {
+      tracing::debug!("multiplex: {}", err);
+      return;
+    }
+  };
+  tracing::debug!(peer_addr = %mux.peer_addr(), "multiplexed connection");
+  while let Some(stream) = mux.next().await {
+    tokio::spawn(serve_stream(state.clone(), stream, None));
+  }
+}
+
+/// Serves a player stream, `connect` is the connect frame if it was already received
+async fn serve_stream(state: GlobalStateRef, mut stream: FloStream, connect: Option<Frame>) {
+  let claim = match handshake(&state, &mut stream, connect).await {
+    Ok(Handshake::Local(claim)) => claim,
+    Ok(Handshake::Relay(connect, token, target)) => {
+      let game_id = target.game_id;
+      let player_id = target.player_id;
+      tracing::debug!(game_id, player_id, "relaying to {}", target.addr);
+      if let Err(err) = serve_relay(state.relays(), stream, connect, token, target).await {
+        tracing::debug!(game_id, player_id, "relay: {}", err);
+      }
+      return;
+    }
+    Err(err) => {
+      reject_handshake(&mut stream, err).await;
+      return;
+    }
+  };
+
+  tracing::debug!(
+    game_id = claim.game_id,
+    player_id = claim.player_id,
+    "connected"
+  );
+
+  let session = match state.get_game(claim.game_id) {
+    Some(session) => session,
+    None => {
+      stream
+        .send(PacketClientConnectReject {
+          reason: ClientConnectRejectReason::Unknown.into(),
+          message: format!("Game session was not found."),
+        })
+        .await
+        .ok();
+      return;
+    }
+  };
+
+  if claim.shutdown_retry {
+    if let Err(err) = session
+      .retry_shutdown(claim.player_id, claim.leave_reason, &mut stream)
+      .await
+    {
+      tracing::error!(
+        game_id = claim.game_id,
+        player_id = claim.player_id,
+        "retry_shutdown: {}",
+        err
+      );
+      reject(&mut stream, err).await.ok();
+    }
+  } else {
+    if let Err((stream, err)) = session
+      .register_player_stream(claim.player_id, stream)
+      .await
+    {
+      tracing::error!(
+        game_id = claim.game_id,
+        player_id = claim.player_id,
+        "register player stream: {}",
+        err
+      );
+      if let Some(mut stream) = stream {
+        reject(&mut stream, err).await.ok();
+      }
+    }
+  }
+}
+
+async fn reject_handshake(stream: &mut FloStream, err: Error) {
+  let reason = match &err {
+    Error::InvalidToken => ClientConnectRejectReason::InvalidToken,
+    _ => ClientConnectRejectReason::Unknown,
+  };
+  stream
+    .send(PacketClientConnectReject {
+      reason: reason.into(),
+      message: format!("{}", err),
+    })
+    .await
+    .ok();
+}
+
 async fn reject(stream: &mut FloStream, err: Error) -> Result<()> {
   stream
     .send(PacketClientConnectReject {
@@ -104,28 +158,46 @@ async fn reject(stream: &mut FloStream, err: Error) -> Result<()> {
   Ok(())
 }
 
-async fn handshake(state: &GlobalState, stream: &mut FloStream) -> Result<Claim> {
-  use std::time::Duration;
-  const RECV_TIMEOUT: Duration = Duration::from_secs(3);
+enum Handshake {
+  Local(Claim),
+  /// The player's game is hosted by another node
+  Relay(PacketClientConnect, PlayerToken, RelayTarget),
+}
 
-  let connect: PacketClientConnect = stream.recv_timeout(RECV_TIMEOUT).await?;
+async fn handshake(
+  state: &GlobalState,
+  stream: &mut FloStream,
+  connect: Option<Frame>,
+) -> Result<Handshake> {
+  let connect: PacketClientConnect = match connect {
+    Some(frame) => frame.decode()?,
+    None => stream.recv_timeout(RECV_TIMEOUT).await?,
+  };
+
+  stream.set_capabilities(Capabilities::local().negotiate(connect.capabilities.as_ref()));
 
-  let token = if let Some(token) = PlayerToken::from_vec(connect.token) {
+  let token = if let Some(token) = PlayerToken::from_vec(connect.token.clone()) {
     token
   } else {
     return Err(Error::InvalidToken);
   };
 
-  let pending = state
-    .get_pending_player(&token)
-    .ok_or_else(|| Error::InvalidToken)?;
+  let pending = match state.get_pending_player(&token) {
+    Some(pending) => pending,
+    None => {
+      let target = state
+        .take_relay_target(&token)
+        .ok_or_else(|| Error::InvalidToken)?;
+      return Ok(Handshake::Relay(connect, token, target));
+    }
+  };
 
-  Ok(Claim {
+  Ok(Handshake::Local(Claim {
     game_id: pending.game_id,
     player_id: pending.player_id,
     shutdown_retry: connect.retry_shutdown,
     leave_reason: connect.leave_reason.map(LeaveReason::from),
-  })
+  }))
 }
 
 #[derive(Debug)]
@@ -0,0 +1,189 @@
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use serde::Deserialize;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::constants::{
+  ACTION_ANALYZER_MAX_ACTIONS_PER_SECOND, CHAT_FLOOD_MAX_MESSAGES, CHAT_FLOOD_WINDOW,
+  GAME_CLOCK_MAX_PAUSE, GAME_DEFAULT_STEP_MS, GAME_DELAY_RANGE, GAME_MAX_PLAYER_ACTIONS_PER_TICK,
+  GAME_PING_TIMEOUT, GAME_PLAYER_LAGGING_THRESHOLD_MS, GAME_SEND_QUEUE_THRESHOLD,
+};
+use crate::error::*;
+
+static CURRENT: Lazy<RwLock<Arc<NodeConfig>>> = Lazy::new(|| {
+  let config = NodeConfig::load().unwrap_or_else(|err| {
+    tracing::error!("load config: {}, using defaults", err);
+    NodeConfig::default()
+  });
+  RwLock::new(Arc::new(config))
+});
+
+/// Operational settings that can be reloaded without restarting the node.
+///
+/// Loaded from `FLO_NODE_CONFIG` (default: `flo-node.toml`),
+/// all fields are optional.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct NodeConfig {
+  /// Tick step of new games
+  pub game_step_ms: u16,
+  /// Allowed range of the `!delay` command
+  pub game_delay_min_ms: u64,
+  pub game_delay_max_ms: u64,
+  /// Time before a player is considered lagging
+  pub game_player_lagging_threshold_ms: u32,
+  pub game_ping_timeout_ms: u64,
+  /// Max time the game clock can be paused
+  pub game_clock_max_pause_ms: u64,
+  /// Frames queued for a player before `game_slow_player_policy` applies,
+  /// the player's stream is closed once the queue is full
+  pub game_send_queue_threshold: usize,
+  pub game_slow_player_policy: SlowPlayerPolicy,
+  /// Actions a player can send in a game tick, further actions are merged or dropped.
+  /// 0 disables the limit
+  pub game_max_player_actions_per_tick: usize,
+  /// Max number of hosted games, unlimited if not set
+  pub max_games: Option<usize>,
+  /// Let observers chat with players once the game is decided,
+  /// observer chat is restricted to other observers otherwise
+  pub observer_all_chat_after_end: bool,
+  /// Max chat messages a player can send in `chat_flood_window_ms`,
+  /// further messages are dropped. 0 disables the limit
+  pub chat_flood_max_messages: usize,
+  pub chat_flood_window_ms: u64,
+  /// Report action streams of new games with impossible action rates
+  /// or undecodable actions to the controller
+  pub action_analyzer: bool,
+  pub action_analyzer_max_actions_per_second: u32,
+  /// Forward players to the node hosting their game when asked by the controller
+  pub relay: bool,
+  /// Log filter, e.g. `flo_node=debug`
+  pub log: Option<String>,
+}
+
+/// What to do with players that do not receive frames fast enough
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SlowPlayerPolicy {
+  /// Show the lag screen until the player catches up
+  Lag,
+  /// Hold game ticks until the player catches up
+  Pause,
+  /// Remove the player from the game
+  Drop,
+}
+
+impl Default for NodeConfig {
+  fn default() -> Self {
+    let [delay_min, delay_max] = GAME_DELAY_RANGE;
+    NodeConfig {
+      game_step_ms: *GAME_DEFAULT_STEP_MS,
+      game_delay_min_ms: delay_min.as_millis() as u64,
+      game_delay_max_ms: delay_max.as_millis() as u64,
+      game_player_lagging_threshold_ms: GAME_PLAYER_LAGGING_THRESHOLD_MS,
+      game_ping_timeout_ms: GAME_PING_TIMEOUT.as_millis() as u64,
+      game_clock_max_pause_ms: GAME_CLOCK_MAX_PAUSE.as_millis() as u64,
+      game_send_queue_threshold: GAME_SEND_QUEUE_THRESHOLD,
+      game_slow_player_policy: SlowPlayerPolicy::Lag,
+      game_max_player_actions_per_tick: GAME_MAX_PLAYER_ACTIONS_PER_TICK,
+      max_games: None,
+      observer_all_chat_after_end: false,
+      chat_flood_max_messages: CHAT_FLOOD_MAX_MESSAGES,
+      chat_flood_window_ms: CHAT_FLOOD_WINDOW.as_millis() as u64,
+      action_analyzer: false,
+      action_analyzer_max_actions_per_second: ACTION_ANALYZER_MAX_ACTIONS_PER_SECOND,
+      relay: false,
+      log: None,
+    }
+  }
+}
+
+impl NodeConfig {
+  pub fn path() -> PathBuf {
+    std::env::var("FLO_NODE_CONFIG")
+      .map(PathBuf::from)
+      .unwrap_or_else(|_| PathBuf::from("flo-node.toml"))
+  }
+
+  fn load() -> Result<Self> {
+    let path = Self::path();
+    if !path.exists() {
+      return Ok(Self::default());
+    }
+    let content = std::fs::read_to_string(&path)?;
+    let config: Self = toml::from_str(&content)?;
+    if config.game_delay_min_ms > config.game_delay_max_ms {
+      return Err(Error::InvalidConfig(
+        "game_delay_min_ms is greater than game_delay_max_ms".to_string(),
+      ));
+    }
+    Ok(config)
+  }
+
+  pub fn game_delay_range(&self) -> [Duration; 2] {
+    [
+      Duration::from_millis(self.game_delay_min_ms),
+      Duration::from_millis(self.game_delay_max_ms),
+    ]
+  }
+
+  pub fn game_ping_timeout(&self) -> Duration {
+    Duration::from_millis(self.game_ping_timeout_ms)
+  }
+
+  pub fn game_clock_max_pause(&self) -> Duration {
+    Duration::from_millis(self.game_clock_max_pause_ms)
+  }
+
+  pub fn chat_flood_window(&self) -> Duration {
+    Duration::from_millis(self.chat_flood_window_ms)
+  }
+}
+
+pub fn current() -> Arc<NodeConfig> {
+  CURRENT.read().clone()
+}
+
+/// Reloads the config file, the current config is kept if the file is invalid.
+///
+/// Running games keep their tick step, other settings apply immediately.
+pub fn reload() -> Result<Arc<NodeConfig>> {
+  let config = Arc::new(NodeConfig::load()?);
+  apply_log_filter(&config);
+  *CURRENT.write() = config.clone();
+  tracing::info!("config reloaded: {:?}", config);
+  Ok(config)
+}
+
+pub fn init() {
+  apply_log_filter(&current());
+}
+
+fn apply_log_filter(config: &NodeConfig) {
+  if let Some(filter) = config.log.as_ref() {
+    if let Err(err) = flo_log_subscriber::reload_filter(filter) {
+      tracing::error!("reload log filter: {}", err);
+    }
+  }
+}
+
+/// Reloads the config on SIGHUP
+#[cfg(unix)]
+pub async fn serve_reload_signal() -> Result<()> {
+  use tokio::signal::unix::{signal, SignalKind};
+  let mut hup = signal(SignalKind::hangup())?;
+  while hup.recv().await.is_some() {
+    tracing::info!("SIGHUP received, reloading config");
+    if let Err(err) = reload() {
+      tracing::error!("reload config: {}", err);
+    }
+  }
+  Ok(())
+}
+
+#[cfg(not(unix))]
+pub async fn serve_reload_signal() -> Result<()> {
+  futures::future::pending().await
+}
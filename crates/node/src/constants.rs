@@ -3,6 +3,9 @@ use once_cell::sync::Lazy;
 use std::time::Duration;
 
 pub const PEER_CHANNEL_SIZE: usize = 250;
+pub const PEER_SEND_BATCH_SIZE: usize = 32;
+pub const GAME_SEND_QUEUE_THRESHOLD: usize = 200;
+pub const GAME_MAX_PLAYER_ACTIONS_PER_TICK: usize = 16;
 pub const CONTROLLER_SENDER_BUF_SIZE: usize = 10;
 pub const GAME_DISPATCH_BUF_SIZE: usize = 256;
 pub const GAME_PLAYER_LAGGING_THRESHOLD_MS: u32 = 3000;
@@ -33,5 +36,22 @@ pub static OBS_SOURCE: Lazy<ObserverRecordSource> = Lazy::new(|| {
     .unwrap_or(ObserverRecordSource::Test)
 });
 
+pub const CHAT_LOG_MAX_ENTRIES: usize = 10000;
+pub const CHAT_FLOOD_MAX_MESSAGES: usize = 6;
+pub const CHAT_FLOOD_WINDOW: Duration = Duration::from_secs(5);
+pub const ACTION_ANALYZER_MAX_ACTIONS_PER_SECOND: u32 = 40;
+pub const ACTION_INCIDENT_REPORT_INTERVAL: Duration = Duration::from_secs(60);
 pub const RTT_STATS_REPORT_DELAY: Duration = std::time::Duration::from_secs(5);
 pub const RTT_STATS_REPORT_INTERVAL: Duration = std::time::Duration::from_secs(15);
+pub const PING_EQUALIZE_INTERVAL: Duration = Duration::from_secs(5);
+pub const UTILIZATION_REPORT_INTERVAL: Duration = Duration::from_secs(10);
+
+// unused relay tokens expire, tokens of dropped relayed connections are kept for reconnects
+pub const RELAY_TOKEN_TTL: Duration = Duration::from_secs(6 * 60 * 60);
+
+pub const SNAPSHOT_INTERVAL: Duration = Duration::from_secs(10);
+
+pub const DRAIN_TIMEOUT: Duration = Duration::from_secs(2 * 60 * 60);
+pub const DRAIN_POLL_INTERVAL: Duration = Duration::from_secs(1);
+// time for the observer publisher and the controller connection to flush after games ended
+pub const DRAIN_FLUSH_DELAY: Duration = Duration::from_secs(5);
@@ -3,18 +3,22 @@ use futures::stream::StreamExt;
 use parking_lot::RwLock;
 use std::sync::Arc;
 use tokio::sync::mpsc::{channel, Receiver, Sender};
+use tokio::time::{interval_at, Instant, MissedTickBehavior};
 use tracing_futures::Instrument;
 
 use flo_constants::NODE_CONTROLLER_PORT;
+use flo_net::capability::{Capabilities, Feature};
 use flo_net::listener::FloListener;
-use flo_net::packet::Frame;
+use flo_net::packet::{FloPacket, Frame};
 use flo_net::proto::flo_node::*;
 use flo_net::stream::FloStream;
 use flo_net::try_flo_packet;
 use flo_task::{SpawnScope, SpawnScopeHandle};
 
+use crate::constants::UTILIZATION_REPORT_INTERVAL;
 use crate::error::*;
 use crate::state::GlobalStateRef;
+use crate::utilization::UtilizationSampler;
 use flo_net::ping::PingStream;
 
 #[derive(Debug)]
@@ -49,7 +53,7 @@ impl ControllerServer {
   }
 
   pub async fn serve(&mut self) -> Result<()> {
-    let mut listener = FloListener::bind_v4(NODE_CONTROLLER_PORT).await?;
+    let mut listener = FloListener::bind(NODE_CONTROLLER_PORT).await?;
 
     while let Some(incoming) = listener.incoming().next().await {
       if let Ok(stream) = incoming {
@@ -68,6 +72,8 @@ impl ControllerServer {
 
     let connect: PacketControllerConnect = stream.recv_timeout(RECV_TIMEOUT).await?;
 
+    stream.set_capabilities(Capabilities::local().negotiate(connect.capabilities.as_ref()));
+
     if connect.secret != crate::env::Env::get().secret_key {
       stream
         .send(PacketControllerConnectReject {
@@ -80,6 +86,7 @@ impl ControllerServer {
     stream
       .send(PacketControllerConnectAccept {
         version: Some(crate::version::FLO_NODE_VERSION.into()),
+        capabilities: Some(Capabilities::local().into()),
       })
       .await?;
 
@@ -141,6 +148,25 @@ async fn handle_stream(
   mut scope: SpawnScopeHandle,
 ) -> Result<()> {
   let mut rx = state.frame_rx.lock().await;
+
+  if stream.capabilities().supports(Feature::GameRecovery) {
+    let games = state.g_state.orphaned_games();
+    if !games.is_empty() {
+      let game_ids: Vec<i32> = games.iter().map(|game| game.game_id).collect();
+      tracing::info!(?game_ids, "report orphaned games");
+      let frame = PacketNodeOrphanedGames { games }.encode_as_frame()?;
+      stream.send_frame_timeout(frame).await?;
+      state.g_state.remove_orphaned_games(&game_ids);
+    }
+  }
+
+  let report_utilization = stream.capabilities().supports(Feature::NodeUtilization);
+  let mut utilization_interval = interval_at(
+    Instant::now() + UTILIZATION_REPORT_INTERVAL,
+    UTILIZATION_REPORT_INTERVAL,
+  );
+  utilization_interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+  let mut utilization = UtilizationSampler::default();
   loop {
     tokio::select! {
       _ = scope.left() => {
@@ -162,6 +188,10 @@ async fn handle_stream(
           break;
         }
       }
+      _ = utilization_interval.tick(), if report_utilization => {
+        let frame = utilization.sample(&state.g_state).encode_as_frame()?;
+        stream.send_frame_timeout(frame).await?;
+      }
     }
   }
   Ok(())
@@ -178,13 +208,22 @@ async fn handle_frame(state: &Arc<State>, mut frame: Frame) -> Result<()> {
   try_flo_packet! {
     frame => {
       pkt: PacketControllerCreateGame => {
-        let frame = state.g_state.handle_controller_create_game(ControllerServerHandle::new(state.clone()), pkt)?;
+        let span = tracing::info_span!("create_game");
+        flo_log::trace_context::set_parent(&span, &pkt.trace_context);
+        let frame = span.in_scope(|| state.g_state.handle_controller_create_game(ControllerServerHandle::new(state.clone()), pkt))?;
         flo_log::result_ok!("create game", tx.send(frame).await);
       }
       pkt: PacketControllerUpdateSlotStatus => {
         let frame = state.g_state.handle_controller_update_slot_client_status(pkt).await?;
         flo_log::result_ok!("update slot status", tx.send(frame).await);
       }
+      pkt: PacketControllerRelayPlayers => {
+        let frame = state.g_state.handle_controller_relay_players(pkt)?;
+        flo_log::result_ok!("relay players", tx.send(frame).await);
+      }
+      pkt: PacketControllerUpdatePlayerBans => {
+        state.g_state.handle_controller_update_player_bans(pkt).await?;
+      }
     }
   }
   Ok(())
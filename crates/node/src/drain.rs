@@ -0,0 +1,61 @@
+use crate::constants::{DRAIN_FLUSH_DELAY, DRAIN_POLL_INTERVAL};
+use crate::env::Env;
+use crate::error::Result;
+use crate::state::GlobalStateRef;
+use tokio::time::{sleep, Instant};
+
+/// Resolves after the node has been drained.
+///
+/// Triggered by SIGTERM / Ctrl-C or `POST /drain`.
+/// New games are rejected, running games are given `FLO_NODE_DRAIN_TIMEOUT_SECS` to end.
+pub async fn serve_drain(state: GlobalStateRef) -> Result<()> {
+  tokio::select! {
+    res = shutdown_signal() => {
+      res?;
+      tracing::info!("shutdown signal received");
+      state.request_drain();
+    }
+    _ = state.drain_requested() => {
+      tracing::info!("drain requested");
+    }
+  }
+
+  let deadline = Instant::now() + Env::get().drain_timeout;
+  loop {
+    let games = state.game_count();
+    if games == 0 {
+      break;
+    }
+    if Instant::now() >= deadline {
+      tracing::warn!(games, "drain timed out, ending running games");
+      for game in state.get_games() {
+        game.force_end().await;
+      }
+      break;
+    }
+    tracing::debug!(games, "waiting for running games to end");
+    sleep(DRAIN_POLL_INTERVAL).await;
+  }
+
+  sleep(DRAIN_FLUSH_DELAY).await;
+  tracing::info!("drained");
+
+  Ok(())
+}
+
+#[cfg(unix)]
+async fn shutdown_signal() -> Result<()> {
+  use tokio::signal::unix::{signal, SignalKind};
+  let mut term = signal(SignalKind::terminate())?;
+  tokio::select! {
+    _ = term.recv() => {},
+    res = tokio::signal::ctrl_c() => res?,
+  }
+  Ok(())
+}
+
+#[cfg(not(unix))]
+async fn shutdown_signal() -> Result<()> {
+  tokio::signal::ctrl_c().await?;
+  Ok(())
+}
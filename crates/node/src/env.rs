@@ -1,15 +1,27 @@
 use once_cell::sync::Lazy;
 use std::env;
+use std::path::PathBuf;
+use std::time::Duration;
 
 #[derive(Debug)]
 pub struct Env {
   pub secret_key: String,
+  /// Max time to wait for running games to end before shutting down
+  pub drain_timeout: Duration,
+  /// Directory of the game snapshots used to recover from crashes, disabled if not set
+  pub snapshot_dir: Option<PathBuf>,
 }
 
 impl Env {
   pub fn get() -> &'static Env {
     static INSTANCE: Lazy<Env> = Lazy::new(|| Env {
       secret_key: env::var("FLO_NODE_SECRET").unwrap_or_default(),
+      drain_timeout: env::var("FLO_NODE_DRAIN_TIMEOUT_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .map(Duration::from_secs)
+        .unwrap_or(crate::constants::DRAIN_TIMEOUT),
+      snapshot_dir: env::var("FLO_NODE_SNAPSHOT_DIR").ok().map(PathBuf::from),
     });
     &INSTANCE
   }
@@ -29,6 +29,10 @@ pub enum Error {
   InvalidSecret,
   #[error("invalid token")]
   InvalidToken,
+  #[error("relay disabled")]
+  RelayDisabled,
+  #[error("invalid relay target: {0}")]
+  InvalidRelayTarget(String),
   #[error("invalid client status transition: {0:?} => {1:?}")]
   InvalidClientStatusTransition(SlotClientStatus, SlotClientStatus),
   #[error("observer put record: {0}")]
@@ -45,6 +49,10 @@ pub enum Error {
   Proto(#[from] s2_grpc_utils::result::Error),
   #[error("http: {0}")]
   Http(#[from] hyper::Error),
+  #[error("invalid config: {0}")]
+  InvalidConfig(String),
+  #[error("parse config: {0}")]
+  ParseConfig(#[from] toml::de::Error),
 }
 
 pub type Result<T, E = Error> = std::result::Result<T, E>;
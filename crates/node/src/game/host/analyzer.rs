@@ -0,0 +1,135 @@
+use bytes::Bytes;
+use flo_net::proto::flo_node::ActionIncidentKind;
+use flo_w3gs::analysis::ActionCategory;
+use flo_w3gs::protocol::action::PlayerAction;
+use std::collections::{BTreeMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use crate::constants::ACTION_INCIDENT_REPORT_INTERVAL;
+
+const WINDOW: Duration = Duration::from_secs(1);
+
+/// Flags action streams that are unlikely to come from a human player:
+/// sustained action rates above `max_actions_per_second` and undecodable actions.
+///
+/// Incidents are only reported for review, players are never kicked.
+#[derive(Debug)]
+pub struct ActionAnalyzer {
+  max_actions_per_second: u32,
+  players: BTreeMap<i32, PlayerWindow>,
+}
+
+#[derive(Debug, Default)]
+struct PlayerWindow {
+  window: VecDeque<(Instant, u32)>,
+  count: u32,
+  last_reports: BTreeMap<ActionIncidentKind, Instant>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ActionIncident {
+  pub player_id: i32,
+  pub kind: ActionIncidentKind,
+  pub detail: String,
+}
+
+impl ActionAnalyzer {
+  pub fn new<I>(player_ids: I, max_actions_per_second: u32) -> Self
+  where
+    I: IntoIterator<Item = i32>,
+  {
+    ActionAnalyzer {
+      max_actions_per_second,
+      players: player_ids
+        .into_iter()
+        .map(|id| (id, PlayerWindow::default()))
+        .collect(),
+    }
+  }
+
+  pub fn check(&mut self, player_id: i32, data: &Bytes) -> Option<ActionIncident> {
+    self.check_at(Instant::now(), player_id, data)
+  }
+
+  fn check_at(&mut self, now: Instant, player_id: i32, data: &Bytes) -> Option<ActionIncident> {
+    let player = self.players.get_mut(&player_id)?;
+
+    let action = PlayerAction {
+      player_id: 0,
+      data: data.clone(),
+    };
+    let mut count = 0;
+    let mut malformed = None;
+    for action in action.actions() {
+      match action {
+        Ok(action) => {
+          if ActionCategory::of(&action).is_some() {
+            count += 1;
+          }
+        }
+        Err(err) => {
+          malformed = Some(err.to_string());
+          break;
+        }
+      }
+    }
+
+    player.window.push_back((now, count));
+    player.count += count;
+    while let Some((t, count)) = player.window.front().cloned() {
+      if now.saturating_duration_since(t) < WINDOW {
+        break;
+      }
+      player.count -= count;
+      player.window.pop_front();
+    }
+
+    let (kind, detail) = if let Some(err) = malformed {
+      (ActionIncidentKind::MalformedActions, err)
+    } else if player.count > self.max_actions_per_second {
+      (
+        ActionIncidentKind::HighActionRate,
+        format!("{} actions in {}ms", player.count, WINDOW.as_millis()),
+      )
+    } else {
+      return None;
+    };
+
+    if let Some(last) = player.last_reports.get(&kind) {
+      if now.saturating_duration_since(*last) < ACTION_INCIDENT_REPORT_INTERVAL {
+        return None;
+      }
+    }
+    player.last_reports.insert(kind, now);
+
+    Some(ActionIncident {
+      player_id,
+      kind,
+      detail,
+    })
+  }
+}
+
+#[test]
+fn test_action_analyzer() {
+  // select group 1
+  let data = Bytes::from_static(&[0x18, 0x01, 0x00, 0x18, 0x01, 0x00]);
+  let mut analyzer = ActionAnalyzer::new(vec![1], 5);
+  let t = Instant::now();
+
+  assert_eq!(analyzer.check_at(t, 1, &data), None);
+  assert_eq!(analyzer.check_at(t, 1, &data), None);
+  assert_eq!(analyzer.check_at(t + WINDOW, 1, &data), None);
+  assert_eq!(analyzer.check_at(t + WINDOW, 1, &data), None);
+  let incident = analyzer.check_at(t + WINDOW, 1, &data).unwrap();
+  assert_eq!(incident.kind, ActionIncidentKind::HighActionRate);
+  assert_eq!(incident.detail, "6 actions in 1000ms");
+  // reported once per interval
+  assert_eq!(analyzer.check_at(t + WINDOW, 1, &data), None);
+
+  let incident = analyzer
+    .check_at(t, 1, &Bytes::from_static(&[0xFF]))
+    .unwrap();
+  assert_eq!(incident.kind, ActionIncidentKind::MalformedActions);
+  assert_eq!(analyzer.check_at(t, 2, &data), None);
+}
@@ -1,66 +1,156 @@
+use bytes::BytesMut;
 use futures::stream::Stream;
+use std::collections::BTreeMap;
 use std::future::Future;
 use std::pin::Pin;
-use std::time::{Duration, Instant};
-use tokio::time::{sleep, Sleep};
+use std::time::Duration;
+use tokio::time::{sleep_until, Instant, Sleep};
 
 use flo_w3gs::protocol::action::PlayerAction;
 use futures::task::{Context, Poll};
 use std::task::Waker;
 
+/// Allowed game step range of a game, within the global limits
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StepRange {
+  min: u16,
+  max: u16,
+}
+
+impl Default for StepRange {
+  fn default() -> Self {
+    StepRange {
+      min: ActionTickStream::MIN_STEP,
+      max: ActionTickStream::MAX_STEP,
+    }
+  }
+}
+
+impl StepRange {
+  /// Overrides out of the global limits are clamped, `max` is raised to `min` if lower
+  pub fn new(min: Option<u16>, max: Option<u16>) -> Self {
+    let global = Self::default();
+    let min = min.map(|v| global.clamp(v)).unwrap_or(global.min);
+    let max = max
+      .map(|v| std::cmp::max(min, global.clamp(v)))
+      .unwrap_or(global.max);
+    StepRange { min, max }
+  }
+
+  pub fn clamp(&self, step: u16) -> u16 {
+    std::cmp::min(self.max, std::cmp::max(self.min, step))
+  }
+
+  pub fn is_locked(&self) -> bool {
+    self.min == self.max
+  }
+}
+
+/// Emits action ticks on an absolute schedule: ticks are due at `origin + n * step`,
+/// so late wake-ups do not push back the following ticks.
+/// The time increment of each tick is the wall-clock time elapsed since the previous one.
 #[derive(Debug)]
 pub struct ActionTickStream {
   paused: bool,
+  range: StepRange,
   step: u16,
   step_duration: Duration,
+  origin: Instant,
+  ticks: u32,
+  elapsed_ms: u64,
   delay: Pin<Box<Sleep>>,
   actions: Vec<PlayerAction>,
-  _last_instant: Instant,
+  max_player_actions: usize,
+  player_actions: BTreeMap<u8, usize>,
+  dropped_actions: BTreeMap<u8, usize>,
   resume_waker: Option<Waker>,
 }
 
 impl ActionTickStream {
-  pub const MIN_STEP: u16 = 15;
-  pub const MAX_STEP: u16 = 250;
+  pub const MIN_STEP: u16 = flo_constants::GAME_STEP_MIN_MS;
+  pub const MAX_STEP: u16 = flo_constants::GAME_STEP_MAX_MS;
+  pub const MAX_COALESCED_ACTION_LEN: usize = 512;
 
-  pub fn new(step: u16) -> Self {
-    let step = std::cmp::max(Self::MIN_STEP, step);
+  pub fn new(step: u16, range: StepRange) -> Self {
+    let step = range.clamp(step);
     let step_duration = Duration::from_millis(step as u64);
+    let origin = Instant::now();
     ActionTickStream {
       paused: false,
+      range,
       step,
       step_duration,
-      delay: Box::pin(sleep(step_duration)),
+      origin,
+      ticks: 1,
+      elapsed_ms: 0,
+      delay: Box::pin(sleep_until(origin + step_duration)),
       actions: vec![],
-      _last_instant: Instant::now(),
+      max_player_actions: 0,
+      player_actions: BTreeMap::new(),
+      dropped_actions: BTreeMap::new(),
       resume_waker: None,
     }
   }
 
   pub fn set_step(&mut self, value: u16) {
-    self.step = std::cmp::min(Self::MAX_STEP, std::cmp::max(Self::MIN_STEP, value));
-    self.step_duration = Duration::from_millis(value as u64);
-    self
-      .delay
-      .as_mut()
-      .reset((Instant::now() + self.step_duration).into());
+    self.step = self.range.clamp(value);
+    self.step_duration = Duration::from_millis(self.step as u64);
+    // keep the time already sent to players
+    let origin = self.origin + Duration::from_millis(self.elapsed_ms);
+    self.rebase(origin);
   }
 
   pub fn step(&self) -> u16 {
     self.step
   }
 
+  /// Limits the actions a player can send in a tick, 0 disables the limit
+  pub fn set_max_player_actions(&mut self, value: usize) {
+    self.max_player_actions = value;
+  }
+
+  /// Past the limit, actions are merged into the last action of the player in the tick,
+  /// or dropped if the merged action would exceed `MAX_COALESCED_ACTION_LEN`
   pub fn add_action(&mut self, action: PlayerAction) {
-    self.actions.push(action)
+    let count = self.player_actions.entry(action.player_id).or_default();
+    *count += 1;
+    if self.max_player_actions == 0 || *count <= self.max_player_actions {
+      self.actions.push(action);
+      return;
+    }
+
+    let last = self
+      .actions
+      .iter_mut()
+      .rev()
+      .find(|v| v.player_id == action.player_id);
+    match last {
+      Some(last) if last.data.len() + action.data.len() <= Self::MAX_COALESCED_ACTION_LEN => {
+        let mut data = BytesMut::with_capacity(last.data.len() + action.data.len());
+        data.extend_from_slice(&last.data);
+        data.extend_from_slice(&action.data);
+        last.data = data.freeze();
+      }
+      _ => {
+        *self.dropped_actions.entry(action.player_id).or_default() += 1;
+      }
+    }
   }
 
   pub fn replace_actions(&mut self, actions: Vec<PlayerAction>) {
     self.actions = actions;
   }
 
+  /// Puts back the actions of a tick that was not dispatched,
+  /// before the actions received since
+  pub fn requeue_actions(&mut self, mut actions: Vec<PlayerAction>) {
+    actions.extend(std::mem::replace(&mut self.actions, vec![]));
+    self.actions = actions;
+  }
+
   pub fn pause(&mut self) {
     self.paused = true;
-    self.delay.as_mut().reset(Instant::now().into());
+    self.delay.as_mut().reset(Instant::now());
   }
 
   pub fn is_paused(&self) -> bool {
@@ -71,12 +161,16 @@ impl ActionTickStream {
 
   pub fn resume(&mut self) {
     self.paused = false;
-    self
-      .delay
-      .as_mut()
-      .reset((Instant::now() + self.step_duration).into());
+    self.rebase(Instant::now());
     self.resume_waker.take().map(|w| w.wake());
   }
+
+  fn rebase(&mut self, origin: Instant) {
+    self.origin = origin;
+    self.ticks = 1;
+    self.elapsed_ms = 0;
+    self.delay.as_mut().reset(origin + self.step_duration);
+  }
 }
 
 #[derive(Debug)]
@@ -84,6 +178,8 @@ pub struct Tick {
   pub time_increment_ms: u16,
   pub actions: Vec<PlayerAction>,
   pub actions_bytes_len: usize,
+  /// Number of actions dropped by slot player id
+  pub dropped_actions: BTreeMap<u8, usize>,
 }
 
 impl Stream for ActionTickStream {
@@ -100,20 +196,103 @@ impl Stream for ActionTickStream {
     // Wait for the delay to be done
     futures::ready!(Pin::new(&mut self.delay).poll(cx));
 
-    let now = self.delay.deadline();
+    let now = Instant::now();
+    let lateness = now.saturating_duration_since(self.delay.deadline());
+    crate::metrics::GAME_TICK_LATENESS.observe(lateness.as_secs_f64());
 
-    let delay = (tokio::time::Instant::now().saturating_duration_since(now)).as_millis() as u16;
+    let total_ms = now.saturating_duration_since(self.origin).as_millis() as u64;
+    let time_increment_ms = std::cmp::min(total_ms - self.elapsed_ms, u16::MAX as u64) as u16;
+    self.elapsed_ms += time_increment_ms as u64;
 
-    let next = now + self.step_duration;
+    // ticks missed while late are merged into this one
+    let missed = (lateness.as_millis() / self.step_duration.as_millis()) as u32;
+    self.ticks += missed + 1;
+    let next = self.origin + self.step_duration * self.ticks;
     self.delay.as_mut().reset(next);
 
     let actions = std::mem::replace(&mut self.actions, vec![]);
     let actions_bytes_len = actions.iter().map(|a| a.byte_len()).sum();
-    let tick = Tick {
-      time_increment_ms: self.step + delay,
+    self.player_actions.clear();
+    Poll::Ready(Some(Tick {
+      time_increment_ms,
       actions,
       actions_bytes_len,
-    };
-    Poll::Ready(Some(tick))
+      dropped_actions: std::mem::take(&mut self.dropped_actions),
+    }))
   }
 }
+
+#[test]
+fn test_step_range() {
+  let range = StepRange::default();
+  assert_eq!(range.clamp(1), ActionTickStream::MIN_STEP);
+  assert_eq!(range.clamp(1000), ActionTickStream::MAX_STEP);
+
+  let range = StepRange::new(Some(40), Some(60));
+  assert_eq!(range.clamp(30), 40);
+  assert_eq!(range.clamp(50), 50);
+  assert_eq!(range.clamp(100), 60);
+  assert!(!range.is_locked());
+
+  let range = StepRange::new(Some(50), Some(20));
+  assert!(range.is_locked());
+  assert_eq!(range.clamp(30), 50);
+
+  assert_eq!(StepRange::new(Some(1), None), StepRange::default());
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_action_tick_stream_schedule() {
+  use futures::StreamExt;
+
+  let mut stream = ActionTickStream::new(30, StepRange::default());
+  let start = Instant::now();
+  let mut total = 0;
+  for _ in 0..10 {
+    let tick = stream.next().await.unwrap();
+    total += tick.time_increment_ms as u64;
+    // simulates slow processing
+    tokio::time::advance(Duration::from_millis(7)).await;
+  }
+  assert_eq!(
+    start + Duration::from_millis(300),
+    Instant::now() - Duration::from_millis(7)
+  );
+  assert_eq!(total, 300);
+
+  tokio::time::advance(Duration::from_millis(100)).await;
+  let tick = stream.next().await.unwrap();
+  assert_eq!(tick.time_increment_ms, 107);
+  let tick = stream.next().await.unwrap();
+  assert_eq!(tick.time_increment_ms, 13);
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_action_tick_stream_max_player_actions() {
+  use bytes::Bytes;
+  use futures::StreamExt;
+
+  let action = |player_id: u8, len: usize| PlayerAction {
+    player_id,
+    data: Bytes::from(vec![player_id; len]),
+  };
+
+  let mut stream = ActionTickStream::new(30, StepRange::default());
+  stream.set_max_player_actions(2);
+  for _ in 0..3 {
+    stream.add_action(action(1, 10));
+  }
+  stream.add_action(action(1, ActionTickStream::MAX_COALESCED_ACTION_LEN));
+  stream.add_action(action(2, 10));
+
+  let tick = stream.next().await.unwrap();
+  assert_eq!(tick.actions.len(), 3);
+  assert_eq!(tick.actions[1].data.len(), 20);
+  assert_eq!(tick.dropped_actions.get(&1), Some(&1));
+  assert_eq!(tick.dropped_actions.get(&2), None);
+
+  stream.add_action(action(1, 10));
+  let tick = stream.next().await.unwrap();
+  assert_eq!(tick.actions.len(), 1);
+  assert!(tick.dropped_actions.is_empty());
+}
@@ -1,8 +1,13 @@
+use super::analyzer::ActionAnalyzer;
 use super::broadcast;
-use super::clock::ActionTickStream;
+use super::clock::{ActionTickStream, StepRange};
 use super::delay::{DelayedFrame, DelayedFrameStream};
+use super::equalize::PingEqualizer;
+use super::flood::{ChatFloodCheck, ChatFloodGuard};
 use super::player::{PlayerDispatchInfo, PlayerSendError};
+use super::stats::GameStats;
 use super::sync::SyncMap;
+use crate::config::SlowPlayerPolicy;
 use crate::error::*;
 use crate::game::host::clock::Tick;
 use crate::game::host::stream::{PlayerStream, PlayerStreamCmd, PlayerStreamHandle};
@@ -12,14 +17,18 @@ use crate::game::{
   SlotClientStatusUpdateSource,
 };
 use crate::observer::ObserverPublisherHandle;
+use bytes::Bytes;
 use flo_net::packet::{Frame, PacketTypeId};
 use flo_net::ping::{PingMsg, PingStream};
+use flo_net::proto::flo_node::{
+  ChatLogEntry, ChatScope, PacketNodeActionIncident, PacketNodeGameChatLog, PacketNodeGameStats,
+};
 use flo_net::w3gs::{W3GSFrameExt, W3GSMetadata, W3GSPacket, W3GSPacketTypeId};
 use flo_observer::record::{RTTStats, RTTStatsItem};
 use flo_util::chat::{parse_chat_command, ChatCommand};
 use flo_w3gs::action::{IncomingAction, IncomingAction2, OutgoingKeepAlive};
 use flo_w3gs::protocol::action::{OutgoingAction, PlayerAction, TimeSlot};
-use flo_w3gs::protocol::chat::ChatToHost;
+use flo_w3gs::protocol::chat::{ChatMessage, ChatToHost, MessageScope};
 use flo_w3gs::protocol::constants::LeaveReason;
 use flo_w3gs::protocol::lag::{LagPlayer, StartLag, StopLag};
 use flo_w3gs::protocol::leave::LeaveReq;
@@ -30,7 +39,7 @@ use parking_lot::Mutex;
 use s2_grpc_utils::S2ProtoEnum;
 use std::collections::{BTreeMap, BTreeSet, VecDeque};
 use std::sync::Arc;
-use std::time::{Duration, Instant};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use tokio::sync::mpsc::error::TrySendError;
 use tokio::sync::mpsc::{channel, Receiver, Sender};
 use tokio::sync::{oneshot, watch, Notify};
@@ -50,6 +59,10 @@ pub enum Cmd {
     player_id: i32,
     leave_reason: Option<LeaveReason>,
   },
+  UpdatePlayerBans {
+    player_id: i32,
+    ban_list: Vec<PlayerBanType>,
+  },
 }
 
 enum PeerMsg {
@@ -88,6 +101,7 @@ pub struct Dispatcher {
   ct: CancellationToken,
   cmd_tx: Sender<Cmd>,
   start_notify: Arc<Notify>,
+  shared: Arc<Mutex<Shared>>,
 }
 
 impl Drop for Dispatcher {
@@ -100,6 +114,8 @@ impl Dispatcher {
   pub fn new(
     game_id: i32,
     slots: &[PlayerSlot],
+    equalize_ping: bool,
+    step_range: StepRange,
     obs: ObserverPublisherHandle,
     out_tx: GameEventSender,
   ) -> Self {
@@ -112,6 +128,7 @@ impl Dispatcher {
     let state = State::new(
       game_id,
       slots,
+      equalize_ping,
       obs.clone(),
       status_rx,
       action_tx.clone(),
@@ -126,10 +143,17 @@ impl Dispatcher {
       }
       start_messages.push(format!("Some players in this game have been muted: {}", chat_banned_player_names.join(", ")));
     }
+    if step_range.is_locked() {
+      start_messages.push(format!("Game step is locked to {}ms.", step_range.clamp(0)));
+    }
+    if equalize_ping {
+      start_messages.push("Ping equalization is enabled, players with a lower latency will be delayed.".to_string());
+    }
 
     tokio::spawn(
       Self::tick(
         game_id,
+        step_range,
         state.shared.clone(),
         start_messages,
         start_notify.clone(),
@@ -140,6 +164,7 @@ impl Dispatcher {
       .instrument(tracing::debug_span!("tick", game_id)),
     );
 
+    let shared = state.shared.clone();
     tokio::spawn(
       Self::serve(state, cmd_rx, action_tx, out_tx, ct.clone())
         .instrument(tracing::debug_span!("serve", game_id)),
@@ -150,6 +175,19 @@ impl Dispatcher {
       game_id,
       cmd_tx,
       start_notify,
+      shared,
+    }
+  }
+
+  pub fn stats(&self) -> PacketNodeGameStats {
+    let guard = self.shared.lock();
+    guard.stats.to_packet(self.game_id, guard.sync.time())
+  }
+
+  pub fn chat_log(&self) -> PacketNodeGameChatLog {
+    PacketNodeGameChatLog {
+      game_id: self.game_id,
+      entries: self.shared.lock().chat_log.clone(),
     }
   }
 
@@ -184,6 +222,22 @@ impl Dispatcher {
     Ok(())
   }
 
+  pub async fn update_player_bans(
+    &self,
+    player_id: i32,
+    ban_list: Vec<PlayerBanType>,
+  ) -> Result<()> {
+    self
+      .cmd_tx
+      .send(Cmd::UpdatePlayerBans {
+        player_id,
+        ban_list,
+      })
+      .await
+      .map_err(|_| Error::Cancelled)?;
+    Ok(())
+  }
+
   async fn serve(
     mut state: State,
     mut rx: Receiver<Cmd>,
@@ -226,6 +280,7 @@ impl Dispatcher {
 
   async fn tick(
     game_id: i32,
+    step_range: StepRange,
     shared: Arc<Mutex<Shared>>,
     start_messages: Vec<String>,
     start_notify: Arc<Notify>,
@@ -251,7 +306,16 @@ impl Dispatcher {
         }
       }
 
-      let mut tick_stream = ActionTickStream::new(*crate::constants::GAME_DEFAULT_STEP_MS);
+      let mut tick_stream = ActionTickStream::new(crate::config::current().game_step_ms, step_range);
+      tick_stream.set_max_player_actions(crate::config::current().game_max_player_actions_per_tick);
+      shared.lock().step = tick_stream.step();
+      let mut referee_paused = false;
+      let equalize_ping = shared.lock().equalizer.is_some();
+      let mut equalize_interval = interval_at(
+        tokio::time::Instant::now() + crate::constants::PING_EQUALIZE_INTERVAL,
+        crate::constants::PING_EQUALIZE_INTERVAL,
+      );
+      equalize_interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
       let pause_timeout = sleep(Duration::from_secs(0));
       tokio::pin!(pause_timeout);
 
@@ -291,12 +355,12 @@ impl Dispatcher {
               }
               ActionMsg::SetStep(step) => {
                 tick_stream.set_step(step);
-                shared
-                  .lock()
-                  .broadcast_message(format!("Game step has been set to {}ms.", tick_stream.step()));
+                let mut shared = shared.lock();
+                shared.step = tick_stream.step();
+                shared.broadcast_message(format!("Game step has been set to {}ms.", tick_stream.step()));
               },
               ActionMsg::CheckStopLag => {
-                if tick_stream.is_paused() {
+                if tick_stream.is_paused() && !referee_paused {
                   match shared.lock().check_stop_lag() {
                     Ok(true) => {
                       tick_stream.resume();
@@ -318,17 +382,42 @@ impl Dispatcher {
                   game_id,
                   "resume clock"
                 );
-                tick_stream.resume();
-                status_tx.send(DispatchStatus::Running).ok();
+                if !referee_paused {
+                  tick_stream.resume();
+                  status_tx.send(DispatchStatus::Running).ok();
+                }
+              }
+              ActionMsg::RefereePause => {
+                if !referee_paused {
+                  tracing::info!(game_id, "referee pause");
+                  referee_paused = true;
+                  tick_stream.pause();
+                  status_tx.send(DispatchStatus::Paused).ok();
+                }
+              }
+              ActionMsg::RefereeResume => {
+                if referee_paused {
+                  tracing::info!(game_id, "referee resume");
+                  referee_paused = false;
+                  if shared.lock().lagging_player_ids.is_empty() {
+                    tick_stream.resume();
+                    status_tx.send(DispatchStatus::Running).ok();
+                  } else {
+                    pause_timeout.as_mut().reset(tokio::time::Instant::now() + crate::config::current().game_clock_max_pause());
+                  }
+                }
               }
             }
           }
           Some(tick) = tick_stream.next() => {
             match shared.lock().dispatch_action_tick(tick) {
               Ok(DispatchResult::Continue) => {},
+              Ok(DispatchResult::Congested(tick)) => {
+                tick_stream.requeue_actions(tick.actions);
+              }
               Ok(DispatchResult::Lag(tick)) => {
                 tick_stream.replace_actions(tick.actions);
-                pause_timeout.as_mut().reset((Instant::now() + crate::constants::GAME_CLOCK_MAX_PAUSE).into());
+                pause_timeout.as_mut().reset(tokio::time::Instant::now() + crate::config::current().game_clock_max_pause());
                 tick_stream.pause();
                 status_tx.send(DispatchStatus::Paused).ok();
               }
@@ -341,7 +430,12 @@ impl Dispatcher {
               }
             }
           }
-          _ = &mut pause_timeout, if tick_stream.is_paused() => {
+          _ = equalize_interval.tick(), if equalize_ping && !tick_stream.is_paused() => {
+            shared
+              .lock()
+              .equalize_ping(Duration::from_millis(tick_stream.step() as u64));
+          }
+          _ = &mut pause_timeout, if tick_stream.is_paused() && !referee_paused => {
             if let Err(err) = shared.lock().drop_all_lag_players() {
               tracing::error!(
                 game_id,
@@ -363,6 +457,8 @@ enum ActionMsg {
   SetStep(u16),
   CheckStopLag,
   ResumeClock,
+  RefereePause,
+  RefereeResume,
 }
 
 #[derive(Debug)]
@@ -375,12 +471,17 @@ struct State {
   _player_name_lookup: BTreeMap<i32, String>,
   chat_banned_player_ids: Vec<i32>,
   left_players: BTreeSet<i32>,
+  observer_player_ids: BTreeSet<i32>,
+  referee_player_ids: BTreeSet<i32>,
+  player_team_lookup: BTreeMap<i32, i32>,
+  chat_flood: ChatFloodGuard,
 }
 
 impl State {
   fn new(
     game_id: i32,
     slots: &[PlayerSlot],
+    equalize_ping: bool,
     obs: ObserverPublisherHandle,
     status_rx: watch::Receiver<DispatchStatus>,
     _action_tx: Sender<ActionMsg>,
@@ -389,7 +490,7 @@ impl State {
     State {
       game_id,
       ct,
-      shared: Arc::new(Mutex::new(Shared::new(game_id, slots, obs))),
+      shared: Arc::new(Mutex::new(Shared::new(game_id, slots, equalize_ping, obs))),
       status_rx,
       game_player_id_lookup: slots
         .into_iter()
@@ -410,6 +511,22 @@ impl State {
         })
         .collect(),
       left_players: BTreeSet::new(),
+      observer_player_ids: slots
+        .into_iter()
+        .filter(|slot| slot.settings.team == 24)
+        .map(|slot| slot.player.player_id)
+        .collect(),
+      referee_player_ids: slots
+        .into_iter()
+        .filter(|slot| slot.settings.team == 24 && slot.referee)
+        .map(|slot| slot.player.player_id)
+        .collect(),
+      player_team_lookup: slots
+        .into_iter()
+        .filter(|slot| slot.settings.team != 24)
+        .map(|slot| (slot.player.player_id, slot.settings.team))
+        .collect(),
+      chat_flood: ChatFloodGuard::default(),
     }
   }
 
@@ -443,11 +560,43 @@ impl State {
           tracing::error!(game_id = self.game_id, player_id, "send shutdown: {}", err);
         }
       }
+      Cmd::UpdatePlayerBans {
+        player_id,
+        ban_list,
+      } => {
+        self.update_player_bans(player_id, ban_list);
+      }
     }
 
     Ok(())
   }
 
+  fn update_player_bans(&mut self, player_id: i32, ban_list: Vec<PlayerBanType>) {
+    let chat_banned = ban_list.contains(&PlayerBanType::Chat);
+    if chat_banned == self.chat_banned_player_ids.contains(&player_id) {
+      return;
+    }
+    tracing::info!(
+      game_id = self.game_id,
+      player_id,
+      chat_banned,
+      "player bans updated"
+    );
+    if chat_banned {
+      self.chat_banned_player_ids.push(player_id);
+      self
+        .shared
+        .lock()
+        .private_message(player_id, "You have been muted.");
+    } else {
+      self.chat_banned_player_ids.retain(|id| *id != player_id);
+      self
+        .shared
+        .lock()
+        .private_message(player_id, "You are no longer muted.");
+    }
+  }
+
   async fn register_stream(
     &mut self,
     stream: PlayerStream,
@@ -580,6 +729,7 @@ impl State {
           ClosePlayerStreamResult::ClosedDisconnected => SlotClientStatus::Disconnected,
           ClosePlayerStreamResult::ClosedLeft => {
             self.left_players.insert(player_id);
+            self.shared.lock().record_leave(player_id, None);
             SlotClientStatus::Left
           }
           ClosePlayerStreamResult::Skipped => {
@@ -598,6 +748,7 @@ impl State {
               stream_id,
               "lagging player stream closed"
             );
+            self.shared.lock().record_leave(player_id, None);
             action_tx
               .send(ActionMsg::CheckStopLag)
               .await
@@ -656,7 +807,7 @@ impl State {
     meta: W3GSMetadata,
     packet: Packet,
     action_tx: &mut Sender<ActionMsg>,
-    _out_tx: &mut GameEventSender,
+    out_tx: &mut GameEventSender,
   ) -> Result<()> {
     use flo_w3gs::protocol::constants::PacketTypeId;
 
@@ -681,6 +832,14 @@ impl State {
     match packet.type_id() {
       PacketTypeId::OutgoingAction => {
         let payload: OutgoingAction = packet.decode_payload()?;
+        let incident = {
+          let mut shared = self.shared.lock();
+          shared.stats.record_actions(player_id, &payload.data);
+          shared.check_actions(player_id, &payload.data)
+        };
+        if let Some(incident) = incident {
+          out_tx.send(GameEvent::ActionIncident(incident)).await.ok();
+        }
         action_tx
           .send(ActionMsg::PlayerAction(PlayerAction {
             player_id: slot_player_id,
@@ -746,6 +905,7 @@ impl State {
 
     let should_check_lag = {
       let mut guard = self.shared.lock();
+      guard.record_leave(player_id, reason);
       let player = guard
         .get_player(player_id)
         .ok_or_else(|| Error::PlayerNotFoundInGame)?;
@@ -805,6 +965,27 @@ impl State {
     use flo_w3gs::protocol::constants::PacketTypeId;
 
     let chat: ChatToHost = packet.decode_simple()?;
+
+    // commands count towards the flood limit
+    if chat.is_in_game_chat() {
+      let config = crate::config::current();
+      match self.chat_flood.check(
+        player_id,
+        config.chat_flood_max_messages,
+        config.chat_flood_window(),
+      ) {
+        ChatFloodCheck::Allow => {}
+        ChatFloodCheck::DropNotify => {
+          self.shared.lock().private_message(
+            player_id,
+            "You are sending messages too fast, your messages are not delivered.",
+          );
+          return Ok(());
+        }
+        ChatFloodCheck::Drop => return Ok(()),
+      }
+    }
+
     if let Some(cmd) = chat.chat_message().and_then(parse_chat_command) {
       if self.handle_command(action_tx, player_id, cmd).await? {
         return Ok(());
@@ -815,9 +996,31 @@ impl State {
       return Ok(());
     }
 
+    // observers only talk to each other until the game is decided, referees talk to everyone
+    let observer_scoped = self.observer_player_ids.contains(&player_id)
+      && !self.referee_player_ids.contains(&player_id)
+      && !self.is_observer_all_chat();
+
     packet.header.type_id = PacketTypeId::ChatFromHost;
     {
       let mut guard = self.shared.lock();
+      if let ChatMessage::Scoped { scope, ref message } = chat.message {
+        let (scope, to_player_id) = match scope {
+          MessageScope::All => (ChatScope::All, None),
+          MessageScope::Allies => (ChatScope::Allies, None),
+          MessageScope::Observers => (ChatScope::Observers, None),
+          MessageScope::Player(id) => (
+            ChatScope::Private,
+            self.game_player_id_lookup.get(&id).cloned(),
+          ),
+        };
+        guard.record_chat(
+          player_id,
+          scope,
+          to_player_id,
+          message.to_string_lossy().to_string(),
+        );
+      }
       guard.obs.push_w3gs(self.game_id, packet.clone());
       guard.broadcast(
         packet,
@@ -827,7 +1030,7 @@ impl State {
             .into_iter()
             .filter_map(|id| {
               if let Some(id) = self.game_player_id_lookup.get(&id).cloned() {
-                if id != player_id {
+                if id != player_id && (!observer_scoped || self.observer_player_ids.contains(&id)) {
                   Some(id)
                 } else {
                   None
@@ -843,6 +1046,21 @@ impl State {
     Ok(())
   }
 
+  /// Observer chat is opened to players once at most one team remains in game,
+  /// if enabled by `observer_all_chat_after_end`
+  fn is_observer_all_chat(&self) -> bool {
+    if !crate::config::current().observer_all_chat_after_end {
+      return false;
+    }
+    let remaining_teams: BTreeSet<i32> = self
+      .player_team_lookup
+      .iter()
+      .filter(|(player_id, _)| !self.left_players.contains(player_id))
+      .map(|(_, team)| *team)
+      .collect();
+    remaining_teams.len() <= 1
+  }
+
   async fn handle_command(
     &self,
     action_tx: &mut Sender<ActionMsg>,
@@ -875,7 +1093,17 @@ impl State {
       }
       "delay" => {
         if let Some(Some((ms,))) = cmd.parse_arguments::<Option<(u16,)>>().ok() {
-          let [min, max] = crate::constants::GAME_DELAY_RANGE;
+          if self.shared.lock().equalizer.is_some() {
+            self.shared.lock().private_message(
+              player_id,
+              format!(
+                "Delay is managed by ping equalization in this game, {}ms ignored.",
+                ms
+              ),
+            );
+            return Ok(true);
+          }
+          let [min, max] = crate::config::current().game_delay_range();
 
           if ms == 0 {
             let mut guard = self.shared.lock();
@@ -930,20 +1158,33 @@ impl State {
           }
         } else {
           let mut lock = self.shared.lock();
-          let msgs: Vec<_> = lock
-            .map
-            .values()
-            .map(|v| {
-              format!(
-                "{}: {}",
-                v.player_name(),
-                match v.delay() {
-                  Some(v) => format!("+{}ms", v.as_millis()),
-                  None => "Not set".to_string(),
-                }
-              )
-            })
-            .collect();
+          let equalized = lock.equalizer.is_some();
+          let mut msgs = vec![format!("Game step: {}ms", lock.step)];
+          if equalized {
+            msgs.push("Ping equalization is enabled for this game.".to_string());
+          }
+          if let Some(player) = lock.map.get(&player_id) {
+            let rtt = player.rtt().map(|v| v.avg).unwrap_or_default();
+            let delay = player.delay().map(|v| v.as_millis()).unwrap_or_default();
+            msgs.push(format!(
+              "Your input delay: ~{:.0}ms ({}ms step + {:.0}ms RTT + {}ms delay)",
+              lock.step as f32 + rtt + delay as f32,
+              lock.step,
+              rtt,
+              delay
+            ));
+          }
+          msgs.extend(lock.map.values().map(|v| {
+            format!(
+              "{}: {}",
+              v.player_name(),
+              match v.delay() {
+                Some(v) if equalized => format!("+{}ms (equalization)", v.as_millis()),
+                Some(v) => format!("+{}ms", v.as_millis()),
+                None => "Not set".to_string(),
+              }
+            )
+          }));
           if let Some(player) = lock.get_player(player_id) {
             for msg in msgs {
               player.send_private_message(&msg);
@@ -1016,6 +1257,44 @@ impl State {
             .private_message(player_id, "Invalid syntax, usage: !step 30");
         }
       },
+      "pause" | "resume" if self.referee_player_ids.contains(&player_id) => {
+        let (msg, action) = if cmd.name() == "pause" {
+          ("paused", ActionMsg::RefereePause)
+        } else {
+          ("resumed", ActionMsg::RefereeResume)
+        };
+        action_tx.send(action).await.map_err(|_| Error::Cancelled)?;
+        let mut guard = self.shared.lock();
+        let name = guard
+          .get_player(player_id)
+          .map(|player| player.player_name().to_string())
+          .unwrap_or_default();
+        guard.broadcast_message(format!("Game {} by referee {}", msg, name));
+      }
+      "time" => {
+        let mut lock = self.shared.lock();
+        let mut msgs = vec![format!(
+          "Game time: {}",
+          format_duration(Duration::from_millis(lock.sync.time() as u64))
+        )];
+        if let Some(started_at) = lock.started_at {
+          let since_epoch = started_at
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+          let secs_of_day = since_epoch % 86400;
+          msgs.push(format!(
+            "Started at: {:02}:{:02}:{:02} UTC, {} ago",
+            secs_of_day / 3600,
+            secs_of_day % 3600 / 60,
+            secs_of_day % 60,
+            format_duration(started_at.elapsed().unwrap_or_default())
+          ));
+        }
+        for msg in msgs {
+          lock.private_message(player_id, msg);
+        }
+      }
       "sync" if debug => {
         tracing::debug!("{}", self.shared.lock().sync.debug_pending());
       }
@@ -1029,21 +1308,33 @@ impl State {
 struct Shared {
   game_id: i32,
   started: bool,
+  started_at: Option<SystemTime>,
   map: BTreeMap<i32, PlayerDispatchInfo>,
   slot_id_lookup: BTreeMap<i32, u8>,
   sync: SyncMap,
   lagging_player_ids: BTreeSet<i32>,
   drop_votes: BTreeSet<i32>,
+  stats: GameStats,
+  analyzer: Option<ActionAnalyzer>,
+  chat_log: Vec<ChatLogEntry>,
+  step: u16,
+  equalizer: Option<PingEqualizer>,
   obs: ObserverPublisherHandle,
 }
 
 impl Shared {
-  fn new(game_id: i32, slots: &[PlayerSlot], obs: ObserverPublisherHandle) -> Self {
+  fn new(
+    game_id: i32,
+    slots: &[PlayerSlot],
+    equalize_ping: bool,
+    obs: ObserverPublisherHandle,
+  ) -> Self {
     let sync = SyncMap::new(slots.iter().map(|s| s.player.player_id).collect());
     let mut slot_id_lookup = BTreeMap::new();
     Self {
       game_id,
       started: false,
+      started_at: None,
       map: slots
         .into_iter()
         .map(|slot| {
@@ -1056,12 +1347,81 @@ impl Shared {
       sync,
       lagging_player_ids: BTreeSet::new(),
       drop_votes: BTreeSet::new(),
+      stats: GameStats::new(
+        slots
+          .iter()
+          .filter(|slot| slot.settings.team != 24)
+          .map(|slot| slot.player.player_id),
+      ),
+      analyzer: {
+        let config = crate::config::current();
+        if config.action_analyzer {
+          Some(ActionAnalyzer::new(
+            slots
+              .iter()
+              .filter(|slot| slot.settings.team != 24)
+              .map(|slot| slot.player.player_id),
+            config.action_analyzer_max_actions_per_second,
+          ))
+        } else {
+          None
+        }
+      },
+      chat_log: vec![],
+      step: crate::config::current().game_step_ms,
+      equalizer: if equalize_ping {
+        Some(PingEqualizer::new(
+          slots
+            .iter()
+            .filter(|slot| slot.settings.team != 24)
+            .map(|slot| slot.player.player_id),
+          crate::config::current().game_delay_range()[1],
+        ))
+      } else {
+        None
+      },
       obs,
     }
   }
 
+  fn record_chat(
+    &mut self,
+    player_id: i32,
+    scope: ChatScope,
+    to_player_id: Option<i32>,
+    message: String,
+  ) {
+    if self.chat_log.len() >= crate::constants::CHAT_LOG_MAX_ENTRIES {
+      return;
+    }
+    self.chat_log.push(ChatLogEntry {
+      player_id,
+      scope: scope.into(),
+      to_player_id,
+      time_ms: self.sync.time(),
+      message,
+    });
+  }
+
+  fn check_actions(&mut self, player_id: i32, data: &Bytes) -> Option<PacketNodeActionIncident> {
+    let incident = self.analyzer.as_mut()?.check(player_id, data)?;
+    Some(PacketNodeActionIncident {
+      game_id: self.game_id,
+      player_id: incident.player_id,
+      kind: incident.kind.into(),
+      time_ms: self.sync.time(),
+      detail: incident.detail,
+    })
+  }
+
+  fn record_leave(&mut self, player_id: i32, reason: Option<LeaveReason>) {
+    let time = self.sync.time();
+    self.stats.record_leave(player_id, time, reason);
+  }
+
   fn set_started(&mut self) {
     self.started = true;
+    self.started_at = Some(SystemTime::now());
   }
 
   fn get_player(&mut self, player_id: i32) -> Option<&mut PlayerDispatchInfo> {
@@ -1070,6 +1430,51 @@ impl Shared {
 
   #[must_use]
   pub fn dispatch_action_tick(&mut self, mut tick: Tick) -> Result<DispatchResult> {
+    let slow_player_ids = self.check_send_queues();
+    if !slow_player_ids.is_empty() {
+      match crate::config::current().game_slow_player_policy {
+        SlowPlayerPolicy::Lag => {
+          let player_ids: Vec<_> = slow_player_ids
+            .into_iter()
+            .filter(|player_id| !self.lagging_player_ids.contains(player_id))
+            .collect();
+          if !player_ids.is_empty() {
+            tracing::warn!(
+              game_id = self.game_id,
+              "slow players lagging: {:?}",
+              player_ids
+            );
+            if self.handle_lag(player_ids)? {
+              return Ok(DispatchResult::Lag(tick));
+            }
+          }
+        }
+        SlowPlayerPolicy::Pause => {
+          return Ok(DispatchResult::Congested(tick));
+        }
+        SlowPlayerPolicy::Drop => {
+          for player_id in slow_player_ids {
+            tracing::warn!(game_id = self.game_id, player_id, "slow player dropped");
+            self.remove_player_and_broadcast(player_id, None)?;
+          }
+        }
+      }
+    }
+
+    for (slot_player_id, count) in &tick.dropped_actions {
+      let player_id = self
+        .slot_id_lookup
+        .iter()
+        .find(|(_, id)| *id == slot_player_id)
+        .map(|(player_id, _)| *player_id);
+      tracing::warn!(
+        game_id = self.game_id,
+        ?player_id,
+        "too many actions in a tick, {} dropped",
+        count
+      );
+    }
+
     let time_increment_ms = tick.time_increment_ms;
     if let ClockResult::Lag(timeouts) = self.sync.clock(time_increment_ms) {
       let player_ids: Vec<_> = timeouts.into_iter().map(|t| t.player_id).collect();
@@ -1137,6 +1542,54 @@ impl Shared {
     Ok(DispatchResult::Continue)
   }
 
+  /// Returns the players whose send queue is over `game_send_queue_threshold`
+  fn check_send_queues(&mut self) -> Vec<i32> {
+    let threshold = crate::config::current().game_send_queue_threshold;
+    self
+      .map
+      .iter()
+      .filter_map(|(player_id, info)| {
+        let len = info.send_queue_len()?;
+        crate::metrics::PLAYER_SEND_QUEUE_DEPTH.observe(len as f64);
+        if len >= threshold {
+          Some(*player_id)
+        } else {
+          None
+        }
+      })
+      .collect()
+  }
+
+  fn equalize_ping(&mut self, resolution: Duration) {
+    let delays = match self.equalizer.as_ref() {
+      Some(equalizer) => equalizer.compute(
+        self
+          .map
+          .iter()
+          .filter(|(_, info)| info.is_connected())
+          .filter_map(|(player_id, info)| info.rtt().map(|rtt| (*player_id, rtt.avg))),
+        resolution,
+      ),
+      None => return,
+    };
+
+    for (player_id, delay) in delays {
+      if let Some(info) = self.map.get_mut(&player_id) {
+        if !PingEqualizer::should_update(info.delay().cloned(), delay, resolution) {
+          continue;
+        }
+        if let Err(err) = info.set_delay(delay) {
+          tracing::warn!(
+            game_id = self.game_id,
+            player_id,
+            "equalize ping: {}",
+            err
+          );
+        }
+      }
+    }
+  }
+
   fn push_rtt_stats(&mut self, time: u32) {
     let items = self.map.iter_mut().map(|(id, info)| {
       let stats = info.take_rtt();
@@ -1596,9 +2049,11 @@ impl PeerWorker {
     }
 
     let mut delay_buf = VecDeque::new();
+    let mut send_buf = Vec::with_capacity(crate::constants::PEER_SEND_BATCH_SIZE);
+    let mut pending_cmd = None;
     let mut ping = PingStream::interval(
       crate::constants::GAME_PING_INTERVAL,
-      crate::constants::GAME_PING_TIMEOUT,
+      crate::config::current().game_ping_timeout(),
     );
     let mut last_status = *self.status_rx.borrow();
 
@@ -1636,6 +2091,7 @@ impl PeerWorker {
         next = self.stream.get_mut().recv_frame() => {
           match next {
             Ok(frame) => {
+              crate::metrics::PLAYER_BYTES_IN.inc_by(frame.payload.len() as u64);
               match frame.type_id {
                 PingStream::PONG_TYPE_ID => {
                   if ping.started() {
@@ -1687,14 +2143,32 @@ impl PeerWorker {
             }
           }
         }
-        Some(cmd) = self.in_rx.recv() => {
+        Some(cmd) = next_cmd(&mut pending_cmd, &mut self.in_rx) => {
           match cmd {
             PlayerStreamCmd::Send(frame) => {
               if self.delay.enabled() {
                 self.delay.insert(DelayedFrame::Out(frame));
                 continue;
               }
-              self.stream.get_mut().send_frame(frame).await?;
+              // write frames queued in the same tick at once
+              send_buf.push(frame);
+              while send_buf.len() < crate::constants::PEER_SEND_BATCH_SIZE {
+                match self.in_rx.try_recv() {
+                  Ok(PlayerStreamCmd::Send(frame)) => send_buf.push(frame),
+                  Ok(cmd) => {
+                    pending_cmd = Some(cmd);
+                    break;
+                  }
+                  Err(_) => break,
+                }
+              }
+              crate::metrics::PLAYER_BYTES_OUT
+                .inc_by(send_buf.iter().map(|frame| frame.payload.len() as u64).sum());
+              if send_buf.len() == 1 {
+                self.stream.get_mut().send_frame(send_buf.remove(0)).await?;
+              } else {
+                self.stream.get_mut().send_frames(send_buf.drain(..)).await?;
+              }
             }
             PlayerStreamCmd::SetDelay(delay) => {
               let expired = if let Some(delay) = delay {
@@ -1839,6 +2313,13 @@ impl PeerWorker {
       }
     }
     if out_buf_write {
+      crate::metrics::PLAYER_BYTES_OUT.inc_by(
+        self
+          .delay_send_buf
+          .iter()
+          .map(|frame| frame.payload.len() as u64)
+          .sum(),
+      );
       self
         .stream
         .get_mut()
@@ -1858,10 +2339,22 @@ impl PeerWorker {
   }
 }
 
+async fn next_cmd(
+  pending: &mut Option<PlayerStreamCmd>,
+  rx: &mut Receiver<PlayerStreamCmd>,
+) -> Option<PlayerStreamCmd> {
+  if let Some(cmd) = pending.take() {
+    return Some(cmd);
+  }
+  rx.recv().await
+}
+
 #[derive(Debug)]
 enum DispatchResult {
   Continue,
   Lag(Tick),
+  /// Held back until slow players catch up
+  Congested(Tick),
 }
 
 #[derive(Debug)]
@@ -1871,3 +2364,20 @@ enum ClosePlayerStreamResult {
   ClosedLagging,
   Skipped,
 }
+
+/// Formats as `m:ss` or `h:mm:ss`
+fn format_duration(d: Duration) -> String {
+  let secs = d.as_secs();
+  if secs >= 3600 {
+    format!("{}:{:02}:{:02}", secs / 3600, secs % 3600 / 60, secs % 60)
+  } else {
+    format!("{}:{:02}", secs / 60, secs % 60)
+  }
+}
+
+#[test]
+fn test_format_duration() {
+  assert_eq!(format_duration(Duration::from_millis(59_999)), "0:59");
+  assert_eq!(format_duration(Duration::from_secs(754)), "12:34");
+  assert_eq!(format_duration(Duration::from_secs(3723)), "1:02:03");
+}
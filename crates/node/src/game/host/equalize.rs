@@ -0,0 +1,93 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::Duration;
+
+/// Delays low latency players so that every player of the game
+/// experiences roughly the same round trip time
+#[derive(Debug)]
+pub struct PingEqualizer {
+  player_ids: BTreeSet<i32>,
+  max_delay: Duration,
+}
+
+impl PingEqualizer {
+  /// Observers are excluded from `player_ids`, they should neither be delayed
+  /// nor raise the delay of the other players
+  pub fn new<I: IntoIterator<Item = i32>>(player_ids: I, max_delay: Duration) -> Self {
+    Self {
+      player_ids: player_ids.into_iter().collect(),
+      max_delay,
+    }
+  }
+
+  /// Returns the delay of each player from their average RTT.
+  /// Differences smaller than `resolution`, usually the game step, are not compensated.
+  pub fn compute<I: IntoIterator<Item = (i32, f32)>>(
+    &self,
+    rtts: I,
+    resolution: Duration,
+  ) -> BTreeMap<i32, Option<Duration>> {
+    let rtts: Vec<_> = rtts
+      .into_iter()
+      .filter(|(player_id, _)| self.player_ids.contains(player_id))
+      .collect();
+    let target = rtts.iter().map(|(_, rtt)| *rtt).fold(0., f32::max);
+    rtts
+      .into_iter()
+      .map(|(player_id, rtt)| {
+        let diff = Duration::from_millis((target - rtt).max(0.) as u64);
+        let delay = if diff < resolution {
+          None
+        } else {
+          Some(std::cmp::min(diff, self.max_delay))
+        };
+        (player_id, delay)
+      })
+      .collect()
+  }
+
+  /// Returns `true` if the delay should be replaced by `next`
+  pub fn should_update(
+    current: Option<Duration>,
+    next: Option<Duration>,
+    resolution: Duration,
+  ) -> bool {
+    match (current, next) {
+      (Some(current), Some(next)) => {
+        let change = if current > next {
+          current - next
+        } else {
+          next - current
+        };
+        change >= resolution
+      }
+      (None, None) => false,
+      _ => true,
+    }
+  }
+}
+
+#[test]
+fn test_ping_equalizer() {
+  let step = Duration::from_millis(30);
+  let eq = PingEqualizer::new(vec![1, 2, 3], Duration::from_millis(100));
+
+  let delays = eq.compute(vec![(1, 20.), (2, 80.), (3, 70.), (4, 500.)], step);
+  assert_eq!(delays.len(), 3);
+  assert_eq!(delays[&1], Some(Duration::from_millis(60)));
+  assert_eq!(delays[&2], None);
+  assert_eq!(delays[&3], None);
+
+  let delays = eq.compute(vec![(1, 10.), (2, 300.)], step);
+  assert_eq!(delays[&1], Some(Duration::from_millis(100)));
+
+  assert!(!PingEqualizer::should_update(
+    Some(Duration::from_millis(60)),
+    Some(Duration::from_millis(70)),
+    step
+  ));
+  assert!(PingEqualizer::should_update(
+    Some(Duration::from_millis(60)),
+    None,
+    step
+  ));
+}
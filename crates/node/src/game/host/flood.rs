@@ -0,0 +1,79 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::time::{Duration, Instant};
+
+/// Per-player chat rate limit over a sliding window
+#[derive(Debug, Default)]
+pub struct ChatFloodGuard {
+  players: BTreeMap<i32, PlayerChat>,
+}
+
+#[derive(Debug, Default)]
+struct PlayerChat {
+  sent: VecDeque<Instant>,
+  notified: bool,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ChatFloodCheck {
+  Allow,
+  /// Dropped, the sender should be notified
+  DropNotify,
+  /// Dropped, the sender has been notified already
+  Drop,
+}
+
+impl ChatFloodGuard {
+  /// Records a message, `max_messages` of 0 disables the limit
+  pub fn check(&mut self, player_id: i32, max_messages: usize, window: Duration) -> ChatFloodCheck {
+    self.check_at(Instant::now(), player_id, max_messages, window)
+  }
+
+  fn check_at(
+    &mut self,
+    now: Instant,
+    player_id: i32,
+    max_messages: usize,
+    window: Duration,
+  ) -> ChatFloodCheck {
+    if max_messages == 0 {
+      return ChatFloodCheck::Allow;
+    }
+
+    let player = self.players.entry(player_id).or_default();
+    while let Some(t) = player.sent.front() {
+      if now.saturating_duration_since(*t) < window {
+        break;
+      }
+      player.sent.pop_front();
+    }
+
+    if player.sent.len() < max_messages {
+      player.sent.push_back(now);
+      player.notified = false;
+      ChatFloodCheck::Allow
+    } else if player.notified {
+      ChatFloodCheck::Drop
+    } else {
+      player.notified = true;
+      ChatFloodCheck::DropNotify
+    }
+  }
+}
+
+#[test]
+fn test_chat_flood_guard() {
+  let window = Duration::from_secs(5);
+  let mut guard = ChatFloodGuard::default();
+  let t = Instant::now();
+
+  assert_eq!(guard.check_at(t, 1, 2, window), ChatFloodCheck::Allow);
+  assert_eq!(guard.check_at(t, 1, 2, window), ChatFloodCheck::Allow);
+  assert_eq!(guard.check_at(t, 1, 2, window), ChatFloodCheck::DropNotify);
+  assert_eq!(guard.check_at(t, 1, 2, window), ChatFloodCheck::Drop);
+  assert_eq!(guard.check_at(t, 2, 2, window), ChatFloodCheck::Allow);
+  assert_eq!(
+    guard.check_at(t + window, 1, 2, window),
+    ChatFloodCheck::Allow
+  );
+  assert_eq!(guard.check_at(t, 1, 0, window), ChatFloodCheck::Allow);
+}
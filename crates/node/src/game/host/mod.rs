@@ -1,20 +1,27 @@
 use s2_grpc_utils::S2ProtoEnum;
 
+pub use clock::{ActionTickStream, StepRange};
 use dispatch::Dispatcher;
 use flo_net::packet::*;
 pub use sync::AckError;
 
 use crate::error::*;
 use crate::game::host::stream::{PlayerStream, PlayerStreamHandle};
-use crate::game::{GameEventSender, NodeGameStatusSnapshot, PlayerSlot};
+use crate::game::{GameEventSender, NodeGameStatusSnapshot, PlayerBanType, PlayerSlot};
 use crate::observer::ObserverPublisherHandle;
 use flo_w3gs::constants::LeaveReason;
 
+mod analyzer;
 mod broadcast;
 mod clock;
 mod delay;
 mod dispatch;
+mod equalize;
+mod flood;
 mod player;
+#[cfg(test)]
+mod sim;
+mod stats;
 pub mod stream;
 mod sync;
 
@@ -28,10 +35,19 @@ impl GameHost {
   pub fn new(
     game_id: i32,
     slots: &[PlayerSlot],
+    equalize_ping: bool,
+    step_range: StepRange,
     obs: ObserverPublisherHandle,
     event_sender: GameEventSender,
   ) -> Self {
-    let dispatcher = Dispatcher::new(game_id, slots, obs, event_sender);
+    let dispatcher = Dispatcher::new(
+      game_id,
+      slots,
+      equalize_ping,
+      step_range,
+      obs,
+      event_sender,
+    );
     Self {
       game_id,
       dispatcher,
@@ -42,6 +58,14 @@ impl GameHost {
     self.dispatcher.start();
   }
 
+  pub fn stats(&self) -> flo_net::proto::flo_node::PacketNodeGameStats {
+    self.dispatcher.stats()
+  }
+
+  pub fn chat_log(&self) -> flo_net::proto::flo_node::PacketNodeGameChatLog {
+    self.dispatcher.chat_log()
+  }
+
   pub async fn register_player_stream(
     &mut self,
     mut stream: PlayerStream,
@@ -55,6 +79,7 @@ impl GameHost {
           version: Some(crate::version::FLO_NODE_VERSION.into()),
           game_id: self.game_id,
           player_id,
+          capabilities: Some(flo_net::capability::Capabilities::local().into()),
           ..Default::default()
         };
         pkt.set_game_status(snapshot.game_status.into_proto_enum());
@@ -79,4 +104,15 @@ impl GameHost {
       .notify_player_shutdown(player_id, leave_reason)
       .await
   }
+
+  pub async fn update_player_bans(
+    &mut self,
+    player_id: i32,
+    ban_list: Vec<PlayerBanType>,
+  ) -> Result<()> {
+    self
+      .dispatcher
+      .update_player_bans(player_id, ban_list)
+      .await
+  }
 }
@@ -99,6 +99,14 @@ impl PlayerDispatchInfo {
     }
   }
 
+  pub fn is_connected(&self) -> bool {
+    self.tx.is_some()
+  }
+
+  pub fn send_queue_len(&self) -> Option<usize> {
+    self.tx.as_ref().map(|v| v.queue_len())
+  }
+
   pub fn stream_id(&self) -> Option<u64> {
     self.tx.as_ref().map(|v| v.stream_id())
   }
@@ -0,0 +1,563 @@
+//! Deterministic simulation of the game host.
+//!
+//! Drives a [`GameHost`] with tokio's clock paused: clients are scripted in-memory streams,
+//! virtual time is advanced in small increments and every packet the host sends to a client
+//! is recorded with the virtual time it was received at.
+//! Tests must run with `#[tokio::test(start_paused = true)]`.
+
+use super::stream::PlayerStream;
+use super::{GameHost, StepRange};
+use crate::error::*;
+use crate::game::{
+  Computer, GameEvent, GamePlayer, GameSlotSettings, NodeGameStatus, NodeGameStatusSnapshot,
+  PlayerSlot, Race, SlotClientStatus,
+};
+use crate::observer::ObserverPublisherHandle;
+use bytes::Bytes;
+use flo_net::packet::{Frame, PacketTypeId};
+use flo_net::ping::PingStream;
+use flo_net::stream::FloStream;
+use flo_net::w3gs::{W3GSAckQueue, W3GSFrameExt, W3GSMetadata, W3GSPacket, W3GSPacketTypeId};
+use flo_w3gs::action::{IncomingAction, IncomingAction2, OutgoingKeepAlive};
+use flo_w3gs::protocol::action::{OutgoingAction, TimeSlot};
+use flo_w3gs::protocol::constants::LeaveReason;
+use flo_w3gs::protocol::lag::{StartLag, StopLag};
+use flo_w3gs::protocol::leave::{LeaveReq, PlayerLeft};
+use flo_w3gs::protocol::packet::Header;
+use futures::FutureExt;
+use std::collections::{BTreeMap, HashMap};
+use std::time::Duration;
+use tokio::sync::mpsc::{channel, Receiver};
+use tokio::time::Instant;
+
+/// Virtual time advanced between two polls of the clients
+const RESOLUTION: Duration = Duration::from_millis(5);
+/// Times the simulation yields to the host tasks after each advance
+const SETTLE_YIELDS: usize = 32;
+/// Checksum sent by all clients, so no desync is ever detected
+const CHECKSUM: u32 = 0x464C4F;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SimEvent {
+  /// An `IncomingAction` packet, `actions` are `(slot player id, data)`
+  Tick {
+    time_increment_ms: u16,
+    actions: Vec<(u8, Bytes)>,
+  },
+  /// A fragment of an over-sized tick
+  TickFragment {
+    actions: Vec<(u8, Bytes)>,
+  },
+  StartLag(Vec<u8>),
+  StopLag(u8),
+  PlayerLeft(u8),
+  Chat,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SimRecord {
+  /// Virtual time since the simulation was created
+  pub time_ms: u64,
+  pub event: SimEvent,
+}
+
+#[derive(Debug)]
+struct SimPlayer {
+  slot_player_id: u8,
+  stream: Option<FloStream>,
+  ack_q: W3GSAckQueue,
+  unacked_ticks: usize,
+  stalled: bool,
+  records: Vec<SimRecord>,
+}
+
+#[derive(Debug)]
+pub struct SimGame {
+  game_id: i32,
+  host: GameHost,
+  started_at: Instant,
+  event_rx: Receiver<GameEvent>,
+  players: BTreeMap<i32, SimPlayer>,
+  status_changes: Vec<(i32, SlotClientStatus)>,
+}
+
+impl SimGame {
+  /// Creates a game with one slot per player, slot `i` is used by `player_ids[i]`
+  pub fn new(player_ids: &[i32]) -> Self {
+    let game_id = 1;
+    let slots: Vec<_> = player_ids
+      .iter()
+      .enumerate()
+      .map(|(i, player_id)| PlayerSlot {
+        id: i as u32,
+        settings: GameSlotSettings {
+          team: i as i32,
+          color: i as i32,
+          computer: Computer::Easy,
+          handicap: 100,
+          race: Race::Human,
+        },
+        player: GamePlayer {
+          player_id: *player_id,
+          name: format!("player{}", player_id),
+          ban_list: vec![],
+        },
+        client_status: SlotClientStatus::Pending,
+        referee: false,
+        sender: None,
+      })
+      .collect();
+    let (event_tx, event_rx) = channel(crate::constants::GAME_DISPATCH_BUF_SIZE);
+    let host = GameHost::new(
+      game_id,
+      &slots,
+      false,
+      StepRange::default(),
+      ObserverPublisherHandle::disabled(),
+      event_tx,
+    );
+    SimGame {
+      game_id,
+      host,
+      started_at: Instant::now(),
+      event_rx,
+      players: slots
+        .iter()
+        .map(|slot| {
+          (
+            slot.player.player_id,
+            SimPlayer {
+              slot_player_id: (slot.id + 1) as u8,
+              stream: None,
+              ack_q: W3GSAckQueue::new(),
+              unacked_ticks: 0,
+              stalled: false,
+              records: vec![],
+            },
+          )
+        })
+        .collect(),
+      status_changes: vec![],
+    }
+  }
+
+  pub fn slot_player_id(&self, player_id: i32) -> u8 {
+    self.player(player_id).slot_player_id
+  }
+
+  /// Connects the player with a fresh stream
+  pub async fn connect(&mut self, player_id: i32) -> Result<()> {
+    let (mut client, server) = FloStream::pair();
+    let snapshot = NodeGameStatusSnapshot {
+      game_id: self.game_id,
+      game_status: NodeGameStatus::Created,
+      player_game_client_status_map: HashMap::new(),
+    };
+    self
+      .host
+      .register_player_stream(PlayerStream::new(player_id, server), snapshot)
+      .await?;
+    // PacketClientConnectAccept
+    client.recv_frame().await?;
+    self.player_mut(player_id).stream.replace(client);
+    Ok(())
+  }
+
+  /// Drops the player's stream without leaving the game
+  pub fn disconnect(&mut self, player_id: i32) {
+    self.player_mut(player_id).stream.take();
+  }
+
+  pub fn start(&mut self) {
+    self.host.start();
+  }
+
+  /// Sends an action, it will be included in one of the next ticks
+  pub async fn send_action(&mut self, player_id: i32, data: &[u8]) -> Result<()> {
+    self
+      .send_w3gs(
+        player_id,
+        W3GSPacket::with_payload(OutgoingAction::new(data))?,
+      )
+      .await
+  }
+
+  /// Votes to drop the lagging players
+  pub async fn request_drop(&mut self, player_id: i32) -> Result<()> {
+    let pkt = W3GSPacket {
+      header: Header::new(W3GSPacketTypeId::DropReq, 4),
+      payload: Bytes::new(),
+    };
+    self.send_w3gs(player_id, pkt).await
+  }
+
+  pub async fn leave(&mut self, player_id: i32) -> Result<()> {
+    self
+      .send_w3gs(
+        player_id,
+        W3GSPacket::simple(LeaveReq::new(LeaveReason::LeaveLost))?,
+      )
+      .await
+  }
+
+  /// A stalled player keeps receiving packets and answering pings,
+  /// but stops acknowledging ticks, the pending ticks are acknowledged once resumed
+  pub fn set_stalled(&mut self, player_id: i32, stalled: bool) {
+    self.player_mut(player_id).stalled = stalled;
+  }
+
+  /// Advances the virtual time by `duration`, clients are polled after each increment
+  pub async fn run_for(&mut self, duration: Duration) -> Result<()> {
+    let deadline = Instant::now() + duration;
+    while Instant::now() < deadline {
+      let d = std::cmp::min(RESOLUTION, deadline - Instant::now());
+      tokio::time::advance(d).await;
+      self.settle().await;
+      self.poll_clients().await?;
+      self.settle().await;
+    }
+    Ok(())
+  }
+
+  /// Packets received by the player so far
+  pub fn records(&self, player_id: i32) -> &[SimRecord] {
+    &self.player(player_id).records
+  }
+
+  /// Ticks received by the player so far
+  pub fn ticks(&self, player_id: i32) -> Vec<(u16, Vec<(u8, Bytes)>)> {
+    self
+      .records(player_id)
+      .iter()
+      .filter_map(|r| match r.event {
+        SimEvent::Tick {
+          time_increment_ms,
+          ref actions,
+        } => Some((time_increment_ms, actions.clone())),
+        _ => None,
+      })
+      .collect()
+  }
+
+  /// `GameEvent::PlayerStatusChange` emitted by the host so far
+  pub fn status_changes(&self) -> &[(i32, SlotClientStatus)] {
+    &self.status_changes
+  }
+
+  async fn send_w3gs(&mut self, player_id: i32, pkt: W3GSPacket) -> Result<()> {
+    let player = self.player_mut(player_id);
+    let frame = player.w3gs_frame(pkt);
+    if let Some(stream) = player.stream.as_mut() {
+      stream.send_frame(frame).await?;
+    }
+    self.settle().await;
+    Ok(())
+  }
+
+  async fn poll_clients(&mut self) -> Result<()> {
+    let time_ms = Instant::now()
+      .saturating_duration_since(self.started_at)
+      .as_millis() as u64;
+    for player in self.players.values_mut() {
+      player.poll(time_ms).await?;
+    }
+    while let Ok(event) = self.event_rx.try_recv() {
+      if let GameEvent::PlayerStatusChange(player_id, status, _) = event {
+        self.status_changes.push((player_id, status));
+      }
+    }
+    Ok(())
+  }
+
+  async fn settle(&self) {
+    for _ in 0..SETTLE_YIELDS {
+      tokio::task::yield_now().await;
+    }
+  }
+
+  fn player(&self, player_id: i32) -> &SimPlayer {
+    self
+      .players
+      .get(&player_id)
+      .unwrap_or_else(|| panic!("unknown player: {}", player_id))
+  }
+
+  fn player_mut(&mut self, player_id: i32) -> &mut SimPlayer {
+    self
+      .players
+      .get_mut(&player_id)
+      .unwrap_or_else(|| panic!("unknown player: {}", player_id))
+  }
+}
+
+impl SimPlayer {
+  fn w3gs_frame(&mut self, pkt: W3GSPacket) -> Frame {
+    let sid = self.ack_q.gen_next_send_sid();
+    let meta = W3GSMetadata::new(pkt.type_id(), sid, self.ack_q.take_ack_received());
+    self.ack_q.push_send(meta.clone(), pkt.clone());
+    Frame::from_w3gs(meta, pkt)
+  }
+
+  async fn poll(&mut self, time_ms: u64) -> Result<()> {
+    let mut out = vec![];
+    let mut closed = false;
+    if let Some(stream) = self.stream.as_mut() {
+      loop {
+        let mut frame = match stream.recv_frame().now_or_never() {
+          Some(Ok(frame)) => frame,
+          Some(Err(_)) => {
+            closed = true;
+            break;
+          }
+          None => break,
+        };
+        match frame.type_id {
+          PingStream::PING_TYPE_ID => {
+            frame.type_id = PingStream::PONG_TYPE_ID;
+            out.push(frame);
+          }
+          PacketTypeId::W3GS => {
+            let (meta, pkt) = frame.try_into_w3gs()?;
+            if !self.ack_q.ack_received(meta.sid()) {
+              continue;
+            }
+            if let Some(ack_sid) = meta.ack_sid() {
+              self.ack_q.ack_sent(ack_sid);
+            }
+            if let Some(event) = decode_event(&pkt)? {
+              if let SimEvent::Tick { .. } = event {
+                self.unacked_ticks += 1;
+              }
+              self.records.push(SimRecord { time_ms, event });
+            }
+          }
+          _ => {}
+        }
+      }
+    }
+
+    if closed {
+      self.stream.take();
+      return Ok(());
+    }
+
+    if !self.stalled {
+      for _ in 0..self.unacked_ticks {
+        let pkt = W3GSPacket::simple(OutgoingKeepAlive {
+          unknown: 0,
+          checksum: CHECKSUM,
+        })?;
+        out.push(self.w3gs_frame(pkt));
+      }
+      self.unacked_ticks = 0;
+    }
+
+    if let Some(stream) = self.stream.as_mut() {
+      if !out.is_empty() {
+        stream.send_frames(out).await?;
+      }
+    }
+    Ok(())
+  }
+}
+
+fn decode_event(pkt: &W3GSPacket) -> Result<Option<SimEvent>> {
+  let actions = |slot: TimeSlot| -> Vec<(u8, Bytes)> {
+    slot
+      .actions
+      .into_iter()
+      .map(|action| (action.player_id, action.data))
+      .collect()
+  };
+  let event = match pkt.type_id() {
+    W3GSPacketTypeId::IncomingAction => {
+      let IncomingAction(slot) = pkt.decode_payload()?;
+      SimEvent::Tick {
+        time_increment_ms: slot.time_increment_ms,
+        actions: actions(slot),
+      }
+    }
+    W3GSPacketTypeId::IncomingAction2 => {
+      let IncomingAction2(slot) = pkt.decode_payload()?;
+      SimEvent::TickFragment {
+        actions: actions(slot),
+      }
+    }
+    W3GSPacketTypeId::StartLag => {
+      let payload: StartLag = pkt.decode_simple()?;
+      SimEvent::StartLag(payload.players().iter().map(|p| p.player_id).collect())
+    }
+    W3GSPacketTypeId::StopLag => {
+      let StopLag(player) = pkt.decode_simple()?;
+      SimEvent::StopLag(player.player_id)
+    }
+    W3GSPacketTypeId::PlayerLeft => {
+      let payload: PlayerLeft = pkt.decode_simple()?;
+      SimEvent::PlayerLeft(payload.player_id)
+    }
+    W3GSPacketTypeId::ChatFromHost => SimEvent::Chat,
+    _ => return Ok(None),
+  };
+  Ok(Some(event))
+}
+
+fn step() -> Duration {
+  Duration::from_millis(crate::config::current().game_step_ms as u64)
+}
+
+fn lag_threshold() -> Duration {
+  Duration::from_millis(crate::config::current().game_player_lagging_threshold_ms as u64)
+}
+
+async fn start_game(player_ids: &[i32]) -> SimGame {
+  let mut game = SimGame::new(player_ids);
+  for player_id in player_ids {
+    game.connect(*player_id).await.unwrap();
+  }
+  game.start();
+  game
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_sim_ticks() {
+  let mut game = start_game(&[1, 2]).await;
+  let step = step();
+
+  game.run_for(step * 10).await.unwrap();
+  for &player_id in &[1, 2] {
+    let ticks = game.ticks(player_id);
+    assert_eq!(ticks.len(), 10);
+    assert!(ticks
+      .iter()
+      .all(|(t, actions)| *t as u128 == step.as_millis() && actions.is_empty()));
+  }
+
+  game.send_action(2, &[1, 2, 3]).await.unwrap();
+  game.run_for(step).await.unwrap();
+  let slot_player_id = game.slot_player_id(2);
+  for &player_id in &[1, 2] {
+    let ticks = game.ticks(player_id);
+    assert_eq!(ticks.len(), 11);
+    assert_eq!(
+      ticks[10].1,
+      vec![(slot_player_id, Bytes::from_static(&[1, 2, 3]))]
+    );
+  }
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_sim_lag() {
+  let mut game = start_game(&[1, 2]).await;
+  let step = step();
+
+  game.run_for(step * 10).await.unwrap();
+  game.set_stalled(2, true);
+  let stalled_at = step.as_millis() as u64 * 10;
+  game.run_for(lag_threshold() + step * 10).await.unwrap();
+
+  let lagging = game.slot_player_id(2);
+  let start_lag: Vec<_> = game
+    .records(1)
+    .iter()
+    .filter_map(|r| match r.event {
+      SimEvent::StartLag(ref ids) => Some((r.time_ms, ids.clone())),
+      _ => None,
+    })
+    .collect();
+  assert_eq!(start_lag.len(), 1);
+  assert_eq!(start_lag[0].1, vec![lagging]);
+  assert!(start_lag[0].0 >= stalled_at + lag_threshold().as_millis() as u64);
+  assert!(!game
+    .records(2)
+    .iter()
+    .any(|r| matches!(r.event, SimEvent::StartLag(_))));
+
+  // the clock is paused while the player is lagging
+  let ticks = game.ticks(1).len();
+  game.run_for(Duration::from_secs(1)).await.unwrap();
+  assert_eq!(game.ticks(1).len(), ticks);
+
+  game.set_stalled(2, false);
+  game.run_for(step * 10).await.unwrap();
+  assert!(game
+    .records(1)
+    .iter()
+    .any(|r| r.event == SimEvent::StopLag(lagging)));
+  assert!(game.ticks(1).len() > ticks);
+  assert_eq!(game.ticks(1).len(), game.ticks(2).len());
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_sim_drop_vote() {
+  let mut game = start_game(&[1, 2]).await;
+  let step = step();
+
+  game.run_for(step * 10).await.unwrap();
+  game.set_stalled(2, true);
+  game.run_for(lag_threshold() + step * 10).await.unwrap();
+  let ticks = game.ticks(1).len();
+
+  game.request_drop(1).await.unwrap();
+  game.run_for(step * 10).await.unwrap();
+
+  let dropped = game.slot_player_id(2);
+  assert!(game
+    .records(1)
+    .iter()
+    .any(|r| r.event == SimEvent::PlayerLeft(dropped)));
+  assert!(game.ticks(1).len() > ticks);
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_sim_disconnect() {
+  let mut game = start_game(&[1, 2]).await;
+  let step = step();
+
+  game.run_for(step * 10).await.unwrap();
+  game.disconnect(2);
+  game.run_for(step).await.unwrap();
+  assert!(game
+    .status_changes()
+    .contains(&(2, SlotClientStatus::Disconnected)));
+
+  // a disconnected player lags the game until the max pause is reached
+  game.run_for(lag_threshold() + step * 10).await.unwrap();
+  let dropped = game.slot_player_id(2);
+  assert!(game
+    .records(1)
+    .iter()
+    .any(|r| r.event == SimEvent::StartLag(vec![dropped])));
+  let ticks = game.ticks(1).len();
+
+  game
+    .run_for(crate::config::current().game_clock_max_pause() + step * 10)
+    .await
+    .unwrap();
+  assert!(game
+    .records(1)
+    .iter()
+    .any(|r| r.event == SimEvent::PlayerLeft(dropped)));
+  assert!(game.ticks(1).len() > ticks);
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_sim_leave() {
+  let mut game = start_game(&[1, 2, 3]).await;
+  let step = step();
+
+  game.run_for(step * 10).await.unwrap();
+  game.leave(3).await.unwrap();
+  game.run_for(step * 10).await.unwrap();
+
+  let left = game.slot_player_id(3);
+  for &player_id in &[1, 2] {
+    assert!(game
+      .records(player_id)
+      .iter()
+      .any(|r| r.event == SimEvent::PlayerLeft(left)));
+  }
+  assert!(game.status_changes().contains(&(3, SlotClientStatus::Left)));
+  // the game keeps running without the player
+  assert_eq!(game.ticks(1).len(), game.ticks(2).len());
+  assert!(game.ticks(1).len() > 10);
+}
@@ -0,0 +1,132 @@
+use bytes::Bytes;
+use flo_net::proto::flo_common::PlayerGameResult;
+use flo_net::proto::flo_node::{PacketNodeGameStats, PlayerGameStats};
+use flo_w3gs::actions::ActionTypeId;
+use flo_w3gs::constants::LeaveReason;
+use flo_w3gs::protocol::action::PlayerAction;
+use std::collections::BTreeMap;
+
+/// Per-player summary of a game, reported to the controller at game end
+#[derive(Debug, Default)]
+pub struct GameStats {
+  players: BTreeMap<i32, PlayerStats>,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct PlayerStats {
+  pub actions: u32,
+  pub pauses: u32,
+  pub left_at_ms: Option<u32>,
+  pub result: Option<PlayerGameResult>,
+}
+
+impl GameStats {
+  pub fn new<I>(player_ids: I) -> Self
+  where
+    I: IntoIterator<Item = i32>,
+  {
+    GameStats {
+      players: player_ids
+        .into_iter()
+        .map(|id| (id, PlayerStats::default()))
+        .collect(),
+    }
+  }
+
+  pub fn record_actions(&mut self, player_id: i32, data: &Bytes) {
+    let stats = if let Some(stats) = self.players.get_mut(&player_id) {
+      stats
+    } else {
+      return;
+    };
+    let action = PlayerAction {
+      player_id: 0,
+      data: data.clone(),
+    };
+    for action in action.actions() {
+      let action = if let Ok(action) = action {
+        action
+      } else {
+        break;
+      };
+      stats.actions += 1;
+      if action.type_id() == ActionTypeId::PauseGame {
+        stats.pauses += 1;
+      }
+    }
+  }
+
+  /// Records the first leave of a player, `time_ms` is the game time
+  pub fn record_leave(&mut self, player_id: i32, time_ms: u32, reason: Option<LeaveReason>) {
+    if let Some(stats) = self.players.get_mut(&player_id) {
+      if stats.left_at_ms.is_none() {
+        stats.left_at_ms = Some(time_ms);
+        stats.result = reason.and_then(result_of_leave_reason);
+      }
+    }
+  }
+
+  pub fn first_leaver(&self) -> Option<i32> {
+    self
+      .players
+      .iter()
+      .filter_map(|(id, stats)| stats.left_at_ms.map(|t| (t, *id)))
+      .min()
+      .map(|(_, id)| id)
+  }
+
+  pub fn to_packet(&self, game_id: i32, duration_ms: u32) -> PacketNodeGameStats {
+    let first_leaver = self.first_leaver();
+    PacketNodeGameStats {
+      game_id,
+      duration_ms,
+      players: self
+        .players
+        .iter()
+        .map(|(id, stats)| PlayerGameStats {
+          player_id: *id,
+          actions: stats.actions,
+          apm: apm(stats.actions, stats.left_at_ms.unwrap_or(duration_ms)),
+          pauses: stats.pauses,
+          left_at_ms: stats.left_at_ms,
+          first_leaver: first_leaver == Some(*id),
+          result: stats.result.unwrap_or(PlayerGameResult::Unknown).into(),
+        })
+        .collect(),
+    }
+  }
+}
+
+fn result_of_leave_reason(reason: LeaveReason) -> Option<PlayerGameResult> {
+  match reason {
+    LeaveReason::LeaveWon => Some(PlayerGameResult::Won),
+    LeaveReason::LeaveLost | LeaveReason::LeaveLostBuildings => Some(PlayerGameResult::Lost),
+    LeaveReason::LeaveDraw => Some(PlayerGameResult::Draw),
+    _ => None,
+  }
+}
+
+fn apm(actions: u32, duration_ms: u32) -> u32 {
+  if duration_ms == 0 {
+    return 0;
+  }
+  (actions as u64 * 60_000 / duration_ms as u64) as u32
+}
+
+#[test]
+fn test_game_stats() {
+  let mut stats = GameStats::new(vec![1, 2]);
+  stats.record_leave(2, 120_000, Some(LeaveReason::LeaveLost));
+  stats.record_leave(1, 180_000, Some(LeaveReason::LeaveWon));
+  stats.record_leave(2, 150_000, Some(LeaveReason::LeaveWon));
+  stats.players.get_mut(&1).unwrap().actions = 300;
+
+  let pkt = stats.to_packet(1, 180_000);
+  assert_eq!(stats.first_leaver(), Some(2));
+  assert_eq!(pkt.players[0].apm, 100);
+  assert_eq!(pkt.players[1].left_at_ms, Some(120_000));
+  assert!(pkt.players[1].first_leaver);
+  assert!(!pkt.players[0].first_leaver);
+  assert_eq!(pkt.players[0].result(), PlayerGameResult::Won);
+  assert_eq!(pkt.players[1].result(), PlayerGameResult::Lost);
+}
@@ -78,6 +78,11 @@ impl PlayerStreamHandle {
     self.stream_id
   }
 
+  /// Number of commands waiting to be processed by the peer worker
+  pub fn queue_len(&self) -> usize {
+    crate::constants::PEER_CHANNEL_SIZE.saturating_sub(self.tx.capacity())
+  }
+
   pub fn close(&self) {
     self.ct.cancel();
   }
@@ -50,11 +50,10 @@ impl SyncMap {
   }
 
   fn check_timeout(&mut self, time_increment: u16) -> Option<Vec<PlayerTimeout>> {
+    let threshold = crate::config::current().game_player_lagging_threshold_ms;
     for id in self.pending_tick.values() {
       let item = &mut self.pending_slab[*id];
-      if (self.time + time_increment as u32) - item.time
-        > crate::constants::GAME_PLAYER_LAGGING_THRESHOLD_MS
-      {
+      if (self.time + time_increment as u32) - item.time > threshold {
         return Some(
           self
             .players
@@ -15,6 +15,8 @@ use flo_task::SpawnScope;
 pub use flo_types::node::*;
 use host::stream::PlayerStreamHandle;
 pub use host::AckError;
+#[doc(hidden)]
+pub use host::{ActionTickStream, StepRange};
 use host::GameHost;
 
 use crate::controller::ControllerServerHandle;
@@ -30,6 +32,7 @@ mod host;
 pub enum GameEvent {
   GameStatusChange(NodeGameStatus),
   PlayerStatusChange(i32, SlotClientStatus, SlotClientStatusUpdateSource),
+  ActionIncident(proto::PacketNodeActionIncident),
 }
 
 pub type GameEventSender = Sender<GameEvent>;
@@ -54,6 +57,21 @@ impl GameSession {
   ) -> Result<Self> {
     let scope = SpawnScope::new();
     let game_id = game.id;
+    let equalize_ping = game
+      .settings
+      .as_ref()
+      .map(|settings| settings.equalize_ping)
+      .unwrap_or_default();
+    let step_range = game
+      .settings
+      .as_ref()
+      .map(|settings| {
+        StepRange::new(
+          settings.step_min.map(|v| v as u16),
+          settings.step_max.map(|v| v as u16),
+        )
+      })
+      .unwrap_or_default();
     let (tx, mut rx) = GameEvent::channel(32);
     let slots: Vec<_> = Vec::<GameSlot>::unpack(game.slots)?
       .into_iter()
@@ -64,7 +82,14 @@ impl GameSession {
     let state = Arc::new(Mutex::new(State {
       game_id,
       g_event_sender,
-      host: GameHost::new(game_id, &slots, obs.clone(), tx.clone()),
+      host: GameHost::new(
+        game_id,
+        &slots,
+        equalize_ping,
+        step_range,
+        obs.clone(),
+        tx.clone(),
+      ),
       status: NodeGameStatus::Created,
       player_slots: slots
         .into_iter()
@@ -119,6 +144,17 @@ impl GameSession {
           .update_player_client_status(source, player_id, status)
           .await?;
       }
+      GameEvent::ActionIncident(incident) => {
+        tracing::warn!(
+          player_id = incident.player_id,
+          kind = ?incident.kind(),
+          "action incident: {}",
+          incident.detail
+        );
+        let frame = incident.encode_as_frame()?;
+        let guard = handle.0.lock().await;
+        guard.ctrl.send(frame).await.ok();
+      }
       GameEvent::GameStatusChange(status) => {
         let mut guard = handle.0.lock().await;
         let game_id = guard.game_id;
@@ -208,6 +244,64 @@ impl GameSessionHandle {
     Ok(())
   }
 
+  pub async fn update_player_bans(
+    &self,
+    player_id: i32,
+    ban_list: Vec<PlayerBanType>,
+  ) -> Result<()> {
+    let mut guard = self.0.lock().await;
+    if !guard.player_slots.contains_key(&player_id) {
+      return Err(Error::PlayerNotFoundInGame);
+    }
+    guard.host.update_player_bans(player_id, ban_list).await
+  }
+
+  /// Checkpoint for crash recovery, `None` once the game ended
+  pub async fn snapshot(&self) -> Option<proto::NodeGameSnapshot> {
+    let guard = self.0.lock().await;
+    if guard.status == NodeGameStatus::Ended {
+      return None;
+    }
+    let saved_at = std::time::SystemTime::now()
+      .duration_since(std::time::UNIX_EPOCH)
+      .map(|d| d.as_millis() as i64)
+      .unwrap_or_default();
+    let mut snapshot = proto::NodeGameSnapshot {
+      game_id: guard.game_id,
+      stats: Some(guard.host.stats()),
+      saved_at,
+      ..Default::default()
+    };
+    snapshot.set_status(guard.status.into_proto_enum());
+    for slot in guard.player_slots.values() {
+      snapshot.insert_player_client_status_map(
+        slot.player.player_id,
+        slot.client_status.into_proto_enum(),
+      );
+    }
+    Some(snapshot)
+  }
+
+  /// Ends the game without waiting for players to leave
+  pub async fn force_end(&self) {
+    let mut guard = self.0.lock().await;
+    if guard.status == NodeGameStatus::Ended {
+      return;
+    }
+    let game_id = guard.game_id;
+    tracing::warn!(game_id, "force end game");
+    guard.status = NodeGameStatus::Ended;
+    guard.obs.push_game_end(game_id);
+    if let Err(err) = guard.broadcast_status_update(StatusUpdate::Full).await {
+      tracing::error!(game_id, "broadcast status update: {}", err);
+    }
+    guard
+      .g_event_sender
+      .send(GlobalEvent::GameEnded(game_id))
+      .await
+      .ok();
+  }
+
   pub async fn update_player_client_status(
     &self,
     source: SlotClientStatusUpdateSource,
@@ -499,6 +593,7 @@ pub struct PlayerSlot {
   pub settings: GameSlotSettings,
   pub player: GamePlayer,
   pub client_status: SlotClientStatus,
+  pub referee: bool,
   pub sender: Option<PlayerStreamHandle>,
 }
 
@@ -510,6 +605,7 @@ impl PlayerSlot {
       settings: slot.settings,
       player,
       client_status: slot.client_status,
+      referee: slot.referee,
       sender: None,
     })
   }
@@ -524,6 +620,22 @@ impl State {
     }) {
       self.status = NodeGameStatus::Ended;
       tracing::debug!("all player left, end game");
+      match self.host.stats().encode_as_frame() {
+        Ok(frame) => {
+          self.ctrl.send(frame).await.ok();
+        }
+        Err(err) => {
+          tracing::error!(game_id = self.game_id, "encode game stats: {}", err);
+        }
+      }
+      match self.host.chat_log().encode_as_frame() {
+        Ok(frame) => {
+          self.ctrl.send(frame).await.ok();
+        }
+        Err(err) => {
+          tracing::error!(game_id = self.game_id, "encode chat log: {}", err);
+        }
+      }
       self.obs.push_game_end(self.game_id);
       self
         .g_event_sender
@@ -570,6 +682,7 @@ pub struct GameSlot {
   settings: GameSlotSettings,
   player: Option<GamePlayer>,
   client_status: SlotClientStatus,
+  referee: bool,
 }
 
 impl S2ProtoUnpack<proto::GameSlot> for GameSlot {
@@ -579,6 +692,7 @@ impl S2ProtoUnpack<proto::GameSlot> for GameSlot {
       settings: GameSlotSettings::unpack(value.settings)?,
       player: Option::<GamePlayer>::unpack(value.player)?,
       client_status: SlotClientStatus::unpack(value.client_status)?,
+      referee: value.referee,
     })
   }
 }
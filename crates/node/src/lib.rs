@@ -1,10 +1,15 @@
 mod client;
+mod config;
 mod controller;
+mod drain;
 mod echo;
 mod env;
 mod game;
 mod metrics;
+mod relay;
+mod snapshot;
 mod state;
+mod utilization;
 mod version;
 
 mod constants;
@@ -13,32 +18,52 @@ mod observer;
 
 use error::Result;
 
+/// Internals exposed for the benchmarks, not a stable API
+#[doc(hidden)]
+pub mod bench {
+  pub use crate::game::{ActionTickStream, StepRange};
+}
+
 use flo_event::*;
 
 use self::client::serve_client;
+use self::drain::serve_drain;
 use self::echo::serve_echo;
 use self::metrics::serve_metrics;
+use self::snapshot::serve_snapshots;
 use crate::state::GlobalState;
 use state::event::{handle_global_events, FloNodeEventContext, GlobalEvent};
 
 pub async fn serve() -> Result<()> {
+  config::init();
+
   let (event_sender, event_receiver) = GlobalEvent::channel(30);
   let state = GlobalState::new(event_sender).into_ref();
+  state.set_orphaned_games(snapshot::load_orphaned_games());
   let mut ctrl = controller::ControllerServer::new(state.clone());
   let ctrl_handle = ctrl.handle();
 
-  tokio::try_join!(
-    ctrl.serve(),
-    serve_client(state.clone()),
-    serve_metrics(),
-    serve_echo(),
-    handle_global_events(
-      FloNodeEventContext {
-        state,
-        ctrl: ctrl_handle,
-      },
-      event_receiver
+  let serve = async {
+    tokio::try_join!(
+      ctrl.serve(),
+      serve_client(state.clone()),
+      serve_metrics(state.clone()),
+      serve_echo(),
+      serve_snapshots(state.clone()),
+      config::serve_reload_signal(),
+      handle_global_events(
+        FloNodeEventContext {
+          state: state.clone(),
+          ctrl: ctrl_handle,
+        },
+        event_receiver
+      )
     )
-  )
-  .map(|_| ())
+    .map(|_| ())
+  };
+
+  tokio::select! {
+    res = serve => res,
+    res = serve_drain(state.clone()) => res,
+  }
 }
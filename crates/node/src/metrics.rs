@@ -1,8 +1,13 @@
 use once_cell::sync::Lazy;
-use prometheus::{register_int_gauge, Encoder, IntGauge, TextEncoder};
+use prometheus::{
+  register_histogram, register_int_counter, register_int_gauge, Encoder, Histogram, IntCounter,
+  IntGauge, TextEncoder,
+};
 
+use crate::env::Env;
 use crate::error::*;
-use hyper::header::CONTENT_TYPE;
+use crate::state::GlobalStateRef;
+use hyper::header::{AUTHORIZATION, CONTENT_TYPE};
 
 pub static GAME_SESSIONS: Lazy<IntGauge> =
   Lazy::new(|| register_int_gauge!("flonode_game_sessions", "Number of game sessions").unwrap());
@@ -21,12 +26,85 @@ pub static PLAYER_TOKENS: Lazy<IntGauge> = Lazy::new(|| {
   .unwrap()
 });
 
-pub async fn serve_metrics() -> Result<()> {
+pub static RELAYED_CONNECTIONS: Lazy<IntGauge> = Lazy::new(|| {
+  register_int_gauge!(
+    "flonode_relayed_connections",
+    "Number of player connections forwarded to other nodes"
+  )
+  .unwrap()
+});
+
+pub static PLAYER_BYTES_IN: Lazy<IntCounter> = Lazy::new(|| {
+  register_int_counter!(
+    "flonode_player_bytes_in",
+    "Payload bytes received from players"
+  )
+  .unwrap()
+});
+
+pub static PLAYER_BYTES_OUT: Lazy<IntCounter> = Lazy::new(|| {
+  register_int_counter!("flonode_player_bytes_out", "Payload bytes sent to players").unwrap()
+});
+
+pub static GAME_TICK_LATENESS: Lazy<Histogram> = Lazy::new(|| {
+  register_histogram!(
+    "flonode_game_tick_lateness_seconds",
+    "Delay between the scheduled and the actual time of game ticks",
+    vec![0.001, 0.002, 0.005, 0.01, 0.02, 0.05, 0.1, 0.25, 0.5]
+  )
+  .unwrap()
+});
+
+pub static PLAYER_SEND_QUEUE_DEPTH: Lazy<Histogram> = Lazy::new(|| {
+  register_histogram!(
+    "flonode_player_send_queue_depth",
+    "Frames queued for players at each game tick",
+    vec![0., 1., 2., 5., 10., 25., 50., 100., 200.]
+  )
+  .unwrap()
+});
+
+pub async fn serve_metrics(state: GlobalStateRef) -> Result<()> {
   use hyper::service::{make_service_fn, service_fn};
-  use hyper::{Body, Request, Response, Server};
+  use hyper::{Body, Method, Request, Response, Server};
   use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
 
-  async fn serve_req(req: Request<Body>) -> Result<Response<Body>, hyper::Error> {
+  async fn serve_req(
+    state: GlobalStateRef,
+    req: Request<Body>,
+  ) -> Result<Response<Body>, hyper::Error> {
+    let is_admin_req = req.method() == Method::POST
+      && (req.uri().path() == "/drain" || req.uri().path() == "/reload");
+    if is_admin_req && !is_authorized(&req) {
+      return Ok(Response::builder().status(401).body(Body::empty()).unwrap());
+    }
+
+    if req.uri().path() == "/reload" && req.method() == Method::POST {
+      let response = match crate::config::reload() {
+        Ok(config) => Response::builder()
+          .status(200)
+          .body(Body::from(format!("{:#?}", config))),
+        Err(err) => Response::builder()
+          .status(400)
+          .body(Body::from(err.to_string())),
+      };
+      return Ok(response.unwrap());
+    }
+
+    if req.uri().path() == "/drain" && req.method() == Method::POST {
+      state.request_drain();
+
+      let response = Response::builder()
+        .status(202)
+        .body(Body::from(format!(
+          "draining, games: {}",
+          state.game_count()
+        )))
+        .unwrap();
+
+      return Ok(response);
+    }
+
     if req.uri().path() == "/version" {
       let response = Response::builder()
         .status(200)
@@ -51,13 +129,31 @@ pub async fn serve_metrics() -> Result<()> {
     Ok(response)
   }
 
+  fn is_authorized(req: &Request<Body>) -> bool {
+    req
+      .headers()
+      .get(AUTHORIZATION)
+      .and_then(|v| v.to_str().ok())
+      .and_then(|v| v.strip_prefix("Bearer "))
+      .map(|v| {
+        !v.is_empty()
+          && ring::constant_time::verify_slices_are_equal(
+            v.as_bytes(),
+            Env::get().secret_key.as_bytes(),
+          )
+          .is_ok()
+      })
+      .unwrap_or_default()
+  }
+
   let addr = SocketAddr::from(SocketAddrV4::new(
     Ipv4Addr::UNSPECIFIED,
     flo_constants::NODE_HTTP_PORT,
   ));
 
-  let server = Server::bind(&addr).serve(make_service_fn(|_| async {
-    Ok::<_, hyper::Error>(service_fn(serve_req))
+  let server = Server::bind(&addr).serve(make_service_fn(move |_| {
+    let state = state.clone();
+    async move { Ok::<_, hyper::Error>(service_fn(move |req| serve_req(state.clone(), req))) }
   }));
   server.await?;
 
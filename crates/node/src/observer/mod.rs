@@ -54,6 +54,16 @@ pub struct ObserverPublisherHandle {
 }
 
 impl ObserverPublisherHandle {
+  /// A handle that discards all records
+  #[cfg(test)]
+  pub(crate) fn disabled() -> Self {
+    let (tx, _) = channel(1);
+    ObserverPublisherHandle {
+      broken: Cell::new(true),
+      tx,
+    }
+  }
+
   pub fn push_w3gs(&self, game_id: i32, packet: Packet) {
     self.push_record(GameRecord::new_w3gs(game_id, packet))
   }
@@ -0,0 +1,216 @@
+//! Forwards players to the node hosting their game.
+//!
+//! The controller registers the tokens of players far from the game's node on a node
+//! closer to them. Their connections are piped frame by frame to the game's node,
+//! which authenticates them with the same token, so the host loop does not know
+//! whether a player is relayed.
+//!
+//! Each relay node keeps one persistent link to each node it forwards players to,
+//! the players are multiplexed over it, see [`flo_net::mux`].
+
+use dashmap::DashMap;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::time::Instant;
+
+use flo_net::mux::Multiplexer;
+use flo_net::proto::flo_node::{PacketClientConnect, PacketControllerRelayPlayers};
+use flo_net::stream::FloStream;
+
+use crate::constants::RELAY_TOKEN_TTL;
+use crate::error::*;
+use crate::metrics;
+use crate::state::PlayerToken;
+
+#[derive(Debug, Clone)]
+pub struct RelayTarget {
+  pub game_id: i32,
+  pub player_id: i32,
+  pub addr: SocketAddr,
+  registered_at: Instant,
+}
+
+#[derive(Debug)]
+pub struct RelayRegistry {
+  map: DashMap<PlayerToken, RelayTarget>,
+  links: RelayLinks,
+}
+
+impl RelayRegistry {
+  pub fn new() -> Self {
+    RelayRegistry {
+      map: DashMap::new(),
+      links: RelayLinks::default(),
+    }
+  }
+
+  pub fn register(&self, packet: PacketControllerRelayPlayers) -> Result<()> {
+    if !crate::config::current().relay {
+      return Err(Error::RelayDisabled);
+    }
+
+    let addr = flo_net::addr::parse_node_addr(
+      &packet.target_addr,
+      flo_constants::NODE_CLIENT_PORT,
+      flo_constants::NODE_CLIENT_PORT_OFFSET,
+    )
+    .ok_or_else(|| Error::InvalidRelayTarget(packet.target_addr.clone()))?;
+
+    self
+      .map
+      .retain(|_, target| target.registered_at.elapsed() < RELAY_TOKEN_TTL);
+
+    let registered_at = Instant::now();
+    for player in packet.players {
+      let token = PlayerToken::from_vec(player.token).ok_or_else(|| Error::InvalidToken)?;
+      self.map.insert(
+        token,
+        RelayTarget {
+          game_id: packet.game_id,
+          player_id: player.player_id,
+          addr,
+          registered_at,
+        },
+      );
+    }
+
+    Ok(())
+  }
+
+  /// Removes the token, so it can't be used by another connection
+  pub fn take(&self, token: &PlayerToken) -> Option<RelayTarget> {
+    self
+      .map
+      .remove(token)
+      .map(|(_, target)| target)
+      .filter(|target| target.registered_at.elapsed() < RELAY_TOKEN_TTL)
+  }
+
+  /// Registers the token of a player whose connection dropped again, so the player can reconnect
+  fn restore(&self, token: PlayerToken, target: RelayTarget) {
+    if target.registered_at.elapsed() < RELAY_TOKEN_TTL {
+      self.map.insert(token, target);
+    }
+  }
+}
+
+/// Persistent links to the nodes hosting relayed games, one per node
+#[derive(Debug, Default)]
+struct RelayLinks {
+  links: tokio::sync::Mutex<HashMap<SocketAddr, Multiplexer>>,
+}
+
+impl RelayLinks {
+  /// Opens a stream on the link to `addr`, the link is (re)connected if needed
+  async fn open(&self, addr: SocketAddr) -> Result<FloStream> {
+    let mut links = self.links.lock().await;
+    if let Some(link) = links.get_mut(&addr) {
+      if !link.is_closed() {
+        if let Ok(stream) = link.open().await {
+          return Ok(stream);
+        }
+      }
+      links.remove(&addr);
+    }
+
+    tracing::debug!("relay link to {}", addr);
+    let mut link = Multiplexer::connect(FloStream::connect_no_delay(addr).await?)?;
+    let stream = link.open().await?;
+    links.insert(addr, link);
+    Ok(stream)
+  }
+}
+
+/// Which side ended a relayed connection
+enum RelayEnd {
+  Player(Error),
+  Upstream(Error),
+}
+
+/// Opens a stream to the game's node with the player's connect packet,
+/// then pipes frames in both directions until one side disconnects.
+/// The token is registered again if the player's side dropped.
+pub async fn serve_relay(
+  relays: &RelayRegistry,
+  mut stream: FloStream,
+  connect: PacketClientConnect,
+  token: PlayerToken,
+  target: RelayTarget,
+) -> Result<()> {
+  let mut upstream = relays.links.open(target.addr).await?;
+  upstream.send(connect).await?;
+
+  // frames are forwarded as received
+  stream.set_passthrough(true);
+
+  metrics::RELAYED_CONNECTIONS.inc();
+  let end = loop {
+    tokio::select! {
+      frame = stream.recv_frame() => {
+        let frame = match frame {
+          Ok(frame) => frame,
+          Err(err) => break RelayEnd::Player(err.into()),
+        };
+        if let Err(err) = upstream.send_frame(frame).await {
+          break RelayEnd::Upstream(err.into());
+        }
+      }
+      frame = upstream.recv_frame() => {
+        let frame = match frame {
+          Ok(frame) => frame,
+          Err(err) => break RelayEnd::Upstream(err.into()),
+        };
+        if let Err(err) = stream.send_frame(frame).await {
+          break RelayEnd::Player(err.into());
+        }
+      }
+    }
+  };
+  metrics::RELAYED_CONNECTIONS.dec();
+
+  match end {
+    RelayEnd::Player(err) => {
+      relays.restore(token, target);
+      Err(err)
+    }
+    RelayEnd::Upstream(err) => Err(err),
+  }
+}
+
+#[tokio::test]
+async fn test_relay_links() {
+  use flo_net::packet::{Frame, PacketTypeId};
+  use futures::stream::StreamExt;
+  use tokio::net::TcpListener;
+
+  let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+  let addr = listener.local_addr().unwrap();
+
+  let server = tokio::spawn(async move {
+    let (socket, _) = listener.accept().await.unwrap();
+    let mut stream = FloStream::new(socket);
+    let first = stream.recv_frame().await.unwrap();
+    let mut mux = Multiplexer::accept(stream, first).unwrap();
+    for _ in 0..2 {
+      let mut stream = mux.next().await.unwrap();
+      let frame = stream.recv_frame().await.unwrap();
+      assert_eq!(frame.type_id, PacketTypeId::Ping);
+    }
+    // the second player used the same connection
+    tokio::time::timeout(std::time::Duration::from_millis(100), listener.accept())
+      .await
+      .unwrap_err();
+  });
+
+  let links = RelayLinks::default();
+  for _ in 0..2 {
+    let mut stream = links.open(addr).await.unwrap();
+    assert!(stream.is_multiplexed());
+    stream
+      .send_frame(Frame::new(PacketTypeId::Ping, b""))
+      .await
+      .unwrap();
+  }
+
+  server.await.unwrap();
+}
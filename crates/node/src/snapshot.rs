@@ -0,0 +1,171 @@
+//! Periodic checkpoints of running games, written to `FLO_NODE_SNAPSHOT_DIR`.
+//!
+//! Snapshots left on disk when the node starts belong to games interrupted by a crash
+//! or a restart, they are reported to the controller so it can save their results
+//! and close them.
+
+use bytes::BytesMut;
+use flo_net::packet::Message;
+use flo_net::proto::flo_node::NodeGameSnapshot;
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tokio::time::{interval_at, Instant, MissedTickBehavior};
+
+use crate::constants::SNAPSHOT_INTERVAL;
+use crate::env::Env;
+use crate::error::Result;
+use crate::state::GlobalStateRef;
+
+const EXTENSION: &str = "snapshot";
+
+/// Loads the snapshots of the previous run
+pub fn load_orphaned_games() -> Vec<NodeGameSnapshot> {
+  let dir = if let Some(dir) = Env::get().snapshot_dir.as_ref() {
+    dir
+  } else {
+    return vec![];
+  };
+  match read_snapshots(dir) {
+    Ok(games) => {
+      if !games.is_empty() {
+        tracing::warn!(count = games.len(), "orphaned games found");
+      }
+      games
+    }
+    Err(err) => {
+      tracing::error!("load snapshots: {}", err);
+      vec![]
+    }
+  }
+}
+
+pub async fn serve_snapshots(state: GlobalStateRef) -> Result<()> {
+  let dir = if let Some(dir) = Env::get().snapshot_dir.clone() {
+    dir
+  } else {
+    return Ok(());
+  };
+  fs::create_dir_all(&dir)?;
+
+  let mut interval = interval_at(Instant::now() + SNAPSHOT_INTERVAL, SNAPSHOT_INTERVAL);
+  interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+  loop {
+    interval.tick().await;
+
+    let mut keep: BTreeSet<i32> = state
+      .orphaned_games()
+      .iter()
+      .map(|game| game.game_id)
+      .collect();
+    for game in state.get_games() {
+      if let Some(snapshot) = game.snapshot().await {
+        let game_id = snapshot.game_id;
+        if let Err(err) = write_snapshot(&dir, &snapshot) {
+          tracing::error!(game_id, "write snapshot: {}", err);
+        }
+        keep.insert(game_id);
+      }
+    }
+
+    if let Err(err) = remove_snapshots(&dir, &keep) {
+      tracing::error!("remove snapshots: {}", err);
+    }
+  }
+}
+
+fn snapshot_path(dir: &Path, game_id: i32) -> PathBuf {
+  dir.join(format!("{}.{}", game_id, EXTENSION))
+}
+
+fn snapshot_game_id(path: &Path) -> Option<i32> {
+  if path.extension()? != EXTENSION {
+    return None;
+  }
+  path.file_stem()?.to_str()?.parse().ok()
+}
+
+fn write_snapshot(dir: &Path, snapshot: &NodeGameSnapshot) -> Result<()> {
+  let mut buf = BytesMut::with_capacity(snapshot.encoded_len());
+  snapshot
+    .encode(&mut buf)
+    .map_err(flo_net::error::Error::from)?;
+  // write then rename so a crash never leaves a truncated snapshot
+  let path = snapshot_path(dir, snapshot.game_id);
+  let tmp_path = path.with_extension("tmp");
+  fs::write(&tmp_path, &buf)?;
+  fs::rename(&tmp_path, &path)?;
+  Ok(())
+}
+
+fn read_snapshots(dir: &Path) -> Result<Vec<NodeGameSnapshot>> {
+  if !dir.exists() {
+    return Ok(vec![]);
+  }
+  let mut games = vec![];
+  for entry in fs::read_dir(dir)? {
+    let path = entry?.path();
+    if snapshot_game_id(&path).is_none() {
+      continue;
+    }
+    let bytes = fs::read(&path)?;
+    match NodeGameSnapshot::decode(bytes.as_slice()) {
+      Ok(snapshot) => games.push(snapshot),
+      Err(err) => {
+        tracing::warn!("invalid snapshot `{}`: {}", path.display(), err);
+      }
+    }
+  }
+  Ok(games)
+}
+
+/// Removes the snapshots of ended games and of reported orphaned games
+fn remove_snapshots(dir: &Path, keep: &BTreeSet<i32>) -> Result<()> {
+  for entry in fs::read_dir(dir)? {
+    let path = entry?.path();
+    if let Some(game_id) = snapshot_game_id(&path) {
+      if !keep.contains(&game_id) {
+        fs::remove_file(&path)?;
+      }
+    }
+  }
+  Ok(())
+}
+
+#[test]
+fn test_snapshot_files() {
+  let dir = std::env::temp_dir().join(format!("flo-node-snapshot-{}", std::process::id()));
+  fs::create_dir_all(&dir).unwrap();
+
+  for game_id in 1..=3 {
+    write_snapshot(
+      &dir,
+      &NodeGameSnapshot {
+        game_id,
+        saved_at: 1000,
+        ..Default::default()
+      },
+    )
+    .unwrap();
+  }
+  fs::write(dir.join("other.txt"), b"").unwrap();
+
+  let mut ids: Vec<i32> = read_snapshots(&dir)
+    .unwrap()
+    .into_iter()
+    .map(|game| game.game_id)
+    .collect();
+  ids.sort();
+  assert_eq!(ids, vec![1, 2, 3]);
+
+  remove_snapshots(&dir, &vec![2].into_iter().collect()).unwrap();
+  let ids: Vec<i32> = read_snapshots(&dir)
+    .unwrap()
+    .into_iter()
+    .map(|game| game.game_id)
+    .collect();
+  assert_eq!(ids, vec![2]);
+  assert!(dir.join("other.txt").exists());
+
+  fs::remove_dir_all(&dir).unwrap();
+}
@@ -8,28 +8,36 @@ use dashmap::DashMap;
 use parking_lot::RwLock;
 use s2_grpc_utils::S2ProtoEnum;
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
+use tokio::sync::Notify;
 
 use flo_net::packet::{FloPacket, Frame, OptionalFieldExt};
 use flo_net::proto::flo_node::{
-  ControllerCreateGameRejectReason, Game, PacketControllerCreateGame,
-  PacketControllerCreateGameAccept, PacketControllerCreateGameReject,
-  PacketControllerUpdateSlotStatus, PacketControllerUpdateSlotStatusAccept,
-  PacketControllerUpdateSlotStatusReject,
+  ControllerCreateGameRejectReason, Game, NodeGameSnapshot, PacketControllerCreateGame,
+  PacketControllerCreateGameAccept, PacketControllerCreateGameReject, PacketControllerRelayPlayers,
+  PacketControllerRelayPlayersAccept, PacketControllerRelayPlayersReject,
+  PacketControllerUpdatePlayerBans, PacketControllerUpdateSlotStatus,
+  PacketControllerUpdateSlotStatusAccept, PacketControllerUpdateSlotStatusReject,
 };
 
 use crate::controller::ControllerServerHandle;
 use crate::error::*;
-use crate::game::{GameSession, GameSessionHandle, SlotClientStatusUpdateSource};
+use crate::game::{GameSession, GameSessionHandle, PlayerBanType, SlotClientStatusUpdateSource};
 use crate::metrics;
 use crate::observer::{ObserverPublisher, ObserverPublisherHandle};
+use crate::relay::{RelayRegistry, RelayTarget};
 
 #[derive(Debug)]
 pub struct GlobalState {
   event_sender: GlobalEventSender,
   players: PlayerRegistry,
   games: GameRegistry,
+  relays: RelayRegistry,
   obs: ObserverPublisher,
+  draining: AtomicBool,
+  drain_notify: Notify,
+  orphaned_games: RwLock<Vec<NodeGameSnapshot>>,
 }
 
 pub type GlobalStateRef = Arc<GlobalState>;
@@ -40,7 +48,11 @@ impl GlobalState {
       event_sender,
       players: PlayerRegistry::new(),
       games: GameRegistry::new(),
+      relays: RelayRegistry::new(),
       obs: ObserverPublisher::new(),
+      draining: AtomicBool::new(false),
+      drain_notify: Notify::new(),
+      orphaned_games: RwLock::new(vec![]),
     }
   }
 
@@ -52,6 +64,57 @@ impl GlobalState {
     self.players.get_by_token(token)
   }
 
+  /// Relay tokens can only be used once, see [`RelayRegistry::take`]
+  pub fn take_relay_target(&self, token: &PlayerToken) -> Option<RelayTarget> {
+    self.relays.take(token)
+  }
+
+  pub fn relays(&self) -> &RelayRegistry {
+    &self.relays
+  }
+
+  pub fn handle_controller_relay_players(
+    &self,
+    packet: PacketControllerRelayPlayers,
+  ) -> Result<Frame> {
+    let game_id = packet.game_id;
+    let frame = match self.relays.register(packet) {
+      Ok(_) => {
+        tracing::info!(game_id, "relay players registered");
+        PacketControllerRelayPlayersAccept { game_id }.encode_as_frame()?
+      }
+      Err(err) => {
+        tracing::warn!(game_id, "relay players rejected: {}", err);
+        PacketControllerRelayPlayersReject {
+          game_id,
+          message: err.to_string(),
+        }
+        .encode_as_frame()?
+      }
+    };
+    Ok(frame)
+  }
+
+  pub async fn handle_controller_update_player_bans(
+    &self,
+    packet: PacketControllerUpdatePlayerBans,
+  ) -> Result<()> {
+    let game_id = packet.game_id;
+    let player_id = packet.player_id;
+    let game = match self.games.get(game_id) {
+      Some(game) => game,
+      None => {
+        tracing::debug!(game_id, player_id, "update player bans: game not found");
+        return Ok(());
+      }
+    };
+    let ban_list = packet
+      .ban_list()
+      .map(|v| PlayerBanType::unpack_enum(v))
+      .collect();
+    game.update_player_bans(player_id, ban_list).await
+  }
+
   pub fn get_game(&self, id: i32) -> Option<GameSessionHandle> {
     self.games.get(id)
   }
@@ -61,6 +124,45 @@ impl GlobalState {
     self.games.remove(id);
   }
 
+  pub fn game_count(&self) -> usize {
+    self.games.len()
+  }
+
+  pub fn get_games(&self) -> Vec<GameSessionHandle> {
+    self.games.handles()
+  }
+
+  /// Stops accepting new games
+  pub fn request_drain(&self) {
+    if !self.draining.swap(true, Ordering::SeqCst) {
+      self.drain_notify.notify_one();
+    }
+  }
+
+  pub fn is_draining(&self) -> bool {
+    self.draining.load(Ordering::SeqCst)
+  }
+
+  pub async fn drain_requested(&self) {
+    self.drain_notify.notified().await
+  }
+
+  /// Games of the previous run not reported to the controller yet
+  pub fn orphaned_games(&self) -> Vec<NodeGameSnapshot> {
+    self.orphaned_games.read().clone()
+  }
+
+  pub fn set_orphaned_games(&self, games: Vec<NodeGameSnapshot>) {
+    *self.orphaned_games.write() = games;
+  }
+
+  pub fn remove_orphaned_games(&self, game_ids: &[i32]) {
+    self
+      .orphaned_games
+      .write()
+      .retain(|game| !game_ids.contains(&game.game_id));
+  }
+
   pub fn handle_controller_create_game(
     &self,
     ctrl: ControllerServerHandle,
@@ -79,6 +181,30 @@ impl GlobalState {
       return Err(Error::NoPlayer);
     }
 
+    if let Some(max_games) = crate::config::current().max_games {
+      if self.games.len() >= max_games {
+        tracing::warn!(game_id, max_games, "reject game: node is full");
+        return Ok(
+          PacketControllerCreateGameReject {
+            game_id,
+            reason: ControllerCreateGameRejectReason::NodeFull.into(),
+          }
+          .encode_as_frame()?,
+        );
+      }
+    }
+
+    if self.is_draining() {
+      tracing::info!(game_id, "reject game: node is draining");
+      return Ok(
+        PacketControllerCreateGameReject {
+          game_id,
+          reason: ControllerCreateGameRejectReason::Maintenance.into(),
+        }
+        .encode_as_frame()?,
+      );
+    }
+
     let pending: Vec<(PlayerToken, RegisteredPlayer)> = {
       let players: Vec<_> = game
         .slots
@@ -354,6 +480,14 @@ impl GameRegistry {
     self.map.get(&game_id).map(|r| r.value().handle())
   }
 
+  fn handles(&self) -> Vec<GameSessionHandle> {
+    self.map.iter().map(|r| r.value().handle()).collect()
+  }
+
+  fn len(&self) -> usize {
+    self.map.len()
+  }
+
   fn remove(&self, id: i32) {
     if let Some(_) = self.map.remove(&id) {
       metrics::GAME_SESSIONS.dec();
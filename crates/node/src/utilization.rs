@@ -0,0 +1,154 @@
+//! Load of the node, reported to the controller for node selection.
+
+use std::time::Instant;
+
+use flo_net::proto::flo_node::PacketNodeUtilization;
+
+use crate::state::GlobalState;
+
+#[derive(Debug, Default)]
+pub struct UtilizationSampler {
+  last: Option<Sample>,
+}
+
+#[derive(Debug)]
+struct Sample {
+  time: Instant,
+  cpu: Option<CpuTimes>,
+  bytes_in: u64,
+  bytes_out: u64,
+}
+
+impl UtilizationSampler {
+  pub fn sample(&mut self, state: &GlobalState) -> PacketNodeUtilization {
+    let current = Sample {
+      time: Instant::now(),
+      cpu: CpuTimes::read(),
+      bytes_in: crate::metrics::PLAYER_BYTES_IN.get(),
+      bytes_out: crate::metrics::PLAYER_BYTES_OUT.get(),
+    };
+
+    let mut pkt = PacketNodeUtilization {
+      memory_usage: read_memory_usage(),
+      games: state.game_count() as u32,
+      player_connections: crate::metrics::PLAYERS_CONNECTIONS.get().max(0) as u32,
+      max_games: crate::config::current().max_games.map(|v| v as u32),
+      ..Default::default()
+    };
+
+    if let Some(last) = self.last.as_ref() {
+      let secs = current.time.duration_since(last.time).as_secs_f64();
+      if secs > 0. {
+        pkt.bytes_in_per_sec =
+          (current.bytes_in.saturating_sub(last.bytes_in) as f64 / secs) as u64;
+        pkt.bytes_out_per_sec =
+          (current.bytes_out.saturating_sub(last.bytes_out) as f64 / secs) as u64;
+      }
+      if let (Some(prev), Some(cpu)) = (last.cpu.as_ref(), current.cpu.as_ref()) {
+        pkt.cpu_usage = cpu.usage_since(prev);
+      }
+    }
+
+    self.last = Some(current);
+    pkt
+  }
+}
+
+/// Aggregated jiffies of all CPUs, from `/proc/stat`
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct CpuTimes {
+  idle: u64,
+  total: u64,
+}
+
+impl CpuTimes {
+  #[cfg(target_os = "linux")]
+  fn read() -> Option<Self> {
+    let content = std::fs::read_to_string("/proc/stat").ok()?;
+    Self::parse(&content)
+  }
+
+  #[cfg(not(target_os = "linux"))]
+  fn read() -> Option<Self> {
+    None
+  }
+
+  fn parse(content: &str) -> Option<Self> {
+    let line = content.lines().find(|line| line.starts_with("cpu "))?;
+    let values: Vec<u64> = line
+      .split_whitespace()
+      .skip(1)
+      .filter_map(|v| v.parse().ok())
+      .collect();
+    if values.len() < 4 {
+      return None;
+    }
+    // idle + iowait
+    let idle = values[3] + values.get(4).cloned().unwrap_or_default();
+    Some(CpuTimes {
+      idle,
+      total: values.iter().sum(),
+    })
+  }
+
+  fn usage_since(&self, prev: &CpuTimes) -> Option<f32> {
+    let total = self.total.checked_sub(prev.total)?;
+    let idle = self.idle.checked_sub(prev.idle)?;
+    if total == 0 {
+      return None;
+    }
+    Some((total.saturating_sub(idle) as f64 * 100. / total as f64) as f32)
+  }
+}
+
+#[cfg(target_os = "linux")]
+fn read_memory_usage() -> Option<f32> {
+  let content = std::fs::read_to_string("/proc/meminfo").ok()?;
+  parse_memory_usage(&content)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn read_memory_usage() -> Option<f32> {
+  None
+}
+
+fn parse_memory_usage(content: &str) -> Option<f32> {
+  let value = |name: &str| -> Option<u64> {
+    content
+      .lines()
+      .find(|line| line.starts_with(name))?
+      .split_whitespace()
+      .nth(1)?
+      .parse()
+      .ok()
+  };
+  let total = value("MemTotal:")?;
+  let available = value("MemAvailable:")?;
+  if total == 0 {
+    return None;
+  }
+  Some((total.saturating_sub(available) as f64 * 100. / total as f64) as f32)
+}
+
+#[test]
+fn test_cpu_usage() {
+  let prev = CpuTimes::parse("cpu  100 0 100 700 100 0 0 0 0 0\ncpu0 1 2 3 4\n").unwrap();
+  assert_eq!(
+    prev,
+    CpuTimes {
+      idle: 800,
+      total: 1000
+    }
+  );
+  let next = CpuTimes::parse("cpu  150 0 150 800 100 0 0 0 0 0\n").unwrap();
+  assert_eq!(next.usage_since(&prev), Some(50.));
+  assert_eq!(prev.usage_since(&next), None);
+  assert_eq!(CpuTimes::parse("intr 1 2 3"), None);
+}
+
+#[test]
+fn test_memory_usage() {
+  let content = "MemTotal:       1000 kB\nMemFree:         100 kB\nMemAvailable:    250 kB\n";
+  assert_eq!(parse_memory_usage(content), Some(75.));
+  assert_eq!(parse_memory_usage("MemTotal: 1000 kB\n"), None);
+}
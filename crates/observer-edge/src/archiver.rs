@@ -1,8 +1,9 @@
+use crate::controller::Controller;
 use crate::env::ENV;
 use crate::error::{Error, Result};
 use backoff::backoff::Backoff;
 use bytes::Bytes;
-use rusoto_core::{credential::StaticProvider, request::HttpClient};
+use rusoto_core::{credential::StaticProvider, request::HttpClient, Region};
 use rusoto_s3::{S3Client, S3};
 use std::io::Write;
 use std::sync::Arc;
@@ -11,11 +12,13 @@ use tokio::sync::mpsc;
 pub struct Archiver {
   s3_bucket: String,
   s3_client: Arc<S3Client>,
+  public_url: String,
+  controller: Controller,
   rx: mpsc::Receiver<Msg>,
 }
 
 impl Archiver {
-  pub fn new() -> Result<Option<(Self, ArchiverHandle)>> {
+  pub fn new(controller: Controller) -> Result<Option<(Self, ArchiverHandle)>> {
     let s3_bucket = if let Some(value) = ENV.aws_s3_bucket.clone() {
       value
     } else {
@@ -35,14 +38,17 @@ impl Archiver {
         None,
       );
       let client = HttpClient::new().unwrap();
-      let region = ENV
-        .aws_s3_region
-        .clone()
-        .ok_or_else(|| Error::InvalidS3Credentials("missing env AWS_SECRET_ACCESS_KEY"))?
-        .parse()
-        .map_err(|_| Error::InvalidS3Credentials("invalid env AWS_S3_REGION"))?;
-      S3Client::new_with(client, provider, region)
+      S3Client::new_with(client, provider, Self::region()?)
     });
+    let public_url = match (ENV.aws_s3_public_url.as_ref(), ENV.aws_s3_endpoint.as_ref()) {
+      (Some(url), _) => url.trim_end_matches('/').to_string(),
+      (None, Some(endpoint)) => format!("{}/{}", endpoint.trim_end_matches('/'), s3_bucket),
+      (None, None) => format!(
+        "https://{}.s3.{}.amazonaws.com",
+        s3_bucket,
+        Self::region()?.name()
+      ),
+    };
 
     let (tx, rx) = mpsc::channel(100);
 
@@ -51,6 +57,8 @@ impl Archiver {
         Self {
           s3_bucket: s3_bucket.clone(),
           s3_client: s3_client.clone(),
+          public_url,
+          controller,
           rx,
         },
         ArchiverHandle {
@@ -63,19 +71,46 @@ impl Archiver {
     )
   }
 
+  fn region() -> Result<Region> {
+    let name = ENV
+      .aws_s3_region
+      .clone()
+      .ok_or_else(|| Error::InvalidS3Credentials("missing env AWS_S3_REGION"))?;
+    if let Some(endpoint) = ENV.aws_s3_endpoint.clone() {
+      return Ok(Region::Custom { name, endpoint });
+    }
+    name
+      .parse()
+      .map_err(|_| Error::InvalidS3Credentials("invalid env AWS_S3_REGION"))
+  }
+
   pub async fn serve(self) {
     let Self {
       s3_bucket,
       s3_client,
+      public_url,
+      controller,
       mut rx,
     } = self;
 
+    if let Some(days) = ENV.aws_s3_expiration_days {
+      if let Err(err) = Self::put_lifecycle(&s3_bucket, &s3_client, days).await {
+        tracing::error!("configure archive expiration: {}", err);
+      }
+    }
+
     loop {
       tokio::select! {
         msg = rx.recv() => {
           match msg {
             Some(Msg::AddArchive(archive)) => {
-              Self::upload(&s3_bucket, s3_client.clone(), archive).await;
+              let game_id = archive.game_id;
+              if Self::upload(&s3_bucket, s3_client.clone(), archive).await {
+                let url = format!("{}/{}", public_url, game_id);
+                if let Err(err) = controller.update_game_archive_url(game_id, url).await {
+                  tracing::error!(game_id, "update archive url: {}", err);
+                }
+              }
             },
             None => break,
           }
@@ -84,11 +119,43 @@ impl Archiver {
     }
   }
 
+  async fn put_lifecycle(bucket: &str, s3_client: &S3Client, days: i64) -> Result<()> {
+    use rusoto_s3::{
+      BucketLifecycleConfiguration, LifecycleExpiration, LifecycleRule, LifecycleRuleFilter,
+      PutBucketLifecycleConfigurationRequest,
+    };
+
+    s3_client
+      .put_bucket_lifecycle_configuration(PutBucketLifecycleConfigurationRequest {
+        bucket: bucket.to_string(),
+        lifecycle_configuration: Some(BucketLifecycleConfiguration {
+          rules: vec![LifecycleRule {
+            id: Some("flo-archive-expiration".to_string()),
+            status: "Enabled".to_string(),
+            filter: Some(LifecycleRuleFilter {
+              prefix: Some("".to_string()),
+              ..Default::default()
+            }),
+            expiration: Some(LifecycleExpiration {
+              days: Some(days),
+              ..Default::default()
+            }),
+            ..Default::default()
+          }],
+        }),
+        ..Default::default()
+      })
+      .await?;
+    tracing::info!("archives expire after {} days", days);
+    Ok(())
+  }
+
+  /// Returns `true` if the archive was uploaded
   async fn upload(
     bucket: &str,
     s3_client: Arc<S3Client>,
     ArchiveInfo { game_id, data, md5 }: ArchiveInfo,
-  ) {
+  ) -> bool {
     use futures::stream;
     use rusoto_core::{ByteStream, RusotoError};
     use rusoto_s3::PutObjectRequest;
@@ -117,7 +184,7 @@ impl Archiver {
           span.in_scope(|| {
             tracing::info!("uploaded: {} bytes", data.len());
           });
-          break
+          return true;
         },
         Err(RusotoError::HttpDispatch(err)) => {
           span.in_scope(|| {
@@ -135,7 +202,7 @@ impl Archiver {
           span.in_scope(|| {
             tracing::error!("upload: {:?}", err);
           });
-          break;
+          return false;
         }
       }
     }
@@ -155,7 +222,6 @@ impl ArchiverHandle {
     self.tx.try_send(Msg::AddArchive(archive)).is_ok()
   }
 
-  #[allow(unused)]
   pub async fn fetch(&self, game_id: i32) -> Result<Option<Vec<Bytes>>> {
     use futures::stream::StreamExt;
     use rusoto_core::RusotoError;
@@ -41,6 +41,17 @@ impl Controller {
       },
     }
   }
+
+  pub async fn update_game_archive_url(&self, game_id: i32, url: String) -> Result<()> {
+    use flo_grpc::controller::UpdateGameArchiveUrlRequest;
+    self
+      .client
+      .clone()
+      .update_game_archive_url(UpdateGameArchiveUrlRequest { game_id, url })
+      .await
+      .map_err(Error::ControllerService)?;
+    Ok(())
+  }
 }
 
 #[derive(Clone)]
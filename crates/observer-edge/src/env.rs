@@ -13,6 +13,12 @@ pub struct Env {
   pub aws_s3_bucket: Option<String>,
   pub aws_access_key_id: Option<String>,
   pub aws_secret_access_key: Option<String>,
+  /// Custom endpoint for S3-compatible storages such as minio
+  pub aws_s3_endpoint: Option<String>,
+  /// Base URL of uploaded archives, defaults to `<endpoint>/<bucket>`
+  pub aws_s3_public_url: Option<String>,
+  /// Expire uploaded archives after this many days
+  pub aws_s3_expiration_days: Option<i64>,
 }
 
 pub static ENV: Lazy<Env> = Lazy::new(|| {
@@ -32,5 +38,10 @@ pub static ENV: Lazy<Env> = Lazy::new(|| {
     aws_s3_bucket: env::var("AWS_S3_BUCKET").ok(),
     aws_access_key_id: env::var("AWS_ACCESS_KEY_ID").ok(),
     aws_secret_access_key: env::var("AWS_SECRET_ACCESS_KEY").ok(),
+    aws_s3_endpoint: env::var("AWS_S3_ENDPOINT").ok(),
+    aws_s3_public_url: env::var("AWS_S3_PUBLIC_URL").ok(),
+    aws_s3_expiration_days: env::var("AWS_S3_EXPIRATION_DAYS")
+      .ok()
+      .and_then(|v| v.parse().ok()),
   }
 });
@@ -35,6 +35,8 @@ pub enum Error {
   Net(#[from] flo_net::error::Error),
   #[error("get archived object: {0}")]
   GetArchivedObject(#[from] RusotoError<rusoto_s3::GetObjectError>),
+  #[error("put bucket lifecycle: {0}")]
+  PutBucketLifecycle(#[from] RusotoError<rusoto_s3::PutBucketLifecycleConfigurationError>),
   #[error("invalid S3 credentials: {0}")]
   InvalidS3Credentials(&'static str),
 }
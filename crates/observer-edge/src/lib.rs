@@ -10,7 +10,7 @@ mod services;
 mod version;
 mod archiver;
 
-use crate::archiver::Archiver;
+use crate::archiver::{Archiver, ArchiverHandle};
 use crate::broadcast::BroadcastReceiver;
 use dispatcher::{
   AddIterator, Dispatcher, GetGame, ListGames, SubscribeGameListUpdate, SubscribeGameUpdate,
@@ -20,6 +20,7 @@ use flo_kinesis::{data_stream::DataStream, iterator::ShardIteratorType};
 use flo_state::{Actor, Addr, Owner};
 use game::event::{GameListUpdateEvent, GameUpdateEvent};
 use game::snapshot::{GameSnapshot, GameSnapshotWithStats};
+use bytes::Bytes;
 use server::StreamServer;
 use services::Services;
 use std::time::Duration;
@@ -28,12 +29,13 @@ pub struct FloObserverEdge {
   dispatcher: Owner<Dispatcher>,
   stream_server: StreamServer,
   archiver: Option<Archiver>,
+  archiver_handle: Option<ArchiverHandle>,
 }
 
 impl FloObserverEdge {
   pub async fn from_env() -> Result<Self> {
     let mut services = Services::from_env();
-    let archiver = if let Some((archiver, handle)) = Archiver::new()? {
+    let archiver = if let Some((archiver, handle)) = Archiver::new(services.controller.clone())? {
       services.archiver = Some(handle);
       tracing::debug!("archiver enabled.");
       Some(archiver)
@@ -41,6 +43,7 @@ impl FloObserverEdge {
       tracing::debug!("archiver disabled.");
       None
     };
+    let archiver_handle = services.archiver.clone();
     let dispatcher = Dispatcher::new(services).start();
 
     let data_stream = DataStream::from_env();
@@ -69,6 +72,7 @@ impl FloObserverEdge {
       dispatcher,
       stream_server,
       archiver,
+      archiver_handle,
     })
   }
 
@@ -91,14 +95,25 @@ impl FloObserverEdge {
   }
 
   pub fn handle(&self) -> FloObserverEdgeHandle {
-    FloObserverEdgeHandle(self.dispatcher.addr())
+    FloObserverEdgeHandle(self.dispatcher.addr(), self.archiver_handle.clone())
   }
 }
 
 #[derive(Clone)]
-pub struct FloObserverEdgeHandle(Addr<Dispatcher>);
+pub struct FloObserverEdgeHandle(Addr<Dispatcher>, Option<ArchiverHandle>);
 
 impl FloObserverEdgeHandle {
+  /// Downloads the archived records of a game, `None` if archiving is disabled or
+  /// the game was not archived
+  pub async fn fetch_archive(&self, game_id: i32) -> Result<Option<Bytes>> {
+    let archiver = if let Some(archiver) = self.1.as_ref() {
+      archiver
+    } else {
+      return Ok(None);
+    };
+    Ok(archiver.fetch(game_id).await?.map(|parts| parts.concat().into()))
+  }
+
   pub async fn list_games(&self) -> Result<Vec<GameSnapshot>> {
     self.0.send(ListGames).await.map_err(Into::into)
   }
@@ -12,22 +12,38 @@ use tokio_stream::StreamExt;
 
 pub struct StreamServer {
   listener: FloListener,
+  ws_listener: FloListener,
   dispatcher: Addr<Dispatcher>,
 }
 
 impl StreamServer {
   pub async fn new(dispatcher: Addr<Dispatcher>) -> Result<Self> {
-    let listener = FloListener::bind_v4(flo_constants::OBSERVER_SOCKET_PORT).await?;
+    let listener = FloListener::bind(flo_constants::OBSERVER_SOCKET_PORT).await?;
+    let ws_listener = FloListener::bind(flo_constants::OBSERVER_WEBSOCKET_PORT).await?;
     Ok(Self {
       listener,
+      ws_listener,
       dispatcher,
     })
   }
 
-  pub async fn serve(mut self) -> Result<()> {
-    while let Some(transport) = self.listener.incoming().try_next().await? {
+  pub async fn serve(self) -> Result<()> {
+    let Self {
+      mut listener,
+      mut ws_listener,
+      dispatcher,
+    } = self;
+    tokio::try_join!(
+      Self::serve_socket(&mut listener, dispatcher.clone()),
+      Self::serve_websocket(&mut ws_listener, dispatcher.clone())
+    )?;
+    Ok(())
+  }
+
+  async fn serve_socket(listener: &mut FloListener, dispatcher: Addr<Dispatcher>) -> Result<()> {
+    while let Some(transport) = listener.incoming().try_next().await? {
       let handler = Handler {
-        dispatcher: self.dispatcher.clone(),
+        dispatcher: dispatcher.clone(),
         transport,
       };
       tokio::spawn(async move {
@@ -38,6 +54,31 @@ impl StreamServer {
     }
     Ok(())
   }
+
+  /// Serves browsers, the protocol is the same as `serve_socket`
+  /// with every frame sent as a binary WebSocket message
+  async fn serve_websocket(listener: &mut FloListener, dispatcher: Addr<Dispatcher>) -> Result<()> {
+    loop {
+      let (socket, addr) = listener.accept_socket().await?;
+      let dispatcher = dispatcher.clone();
+      tokio::spawn(async move {
+        let transport = match FloStream::accept_websocket(socket).await {
+          Ok(transport) => transport,
+          Err(err) => {
+            tracing::debug!(%addr, "websocket handshake: {}", err);
+            return;
+          }
+        };
+        let handler = Handler {
+          dispatcher,
+          transport,
+        };
+        if let Err(err) = handler.run().await {
+          tracing::error!("websocket stream handler: {}", err);
+        }
+      });
+    }
+  }
 }
 
 struct Handler {
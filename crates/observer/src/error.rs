@@ -4,6 +4,8 @@ use thiserror::Error;
 pub enum Error {
   #[error("observer token expired")]
   ObserverTokenExpired,
+  #[error("replay token expired")]
+  ReplayTokenExpired,
   #[error("json web token: {0}")]
   JsonWebToken(#[from] jsonwebtoken::errors::Error),
 }
@@ -1,11 +1,17 @@
+#[cfg(feature = "service")]
 mod kinesis;
 pub mod record;
+#[cfg(feature = "service")]
 pub mod error;
+#[cfg(feature = "service")]
 pub mod token;
 
+#[cfg(feature = "service")]
 use once_cell::sync::Lazy;
 
+#[cfg(feature = "service")]
 pub use kinesis::KINESIS_CLIENT;
+#[cfg(feature = "service")]
 pub static KINESIS_STREAM_NAME: Lazy<String> = Lazy::new(|| {
   std::env::var("AWS_KINESIS_STREAM_NAME")
     .ok()
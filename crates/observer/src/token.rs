@@ -9,6 +9,9 @@ use crate::error::*;
 // 15mins
 const TOKEN_EXPIRATION_SECS: i64 = 60 * 15;
 const TOKEN_SUB: &str = "flo-observer";
+// 1hour
+pub const REPLAY_TOKEN_EXPIRATION_SECS: i64 = 60 * 60;
+const REPLAY_TOKEN_SUB: &str = "flo-replay";
 
 static JWT_SECRET_BASE64: Lazy<String> =
   Lazy::new(|| std::env::var("JWT_SECRET_BASE64").expect("env JWT_SECRET_BASE64"));
@@ -45,3 +48,41 @@ pub fn validate_observer_token(token: &str) -> Result<ObserverToken> {
       _ => e.into(),
     })
 }
+
+/// Grants downloading the archived records of a game
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReplayToken {
+  pub sub: String,
+  pub game_id: i32,
+  pub exp: usize,
+}
+
+/// Returns the token and its expiration as unix timestamp
+pub fn create_replay_token(game_id: i32) -> Result<(String, i64)> {
+  static ENCODING_KEY: Lazy<EncodingKey> = Lazy::new(|| {
+    EncodingKey::from_base64_secret(&JWT_SECRET_BASE64).expect("DecodingKey::from_base64_secret")
+  });
+
+  let exp = Utc::now().timestamp() + REPLAY_TOKEN_EXPIRATION_SECS;
+  let claims = ReplayToken {
+    sub: REPLAY_TOKEN_SUB.to_string(),
+    game_id,
+    exp: exp as usize,
+  };
+  let token = encode(&Header::default(), &claims, &ENCODING_KEY)?;
+  Ok((token, exp))
+}
+
+pub fn validate_replay_token(token: &str) -> Result<ReplayToken> {
+  let decoding_key = DecodingKey::from_base64_secret(&JWT_SECRET_BASE64)?;
+  let validation = Validation {
+    sub: Some(REPLAY_TOKEN_SUB.to_string()),
+    ..Default::default()
+  };
+  decode(token, &decoding_key, &validation)
+    .map(|data| data.claims)
+    .map_err(|e| match e.kind() {
+      ErrorKind::ExpiredSignature => Error::ReplayTokenExpired,
+      _ => e.into(),
+    })
+}
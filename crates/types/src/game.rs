@@ -17,6 +17,7 @@ pub struct PlayerSession {
   pub player: PlayerInfo,
   pub status: PlayerStatus,
   pub game_id: Option<i32>,
+  pub read_only: bool,
 }
 
 #[derive(Debug, S2ProtoUnpack, Serialize, Clone)]
@@ -24,6 +25,7 @@ pub struct PlayerSession {
 pub struct PlayerSessionUpdate {
   pub status: PlayerStatus,
   pub game_id: Option<i32>,
+  pub read_only: Option<bool>,
 }
 
 #[derive(Debug, S2ProtoEnum, PartialEq, Copy, Clone, Serialize)]
@@ -39,6 +41,7 @@ pub struct PlayerInfo {
   pub id: i32,
   pub name: String,
   pub source: PlayerSource,
+  pub clan_tag: Option<String>,
 }
 
 #[derive(Debug, S2ProtoEnum, PartialEq, Copy, Clone, Serialize, Deserialize)]
@@ -47,6 +50,8 @@ pub enum PlayerSource {
   Test = 0,
   BNet = 1,
   Api = 2,
+  Guest = 3,
+  OAuth = 4,
 }
 
 #[derive(Debug, S2ProtoEnum, PartialEq, Copy, Clone, Serialize)]
@@ -55,6 +60,7 @@ pub enum RejectReason {
   Unknown = 0,
   ClientVersionTooOld = 1,
   InvalidToken = 2,
+  SessionExists = 3,
 }
 
 #[derive(Debug, S2ProtoUnpack, Serialize)]
@@ -70,6 +76,10 @@ pub struct GameInfo {
   pub is_live: bool,
   pub random_seed: i32,
   pub created_by: Option<PlayerInfo>,
+  #[s2_grpc(proto_enum)]
+  pub game_mode: GameMode,
+  pub random_races: bool,
+  pub random_teams: bool,
 }
 
 #[derive(Debug, S2ProtoEnum, PartialEq, Copy, Clone, Serialize)]
@@ -83,6 +93,22 @@ pub enum GameStatus {
   Terminated = 5,
 }
 
+#[derive(Debug, S2ProtoEnum, PartialEq, Copy, Clone, Serialize, Deserialize)]
+#[s2_grpc(proto_enum_type = "flo_net::proto::flo_common::GameMode")]
+pub enum GameMode {
+  Melee = 0,
+  FFA = 1,
+  Custom = 2,
+}
+
+impl GameMode {
+  /// Whether players on the same team are allies.
+  /// In FFA games every other player is an opponent regardless of the team setting.
+  pub fn is_team_based(&self) -> bool {
+    *self != GameMode::FFA
+  }
+}
+
 #[derive(Debug, S2ProtoUnpack, Serialize, Clone)]
 #[s2_grpc(message_type = "flo_net::proto::flo_connect::Node")]
 pub struct Node {
@@ -91,6 +117,7 @@ pub struct Node {
   pub location: String,
   pub ip_addr: String,
   pub country_id: String,
+  pub ip_addr_v6: Option<String>,
 }
 
 #[derive(Debug, S2ProtoUnpack, Serialize)]
@@ -159,6 +186,22 @@ pub struct SlotSettings {
   pub race: Race,
 }
 
+impl SlotSettings {
+  /// W3GS only accepts handicap values of 50 to 100 in steps of 10
+  pub fn normalize_handicap(value: i32) -> Option<i32> {
+    if value >= 50 && value <= 100 {
+      Some(value - (value % 10))
+    } else {
+      None
+    }
+  }
+
+  /// Handicap value for the W3GS slot, `None` if out of the 50-100 range
+  pub fn lan_handicap(&self) -> Option<u8> {
+    Self::normalize_handicap(self.handicap).map(|value| value as u8)
+  }
+}
+
 impl Default for SlotSettings {
   fn default() -> Self {
     SlotSettings {
@@ -274,6 +317,7 @@ pub struct MapDetail {
   pub num_players: usize,
   pub players: Vec<MapPlayerOwned>,
   pub forces: Vec<MapForceOwned>,
+  pub flags: u32,
 }
 
 #[derive(Debug, Serialize)]
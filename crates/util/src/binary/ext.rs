@@ -125,9 +125,10 @@ where
   where
     TItem: BinDecode,
   {
-    self.check_size(TItem::MIN_SIZE * len)?;
+    self.check_size(TItem::MIN_SIZE.saturating_mul(len))?;
 
-    let mut items = Vec::with_capacity(len);
+    // `len` is read from the input, don't trust it for zero sized items
+    let mut items = Vec::with_capacity(std::cmp::min(len, self.remaining()));
     for _ in 0..len {
       items.push(TItem::decode(self)?)
     }
@@ -2,6 +2,7 @@ pub mod binary;
 pub mod chat;
 pub mod dword_string;
 pub mod error;
+pub mod name_pattern;
 pub mod stat_string;
 pub mod uptime;
 
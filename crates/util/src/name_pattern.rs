@@ -0,0 +1,63 @@
+//! Player name patterns used by the mute list.
+//! `*` matches any sequence of characters, `?` matches a single character,
+//! matching is case insensitive.
+
+const MAX_PATTERN_LEN: usize = 32;
+
+/// Returns `true` if `value` contains a wildcard
+pub fn is_pattern(value: &str) -> bool {
+  value.contains(|c| c == '*' || c == '?')
+}
+
+/// Trims and lowercases `value`.
+/// Returns `None` if it is too long or only contains wildcards, which would match every player
+pub fn normalize(value: &str) -> Option<String> {
+  let value = value.trim();
+  if value.len() > MAX_PATTERN_LEN || value.chars().all(|c| c == '*' || c == '?') {
+    return None;
+  }
+  Some(value.to_lowercase())
+}
+
+pub fn matches(pattern: &str, name: &str) -> bool {
+  let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+  let name: Vec<char> = name.to_lowercase().chars().collect();
+  let (mut p, mut n) = (0, 0);
+  // position of the last `*` and the name position it currently matches up to
+  let mut star: Option<(usize, usize)> = None;
+  while n < name.len() {
+    if p < pattern.len() && (pattern[p] == '?' || pattern[p] == name[n]) {
+      p += 1;
+      n += 1;
+    } else if p < pattern.len() && pattern[p] == '*' {
+      star = Some((p, n));
+      p += 1;
+    } else if let Some((star_p, star_n)) = star {
+      p = star_p + 1;
+      n = star_n + 1;
+      star = Some((star_p, star_n + 1));
+    } else {
+      return false;
+    }
+  }
+  pattern[p..].iter().all(|c| *c == '*')
+}
+
+#[test]
+fn test_name_pattern() {
+  assert!(is_pattern("smurf*"));
+  assert!(!is_pattern("smurf"));
+
+  assert_eq!(normalize(" Smurf* "), Some("smurf*".to_string()));
+  assert_eq!(normalize("*?*"), None);
+  assert_eq!(normalize(""), None);
+
+  assert!(matches("smurf*", "Smurf"));
+  assert!(matches("smurf*", "SMURF123"));
+  assert!(!matches("smurf*", "NotSmurf"));
+  assert!(matches("*smurf*", "NotSmurf2"));
+  assert!(matches("sm?rf", "smerf"));
+  assert!(!matches("sm?rf", "smrf"));
+  assert!(matches("a*b*c", "aXXbYYbc"));
+  assert!(!matches("a*b*c", "aXXbYYbd"));
+}
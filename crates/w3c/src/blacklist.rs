@@ -1,10 +1,56 @@
 use anyhow::{ Result, anyhow };
+use serde::{ Deserialize, Serialize };
+use std::fmt;
+use std::time::{ Duration, SystemTime, UNIX_EPOCH };
 
 use once_cell::sync::OnceCell;
 
 static SLED: &str = "blacklist.sled";
 static SLED_DB: OnceCell<sled::Db> = OnceCell::new();
 
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BlacklistEntry {
+  pub reason: String,
+  /// Reasons given when the player was blacklisted again, one per line
+  #[serde(default)]
+  pub notes: String,
+  /// Unix timestamp in seconds, `None` never expires
+  #[serde(default)]
+  pub expires_at: Option<u64>,
+}
+
+impl BlacklistEntry {
+  fn decode(bytes: &[u8]) -> Result<Self> {
+    if let Ok(entry) = serde_json::from_slice(bytes) {
+      return Ok(entry)
+    }
+    // entries saved before expiry support only contain the reason
+    Ok(BlacklistEntry {
+      reason: String::from_utf8(bytes.to_vec())?,
+      notes: String::new(),
+      expires_at: None,
+    })
+  }
+
+  pub fn is_expired(&self, now: u64) -> bool {
+    self.expires_at.map(|t| t <= now).unwrap_or(false)
+  }
+}
+
+impl fmt::Display for BlacklistEntry {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}", self.reason)?;
+    if let Some(expires_at) = self.expires_at {
+      let days = expires_at.saturating_sub(now()).saturating_add(86399) / 86400;
+      write!(f, ", expires in {}d", days)?;
+    }
+    if !self.notes.is_empty() {
+      write!(f, ", notes: {}", self.notes.lines().collect::<Vec<_>>().join(" / "))?;
+    }
+    Ok(())
+  }
+}
+
 fn get_db_handle() -> Result<&'static sled::Db> {
   if let Some(existing_handle) = SLED_DB.get() {
     Ok(existing_handle)
@@ -15,12 +61,45 @@ fn get_db_handle() -> Result<&'static sled::Db> {
   }
 }
 
-pub fn read(target: &str) -> Result<Option<String>> {
+fn now() -> u64 {
+  SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map(|d| d.as_secs())
+    .unwrap_or_default()
+}
+
+/// Parses durations like `45m`, `12h`, `30d` or `2w`
+pub fn parse_duration(value: &str) -> Option<Duration> {
+  if value.len() < 2 || !value.is_ascii() {
+    return None
+  }
+  let (n, unit) = value.split_at(value.len() - 1);
+  let n: u64 = n.parse().ok()?;
+  let secs = match unit {
+    "m" => 60,
+    "h" => 3600,
+    "d" => 86400,
+    "w" => 86400 * 7,
+    _ => return None,
+  };
+  Some(Duration::from_secs(n.checked_mul(secs)?))
+}
+
+/// Huge durations never overflow, the entry expires at `u64::MAX`
+fn expires_at(now: u64, duration: Option<Duration>) -> Option<u64> {
+  duration.map(|d| now.saturating_add(d.as_secs()))
+}
+
+/// Returns `None` if the player is not blacklisted or the entry expired
+pub fn read(target: &str) -> Result<Option<BlacklistEntry>> {
   let sled = get_db_handle()?;
   if let Some(val) = sled.get(target)? {
-    Ok(Some(
-      String::from_utf8(val.to_vec())?
-    ))
+    let entry = BlacklistEntry::decode(&val)?;
+    if entry.is_expired(now()) {
+      Ok(None)
+    } else {
+      Ok(Some(entry))
+    }
   } else {
     Ok(None)
   }
@@ -28,20 +107,41 @@ pub fn read(target: &str) -> Result<Option<String>> {
 
 pub fn blacklisted() -> Result<String> {
   let sled = get_db_handle()?;
+  let now = now();
   let mut result = vec![];
-  for key in sled.iter().keys() {
-    if let Ok(k) = key {
-      if let Ok(kk) = String::from_utf8(k.to_vec()) {
-        result.push(kk);
-      }
+  for (k, v) in sled.iter().flatten() {
+    if BlacklistEntry::decode(&v).map(|e| e.is_expired(now)).unwrap_or(false) {
+      continue;
+    }
+    if let Ok(kk) = String::from_utf8(k.to_vec()) {
+      result.push(kk);
     }
   }
   Ok(result.join(", "))
 }
 
-pub fn blacklist(target: &str, reason: &str) -> Result<()> {
+/// Blacklisting a player again keeps the first reason and adds the new one to the notes.
+/// The expiry is replaced by `duration`.
+pub fn blacklist(target: &str, reason: &str, duration: Option<Duration>) -> Result<()> {
   let sled = get_db_handle()?;
-  sled.insert(target, reason)?;
+  let now = now();
+  let expires_at = expires_at(now, duration);
+  let entry = match read(target)? {
+    Some(mut entry) => {
+      if !entry.notes.is_empty() {
+        entry.notes.push('\n');
+      }
+      entry.notes.push_str(reason);
+      entry.expires_at = expires_at;
+      entry
+    }
+    None => BlacklistEntry {
+      reason: reason.to_string(),
+      notes: String::new(),
+      expires_at,
+    },
+  };
+  sled.insert(target, serde_json::to_vec(&entry)?)?;
   Ok(())
 }
 
@@ -50,3 +150,65 @@ pub fn unblacklist(target: &str) -> Result<()> {
   sled.remove(target)?;
   Ok(())
 }
+
+/// Removes expired entries, returns the number of removed entries
+pub fn prune_expired() -> Result<usize> {
+  let sled = get_db_handle()?;
+  let now = now();
+  let mut removed = 0;
+  for item in sled.iter() {
+    let (k, v) = item?;
+    if BlacklistEntry::decode(&v).map(|e| e.is_expired(now)).unwrap_or(false) {
+      sled.remove(k)?;
+      removed += 1;
+    }
+  }
+  Ok(removed)
+}
+
+#[test]
+fn test_blacklist_entry() {
+  assert_eq!(parse_duration("30d"), Some(Duration::from_secs(30 * 86400)));
+  assert_eq!(parse_duration("2w"), Some(Duration::from_secs(14 * 86400)));
+  assert_eq!(parse_duration("45m"), Some(Duration::from_secs(45 * 60)));
+  assert_eq!(parse_duration("d"), None);
+  assert_eq!(parse_duration("griefing"), None);
+
+  let legacy = BlacklistEntry::decode(b"griefing").unwrap();
+  assert_eq!(legacy.reason, "griefing");
+  assert!(!legacy.is_expired(now()));
+
+  let entry = BlacklistEntry {
+    reason: "griefing".to_string(),
+    notes: "left early\nflamed".to_string(),
+    expires_at: Some(100),
+  };
+  let decoded = BlacklistEntry::decode(&serde_json::to_vec(&entry).unwrap()).unwrap();
+  assert_eq!(decoded, entry);
+  assert!(entry.is_expired(100));
+  assert!(!entry.is_expired(99));
+  assert_eq!(
+    BlacklistEntry {
+      expires_at: None,
+      ..entry
+    }
+    .to_string(),
+    "griefing, notes: left early / flamed"
+  );
+}
+
+#[test]
+fn test_blacklist_huge_duration() {
+  let now = now();
+  assert_eq!(expires_at(now, None), None);
+  assert_eq!(expires_at(now, Some(Duration::from_secs(60))), Some(now + 60));
+  assert_eq!(expires_at(now, Some(Duration::from_secs(u64::MAX))), Some(u64::MAX));
+
+  let entry = BlacklistEntry {
+    reason: "griefing".to_string(),
+    notes: String::new(),
+    expires_at: Some(u64::MAX),
+  };
+  assert!(!entry.is_expired(now));
+  assert!(entry.to_string().starts_with("griefing, expires in "));
+}
@@ -0,0 +1,65 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use flo_util::binary::{Bytes, BytesMut};
+use flo_w3gs::action::{IncomingAction, PlayerAction, TimeSlot};
+use flo_w3gs::packet::Packet;
+
+/// Tick sizes of an idle game, a busy team fight and a flood of actions
+const ACTION_COUNTS: &[usize] = &[0, 24, 96];
+
+fn time_slot(actions: usize) -> TimeSlot {
+  TimeSlot {
+    time_increment_ms: 30,
+    actions: (0..actions)
+      .map(|i| PlayerAction {
+        player_id: (i % 24) as u8 + 1,
+        data: Bytes::from(vec![0x16; 13]),
+      })
+      .collect(),
+  }
+}
+
+fn encode(c: &mut Criterion) {
+  let mut group = c.benchmark_group("w3gs_encode");
+  for &count in ACTION_COUNTS {
+    let packet = Packet::with_payload(IncomingAction(time_slot(count))).unwrap();
+    group.throughput(Throughput::Bytes(packet.get_encode_len() as u64));
+    group.bench_with_input(BenchmarkId::new("packet", count), &count, |b, &count| {
+      let mut buf = BytesMut::with_capacity(4096);
+      b.iter(|| {
+        buf.clear();
+        Packet::with_payload(IncomingAction(time_slot(count)))
+          .unwrap()
+          .encode(&mut buf);
+        black_box(&buf);
+      })
+    });
+  }
+  group.finish();
+}
+
+fn decode(c: &mut Criterion) {
+  let mut group = c.benchmark_group("w3gs_decode");
+  for &count in ACTION_COUNTS {
+    let packet = Packet::with_payload(IncomingAction(time_slot(count))).unwrap();
+    let mut bytes = BytesMut::new();
+    packet.encode(&mut bytes);
+    let bytes = bytes.freeze();
+    group.throughput(Throughput::Bytes(bytes.len() as u64));
+
+    group.bench_with_input(BenchmarkId::new("header", count), &bytes, |b, bytes| {
+      b.iter(|| {
+        let mut buf = BytesMut::from(&bytes[..]);
+        let header = Packet::decode_header(&mut buf).unwrap();
+        black_box(Packet::decode(header, &mut buf).unwrap())
+      })
+    });
+
+    group.bench_with_input(BenchmarkId::new("payload", count), &packet, |b, packet| {
+      b.iter(|| black_box(packet.decode_payload::<IncomingAction>().unwrap()))
+    });
+  }
+  group.finish();
+}
+
+criterion_group!(benches, encode, decode);
+criterion_main!(benches);
@@ -0,0 +1,26 @@
+//! Decodes the input as the action data of a single player, and runs it through the analyzers
+#![no_main]
+
+use bytes::Bytes;
+use flo_w3gs::action::PlayerAction;
+use flo_w3gs::analysis::{ActionCategory, ApmBreakdown, BuildOrder};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+  let action = PlayerAction {
+    player_id: 0,
+    data: Bytes::copy_from_slice(data),
+  };
+
+  for res in action.actions() {
+    match res {
+      Ok(action) => {
+        ActionCategory::of(&action);
+      }
+      Err(_) => break,
+    }
+  }
+
+  ApmBreakdown::default().add_player_action(&action);
+  BuildOrder::default().add_player_action(0, &action);
+});
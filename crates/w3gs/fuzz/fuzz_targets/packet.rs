@@ -0,0 +1,137 @@
+//! Splits the input into W3GS packets like `W3GSCodec`, and decodes the payload of each packet
+#![no_main]
+
+use bytes::BytesMut;
+use flo_util::binary::BinDecode;
+use flo_w3gs::action::{
+  IncomingAction, IncomingAction2, OutgoingAction, OutgoingKeepAlive, PlayerAction,
+};
+use flo_w3gs::chat::{ChatFromHost, ChatFromOthers, ChatToHost};
+use flo_w3gs::constants::PacketTypeId;
+use flo_w3gs::desync::Desync;
+use flo_w3gs::game::{GameLoadedSelf, GameSettings, PlayerLoaded};
+use flo_w3gs::join::{RejectJoin, ReqJoin, SlotInfoJoin};
+use flo_w3gs::lag::{StartLag, StopLag};
+use flo_w3gs::leave::{LeaveAck, LeaveReq, PlayerKicked, PlayerLeft};
+use flo_w3gs::map::{MapCheck, MapSize};
+use flo_w3gs::packet::{Packet, ProtoBufPayload};
+use flo_w3gs::ping::{PingFromHost, PongToHost};
+use flo_w3gs::player::PlayerInfo;
+use flo_w3gs::slot::SlotInfo;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+  GameSettings::decode(&mut &data[..]).ok();
+
+  let mut buf = BytesMut::from(data);
+  while !buf.is_empty() {
+    let packet = match Packet::decode_header(&mut buf).and_then(|h| Packet::decode(h, &mut buf)) {
+      Ok(packet) => packet,
+      Err(_) => break,
+    };
+    decode_payload(&packet);
+  }
+});
+
+fn decode_payload(packet: &Packet) {
+  match packet.type_id() {
+    PacketTypeId::PingFromHost => {
+      packet.decode_simple::<PingFromHost>().ok();
+    }
+    PacketTypeId::PongToHost => {
+      packet.decode_simple::<PongToHost>().ok();
+    }
+    PacketTypeId::SlotInfoJoin => {
+      packet.decode_simple::<SlotInfoJoin>().ok();
+    }
+    PacketTypeId::RejectJoin => {
+      packet.decode_simple::<RejectJoin>().ok();
+    }
+    PacketTypeId::PlayerInfo => {
+      packet.decode_simple::<PlayerInfo>().ok();
+    }
+    PacketTypeId::PlayerLeft => {
+      packet.decode_simple::<PlayerLeft>().ok();
+    }
+    PacketTypeId::PlayerLoaded => {
+      packet.decode_simple::<PlayerLoaded>().ok();
+    }
+    PacketTypeId::SlotInfo => {
+      packet.decode_simple::<SlotInfo>().ok();
+    }
+    PacketTypeId::IncomingAction => {
+      if let Ok(IncomingAction(time_slot)) = packet.decode_payload() {
+        decode_actions(&time_slot.actions);
+      }
+    }
+    PacketTypeId::ChatFromHost => {
+      packet.decode_simple::<ChatFromHost>().ok();
+    }
+    PacketTypeId::StartLag => {
+      packet.decode_simple::<StartLag>().ok();
+    }
+    PacketTypeId::StopLag => {
+      packet.decode_simple::<StopLag>().ok();
+    }
+    PacketTypeId::Desync => {
+      packet.decode_simple::<Desync>().ok();
+    }
+    PacketTypeId::ReqJoin => {
+      packet.decode_simple::<ReqJoin>().ok();
+    }
+    PacketTypeId::LeaveReq => {
+      packet.decode_simple::<LeaveReq>().ok();
+    }
+    PacketTypeId::LeaveAck => {
+      packet.decode_simple::<LeaveAck>().ok();
+    }
+    PacketTypeId::GameLoadedSelf => {
+      packet.decode_simple::<GameLoadedSelf>().ok();
+    }
+    PacketTypeId::OutgoingAction => {
+      if let Ok(payload) = packet.decode_payload::<OutgoingAction>() {
+        decode_actions(&[PlayerAction {
+          player_id: 0,
+          data: payload.data,
+        }]);
+      }
+    }
+    PacketTypeId::OutgoingKeepAlive => {
+      packet.decode_simple::<OutgoingKeepAlive>().ok();
+    }
+    PacketTypeId::ChatToHost => {
+      packet.decode_simple::<ChatToHost>().ok();
+    }
+    PacketTypeId::MapCheck => {
+      packet.decode_simple::<MapCheck>().ok();
+    }
+    PacketTypeId::MapSize => {
+      packet.decode_simple::<MapSize>().ok();
+    }
+    PacketTypeId::PlayerKicked => {
+      packet.decode_simple::<PlayerKicked>().ok();
+    }
+    PacketTypeId::IncomingAction2 => {
+      if let Ok(IncomingAction2(time_slot)) = packet.decode_payload() {
+        decode_actions(&time_slot.actions);
+      }
+    }
+    PacketTypeId::ChatFromOthers => {
+      packet.decode_simple::<ChatFromOthers>().ok();
+    }
+    PacketTypeId::ProtoBuf => {
+      packet.decode_simple::<ProtoBufPayload>().ok();
+    }
+    _ => {}
+  }
+}
+
+fn decode_actions(actions: &[PlayerAction]) {
+  for action in actions {
+    for res in action.actions() {
+      if res.is_err() {
+        break;
+      }
+    }
+  }
+}
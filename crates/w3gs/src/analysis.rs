@@ -0,0 +1,237 @@
+//! Interpretation of decoded actions: ability ids, action categories and build orders.
+//!
+//! Enabled by the `analysis` feature.
+
+use crate::actions::{Action, ActionTypeId};
+use crate::protocol::action::PlayerAction;
+use std::fmt;
+
+/// The `item_id` of unit and building abilities.
+/// Either a four character object id (`hpea`, `hbar`, ...) or a numeric order id
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ItemId(pub u32);
+
+impl ItemId {
+  pub const SMART: ItemId = ItemId(0xD0003);
+  pub const STOP: ItemId = ItemId(0xD0004);
+  pub const ATTACK: ItemId = ItemId(0xD000F);
+  pub const MOVE: ItemId = ItemId(0xD0012);
+  pub const PATROL: ItemId = ItemId(0xD0016);
+  pub const HOLD_POSITION: ItemId = ItemId(0xD0019);
+
+  /// Four character id of the unit, building, upgrade or item
+  pub fn object_id(&self) -> Option<[u8; 4]> {
+    let bytes = self.0.to_be_bytes();
+    if bytes.iter().all(|b| b.is_ascii_alphanumeric()) {
+      Some(bytes)
+    } else {
+      None
+    }
+  }
+
+  pub fn is_order(&self) -> bool {
+    self.0 & 0xFFFF0000 == 0x000D0000
+  }
+}
+
+impl fmt::Display for ItemId {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    if let Some(id) = self.object_id() {
+      // object ids are always ascii
+      write!(f, "{}", std::str::from_utf8(&id).unwrap_or_default())
+    } else {
+      write!(f, "0x{:X}", self.0)
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ActionCategory {
+  /// Abilities, orders, training and construction
+  Order,
+  Selection,
+  Hotkey,
+  /// Other player-issued actions, e.g. minimap signals or resource transfers
+  Other,
+}
+
+impl ActionCategory {
+  /// Category of an action, `None` if it isn't issued by the player
+  /// and shouldn't count toward APM
+  pub fn of(action: &Action) -> Option<Self> {
+    use ActionTypeId::*;
+    let category = match action.type_id() {
+      UnitBuildingAbility
+      | UnitBuildingAbilityTargeted
+      | UnitBuildingAbilityTargetedId
+      | ItemGivenDropped
+      | UnitBuildingAbility2Targets2Items
+      | RemoveUnitFromBuildingQueue
+      | CancelHeroRevival => ActionCategory::Order,
+      ChangeSelection | SelectGroundItem => ActionCategory::Selection,
+      AssignGroupHotkey | SelectGroupHotkey => ActionCategory::Hotkey,
+      ChangeAllyOptions
+      | TransferResources
+      | MapTriggerChatCommand
+      | EscPressed
+      | EnterChooseHeroSkillSubmenu
+      | EnterChooseBuildingSubmenu
+      | MinimapSignal => ActionCategory::Other,
+      _ => return None,
+    };
+    Some(category)
+  }
+}
+
+impl Action {
+  /// The ability or object id of unit and building abilities
+  pub fn item_id(&self) -> Option<ItemId> {
+    let id = match *self {
+      Action::UnitBuildingAbility(ref v) => v.item_id,
+      Action::UnitBuildingAbilityTargeted(ref v) => v.item_id,
+      Action::UnitBuildingAbilityTargetedId(ref v) => v.item_id,
+      Action::ItemGivenDropped(ref v) => v.item_id,
+      Action::UnitBuildingAbility2Targets2Items(ref v) => v.item_id,
+      Action::RemoveUnitFromBuildingQueue(ref v) => v.item_id,
+      _ => return None,
+    };
+    Some(ItemId(id))
+  }
+}
+
+/// Action counts of a player by category
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ApmBreakdown {
+  pub orders: u32,
+  pub selections: u32,
+  pub hotkeys: u32,
+  pub other: u32,
+}
+
+impl ApmBreakdown {
+  pub fn add(&mut self, action: &Action) {
+    match ActionCategory::of(action) {
+      Some(ActionCategory::Order) => self.orders += 1,
+      Some(ActionCategory::Selection) => self.selections += 1,
+      Some(ActionCategory::Hotkey) => self.hotkeys += 1,
+      Some(ActionCategory::Other) => self.other += 1,
+      None => {}
+    }
+  }
+
+  /// Adds all actions of a player action block, stops at the first undecodable action
+  pub fn add_player_action(&mut self, action: &PlayerAction) {
+    for action in action.actions() {
+      match action {
+        Ok(action) => self.add(&action),
+        Err(_) => break,
+      }
+    }
+  }
+
+  pub fn total(&self) -> u32 {
+    self.orders + self.selections + self.hotkeys + self.other
+  }
+
+  pub fn apm(&self, duration_ms: u32) -> u32 {
+    if duration_ms == 0 {
+      return 0;
+    }
+    (self.total() as u64 * 60_000 / duration_ms as u64) as u32
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BuildOrderEntry {
+  /// Game time
+  pub time_ms: u32,
+  pub item_id: ItemId,
+}
+
+/// Units, buildings and upgrades queued by a player, in order
+#[derive(Debug, Default, Clone)]
+pub struct BuildOrder {
+  pub entries: Vec<BuildOrderEntry>,
+}
+
+impl BuildOrder {
+  pub fn add(&mut self, time_ms: u32, action: &Action) {
+    let item_id = match *action {
+      // train, research and upgrade
+      Action::UnitBuildingAbility(ref v) => ItemId(v.item_id),
+      // construction
+      Action::UnitBuildingAbilityTargeted(ref v) => ItemId(v.item_id),
+      _ => return,
+    };
+    if item_id.object_id().is_some() {
+      self.entries.push(BuildOrderEntry { time_ms, item_id });
+    }
+  }
+
+  pub fn add_player_action(&mut self, time_ms: u32, action: &PlayerAction) {
+    for action in action.actions() {
+      match action {
+        Ok(action) => self.add(time_ms, &action),
+        Err(_) => break,
+      }
+    }
+  }
+}
+
+#[test]
+fn test_item_id() {
+  let peasant = ItemId(u32::from_le_bytes(*b"aeph"));
+  assert_eq!(peasant.object_id(), Some(*b"hpea"));
+  assert_eq!(peasant.to_string(), "hpea");
+  assert!(!peasant.is_order());
+  assert!(ItemId::MOVE.is_order());
+  assert_eq!(ItemId::MOVE.object_id(), None);
+  assert_eq!(ItemId::MOVE.to_string(), "0xD0012");
+}
+
+#[test]
+fn test_analysis() {
+  use flo_util::binary::{BufMut, BytesMut};
+
+  let mut buf = BytesMut::new();
+  // train a peasant
+  buf.put_u8(0x10);
+  buf.put_u16_le(0x40);
+  buf.put_slice(b"aeph");
+  buf.put_u32_le(0xFFFFFFFF);
+  buf.put_u32_le(0xFFFFFFFF);
+  // select group 1
+  buf.put_u8(0x18);
+  buf.put_u8(1);
+  buf.put_u8(0);
+  // pause
+  buf.put_u8(0x01);
+
+  let action = PlayerAction {
+    player_id: 1,
+    data: buf.freeze(),
+  };
+
+  let mut apm = ApmBreakdown::default();
+  apm.add_player_action(&action);
+  assert_eq!(
+    apm,
+    ApmBreakdown {
+      orders: 1,
+      selections: 0,
+      hotkeys: 1,
+      other: 0,
+    }
+  );
+  assert_eq!(apm.apm(30_000), 4);
+
+  let mut build_order = BuildOrder::default();
+  build_order.add_player_action(1000, &action);
+  assert_eq!(
+    build_order.entries,
+    vec![BuildOrderEntry {
+      time_ms: 1000,
+      item_id: ItemId(u32::from_le_bytes(*b"aeph")),
+    }]
+  );
+}
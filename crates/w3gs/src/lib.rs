@@ -1,6 +1,9 @@
 pub mod error;
+#[cfg(feature = "net")]
 pub mod net;
 pub mod protocol;
 
 pub use protocol::*;
 pub mod actions;
+#[cfg(feature = "analysis")]
+pub mod analysis;
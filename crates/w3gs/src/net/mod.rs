@@ -2,6 +2,7 @@ use futures::sink::SinkExt;
 use futures::stream::TryStreamExt;
 use futures::{ready, StreamExt};
 use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+use std::ops::RangeInclusive;
 use std::pin::Pin;
 use std::task::{Context, Poll};
 use tokio::net::{TcpListener, TcpStream, ToSocketAddrs};
@@ -22,7 +23,11 @@ pub struct W3GSListener {
 
 impl W3GSListener {
   pub async fn bind() -> Result<Self, Error> {
-    let listener = TcpListener::bind(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 0)).await?;
+    Self::bind_port(0).await
+  }
+
+  pub async fn bind_port(port: u16) -> Result<Self, Error> {
+    let listener = TcpListener::bind(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, port)).await?;
     let local_addr = listener.local_addr()?;
     Ok(W3GSListener {
       listener,
@@ -30,6 +35,20 @@ impl W3GSListener {
     })
   }
 
+  /// Binds the first available port in the range
+  pub async fn bind_port_range(range: RangeInclusive<u16>) -> Result<Self, Error> {
+    let mut last_err = None;
+    for port in range {
+      match Self::bind_port(port).await {
+        Ok(listener) => return Ok(listener),
+        Err(err) => last_err = Some(err),
+      }
+    }
+    Err(last_err.unwrap_or_else(|| {
+      std::io::Error::new(std::io::ErrorKind::InvalidInput, "empty port range").into()
+    }))
+  }
+
   pub fn incoming(&mut self) -> Incoming {
     Incoming::new(&mut self.listener)
   }
@@ -356,7 +356,12 @@ impl Iterator for ActionIter {
 
   fn next(&mut self) -> Option<Self::Item> {
     if self.data.has_remaining() {
-      Some(Action::decode(&mut self.data).map_err(Into::into))
+      let res = Action::decode(&mut self.data);
+      if res.is_err() {
+        // the rest of the data can not be split into actions
+        self.data.clear();
+      }
+      Some(res.map_err(Into::into))
     } else {
       None
     }
@@ -448,3 +453,14 @@ fn test_incoming_action2_split_chunk_30() {
     30_usize
   )
 }
+
+#[test]
+fn test_action_iter_stops_on_error() {
+  let action = PlayerAction {
+    player_id: 1,
+    data: Bytes::from_static(&[0xFF, 0x01, 0x01]),
+  };
+  let mut iter = action.actions();
+  assert!(iter.next().unwrap().is_err());
+  assert!(iter.next().is_none());
+}
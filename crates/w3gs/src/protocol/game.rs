@@ -92,6 +92,11 @@ impl BinDecode for GameSettings {
     let cstr = CString::decode(buf)?;
     let data = flo_util::stat_string::decode(cstr.as_bytes());
 
+    // the encoded string can be terminated early by a null byte
+    if data.len() < min_len {
+      return Err(BinDecodeError::incomplete());
+    }
+
     let mut buf = &data[..];
 
     let game_setting_flags = buf.get_u32_le();
@@ -166,6 +171,14 @@ impl PacketPayload for PlayerLoaded {
   const PACKET_TYPE_ID: PacketTypeId = PacketTypeId::PlayerLoaded;
 }
 
+#[test]
+fn test_game_settings_truncated() {
+  // a short stat string followed by enough bytes to pass the length check
+  let mut data = vec![0x01, 0x01, 0x00];
+  data.resize(64, 0x01);
+  assert!(GameSettings::decode(&mut data.as_slice()).is_err());
+}
+
 #[test]
 fn test_count_down_start() {
   crate::packet::test_simple_payload_type("count_down_start.bin", &CountDownStart)
@@ -0,0 +1,2 @@
+alter table game
+    drop column game_mode;
\ No newline at end of file
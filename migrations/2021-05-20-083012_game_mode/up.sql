@@ -0,0 +1,2 @@
+alter table game
+    add column game_mode integer default 0 not null;
\ No newline at end of file
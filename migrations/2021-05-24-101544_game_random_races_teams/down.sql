@@ -0,0 +1,3 @@
+alter table game
+    drop column random_races,
+    drop column random_teams;
\ No newline at end of file
@@ -0,0 +1,3 @@
+alter table game
+    add column random_races boolean default false not null,
+    add column random_teams boolean default false not null;
\ No newline at end of file
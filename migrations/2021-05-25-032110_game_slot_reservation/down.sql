@@ -0,0 +1 @@
+drop table game_slot_reservation;
\ No newline at end of file
@@ -0,0 +1,11 @@
+create table game_slot_reservation (
+    id serial not null primary key,
+    game_id integer not null references game(id) on delete cascade,
+    player_id integer not null references player(id) on delete cascade,
+    slot_index integer not null,
+    created_at timestamp with time zone default now() not null,
+    unique(game_id, player_id),
+    unique(game_id, slot_index)
+);
+
+create index game_slot_reservation_game_id on game_slot_reservation(game_id);
@@ -0,0 +1 @@
+alter table node drop column ip_addr_v6;
@@ -0,0 +1 @@
+alter table node add column ip_addr_v6 text;
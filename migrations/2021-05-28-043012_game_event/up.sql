@@ -0,0 +1,8 @@
+create table game_event (
+  id bigserial not null primary key,
+  game_id integer not null references game(id) on delete cascade,
+  data jsonb not null,
+  created_at timestamp with time zone default now() not null
+);
+
+create index game_event_game_id on game_event(game_id, id);
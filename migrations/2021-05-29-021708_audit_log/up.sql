@@ -0,0 +1,11 @@
+create table audit_log (
+  id serial not null primary key,
+  api_client_id integer not null references api_client(id),
+  actor text,
+  action integer not null,
+  target_id integer,
+  reason text,
+  created_at timestamp with time zone default now() not null
+);
+
+create index audit_log_api_client_id on audit_log(api_client_id);
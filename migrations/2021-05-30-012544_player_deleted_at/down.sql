@@ -0,0 +1 @@
+alter table player drop column deleted_at;
@@ -0,0 +1 @@
+alter table player add column deleted_at timestamp with time zone;
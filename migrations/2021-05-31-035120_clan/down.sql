@@ -0,0 +1,4 @@
+alter table game drop column clan_id;
+alter table player drop column clan_tag;
+drop table clan_member;
+drop table clan;
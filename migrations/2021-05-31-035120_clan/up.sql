@@ -0,0 +1,24 @@
+create table clan (
+  id serial not null primary key,
+  api_client_id integer not null references api_client(id),
+  name text not null,
+  tag text not null,
+  created_at timestamp with time zone default now() not null,
+  updated_at timestamp with time zone default now() not null,
+  unique(api_client_id, tag)
+);
+
+SELECT diesel_manage_updated_at('clan');
+
+create table clan_member (
+  id serial not null primary key,
+  clan_id integer not null references clan(id) on delete cascade,
+  player_id integer not null references player(id) on delete cascade,
+  created_at timestamp with time zone default now() not null,
+  unique(player_id)
+);
+
+create index clan_member_clan_id on clan_member(clan_id);
+
+alter table player add column clan_tag text;
+alter table game add column clan_id integer references clan(id) on delete set null;
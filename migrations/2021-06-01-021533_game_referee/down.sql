@@ -0,0 +1 @@
+alter table game drop column referee_player_ids;
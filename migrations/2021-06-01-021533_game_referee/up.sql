@@ -0,0 +1 @@
+alter table game add column referee_player_ids integer[] not null default '{}';
@@ -0,0 +1,2 @@
+drop table game_player_stats;
+alter table game drop column duration_ms;
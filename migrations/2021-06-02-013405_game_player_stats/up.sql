@@ -0,0 +1,15 @@
+alter table game add column duration_ms integer;
+
+create table game_player_stats (
+  game_id integer not null references game(id) on delete cascade,
+  player_id integer not null references player(id) on delete cascade,
+  actions integer not null,
+  apm integer not null,
+  pauses integer not null,
+  left_at_ms integer,
+  first_leaver boolean not null default false,
+  created_at timestamp with time zone default now() not null,
+  primary key (game_id, player_id)
+);
+
+create index game_player_stats_player_id on game_player_stats(player_id);
@@ -0,0 +1 @@
+drop table action_incident;
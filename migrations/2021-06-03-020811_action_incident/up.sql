@@ -0,0 +1,13 @@
+create table action_incident (
+  id serial not null primary key,
+  game_id integer not null references game(id) on delete cascade,
+  player_id integer not null references player(id) on delete cascade,
+  node_id integer not null references node(id),
+  kind integer not null,
+  time_ms integer not null,
+  detail text not null,
+  created_at timestamp with time zone default now() not null
+);
+
+create index action_incident_player_id on action_incident(player_id);
+create index action_incident_game_id on action_incident(game_id);
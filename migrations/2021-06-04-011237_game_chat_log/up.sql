@@ -0,0 +1,13 @@
+create table game_chat_log (
+  id bigserial not null primary key,
+  game_id integer not null references game(id) on delete cascade,
+  player_id integer not null references player(id) on delete cascade,
+  scope integer not null,
+  to_player_id integer references player(id) on delete set null,
+  time_ms integer not null,
+  message text not null,
+  created_at timestamp with time zone default now() not null
+);
+
+create index game_chat_log_game_id on game_chat_log(game_id);
+create index game_chat_log_player_id on game_chat_log(player_id);
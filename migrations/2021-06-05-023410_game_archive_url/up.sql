@@ -0,0 +1 @@
+alter table game add column archive_url text;
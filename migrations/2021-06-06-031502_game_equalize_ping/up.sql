@@ -0,0 +1 @@
+alter table game add column equalize_ping boolean default false not null;
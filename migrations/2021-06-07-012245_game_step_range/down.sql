@@ -0,0 +1,2 @@
+alter table game drop column step_min;
+alter table game drop column step_max;
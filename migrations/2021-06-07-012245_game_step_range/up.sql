@@ -0,0 +1,2 @@
+alter table game add column step_min integer;
+alter table game add column step_max integer;
@@ -0,0 +1 @@
+alter table game drop column allow_guests;
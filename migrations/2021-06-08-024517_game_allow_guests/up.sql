@@ -0,0 +1 @@
+alter table game add column allow_guests boolean not null default false;
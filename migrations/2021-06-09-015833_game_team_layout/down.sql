@@ -0,0 +1 @@
+alter table game drop column team_layout;
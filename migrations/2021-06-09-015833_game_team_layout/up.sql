@@ -0,0 +1 @@
+alter table game add column team_layout text;
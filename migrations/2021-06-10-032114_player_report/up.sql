@@ -0,0 +1,16 @@
+create table player_report (
+  id serial not null primary key,
+  -- null for reports created by the controller
+  reporter_player_id integer references player(id) on delete set null,
+  target_player_id integer not null references player(id) on delete cascade,
+  game_id integer references game(id) on delete set null,
+  category integer not null,
+  message text not null,
+  status integer not null default 0,
+  resolution text,
+  resolved_at timestamp with time zone,
+  created_at timestamp with time zone default now() not null
+);
+
+create index player_report_target_player_id on player_report(target_player_id);
+create index player_report_status on player_report(status);
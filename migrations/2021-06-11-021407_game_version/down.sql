@@ -0,0 +1 @@
+alter table game drop column version;
@@ -0,0 +1 @@
+alter table game add column version integer not null default 0;
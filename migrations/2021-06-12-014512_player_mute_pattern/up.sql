@@ -0,0 +1,7 @@
+create table player_mute_pattern (
+  id serial not null primary key,
+  player_id integer not null references player(id) on delete cascade,
+  pattern text not null,
+  created_at timestamp with time zone default now() not null,
+  unique(player_id, pattern)
+);
@@ -0,0 +1,10 @@
+create table player_pending_frame (
+  id bigserial not null primary key,
+  player_id integer not null references player(id) on delete cascade,
+  type_id smallint not null,
+  payload bytea not null,
+  expires_at timestamp with time zone not null,
+  created_at timestamp with time zone default now() not null
+);
+
+create index player_pending_frame_player_id on player_pending_frame(player_id);